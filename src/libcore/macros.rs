@@ -957,7 +957,11 @@ pub(crate) mod builtin {
     /// are placed on the syntax of the macro invocation itself.
     ///
     /// Note that the expanded results of the input tokens may change in the
-    /// future. You should be careful if you rely on the output.
+    /// future. You should be careful if you rely on the output. [`stringify_source!`]
+    /// and [`stringify_tokens!`] are variants with a documented output.
+    ///
+    /// [`stringify_source!`]: macro.stringify_source.html
+    /// [`stringify_tokens!`]: macro.stringify_tokens.html
     ///
     /// # Examples
     ///
@@ -970,6 +974,58 @@ pub(crate) mod builtin {
     #[macro_export]
     macro_rules! stringify { ($($t:tt)*) => { /* compiler built-in */ } }
 
+    /// Stringifies its arguments as they are written in the source code.
+    ///
+    /// This macro will yield an expression of type `&'static str` holding the
+    /// source text of the tokens passed to the macro, including the whitespace
+    /// and comments between them.
+    ///
+    /// Tokens are only rendered together with the text they are written next
+    /// to. Where the arguments are put together by another macro, e.g., from
+    /// fragments passed to a `macro_rules!` macro, the text of each fragment is
+    /// kept and the fragments are separated by a single space. Tokens that have
+    /// no source text, such as those created by procedural macros, are rendered
+    /// like [`stringify_tokens!`] does.
+    ///
+    /// [`stringify_tokens!`]: macro.stringify_tokens.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #![feature(stringify_variants)]
+    ///
+    /// let sum = stringify_source!(1+   1 /* one */ + 1);
+    /// assert_eq!(sum, "1+   1 /* one */ + 1");
+    /// ```
+    #[unstable(feature = "stringify_variants", issue = "0",
+               reason = "`stringify_source!` is new and its output may change")]
+    #[rustc_builtin_macro]
+    #[macro_export]
+    macro_rules! stringify_source { ($($t:tt)*) => { /* compiler built-in */ } }
+
+    /// Stringifies its arguments as tokens, independently of how they are
+    /// written.
+    ///
+    /// This macro will yield an expression of type `&'static str` holding the
+    /// canonical rendering of the tokens passed to the macro: whitespace and
+    /// comments are dropped and tokens are separated by a single space where
+    /// the compiler's pretty printer puts one. Tokens that render the same
+    /// stringify the same, wherever they come from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #![feature(stringify_variants)]
+    ///
+    /// let sum = stringify_tokens!(1+   1 /* one */ + 1);
+    /// assert_eq!(sum, "1 + 1 + 1");
+    /// ```
+    #[unstable(feature = "stringify_variants", issue = "0",
+               reason = "`stringify_tokens!` is new and its output may change")]
+    #[rustc_builtin_macro]
+    #[macro_export]
+    macro_rules! stringify_tokens { ($($t:tt)*) => { /* compiler built-in */ } }
+
     /// Includes a utf8-encoded file as a string.
     ///
     /// The file is located relative to the current file. (similarly to how
@@ -1220,13 +1276,18 @@ pub(crate) mod builtin {
     macro_rules! log_syntax { ($($arg:tt)*) => { /* compiler built-in */ } }
 
     /// Enables or disables tracing functionality used for debugging other macros.
+    ///
+    /// Instead of `true`, string literals can be passed to trace only the macros they match:
+    /// `trace_macros!("my_macro", "serde::*")` traces the macros invoked by a path ending with
+    /// `my_macro` or starting with `serde::`.
     #[unstable(feature = "trace_macros", issue = "29598",
                reason = "`trace_macros` is not stable enough for use and is subject to change")]
     #[rustc_builtin_macro]
     #[macro_export]
     macro_rules! trace_macros {
         (true) => ({ /* compiler built-in */ });
-        (false) => ({ /* compiler built-in */ })
+        (false) => ({ /* compiler built-in */ });
+        ($($filter:literal),+ $(,)?) => ({ /* compiler built-in */ })
     }
 
     /// Attribute macro applied to a function to turn it into a unit test.
@@ -76,6 +76,8 @@ pub use crate::{
     module_path,
     option_env,
     stringify,
+    stringify_source,
+    stringify_tokens,
     trace_macros,
 };
 
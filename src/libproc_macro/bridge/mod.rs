@@ -127,6 +127,8 @@ macro_rules! with_api {
                 fn eq($self: &$S::SourceFile, other: &$S::SourceFile) -> bool;
                 fn path($self: &$S::SourceFile) -> String;
                 fn is_real($self: &$S::SourceFile) -> bool;
+                fn track_env_var(var: &str) -> Option<String>;
+                fn track_path(path: &str);
             },
             MultiSpan {
                 fn drop($self: $S::MultiSpan);
@@ -156,6 +158,7 @@ macro_rules! with_api {
                 fn join($self: $S::Span, other: $S::Span) -> Option<$S::Span>;
                 fn resolved_at($self: $S::Span, at: $S::Span) -> $S::Span;
                 fn source_text($self: $S::Span) -> Option<String>;
+                fn leading_comments($self: $S::Span) -> Vec<String>;
             },
         }
     };
@@ -332,6 +335,7 @@ mark_noop! {
     &'a [u8],
     &'a str,
     String,
+    Vec<String>,
     Delimiter,
     Level,
     LineColumn,
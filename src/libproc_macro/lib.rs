@@ -219,6 +219,59 @@ pub mod token_stream {
     }
 }
 
+/// Reading the environment and files in a way the compiler knows about.
+///
+/// A macro declared pure with `#[rustc_pure_macro]` must not read anything, which the compiler
+/// checks when the reads go through this module. Other macros can use it too.
+#[unstable(feature = "proc_macro_tracked", issue = "0")]
+pub mod tracked {
+    use crate::bridge;
+
+    use std::env::VarError;
+    use std::fmt;
+    use std::path::{Path, PathBuf};
+
+    /// Retrieves the value of the environment variable `key` while compiling, like
+    /// `std::env::var`. Values that aren't valid unicode are reported as not present.
+    #[unstable(feature = "proc_macro_tracked", issue = "0")]
+    pub fn env_var(key: &str) -> Result<String, VarError> {
+        bridge::client::SourceFile::track_env_var(key).ok_or(VarError::NotPresent)
+    }
+
+    /// Records that the macro reads the file at `path`. Paths that aren't valid unicode can't
+    /// be recorded, and are returned as an error instead.
+    #[unstable(feature = "proc_macro_tracked", issue = "0")]
+    pub fn path<P: AsRef<Path>>(path: P) -> Result<(), NonUnicodePath> {
+        let path = path.as_ref();
+        match path.to_str() {
+            Some(path) => Ok(bridge::client::SourceFile::track_path(path)),
+            None => Err(NonUnicodePath { path: path.to_path_buf() }),
+        }
+    }
+
+    /// Error returned from `path` for a path that isn't valid unicode.
+    #[unstable(feature = "proc_macro_tracked", issue = "0")]
+    #[derive(Clone, Debug)]
+    pub struct NonUnicodePath {
+        path: PathBuf,
+    }
+
+    impl NonUnicodePath {
+        /// The path that couldn't be recorded.
+        #[unstable(feature = "proc_macro_tracked", issue = "0")]
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    #[unstable(feature = "proc_macro_tracked", issue = "0")]
+    impl fmt::Display for NonUnicodePath {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "the path `{}` isn't valid unicode", self.path.display())
+        }
+    }
+}
+
 /// `quote!(..)` accepts arbitrary tokens and expands into a `TokenStream` describing the input.
 /// For example, `quote!(a + b)` will produce a expression, that, when evaluated, constructs
 /// the `TokenStream` `[Ident("a"), Punct('+', Alone), Ident("b")]`.
@@ -344,6 +397,18 @@ impl Span {
         self.0.source_text()
     }
 
+    /// Returns the ordinary comments written right before the token this span starts at,
+    /// e.g., `// note` in `// note\nfn f() {}` for the span of `fn`. Comments are only kept
+    /// if the compiler is asked to, with `-Z preserve-comments`; otherwise, and for spans
+    /// that don't start at a token of real source code, nothing is returned.
+    ///
+    /// Like `source_text`, this is meant for tools that reformat or forward code, the tokens
+    /// produced by a macro shouldn't depend on it.
+    #[unstable(feature = "proc_macro_span", issue = "54725")]
+    pub fn leading_comments(&self) -> Vec<String> {
+        self.0.leading_comments()
+    }
+
     diagnostic_method!(error, Level::Error);
     diagnostic_method!(warning, Level::Warning);
     diagnostic_method!(note, Level::Note);
@@ -995,12 +995,23 @@ impl<'a> LoweringContext<'a> {
             id: attr.id,
             style: attr.style,
             path: attr.path.clone(),
-            tokens: self.lower_token_stream(attr.tokens.clone()),
+            args: self.lower_attr_args(&attr.args),
             is_sugared_doc: attr.is_sugared_doc,
             span: attr.span,
         }
     }
 
+    fn lower_attr_args(&mut self, args: &AttrArgs) -> AttrArgs {
+        match *args {
+            AttrArgs::Empty => AttrArgs::Empty,
+            AttrArgs::Delimited(dspan, delim, ref tokens) => {
+                AttrArgs::Delimited(dspan, delim, self.lower_token_stream(tokens.clone()))
+            }
+            // The value is a literal, other expressions are rejected after expansion.
+            AttrArgs::Eq(eq_span, ref expr) => AttrArgs::Eq(eq_span, expr.clone()),
+        }
+    }
+
     fn lower_token_stream(&mut self, tokens: TokenStream) -> TokenStream {
         tokens
             .into_trees()
@@ -531,6 +531,10 @@ impl Definitions {
         self.invocation_parents[&invoc_id]
     }
 
+    pub fn opt_invocation_parent(&self, invoc_id: ExpnId) -> Option<DefIndex> {
+        self.invocation_parents.get(&invoc_id).cloned()
+    }
+
     pub fn set_invocation_parent(&mut self, invoc_id: ExpnId, parent: DefIndex) {
         let old_parent = self.invocation_parents.insert(invoc_id, parent);
         assert!(old_parent.is_none(), "parent def-index is reset for an invocation");
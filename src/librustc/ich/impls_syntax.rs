@@ -93,7 +93,7 @@ impl_stable_hash_for!(enum ::rustc_target::spec::abi::Abi {
     Unadjusted
 });
 
-impl_stable_hash_for!(struct ::syntax::attr::Deprecation { since, note });
+impl_stable_hash_for!(struct ::syntax::attr::Deprecation { since, note, suggestion });
 impl_stable_hash_for!(struct ::syntax::attr::Stability {
     level,
     feature,
@@ -218,15 +218,28 @@ impl<'a> HashStable<StableHashingContext<'a>> for ast::Attribute {
             id: _,
             style,
             ref path,
-            ref tokens,
+            ref args,
             is_sugared_doc: _,
             span,
         } = *self;
 
         style.hash_stable(hcx, hasher);
         path.hash_stable(hcx, hasher);
-        for tt in tokens.trees() {
-            tt.hash_stable(hcx, hasher);
+        mem::discriminant(args).hash_stable(hcx, hasher);
+        match *args {
+            ast::AttrArgs::Eq(eq_span, ref expr) => {
+                eq_span.hash_stable(hcx, hasher);
+                // Other expressions are rejected before the crate is lowered.
+                match expr.node {
+                    ast::ExprKind::Lit(ref lit) => lit.hash_stable(hcx, hasher),
+                    _ => expr.span.hash_stable(hcx, hasher),
+                }
+            }
+            _ => {
+                for tt in args.outer_tokens().trees() {
+                    tt.hash_stable(hcx, hasher);
+                }
+            }
         }
         span.hash_stable(hcx, hasher);
     }
@@ -384,7 +397,8 @@ impl_stable_hash_for!(struct ::syntax_pos::hygiene::ExpnData {
     allow_internal_unstable,
     allow_internal_unsafe,
     local_inner_macros,
-    edition
+    edition,
+    default_transparency
 });
 
 impl_stable_hash_for!(enum ::syntax_pos::hygiene::ExpnKind {
@@ -430,11 +444,11 @@ impl<'a> HashStable<StableHashingContext<'a>> for SourceFile {
             src: _,
             src_hash,
             external_src: _,
-            start_pos,
+            start_pos: _,
             end_pos: _,
-            ref lines,
-            ref multibyte_chars,
-            ref non_narrow_chars,
+            // The line index is found from the source alone, and only holds positions relative
+            // to the start of the file, so `src_hash` covers it without the index being built.
+            line_index: _,
         } = *self;
 
         (name_hash as u64).hash_stable(hcx, hasher);
@@ -446,52 +460,9 @@ impl<'a> HashStable<StableHashingContext<'a>> for SourceFile {
         }.hash_stable(hcx, hasher);
 
         src_hash.hash_stable(hcx, hasher);
-
-        // We only hash the relative position within this source_file
-        lines.len().hash_stable(hcx, hasher);
-        for &line in lines.iter() {
-            stable_byte_pos(line, start_pos).hash_stable(hcx, hasher);
-        }
-
-        // We only hash the relative position within this source_file
-        multibyte_chars.len().hash_stable(hcx, hasher);
-        for &char_pos in multibyte_chars.iter() {
-            stable_multibyte_char(char_pos, start_pos).hash_stable(hcx, hasher);
-        }
-
-        non_narrow_chars.len().hash_stable(hcx, hasher);
-        for &char_pos in non_narrow_chars.iter() {
-            stable_non_narrow_char(char_pos, start_pos).hash_stable(hcx, hasher);
-        }
     }
 }
 
-fn stable_byte_pos(pos: ::syntax_pos::BytePos,
-                   source_file_start: ::syntax_pos::BytePos)
-                   -> u32 {
-    pos.0 - source_file_start.0
-}
-
-fn stable_multibyte_char(mbc: ::syntax_pos::MultiByteChar,
-                         source_file_start: ::syntax_pos::BytePos)
-                         -> (u32, u32) {
-    let ::syntax_pos::MultiByteChar {
-        pos,
-        bytes,
-    } = mbc;
-
-    (pos.0 - source_file_start.0, bytes as u32)
-}
-
-fn stable_non_narrow_char(swc: ::syntax_pos::NonNarrowChar,
-                          source_file_start: ::syntax_pos::BytePos)
-                          -> (u32, u32) {
-    let pos = swc.pos();
-    let width = swc.width();
-
-    (pos.0 - source_file_start.0, width as u32)
-}
-
 impl<'tcx> HashStable<StableHashingContext<'tcx>> for feature_gate::Features {
     fn hash_stable<W: StableHasherResult>(
         &self,
@@ -368,6 +368,24 @@ pub mod parser {
         Allow,
         "possible meta-variable misuse at macro definition"
     }
+
+    declare_lint! {
+        pub MACRO_BACKTRACKING,
+        Allow,
+        "macro matchers that can match the same input in exponentially many ways"
+    }
+
+    declare_lint! {
+        pub GENERATED_IDENT_STYLE,
+        Warn,
+        "names that macros derive from their input that don't follow the naming conventions"
+    }
+
+    declare_lint! {
+        pub MISPLACED_DERIVE_HELPERS,
+        Warn,
+        "derive helper attributes used outside of the scope declared by their derive"
+    }
 }
 
 declare_lint! {
@@ -455,6 +473,9 @@ declare_lint_pass! {
         MACRO_EXPANDED_MACRO_EXPORTS_ACCESSED_BY_ABSOLUTE_PATHS,
         parser::ILL_FORMED_ATTRIBUTE_INPUT,
         parser::META_VARIABLE_MISUSE,
+        parser::MACRO_BACKTRACKING,
+        parser::GENERATED_IDENT_STYLE,
+        parser::MISPLACED_DERIVE_HELPERS,
         DEPRECATED_IN_FUTURE,
         AMBIGUOUS_ASSOCIATED_ITEMS,
         NESTED_IMPL_TRAIT,
@@ -479,6 +500,7 @@ pub enum BuiltinLintDiagnostics {
     NestedImplTrait { outer_impl_trait_span: Span, inner_impl_trait_span: Span },
     RedundantImport(Vec<(Span, bool)>, ast::Ident),
     DeprecatedMacro(Option<Symbol>, Span),
+    Notes(Vec<String>),
 }
 
 pub(crate) fn add_elided_lifetime_in_path_suggestion(
@@ -606,6 +628,11 @@ impl BuiltinLintDiagnostics {
             }
             BuiltinLintDiagnostics::DeprecatedMacro(suggestion, span) =>
                 stability::deprecation_suggestion(db, suggestion, span),
+            BuiltinLintDiagnostics::Notes(notes) => {
+                for note in notes {
+                    db.note(&note);
+                }
+            }
         }
     }
 }
@@ -26,8 +26,10 @@ use rustc_data_structures::sync;
 use crate::hir::def_id::{CrateNum, LOCAL_CRATE};
 use crate::hir::intravisit;
 use crate::hir;
-use crate::lint::builtin::BuiltinLintDiagnostics;
+use crate::lint::builtin::{BuiltinLintDiagnostics, DEPRECATED};
 use crate::lint::builtin::parser::{ILL_FORMED_ATTRIBUTE_INPUT, META_VARIABLE_MISUSE};
+use crate::lint::builtin::parser::{GENERATED_IDENT_STYLE, MACRO_BACKTRACKING};
+use crate::lint::builtin::parser::MISPLACED_DERIVE_HELPERS;
 use crate::session::{Session, DiagnosticMessageId};
 use crate::ty::TyCtxt;
 use crate::ty::query::Providers;
@@ -83,6 +85,10 @@ impl Lint {
         match lint_id {
             BufferedEarlyLintId::IllFormedAttributeInput => ILL_FORMED_ATTRIBUTE_INPUT,
             BufferedEarlyLintId::MetaVariableMisuse => META_VARIABLE_MISUSE,
+            BufferedEarlyLintId::MacroBacktracking => MACRO_BACKTRACKING,
+            BufferedEarlyLintId::GeneratedIdentStyle => GENERATED_IDENT_STYLE,
+            BufferedEarlyLintId::MisplacedDeriveHelpers => MISPLACED_DERIVE_HELPERS,
+            BufferedEarlyLintId::DeprecatedMacroArm => DEPRECATED,
         }
     }
 
@@ -739,6 +745,20 @@ pub fn struct_lint_level<'a>(sess: &'a Session,
         }
     }
 
+    // Macros can suppress some warnings in the code they generate, see
+    // `ExtCtxt::with_suppressed_warnings`. This includes the warnings turned into errors by the
+    // level of the `warnings` lint, e.g. with `-D warnings`, but not the lints denied by name.
+    let is_warning = match src {
+        LintSource::CommandLine(level_name) | LintSource::Node(level_name, ..) => {
+            level == Level::Warn || level_name == sym::warnings
+        }
+        LintSource::Default => level == Level::Warn,
+    };
+    if is_warning && future_incompatible.is_none() &&
+       err.span.primary_spans().iter().any(|s| sess.parse_sess.suppresses_warning(&name, *s)) {
+        err.cancel()
+    }
+
     return err
 }
 
@@ -1296,6 +1296,13 @@ options! {DebuggingOptions, DebuggingSetter, basic_debugging_options,
         "set the current terminal width"),
     continue_parse_after_error: bool = (false, parse_bool, [TRACKED],
         "attempt to recover from parse errors (experimental)"),
+    parser_nesting_limit: Option<usize> = (None, parse_opt_uint, [TRACKED],
+        "set the maximum nesting depth of expressions, types and patterns accepted by the parser"),
+    literal_size_limit: Option<usize> = (None, parse_opt_uint, [TRACKED],
+        "set the maximum length in bytes of string and byte string literals accepted by the lexer"),
+    preserve_comments: bool = (false, parse_bool, [UNTRACKED],
+        "keep the ordinary comments of source files, so that procedural macros can get the \
+         comments before a token with `Span::leading_comments`"),
     dep_tasks: bool = (false, parse_bool, [UNTRACKED],
         "print tasks that execute and the color their dep node gets (requires debug build)"),
     incremental: Option<String> = (None, parse_opt_string, [UNTRACKED],
@@ -1330,6 +1337,56 @@ options! {DebuggingOptions, DebuggingSetter, basic_debugging_options,
         "force overflow checks on or off"),
     trace_macros: bool = (false, parse_bool, [UNTRACKED],
         "for every macro invocation, print its name and arguments"),
+    trace_macros_diff: bool = (false, parse_bool, [UNTRACKED],
+        "in `trace_macros!` output, also show how the output of each `macro_rules!` invocation \
+         differs from its input"),
+    macro_print_limit: Option<usize> = (None, parse_opt_uint, [UNTRACKED],
+        "elide the middle of token streams longer than this in macro traces and diagnostics"),
+    macro_dry_run: bool = (false, parse_bool, [UNTRACKED],
+        "invoke attribute and derive macros without using their output, and report what they \
+         would have generated with `--error-format=json`"),
+    strict_ident_eq: bool = (false, parse_bool, [UNTRACKED],
+        "warn about `==` comparisons of identifiers that differ only in hygiene made during macro \
+         expansion (requires a compiler built with debug assertions)"),
+    validate_macro_output: bool = (false, parse_bool, [UNTRACKED],
+        "check the output of each macro right after it's expanded, and warn about problems in it \
+         that would otherwise be reported later without naming the macro"),
+    check_macro_item_collisions: bool = (false, parse_bool, [UNTRACKED],
+        "once macros are expanded, warn about items with the same name in the same module that \
+         were generated by different macro invocations"),
+    check_dead_attrs: bool = (false, parse_bool, [UNTRACKED],
+        "once macros are expanded, warn about attributes that no macro consumed, e.g., helpers \
+         of a derive that isn't applied to the item"),
+    stable_expansion_ids: bool = (false, parse_bool, [UNTRACKED],
+        "derive the IDs of macro expansions from their position in the expansion tree, so that \
+         they don't depend on the expansion order"),
+    macro_rules_cache: bool = (false, parse_bool, [UNTRACKED],
+        "remember which arm of a `macro_rules!` macro matched an input, so that invoking the \
+         macro with the same tokens again skips matching"),
+    no_pure_macro_cache: bool = (false, parse_bool, [UNTRACKED],
+        "invoke pure procedural macros every time, even if they were invoked with the same tokens \
+         before"),
+    macro_trace_json: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "write a trace of every expanded macro invocation to the given file, as JSON"),
+    macro_expansion_graph: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "write the tree of expanded macro invocations to the given file, as a graph in the DOT \
+         format"),
+    macro_manifest: Option<String> = (None, parse_opt_string, [UNTRACKED],
+        "once macros are expanded, write a JSON summary of the macros the crate defines to the \
+         given file, see `MacroManifest`"),
+    injected_crates: Option<Vec<String>> = (None, parse_opt_comma_list, [TRACKED],
+        "the crates to inject `extern crate` items for, the first one providing the prelude \
+         (default: `std`, or `core,compiler_builtins` for `#![no_std]` crates)"),
+    prelude_path: Option<String> = (None, parse_opt_string, [TRACKED],
+        "the path of the module glob imported as the prelude, relative to the crate providing \
+         it (default: `prelude::v1`)"),
+    extra_root_imports: Vec<String> = (Vec::new(), parse_list, [TRACKED],
+        "paths to import into the crate root next to the prelude, e.g., `alloc::vec::Vec`"),
+    intern_token_streams: bool = (false, parse_bool, [UNTRACKED],
+        "share the token streams produced by macros with identical ones produced before"),
+    macro_stats: bool = (false, parse_bool, [UNTRACKED],
+        "print how many token streams produced by macros were shared with identical ones \
+         (requires `-Z intern-token-streams`)"),
     debug_macros: bool = (false, parse_bool, [TRACKED],
         "emit line numbers debug info inside macros"),
     keep_hygiene_data: bool = (false, parse_bool, [UNTRACKED],
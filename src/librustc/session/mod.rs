@@ -1185,7 +1185,12 @@ fn build_session_(
     );
     let target_cfg = config::build_target_config(&sopts, &span_diagnostic);
 
-    let p_s = parse::ParseSess::with_span_handler(span_diagnostic, source_map);
+    let mut p_s = parse::ParseSess::with_span_handler(span_diagnostic, source_map);
+    if let Some(limit) = sopts.debugging_opts.parser_nesting_limit {
+        p_s.max_nesting_depth = limit;
+    }
+    p_s.max_literal_len = sopts.debugging_opts.literal_size_limit;
+    p_s.preserve_comments = sopts.debugging_opts.preserve_comments;
     let sysroot = match &sopts.maybe_sysroot {
         Some(sysroot) => sysroot.clone(),
         None => filesearch::get_or_default_sysroot(),
@@ -583,7 +583,7 @@ impl<'a, 'tcx> SpecializedDecoder<Span> for CacheDecoder<'a, 'tcx> {
         let len = BytePos::decode(self)?;
 
         let file_lo = self.file_index_to_file(file_lo_index);
-        let lo = file_lo.lines[line_lo - 1] + col_lo;
+        let lo = file_lo.lines()[line_lo - 1] + col_lo;
         let hi = lo + len;
 
         let expn_data_tag = u8::decode(self)?;
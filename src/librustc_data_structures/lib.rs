@@ -85,6 +85,7 @@ pub mod snapshot_map;
 pub use ena::snapshot_vec;
 pub mod sorted_map;
 #[macro_use] pub mod stable_hasher;
+pub mod stack;
 pub mod sync;
 pub mod sharded;
 pub mod tiny_list;
@@ -569,6 +569,13 @@ impl<T> Once<T> {
     }
 }
 
+impl<T: Clone> Clone for Once<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Once(self.0.clone(), PhantomData)
+    }
+}
+
 #[derive(Debug)]
 pub struct Lock<T>(InnerLock<T>);
 
@@ -24,6 +24,7 @@ use rustc_data_structures::sync::Lrc;
 use std::borrow::Cow;
 use std::io::prelude::*;
 use std::io;
+use std::iter;
 use std::cmp::{min, max, Reverse};
 use std::path::Path;
 use termcolor::{StandardStream, ColorChoice, ColorSpec, BufferWriter, Ansi};
@@ -187,6 +188,10 @@ pub trait Emitter {
     /// other formats can, and will, simply ignore it.
     fn emit_artifact_notification(&mut self, _path: &Path, _artifact_type: &str) {}
 
+    /// Emit data a macro recorded during expansion for the embedder, `payload` being of the
+    /// given `kind`. Like artifact notifications, this is only supported for the JSON format.
+    fn emit_expansion_artifact(&mut self, _kind: &str, _payload: &str) {}
+
     /// Checks if should show explanations about "rustc --explain"
     fn should_show_explain(&self) -> bool {
         true
@@ -254,6 +259,7 @@ impl Emitter for EmitterWriter {
         let mut children = db.children.clone();
         let (mut primary_span, suggestions) = self.primary_span_formatted(&db);
 
+        self.redirect_spans(&mut primary_span, &mut children);
         self.fix_multispans_in_std_macros(&mut primary_span,
                                           &mut children,
                                           &db.level,
@@ -1005,6 +1011,27 @@ impl EmitterWriter {
         spans_updated
     }
 
+    /// Moves the spans of generated code to the code it was generated from, see
+    /// `SourceMapper::redirected_span`.
+    fn redirect_spans(&self, span: &mut MultiSpan, children: &mut Vec<SubDiagnostic>) {
+        let sm = match self.sm {
+            Some(ref sm) => sm,
+            None => return,
+        };
+        let spans = iter::once(span).chain(children.iter_mut().map(|child| &mut child.span));
+        for span in spans {
+            let original_spans = span.primary_spans().iter().cloned()
+                .chain(span.span_labels().into_iter().map(|label| label.span))
+                .collect::<Vec<_>>();
+            for sp in original_spans {
+                let redirected = sm.redirected_span(sp);
+                if redirected != sp {
+                    span.replace(sp, redirected);
+                }
+            }
+        }
+    }
+
     // This does a small "fix" for multispans by looking to see if it can find any that
     // point directly at <*macros>. Since these are often difficult to read, this
     // will change the span to point at the use site.
@@ -22,7 +22,7 @@ use rustc_data_structures::stable_hasher::StableHasher;
 
 use std::borrow::Cow;
 use std::cell::Cell;
-use std::{error, fmt};
+use std::{error, fmt, mem};
 use std::panic;
 use std::path::Path;
 
@@ -150,6 +150,9 @@ pub trait SourceMapper {
     fn call_span_if_macro(&self, sp: Span) -> Span;
     fn ensure_source_file_source_present(&self, source_file: Lrc<SourceFile>) -> bool;
     fn doctest_offset_line(&self, file: &FileName, line: usize) -> usize;
+    /// The span diagnostics pointing at `sp` are displayed at, which differs from `sp` if the
+    /// code at `sp` was generated from some other code that the macro producing it pointed to.
+    fn redirected_span(&self, sp: Span) -> Span;
 }
 
 impl CodeSuggestion {
@@ -322,6 +325,9 @@ pub struct Handler {
     /// this handler. These hashes is used to avoid emitting the same error
     /// twice.
     emitted_diagnostics: Lock<FxHashSet<u128>>,
+
+    /// The errors reported during `capture_errors`, which are kept instead of being emitted.
+    captured_errors: Lock<Option<Vec<Diagnostic>>>,
 }
 
 fn default_track_diagnostic(_: &Diagnostic) {}
@@ -412,6 +418,7 @@ impl Handler {
             taught_diagnostics: Default::default(),
             emitted_diagnostic_codes: Default::default(),
             emitted_diagnostics: Default::default(),
+            captured_errors: Lock::new(None),
         }
     }
 
@@ -419,6 +426,16 @@ impl Handler {
         self.continue_after_error.store(continue_after_error, SeqCst);
     }
 
+    /// Runs `f` and returns the errors reported while it runs instead of emitting them. They
+    /// aren't counted until the caller emits them, e.g., with `DiagnosticBuilder::new_diagnostic`.
+    /// Warnings and notes are emitted as usual.
+    pub fn capture_errors<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<Diagnostic>) {
+        let outer = self.captured_errors.borrow_mut().replace(Vec::new());
+        let result = f();
+        let captured = mem::replace(&mut *self.captured_errors.borrow_mut(), outer);
+        (result, captured.unwrap_or_default())
+    }
+
     /// Resets the diagnostic error count as well as the cached emitted diagnostics.
     ///
     /// NOTE: *do not* call this function from rustc. It is only meant to be called from external
@@ -749,6 +766,13 @@ impl Handler {
     fn emit_db(&self, db: &DiagnosticBuilder<'_>) {
         let diagnostic = &**db;
 
+        if db.is_error() {
+            if let Some(captured) = self.captured_errors.borrow_mut().as_mut() {
+                captured.push(diagnostic.clone());
+                return;
+            }
+        }
+
         TRACK_DIAGNOSTICS.with(|track_diagnostics| {
             track_diagnostics.get()(diagnostic);
         });
@@ -780,6 +804,10 @@ impl Handler {
     pub fn emit_artifact_notification(&self, path: &Path, artifact_type: &str) {
         self.emitter.borrow_mut().emit_artifact_notification(path, artifact_type);
     }
+
+    pub fn emit_expansion_artifact(&self, kind: &str, payload: &str) {
+        self.emitter.borrow_mut().emit_expansion_artifact(kind, payload);
+    }
 }
 
 #[derive(Copy, PartialEq, Clone, Hash, Debug, RustcEncodable, RustcDecodable)]
@@ -40,10 +40,11 @@ use syntax::ext::base::{NamedSyntaxExtension, ExtCtxt};
 use syntax::mut_visit::MutVisitor;
 use syntax::parse::{self, PResult};
 use syntax::util::node_count::NodeCounter;
-use syntax::symbol::Symbol;
+use syntax::symbol::{Symbol, sym};
 use syntax::feature_gate::AttributeType;
 use syntax_pos::FileName;
 use syntax_ext;
+use syntax_ext::standard_library_imports::{self, InjectionConfig};
 
 use rustc_serialize::json;
 use tempfile::Builder as TempFileBuilder;
@@ -269,9 +270,19 @@ pub fn register_plugins<'a>(
     });
 
     krate = time(sess, "crate injection", || {
-        let alt_std_name = sess.opts.alt_std_name.as_ref().map(|s| &**s);
-        let (krate, name) =
-            syntax_ext::standard_library_imports::inject(krate, alt_std_name, sess.edition());
+        let opts = &sess.opts.debugging_opts;
+        let path = |path: &str| path.split("::").map(Symbol::intern).collect::<Vec<_>>();
+        let config = InjectionConfig {
+            crates: opts.injected_crates.as_ref().map(|crates| {
+                crates.iter().filter(|name| !name.is_empty()).map(|name| Symbol::intern(name))
+                    .collect()
+            }),
+            alt_std_name: sess.opts.alt_std_name.as_ref().map(|name| Symbol::intern(name)),
+            prelude_path: opts.prelude_path.as_ref().map(|prelude_path| path(prelude_path)),
+            extra_imports: opts.extra_root_imports.iter().map(|import| path(import)).collect(),
+            ..InjectionConfig::new(sess.edition())
+        };
+        let (krate, name) = standard_library_imports::inject_with_config(krate, &config);
         if let Some(name) = name {
             sess.parse_sess.injected_crate_name.set(name);
         }
@@ -415,6 +426,19 @@ fn configure_and_expand_inner<'a>(
             features: Some(&features),
             recursion_limit: *sess.recursion_limit.get(),
             trace_mac: sess.opts.debugging_opts.trace_macros,
+            print_token_limit: sess.opts.debugging_opts.macro_print_limit,
+            trace_diff: sess.opts.debugging_opts.trace_macros_diff,
+            attr_dry_run: sess.opts.debugging_opts.macro_dry_run,
+            strict_ident_eq: sess.opts.debugging_opts.strict_ident_eq,
+            validate_output: sess.opts.debugging_opts.validate_macro_output,
+            check_item_collisions: sess.opts.debugging_opts.check_macro_item_collisions,
+            check_dead_attrs: sess.opts.debugging_opts.check_dead_attrs,
+            stable_expn_ids: sess.opts.debugging_opts.stable_expansion_ids,
+            macro_rules_cache: sess.opts.debugging_opts.macro_rules_cache,
+            pure_macro_cache: !sess.opts.debugging_opts.no_pure_macro_cache,
+            intern_token_streams: sess.opts.debugging_opts.intern_token_streams,
+            trace_json: sess.opts.debugging_opts.macro_trace_json.is_some() ||
+                sess.opts.debugging_opts.macro_expansion_graph.is_some(),
             should_test: sess.opts.test,
             ..syntax::ext::expand::ExpansionConfig::default(crate_name.to_string())
         };
@@ -445,6 +469,35 @@ fn configure_and_expand_inner<'a>(
             let msg = "missing fragment specifier";
             sess.buffer_lint(lint, ast::CRATE_NODE_ID, span, msg);
         }
+
+        if sess.opts.debugging_opts.macro_dry_run {
+            for artifact in ecx.parse_sess.expansion_artifacts.borrow().iter() {
+                if artifact.kind == sym::macro_dry_run {
+                    let kind = artifact.kind.as_str();
+                    sess.diagnostic().emit_expansion_artifact(&kind, &artifact.payload);
+                }
+            }
+        }
+        if let Some(ref path) = sess.opts.debugging_opts.macro_trace_json {
+            if let Err(err) = fs::write(path, ecx.macro_trace.to_json()) {
+                sess.err(&format!("failed to write macro trace to `{}`: {}", path, err));
+            }
+        }
+        if let Some(ref path) = sess.opts.debugging_opts.macro_expansion_graph {
+            if let Err(err) = fs::write(path, ecx.macro_trace.to_dot()) {
+                sess.err(&format!("failed to write macro expansion graph to `{}`: {}", path, err));
+            }
+        }
+        if sess.opts.debugging_opts.macro_stats {
+            let stats = ecx.token_interner.borrow().stats();
+            println!(
+                "token streams: {} interned, {} shared ({} trees, ~{} bytes saved)",
+                stats.streams,
+                stats.shared,
+                stats.shared_trees,
+                stats.bytes_saved(),
+            );
+        }
         if cfg!(windows) {
             env::set_var("PATH", &old_path);
         }
@@ -452,6 +505,13 @@ fn configure_and_expand_inner<'a>(
     });
     sess.profiler(|p| p.end_activity("macro expansion"));
 
+    if let Some(ref path) = sess.opts.debugging_opts.macro_manifest {
+        let manifest = resolver.macro_manifest(crate_name);
+        if let Err(err) = fs::write(path, manifest.to_json()) {
+            sess.err(&format!("failed to write macro manifest to `{}`: {}", path, err));
+        }
+    }
+
     time(sess, "maybe building test harness", || {
         syntax_ext::test_harness::inject(
             &sess.parse_sess,
@@ -507,9 +567,14 @@ fn configure_and_expand_inner<'a>(
     }
 
     // Done with macro expansion!
+    syntax_pos::shrink_span_interner();
 
     if sess.opts.debugging_opts.input_stats {
         println!("Post-expansion node count: {}", count_nodes(&krate));
+        let spans = syntax_pos::span_interner_stats();
+        println!("Interned spans:            {} ({} distinct, {} long, {} with large contexts)",
+                 spans.requests, spans.distinct, spans.long, spans.large_ctxt);
+        println!("Span interner size:        {} bytes", spans.allocated_bytes);
     }
 
     if sess.opts.debugging_opts.hir_stats {
@@ -538,9 +603,14 @@ fn configure_and_expand_inner<'a>(
     // Add all buffered lints from the `ParseSess` to the `Session`.
     sess.parse_sess.buffered_lints.with_lock(|buffered_lints| {
         info!("{} parse sess buffered_lints", buffered_lints.len());
-        for BufferedEarlyLint{id, span, msg, lint_id} in buffered_lints.drain(..) {
+        for BufferedEarlyLint{id, span, msg, notes, lint_id} in buffered_lints.drain(..) {
             let lint = lint::Lint::from_parser_lint_id(lint_id);
-            sess.buffer_lint(lint, id, span, &msg);
+            if notes.is_empty() {
+                sess.buffer_lint(lint, id, span, &msg);
+            } else {
+                let diagnostic = lint::builtin::BuiltinLintDiagnostics::Notes(notes);
+                sess.buffer_lint_with_diagnostic(lint, id, span, &msg, diagnostic);
+            }
         }
     });
 
@@ -674,6 +744,12 @@ fn write_out_deps(compiler: &Compiler, outputs: &OutputFilenames, out_filenames:
             .filter(|fmap| !fmap.is_imported())
             .map(|fmap| escape_dep_filename(&fmap.unmapped_path.as_ref().unwrap_or(&fmap.name)))
             .collect();
+        for path in sess.source_map().binary_files() {
+            let path = escape_dep_filename(&FileName::Real(path));
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
 
         if sess.binary_dep_depinfo() {
             for cnum in compiler.cstore.crates_untracked() {
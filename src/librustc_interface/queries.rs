@@ -14,6 +14,7 @@ use std::sync::mpsc;
 use std::any::Any;
 use std::mem;
 use syntax::{self, ast};
+use syntax::util::iterative_drop;
 
 /// Represent the result of a query.
 /// This result can be stolen with the `take` method and returned with the `give` method.
@@ -278,8 +279,11 @@ impl Compiler {
 
         self.global_ctxt()?;
 
-        // Drop AST after creating GlobalCtxt to free memory
-        mem::drop(self.expansion()?.take());
+        // Drop AST after creating GlobalCtxt to free memory. The crate is dismantled
+        // iteratively because deeply nested generated code overflows the derived destructors.
+        let (krate, resolver) = self.expansion()?.take();
+        iterative_drop::drop_crate_iteratively(krate);
+        mem::drop(resolver);
 
         self.ongoing_codegen()?;
 
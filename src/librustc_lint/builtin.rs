@@ -39,11 +39,10 @@ use syntax::ast::{self, Expr};
 use syntax::ptr::P;
 use syntax::attr::{self, HasAttrs, AttributeTemplate};
 use syntax::source_map::Spanned;
-use syntax::edition::Edition;
 use syntax::feature_gate::{self, AttributeGate, AttributeType};
 use syntax::feature_gate::{Stability, deprecated_attributes};
 use syntax_pos::{BytePos, Span};
-use syntax::symbol::{Symbol, kw, sym};
+use syntax::symbol::{EditionKeywords, Symbol, kw, sym};
 use syntax::errors::{Applicability, DiagnosticBuilder};
 use syntax::print::pprust::expr_to_string;
 use syntax::visit::FnKind;
@@ -1439,33 +1438,27 @@ impl KeywordIdents {
                          UnderMacro(under_macro): UnderMacro,
                          ident: ast::Ident)
     {
-        let next_edition = match cx.sess.edition() {
-            Edition::Edition2015 => {
-                match ident.name {
-                    kw::Async | kw::Await | kw::Try => Edition::Edition2018,
-
-                    // rust-lang/rust#56327: Conservatively do not
-                    // attempt to report occurrences of `dyn` within
-                    // macro definitions or invocations, because `dyn`
-                    // can legitimately occur as a contextual keyword
-                    // in 2015 code denoting its 2018 meaning, and we
-                    // do not want rustfix to inject bugs into working
-                    // code by rewriting such occurrences.
-                    //
-                    // But if we see `dyn` outside of a macro, we know
-                    // its precise role in the parsed AST and thus are
-                    // assured this is truly an attempt to use it as
-                    // an identifier.
-                    kw::Dyn if !under_macro => Edition::Edition2018,
-
-                    _ => return,
-                }
-            }
-
-            // There are no new keywords yet for the 2018 edition and beyond.
+        let next_edition = match EditionKeywords::get(ident.name) {
+            Some(keyword) if keyword.edition > cx.sess.edition() => keyword.edition,
             _ => return,
         };
 
+        // rust-lang/rust#56327: Conservatively do not
+        // attempt to report occurrences of `dyn` within
+        // macro definitions or invocations, because `dyn`
+        // can legitimately occur as a contextual keyword
+        // in 2015 code denoting its 2018 meaning, and we
+        // do not want rustfix to inject bugs into working
+        // code by rewriting such occurrences.
+        //
+        // But if we see `dyn` outside of a macro, we know
+        // its precise role in the parsed AST and thus are
+        // assured this is truly an attempt to use it as
+        // an identifier.
+        if ident.name == kw::Dyn && under_macro {
+            return;
+        }
+
         // Don't lint `r#foo`.
         if cx.sess.parse_sess.raw_identifier_spans.borrow().contains(&ident.span) {
             return;
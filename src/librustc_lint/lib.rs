@@ -41,6 +41,7 @@ use rustc::lint::builtin::{
     MISSING_DOC_CODE_EXAMPLES,
     PRIVATE_DOC_TESTS,
     parser::ILL_FORMED_ATTRIBUTE_INPUT,
+    parser::GENERATED_IDENT_STYLE,
 };
 use rustc::session;
 use rustc::hir;
@@ -245,7 +246,8 @@ pub fn register_builtins(store: &mut lint::LintStore, sess: Option<&Session>) {
                     "nonstandard_style",
                     NON_CAMEL_CASE_TYPES,
                     NON_SNAKE_CASE,
-                    NON_UPPER_CASE_GLOBALS);
+                    NON_UPPER_CASE_GLOBALS,
+                    GENERATED_IDENT_STYLE);
 
     add_lint_group!(sess,
                     "unused",
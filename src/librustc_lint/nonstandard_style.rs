@@ -7,6 +7,7 @@ use rustc_target::spec::abi::Abi;
 use lint::{EarlyContext, LateContext, LintContext, LintArray};
 use lint::{EarlyLintPass, LintPass, LateLintPass};
 use syntax::ast;
+use syntax::ast_util::IdentStyle;
 use syntax::attr;
 use syntax::errors::Applicability;
 use syntax::symbol::sym;
@@ -41,84 +42,17 @@ declare_lint! {
 
 declare_lint_pass!(NonCamelCaseTypes => [NON_CAMEL_CASE_TYPES]);
 
-fn char_has_case(c: char) -> bool {
-    c.is_lowercase() || c.is_uppercase()
-}
-
-fn is_camel_case(name: &str) -> bool {
-    let name = name.trim_matches('_');
-    if name.is_empty() {
-        return true;
-    }
-
-    // start with a non-lowercase letter rather than non-uppercase
-    // ones (some scripts don't have a concept of upper/lowercase)
-    !name.chars().next().unwrap().is_lowercase()
-        && !name.contains("__")
-        && !name.chars().collect::<Vec<_>>().windows(2).any(|pair| {
-            // contains a capitalisable character followed by, or preceded by, an underscore
-            char_has_case(pair[0]) && pair[1] == '_' || char_has_case(pair[1]) && pair[0] == '_'
-        })
-}
-
-fn to_camel_case(s: &str) -> String {
-    s.trim_matches('_')
-        .split('_')
-        .filter(|component| !component.is_empty())
-        .map(|component| {
-            let mut camel_cased_component = String::new();
-
-            let mut new_word = true;
-            let mut prev_is_lower_case = true;
-
-            for c in component.chars() {
-                // Preserve the case if an uppercase letter follows a lowercase letter, so that
-                // `camelCase` is converted to `CamelCase`.
-                if prev_is_lower_case && c.is_uppercase() {
-                    new_word = true;
-                }
-
-                if new_word {
-                    camel_cased_component.push_str(&c.to_uppercase().to_string());
-                } else {
-                    camel_cased_component.push_str(&c.to_lowercase().to_string());
-                }
-
-                prev_is_lower_case = c.is_lowercase();
-                new_word = false;
-            }
-
-            camel_cased_component
-        })
-        .fold(
-            (String::new(), None),
-            |(acc, prev): (String, Option<String>), next| {
-                // separate two components with an underscore if their boundary cannot
-                // be distinguished using a uppercase/lowercase case distinction
-                let join = if let Some(prev) = prev {
-                    let l = prev.chars().last().unwrap();
-                    let f = next.chars().next().unwrap();
-                    !char_has_case(l) && !char_has_case(f)
-                } else {
-                    false
-                };
-                (acc + if join { "_" } else { "" } + &next, Some(next))
-            },
-        )
-        .0
-}
-
 impl NonCamelCaseTypes {
     fn check_case(&self, cx: &EarlyContext<'_>, sort: &str, ident: &Ident) {
         let name = &ident.name.as_str();
 
-        if !is_camel_case(name) {
+        if !IdentStyle::Camel.matches(name) {
             let msg = format!("{} `{}` should have an upper camel case name", sort, name);
             cx.struct_span_lint(NON_CAMEL_CASE_TYPES, ident.span, &msg)
                 .span_suggestion(
                     ident.span,
                     "convert the identifier to upper camel case",
-                    to_camel_case(name),
+                    IdentStyle::Camel.convert(name),
                     Applicability::MaybeIncorrect,
                 )
                 .emit();
@@ -166,63 +100,12 @@ declare_lint! {
 declare_lint_pass!(NonSnakeCase => [NON_SNAKE_CASE]);
 
 impl NonSnakeCase {
-    fn to_snake_case(mut str: &str) -> String {
-        let mut words = vec![];
-        // Preserve leading underscores
-        str = str.trim_start_matches(|c: char| {
-            if c == '_' {
-                words.push(String::new());
-                true
-            } else {
-                false
-            }
-        });
-        for s in str.split('_') {
-            let mut last_upper = false;
-            let mut buf = String::new();
-            if s.is_empty() {
-                continue;
-            }
-            for ch in s.chars() {
-                if !buf.is_empty() && buf != "'" && ch.is_uppercase() && !last_upper {
-                    words.push(buf);
-                    buf = String::new();
-                }
-                last_upper = ch.is_uppercase();
-                buf.extend(ch.to_lowercase());
-            }
-            words.push(buf);
-        }
-        words.join("_")
-    }
-
     /// Checks if a given identifier is snake case, and reports a diagnostic if not.
     fn check_snake_case(&self, cx: &LateContext<'_, '_>, sort: &str, ident: &Ident) {
-        fn is_snake_case(ident: &str) -> bool {
-            if ident.is_empty() {
-                return true;
-            }
-            let ident = ident.trim_start_matches('\'');
-            let ident = ident.trim_matches('_');
-
-            let mut allow_underscore = true;
-            ident.chars().all(|c| {
-                allow_underscore = match c {
-                    '_' if !allow_underscore => return false,
-                    '_' => false,
-                    // It would be more obvious to use `c.is_lowercase()`,
-                    // but some characters do not have a lowercase form
-                    c if !c.is_uppercase() => true,
-                    _ => return false,
-                };
-                true
-            })
-        }
-
         let name = &ident.name.as_str();
 
-        if !is_snake_case(name) {
-            let sc = NonSnakeCase::to_snake_case(name);
+        if !IdentStyle::Snake.matches(name) {
+            let sc = IdentStyle::Snake.convert(name);
 
             let msg = format!("{} `{}` should have a snake case name", sort, name);
             let mut err = cx.struct_span_lint(NON_SNAKE_CASE, ident.span, &msg);
@@ -369,8 +252,8 @@ impl NonUpperCaseGlobals {
     fn check_upper_case(cx: &LateContext<'_, '_>, sort: &str, ident: &Ident) {
         let name = &ident.name.as_str();
 
-        if name.chars().any(|c| c.is_lowercase()) {
-            let uc = NonSnakeCase::to_snake_case(&name).to_uppercase();
+        if !IdentStyle::Screaming.matches(name) {
+            let uc = IdentStyle::Screaming.convert(name);
 
             let msg = format!("{} `{}` should have an upper case name", sort, name);
             cx.struct_span_lint(NON_UPPER_CASE_GLOBALS, ident.span, &msg)
@@ -435,6 +318,3 @@ impl<'a, 'tcx> LateLintPass<'a, 'tcx> for NonUpperCaseGlobals {
         }
     }
 }
-
-#[cfg(test)]
-mod tests;
@@ -1314,11 +1314,13 @@ impl<'a, 'tcx> CrateMetadata {
                                       src_hash,
                                       start_pos,
                                       end_pos,
-                                      mut lines,
-                                      mut multibyte_chars,
-                                      mut non_narrow_chars,
+                                      line_index,
                                       name_hash,
                                       .. } = source_file_to_import;
+            let syntax_pos::LineIndex { mut lines,
+                                        mut multibyte_chars,
+                                        mut non_narrow_chars } =
+                line_index.into_inner().expect("decoded source files have their line index");
 
             let source_length = (end_pos - start_pos).to_usize();
 
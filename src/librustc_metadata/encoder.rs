@@ -344,6 +344,8 @@ impl<'tcx> EncodeContext<'tcx> {
 
     fn encode_source_map(&mut self) -> Lazy<[syntax_pos::SourceFile]> {
         let source_map = self.tcx.sess.source_map();
+        // Every local file is encoded with its line index, build them all at once.
+        source_map.precompute_line_indices();
         let all_source_files = source_map.files();
 
         let (working_dir, _cwd_remapped) = self.tcx.sess.working_dir.clone();
@@ -449,6 +449,13 @@ impl<'a> Visitor<'a> for AstValidator<'a> {
             ExprKind::InlineAsm(..) if !self.session.target.target.options.allow_asm => {
                 span_err!(self.session, expr.span, E0472, "asm! is unsupported on this target");
             }
+            // Expression attribute macros can take attributes off `if` expressions during
+            // expansion, so the attributes left are only rejected here.
+            ExprKind::If(..) if !expr.attrs.is_empty() => {
+                // Just point to the first attribute in there...
+                self.err_handler().span_err(expr.attrs[0].span,
+                                            "attributes are not yet allowed on `if` expressions");
+            }
             _ => {}
         }
 
@@ -1082,11 +1082,20 @@ impl<'a, 'b> LateResolutionVisitor<'a, '_> {
                                             }
                                             ImplItemKind::TyAlias(ref ty) => {
                                                 // If this is a trait impl, ensure the type
-                                                // exists in trait
-                                                this.check_trait_item(impl_item.ident,
-                                                                      TypeNS,
-                                                                      impl_item.span,
-                                                    |n, s| TypeNotMemberOfTrait(n, s));
+                                                // exists in trait, unless it's a stub for
+                                                // a failed macro, see
+                                                // `DummyResult::raw_impl_item_stub`.
+                                                let is_stub = if let TyKind::Err = ty.node {
+                                                    true
+                                                } else {
+                                                    false
+                                                };
+                                                if !is_stub {
+                                                    this.check_trait_item(impl_item.ident,
+                                                                          TypeNS,
+                                                                          impl_item.span,
+                                                        |n, s| TypeNotMemberOfTrait(n, s));
+                                                }
 
                                                 this.visit_ty(ty);
                                             }
@@ -920,6 +920,9 @@ pub struct Resolver<'a> {
                                            Option<&'a NameBinding<'a>>)>,
     multi_segment_macro_resolutions: Vec<(Vec<Segment>, Span, MacroKind, ParentScope<'a>,
                                           Option<Res>)>,
+    /// Paths that macros asked about with `ExtCtxt::path_resolves`, with the answers they got,
+    /// validated like the macro resolutions above.
+    expansion_path_resolutions: Vec<(Vec<Segment>, Span, Namespace, ParentScope<'a>, bool)>,
     builtin_attrs: Vec<(Ident, ParentScope<'a>)>,
     /// Some built-in derives mark items they are applied to so they are treated specially later.
     /// Derive macros cannot modify the item themselves and have to store the markers in the global
@@ -1184,6 +1187,7 @@ impl<'a> Resolver<'a> {
             proc_macro_stubs: Default::default(),
             single_segment_macro_resolutions: Default::default(),
             multi_segment_macro_resolutions: Default::default(),
+            expansion_path_resolutions: Default::default(),
             builtin_attrs: Default::default(),
             special_derives: Default::default(),
             active_features:
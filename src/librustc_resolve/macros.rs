@@ -3,20 +3,22 @@
 
 use crate::{AmbiguityError, AmbiguityKind, AmbiguityErrorMisc, Determinacy};
 use crate::{CrateLint, Resolver, ResolutionError, Scope, ScopeSet, ParentScope, Weak};
-use crate::{ModuleKind, NameBinding, PathResult, Segment, ToNameBinding};
+use crate::{ModuleKind, NameBinding, Namespace, PathResult, Segment, ToNameBinding};
 use crate::{ModuleOrUniformRoot, KNOWN_TOOLS};
 use crate::Namespace::*;
 use crate::resolve_imports::ImportResolver;
 use rustc::hir::def::{self, DefKind, NonMacroAttrKind};
+use rustc::hir::def_id::DefId;
 use rustc::middle::stability;
 use rustc::{ty, lint, span_bug};
 use syntax::ast::{self, NodeId, Ident};
 use syntax::attr::StabilityLevel;
 use syntax::edition::Edition;
 use syntax::ext::base::{self, InvocationRes, Indeterminate, SpecialDerives};
-use syntax::ext::base::{MacroKind, SyntaxExtension};
+use syntax::ext::base::{MacroKind, PathNamespace, SyntaxExtension};
 use syntax::ext::expand::{AstFragment, Invocation, InvocationKind};
 use syntax::ext::hygiene::{self, ExpnId, ExpnData, ExpnKind};
+use syntax::ext::manifest::MacroManifest;
 use syntax::ext::tt::macro_rules;
 use syntax::feature_gate::{emit_feature_err, is_builtin_attr_name};
 use syntax::feature_gate::GateIssue;
@@ -95,51 +97,6 @@ impl<'a> base::Resolver for Resolver<'a> {
         self.session.next_node_id()
     }
 
-    fn get_module_scope(&mut self, id: NodeId) -> ExpnId {
-        let expn_id = ExpnId::fresh(Some(ExpnData::default(
-            ExpnKind::Macro(MacroKind::Attr, sym::test_case), DUMMY_SP, self.session.edition()
-        )));
-        let module = self.module_map[&self.definitions.local_def_id(id)];
-        self.invocation_parent_scopes.insert(expn_id, ParentScope::module(module));
-        self.definitions.set_invocation_parent(expn_id, module.def_id().unwrap().index);
-        expn_id
-    }
-
-    fn resolve_dollar_crates(&mut self) {
-        hygiene::update_dollar_crate_names(|ctxt| {
-            let ident = Ident::new(kw::DollarCrate, DUMMY_SP.with_ctxt(ctxt));
-            match self.resolve_crate_root(ident).kind {
-                ModuleKind::Def(.., name) if name != kw::Invalid => name,
-                _ => kw::Crate,
-            }
-        });
-    }
-
-    // FIXME: `extra_placeholders` should be included into the `fragment` as regular placeholders.
-    fn visit_ast_fragment_with_placeholders(
-        &mut self, expansion: ExpnId, fragment: &AstFragment, extra_placeholders: &[NodeId]
-    ) {
-        // Integrate the new AST fragment into all the definition and module structures.
-        // We are inside the `expansion` now, but other parent scope components are still the same.
-        let parent_scope = ParentScope { expansion, ..self.invocation_parent_scopes[&expansion] };
-        let output_legacy_scope =
-            self.build_reduced_graph(fragment, extra_placeholders, parent_scope);
-        self.output_legacy_scopes.insert(expansion, output_legacy_scope);
-
-        parent_scope.module.unexpanded_invocations.borrow_mut().remove(&expansion);
-    }
-
-    fn register_builtin_macro(&mut self, ident: ast::Ident, ext: SyntaxExtension) {
-        if self.builtin_macros.insert(ident.name, ext).is_some() {
-            self.session.span_err(ident.span,
-                                  &format!("built-in macro `{}` was already defined", ident));
-        }
-    }
-
-    fn resolve_imports(&mut self) {
-        ImportResolver { r: self }.resolve_imports()
-    }
-
     fn resolve_macro_invocation(
         &mut self, invoc: &Invocation, eager_expansion_root: ExpnId, force: bool
     ) -> Result<InvocationRes, Indeterminate> {
@@ -188,9 +145,29 @@ impl<'a> base::Resolver for Resolver<'a> {
 
         // Derives are not included when `invocations` are collected, so we have to add them here.
         let parent_scope = &ParentScope { derives, ..parent_scope };
-        let (ext, res) = self.smart_resolve_macro_path(path, kind, parent_scope, force)?;
-
         let span = invoc.span();
+        if invoc.is_unconfigured() {
+            // The item is removed unless the macro wants to see it, so nothing is reported
+            // about the macro or its stability before that's known.
+            match self.resolve_macro_path(path, Some(kind), parent_scope, false, force) {
+                Ok((Some(ref ext), _)) if ext.sees_unconfigured => {}
+                Err(Determinacy::Undetermined) => return Err(Indeterminate),
+                _ => {
+                    let ext = self.dummy_ext(kind);
+                    invoc_id.set_expn_data(
+                        ext.expn_data(parent_scope.expansion, span, fast_print_path(path))
+                    );
+                    return Ok(InvocationRes::Single(ext));
+                }
+            }
+        }
+
+        let mac = match invoc.kind {
+            InvocationKind::Bang { ref mac, .. } => Some(mac),
+            _ => None,
+        };
+        let (ext, res) = self.smart_resolve_macro_path(path, kind, mac, parent_scope, force)?;
+
         invoc_id.set_expn_data(ext.expn_data(parent_scope.expansion, span, fast_print_path(path)));
 
         if let Res::Def(_, def_id) = res {
@@ -204,6 +181,84 @@ impl<'a> base::Resolver for Resolver<'a> {
 
         Ok(InvocationRes::Single(ext))
     }
+}
+
+impl<'a> base::ExpansionScopes for Resolver<'a> {
+    fn get_module_scope(&mut self, id: NodeId) -> ExpnId {
+        let expn_id = ExpnId::fresh(Some(ExpnData::default(
+            ExpnKind::Macro(MacroKind::Attr, sym::test_case), DUMMY_SP, self.session.edition()
+        )));
+        let module = self.module_map[&self.definitions.local_def_id(id)];
+        self.invocation_parent_scopes.insert(expn_id, ParentScope::module(module));
+        self.definitions.set_invocation_parent(expn_id, module.def_id().unwrap().index);
+        expn_id
+    }
+
+    fn lint_node_id(&mut self, expn_id: ExpnId) -> NodeId {
+        // Eagerly expanded invocations have no parent of their own and fall back to the crate.
+        self.definitions.opt_invocation_parent(expn_id)
+            .and_then(|index| self.definitions.as_local_node_id(DefId::local(index)))
+            .unwrap_or(ast::CRATE_NODE_ID)
+    }
+
+    fn resolve_path_at_expansion(
+        &mut self, path: &ast::Path, ns: PathNamespace, expn_id: ExpnId
+    ) -> Result<bool, Indeterminate> {
+        // Eagerly expanded invocations have no scope of their own, they are only resolved once
+        // their root is expanded.
+        let parent_scope = match self.invocation_parent_scopes.get(&expn_id) {
+            Some(parent_scope) => *parent_scope,
+            None => return Err(Indeterminate),
+        };
+        let ns = match ns {
+            PathNamespace::Type => TypeNS,
+            PathNamespace::Value => ValueNS,
+            PathNamespace::Macro => MacroNS,
+        };
+        let mut segments = Segment::from_path(path);
+        for seg in &mut segments { seg.id = None; }
+        let found = self.path_resolves(&segments, ns, &parent_scope, path.span)?;
+        // The macro acts on the answer, so it must not change until the end of expansion.
+        self.expansion_path_resolutions.push((segments, path.span, ns, parent_scope, found));
+        Ok(found)
+    }
+
+    fn resolve_dollar_crates(&mut self) {
+        hygiene::update_dollar_crate_names(|ctxt| {
+            let ident = Ident::new(kw::DollarCrate, DUMMY_SP.with_ctxt(ctxt));
+            match self.resolve_crate_root(ident).kind {
+                ModuleKind::Def(.., name) if name != kw::Invalid => name,
+                _ => kw::Crate,
+            }
+        });
+    }
+
+    // FIXME: `extra_placeholders` should be included into the `fragment` as regular placeholders.
+    fn visit_ast_fragment_with_placeholders(
+        &mut self, expansion: ExpnId, fragment: &AstFragment, extra_placeholders: &[NodeId]
+    ) {
+        // Integrate the new AST fragment into all the definition and module structures.
+        // We are inside the `expansion` now, but other parent scope components are still the same.
+        let parent_scope = ParentScope { expansion, ..self.invocation_parent_scopes[&expansion] };
+        let output_legacy_scope =
+            self.build_reduced_graph(fragment, extra_placeholders, parent_scope);
+        self.output_legacy_scopes.insert(expansion, output_legacy_scope);
+
+        parent_scope.module.unexpanded_invocations.borrow_mut().remove(&expansion);
+    }
+
+    fn resolve_imports(&mut self) {
+        ImportResolver { r: self }.resolve_imports()
+    }
+}
+
+impl<'a> base::MacroRegistry for Resolver<'a> {
+    fn register_builtin_macro(&mut self, ident: ast::Ident, ext: SyntaxExtension) {
+        if self.builtin_macros.insert(ident.name, ext).is_some() {
+            self.session.span_err(ident.span,
+                                  &format!("built-in macro `{}` was already defined", ident));
+        }
+    }
 
     fn check_unused_macros(&self) {
         for (&node_id, &span) in self.unused_macros.iter() {
@@ -212,7 +267,9 @@ impl<'a> base::Resolver for Resolver<'a> {
             );
         }
     }
+}
 
+impl<'a> base::DeriveRegistry for Resolver<'a> {
     fn has_derives(&self, expn_id: ExpnId, derives: SpecialDerives) -> bool {
         self.has_derives(expn_id, derives)
     }
@@ -228,6 +285,7 @@ impl<'a> Resolver<'a> {
         &mut self,
         path: &ast::Path,
         kind: MacroKind,
+        mac: Option<&ast::Mac>,
         parent_scope: &ParentScope<'a>,
         force: bool,
     ) -> Result<(Lrc<SyntaxExtension>, Res), Indeterminate> {
@@ -276,7 +334,7 @@ impl<'a> Resolver<'a> {
             _ => panic!("expected `DefKind::Macro` or `Res::NonMacroAttr`"),
         };
 
-        self.check_stability_and_deprecation(&ext, path);
+        self.check_stability_and_deprecation(&ext, path, mac);
 
         Ok(if ext.macro_kind() != kind {
             let expected = if kind == MacroKind::Attr { "attribute" } else  { kind.descr() };
@@ -740,6 +798,24 @@ impl<'a> Resolver<'a> {
             }
         }
 
+        let path_resolutions = mem::take(&mut self.expansion_path_resolutions);
+        for (path, span, ns, parent_scope, initial_found) in path_resolutions {
+            match self.path_resolves(&path, ns, &parent_scope, span) {
+                Ok(found) if found != initial_found => {
+                    let msg = format!("cannot determine whether `{}` resolves",
+                                      Segment::names_to_string(&path));
+                    let note = if initial_found {
+                        "a macro was told that the path resolves, but it doesn't after expansion"
+                    } else {
+                        "a macro was told that the path doesn't resolve, but it does after \
+                         expansion"
+                    };
+                    self.session.struct_span_err(span, &msg).note(note).emit();
+                }
+                _ => {}
+            }
+        }
+
         let builtin_attrs = mem::take(&mut self.builtin_attrs);
         for (ident, parent_scope) in builtin_attrs {
             let _ = self.early_resolve_ident_in_lexical_scope(
@@ -748,7 +824,22 @@ impl<'a> Resolver<'a> {
         }
     }
 
-    fn check_stability_and_deprecation(&self, ext: &SyntaxExtension, path: &ast::Path) {
+    /// Resolves `path` speculatively, returning whether it resolves to something in `ns`.
+    fn path_resolves(&mut self, path: &[Segment], ns: Namespace, parent_scope: &ParentScope<'a>,
+                     span: Span) -> Result<bool, Indeterminate> {
+        match self.resolve_path(path, Some(ns), parent_scope, false, span, CrateLint::No) {
+            PathResult::Module(..) => Ok(ns == TypeNS),
+            PathResult::NonModule(path_res) => {
+                Ok(path_res.unresolved_segments() == 0 && path_res.base_res() != Res::Err)
+            }
+            PathResult::Indeterminate => Err(Indeterminate),
+            PathResult::Failed { .. } => Ok(false),
+        }
+    }
+
+    fn check_stability_and_deprecation(
+        &self, ext: &SyntaxExtension, path: &ast::Path, mac: Option<&ast::Mac>
+    ) {
         let span = path.span;
         if let Some(stability) = &ext.stability {
             if let StabilityLevel::Unstable { reason, issue } = stability.level {
@@ -766,7 +857,16 @@ impl<'a> Resolver<'a> {
         }
         if let Some(depr) = &ext.deprecation {
             let (message, lint) = stability::deprecation_message(depr, &path.to_string());
-            stability::early_report_deprecation(self.session, &message, None, lint, span);
+            // A replacement template rewrites the whole invocation, not just the path.
+            let replacement = mac.and_then(|mac| {
+                let replacement = ext.deprecation_replacement(mac, self.session.source_map())?;
+                Some((Symbol::intern(&replacement), mac.span))
+            });
+            let (suggestion, span) = match replacement {
+                Some((replacement, mac_span)) => (Some(replacement), mac_span),
+                None => (None, span),
+            };
+            stability::early_report_deprecation(self.session, &message, suggestion, lint, span);
         }
     }
 
@@ -797,6 +897,24 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    /// Summarizes the macros defined in the current crate, see `MacroManifest`.
+    pub fn macro_manifest(&self, crate_name: &str) -> MacroManifest {
+        let mut macros: Vec<_> = self.macro_map.iter().filter_map(|(def_id, ext)| {
+            if !def_id.is_local() {
+                return None;
+            }
+            let def_key = self.definitions.def_key(def_id.index);
+            Some((def_key.disambiguated_data.data.get_opt_name()?.as_symbol(), ext))
+        }).collect();
+        macros.sort_by_cached_key(|(name, _)| name.as_str());
+
+        let mut manifest = MacroManifest::new(crate_name.to_string());
+        for (name, ext) in macros {
+            manifest.add(name, ext);
+        }
+        manifest
+    }
+
     /// Compile the macro into a `SyntaxExtension` and possibly replace it with a pre-defined
     /// extension partially or entirely for built-in macros and legacy plugin macros.
     crate fn compile_macro(&mut self, item: &ast::Item, edition: Edition) -> SyntaxExtension {
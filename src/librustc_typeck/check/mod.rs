@@ -1639,6 +1639,17 @@ fn check_specialization_validity<'tcx>(
 
 }
 
+/// Whether `impl_item` stands in for an item that a macro failed to produce, see
+/// `DummyResult::raw_impl_item_stub`.
+crate fn is_failed_macro_stub(impl_item: &hir::ImplItem) -> bool {
+    match impl_item.node {
+        hir::ImplItemKind::TyAlias(ref ty) => {
+            if let hir::TyKind::Err = ty.node { true } else { false }
+        }
+        _ => false,
+    }
+}
+
 fn check_impl_items_against_trait<'tcx>(
     tcx: TyCtxt<'tcx>,
     impl_span: Span,
@@ -1659,9 +1670,17 @@ fn check_impl_items_against_trait<'tcx>(
 
     let impl_items = || impl_item_refs.iter().map(|iiref| tcx.hir().impl_item(iiref.id));
 
+    // The stubs for the items of macros that failed to expand aren't checked, but the trait
+    // items named like them may well have been defined by these macros, so they aren't
+    // reported as missing.
+    let stub_names = impl_items()
+        .filter(|impl_item| is_failed_macro_stub(impl_item))
+        .map(|impl_item| impl_item.ident.name)
+        .collect::<Vec<_>>();
+
     // Check existing impl methods to see if they are both present in trait
     // and compatible with trait signature
-    for impl_item in impl_items() {
+    for impl_item in impl_items().filter(|impl_item| !is_failed_macro_stub(impl_item)) {
         let ty_impl_item = tcx.associated_item(
             tcx.hir().local_def_id(impl_item.hir_id));
         let ty_trait_item = tcx.associated_items(impl_trait_ref.def_id)
@@ -1759,7 +1778,9 @@ fn check_impl_items_against_trait<'tcx>(
 
         if !is_implemented && !tcx.impl_is_default(impl_id) {
             if !trait_item.defaultness.has_value() {
-                missing_items.push(trait_item);
+                if !stub_names.contains(&trait_item.ident.name) {
+                    missing_items.push(trait_item);
+                }
             } else if associated_type_overridden {
                 invalidated_items.push(trait_item.ident);
             }
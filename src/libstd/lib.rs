@@ -304,6 +304,7 @@
 #![feature(stdsimd)]
 #![feature(stmt_expr_attributes)]
 #![feature(str_internals)]
+#![feature(stringify_variants)]
 #![feature(test)]
 #![feature(thread_local)]
 #![feature(todo_macro)]
@@ -554,6 +555,8 @@ pub use core::{
     format_args_nl,
     global_asm,
     log_syntax,
+    stringify_source,
+    stringify_tokens,
     trace_macros,
 };
 
@@ -11,7 +11,7 @@ use crate::print::pprust;
 use crate::ptr::P;
 use crate::source_map::{dummy_spanned, respan, Spanned};
 use crate::symbol::{kw, sym, Symbol};
-use crate::tokenstream::TokenStream;
+use crate::tokenstream::{DelimSpan, TokenStream, TokenTree};
 use crate::ThinVec;
 
 use rustc_data_structures::indexed_vec::Idx;
@@ -903,6 +903,16 @@ pub enum MacStmtStyle {
     NoBraces,
 }
 
+impl MacStmtStyle {
+    /// The style of a macro statement delimited with `delim`, before any trailing semicolon.
+    pub fn from_delim(delim: MacDelimiter) -> MacStmtStyle {
+        match MacPosition::Stmt.semicolon(delim) {
+            MacSemicolon::Required => MacStmtStyle::NoBraces,
+            _ => MacStmtStyle::Braces,
+        }
+    }
+}
+
 /// Local represents a `let` statement, e.g., `let <pat>:<ty> = <expr>;`.
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
 pub struct Local {
@@ -925,6 +935,9 @@ pub struct Local {
 ///     _ => { println!("no match!") },
 /// }
 /// ```
+///
+/// A macro invocation in arm position, e.g., `arms!(),`, is an arm without patterns whose
+/// body is the `ExprKind::Mac`, see `Arm::mac`. Those only exist until expansion.
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
 pub struct Arm {
     pub attrs: Vec<Attribute>,
@@ -935,6 +948,16 @@ pub struct Arm {
     pub id: NodeId,
 }
 
+impl Arm {
+    /// Returns the macro invocation if this arm is one.
+    pub fn mac(&self) -> Option<&Mac> {
+        match self.body.node {
+            ExprKind::Mac(ref mac) if self.pats.is_empty() => Some(mac),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
 pub struct Field {
     pub ident: Ident,
@@ -1327,6 +1350,57 @@ impl MacDelimiter {
             MacDelimiter::Brace => DelimToken::Brace,
         }
     }
+
+    crate fn from_token(delim: DelimToken) -> Option<MacDelimiter> {
+        match delim {
+            DelimToken::Paren => Some(MacDelimiter::Parenthesis),
+            DelimToken::Bracket => Some(MacDelimiter::Bracket),
+            DelimToken::Brace => Some(MacDelimiter::Brace),
+            DelimToken::NoDelim => None,
+        }
+    }
+}
+
+/// The kind of position a macro invocation is written in, which determines whether its
+/// delimiters must be followed by a semicolon, see `MacPosition::semicolon`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MacPosition {
+    /// An item, trait item, impl item or foreign item, e.g., `m! { ... }` or `m!(...);`.
+    Item,
+    /// A statement in a block, e.g., `m! { ... }` or `m!(...);`.
+    Stmt,
+    /// An expression, pattern or type, e.g., `m!(...)`.
+    Expr,
+}
+
+/// Whether a semicolon follows a macro invocation, as part of it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MacSemicolon {
+    /// The invocation must be followed by a semicolon.
+    Required,
+    /// The invocation may be followed by a semicolon.
+    Optional,
+    /// A semicolon following the invocation isn't part of it, and is an error if it can't be
+    /// parsed as something else.
+    NotAllowed,
+}
+
+impl MacPosition {
+    /// Returns whether an invocation delimited with `delim` in this position is followed by a
+    /// semicolon.
+    ///
+    /// An invocation in statement position that isn't delimited with braces requires a
+    /// semicolon, unless it's the last statement of its block, in which case it's parsed as the
+    /// trailing expression.
+    pub fn semicolon(self, delim: MacDelimiter) -> MacSemicolon {
+        match (self, delim) {
+            (MacPosition::Item, MacDelimiter::Brace) => MacSemicolon::NotAllowed,
+            (MacPosition::Item, _) => MacSemicolon::Required,
+            (MacPosition::Stmt, MacDelimiter::Brace) => MacSemicolon::Optional,
+            (MacPosition::Stmt, _) => MacSemicolon::Required,
+            (MacPosition::Expr, _) => MacSemicolon::NotAllowed,
+        }
+    }
 }
 
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
@@ -1354,7 +1428,8 @@ pub enum StrStyle {
 /// An AST literal.
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
 pub struct Lit {
-    /// The original literal token as written in source code.
+    /// The original literal token as written in source code. Use `Lit::token`, which also
+    /// builds the token of the byte strings of `Lit::from_byte_blob`, whose symbol is empty here.
     pub token: token::Lit,
     /// The "semantic" representation of the literal lowered from the original tokens.
     /// Strings are unescaped, hexadecimal forms are eliminated, etc.
@@ -1792,6 +1867,10 @@ pub struct InlineAsm {
 /// A parameter in a function header.
 ///
 /// E.g., `bar: usize` as in `fn foo(bar: usize)`.
+///
+/// A macro invocation in parameter position, e.g., `params!()` in `fn foo(params!())` or
+/// `|params!()| ()`, is a parameter whose pattern is the `PatKind::Mac` and whose type is
+/// inferred, see `Param::mac`. Those only exist until expansion.
 #[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
 pub struct Param {
     pub attrs: ThinVec<Attribute>,
@@ -1817,6 +1896,14 @@ pub enum SelfKind {
 pub type ExplicitSelf = Spanned<SelfKind>;
 
 impl Param {
+    /// Returns the macro invocation if this parameter is one.
+    pub fn mac(&self) -> Option<&Mac> {
+        match (&self.pat.node, &self.ty.node) {
+            (PatKind::Mac(mac), TyKind::Infer) => Some(mac),
+            _ => None,
+        }
+    }
+
     pub fn to_self(&self) -> Option<ExplicitSelf> {
         if let PatKind::Ident(BindingMode::ByValue(mutbl), ident, _) = self.pat.node {
             if ident.name == kw::SelfLower {
@@ -2136,11 +2223,67 @@ pub struct Attribute {
     pub id: AttrId,
     pub style: AttrStyle,
     pub path: Path,
-    pub tokens: TokenStream,
+    pub args: AttrArgs,
     pub is_sugared_doc: bool,
     pub span: Span,
 }
 
+/// The arguments of an attribute, i.e., everything after its path. They are classified when
+/// the attribute is parsed, so that the value of `#[key = value]` doesn't need to be parsed
+/// again every time it's used.
+#[derive(Clone, RustcEncodable, RustcDecodable, Debug)]
+pub enum AttrArgs {
+    /// No arguments: `#[attr]`.
+    Empty,
+    /// Delimited arguments: `#[attr(...)]`, `#[attr[...]]` or `#[attr{...}]`.
+    Delimited(DelimSpan, MacDelimiter, TokenStream),
+    /// A value: `#[attr = value]`. The span is the one of `=`. The value is a literal, or an
+    /// expression passed to a macro, e.g., the `$doc` of `#[doc = $doc]`.
+    Eq(Span, P<Expr>),
+}
+
+impl AttrArgs {
+    /// The tokens inside the delimiters, or the tokens of the value after `=`.
+    pub fn inner_tokens(&self) -> TokenStream {
+        match self {
+            AttrArgs::Empty => TokenStream::empty(),
+            AttrArgs::Delimited(.., tokens) => tokens.clone(),
+            AttrArgs::Eq(_, expr) => match &expr.node {
+                ExprKind::Lit(lit) => lit.tokens(),
+                _ => {
+                    let nt = token::Interpolated(Lrc::new(token::NtExpr(expr.clone())));
+                    TokenTree::token(nt, expr.span).into()
+                }
+            },
+        }
+    }
+
+    /// The tokens after the path of the attribute, including the delimiters or `=`, which is
+    /// how attribute arguments were stored before they were classified.
+    pub fn outer_tokens(&self) -> TokenStream {
+        match self {
+            AttrArgs::Empty => TokenStream::empty(),
+            AttrArgs::Delimited(dspan, delim, tokens) => {
+                TokenTree::Delimited(*dspan, delim.to_token(), tokens.clone()).into()
+            }
+            AttrArgs::Eq(eq_span, _) => {
+                let mut trees = vec![TokenTree::token(token::Eq, *eq_span).into()];
+                self.inner_tokens().append_to_tree_and_joint_vec(&mut trees);
+                TokenStream::new(trees)
+            }
+        }
+    }
+
+    /// The span of the arguments, including the delimiters or `=`, if there are any.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            AttrArgs::Empty => None,
+            AttrArgs::Delimited(dspan, ..) => Some(dspan.entire()),
+            AttrArgs::Eq(eq_span, expr) => Some(eq_span.to(expr.span)),
+        }
+    }
+}
+
 /// `TraitRef`s appear in impls.
 ///
 /// Resolution maps each `TraitRef`'s `ref_id` to its defining trait; that's all
@@ -2254,6 +2397,106 @@ impl VariantData {
     }
 }
 
+/// A struct, enum or union item, giving uniform access to its variants and fields.
+///
+/// A struct or union is treated as an enum with a single, unnamed variant.
+#[derive(Clone, Copy, Debug)]
+pub struct StructLike<'a> {
+    pub item: &'a Item,
+    pub kind: StructLikeKind<'a>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum StructLikeKind<'a> {
+    Struct(&'a VariantData),
+    Enum(&'a EnumDef),
+    Union(&'a VariantData),
+}
+
+/// A variant of an enum, or the body of a struct or union.
+#[derive(Clone, Copy, Debug)]
+pub struct StructLikeVariant<'a> {
+    /// The enum variant, `None` for a struct or union.
+    pub variant: Option<&'a Variant>,
+    pub data: &'a VariantData,
+    /// The span of the enum variant, or of the whole struct or union.
+    pub span: Span,
+}
+
+impl<'a> StructLike<'a> {
+    pub fn from_item(item: &'a Item) -> Option<StructLike<'a>> {
+        let kind = match item.node {
+            ItemKind::Struct(ref data, _) => StructLikeKind::Struct(data),
+            ItemKind::Enum(ref def, _) => StructLikeKind::Enum(def),
+            ItemKind::Union(ref data, _) => StructLikeKind::Union(data),
+            _ => return None,
+        };
+        Some(StructLike { item, kind })
+    }
+
+    /// The variants of an enum in order, or the single variant of a struct or union.
+    pub fn variants(self) -> Vec<StructLikeVariant<'a>> {
+        match self.kind {
+            StructLikeKind::Struct(data) | StructLikeKind::Union(data) => {
+                vec![StructLikeVariant { variant: None, data, span: self.item.span }]
+            }
+            StructLikeKind::Enum(def) => def.variants.iter().map(|variant| StructLikeVariant {
+                variant: Some(variant),
+                data: &variant.data,
+                span: variant.span,
+            }).collect(),
+        }
+    }
+
+    /// The fields of all the variants, in order.
+    pub fn fields(self) -> impl Iterator<Item = &'a StructField> {
+        let (data, variants) = match self.kind {
+            StructLikeKind::Struct(data) | StructLikeKind::Union(data) => (Some(data), &[][..]),
+            StructLikeKind::Enum(def) => (None, &def.variants[..]),
+        };
+        data.into_iter()
+            .chain(variants.iter().map(|variant| &variant.data))
+            .flat_map(|data| data.fields())
+    }
+
+    /// The fields of all the variants that have the attribute `name`, e.g., a derive helper.
+    pub fn fields_with_attr(self, name: Symbol) -> impl Iterator<Item = &'a StructField> {
+        self.fields().filter(move |field| field.attrs.iter().any(|attr| attr.check_name(name)))
+    }
+
+    /// The enum variants that have the attribute `name`. Empty for a struct or union.
+    pub fn variants_with_attr(self, name: Symbol) -> impl Iterator<Item = &'a Variant> {
+        let variants = match self.kind {
+            StructLikeKind::Enum(def) => &def.variants[..],
+            StructLikeKind::Struct(..) | StructLikeKind::Union(..) => &[],
+        };
+        variants.iter()
+            .filter(move |variant| variant.attrs.iter().any(|attr| attr.check_name(name)))
+    }
+}
+
+impl<'a> StructLikeVariant<'a> {
+    /// The name of the enum variant, `None` for a struct or union.
+    pub fn ident(&self) -> Option<Ident> {
+        self.variant.map(|variant| variant.ident)
+    }
+
+    /// The attributes of the enum variant, empty for a struct or union whose attributes are
+    /// those of the item.
+    pub fn attrs(&self) -> &'a [Attribute] {
+        self.variant.map_or(&[], |variant| &variant.attrs)
+    }
+
+    /// The explicit discriminant of the enum variant, e.g., `1` in `Foo = 1`.
+    pub fn disr_expr(&self) -> Option<&'a AnonConst> {
+        self.variant.and_then(|variant| variant.disr_expr.as_ref())
+    }
+
+    pub fn fields(&self) -> &'a [StructField] {
+        self.data.fields()
+    }
+}
+
 /// An item.
 ///
 /// The name might be a dummy name in case of anonymous items.
@@ -6,3 +6,40 @@ fn check_asts_encodable() {
     fn assert_encodable<T: rustc_serialize::Encodable>() {}
     assert_encodable::<Crate>();
 }
+
+#[test]
+fn struct_like_fields_with_attr() {
+    use crate::parse::ParseSess;
+    use crate::source_map::FilePathMapping;
+    use crate::tests::with_error_checking_parse;
+    use crate::with_default_globals;
+
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let source = "enum E { A(#[skip] u8, u16), B { #[skip] x: u32 }, C = 2 }".to_string();
+        let item = with_error_checking_parse(source, &ps, |p| p.parse_item()).unwrap();
+        let struct_like = StructLike::from_item(&item).unwrap();
+
+        let variants = struct_like.variants();
+        assert_eq!(variants.len(), 3);
+        assert!(variants[2].disr_expr().is_some());
+        assert_eq!(struct_like.fields().count(), 3);
+        let skipped: Vec<_> = struct_like.fields_with_attr(Symbol::intern("skip"))
+            .map(|field| pprust::ty_to_string(&field.ty))
+            .collect();
+        assert_eq!(skipped, ["u8", "u32"]);
+    })
+}
+
+#[test]
+fn mac_semicolon_rules() {
+    use MacDelimiter::*;
+
+    assert_eq!(MacPosition::Item.semicolon(Brace), MacSemicolon::NotAllowed);
+    assert_eq!(MacPosition::Item.semicolon(Parenthesis), MacSemicolon::Required);
+    assert_eq!(MacPosition::Stmt.semicolon(Brace), MacSemicolon::Optional);
+    assert_eq!(MacPosition::Stmt.semicolon(Bracket), MacSemicolon::Required);
+    assert_eq!(MacPosition::Expr.semicolon(Brace), MacSemicolon::NotAllowed);
+    assert_eq!(MacStmtStyle::from_delim(Brace), MacStmtStyle::Braces);
+    assert_eq!(MacStmtStyle::from_delim(Parenthesis), MacStmtStyle::NoBraces);
+}
@@ -0,0 +1,152 @@
+//! Stable hashing of AST fragments.
+//!
+//! The hash identifies a fragment by its contents rather than by its place in a particular
+//! compilation session: `NodeId`s are ignored and spans are only taken into account relative to
+//! the start of the fragment, so moving an item around in a file or renumbering the nodes of a
+//! crate doesn't change the hash, while any change to the structure of the fragment, its names
+//! or its literal values does.
+
+use crate::ast;
+use crate::ext::expand::AstFragment;
+use crate::source_map::SourceMap;
+use crate::visit::Visitor;
+
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_serialize::{Encodable, Encoder, SpecializedEncoder};
+use syntax_pos::{Loc, Span};
+
+use std::hash::Hash;
+
+#[cfg(test)]
+mod tests;
+
+/// Computes a hash of `fragment` that is insensitive to `NodeId`s and absolute span positions.
+pub fn stable_hash(fragment: &AstFragment, source_map: &SourceMap) -> u64 {
+    let mut encoder = HashEncoder { source_map, base: None, hasher: StableHasher::new() };
+    fragment.visit_with(&mut encoder);
+    encoder.hasher.finish()
+}
+
+/// Hashes the nodes of a fragment through their `Encodable` implementations. `NodeId`s are
+/// skipped and spans are hashed relative to the first one encoded.
+struct HashEncoder<'a> {
+    source_map: &'a SourceMap,
+    base: Option<Loc>,
+    hasher: StableHasher<u64>,
+}
+
+macro_rules! hash_emitted_values {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method(&mut self, v: $ty) -> Result<(), !> {
+            v.hash(&mut self.hasher);
+            Ok(())
+        }
+    )*}
+}
+
+impl Encoder for HashEncoder<'_> {
+    type Error = !;
+
+    fn emit_unit(&mut self) -> Result<(), !> {
+        Ok(())
+    }
+
+    hash_emitted_values! {
+        emit_usize(usize);
+        emit_u128(u128);
+        emit_u64(u64);
+        emit_u32(u32);
+        emit_u16(u16);
+        emit_u8(u8);
+        emit_isize(isize);
+        emit_i128(i128);
+        emit_i64(i64);
+        emit_i32(i32);
+        emit_i16(i16);
+        emit_i8(i8);
+        emit_bool(bool);
+        emit_char(char);
+        emit_str(&str);
+    }
+
+    fn emit_f64(&mut self, v: f64) -> Result<(), !> {
+        v.to_bits().hash(&mut self.hasher);
+        Ok(())
+    }
+
+    fn emit_f32(&mut self, v: f32) -> Result<(), !> {
+        v.to_bits().hash(&mut self.hasher);
+        Ok(())
+    }
+}
+
+impl SpecializedEncoder<ast::NodeId> for HashEncoder<'_> {
+    fn specialized_encode(&mut self, _: &ast::NodeId) -> Result<(), !> {
+        Ok(())
+    }
+}
+
+impl SpecializedEncoder<Span> for HashEncoder<'_> {
+    fn specialized_encode(&mut self, span: &Span) -> Result<(), !> {
+        if span.is_dummy() {
+            0u8.hash(&mut self.hasher);
+            return Ok(());
+        }
+        let loc = self.source_map.lookup_char_pos(span.lo());
+        let base = self.base.get_or_insert_with(|| loc.clone());
+        if loc.file.name == base.file.name {
+            1u8.hash(&mut self.hasher);
+            (loc.line as isize - base.line as isize).hash(&mut self.hasher);
+            (loc.col.0 as isize - base.col.0 as isize).hash(&mut self.hasher);
+        } else {
+            // Spans pointing into other files (e.g., from macro definitions) are kept apart
+            // from the fragment itself but their position isn't tied to the fragment's.
+            2u8.hash(&mut self.hasher);
+        }
+        (span.hi() - span.lo()).0.hash(&mut self.hasher);
+        Ok(())
+    }
+}
+
+/// Encodes the top-level nodes of a fragment, see `AstFragment::visit_with`.
+impl<'ast> Visitor<'ast> for HashEncoder<'_> {
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        expr.encode(self).unwrap();
+    }
+
+    fn visit_pat(&mut self, pat: &'ast ast::Pat) {
+        pat.encode(self).unwrap();
+    }
+
+    fn visit_ty(&mut self, ty: &'ast ast::Ty) {
+        ty.encode(self).unwrap();
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast ast::Stmt) {
+        stmt.encode(self).unwrap();
+    }
+
+    fn visit_item(&mut self, item: &'ast ast::Item) {
+        item.encode(self).unwrap();
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast ast::TraitItem) {
+        item.encode(self).unwrap();
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast ast::ImplItem) {
+        item.encode(self).unwrap();
+    }
+
+    fn visit_foreign_item(&mut self, item: &'ast ast::ForeignItem) {
+        item.encode(self).unwrap();
+    }
+
+    fn visit_arm(&mut self, arm: &'ast ast::Arm) {
+        arm.encode(self).unwrap();
+    }
+
+    fn visit_param(&mut self, param: &'ast ast::Param) {
+        param.encode(self).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+use super::*;
+
+use crate::ext::expand::AstFragment;
+use crate::parse::ParseSess;
+use crate::source_map::FilePathMapping;
+use crate::tests::with_error_checking_parse;
+use crate::with_default_globals;
+
+fn item_hash(ps: &ParseSess, source: &str) -> u64 {
+    let item = with_error_checking_parse(source.to_string(), ps, |p| p.parse_item()).unwrap();
+    stable_hash(&AstFragment::Items(vec![item].into()), ps.source_map())
+}
+
+#[test]
+fn stable_hash_ignores_position() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let hash = item_hash(&ps, "fn f() {\n    1 + 2\n}");
+        assert_eq!(item_hash(&ps, "\n\n    fn f() {\n        1 + 2\n    }"), hash);
+        assert_ne!(item_hash(&ps, "fn f() {\n    1 + 3\n}"), hash);
+        assert_ne!(item_hash(&ps, "fn f() {\n    1 +\n    2\n}"), hash);
+    })
+}
+
+#[test]
+fn stable_hash_ignores_node_ids() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let source = "fn f() { 1 + 2 }".to_string();
+        let item = with_error_checking_parse(source, &ps, |p| p.parse_item()).unwrap();
+        let mut renumbered = item.clone();
+        renumbered.id = ast::NodeId::from_u32(7);
+        let hash = |item| stable_hash(&AstFragment::Items(vec![item].into()), ps.source_map());
+        assert_eq!(hash(renumbered), hash(item));
+    })
+}
@@ -0,0 +1,462 @@
+//! Summaries of the generic parameters and lifetimes of AST nodes, for code that constructs
+//! matching generics of its own, e.g., the header of a derived impl, and of the error nodes
+//! left in them by recovery from earlier errors. Also comparisons of bounds regardless of their
+//! spans, and checks of the style of identifiers that such code derives from user input.
+
+use crate::ast::{self, GenericBound, GenericParamKind, Ident, ItemKind, NodeId, WherePredicate};
+use crate::early_buffered_lints::BufferedEarlyLintId;
+use crate::ext::expand::AstFragment;
+use crate::parse::ParseSess;
+use crate::ptr::P;
+use crate::symbol::kw;
+use crate::visit::{self, Visitor};
+
+use syntax_pos::{Span, DUMMY_SP};
+
+use std::mem;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParamKind {
+    Lifetime,
+    Type,
+    Const,
+}
+
+/// A generic parameter declared by an item.
+#[derive(Clone, Debug)]
+pub struct ParamInScope {
+    pub ident: Ident,
+    pub kind: ParamKind,
+    /// The bounds declared with the parameter followed by those of the `where` clause
+    /// predicates that bound the parameter alone, e.g., `T: Clone` or `'a: 'b`.
+    pub bounds: Vec<GenericBound>,
+    /// The type of a const parameter.
+    pub const_ty: Option<P<ast::Ty>>,
+}
+
+impl ParamInScope {
+    pub fn span(&self) -> Span {
+        self.ident.span
+    }
+}
+
+/// The generic parameters in scope within an item.
+#[derive(Clone, Debug)]
+pub struct GenericsInScope {
+    pub params: Vec<ParamInScope>,
+    /// The `where` clause predicates that don't bound a single parameter, e.g., `Vec<T>: Debug`.
+    pub other_predicates: Vec<WherePredicate>,
+    /// Whether `Self` is in scope, i.e., the item is a trait or an impl.
+    pub has_self: bool,
+    /// The span of the generics of the item, not including the `where` clause.
+    pub span: Span,
+}
+
+/// Collects the generic parameters that `item` declares for its contents, merging the bounds
+/// its `where` clause puts on each of them.
+pub fn collect_generics_in_scope(item: &ast::Item) -> GenericsInScope {
+    let (generics, has_self) = match item.node {
+        ItemKind::Fn(_, _, ref generics, _) |
+        ItemKind::TyAlias(_, ref generics) |
+        ItemKind::OpaqueTy(_, ref generics) |
+        ItemKind::Enum(_, ref generics) |
+        ItemKind::Struct(_, ref generics) |
+        ItemKind::Union(_, ref generics) |
+        ItemKind::TraitAlias(ref generics, _) => (Some(generics), false),
+        ItemKind::Trait(_, _, ref generics, ..) |
+        ItemKind::Impl(_, _, _, ref generics, ..) => (Some(generics), true),
+        _ => (None, false),
+    };
+    let mut in_scope = GenericsInScope {
+        params: Vec::new(),
+        other_predicates: Vec::new(),
+        has_self,
+        span: generics.map_or(DUMMY_SP, |generics| generics.span),
+    };
+    let generics = match generics {
+        Some(generics) => generics,
+        None => return in_scope,
+    };
+
+    in_scope.params = generics.params.iter().map(|param| ParamInScope {
+        ident: param.ident,
+        kind: match param.kind {
+            GenericParamKind::Lifetime => ParamKind::Lifetime,
+            GenericParamKind::Type { .. } => ParamKind::Type,
+            GenericParamKind::Const { .. } => ParamKind::Const,
+        },
+        bounds: param.bounds.clone(),
+        const_ty: match param.kind {
+            GenericParamKind::Const { ref ty } => Some(ty.clone()),
+            _ => None,
+        },
+    }).collect();
+
+    for predicate in &generics.where_clause.predicates {
+        let bounded = match predicate {
+            WherePredicate::BoundPredicate(predicate)
+                    if predicate.bound_generic_params.is_empty() => {
+                bounded_param(&predicate.bounded_ty)
+                    .map(|ident| (ident, ParamKind::Type, &predicate.bounds))
+            }
+            WherePredicate::RegionPredicate(predicate) => {
+                Some((predicate.lifetime.ident, ParamKind::Lifetime, &predicate.bounds))
+            }
+            _ => None,
+        };
+        let param = bounded.and_then(|(ident, kind, bounds)| {
+            in_scope.params.iter_mut()
+                .find(|param| param.ident == ident && param.kind == kind)
+                .map(|param| (param, bounds))
+        });
+        match param {
+            Some((param, bounds)) => param.bounds.extend(bounds.iter().cloned()),
+            None => in_scope.other_predicates.push(predicate.clone()),
+        }
+    }
+    in_scope
+}
+
+/// The parameter named by `ty`, if it's a plain single-segment path.
+fn bounded_param(ty: &ast::Ty) -> Option<Ident> {
+    match ty.node {
+        ast::TyKind::Path(None, ref path) => match &path.segments[..] {
+            [segment] if segment.args.is_none() => Some(segment.ident),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `a` and `b` are the same bound, regardless of their spans. Bounds with generic
+/// arguments are compared conservatively: types other than paths, references, pointers,
+/// slices, tuples and `!`, and const arguments, are never considered the same.
+pub fn bounds_eq_unspanned(a: &GenericBound, b: &GenericBound) -> bool {
+    match (a, b) {
+        (GenericBound::Outlives(a), GenericBound::Outlives(b)) => a.ident.name == b.ident.name,
+        (GenericBound::Trait(a, a_modifier), GenericBound::Trait(b, b_modifier)) => {
+            a_modifier == b_modifier &&
+            slices_eq(&a.bound_generic_params, &b.bound_generic_params, |a, b| {
+                a.ident.name == b.ident.name && slices_eq(&a.bounds, &b.bounds, bounds_eq_unspanned)
+            }) &&
+            paths_eq_unspanned(&a.trait_ref.path, &b.trait_ref.path)
+        }
+        _ => false,
+    }
+}
+
+fn slices_eq<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| eq(a, b))
+}
+
+fn paths_eq_unspanned(a: &ast::Path, b: &ast::Path) -> bool {
+    slices_eq(&a.segments, &b.segments, |a, b| {
+        a.ident.name == b.ident.name && match (&a.args, &b.args) {
+            (None, None) => true,
+            (Some(a), Some(b)) => generic_args_eq_unspanned(a, b),
+            _ => false,
+        }
+    })
+}
+
+fn generic_args_eq_unspanned(a: &ast::GenericArgs, b: &ast::GenericArgs) -> bool {
+    match (a, b) {
+        (ast::GenericArgs::AngleBracketed(a), ast::GenericArgs::AngleBracketed(b)) => {
+            slices_eq(&a.args, &b.args, |a, b| match (a, b) {
+                (ast::GenericArg::Lifetime(a), ast::GenericArg::Lifetime(b)) => {
+                    a.ident.name == b.ident.name
+                }
+                (ast::GenericArg::Type(a), ast::GenericArg::Type(b)) => tys_eq_unspanned(a, b),
+                _ => false,
+            }) &&
+            slices_eq(&a.constraints, &b.constraints, |a, b| {
+                a.ident.name == b.ident.name && match (&a.kind, &b.kind) {
+                    (
+                        ast::AssocTyConstraintKind::Equality { ty: a },
+                        ast::AssocTyConstraintKind::Equality { ty: b },
+                    ) => tys_eq_unspanned(a, b),
+                    (
+                        ast::AssocTyConstraintKind::Bound { bounds: a },
+                        ast::AssocTyConstraintKind::Bound { bounds: b },
+                    ) => slices_eq(a, b, bounds_eq_unspanned),
+                    _ => false,
+                }
+            })
+        }
+        (ast::GenericArgs::Parenthesized(a), ast::GenericArgs::Parenthesized(b)) => {
+            slices_eq(&a.inputs, &b.inputs, |a, b| tys_eq_unspanned(a, b)) &&
+            match (&a.output, &b.output) {
+                (None, None) => true,
+                (Some(a), Some(b)) => tys_eq_unspanned(a, b),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn tys_eq_unspanned(a: &ast::Ty, b: &ast::Ty) -> bool {
+    let mut_tys_eq = |a: &ast::MutTy, b: &ast::MutTy| {
+        a.mutbl == b.mutbl && tys_eq_unspanned(&a.ty, &b.ty)
+    };
+    match (&a.node, &b.node) {
+        (ast::TyKind::Path(None, a), ast::TyKind::Path(None, b)) => paths_eq_unspanned(a, b),
+        (ast::TyKind::Rptr(a_lifetime, a), ast::TyKind::Rptr(b_lifetime, b)) => {
+            a_lifetime.map(|lifetime| lifetime.ident.name) ==
+                b_lifetime.map(|lifetime| lifetime.ident.name) &&
+            mut_tys_eq(a, b)
+        }
+        (ast::TyKind::Ptr(a), ast::TyKind::Ptr(b)) => mut_tys_eq(a, b),
+        (ast::TyKind::Slice(a), ast::TyKind::Slice(b)) |
+        (ast::TyKind::Paren(a), ast::TyKind::Paren(b)) => tys_eq_unspanned(a, b),
+        (ast::TyKind::Tup(a), ast::TyKind::Tup(b)) => {
+            slices_eq(a, b, |a, b| tys_eq_unspanned(a, b))
+        }
+        (ast::TyKind::Never, ast::TyKind::Never) |
+        (ast::TyKind::ImplicitSelf, ast::TyKind::ImplicitSelf) => true,
+        _ => false,
+    }
+}
+
+/// The named lifetimes used in `fragment`, in order of appearance and including repeated uses.
+/// `'static` and `'_` aren't included, nor are the declarations of lifetime parameters.
+pub fn referenced_lifetimes(fragment: &AstFragment) -> Vec<ast::Lifetime> {
+    let mut collector = LifetimeCollector { lifetimes: Vec::new() };
+    fragment.visit_with(&mut collector);
+    collector.lifetimes
+}
+
+struct LifetimeCollector {
+    lifetimes: Vec<ast::Lifetime>,
+}
+
+impl<'ast> Visitor<'ast> for LifetimeCollector {
+    fn visit_lifetime(&mut self, lifetime: &'ast ast::Lifetime) {
+        if lifetime.ident.name != kw::StaticLifetime &&
+           lifetime.ident.name != kw::UnderscoreLifetime {
+            self.lifetimes.push(*lifetime);
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &'ast ast::Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+/// The spans of the `ExprKind::Err` and `TyKind::Err` nodes in `fragment`, which stand in for
+/// code that errors have already been reported for. Unexpanded macro invocations are skipped.
+pub fn error_node_spans(fragment: &AstFragment) -> Vec<Span> {
+    let mut collector = ErrorNodeCollector { spans: Vec::new() };
+    fragment.visit_with(&mut collector);
+    collector.spans
+}
+
+struct ErrorNodeCollector {
+    spans: Vec<Span>,
+}
+
+impl<'ast> Visitor<'ast> for ErrorNodeCollector {
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr.node {
+            ast::ExprKind::Err => self.spans.push(expr.span),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_ty(&mut self, ty: &'ast ast::Ty) {
+        match ty.node {
+            ast::TyKind::Err => self.spans.push(ty.span),
+            _ => visit::walk_ty(self, ty),
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast ast::Mac) {}
+}
+
+/// A naming convention for identifiers, as checked by the `non_camel_case_types`,
+/// `non_snake_case` and `non_upper_case_globals` lints.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IdentStyle {
+    /// `snake_case`, for functions, variables and modules.
+    Snake,
+    /// `SCREAMING_SNAKE_CASE`, for constants and statics.
+    Screaming,
+    /// `UpperCamelCase`, for types, traits and enum variants.
+    Camel,
+}
+
+impl IdentStyle {
+    /// The name of the style, as used in the messages of the style lints.
+    pub fn descr(self) -> &'static str {
+        match self {
+            IdentStyle::Snake => "snake case",
+            IdentStyle::Screaming => "upper case",
+            IdentStyle::Camel => "upper camel case",
+        }
+    }
+
+    /// Whether `name` follows the style. Leading and trailing underscores are allowed, as are
+    /// characters without case.
+    pub fn matches(self, name: &str) -> bool {
+        match self {
+            IdentStyle::Snake => is_snake_case(name),
+            IdentStyle::Screaming => !name.chars().any(|c| c.is_lowercase()),
+            IdentStyle::Camel => is_camel_case(name),
+        }
+    }
+
+    /// Converts `name` to the style, keeping the word boundaries it marks with either
+    /// underscores or case changes.
+    pub fn convert(self, name: &str) -> String {
+        match self {
+            IdentStyle::Snake => to_snake_case(name),
+            IdentStyle::Screaming => to_snake_case(name).to_uppercase(),
+            IdentStyle::Camel => to_camel_case(name),
+        }
+    }
+}
+
+/// An identifier that doesn't follow the expected `IdentStyle`.
+#[derive(Clone, Debug)]
+pub struct IdentStyleMismatch {
+    pub ident: Ident,
+    pub style: IdentStyle,
+    /// The identifier converted to the expected style.
+    pub suggestion: String,
+}
+
+impl IdentStyleMismatch {
+    /// A message in the form of the style lints', e.g., "constant `Foo` should have an upper
+    /// case name", where `sort` describes what the identifier names.
+    pub fn message(&self, sort: &str) -> String {
+        format!("{} `{}` should have {} {} name",
+                sort,
+                self.ident,
+                if self.style == IdentStyle::Snake { "a" } else { "an" },
+                self.style.descr())
+    }
+
+    /// Buffers a `generated_ident_style` lint at the identifier, for the node `id` that a macro
+    /// derived a name from it for.
+    pub fn buffer_lint(&self, sess: &ParseSess, id: NodeId, sort: &str) {
+        sess.buffer_lint_with_notes(
+            BufferedEarlyLintId::GeneratedIdentStyle,
+            self.ident.span,
+            id,
+            &self.message(sort),
+            vec![format!("the name is derived from this identifier, converted to {}: `{}`",
+                         self.style.descr(), self.suggestion)],
+        );
+    }
+}
+
+/// Checks that `ident` follows `style`, suggesting a converted identifier if it doesn't.
+/// Raw identifiers are checked without the `r#` prefix.
+///
+/// Code that derives names from user input, e.g., the name of a constant from the name of an
+/// enum variant, can use this to point out the offending input instead of generating names
+/// that the style lints then report at the expansion.
+pub fn validate_ident_style(ident: Ident, style: IdentStyle) -> Result<(), IdentStyleMismatch> {
+    let name = ident.name.as_str();
+    if style.matches(&name) {
+        Ok(())
+    } else {
+        Err(IdentStyleMismatch { ident, style, suggestion: style.convert(&name) })
+    }
+}
+
+fn char_has_case(c: char) -> bool {
+    c.is_lowercase() || c.is_uppercase()
+}
+
+fn is_snake_case(name: &str) -> bool {
+    let name = name.trim_start_matches('\'').trim_matches('_');
+    let mut allow_underscore = true;
+    name.chars().all(|c| {
+        allow_underscore = match c {
+            '_' if !allow_underscore => return false,
+            '_' => false,
+            // Some characters have no lowercase form, so check for uppercase ones instead.
+            c if !c.is_uppercase() => true,
+            _ => return false,
+        };
+        true
+    })
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut words = vec![];
+    // Preserve leading underscores.
+    let name = name.trim_start_matches(|c: char| {
+        if c == '_' {
+            words.push(String::new());
+            true
+        } else {
+            false
+        }
+    });
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let mut last_upper = false;
+        let mut buf = String::new();
+        for c in part.chars() {
+            if !buf.is_empty() && buf != "'" && c.is_uppercase() && !last_upper {
+                words.push(mem::replace(&mut buf, String::new()));
+            }
+            last_upper = c.is_uppercase();
+            buf.extend(c.to_lowercase());
+        }
+        words.push(buf);
+    }
+    words.join("_")
+}
+
+fn is_camel_case(name: &str) -> bool {
+    let name = name.trim_matches('_');
+    match name.chars().next() {
+        // Scripts without case are allowed, so check for a lowercase start rather than for an
+        // uppercase one.
+        Some(first) => {
+            !first.is_lowercase() &&
+            !name.contains("__") &&
+            !name.chars().collect::<Vec<_>>().windows(2).any(|pair| {
+                // A character with case next to an underscore.
+                char_has_case(pair[0]) && pair[1] == '_' ||
+                char_has_case(pair[1]) && pair[0] == '_'
+            })
+        }
+        None => true,
+    }
+}
+
+fn to_camel_case(name: &str) -> String {
+    let mut camel_cased = String::new();
+    let mut prev_component_end: Option<char> = None;
+    for component in name.trim_matches('_').split('_').filter(|part| !part.is_empty()) {
+        // Separate components with an underscore if their boundary can't be told by case.
+        let first = component.chars().next().unwrap();
+        if prev_component_end.map_or(false, |last| !char_has_case(last) && !char_has_case(first)) {
+            camel_cased.push('_');
+        }
+        let mut new_word = true;
+        let mut prev_is_lower_case = true;
+        for c in component.chars() {
+            // Keep the case of an uppercase letter after a lowercase one, so that `camelCase`
+            // becomes `CamelCase`.
+            if prev_is_lower_case && c.is_uppercase() {
+                new_word = true;
+            }
+            if new_word {
+                camel_cased.extend(c.to_uppercase());
+            } else {
+                camel_cased.extend(c.to_lowercase());
+            }
+            prev_is_lower_case = c.is_lowercase();
+            new_word = false;
+        }
+        prev_component_end = component.chars().last();
+    }
+    camel_cased
+}
@@ -0,0 +1,150 @@
+use super::*;
+
+use crate::ext::base::DummyResult;
+use crate::parse::ParseSess;
+use crate::ptr::P;
+use crate::source_map::FilePathMapping;
+use crate::tests::with_error_checking_parse;
+use crate::with_default_globals;
+
+use syntax_pos::BytePos;
+
+fn parse_item(ps: &ParseSess, source: &str) -> P<ast::Item> {
+    with_error_checking_parse(source.to_string(), ps, |p| p.parse_item()).unwrap()
+}
+
+#[test]
+fn generics_in_scope_merge_where_clauses() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let item = parse_item(&ps, "impl<'a, T: Clone, const N: usize> Foo<'a, T> \
+                                    where T: Send, 'a: 'static, Vec<T>: Copy {}");
+        let generics = collect_generics_in_scope(&item);
+        assert!(generics.has_self);
+
+        let params: Vec<_> = generics.params.iter()
+            .map(|param| (param.ident.to_string(), param.kind, param.bounds.len()))
+            .collect();
+        assert_eq!(params, [
+            ("'a".to_string(), ParamKind::Lifetime, 1),
+            ("T".to_string(), ParamKind::Type, 2),
+            ("N".to_string(), ParamKind::Const, 0),
+        ]);
+        assert_eq!(generics.other_predicates.len(), 1);
+    })
+}
+
+#[test]
+fn bounds_are_compared_regardless_of_spans() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let bound = |source: &str| {
+            let mut bounds = with_error_checking_parse(source.to_string(), &ps, |p| {
+                p.parse_generic_bounds(None)
+            });
+            assert_eq!(bounds.len(), 1);
+            bounds.pop().unwrap()
+        };
+        let same = [
+            "Clone",
+            "'a",
+            "?Sized",
+            "for<'a> Fn(&'a u8) -> (u8, [u8])",
+            "Iterator<Item = &'static mut *const T>",
+            "From<Vec<T>>",
+        ];
+        for source in &same {
+            assert!(bounds_eq_unspanned(&bound(source), &bound(&format!("  {}", source))),
+                    "{}", source);
+        }
+        let different = [
+            ("Clone", "Copy"),
+            ("Clone", "'a"),
+            ("'a", "'b"),
+            ("Sized", "?Sized"),
+            ("std::clone::Clone", "Clone"),
+            ("From<Vec<T>>", "From<Vec<U>>"),
+            ("Fn(u8)", "Fn(u8) -> u8"),
+            ("Iterator<Item = &'a T>", "Iterator<Item = &'b T>"),
+            ("From<[u8; 1]>", "From<[u8; 1]>"),
+        ];
+        for (a, b) in &different {
+            assert!(!bounds_eq_unspanned(&bound(a), &bound(b)), "{} == {}", a, b);
+        }
+    })
+}
+
+#[test]
+fn referenced_lifetimes_skip_static_and_declarations() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let item = parse_item(&ps, "fn f<'a, 'b>(x: &'a u8, y: &'static u8, z: &'_ u8) \
+                                    -> &'a u8 {}");
+        let fragment = AstFragment::Items(vec![item].into());
+        let lifetimes: Vec<_> = referenced_lifetimes(&fragment).iter()
+            .map(|lifetime| lifetime.ident.to_string())
+            .collect();
+        assert_eq!(lifetimes, ["'a", "'a"]);
+    })
+}
+
+#[test]
+fn error_nodes_in_dummy_fragments() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let item = parse_item(&ps, "fn f(x: u8) -> u8 { x }");
+        assert!(!AstFragment::Items(vec![item].into()).has_errors());
+
+        let span = Span::with_root_ctxt(BytePos(0), BytePos(3));
+        let valid = AstFragment::Expr(DummyResult::raw_expr(span, false));
+        assert!(!valid.has_errors());
+        let error = AstFragment::Expr(DummyResult::raw_expr(span, true));
+        assert_eq!(error_node_spans(&error), [span]);
+        let error = AstFragment::Ty(DummyResult::raw_ty(span, true));
+        assert!(error.has_errors());
+    })
+}
+
+#[test]
+fn ident_styles() {
+    with_default_globals(|| {
+        let check = |name, style| validate_ident_style(Ident::from_str(name), style)
+            .err()
+            .map(|mismatch| mismatch.suggestion);
+        assert_eq!(check("foo_bar", IdentStyle::Snake), None);
+        assert_eq!(check("_foo", IdentStyle::Snake), None);
+        assert_eq!(check("FooBar", IdentStyle::Snake), Some("foo_bar".to_string()));
+        assert_eq!(check("FOO_BAR", IdentStyle::Screaming), None);
+        assert_eq!(check("FooBar", IdentStyle::Screaming), Some("FOO_BAR".to_string()));
+        assert_eq!(check("FooBar", IdentStyle::Camel), None);
+        assert_eq!(check("X86_64", IdentStyle::Camel), None);
+        assert_eq!(check("fooBar", IdentStyle::Camel), Some("FooBar".to_string()));
+        assert_eq!(check("ONE_TWO_THREE", IdentStyle::Camel), Some("OneTwoThree".to_string()));
+
+        let mismatch = validate_ident_style(Ident::from_str("Variant"), IdentStyle::Screaming)
+            .unwrap_err();
+        assert_eq!(mismatch.message("constant"),
+                   "constant `Variant` should have an upper case name");
+    })
+}
+
+#[test]
+fn camel_case() {
+    let camel = IdentStyle::Camel;
+    assert!(!camel.matches("userData"));
+    assert_eq!(camel.convert("userData"), "UserData");
+
+    assert!(camel.matches("X86_64"));
+
+    assert!(!camel.matches("X86__64"));
+    assert_eq!(camel.convert("X86__64"), "X86_64");
+
+    assert!(!camel.matches("Abc_123"));
+    assert_eq!(camel.convert("Abc_123"), "Abc123");
+
+    assert!(!camel.matches("A1_b2_c3"));
+    assert_eq!(camel.convert("A1_b2_c3"), "A1B2C3");
+
+    assert!(!camel.matches("ONE_TWO_THREE"));
+    assert_eq!(camel.convert("ONE_TWO_THREE"), "OneTwoThree");
+}
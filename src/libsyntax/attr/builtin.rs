@@ -618,6 +618,9 @@ pub fn eval_condition<F>(cfg: &ast::MetaItem, sess: &ParseSess, eval: &mut F)
 pub struct Deprecation {
     pub since: Option<Symbol>,
     pub note: Option<Symbol>,
+    /// A replacement for uses of a deprecated macro, suggested at each invocation. `$args` in
+    /// the template stands for the arguments of the invocation, e.g. `new!($args)`.
+    pub suggestion: Option<Symbol>,
 }
 
 /// Finds the deprecation attribute. `None` if none exists.
@@ -647,10 +650,10 @@ fn find_deprecation_generic<'a, I>(sess: &ParseSess,
 
         let meta = attr.meta().unwrap();
         depr = match &meta.node {
-            MetaItemKind::Word => Some(Deprecation { since: None, note: None }),
+            MetaItemKind::Word => Some(Deprecation { since: None, note: None, suggestion: None }),
             MetaItemKind::NameValue(..) => {
                 meta.value_str().map(|note| {
-                    Deprecation { since: None, note: Some(note) }
+                    Deprecation { since: None, note: Some(note), suggestion: None }
                 })
             }
             MetaItemKind::List(list) => {
@@ -685,18 +688,24 @@ fn find_deprecation_generic<'a, I>(sess: &ParseSess,
 
                 let mut since = None;
                 let mut note = None;
+                let mut suggestion = None;
                 for meta in list {
                     match meta {
                         NestedMetaItem::MetaItem(mi) => {
                             match mi.name_or_empty() {
                                 sym::since => if !get(mi, &mut since) { continue 'outer },
                                 sym::note => if !get(mi, &mut note) { continue 'outer },
+                                sym::suggestion => if !get(mi, &mut suggestion) {
+                                    continue 'outer
+                                },
                                 _ => {
                                     handle_errors(
                                         sess,
                                         meta.span(),
-                                        AttrError::UnknownMetaItem(mi.path.to_string(),
-                                                                   &["since", "note"]),
+                                        AttrError::UnknownMetaItem(
+                                            mi.path.to_string(),
+                                            &["since", "note", "suggestion"],
+                                        ),
                                     );
                                     continue 'outer
                                 }
@@ -716,7 +725,7 @@ fn find_deprecation_generic<'a, I>(sess: &ParseSess,
                     }
                 }
 
-                Some(Deprecation { since, note })
+                Some(Deprecation { since, note, suggestion })
             }
         };
     }
@@ -19,17 +19,21 @@ use crate::parse::parser::Parser;
 use crate::parse::{self, ParseSess, PResult};
 use crate::parse::token::{self, Token};
 use crate::ptr::P;
-use crate::symbol::{sym, Symbol};
+use crate::symbol::{kw, sym, Symbol};
 use crate::ThinVec;
 use crate::tokenstream::{TokenStream, TokenTree, DelimSpan};
 use crate::GLOBALS;
 
+use errors::{Applicability, Diagnostic, DiagnosticBuilder};
 use log::debug;
 use syntax_pos::{FileName, Span};
 
 use std::iter;
 use std::ops::DerefMut;
 
+#[cfg(test)]
+mod tests;
+
 pub fn mark_used(attr: &Attribute) {
     debug!("marking {:?} as used", attr);
     GLOBALS.with(|globals| {
@@ -180,7 +184,10 @@ impl Attribute {
     }
 
     pub fn is_word(&self) -> bool {
-        self.tokens.is_empty()
+        match self.args {
+            ast::AttrArgs::Empty => true,
+            _ => false,
+        }
     }
 
     pub fn is_meta_item_list(&self) -> bool {
@@ -256,28 +263,46 @@ impl MetaItem {
 
 impl Attribute {
     /// Extracts the MetaItem from inside this Attribute.
+    /// The conversion is the one of `MetaItem::from_token_stream`, except that the path and
+    /// the arguments are already separate and the meta item gets the span of the whole
+    /// attribute.
     pub fn meta(&self) -> Option<MetaItem> {
-        let mut tokens = self.tokens.trees().peekable();
         Some(MetaItem {
             path: self.path.clone(),
-            node: if let Some(node) = MetaItemKind::from_tokens(&mut tokens) {
-                if tokens.peek().is_some() {
-                    return None;
-                }
-                node
-            } else {
-                return None;
-            },
+            node: MetaItemKind::from_attr_args(&self.args)?,
             span: self.span,
         })
     }
 
+    /// The tokens of the attribute between its brackets, e.g., `path::to::name(args)`. For
+    /// attributes that are meta items, converting them with `MetaItem::from_token_stream` gives
+    /// the meta item of `Attribute::meta` with the span of its tokens.
+    pub fn tokens(&self) -> TokenStream {
+        let mut trees = Vec::new();
+        let mut needs_mod_sep = false;
+        for segment in &self.path.segments {
+            if segment.ident.name == kw::PathRoot {
+                trees.push(TokenTree::token(token::ModSep, segment.ident.span).into());
+                needs_mod_sep = false;
+                continue;
+            }
+            if needs_mod_sep {
+                let span = segment.ident.span.shrink_to_lo();
+                trees.push(TokenTree::token(token::ModSep, span).into());
+            }
+            trees.push(TokenTree::Token(Token::from_ast_ident(segment.ident)).into());
+            needs_mod_sep = true;
+        }
+        self.args.outer_tokens().append_to_tree_and_joint_vec(&mut trees);
+        TokenStream::new(trees)
+    }
+
     pub fn parse<'a, T, F>(&self, sess: &'a ParseSess, mut f: F) -> PResult<'a, T>
         where F: FnMut(&mut Parser<'a>) -> PResult<'a, T>,
     {
         let mut parser = Parser::new(
             sess,
-            self.tokens.clone(),
+            self.args.outer_tokens(),
             None,
             false,
             false,
@@ -293,7 +318,7 @@ impl Attribute {
     pub fn parse_list<'a, T, F>(&self, sess: &'a ParseSess, mut f: F) -> PResult<'a, Vec<T>>
         where F: FnMut(&mut Parser<'a>) -> PResult<'a, T>,
     {
-        if self.tokens.is_empty() {
+        if let ast::AttrArgs::Empty = self.args {
             return Ok(Vec::new());
         }
         self.parse(sess, |parser| {
@@ -335,7 +360,7 @@ impl Attribute {
                 id: self.id,
                 style: self.style,
                 path: meta.path,
-                tokens: meta.node.tokens(meta.span),
+                args: meta.node.attr_args(meta.span),
                 is_sugared_doc: true,
                 span: self.span,
             })
@@ -345,6 +370,69 @@ impl Attribute {
     }
 }
 
+/// Parses the list of traits of a `#[derive(...)]` attribute into their paths, each with the span
+/// of its entry in the list. Entries that aren't paths, like literals or paths with arguments,
+/// make the whole attribute invalid, the returned error is the diagnostic to report about the
+/// first of them.
+pub fn parse_derives(sess: &ParseSess, attr: &Attribute) -> Result<Vec<(Path, Span)>, Diagnostic> {
+    fn into_diagnostic(mut err: DiagnosticBuilder<'_>) -> Diagnostic {
+        let diagnostic = Diagnostic::clone(&err);
+        err.cancel();
+        diagnostic
+    }
+
+    let handler = &sess.span_diagnostic;
+    let list = match attr.parse_meta(sess) {
+        Ok(MetaItem { node: MetaItemKind::List(list), .. }) => list,
+        Ok(_) => {
+            let mut err = handler.struct_span_err(attr.span, "malformed `derive` attribute input");
+            err.span_suggestion(
+                attr.span,
+                "missing traits to be derived",
+                "#[derive(Trait1, Trait2, ...)]".to_owned(),
+                Applicability::HasPlaceholders,
+            );
+            return Err(into_diagnostic(err));
+        }
+        Err(err) => return Err(into_diagnostic(err)),
+    };
+
+    list.into_iter().map(|nested| match nested {
+        NestedMetaItem::MetaItem(MetaItem { path, node: MetaItemKind::Word, span }) => {
+            Ok((path, span))
+        }
+        NestedMetaItem::MetaItem(meta) => {
+            let mut err = handler.struct_span_err(
+                meta.span,
+                "traits in `#[derive(...)]` don't accept arguments",
+            );
+            err.span_suggestion(
+                meta.span,
+                "remove the arguments",
+                meta.path.to_string(),
+                Applicability::MachineApplicable,
+            );
+            Err(into_diagnostic(err))
+        }
+        NestedMetaItem::Literal(lit) => {
+            let mut err = handler.struct_span_err(
+                lit.span,
+                "expected path to a trait, found literal",
+            );
+            err.span_label(lit.span, "not a trait");
+            if let LitKind::Str(s, _) = lit.node {
+                err.span_suggestion(
+                    lit.span,
+                    "try using the string's contents as a path",
+                    s.to_string(),
+                    Applicability::MaybeIncorrect,
+                );
+            }
+            Err(into_diagnostic(err))
+        }
+    }).collect()
+}
+
 /* Constructors */
 
 pub fn mk_name_value_item_str(ident: Ident, str: Symbol, str_span: Span) -> MetaItem {
@@ -387,7 +475,7 @@ pub fn mk_attr_inner(item: MetaItem) -> Attribute {
         id: mk_attr_id(),
         style: ast::AttrStyle::Inner,
         path: item.path,
-        tokens: item.node.tokens(item.span),
+        args: item.node.attr_args(item.span),
         is_sugared_doc: false,
         span: item.span,
     }
@@ -399,7 +487,7 @@ pub fn mk_attr_outer(item: MetaItem) -> Attribute {
         id: mk_attr_id(),
         style: ast::AttrStyle::Outer,
         path: item.path,
-        tokens: item.node.tokens(item.span),
+        args: item.node.attr_args(item.span),
         is_sugared_doc: false,
         span: item.span,
     }
@@ -413,7 +501,7 @@ pub fn mk_sugared_doc_attr(text: Symbol, span: Span) -> Attribute {
         id: mk_attr_id(),
         style,
         path: Path::from_ident(Ident::with_dummy_span(sym::doc).with_span_pos(span)),
-        tokens: MetaItemKind::NameValue(lit).tokens(span),
+        args: MetaItemKind::NameValue(lit).attr_args(span),
         is_sugared_doc: true,
         span,
     }
@@ -447,10 +535,22 @@ pub fn first_attr_value_str_by_name(attrs: &[Attribute], name: Symbol) -> Option
 }
 
 impl MetaItem {
-    fn tokens(&self) -> TokenStream {
+    /// Converts the meta item into tokens, e.g., `path::to::name(list, of = "items")`.
+    ///
+    /// This is the inverse of `MetaItem::from_token_stream`: converting the tokens back gives an
+    /// equal meta item with the same spans. Path segments, raw identifiers and literals (with
+    /// their suffixes) keep their own tokens and spans. The `=`, `,` and delimiters, which meta
+    /// items don't record, get the span of the meta item they belong to, see
+    /// `MetaItemKind::tokens`.
+    pub fn to_token_stream(&self) -> TokenStream {
         let mut idents = vec![];
         let mut last_pos = BytePos(0 as u32);
         for (i, segment) in self.path.segments.iter().enumerate() {
+            if segment.ident.name == kw::PathRoot {
+                // The `::` of a global path is pushed before the next segment.
+                last_pos = segment.ident.span.lo();
+                continue;
+            }
             let is_first = i == 0;
             if !is_first {
                 let mod_sep_span = Span::new(last_pos,
@@ -518,16 +618,41 @@ impl MetaItem {
         let span = path.span.with_hi(hi);
         Some(MetaItem { path, node, span })
     }
+
+    /// Converts tokens of the form `path`, `path = lit` or `path(nested, items)` into a meta item,
+    /// or returns `None` if they aren't exactly one meta item. Interpolated meta items, paths
+    /// and identifiers are accepted in place of the path.
+    pub fn from_token_stream(tokens: TokenStream) -> Option<MetaItem> {
+        let mut tokens = tokens.into_trees().peekable();
+        let meta = MetaItem::from_tokens(&mut tokens)?;
+        if tokens.peek().is_some() {
+            return None;
+        }
+        Some(meta)
+    }
 }
 
 impl MetaItemKind {
+    /// Converts the part of a meta item after its path into tokens, as they are written after
+    /// the path of an attribute. The `=`, `,` and delimiters get `span`, the span of the meta
+    /// item.
     pub fn tokens(&self, span: Span) -> TokenStream {
+        self.attr_args(span).outer_tokens()
+    }
+
+    /// Converts the part of a meta item after its path into the arguments of an attribute.
+    /// The `=`, `,` and delimiters get `span`, the span of the meta item.
+    pub fn attr_args(&self, span: Span) -> ast::AttrArgs {
         match *self {
-            MetaItemKind::Word => TokenStream::empty(),
+            MetaItemKind::Word => ast::AttrArgs::Empty,
             MetaItemKind::NameValue(ref lit) => {
-                let mut vec = vec![TokenTree::token(token::Eq, span).into()];
-                lit.tokens().append_to_tree_and_joint_vec(&mut vec);
-                TokenStream::new(vec)
+                let expr = P(Expr {
+                    id: ast::DUMMY_NODE_ID,
+                    node: ast::ExprKind::Lit(lit.clone()),
+                    span: lit.span,
+                    attrs: ThinVec::new(),
+                });
+                ast::AttrArgs::Eq(span, expr)
             }
             MetaItemKind::List(ref list) => {
                 let mut tokens = Vec::new();
@@ -537,15 +662,30 @@ impl MetaItemKind {
                     }
                     item.tokens().append_to_tree_and_joint_vec(&mut tokens);
                 }
-                TokenTree::Delimited(
+                ast::AttrArgs::Delimited(
                     DelimSpan::from_single(span),
-                    token::Paren,
-                    TokenStream::new(tokens).into(),
-                ).into()
+                    ast::MacDelimiter::Parenthesis,
+                    TokenStream::new(tokens),
+                )
             }
         }
     }
 
+    /// Converts the arguments of an attribute, e.g., `= "value"` or `(list)`.
+    fn from_attr_args(args: &ast::AttrArgs) -> Option<MetaItemKind> {
+        match args {
+            ast::AttrArgs::Empty => Some(MetaItemKind::Word),
+            ast::AttrArgs::Delimited(_, ast::MacDelimiter::Parenthesis, tokens) => {
+                MetaItemKind::list_from_tokens(tokens.clone())
+            }
+            ast::AttrArgs::Delimited(..) => None,
+            ast::AttrArgs::Eq(_, expr) => match expr.node {
+                ast::ExprKind::Lit(ref lit) => Some(MetaItemKind::NameValue(lit.clone())),
+                _ => None,
+            },
+        }
+    }
+
     fn from_tokens<I>(tokens: &mut iter::Peekable<I>) -> Option<MetaItemKind>
         where I: Iterator<Item = TokenTree>,
     {
@@ -564,7 +704,11 @@ impl MetaItemKind {
             }
             _ => return Some(MetaItemKind::Word),
         };
+        MetaItemKind::list_from_tokens(delimited)
+    }
 
+    /// Converts the tokens inside the parentheses of a list, e.g., `a, b = "c"`.
+    fn list_from_tokens(delimited: TokenStream) -> Option<MetaItemKind> {
         let mut tokens = delimited.into_trees().peekable();
         let mut result = Vec::new();
         while let Some(..) = tokens.peek() {
@@ -589,7 +733,7 @@ impl NestedMetaItem {
 
     fn tokens(&self) -> TokenStream {
         match *self {
-            NestedMetaItem::MetaItem(ref item) => item.tokens(),
+            NestedMetaItem::MetaItem(ref item) => item.to_token_stream(),
             NestedMetaItem::Literal(ref lit) => lit.tokens(),
         }
     }
@@ -726,7 +870,7 @@ pub fn inject(mut krate: ast::Crate, parse_sess: &ParseSess, attrs: &[String]) -
         );
 
         let start_span = parser.token.span;
-        let (path, tokens) = panictry!(parser.parse_meta_item_unrestricted());
+        let (path, args) = panictry!(parser.parse_meta_item_unrestricted());
         let end_span = parser.token.span;
         if parser.token != token::Eof {
             parse_sess.span_diagnostic
@@ -738,7 +882,7 @@ pub fn inject(mut krate: ast::Crate, parse_sess: &ParseSess, attrs: &[String]) -
             id: mk_attr_id(),
             style: AttrStyle::Inner,
             path,
-            tokens,
+            args,
             is_sugared_doc: false,
             span: start_span.to(end_span),
         });
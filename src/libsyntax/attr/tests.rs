@@ -0,0 +1,102 @@
+use super::*;
+
+use crate::print::pprust;
+use crate::source_map::FilePathMapping;
+use crate::tests::string_to_stream;
+use crate::with_default_globals;
+
+fn round_trip(src: &str) -> MetaItem {
+    let tokens = string_to_stream(src.to_string());
+    let meta = MetaItem::from_token_stream(tokens.clone()).unwrap();
+    let new_tokens = meta.to_token_stream();
+    assert!(new_tokens.eq_unspanned(&tokens), "{} became {:?}", src, new_tokens);
+    let new_meta = MetaItem::from_token_stream(new_tokens).unwrap();
+    assert_eq!(format!("{:?}", new_meta), format!("{:?}", meta));
+    meta
+}
+
+#[test]
+fn meta_items_survive_token_round_trips() {
+    with_default_globals(|| {
+        let meta = round_trip("word");
+        assert!(meta.is_word());
+
+        let meta = round_trip("r#match::r#in = 1_000u32");
+        assert_eq!(meta.path.segments.len(), 2);
+        assert_eq!(meta.name_value_literal().unwrap().suffix(), Some(Symbol::intern("u32")));
+
+        let meta = round_trip("a::b(1u8, \"s\", c = 2.5f32, d(e, r#\"raw\"#), ::f)");
+        let list = meta.meta_item_list().unwrap();
+        assert_eq!(list.len(), 5);
+        assert_eq!(list[0].literal().unwrap().suffix(), Some(Symbol::intern("u8")));
+        assert_eq!(list[3].meta_item_list().unwrap().len(), 2);
+    })
+}
+
+#[test]
+fn meta_items_need_all_tokens() {
+    with_default_globals(|| {
+        assert!(MetaItem::from_token_stream(string_to_stream("a b".to_string())).is_none());
+        assert!(MetaItem::from_token_stream(string_to_stream("a = b".to_string())).is_none());
+        assert!(MetaItem::from_token_stream(TokenStream::empty()).is_none());
+    })
+}
+
+#[test]
+fn attribute_args_are_classified() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let parse = |src: &str| {
+            let name = FileName::Custom("attr".to_string());
+            let mut parser = parse::new_parser_from_source_str(&sess, name, src.to_string());
+            parser.parse_attribute(true).unwrap()
+        };
+
+        let attr = parse("#[doc = \"text\"]");
+        match &attr.args {
+            ast::AttrArgs::Eq(eq_span, expr) => {
+                assert_eq!((eq_span.lo(), eq_span.hi()), (BytePos(6), BytePos(7)));
+                assert_eq!((expr.span.lo(), expr.span.hi()), (BytePos(8), BytePos(14)));
+            }
+            args => panic!("expected a value, found {:?}", args),
+        }
+        assert_eq!(attr.value_str(), Some(Symbol::intern("text")));
+        assert_eq!(pprust::tts_to_string(attr.args.outer_tokens()), "= \"text\"");
+
+        let attr = parse("#![allow(dead_code, r#unused)]");
+        match &attr.args {
+            ast::AttrArgs::Delimited(_, ast::MacDelimiter::Parenthesis, tokens) => {
+                assert_eq!(pprust::tts_to_string(tokens.clone()), "dead_code, r#unused")
+            }
+            args => panic!("expected a list, found {:?}", args),
+        }
+        assert_eq!(attr.meta_item_list().unwrap().len(), 2);
+
+        let attr = parse("#[test]");
+        assert!(attr.is_word());
+        assert!(attr.args.span().is_none());
+        assert!(!sess.span_diagnostic.has_errors());
+    })
+}
+
+#[test]
+fn attribute_tokens_convert_to_their_meta_item() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let parse = |src: &str| {
+            let name = FileName::Custom("attr".to_string());
+            let mut parser = parse::new_parser_from_source_str(&sess, name, src.to_string());
+            parser.parse_attribute(true).unwrap()
+        };
+
+        for src in &["#[a]", "#[a::b = 1u8]", "#![::a::r#in(b, c = \"d\", e(f))]"] {
+            let attr = parse(src);
+            let meta = MetaItem::from_token_stream(attr.tokens()).unwrap();
+            let attr_meta = attr.meta().unwrap();
+            assert_eq!(meta.path.to_string(), attr_meta.path.to_string(), "{}", src);
+            assert_eq!(format!("{:?}", meta.node), format!("{:?}", attr_meta.node), "{}", src);
+            assert!(meta.to_token_stream().eq_unspanned(&attr.tokens()), "{}", src);
+        }
+        assert!(!sess.span_diagnostic.has_errors());
+    })
+}
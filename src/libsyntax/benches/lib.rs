@@ -0,0 +1,123 @@
+//! Micro-benchmarks for lexing, parsing and expansion.
+//!
+//! Run with `./x.py bench src/libsyntax`. The inputs are synthesized, so the numbers are only
+//! meaningful relative to each other, e.g. before and after a change to the token cursor, or
+//! with and without the `macro_rules!` match cache.
+
+#![feature(rustc_private)]
+#![feature(test)]
+
+extern crate syntax;
+extern crate syntax_pos;
+extern crate test;
+
+use syntax::ext::basic_resolver::expand_local_macros;
+use syntax::ext::expand::ExpansionConfig;
+use syntax::parse::{self, ParseSess};
+use syntax::source_map::FilePathMapping;
+use syntax::with_default_globals;
+use syntax_pos::FileName;
+use test::Bencher;
+
+/// A crate with many small items, exercising the common paths of the parser.
+fn flat_source() -> String {
+    let mut src = String::new();
+    for i in 0..500 {
+        src.push_str(&format!(r#"
+#[derive(Clone, Debug)]
+pub struct S{i} {{ a: u32, b: Vec<Option<&'static str>>, c: (u8, i64) }}
+
+impl S{i} {{
+    pub fn get(&self, x: u32) -> u32 {{
+        if x > {i} {{ self.a.wrapping_add(x) * 2 }} else {{ [1, 2, 3][x as usize] }}
+    }}
+}}
+"#, i = i));
+    }
+    src
+}
+
+/// A single deeply nested expression, exercising the cursor's frame stack.
+fn nested_source() -> String {
+    let depth = 100;
+    format!("fn f() {{ let _ = {}0{}; }}", "(1 + [".repeat(depth), "][0])".repeat(depth))
+}
+
+/// A `macro_rules!` macro invoked many times with the same tokens, which have to be matched
+/// against several arms.
+fn repeated_macro_source() -> String {
+    let mut src = String::from("macro_rules! m {
+        (struct $name:ident) => { 0 };
+        (enum $name:ident) => { 1 };
+        ($($a:ident : $b:tt),* $(,)?) => { 2 };
+    }\n");
+    for i in 0..500 {
+        src.push_str(&format!("fn f{}() {{ m!(a: 1, b: [2, 3], c: (4, 5), d: {{ 6 }},); }}\n", i));
+    }
+    src
+}
+
+fn bench_lex(b: &mut Bencher, src: String) {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        b.iter(|| {
+            let name = FileName::Custom("bench".to_string());
+            parse::parse_stream_from_source_str(name, src.clone(), &sess, None)
+        });
+    })
+}
+
+fn bench_parse(b: &mut Bencher, src: String) {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        b.iter(|| {
+            let name = FileName::Custom("bench".to_string());
+            parse::parse_crate_from_source_str(name, src.clone(), &sess).ok()
+        });
+    })
+}
+
+#[bench]
+fn lex_flat(b: &mut Bencher) {
+    bench_lex(b, flat_source());
+}
+
+#[bench]
+fn lex_nested(b: &mut Bencher) {
+    bench_lex(b, nested_source());
+}
+
+#[bench]
+fn parse_flat(b: &mut Bencher) {
+    bench_parse(b, flat_source());
+}
+
+#[bench]
+fn parse_nested(b: &mut Bencher) {
+    bench_parse(b, nested_source());
+}
+
+fn bench_expand(b: &mut Bencher, src: String, macro_rules_cache: bool) {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        b.iter(|| {
+            let name = FileName::Custom("bench".to_string());
+            let krate = parse::parse_crate_from_source_str(name, src.clone(), &sess).unwrap();
+            let config = ExpansionConfig {
+                macro_rules_cache,
+                ..ExpansionConfig::default("bench".to_string())
+            };
+            expand_local_macros(krate, &sess, config)
+        });
+    })
+}
+
+#[bench]
+fn expand_repeated_macro(b: &mut Bencher) {
+    bench_expand(b, repeated_macro_source(), false);
+}
+
+#[bench]
+fn expand_repeated_macro_cached(b: &mut Bencher) {
+    bench_expand(b, repeated_macro_source(), true);
+}
@@ -18,6 +18,8 @@ use crate::util::map_in_place::MapInPlace;
 use errors::Applicability;
 use smallvec::SmallVec;
 
+use std::slice;
+
 /// A folder that strips out items that do not belong in the current configuration.
 pub struct StripUnconfigured<'a> {
     pub sess: &'a ParseSess,
@@ -62,7 +64,13 @@ macro_rules! configure {
             Some(node) => node,
             None => return Default::default(),
         }
-    }
+    };
+    ($this:ident, $node:ident, annotated) => {
+        match $this.configure_annotated($node) {
+            Some(node) => node,
+            None => return Default::default(),
+        }
+    };
 }
 
 impl<'a> StripUnconfigured<'a> {
@@ -94,7 +102,7 @@ impl<'a> StripUnconfigured<'a> {
         if attr.path != sym::cfg_attr {
             return vec![attr];
         }
-        if attr.tokens.is_empty() {
+        if let ast::AttrArgs::Empty = attr.args {
             self.sess.span_diagnostic
                 .struct_span_err(
                     attr.span,
@@ -122,8 +130,8 @@ impl<'a> StripUnconfigured<'a> {
 
             while !parser.check(&token::CloseDelim(token::Paren)) {
                 let lo = parser.token.span.lo();
-                let (path, tokens) = parser.parse_meta_item_unrestricted()?;
-                expanded_attrs.push((path, tokens, parser.prev_span.with_lo(lo)));
+                let (path, args) = parser.parse_meta_item_unrestricted()?;
+                expanded_attrs.push((path, args, parser.prev_span.with_lo(lo)));
                 parser.expect_one_of(&[token::Comma], &[token::CloseDelim(token::Paren)])?;
             }
 
@@ -150,11 +158,11 @@ impl<'a> StripUnconfigured<'a> {
             // `cfg_attr` inside of another `cfg_attr`. E.g.
             //  `#[cfg_attr(false, cfg_attr(true, some_attr))]`.
             expanded_attrs.into_iter()
-            .flat_map(|(path, tokens, span)| self.process_cfg_attr(ast::Attribute {
+            .flat_map(|(path, args, span)| self.process_cfg_attr(ast::Attribute {
                 id: attr::mk_attr_id(),
                 style: attr.style,
                 path,
-                tokens,
+                args,
                 is_sugared_doc: false,
                 span,
             }))
@@ -164,6 +172,12 @@ impl<'a> StripUnconfigured<'a> {
         }
     }
 
+    /// Returns the index of the first `#[cfg]` in `attrs` excluding the node from this
+    /// configuration, if any.
+    pub fn first_disabling_cfg(&mut self, attrs: &[ast::Attribute]) -> Option<usize> {
+        attrs.iter().position(|attr| !self.in_cfg(slice::from_ref(attr)))
+    }
+
     /// Determines if a node with the given attributes should be included in this configuration.
     pub fn in_cfg(&mut self, attrs: &[ast::Attribute]) -> bool {
         attrs.iter().all(|attr| {
@@ -326,10 +340,15 @@ impl<'a> MutVisitor for StripUnconfigured<'a> {
     }
 
     fn filter_map_expr(&mut self, expr: P<ast::Expr>) -> Option<P<ast::Expr>> {
-        let mut expr = configure!(self, expr);
-        self.configure_expr_kind(&mut expr.node);
-        noop_visit_expr(&mut expr, self);
-        Some(expr)
+        expr.filter_map_preserving(|expr| {
+            self.process_cfg_attrs(expr);
+            if !self.in_cfg(expr.attrs()) {
+                return false;
+            }
+            self.configure_expr_kind(&mut expr.node);
+            noop_visit_expr(expr, self);
+            true
+        })
     }
 
     fn flat_map_stmt(&mut self, stmt: ast::Stmt) -> SmallVec<[ast::Stmt; 1]> {
@@ -11,6 +11,10 @@ use syntax_pos::MultiSpan;
 pub enum BufferedEarlyLintId {
     IllFormedAttributeInput,
     MetaVariableMisuse,
+    MacroBacktracking,
+    GeneratedIdentStyle,
+    MisplacedDeriveHelpers,
+    DeprecatedMacroArm,
 }
 
 /// Stores buffered lint info which can later be passed to `librustc`.
@@ -21,6 +25,9 @@ pub struct BufferedEarlyLint {
    /// The lint message.
    pub msg: String,
 
+   /// Notes attached to the lint after its message.
+   pub notes: Vec<String>,
+
    /// The `NodeId` of the AST node that generated the lint.
    pub id: NodeId,
 
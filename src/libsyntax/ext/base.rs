@@ -1,32 +1,46 @@
 use crate::ast::{self, NodeId, Attribute, Name, PatKind};
 use crate::attr::{self, HasAttrs, Stability, Deprecation};
-use crate::source_map::SourceMap;
+use crate::source_map::{respan, SourceMap};
+use crate::early_buffered_lints::BufferedEarlyLintId;
 use crate::edition::Edition;
+use crate::ext::diagnostics::ExpansionDiagnostics;
 use crate::ext::expand::{self, AstFragment, Invocation};
-use crate::ext::hygiene::{ExpnId, Transparency};
+use crate::ext::hygiene::{self, ExpnId, Transparency};
+use crate::ext::macro_trace::MacroTrace;
+use crate::ext::pure_macro::{self, PureExpansion, PureOutputCache, TrackedRead};
+use crate::ext::validate::AttrConsumption;
 use crate::mut_visit::{self, MutVisitor};
 use crate::parse::{self, parser, ParseSess, DirectoryOwnership};
 use crate::parse::token;
+use crate::print::pprust;
 use crate::ptr::P;
 use crate::symbol::{kw, sym, Ident, Symbol};
 use crate::{ThinVec, MACRO_ARGUMENTS};
-use crate::tokenstream::{self, TokenStream};
-use crate::visit::Visitor;
+use crate::tokenstream::{self, TokenInterner, TokenStream};
+use crate::visit::{self, Visitor};
 
-use errors::{DiagnosticBuilder, DiagnosticId};
+use errors::{DiagnosticBuilder, DiagnosticId, FatalError};
 use smallvec::{smallvec, SmallVec};
 use syntax_pos::{FileName, Span, MultiSpan, DUMMY_SP};
 use syntax_pos::hygiene::{ExpnData, ExpnKind};
 
 use rustc_data_structures::fx::FxHashMap;
 use rustc_data_structures::sync::{self, Lrc};
+use rustc_serialize::{opaque, Decodable, Encodable};
+use std::cell::RefCell;
+use std::env;
+use std::io;
 use std::iter;
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::default::Default;
 
 pub use syntax_pos::hygiene::MacroKind;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Debug,Clone)]
 pub enum Annotatable {
     Item(P<ast::Item>),
@@ -35,6 +49,16 @@ pub enum Annotatable {
     ForeignItem(P<ast::ForeignItem>),
     Stmt(P<ast::Stmt>),
     Expr(P<ast::Expr>),
+    /// The crate root, for attribute macros applied with `#![attr]` at the top of the crate.
+    Crate(ast::Crate),
+    /// A struct or union field.
+    Field(ast::StructField),
+    /// An enum variant.
+    Variant(ast::Variant),
+    /// A function or closure parameter.
+    Param(ast::Param),
+    /// A lifetime, type or const parameter.
+    GenericParam(ast::GenericParam),
 }
 
 impl HasAttrs for Annotatable {
@@ -46,6 +70,11 @@ impl HasAttrs for Annotatable {
             Annotatable::ForeignItem(ref foreign_item) => &foreign_item.attrs,
             Annotatable::Stmt(ref stmt) => stmt.attrs(),
             Annotatable::Expr(ref expr) => &expr.attrs,
+            Annotatable::Crate(ref krate) => &krate.attrs,
+            Annotatable::Field(ref field) => &field.attrs,
+            Annotatable::Variant(ref variant) => &variant.attrs,
+            Annotatable::Param(ref param) => &param.attrs,
+            Annotatable::GenericParam(ref param) => &param.attrs,
         }
     }
 
@@ -57,6 +86,11 @@ impl HasAttrs for Annotatable {
             Annotatable::ForeignItem(foreign_item) => foreign_item.visit_attrs(f),
             Annotatable::Stmt(stmt) => stmt.visit_attrs(f),
             Annotatable::Expr(expr) => expr.visit_attrs(f),
+            Annotatable::Crate(krate) => krate.attrs.visit_attrs(f),
+            Annotatable::Field(field) => field.visit_attrs(f),
+            Annotatable::Variant(variant) => variant.visit_attrs(f),
+            Annotatable::Param(param) => param.visit_attrs(f),
+            Annotatable::GenericParam(param) => param.visit_attrs(f),
         }
     }
 }
@@ -70,6 +104,11 @@ impl Annotatable {
             Annotatable::ForeignItem(ref foreign_item) => foreign_item.span,
             Annotatable::Stmt(ref stmt) => stmt.span,
             Annotatable::Expr(ref expr) => expr.span,
+            Annotatable::Crate(ref krate) => krate.span,
+            Annotatable::Field(ref field) => field.span,
+            Annotatable::Variant(ref variant) => variant.span,
+            Annotatable::Param(ref param) => param.span,
+            Annotatable::GenericParam(ref param) => param.ident.span,
         }
     }
 
@@ -81,16 +120,35 @@ impl Annotatable {
             Annotatable::ForeignItem(foreign_item) => visitor.visit_foreign_item(foreign_item),
             Annotatable::Stmt(stmt) => visitor.visit_stmt(stmt),
             Annotatable::Expr(expr) => visitor.visit_expr(expr),
+            Annotatable::Crate(krate) => visit::walk_crate(visitor, krate),
+            Annotatable::Field(field) => visitor.visit_struct_field(field),
+            Annotatable::Variant(variant) => visitor.visit_variant(variant),
+            Annotatable::Param(param) => visitor.visit_param(param),
+            Annotatable::GenericParam(param) => visitor.visit_generic_param(param),
         }
     }
 
-    pub fn expect_item(self) -> P<ast::Item> {
-        match self {
-            Annotatable::Item(i) => i,
-            _ => panic!("expected Item")
+    /// A short description of the kind of the annotatable, e.g., "trait item", for diagnostics.
+    pub fn kind_descr(&self) -> &'static str {
+        match *self {
+            Annotatable::Item(..) => "item",
+            Annotatable::TraitItem(..) => "trait item",
+            Annotatable::ImplItem(..) => "impl item",
+            Annotatable::ForeignItem(..) => "foreign item",
+            Annotatable::Stmt(..) => "statement",
+            Annotatable::Expr(..) => "expression",
+            Annotatable::Crate(..) => "crate",
+            Annotatable::Field(..) => "struct field",
+            Annotatable::Variant(..) => "variant",
+            Annotatable::Param(..) => "parameter",
+            Annotatable::GenericParam(..) => "generic parameter",
         }
     }
 
+    fn wrong_kind(&self, expected: &str) -> ! {
+        panic!("expected {}, found {}", expected, self.kind_descr())
+    }
+
     pub fn map_item_or<F, G>(self, mut f: F, mut or: G) -> Annotatable
         where F: FnMut(P<ast::Item>) -> P<ast::Item>,
               G: FnMut(Annotatable) -> Annotatable
@@ -101,41 +159,139 @@ impl Annotatable {
         }
     }
 
+    /// Returns the item, or gives back `self` if this isn't one, so that the caller can report
+    /// an error with `span` and `kind_descr` instead of panicking like `expect_item`.
+    pub fn try_into_item(self) -> Result<P<ast::Item>, Annotatable> {
+        match self {
+            Annotatable::Item(i) => Ok(i),
+            _ => Err(self),
+        }
+    }
+
+    pub fn expect_item(self) -> P<ast::Item> {
+        self.try_into_item().unwrap_or_else(|a| a.wrong_kind("item"))
+    }
+
+    /// Like `try_into_item`, for a trait item.
+    pub fn try_into_trait_item(self) -> Result<ast::TraitItem, Annotatable> {
+        match self {
+            Annotatable::TraitItem(i) => Ok(i.into_inner()),
+            _ => Err(self),
+        }
+    }
+
     pub fn expect_trait_item(self) -> ast::TraitItem {
+        self.try_into_trait_item().unwrap_or_else(|a| a.wrong_kind("trait item"))
+    }
+
+    /// Like `try_into_item`, for an impl item.
+    pub fn try_into_impl_item(self) -> Result<ast::ImplItem, Annotatable> {
         match self {
-            Annotatable::TraitItem(i) => i.into_inner(),
-            _ => panic!("expected Item")
+            Annotatable::ImplItem(i) => Ok(i.into_inner()),
+            _ => Err(self),
         }
     }
 
     pub fn expect_impl_item(self) -> ast::ImplItem {
+        self.try_into_impl_item().unwrap_or_else(|a| a.wrong_kind("impl item"))
+    }
+
+    /// Like `try_into_item`, for a foreign item.
+    pub fn try_into_foreign_item(self) -> Result<ast::ForeignItem, Annotatable> {
         match self {
-            Annotatable::ImplItem(i) => i.into_inner(),
-            _ => panic!("expected Item")
+            Annotatable::ForeignItem(i) => Ok(i.into_inner()),
+            _ => Err(self),
         }
     }
 
     pub fn expect_foreign_item(self) -> ast::ForeignItem {
+        self.try_into_foreign_item().unwrap_or_else(|a| a.wrong_kind("foreign item"))
+    }
+
+    /// Like `try_into_item`, for a statement.
+    pub fn try_into_stmt(self) -> Result<ast::Stmt, Annotatable> {
         match self {
-            Annotatable::ForeignItem(i) => i.into_inner(),
-            _ => panic!("expected foreign item")
+            Annotatable::Stmt(i) => Ok(i.into_inner()),
+            _ => Err(self),
         }
     }
 
     pub fn expect_stmt(self) -> ast::Stmt {
+        self.try_into_stmt().unwrap_or_else(|a| a.wrong_kind("statement"))
+    }
+
+    /// Like `try_into_item`, for an expression.
+    pub fn try_into_expr(self) -> Result<P<ast::Expr>, Annotatable> {
         match self {
-            Annotatable::Stmt(stmt) => stmt.into_inner(),
-            _ => panic!("expected statement"),
+            Annotatable::Expr(i) => Ok(i),
+            _ => Err(self),
         }
     }
 
     pub fn expect_expr(self) -> P<ast::Expr> {
+        self.try_into_expr().unwrap_or_else(|a| a.wrong_kind("expression"))
+    }
+
+    /// Like `try_into_item`, for a crate.
+    pub fn try_into_crate(self) -> Result<ast::Crate, Annotatable> {
+        match self {
+            Annotatable::Crate(i) => Ok(i),
+            _ => Err(self),
+        }
+    }
+
+    pub fn expect_crate(self) -> ast::Crate {
+        self.try_into_crate().unwrap_or_else(|a| a.wrong_kind("crate"))
+    }
+
+    /// Like `try_into_item`, for a struct field.
+    pub fn try_into_field(self) -> Result<ast::StructField, Annotatable> {
+        match self {
+            Annotatable::Field(i) => Ok(i),
+            _ => Err(self),
+        }
+    }
+
+    pub fn expect_field(self) -> ast::StructField {
+        self.try_into_field().unwrap_or_else(|a| a.wrong_kind("struct field"))
+    }
+
+    /// Like `try_into_item`, for a variant.
+    pub fn try_into_variant(self) -> Result<ast::Variant, Annotatable> {
+        match self {
+            Annotatable::Variant(i) => Ok(i),
+            _ => Err(self),
+        }
+    }
+
+    pub fn expect_variant(self) -> ast::Variant {
+        self.try_into_variant().unwrap_or_else(|a| a.wrong_kind("variant"))
+    }
+
+    /// Like `try_into_item`, for a parameter.
+    pub fn try_into_param(self) -> Result<ast::Param, Annotatable> {
         match self {
-            Annotatable::Expr(expr) => expr,
-            _ => panic!("expected expression"),
+            Annotatable::Param(i) => Ok(i),
+            _ => Err(self),
         }
     }
 
+    pub fn expect_param(self) -> ast::Param {
+        self.try_into_param().unwrap_or_else(|a| a.wrong_kind("parameter"))
+    }
+
+    /// Like `try_into_item`, for a generic parameter.
+    pub fn try_into_generic_param(self) -> Result<ast::GenericParam, Annotatable> {
+        match self {
+            Annotatable::GenericParam(i) => Ok(i),
+            _ => Err(self),
+        }
+    }
+
+    pub fn expect_generic_param(self) -> ast::GenericParam {
+        self.try_into_generic_param().unwrap_or_else(|a| a.wrong_kind("generic parameter"))
+    }
+
     pub fn derive_allowed(&self) -> bool {
         match *self {
             Annotatable::Item(ref item) => match item.node {
@@ -149,6 +305,40 @@ impl Annotatable {
     }
 }
 
+/// A class of warnings that `ExtCtxt::with_suppressed_warnings` can suppress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SuppressedWarning {
+    UnusedVariables,
+    UnusedMut,
+    UnusedImports,
+    DeadCode,
+    Deprecated,
+}
+
+impl SuppressedWarning {
+    /// The name of the lint reporting these warnings.
+    pub fn lint_name(self) -> &'static str {
+        match self {
+            SuppressedWarning::UnusedVariables => "unused_variables",
+            SuppressedWarning::UnusedMut => "unused_mut",
+            SuppressedWarning::UnusedImports => "unused_imports",
+            SuppressedWarning::DeadCode => "dead_code",
+            SuppressedWarning::Deprecated => "deprecated",
+        }
+    }
+}
+
+/// How faithfully the text returned by `ExtCtxt::item_source_text` reproduces the source of a
+/// node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceFidelity {
+    /// The text as written, including whitespace and comments.
+    Verbatim,
+    /// The node was produced by a macro and has no source text of its own, so it is
+    /// pretty-printed instead.
+    PrettyPrinted,
+}
+
 // `meta_item` is the annotation, and `item` is the item being modified.
 // FIXME Decorators should follow the same pattern too.
 pub trait MultiItemModifier {
@@ -224,6 +414,134 @@ impl<F> AttrProcMacro for F
     }
 }
 
+/// A request to expand a token-based macro, sent to an expander running outside of the
+/// compiler process, see `RemoteProcMacro`.
+///
+/// Spans are serialized without their hygiene contexts, so only their positions survive the
+/// round trip. That's sufficient for the expander, which can't interpret contexts anyway, and
+/// the tokens it produces are marked with the expansion they belong to like any other output.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum ExpansionRequest {
+    /// Expand the function-like macro `name` invoked with `input`.
+    Bang { name: Symbol, input: TokenStream },
+    /// Expand the attribute macro `name` with the arguments `annotation` applied to `annotated`.
+    Attr { name: Symbol, annotation: TokenStream, annotated: TokenStream },
+}
+
+/// The reply to an `ExpansionRequest`.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum ExpansionResponse {
+    Expanded(TokenStream),
+    /// The expansion failed, e.g., the macro panicked, with the given message.
+    Failed(String),
+}
+
+/// The sending half of a connection to an out-of-process expander.
+pub trait TokenStreamSink {
+    fn send(&mut self, request: &ExpansionRequest) -> io::Result<()>;
+}
+
+/// The receiving half of a connection to an out-of-process expander.
+pub trait TokenProvider {
+    fn receive(&mut self) -> io::Result<ExpansionResponse>;
+}
+
+impl<W: io::Write> TokenStreamSink for W {
+    fn send(&mut self, request: &ExpansionRequest) -> io::Result<()> {
+        write_frame(self, request)
+    }
+}
+
+impl<R: io::Read> TokenProvider for R {
+    fn receive(&mut self) -> io::Result<ExpansionResponse> {
+        read_frame(self)
+    }
+}
+
+/// Writes `message` to `writer` as a frame consisting of its length in bytes, as a little-endian
+/// `u32`, followed by the message in the `opaque` binary encoding.
+pub fn write_frame<T: Encodable>(writer: &mut impl io::Write, message: &T) -> io::Result<()> {
+    let mut encoder = opaque::Encoder::new(Vec::new());
+    message.encode(&mut encoder).unwrap();
+    let data = encoder.into_inner();
+    if data.len() > u32::max_value() as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too large for a frame"));
+    }
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(&data)?;
+    writer.flush()
+}
+
+/// Reads a message framed by `write_frame` from `reader`.
+pub fn read_frame<T: Decodable>(reader: &mut impl io::Read) -> io::Result<T> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut data = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut data)?;
+    T::decode(&mut opaque::Decoder::new(&data, 0))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// A function-like or attribute proc macro expanded by another process, reached through
+/// `connection`, e.g., a sandboxed proc macro server.
+pub struct RemoteProcMacro<C> {
+    /// The name the macro is known as by the expander.
+    pub name: Symbol,
+    pub connection: sync::Lock<C>,
+}
+
+impl<C: TokenStreamSink + TokenProvider> RemoteProcMacro<C> {
+    pub fn new(name: Symbol, connection: C) -> RemoteProcMacro<C> {
+        RemoteProcMacro { name, connection: sync::Lock::new(connection) }
+    }
+
+    fn request(&self, ecx: &mut ExtCtxt<'_>, span: Span, request: ExpansionRequest,
+               failure_msg: &str) -> TokenStream {
+        let response = {
+            let mut connection = self.connection.lock();
+            connection.send(&request).and_then(|()| connection.receive())
+        };
+        match response {
+            Ok(ExpansionResponse::Expanded(stream)) => stream,
+            Ok(ExpansionResponse::Failed(msg)) => {
+                let mut err = ecx.struct_span_fatal(span, failure_msg);
+                err.help(&format!("message: {}", msg));
+                err.emit();
+                FatalError.raise();
+            }
+            Err(err) => {
+                let msg = format!("failed to communicate with the expander of `{}`: {}",
+                                  self.name, err);
+                ecx.struct_span_fatal(span, &msg).emit();
+                FatalError.raise();
+            }
+        }
+    }
+}
+
+impl<C: TokenStreamSink + TokenProvider> ProcMacro for RemoteProcMacro<C> {
+    fn expand<'cx>(&self,
+                   ecx: &'cx mut ExtCtxt<'_>,
+                   span: Span,
+                   input: TokenStream)
+                   -> TokenStream {
+        let request = ExpansionRequest::Bang { name: self.name, input };
+        self.request(ecx, span, request, "proc macro panicked")
+    }
+}
+
+impl<C: TokenStreamSink + TokenProvider> AttrProcMacro for RemoteProcMacro<C> {
+    fn expand<'cx>(&self,
+                   ecx: &'cx mut ExtCtxt<'_>,
+                   span: Span,
+                   annotation: TokenStream,
+                   annotated: TokenStream)
+                   -> TokenStream {
+        let request = ExpansionRequest::Attr { name: self.name, annotation, annotated };
+        self.request(ecx, span, request, "custom attribute panicked")
+    }
+}
+
 /// Represents a thing that maps token trees to Macro Results
 pub trait TTMacroExpander {
     fn expand<'cx>(
@@ -232,6 +550,12 @@ pub trait TTMacroExpander {
         span: Span,
         input: TokenStream,
     ) -> Box<dyn MacResult+'cx>;
+
+    /// The patterns accepted by the macro as written in its definition, e.g., the matchers of
+    /// the arms of a `macro_rules!` macro. Empty for macros without such patterns.
+    fn matchers(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 pub type MacroExpanderFn =
@@ -251,6 +575,10 @@ impl<F> TTMacroExpander for F
         struct AvoidInterpolatedIdents;
 
         impl MutVisitor for AvoidInterpolatedIdents {
+            // Identifiers can also be interpolated inside other nonterminals, e.g., in the
+            // arguments of a macro call in an interpolated expression.
+            const VISIT_INTERPOLATED: bool = true;
+
             fn visit_tt(&mut self, tt: &mut tokenstream::TokenTree) {
                 if let tokenstream::TokenTree::Token(token) = tt {
                     if let token::Interpolated(nt) = &token.kind {
@@ -296,6 +624,22 @@ pub trait MacResult {
         None
     }
 
+    /// Whether the items of this result are created by `for_each_item` rather than
+    /// `make_items`.
+    fn streams_items(&self) -> bool {
+        false
+    }
+
+    /// Creates zero or more items and passes them to `f` one at a time, together with the
+    /// context of the expansion, only called if `streams_items` returns `true`.
+    ///
+    /// Macros that create a lot of items can implement this to create them lazily: the
+    /// expander collects the macro invocations of each item as soon as it's passed to `f`,
+    /// instead of collecting them from a vector of all the items afterwards.
+    fn for_each_item(self: Box<Self>, _f: &mut dyn FnMut(&mut ExtCtxt<'_>, P<ast::Item>)) {
+        panic!("`MacResult::for_each_item` called on a result that doesn't stream its items")
+    }
+
     /// Creates zero or more impl items.
     fn make_impl_items(self: Box<Self>) -> Option<SmallVec<[ast::ImplItem; 1]>> {
         None
@@ -325,6 +669,30 @@ pub trait MacResult {
     fn make_ty(self: Box<Self>) -> Option<P<ast::Ty>> {
         None
     }
+
+    /// Creates zero or more `match` arms.
+    fn make_arms(self: Box<Self>) -> Option<SmallVec<[ast::Arm; 1]>> {
+        None
+    }
+
+    /// Creates zero or more function or closure parameters. By default, the pattern
+    /// `make_pat` creates is a single parameter with an inferred type, the way macros invoked
+    /// as closure parameters used to be expanded.
+    fn make_params(self: Box<Self>) -> Option<SmallVec<[ast::Param; 1]>> {
+        self.make_pat().map(|pat| smallvec![param_from_pat(pat)])
+    }
+}
+
+/// A parameter with the pattern `pat` and an inferred type.
+fn param_from_pat(pat: P<ast::Pat>) -> ast::Param {
+    let span = pat.span;
+    ast::Param {
+        attrs: ThinVec::new(),
+        ty: P(ast::Ty { id: ast::DUMMY_NODE_ID, node: ast::TyKind::Infer, span }),
+        pat,
+        id: ast::DUMMY_NODE_ID,
+        span,
+    }
 }
 
 macro_rules! make_MacEager {
@@ -360,6 +728,8 @@ make_MacEager! {
     foreign_items: SmallVec<[ast::ForeignItem; 1]>,
     stmts: SmallVec<[ast::Stmt; 1]>,
     ty: P<ast::Ty>,
+    arms: SmallVec<[ast::Arm; 1]>,
+    params: SmallVec<[ast::Param; 1]>,
 }
 
 impl MacResult for MacEager {
@@ -409,14 +779,25 @@ impl MacResult for MacEager {
     fn make_ty(self: Box<Self>) -> Option<P<ast::Ty>> {
         self.ty
     }
+
+    fn make_arms(self: Box<Self>) -> Option<SmallVec<[ast::Arm; 1]>> {
+        self.arms
+    }
+
+    fn make_params(self: Box<Self>) -> Option<SmallVec<[ast::Param; 1]>> {
+        let MacEager { params, pat, .. } = *self;
+        params.or_else(|| pat.map(|pat| smallvec![param_from_pat(pat)]))
+    }
 }
 
 /// Fill-in macro expansion result, to allow compilation to continue
 /// after hitting errors.
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct DummyResult {
     is_error: bool,
     span: Span,
+    /// The names of the stubs produced among the items of a trait impl, see `any_in_context`.
+    impl_item_stubs: Vec<Ident>,
 }
 
 impl DummyResult {
@@ -425,12 +806,36 @@ impl DummyResult {
     /// Use this as a return value after hitting any errors and
     /// calling `span_err`.
     pub fn any(span: Span) -> Box<dyn MacResult+'static> {
-        Box::new(DummyResult { is_error: true, span })
+        Box::new(DummyResult { is_error: true, span, impl_item_stubs: Vec::new() })
+    }
+
+    /// Same as `any`, after emitting `err`, the error of one of the `try_get_*` argument
+    /// helpers, unless the helper already reported it.
+    pub fn from_arg_error(err: Option<DiagnosticBuilder<'_>>, span: Span)
+                          -> Box<dyn MacResult+'static> {
+        if let Some(mut err) = err {
+            err.emit();
+        }
+        DummyResult::any(span)
     }
 
     /// Same as `any`, but must be a valid fragment, not error.
     pub fn any_valid(span: Span) -> Box<dyn MacResult+'static> {
-        Box::new(DummyResult { is_error: false, span })
+        Box::new(DummyResult { is_error: false, span, impl_item_stubs: Vec::new() })
+    }
+
+    /// Same as `any`, but among the items of a trait impl, as told by the current expansion
+    /// data of `cx`, it produces a stub item for each identifier of `input`, the tokens given
+    /// to the failed macro, see `raw_impl_item_stub`. The impl isn't reported for missing the
+    /// trait items named like these, which the macro may well have defined, but still is for
+    /// the other ones.
+    pub fn any_in_context(cx: &ExtCtxt<'_>, span: Span, input: &TokenStream)
+                          -> Box<dyn MacResult+'static> {
+        let mut impl_item_stubs = Vec::new();
+        if cx.current_expansion.in_trait_impl {
+            collect_stub_names(input, &mut impl_item_stubs);
+        }
+        Box::new(DummyResult { is_error: true, span, impl_item_stubs })
     }
 
     /// A plain dummy expression.
@@ -460,6 +865,40 @@ impl DummyResult {
             span: sp
         })
     }
+
+    /// A stub standing in for an item named `ident` that a macro failed to produce among the
+    /// items of a trait impl, an associated type with an error type. Name resolution and type
+    /// checking don't report it as not being a member of the trait or as conflicting with
+    /// another item, and the impl isn't reported for missing a trait item with the same name.
+    pub fn raw_impl_item_stub(ident: Ident, sp: Span) -> ast::ImplItem {
+        ast::ImplItem {
+            id: ast::DUMMY_NODE_ID,
+            ident,
+            vis: respan(sp.shrink_to_lo(), ast::VisibilityKind::Inherited),
+            defaultness: ast::Defaultness::Final,
+            attrs: Vec::new(),
+            generics: ast::Generics::default(),
+            node: ast::ImplItemKind::TyAlias(DummyResult::raw_ty(sp, true)),
+            span: sp,
+            tokens: None,
+        }
+    }
+}
+
+/// Collects the identifiers of `tts` that could name an item, once for each name.
+fn collect_stub_names(tts: &TokenStream, names: &mut Vec<Ident>) {
+    for tt in tts.trees() {
+        match tt {
+            tokenstream::TokenTree::Token(token) => {
+                if let Some((ident, _)) = token.ident() {
+                    if !ident.is_reserved() && !names.iter().any(|name| name.name == ident.name) {
+                        names.push(ident);
+                    }
+                }
+            }
+            tokenstream::TokenTree::Delimited(_, _, tts) => collect_stub_names(&tts, names),
+        }
+    }
 }
 
 impl MacResult for DummyResult {
@@ -476,7 +915,13 @@ impl MacResult for DummyResult {
     }
 
     fn make_impl_items(self: Box<DummyResult>) -> Option<SmallVec<[ast::ImplItem; 1]>> {
-        Some(SmallVec::new())
+        if !self.is_error {
+            return Some(SmallVec::new());
+        }
+        let span = self.span;
+        Some(self.impl_item_stubs.into_iter()
+            .map(|ident| DummyResult::raw_impl_item_stub(ident, span))
+            .collect())
     }
 
     fn make_trait_items(self: Box<DummyResult>) -> Option<SmallVec<[ast::TraitItem; 1]>> {
@@ -498,6 +943,28 @@ impl MacResult for DummyResult {
     fn make_ty(self: Box<DummyResult>) -> Option<P<ast::Ty>> {
         Some(DummyResult::raw_ty(self.span, self.is_error))
     }
+
+    /// On errors, a `_` arm stands in for the arms the macro failed to produce, so that the
+    /// `match` isn't reported as non-exhaustive.
+    fn make_arms(self: Box<DummyResult>) -> Option<SmallVec<[ast::Arm; 1]>> {
+        if !self.is_error {
+            return Some(SmallVec::new());
+        }
+        Some(smallvec![ast::Arm {
+            attrs: Vec::new(),
+            pats: vec![P(DummyResult::raw_pat(self.span))],
+            guard: None,
+            body: DummyResult::raw_expr(self.span, true),
+            span: self.span,
+            id: ast::DUMMY_NODE_ID,
+        }])
+    }
+
+    /// The number of parameters a failed macro would have produced is unknown, so none stand
+    /// in for them.
+    fn make_params(self: Box<DummyResult>) -> Option<SmallVec<[ast::Param; 1]>> {
+        Some(SmallVec::new())
+    }
 }
 
 /// A syntax extension kind.
@@ -530,6 +997,19 @@ pub enum SyntaxExtensionKind {
         Box<dyn MultiItemModifier + sync::Sync + sync::Send>,
     ),
 
+    /// A token-based attribute macro for expressions.
+    ExprAttr(
+        /// An expander with signature (TokenStream, TokenStream) -> TokenStream.
+        /// The first TokenStream is the attribute itself, the second is the annotated
+        /// expression, passed as a single interpolated token. The produced TokenStream is
+        /// parsed as an expression replacing the annotated one, also when that expression is
+        /// a statement. Tokens the expander makes up without a span, i.e., with `DUMMY_SP`, get
+        /// the span of the call site; the expander gives the other ones their hygiene context,
+        /// as for `Attr`. Attribute procedural macros declared with `#[rustc_expr_attr]` are
+        /// expression attribute macros.
+        Box<dyn AttrProcMacro + sync::Sync + sync::Send>,
+    ),
+
     /// A trivial attribute "macro" that does nothing,
     /// only keeps the attribute and marks it as inert,
     /// thus making it ineligible for further expansion.
@@ -565,12 +1045,44 @@ pub struct SyntaxExtension {
     pub allow_internal_unsafe: bool,
     /// Enables the macro helper hack (`ident!(...)` -> `$crate::ident!(...)`) for this macro.
     pub local_inner_macros: bool,
+    /// The options of the `#[macro_export]` attribute of a `macro_rules!` macro, if it's exported.
+    /// Only `macro_rules::compile` fills them in, `SyntaxExtension::new` leaves them `None` as
+    /// other kinds of macros can't be exported with `#[macro_export]`.
+    pub export_options: Option<MacroExportOptions>,
+    /// The earliest edition this macro can be invoked from. Invocations from code of an
+    /// earlier edition are rejected before expansion.
+    pub min_edition: Option<Edition>,
+    /// A macro to suggest to callers that can't use this one because of `min_edition`.
+    pub min_edition_alternative: Option<Symbol>,
     /// The macro's stability info.
     pub stability: Option<Stability>,
     /// The macro's deprecation info.
     pub deprecation: Option<Deprecation>,
     /// Names of helper attributes registered by this macro.
     pub helper_attrs: Vec<Symbol>,
+    /// For derive macros, where the helper attributes listed here are used, from the macro's
+    /// `#[rustc_helper_attr_scope]` attribute. Helpers that aren't listed are used anywhere in
+    /// the derived item.
+    pub helper_attr_scopes: FxHashMap<Symbol, HelperAttrScope>,
+    /// For attribute macros, keeps the macro attribute on the first item of the output as an
+    /// inert attribute, to show where the output came from.
+    pub keep_attr: bool,
+    /// For attribute macros, passes items disabled by a `#[cfg]` that follows the macro
+    /// attribute to the macro, with the `#[cfg]` in place, instead of removing them before
+    /// expansion. Such a `#[cfg]` is applied to the macro's output like to any other code.
+    pub sees_unconfigured: bool,
+    /// Declares that the macro doesn't read files or environment variables and expands the same
+    /// input to the same output, from the macro's `#[rustc_pure_macro]` attribute. Reads through
+    /// the tracked IO hooks are reported as errors, the output of procedural bang macros is
+    /// cached and bang invocations may be expanded out of order, see `pure_macro`.
+    pub is_pure: bool,
+    /// The transparency of the marks applied to the tokens the macro produces, recorded as
+    /// `ExpnData::default_transparency` of its expansions and used by the `macro_rules!`
+    /// transcriber. `macro_rules::compile` sets it from the `#[rustc_macro_transparency]`
+    /// attribute of `macro_rules!` and `macro` macros, which are semi-transparent and opaque
+    /// respectively without it. It's semi-transparent for other macros, which give their
+    /// tokens a context themselves.
+    pub transparency: Transparency,
     /// Edition of the crate in which this macro is defined.
     pub edition: Edition,
     /// Built-in macros have a couple of special properties like availability
@@ -588,6 +1100,7 @@ impl SyntaxExtension {
             SyntaxExtensionKind::LegacyBang(..) => MacroKind::Bang,
             SyntaxExtensionKind::Attr(..) |
             SyntaxExtensionKind::LegacyAttr(..) |
+            SyntaxExtensionKind::ExprAttr(..) |
             SyntaxExtensionKind::NonMacroAttr { .. } => MacroKind::Attr,
             SyntaxExtensionKind::Derive(..) |
             SyntaxExtensionKind::LegacyDerive(..) => MacroKind::Derive,
@@ -601,9 +1114,17 @@ impl SyntaxExtension {
             allow_internal_unstable: None,
             allow_internal_unsafe: false,
             local_inner_macros: false,
+            export_options: None,
+            min_edition: None,
+            min_edition_alternative: None,
             stability: None,
             deprecation: None,
             helper_attrs: Vec::new(),
+            helper_attr_scopes: Default::default(),
+            keep_attr: false,
+            sees_unconfigured: false,
+            is_pure: false,
+            transparency: Transparency::SemiTransparent,
             edition,
             is_builtin: false,
             is_derive_copy: false,
@@ -622,9 +1143,14 @@ impl SyntaxExtension {
         name: Name,
         attrs: &[ast::Attribute],
     ) -> SyntaxExtension {
+        // Attributes are read as meta items converted from their tokens, see `Attribute::tokens`.
+        let meta_item_list = |attr: &ast::Attribute| {
+            ast::MetaItem::from_token_stream(attr.tokens())
+                .and_then(|meta| meta.meta_item_list().map(|list| list.to_vec()))
+        };
         let allow_internal_unstable =
             attr::find_by_name(attrs, sym::allow_internal_unstable).map(|attr| {
-                attr.meta_item_list()
+                meta_item_list(attr)
                     .map(|list| {
                         list.iter()
                             .filter_map(|it| {
@@ -652,12 +1178,21 @@ impl SyntaxExtension {
 
         let mut local_inner_macros = false;
         if let Some(macro_export) = attr::find_by_name(attrs, sym::macro_export) {
-            if let Some(l) = macro_export.meta_item_list() {
+            if let Some(l) = meta_item_list(macro_export) {
                 local_inner_macros = attr::list_contains_name(&l, sym::local_inner_macros);
             }
         }
 
         let is_builtin = attr::contains_name(attrs, sym::rustc_builtin_macro);
+        let (min_edition, min_edition_alternative) = find_min_edition(sess, attrs);
+        let helper_attr_scopes = find_helper_attr_scopes(sess, attrs, &helper_attrs);
+        let kind = match kind {
+            SyntaxExtensionKind::Attr(expander)
+                    if attr::contains_name(attrs, sym::rustc_expr_attr) => {
+                SyntaxExtensionKind::ExprAttr(expander)
+            }
+            kind => kind,
+        };
 
         SyntaxExtension {
             kind,
@@ -665,9 +1200,17 @@ impl SyntaxExtension {
             allow_internal_unstable,
             allow_internal_unsafe: attr::contains_name(attrs, sym::allow_internal_unsafe),
             local_inner_macros,
+            export_options: None,
+            min_edition,
+            min_edition_alternative,
             stability: attr::find_stability(&sess, attrs, span),
             deprecation: attr::find_deprecation(&sess, attrs, span),
             helper_attrs,
+            helper_attr_scopes,
+            keep_attr: attr::contains_name(attrs, sym::rustc_keep_macro_attr),
+            sees_unconfigured: attr::contains_name(attrs, sym::rustc_sees_unconfigured),
+            is_pure: attr::contains_name(attrs, sym::rustc_pure_macro),
+            transparency: Transparency::SemiTransparent,
             edition,
             is_builtin,
             is_derive_copy: is_builtin && name == sym::Copy,
@@ -704,12 +1247,187 @@ impl SyntaxExtension {
             allow_internal_unsafe: self.allow_internal_unsafe,
             local_inner_macros: self.local_inner_macros,
             edition: self.edition,
+            default_transparency: self.transparency,
         }
     }
+
+    /// The replacement for an invocation of this macro suggested by its `#[deprecated]`
+    /// attribute, with the arguments of `mac` substituted for `$args` in the template.
+    pub fn deprecation_replacement(&self, mac: &ast::Mac, source_map: &SourceMap)
+                                   -> Option<String> {
+        let template = self.deprecation.as_ref()?.suggestion?;
+        let mut spans = mac.tts.trees().map(|tree| tree.span());
+        let args = match (spans.next(), spans.last()) {
+            (None, _) => String::new(),
+            (Some(first), last) => source_map.span_to_snippet(first.to(last.unwrap_or(first)))
+                .unwrap_or_else(|_| pprust::tts_to_string(mac.tts.clone())),
+        };
+        Some(template.as_str().replace("$args", &args))
+    }
+}
+
+/// Parses `#[rustc_min_edition(edition = "2018", alternative = "name")]`, returning the
+/// minimum edition and the alternative macro, if any.
+fn find_min_edition(
+    sess: &ParseSess,
+    attrs: &[ast::Attribute],
+) -> (Option<Edition>, Option<Symbol>) {
+    let attr = match attr::find_by_name(attrs, sym::rustc_min_edition) {
+        Some(attr) => attr,
+        None => return (None, None),
+    };
+    let diag = &sess.span_diagnostic;
+    let (mut edition, mut alternative) = (None, None);
+    for item in attr.meta_item_list().unwrap_or_default() {
+        match (item.name_or_empty(), item.value_str()) {
+            (sym::edition, Some(value)) => match value.as_str().parse() {
+                Ok(value) => edition = Some(value),
+                Err(()) => diag.span_err(item.span(), &format!("unknown edition `{}`", value)),
+            },
+            (sym::alternative, Some(value)) => alternative = Some(value),
+            _ => diag.span_err(
+                item.span(), r#"expected `edition = "..."` or `alternative = "..."`"#,
+            ),
+        }
+    }
+    if edition.is_none() {
+        let msg = r#"`#[rustc_min_edition]` requires an `edition = "..."` argument"#;
+        diag.span_err(attr.span, msg);
+    }
+    (edition, alternative)
+}
+
+/// Where a derive helper attribute is used, see `SyntaxExtension::helper_attr_scopes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HelperAttrScope {
+    /// On the derived item itself.
+    pub item: bool,
+    /// On the fields of the derived struct, union or enum variants.
+    pub fields: bool,
+    /// On the variants of the derived enum.
+    pub variants: bool,
+}
+
+impl HelperAttrScope {
+    pub fn descr(&self) -> String {
+        let places: Vec<_> = [(self.item, "the item itself"),
+                              (self.fields, "fields"),
+                              (self.variants, "variants")]
+            .iter().filter(|(used, _)| *used).map(|(_, place)| *place).collect();
+        match places.split_last() {
+            None => "nowhere".to_string(),
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        }
+    }
+}
+
+/// Parses `#[rustc_helper_attr_scope(helper(item, fields, variants), ...)]`.
+fn find_helper_attr_scopes(
+    sess: &ParseSess,
+    attrs: &[ast::Attribute],
+    helper_attrs: &[Symbol],
+) -> FxHashMap<Symbol, HelperAttrScope> {
+    let mut scopes = FxHashMap::default();
+    let attr = match attr::find_by_name(attrs, sym::rustc_helper_attr_scope) {
+        Some(attr) => attr,
+        None => return scopes,
+    };
+    let diag = &sess.span_diagnostic;
+    for helper in attr.meta_item_list().unwrap_or_default() {
+        let name = helper.name_or_empty();
+        let places = match helper.meta_item_list() {
+            Some(places) if helper_attrs.contains(&name) => places,
+            Some(_) => {
+                let msg = format!("`{}` isn't a helper attribute of this macro", name);
+                diag.span_err(helper.span(), &msg);
+                continue;
+            }
+            None => {
+                diag.span_err(helper.span(), "expected `helper(item, fields, variants)`");
+                continue;
+            }
+        };
+        let mut scope = HelperAttrScope::default();
+        for place in places {
+            match place.ident().filter(|_| place.is_word()).map(|ident| ident.name) {
+                Some(sym::item) => scope.item = true,
+                Some(sym::fields) => scope.fields = true,
+                Some(sym::variants) => scope.variants = true,
+                _ => diag.span_err(place.span(), "expected `item`, `fields` or `variants`"),
+            }
+        }
+        scopes.insert(name, scope);
+    }
+    scopes
 }
 
 pub type NamedSyntaxExtension = (Name, SyntaxExtension);
 
+/// The options of a `#[macro_export(...)]` attribute.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MacroExportOptions {
+    /// `local_inner_macros`, see `SyntaxExtension::local_inner_macros`.
+    pub local_inner_macros: bool,
+    /// `hidden`: the macro is exported, but doesn't appear in documentation, like with
+    /// `#[doc(hidden)]`.
+    pub doc_hidden: bool,
+    /// `deprecated_arms(1, 3)`: the 0-based indices of the `macro_rules!` arms whose use is
+    /// deprecated. The attribute numbers arms from 1.
+    pub deprecated_arms: Vec<usize>,
+    /// `edition = "2018"`: the macro can only be invoked from code of this edition or later,
+    /// it becomes the `SyntaxExtension::min_edition` of the macro.
+    pub edition: Option<Edition>,
+}
+
+impl MacroExportOptions {
+    /// Parses the `#[macro_export]` attribute among `attrs`, reporting invalid options.
+    /// Returns `None` if there's no such attribute.
+    pub fn from_attrs(sess: &ParseSess, attrs: &[ast::Attribute])
+                      -> Option<MacroExportOptions> {
+        let macro_export = attr::find_by_name(attrs, sym::macro_export)?;
+        let mut options = MacroExportOptions::default();
+        let diag = &sess.span_diagnostic;
+        for item in macro_export.meta_item_list().unwrap_or_default() {
+            match item.name_or_empty() {
+                sym::local_inner_macros if item.is_word() => options.local_inner_macros = true,
+                sym::hidden if item.is_word() => options.doc_hidden = true,
+                sym::deprecated_arms => match item.meta_item_list() {
+                    Some(arms) => {
+                        for arm in arms {
+                            match arm.literal().map(|lit| &lit.node) {
+                                Some(&ast::LitKind::Int(n, _)) if n > 0 => {
+                                    options.deprecated_arms.push(n as usize - 1);
+                                }
+                                _ => diag.span_err(
+                                    arm.span(), "expected a macro arm number, counting from 1",
+                                ),
+                            }
+                        }
+                    }
+                    None => diag.span_err(
+                        item.span(), "`deprecated_arms` expects a list of macro arm numbers",
+                    ),
+                },
+                sym::edition => match item.value_str() {
+                    Some(value) => match value.as_str().parse() {
+                        Ok(edition) => options.edition = Some(edition),
+                        Err(()) => diag.span_err(
+                            item.span(), &format!("unknown edition `{}`", value),
+                        ),
+                    },
+                    None => diag.span_err(item.span(), "`edition` expects a string literal"),
+                },
+                _ => diag.struct_span_err(item.span(), "unknown `macro_export` option")
+                    .note("valid options are `local_inner_macros`, `hidden`, \
+                           `deprecated_arms(...)` and `edition = \"...\"`")
+                    .emit(),
+            }
+        }
+        Some(options)
+    }
+}
+
 /// Result of resolving a macro invocation.
 pub enum InvocationRes {
     Single(Lrc<SyntaxExtension>),
@@ -719,6 +1437,16 @@ pub enum InvocationRes {
 /// Error type that denotes indeterminacy.
 pub struct Indeterminate;
 
+/// The namespace a path is resolved in by `ExtCtxt::path_resolves`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathNamespace {
+    /// Types, traits and modules.
+    Type,
+    /// Functions, constants and statics.
+    Value,
+    Macro,
+}
+
 bitflags::bitflags! {
     /// Built-in derives that need some extra tracking beyond the usual macro functionality.
     #[derive(Default)]
@@ -729,26 +1457,63 @@ bitflags::bitflags! {
     }
 }
 
-pub trait Resolver {
+/// The name resolution services macro expansion relies on.
+///
+/// Only the resolution of macro paths and the allocation of node ids are essential, everything
+/// else is grouped into capability traits (`ExpansionScopes`, `MacroRegistry` and
+/// `DeriveRegistry`) whose methods all have defaults, so an embedder with simple needs can
+/// implement them with empty `impl` blocks. `BasicResolver` is a ready-made implementation
+/// that only knows about `macro_rules!` macros defined in the expanded code.
+pub trait Resolver: ExpansionScopes + MacroRegistry + DeriveRegistry {
     fn next_node_id(&mut self) -> NodeId;
 
-    fn get_module_scope(&mut self, id: NodeId) -> ExpnId;
-
-    fn resolve_dollar_crates(&mut self);
-    fn visit_ast_fragment_with_placeholders(&mut self, expn_id: ExpnId, fragment: &AstFragment,
-                                            extra_placeholders: &[NodeId]);
-    fn register_builtin_macro(&mut self, ident: ast::Ident, ext: SyntaxExtension);
-
-    fn resolve_imports(&mut self);
-
     fn resolve_macro_invocation(
         &mut self, invoc: &Invocation, eager_expansion_root: ExpnId, force: bool
     ) -> Result<InvocationRes, Indeterminate>;
+}
+
+/// Tracking of the scopes that macro invocations and their outputs are placed in.
+pub trait ExpansionScopes {
+    fn get_module_scope(&mut self, _id: NodeId) -> ExpnId {
+        ExpnId::root()
+    }
 
-    fn check_unused_macros(&self);
+    /// The node enclosing the given invocation that survives expansion. Lints buffered while
+    /// expanding the invocation are reported against it, so they obey its lint levels.
+    fn lint_node_id(&mut self, _expn_id: ExpnId) -> NodeId {
+        ast::CRATE_NODE_ID
+    }
+
+    /// Tries to resolve `path` in the namespace `ns` from the scope of the invocation
+    /// `expn_id`, as if the path were written in its output. Returns `Err(Indeterminate)` if
+    /// that can't be decided yet, e.g. because the path may go through unresolved imports or
+    /// through macros that aren't expanded yet. The answers given must not change until the end
+    /// of the expansion; the resolvers that can't tell return `Err(Indeterminate)`.
+    fn resolve_path_at_expansion(&mut self, _path: &ast::Path, _ns: PathNamespace,
+                                 _expn_id: ExpnId) -> Result<bool, Indeterminate> {
+        Err(Indeterminate)
+    }
 
-    fn has_derives(&self, expn_id: ExpnId, derives: SpecialDerives) -> bool;
-    fn add_derives(&mut self, expn_id: ExpnId, derives: SpecialDerives);
+    fn resolve_dollar_crates(&mut self) {}
+    fn visit_ast_fragment_with_placeholders(&mut self, _expn_id: ExpnId, _fragment: &AstFragment,
+                                            _extra_placeholders: &[NodeId]) {}
+
+    fn resolve_imports(&mut self) {}
+}
+
+/// Registration and bookkeeping of macro definitions.
+pub trait MacroRegistry {
+    fn register_builtin_macro(&mut self, _ident: ast::Ident, _ext: SyntaxExtension) {}
+
+    fn check_unused_macros(&self) {}
+}
+
+/// Tracking of the built-in derives applied to items, which some derives depend on.
+pub trait DeriveRegistry {
+    fn has_derives(&self, _expn_id: ExpnId, _derives: SpecialDerives) -> bool {
+        false
+    }
+    fn add_derives(&mut self, _expn_id: ExpnId, _derives: SpecialDerives) {}
 }
 
 #[derive(Clone)]
@@ -764,6 +1529,183 @@ pub struct ExpansionData {
     pub module: Rc<ModuleData>,
     pub directory_ownership: DirectoryOwnership,
     pub prior_type_ascription: Option<(Span, bool)>,
+    /// The attributes of the innermost item enclosing the invocation, see `AncestorAttrs`.
+    pub ancestor_attrs: Option<Rc<AncestorAttrs>>,
+    /// Whether the invocation is an item of a trait impl.
+    pub in_trait_impl: bool,
+}
+
+/// The attributes of an item enclosing an invocation, linked to those of the items enclosing
+/// that item, up to the crate. Items without attributes are left out, and the invocations
+/// collected within an item share its node, so entering an item doesn't copy the attributes
+/// of its ancestors.
+#[derive(Debug)]
+pub struct AncestorAttrs {
+    pub attrs: Vec<ast::Attribute>,
+    pub parent: Option<Rc<AncestorAttrs>>,
+}
+
+/// What a `trace_macros!` note records about an invocation.
+#[derive(Clone, Debug)]
+pub enum TraceKind {
+    /// The macro is being invoked with the given input.
+    Expanding(TokenStream),
+    /// The arms of a `macro_rules!` macro that were tried, in order. The last arm is the one
+    /// that matched, if any did.
+    Arms(Vec<ArmTrace>),
+    /// The invocation was transcribed to the given output.
+    To(TokenStream),
+}
+
+/// The outcome of matching an invocation against one arm of a `macro_rules!` macro.
+#[derive(Clone, Debug)]
+pub struct ArmTrace {
+    /// The span of the arm's matcher.
+    pub span: Span,
+    pub outcome: ArmOutcome,
+}
+
+#[derive(Clone, Debug)]
+pub enum ArmOutcome {
+    /// The arm matched, binding the given metavariables, ordered by their position in the
+    /// matcher.
+    Matched(Vec<(Ident, MetaVarBinding)>),
+    /// The arm failed to match at `span`.
+    Failed { span: Span, reason: String },
+}
+
+/// The tokens a metavariable was bound to by a matching arm.
+#[derive(Clone, Debug)]
+pub enum MetaVarBinding {
+    /// A single fragment.
+    Tokens(TokenStream),
+    /// A repetition, with one binding per iteration.
+    Seq(Vec<MetaVarBinding>),
+}
+
+impl MetaVarBinding {
+    fn render(&self, max_tokens: Option<usize>) -> String {
+        match self {
+            MetaVarBinding::Tokens(tokens) => {
+                format!("`{}`", pprust::tts_to_string_truncated(tokens.clone(), max_tokens))
+            }
+            MetaVarBinding::Seq(bindings) => {
+                let bindings = bindings.iter()
+                    .map(|binding| binding.render(max_tokens))
+                    .collect::<Vec<_>>();
+                format!("[{}]", bindings.join(", "))
+            }
+        }
+    }
+}
+
+/// A single `trace_macros!` note. Notes are kept in the order they were recorded and only
+/// rendered into strings when they are reported.
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    /// The outermost call site of the traced invocation, notes are grouped by it.
+    pub span: Span,
+    /// The name of the traced macro.
+    pub macro_name: Ident,
+    pub kind: TraceKind,
+}
+
+impl TraceEntry {
+    /// Renders the note the way `trace_macros_diag` reports it, printing at most
+    /// `max_tokens` tokens of the traced stream.
+    pub fn message(&self, max_tokens: Option<usize>) -> String {
+        match &self.kind {
+            TraceKind::Expanding(input) => {
+                let input = pprust::tts_to_string_truncated(input.clone(), max_tokens);
+                format!("expanding `{}! {{ {} }}`", self.macro_name, input)
+            }
+            TraceKind::Arms(arms) => {
+                let mut message = "matching arms:".to_string();
+                for (i, arm) in arms.iter().enumerate() {
+                    match &arm.outcome {
+                        ArmOutcome::Matched(bindings) => {
+                            message.push_str(&format!("\n  arm {}: matched", i + 1));
+                            for (name, binding) in bindings {
+                                message.push_str(&format!(
+                                    "\n    ${} = {}", name, binding.render(max_tokens),
+                                ));
+                            }
+                        }
+                        ArmOutcome::Failed { reason, .. } => {
+                            message.push_str(&format!("\n  arm {}: {}", i + 1, reason));
+                        }
+                    }
+                }
+                message
+            }
+            TraceKind::To(output) => {
+                format!("to `{}`", pprust::tts_to_string_truncated(output.clone(), max_tokens))
+            }
+        }
+    }
+}
+
+/// A filter given to `trace_macros!`, restricting tracing to the macros it matches.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceFilter {
+    /// `"my_macro"` or `"serde::Serialize"`: macros invoked by this path, or by any path ending
+    /// with this name.
+    Name(String),
+    /// `"serde::*"`: macros invoked by a path starting with these segments. `"*"` matches all
+    /// macros.
+    Prefix(String),
+}
+
+impl TraceFilter {
+    pub fn parse(filter: &str) -> TraceFilter {
+        if filter == "*" {
+            TraceFilter::Prefix(String::new())
+        } else if filter.ends_with("::*") {
+            TraceFilter::Prefix(filter[..filter.len() - 3].to_string())
+        } else {
+            TraceFilter::Name(filter.to_string())
+        }
+    }
+
+    /// Whether the filter matches a macro invoked by `path`, printed like `a::b::c`.
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            TraceFilter::Name(name) => path == name || path.ends_with(&format!("::{}", name)),
+            TraceFilter::Prefix(prefix) => {
+                prefix.is_empty() ||
+                path.starts_with(prefix) && path[prefix.len()..].starts_with("::")
+            }
+        }
+    }
+}
+
+/// A piece of data recorded by a macro with `ExtCtxt::emit_artifact` for the embedder, e.g.,
+/// a route table or a schema hash generated during expansion.
+#[derive(Clone, Debug)]
+pub struct ExpansionArtifact {
+    /// What the artifact is, chosen by the macro so that consumers can find their artifacts.
+    pub kind: Symbol,
+    pub payload: String,
+    /// The call site of the macro that emitted the artifact.
+    pub span: Span,
+}
+
+/// The number of reported `trace_macros!` notes `ExtCtxt` keeps at most.
+const MAX_REPORTED_TRACES: usize = 4096;
+
+/// Groups `trace_macros!` notes by call site, keeping the notes of each group in the order they
+/// were recorded and ordering the groups by their first note.
+fn group_trace_entries(entries: &[TraceEntry]) -> Vec<(Span, Vec<&TraceEntry>)> {
+    let mut groups: Vec<(Span, Vec<&TraceEntry>)> = Vec::new();
+    let mut group_indices = FxHashMap::default();
+    for entry in entries {
+        let index = *group_indices.entry(entry.span).or_insert_with(|| {
+            groups.push((entry.span, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(entry);
+    }
+    groups
 }
 
 /// One of these is made during expansion and incrementally updated as we go;
@@ -775,7 +1717,36 @@ pub struct ExtCtxt<'a> {
     pub root_path: PathBuf,
     pub resolver: &'a mut dyn Resolver,
     pub current_expansion: ExpansionData,
-    pub expansions: FxHashMap<Span, Vec<String>>,
+    /// `trace_macros!` notes that haven't been reported yet, in the order they were recorded.
+    pub expansions: Vec<TraceEntry>,
+    /// The last `MAX_REPORTED_TRACES` `trace_macros!` notes that have already been reported,
+    /// see `reported_traces`.
+    reported_traces: Vec<TraceEntry>,
+    /// The filters of the last `trace_macros!` that enabled tracing. Only the invocations
+    /// matching one of them are traced, or all invocations if there are none.
+    pub trace_filters: Vec<TraceFilter>,
+    /// Imports requested with `ensure_import`, keyed by the path of the module they are
+    /// requested for. They are injected once expansion of the crate is complete.
+    pub requested_imports: FxHashMap<Vec<Name>, Vec<ast::Path>>,
+    /// Items requested with `inject_crate_item`. They are added to the crate root once
+    /// expansion of the crate is complete.
+    pub injected_crate_items: Vec<P<ast::Item>>,
+    /// The number of invocations of each macro path collected so far from the output of each
+    /// expansion, used to derive the IDs of invocations with `ExpansionConfig::stable_expn_ids`.
+    pub stable_expn_indices: FxHashMap<(ExpnId, Symbol), u32>,
+    /// The invocations expanded so far, if `ExpansionConfig::trace_json` is enabled.
+    pub macro_trace: MacroTrace,
+    /// Shares the token streams produced by `macro_rules!` macros and procedural macros that
+    /// are identical to ones produced before, if `ExpansionConfig::intern_token_streams` is set.
+    pub token_interner: RefCell<TokenInterner>,
+    /// The expansion of the pure macro being expanded, if any, including while it expands other
+    /// macros eagerly.
+    pub pure_expansion: Option<Lrc<PureExpansion>>,
+    /// The outputs of pure procedural bang macros, if `ExpansionConfig::pure_macro_cache` is
+    /// enabled.
+    pub pure_output_cache: PureOutputCache,
+    /// The attributes consumed so far, if `ExpansionConfig::check_dead_attrs` is enabled.
+    pub attr_consumption: AttrConsumption,
 }
 
 impl<'a> ExtCtxt<'a> {
@@ -794,8 +1765,20 @@ impl<'a> ExtCtxt<'a> {
                 module: Rc::new(ModuleData { mod_path: Vec::new(), directory: PathBuf::new() }),
                 directory_ownership: DirectoryOwnership::Owned { relative: None },
                 prior_type_ascription: None,
+                ancestor_attrs: None,
+                in_trait_impl: false,
             },
-            expansions: FxHashMap::default(),
+            expansions: Vec::new(),
+            reported_traces: Vec::new(),
+            trace_filters: Vec::new(),
+            requested_imports: FxHashMap::default(),
+            injected_crate_items: Vec::new(),
+            stable_expn_indices: FxHashMap::default(),
+            macro_trace: MacroTrace::default(),
+            token_interner: RefCell::default(),
+            pure_expansion: None,
+            pure_output_cache: PureOutputCache::default(),
+            attr_consumption: AttrConsumption::default(),
         }
     }
 
@@ -809,6 +1792,19 @@ impl<'a> ExtCtxt<'a> {
     pub fn monotonic_expander<'b>(&'b mut self) -> expand::MacroExpander<'b, 'a> {
         expand::MacroExpander::new(self, true)
     }
+    /// Expands all macro invocations in `fragment` right away, so that an expander can accept
+    /// macro calls in its input, like `concat!` does.
+    ///
+    /// Returns `Err(Indeterminate)`, without expanding anything, if none of the invocations can
+    /// be resolved at this point, e.g. because they refer to macros that may be defined by later
+    /// expansions, and nothing is reported about them. The caller may then report that itself,
+    /// or expand the fragment with `MacroExpander::fully_expand_fragment` instead, which reports
+    /// the macros that can't be found. Once an invocation has been expanded, the expansion goes
+    /// on as with `fully_expand_fragment`: invocations that still can't be resolved when
+    /// nothing else can be expanded are reported as usual and expand to dummies.
+    pub fn eager_expand(&mut self, fragment: AstFragment) -> Result<AstFragment, Indeterminate> {
+        self.expander().try_fully_expand_fragment(fragment)
+    }
     pub fn new_parser_from_tts(&self, stream: TokenStream) -> parser::Parser<'a> {
         parse::stream_to_parser(self.parse_sess, stream, MACRO_ARGUMENTS)
     }
@@ -838,6 +1834,25 @@ impl<'a> ExtCtxt<'a> {
         span.with_ctxt_from_mark(self.current_expansion.id, Transparency::SemiTransparent)
     }
 
+    /// Replaces all spans in `fragment` with the call site of the current expansion, with the
+    /// context of `with_call_site_ctxt`. Diagnostics about the generated code then point at the
+    /// invocation, and names in it resolve as if written there.
+    pub fn respan_to_call_site(&self, fragment: &mut AstFragment) {
+        fragment.mut_visit_with(&mut Respanner(self.with_call_site_ctxt(self.call_site())));
+    }
+
+    /// The crate-level attributes and the attributes of the items enclosing the current
+    /// invocation, outermost first, e.g., for macros that behave differently under `#![no_std]`.
+    pub fn ancestor_attrs(&self) -> Vec<&ast::Attribute> {
+        let mut groups = Vec::new();
+        let mut ancestor = self.current_expansion.ancestor_attrs.as_ref();
+        while let Some(attrs) = ancestor {
+            groups.push(&attrs.attrs);
+            ancestor = attrs.parent.as_ref();
+        }
+        groups.into_iter().rev().flatten().collect()
+    }
+
     /// Returns span for the macro which originally caused the current expansion to happen.
     ///
     /// Stops backtracing at include! boundary.
@@ -856,17 +1871,43 @@ impl<'a> ExtCtxt<'a> {
         last_macro
     }
 
+    /// Renders the marks of the syntax context of `span` for debugging hygiene, with the call
+    /// and definition sites of their expansions as source locations. The marks themselves are
+    /// available through `Span::expansion_chain`.
+    pub fn debug_hygiene(&self, span: Span) -> String {
+        hygiene::debug_hygiene_with(span, |span| self.source_map().span_to_string(span))
+    }
+
+    /// Whether a diagnostic with the primary span `sp` would be a follow-on error of one
+    /// already reported for an error node that `sp` lies within, e.g., the expression a failed
+    /// macro call was replaced with. The diagnostic methods below drop such diagnostics.
+    pub fn is_in_error_node(&self, sp: &MultiSpan) -> bool {
+        sp.primary_span().map_or(false, |sp| self.parse_sess.is_in_error_node(sp))
+    }
+
+    fn suppress_in_error_node(&self, sp: &MultiSpan, mut diag: DiagnosticBuilder<'a>)
+                              -> DiagnosticBuilder<'a> {
+        if self.is_in_error_node(sp) {
+            diag.cancel();
+        }
+        diag
+    }
+
     pub fn struct_span_warn<S: Into<MultiSpan>>(&self,
                                                 sp: S,
                                                 msg: &str)
                                                 -> DiagnosticBuilder<'a> {
-        self.parse_sess.span_diagnostic.struct_span_warn(sp, msg)
+        let sp = sp.into();
+        let diag = self.parse_sess.span_diagnostic.struct_span_warn(sp.clone(), msg);
+        self.suppress_in_error_node(&sp, diag)
     }
     pub fn struct_span_err<S: Into<MultiSpan>>(&self,
                                                sp: S,
                                                msg: &str)
                                                -> DiagnosticBuilder<'a> {
-        self.parse_sess.span_diagnostic.struct_span_err(sp, msg)
+        let sp = sp.into();
+        let diag = self.parse_sess.span_diagnostic.struct_span_err(sp.clone(), msg);
+        self.suppress_in_error_node(&sp, diag)
     }
     pub fn struct_span_fatal<S: Into<MultiSpan>>(&self,
                                                  sp: S,
@@ -897,17 +1938,47 @@ impl<'a> ExtCtxt<'a> {
     /// Compilation will be stopped in the near future (at the end of
     /// the macro expansion phase).
     pub fn span_err<S: Into<MultiSpan>>(&self, sp: S, msg: &str) {
-        self.parse_sess.span_diagnostic.span_err(sp, msg);
+        let sp = sp.into();
+        if !self.is_in_error_node(&sp) {
+            self.parse_sess.span_diagnostic.span_err(sp, msg);
+        }
     }
     pub fn span_err_with_code<S: Into<MultiSpan>>(&self, sp: S, msg: &str, code: DiagnosticId) {
-        self.parse_sess.span_diagnostic.span_err_with_code(sp, msg, code);
+        let sp = sp.into();
+        if !self.is_in_error_node(&sp) {
+            self.parse_sess.span_diagnostic.span_err_with_code(sp, msg, code);
+        }
     }
     pub fn mut_span_err<S: Into<MultiSpan>>(&self, sp: S, msg: &str)
                         -> DiagnosticBuilder<'a> {
-        self.parse_sess.span_diagnostic.mut_span_err(sp, msg)
+        let sp = sp.into();
+        let diag = self.parse_sess.span_diagnostic.mut_span_err(sp.clone(), msg);
+        self.suppress_in_error_node(&sp, diag)
     }
     pub fn span_warn<S: Into<MultiSpan>>(&self, sp: S, msg: &str) {
-        self.parse_sess.span_diagnostic.span_warn(sp, msg);
+        let sp = sp.into();
+        if !self.is_in_error_node(&sp) {
+            self.parse_sess.span_diagnostic.span_warn(sp, msg);
+        }
+    }
+
+    /// Buffer the lint `lint_id` at `sp`, to be emitted once lint levels are known.
+    ///
+    /// Unlike `span_warn`, the lint honors `#[allow]`, `#[deny]` and friends on the item
+    /// enclosing the macro call, as well as the command line lint flags.
+    pub fn buffer_lint<S: Into<MultiSpan>>(&mut self,
+                                           lint_id: BufferedEarlyLintId,
+                                           sp: S,
+                                           msg: &str) {
+        self.buffer_lint_with_notes(lint_id, sp, msg, Vec::new());
+    }
+    pub fn buffer_lint_with_notes<S: Into<MultiSpan>>(&mut self,
+                                                      lint_id: BufferedEarlyLintId,
+                                                      sp: S,
+                                                      msg: &str,
+                                                      notes: Vec<String>) {
+        let id = self.resolver.lint_node_id(self.current_expansion.id);
+        self.parse_sess.buffer_lint_with_notes(lint_id, sp, id, msg, notes);
     }
     pub fn span_unimpl<S: Into<MultiSpan>>(&self, sp: S, msg: &str) -> ! {
         self.parse_sess.span_diagnostic.span_unimpl(sp, msg);
@@ -916,15 +1987,39 @@ impl<'a> ExtCtxt<'a> {
         self.parse_sess.span_diagnostic.span_bug(sp, msg);
     }
     pub fn trace_macros_diag(&mut self) {
-        for (sp, notes) in self.expansions.iter() {
-            let mut db = self.parse_sess.span_diagnostic.span_note_diag(*sp, "trace_macro");
-            for note in notes {
-                db.note(note);
+        for (sp, entries) in group_trace_entries(&self.expansions) {
+            let mut db = self.parse_sess.span_diagnostic.span_note_diag(sp, "trace_macro");
+            let mut input = None;
+            for entry in entries {
+                db.note(&entry.message(self.ecfg.print_token_limit));
+                match &entry.kind {
+                    TraceKind::Expanding(tokens) => input = Some(tokens),
+                    TraceKind::To(output) if self.ecfg.trace_diff => {
+                        if let Some(input) = input.take() {
+                            let edits = tokenstream::diff(input, output);
+                            let changes =
+                                tokenstream::render_diff(&edits, self.ecfg.print_token_limit);
+                            if changes.is_empty() {
+                                db.note("the output is the same as the input");
+                            } else {
+                                db.note(&format!("changes:\n{}", changes));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
             }
             db.emit();
         }
-        // Fixme: does this result in errors?
-        self.expansions.clear();
+        self.reported_traces.append(&mut self.expansions);
+        let excess = self.reported_traces.len().saturating_sub(MAX_REPORTED_TRACES);
+        self.reported_traces.drain(..excess);
+    }
+    /// The `trace_macros!` notes reported so far, in the order they were recorded, so that the
+    /// arms tried by each traced invocation can be inspected after expansion. Only the last
+    /// `MAX_REPORTED_TRACES` notes are kept.
+    pub fn reported_traces(&self) -> &[TraceEntry] {
+        &self.reported_traces
     }
     pub fn bug(&self, msg: &str) -> ! {
         self.parse_sess.span_diagnostic.bug(msg);
@@ -933,7 +2028,141 @@ impl<'a> ExtCtxt<'a> {
         self.ecfg.trace_mac
     }
     pub fn set_trace_macros(&mut self, x: bool) {
-        self.ecfg.trace_mac = x
+        self.ecfg.trace_mac = x;
+        self.trace_filters.clear();
+    }
+    /// Enables tracing for the macros matching one of `filters`.
+    pub fn set_trace_filters(&mut self, filters: Vec<TraceFilter>) {
+        self.ecfg.trace_mac = true;
+        self.trace_filters = filters;
+    }
+    /// Whether the invocation being expanded is traced, i.e., tracing is enabled and the path
+    /// the macro is invoked by matches the `trace_macros!` filters, if there are any.
+    pub fn traces_current_invocation(&self) -> bool {
+        if !self.trace_macros() {
+            return false;
+        }
+        if self.trace_filters.is_empty() {
+            return true;
+        }
+        match self.current_expansion.id.expn_data().kind {
+            ExpnKind::Macro(_, path) => {
+                let path = path.as_str();
+                self.trace_filters.iter().any(|filter| filter.matches(&path))
+            }
+            _ => false,
+        }
+    }
+    /// Records an artifact for the embedder instead of having the macro write it to a file.
+    /// Artifacts can be retrieved from `ParseSess::expansion_artifacts` after expansion, in the
+    /// order they were emitted.
+    pub fn emit_artifact(&self, kind: Symbol, payload: String) {
+        self.parse_sess.expansion_artifacts.borrow_mut().push(ExpansionArtifact {
+            kind,
+            payload,
+            span: self.call_site(),
+        });
+    }
+    /// Requests `path` to be imported into the module of the current expansion.
+    ///
+    /// Requests from all expansions are merged, so the same path requested with the same
+    /// hygiene is only imported once. As with any other `use` item, the imported name is only
+    /// visible to code with the syntax context of the last segment of `path`.
+    pub fn ensure_import(&mut self, path: ast::Path) {
+        let module = self.current_expansion.module.mod_path.iter()
+            .map(|ident| ident.name)
+            .collect();
+        let imports = self.requested_imports.entry(module).or_default();
+        let is_same = |import: &ast::Path| {
+            import.segments.iter().map(|segment| segment.ident)
+                .eq(path.segments.iter().map(|segment| segment.ident))
+        };
+        if !imports.iter().any(is_same) {
+            imports.push(path);
+        }
+    }
+    /// Requests `item` to be added to the crate root, e.g., a helper type or function shared by
+    /// all the invocations of a macro.
+    ///
+    /// Requests from all expansions are merged, so that only the first item of each kind is
+    /// added for a given name and hygiene, and macros can request the same item from each of
+    /// their invocations without the crate ending up with several definitions of it.
+    pub fn inject_crate_item(&mut self, item: P<ast::Item>) {
+        let is_same = |injected: &P<ast::Item>| {
+            injected.ident.modern() == item.ident.modern() &&
+                mem::discriminant(&injected.node) == mem::discriminant(&item.node)
+        };
+        if !self.injected_crate_items.iter().any(is_same) {
+            self.injected_crate_items.push(item);
+        }
+    }
+    /// Returns the source text of `annotatable` with its remaining outer attributes, and the
+    /// span that text covers.
+    ///
+    /// Unlike the tokens of the node, the text keeps its whitespace and comments. Nodes produced
+    /// by macro expansions (or whose source isn't available) are pretty-printed instead, which
+    /// the returned `SourceFidelity` tells apart. Generic parameters can't be printed on their
+    /// own, so `None` is returned for those without source text.
+    pub fn item_source_text(&self, annotatable: &Annotatable)
+                            -> Option<(String, Span, SourceFidelity)> {
+        let span = annotatable.attrs().iter()
+            .filter(|attr| attr.style == ast::AttrStyle::Outer)
+            .fold(annotatable.span(), |span, attr| span.to(attr.span));
+        if !annotatable.span().from_expansion() {
+            if let Ok(text) = self.source_map().span_to_snippet(span) {
+                return Some((text, span, SourceFidelity::Verbatim));
+            }
+        }
+
+        let text = match *annotatable {
+            Annotatable::Item(ref item) => pprust::item_to_string(item),
+            Annotatable::TraitItem(ref item) => pprust::to_string(|s| s.print_trait_item(item)),
+            Annotatable::ImplItem(ref item) => pprust::to_string(|s| s.print_impl_item(item)),
+            Annotatable::ForeignItem(ref item) => {
+                pprust::to_string(|s| s.print_foreign_item(item))
+            }
+            Annotatable::Stmt(ref stmt) => pprust::stmt_to_string(stmt),
+            Annotatable::Expr(ref expr) => pprust::expr_to_string(expr),
+            Annotatable::Crate(ref krate) => {
+                pprust::to_string(|s| s.print_mod(&krate.module, &krate.attrs))
+            }
+            Annotatable::Field(ref field) => pprust::to_string(|s| s.print_struct_field(field)),
+            Annotatable::Variant(ref variant) => pprust::variant_to_string(variant),
+            Annotatable::Param(ref param) => pprust::param_to_string(param),
+            Annotatable::GenericParam(_) => return None,
+        };
+        Some((text, span, SourceFidelity::PrettyPrinted))
+    }
+    /// Checks whether `path` resolves to something in the namespace `ns` at this point of the
+    /// expansion, as if it were written in the output of the current invocation. This lets a
+    /// macro choose between alternatives, e.g. generate an implementation of `::serde::Serialize`
+    /// only if that trait is available.
+    ///
+    /// Returns `Err(Indeterminate)` if the answer may still change as expansion progresses, in
+    /// which case the macro should generate code that works either way or report an error.
+    pub fn path_resolves(&mut self, path: &ast::Path, ns: PathNamespace)
+                         -> Result<bool, Indeterminate> {
+        let expn_id = self.current_expansion.id;
+        self.resolver.resolve_path_at_expansion(path, ns, expn_id)
+    }
+    /// Runs `f` in a nested expansion in which the lints of `warnings` are silenced.
+    ///
+    /// This applies to the spans that originate in the nested expansion: those that `f` marks
+    /// with `with_def_site_ctxt` or `with_call_site_ctxt`, and those in the output of the macros
+    /// it expands eagerly. Spans of the macro input aren't affected, so warnings about code
+    /// written by the user are still reported, and so are lints set to deny. Unlike
+    /// `allow_internal_unsafe` and the like, this covers part of an expansion instead of all
+    /// expansions of a macro. As with any nested expansion, identifiers marked inside `f` don't
+    /// resolve to definition-site identifiers marked outside of it.
+    pub fn with_suppressed_warnings<R>(&mut self, warnings: &[SuppressedWarning],
+                                       f: impl FnOnce(&mut Self) -> R) -> R {
+        let parent = self.current_expansion.id;
+        let expn_id = ExpnId::fresh(Some(ExpnData { parent, ..parent.expn_data() }));
+        self.parse_sess.warning_suppressions.borrow_mut().insert(expn_id, warnings.to_vec());
+        let prev_expn_id = mem::replace(&mut self.current_expansion.id, expn_id);
+        let result = f(self);
+        self.current_expansion.id = prev_expn_id;
+        result
     }
     pub fn ident_of(&self, st: &str) -> ast::Ident {
         ast::Ident::from_str(st)
@@ -976,6 +2205,141 @@ impl<'a> ExtCtxt<'a> {
             path
         }
     }
+
+    /// Reads the environment variable `var` on behalf of the macro being expanded. Macros
+    /// should read the environment through this, so that pure macros can't read it.
+    pub fn tracked_env_var(&self, var: &str, span: Span) -> Result<String, env::VarError> {
+        self.track_read(TrackedRead::EnvVar(var), span);
+        env::var(var)
+    }
+
+    /// Records that the macro being expanded reads the file at `path`, which pure macros must
+    /// not do.
+    pub fn track_file(&self, path: &Path, span: Span) {
+        self.track_read(TrackedRead::File(path), span);
+    }
+
+    fn track_read(&self, read: TrackedRead<'_>, span: Span) {
+        let pure = self.pure_expansion.as_ref().map(|pure| &**pure);
+        pure_macro::track_read(self.parse_sess, pure, read, span);
+    }
+}
+
+/// Replaces all spans in a node with the given one, see `ExtCtxt::respan_to_call_site`.
+struct Respanner(Span);
+
+impl MutVisitor for Respanner {
+    const VISIT_INTERPOLATED: bool = true;
+
+    fn visit_span(&mut self, span: &mut Span) {
+        *span = self.0;
+    }
+
+    fn visit_expr(&mut self, expr: &mut P<ast::Expr>) {
+        // The walker doesn't visit the spans of operators.
+        match &mut expr.node {
+            ast::ExprKind::Binary(op, ..) | ast::ExprKind::AssignOp(op, ..) => op.span = self.0,
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(expr, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+bitflags::bitflags! {
+    /// The kinds of literals `expr_to_lit` accepts.
+    pub struct LitKindSet: u8 {
+        const STR      = 1 << 0;
+        const BYTE_STR = 1 << 1;
+        const INT      = 1 << 2;
+        const BOOL     = 1 << 3;
+    }
+}
+
+impl LitKindSet {
+    fn of(kind: &ast::LitKind) -> LitKindSet {
+        match kind {
+            ast::LitKind::Str(..) => LitKindSet::STR,
+            ast::LitKind::ByteStr(..) => LitKindSet::BYTE_STR,
+            ast::LitKind::Int(..) => LitKindSet::INT,
+            ast::LitKind::Bool(..) => LitKindSet::BOOL,
+            _ => LitKindSet::empty(),
+        }
+    }
+
+    /// Describes the kinds in the set, e.g., "a string or byte string literal".
+    fn descr(self) -> String {
+        let names: Vec<_> = [
+            (LitKindSet::STR, "string"),
+            (LitKindSet::BYTE_STR, "byte string"),
+            (LitKindSet::INT, "integer"),
+            (LitKindSet::BOOL, "boolean"),
+        ].iter().filter(|&&(kind, _)| self.contains(kind)).map(|&(_, name)| name).collect();
+        let article = if names.first() == Some(&"integer") { "an" } else { "a" };
+        match names.split_last() {
+            Some((last, [])) => format!("{} {} literal", article, last),
+            Some((last, rest)) => format!("{} {} or {} literal", article, rest.join(", "), last),
+            None => "a literal".to_string(),
+        }
+    }
+}
+
+fn lit_kind_descr(kind: &ast::LitKind) -> &'static str {
+    match kind {
+        ast::LitKind::Str(..) => "a string literal",
+        ast::LitKind::ByteStr(..) => "a byte string literal",
+        ast::LitKind::Byte(..) => "a byte literal",
+        ast::LitKind::Char(..) => "a character literal",
+        ast::LitKind::Int(..) => "an integer literal",
+        ast::LitKind::Float(..) | ast::LitKind::FloatUnsuffixed(..) => "a float literal",
+        ast::LitKind::Bool(..) => "a boolean literal",
+        ast::LitKind::Err(..) => "an invalid literal",
+    }
+}
+
+/// Expands `expr` eagerly, so that, e.g., `concat!("foo", "bar")` is accepted where a literal
+/// is expected, and returns the literal it expands to. Otherwise, returns the expanded
+/// expression, or `None` if it's an error already reported.
+fn expand_to_lit(cx: &mut ExtCtxt<'_>, expr: P<ast::Expr>)
+                 -> Result<ast::Lit, Option<P<ast::Expr>>> {
+    // If none of the invocations can be resolved yet, nothing has been expanded, and forcing
+    // their resolution reports them.
+    let expr = match cx.eager_expand(AstFragment::Expr(expr.clone())) {
+        Ok(fragment) => fragment.make_expr(),
+        Err(Indeterminate) => {
+            cx.expander().fully_expand_fragment(AstFragment::Expr(expr)).make_expr()
+        }
+    };
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Err(_) => Err(None),
+            _ => Ok(lit.clone()),
+        },
+        ast::ExprKind::Err => Err(None),
+        _ => Err(Some(expr)),
+    }
+}
+
+/// Extracts a literal of one of the `expected` kinds from the macro expanded version of
+/// `expr`. Other literals and expressions are reported with a message naming the expected
+/// kinds, unless they are errors already reported. This does not stop compilation on error,
+/// merely emits a non-fatal error and returns `None`.
+pub fn expr_to_lit(cx: &mut ExtCtxt<'_>, expr: P<ast::Expr>, expected: LitKindSet)
+                   -> Option<ast::Lit> {
+    let (span, found) = match expand_to_lit(cx, expr) {
+        Ok(ref lit) if expected.intersects(LitKindSet::of(&lit.node)) => return Some(lit.clone()),
+        Ok(lit) => (lit.span, lit_kind_descr(&lit.node)),
+        Err(Some(expr)) => (expr.span, "an expression"),
+        Err(None) => return None,
+    };
+    let msg = format!("expected {}, found {}", expected.descr(), found);
+    cx.struct_span_err(span, &msg)
+        .span_label(span, format!("expected {}", expected.descr()))
+        .emit();
+    None
 }
 
 /// Extracts a string literal from the macro expanded version of `expr`,
@@ -986,18 +2350,11 @@ pub fn expr_to_spanned_string<'a>(
     expr: P<ast::Expr>,
     err_msg: &str,
 ) -> Result<(Symbol, ast::StrStyle, Span), Option<DiagnosticBuilder<'a>>> {
-    // Perform eager expansion on the expression.
-    // We want to be able to handle e.g., `concat!("foo", "bar")`.
-    let expr = cx.expander().fully_expand_fragment(AstFragment::Expr(expr)).make_expr();
-
-    Err(match expr.node {
-        ast::ExprKind::Lit(ref l) => match l.node {
-            ast::LitKind::Str(s, style) => return Ok((s, style, expr.span)),
-            ast::LitKind::Err(_) => None,
-            _ => Some(cx.struct_span_err(l.span, err_msg))
-        },
-        ast::ExprKind::Err => None,
-        _ => Some(cx.struct_span_err(expr.span, err_msg))
+    Err(match expand_to_lit(cx, expr) {
+        Ok(ast::Lit { node: ast::LitKind::Str(s, style), span, .. }) => return Ok((s, style, span)),
+        Ok(lit) => Some(cx.struct_span_err(lit.span, err_msg)),
+        Err(Some(expr)) => Some(cx.struct_span_err(expr.span, err_msg)),
+        Err(None) => None,
     })
 }
 
@@ -1014,47 +2371,88 @@ pub fn expr_to_string(cx: &mut ExtCtxt<'_>, expr: P<ast::Expr>, err_msg: &str)
 /// compilation should call
 /// `cx.parse_sess.span_diagnostic.abort_if_errors()` (this should be
 /// done as rarely as possible).
-pub fn check_zero_tts(cx: &ExtCtxt<'_>,
+pub fn check_zero_tts(diag: &mut dyn ExpansionDiagnostics,
                       sp: Span,
                       tts: TokenStream,
                       name: &str) {
     if !tts.is_empty() {
-        cx.span_err(sp, &format!("{} takes no arguments", name));
+        diag.span_err(sp.into(), &format!("{} takes no arguments", name));
     }
 }
 
 /// Interpreting `tts` as a comma-separated sequence of expressions,
 /// expect exactly one string literal, or emit an error and return `None`.
+///
+/// A parse error in the argument aborts compilation, see `try_get_single_str_from_tts` for a
+/// variant that returns it instead.
 pub fn get_single_str_from_tts(cx: &mut ExtCtxt<'_>,
                                sp: Span,
                                tts: TokenStream,
                                name: &str)
                                -> Option<String> {
+    match try_get_single_str_from_tts(cx, sp, tts, name) {
+        Ok(s) => Some(s),
+        Err(Some(mut err)) => {
+            err.emit();
+            FatalError.raise()
+        }
+        Err(None) => None,
+    }
+}
+
+/// Like `get_single_str_from_tts`, but a parse error is returned rather than emitted, so that
+/// the caller can emit it and recover, e.g., with a `DummyResult`. `Err(None)` means that the
+/// error has already been reported.
+pub fn try_get_single_str_from_tts<'a>(cx: &mut ExtCtxt<'a>,
+                                       sp: Span,
+                                       tts: TokenStream,
+                                       name: &str)
+                                       -> Result<String, Option<DiagnosticBuilder<'a>>> {
     let mut p = cx.new_parser_from_tts(tts);
     if p.token == token::Eof {
         cx.span_err(sp, &format!("{} takes 1 argument", name));
-        return None
+        return Err(None);
     }
-    let ret = panictry!(p.parse_expr());
+    let ret = p.parse_expr().map_err(Some)?;
     let _ = p.eat(&token::Comma);
 
     if p.token != token::Eof {
         cx.span_err(sp, &format!("{} takes 1 argument", name));
     }
-    expr_to_string(cx, ret, "argument must be a string literal").map(|(s, _)| {
-        s.to_string()
-    })
+    expr_to_string(cx, ret, "argument must be a string literal")
+        .map(|(s, _)| s.to_string())
+        .ok_or(None)
 }
 
 /// Extracts comma-separated expressions from `tts`. If there is a
 /// parsing error, emit a non-fatal error and return `None`.
+///
+/// A parse error in one of the expressions aborts compilation, see `try_get_exprs_from_tts`
+/// for a variant that returns it instead.
 pub fn get_exprs_from_tts(cx: &mut ExtCtxt<'_>,
                           sp: Span,
                           tts: TokenStream) -> Option<Vec<P<ast::Expr>>> {
+    match try_get_exprs_from_tts(cx, sp, tts) {
+        Ok(es) => Some(es),
+        Err(Some(mut err)) => {
+            err.emit();
+            FatalError.raise()
+        }
+        Err(None) => None,
+    }
+}
+
+/// Like `get_exprs_from_tts`, but a parse error is returned rather than emitted, so that the
+/// caller can emit it and recover, e.g., with a `DummyResult`. `Err(None)` means that the
+/// error has already been reported.
+pub fn try_get_exprs_from_tts<'a>(cx: &mut ExtCtxt<'a>,
+                                  sp: Span,
+                                  tts: TokenStream)
+                                  -> Result<Vec<P<ast::Expr>>, Option<DiagnosticBuilder<'a>>> {
     let mut p = cx.new_parser_from_tts(tts);
     let mut es = Vec::new();
     while p.token != token::Eof {
-        let expr = panictry!(p.parse_expr());
+        let expr = p.parse_expr().map_err(Some)?;
 
         // Perform eager expansion on the expression.
         // We want to be able to handle e.g., `concat!("foo", "bar")`.
@@ -1066,8 +2464,8 @@ pub fn get_exprs_from_tts(cx: &mut ExtCtxt<'_>,
         }
         if p.token != token::Eof {
             cx.span_err(sp, "expected token: `,`");
-            return None;
+            return Err(None);
         }
     }
-    Some(es)
+    Ok(es)
 }
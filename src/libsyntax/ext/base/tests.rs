@@ -0,0 +1,215 @@
+use super::*;
+
+use crate::ext::basic_resolver::BasicResolver;
+use crate::ext::expand::ExpansionConfig;
+use crate::feature_gate::Features;
+use crate::source_map::FilePathMapping;
+use crate::tests::{string_to_stream, with_error_checking_parse};
+use crate::with_default_globals;
+
+use syntax_pos::BytePos;
+
+#[test]
+fn expansion_requests_survive_framing() {
+    with_default_globals(|| {
+        let request = ExpansionRequest::Attr {
+            name: Symbol::intern("instrument"),
+            annotation: string_to_stream("level = \"debug\"".to_string()),
+            annotated: string_to_stream("fn f(x: u8) -> u8 { x + 1 }".to_string()),
+        };
+        let response = ExpansionResponse::Failed("unsupported".to_string());
+
+        let mut buf = Vec::new();
+        buf.send(&request).unwrap();
+        write_frame(&mut buf, &response).unwrap();
+
+        let mut reader = &buf[..];
+        assert_eq!(read_frame::<ExpansionRequest>(&mut reader).unwrap(), request);
+        assert_eq!(reader.receive().unwrap(), response);
+        assert!(reader.is_empty());
+        assert_eq!(reader.receive().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    })
+}
+
+#[test]
+fn lit_kind_set_descriptions() {
+    assert_eq!(LitKindSet::STR.descr(), "a string literal");
+    assert_eq!(LitKindSet::INT.descr(), "an integer literal");
+    assert_eq!((LitKindSet::STR | LitKindSet::BYTE_STR).descr(),
+               "a string or byte string literal");
+    assert_eq!((LitKindSet::INT | LitKindSet::BOOL | LitKindSet::STR).descr(),
+               "a string, integer or boolean literal");
+}
+
+#[test]
+fn respan_to_call_site_replaces_all_spans() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let expr = with_error_checking_parse("a + f(b)".to_string(), &sess, |p| p.parse_expr());
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let mut cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+        let call_site = Span::with_root_ctxt(BytePos(100), BytePos(105));
+        cx.current_expansion.id = ExpnId::fresh(Some(ExpnData::default(
+            ExpnKind::Macro(MacroKind::Bang, sym::test),
+            call_site,
+            sess.edition,
+        )));
+        let mut fragment = AstFragment::Expr(expr);
+        cx.respan_to_call_site(&mut fragment);
+
+        let sp = cx.with_call_site_ctxt(call_site);
+        let expr = fragment.make_expr();
+        assert_eq!(expr.span, sp);
+        match &expr.node {
+            ast::ExprKind::Binary(op, lhs, rhs) => {
+                assert_eq!(op.span, sp);
+                assert_eq!(lhs.span, sp);
+                assert_eq!(rhs.span, sp);
+                match &rhs.node {
+                    ast::ExprKind::Call(f, args) => {
+                        assert_eq!(f.span, sp);
+                        assert_eq!(args[0].span, sp);
+                    }
+                    _ => panic!("expected a call, found {:?}", rhs),
+                }
+            }
+            _ => panic!("expected a binary expression, found {:?}", expr),
+        }
+    })
+}
+
+#[test]
+fn sub_item_annotatables() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "enum E { #[a] V(#[b] #[c] u8) }".to_string();
+        let item = with_error_checking_parse(source, &sess, |p| p.parse_item()).unwrap();
+        let variant = match &item.node {
+            ast::ItemKind::Enum(def, _) => def.variants[0].clone(),
+            _ => panic!("expected an enum, found {:?}", item),
+        };
+
+        let field = Annotatable::Field(variant.data.fields()[0].clone());
+        assert_eq!(field.attrs().len(), 2);
+        assert_eq!(field.span(), variant.data.fields()[0].span);
+
+        let mut variant = Annotatable::Variant(variant);
+        assert_eq!(variant.attrs().len(), 1);
+        variant.visit_attrs(|attrs| attrs.clear());
+        assert!(variant.expect_variant().attrs.is_empty());
+    })
+}
+
+#[test]
+fn annotatables_convert_fallibly() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let expr = with_error_checking_parse("1 + 2".to_string(), &sess, |p| p.parse_expr());
+        let span = expr.span;
+
+        let annotatable = Annotatable::Expr(expr);
+        assert_eq!(annotatable.kind_descr(), "expression");
+        let annotatable = annotatable.try_into_item().unwrap_err();
+        assert_eq!(annotatable.span(), span);
+        assert!(annotatable.try_into_expr().is_ok());
+    })
+}
+
+#[test]
+fn diagnostics_within_error_nodes_are_suppressed() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+        let error_node = Span::with_root_ctxt(BytePos(10), BytePos(20));
+        sess.error_node_spans.borrow_mut().push(error_node);
+
+        cx.span_err(Span::with_root_ctxt(BytePos(12), BytePos(14)), "follow-on error");
+        cx.struct_span_warn(error_node, "follow-on warning").emit();
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+        cx.span_err(Span::with_root_ctxt(BytePos(18), BytePos(22)), "overlapping error");
+        assert_eq!(sess.span_diagnostic.err_count(), 1);
+    })
+}
+
+#[test]
+fn artifacts_are_recorded_in_order_with_the_call_site() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let mut cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+        let call_site = Span::with_root_ctxt(BytePos(100), BytePos(105));
+        cx.current_expansion.id = ExpnId::fresh(Some(ExpnData::default(
+            ExpnKind::Macro(MacroKind::Bang, sym::test),
+            call_site,
+            sess.edition,
+        )));
+        let (routes, schema) = (Symbol::intern("routes"), Symbol::intern("schema"));
+        cx.emit_artifact(routes, "GET /".to_string());
+        cx.emit_artifact(schema, "0f3a".to_string());
+        cx.emit_artifact(routes, "POST /".to_string());
+
+        let artifacts = sess.expansion_artifacts.borrow();
+        let artifacts: Vec<_> = artifacts.iter()
+            .map(|artifact| (artifact.kind, &artifact.payload[..], artifact.span))
+            .collect();
+        assert_eq!(artifacts, [
+            (routes, "GET /", call_site),
+            (schema, "0f3a", call_site),
+            (routes, "POST /", call_site),
+        ]);
+    })
+}
+
+#[test]
+fn trace_entries_are_grouped_by_call_site_in_order() {
+    with_default_globals(|| {
+        let (a, b, c) = (
+            Span::with_root_ctxt(BytePos(0), BytePos(5)),
+            Span::with_root_ctxt(BytePos(10), BytePos(15)),
+            Span::with_root_ctxt(BytePos(20), BytePos(25)),
+        );
+        let entry = |span, name, kind| TraceEntry { span, macro_name: Ident::from_str(name), kind };
+        let stream = |source: &str| string_to_stream(source.to_string());
+        let entries = [
+            entry(a, "m", TraceKind::Expanding(stream("1 + 1"))),
+            entry(b, "n", TraceKind::Expanding(stream("x"))),
+            entry(a, "m", TraceKind::To(stream("2"))),
+            entry(c, "o", TraceKind::Expanding(stream(""))),
+            entry(b, "n", TraceKind::To(stream("y z"))),
+        ];
+
+        let groups: Vec<_> = group_trace_entries(&entries).into_iter().map(|(span, entries)| {
+            let messages: Vec<_> = entries.iter().map(|entry| entry.message(None)).collect();
+            (span, messages)
+        }).collect();
+        let strings = |strings: &[&str]| strings.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(groups, [
+            (a, strings(&["expanding `m! { 1 + 1 }`", "to `2`"])),
+            (b, strings(&["expanding `n! { x }`", "to `y z`"])),
+            (c, strings(&["expanding `o! {  }`"])),
+        ]);
+    })
+}
+
+#[test]
+fn macro_definition_attributes_are_read_as_meta_items() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "#[allow_internal_unstable(foo, bar)] \
+                      #[macro_export(local_inner_macros)] \
+                      macro_rules! m { () => {} }".to_string();
+        let item = with_error_checking_parse(source, &sess, |p| p.parse_item()).unwrap();
+        let kind = SyntaxExtension::dummy_bang(sess.edition).kind;
+        let ext = SyntaxExtension::new(
+            &sess, kind, item.span, Vec::new(), sess.edition, item.ident.name, &item.attrs,
+        );
+        let features = ext.allow_internal_unstable.unwrap();
+        assert_eq!(&features[..], [Symbol::intern("foo"), Symbol::intern("bar")]);
+        assert!(ext.local_inner_macros);
+        assert!(!sess.span_diagnostic.has_errors());
+    })
+}
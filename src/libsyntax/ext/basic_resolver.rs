@@ -0,0 +1,331 @@
+//! A minimal `Resolver` for embedders that only need `macro_rules!` expansion.
+
+use crate::ast::{self, Name, NodeId};
+use crate::attr;
+use crate::edition::Edition;
+use crate::ext::base::{DeriveRegistry, ExpansionScopes, ExtCtxt, Indeterminate, InvocationRes};
+use crate::ext::base::{MacroKind, MacroRegistry, PathNamespace, Resolver, SyntaxExtension};
+use crate::ext::expand::{AstFragment, ExpansionConfig, Invocation, InvocationKind};
+use crate::ext::hygiene::ExpnId;
+use crate::ext::tt::macro_rules;
+use crate::feature_gate::Features;
+use crate::parse::ParseSess;
+use crate::symbol::{sym, Symbol};
+use crate::visit::{self, Visitor};
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::sync::Lrc;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+/// Expands the macros in `krate` that are defined by `macro_rules!` in the crate itself,
+/// without the compiler's name resolution. Invocations of any other macros are reported as
+/// unresolved, see `BasicResolver`.
+pub fn expand_local_macros(
+    krate: ast::Crate, parse_sess: &ParseSess, config: ExpansionConfig<'_>
+) -> ast::Crate {
+    let default_features = Features::new();
+    let features = config.features.unwrap_or(&default_features);
+    let config = ExpansionConfig { features: Some(features), ..config };
+    let mut resolver = BasicResolver::new(parse_sess, features, parse_sess.edition);
+    let mut cx = ExtCtxt::new(parse_sess, config, &mut resolver);
+    cx.monotonic_expander().expand_crate(krate)
+}
+
+/// A `Resolver` that resolves macro invocations by name against the `macro_rules!` macros
+/// defined in the expanded code and the registered built-in macros.
+///
+/// There are no modules or imports, only single-segment paths are resolved, using the textual
+/// scoping of `macro_rules!`: a macro is visible after its definition until the end of the
+/// enclosing block or module, or further if the module is `#[macro_use]`. This is enough for
+/// tools that parse and expand self-contained code.
+pub struct BasicResolver<'a> {
+    sess: &'a ParseSess,
+    features: &'a Features,
+    edition: Edition,
+    next_node_id: u32,
+    builtin_macros: FxHashMap<Name, Lrc<SyntaxExtension>>,
+    /// The expansion that produced each invocation that has been collected.
+    invocation_parents: FxHashMap<ExpnId, ExpnId>,
+    /// The textual scope at the position of each invocation that has been collected.
+    invocation_scopes: FxHashMap<ExpnId, Rc<MacroScope>>,
+    /// The textual scope after the output of each expansion.
+    output_scopes: FxHashMap<ExpnId, Rc<MacroScope>>,
+}
+
+/// A textual scope of `macro_rules!` definitions, as a chain going backwards in the source.
+enum MacroScope {
+    Empty,
+    Binding { parent: Rc<MacroScope>, name: Name, ext: Lrc<SyntaxExtension> },
+    /// The (possibly not yet known) output of a macro invocation.
+    Invocation { parent: Rc<MacroScope>, expn_id: ExpnId },
+}
+
+impl<'a> BasicResolver<'a> {
+    pub fn new(sess: &'a ParseSess, features: &'a Features, edition: Edition) -> Self {
+        BasicResolver {
+            sess,
+            features,
+            edition,
+            next_node_id: ast::CRATE_NODE_ID.as_u32() + 1,
+            builtin_macros: FxHashMap::default(),
+            invocation_parents: FxHashMap::default(),
+            invocation_scopes: FxHashMap::default(),
+            output_scopes: FxHashMap::default(),
+        }
+    }
+
+    fn compile_macro(&self, item: &ast::Item) -> Lrc<SyntaxExtension> {
+        let ext = macro_rules::compile(self.sess, self.features, item, self.edition);
+        // Definitions of built-in macros only stand in for the registered implementation.
+        if ext.is_builtin {
+            if let Some(builtin) = self.builtin_macros.get(&item.ident.name) {
+                return builtin.clone();
+            }
+        }
+        Lrc::new(ext)
+    }
+
+    fn lookup<'s>(
+        &'s self, mut scope: &'s Rc<MacroScope>, name: Name, force: bool
+    ) -> Result<Option<Lrc<SyntaxExtension>>, Indeterminate> {
+        loop {
+            scope = match **scope {
+                MacroScope::Empty => return Ok(self.builtin_macros.get(&name).cloned()),
+                MacroScope::Binding { ref parent, name: binding, ref ext } => {
+                    if binding == name {
+                        return Ok(Some(ext.clone()));
+                    }
+                    parent
+                }
+                MacroScope::Invocation { ref parent, expn_id } => {
+                    match self.output_scopes.get(&expn_id) {
+                        Some(output_scope) => output_scope,
+                        // The invocation may still define the macro, but if it can't be expanded
+                        // without the macro being resolved, pretend that it doesn't.
+                        None if force => parent,
+                        None => return Err(Indeterminate),
+                    }
+                }
+            }
+        }
+    }
+
+    fn resolve_macro_path(
+        &self, path: &ast::Path, kind: MacroKind, scope: &Rc<MacroScope>, force: bool
+    ) -> Result<Lrc<SyntaxExtension>, Indeterminate> {
+        let ext = match &path.segments[..] {
+            [segment] => self.lookup(scope, segment.ident.name, force)?,
+            _ => None,
+        };
+        match ext {
+            Some(ref ext) if ext.macro_kind() == kind => Ok(ext.clone()),
+            _ if !force => Err(Indeterminate),
+            _ => {
+                let msg = format!("cannot find {} `{}` in this scope", kind.descr(), path);
+                self.sess.span_diagnostic.span_err(path.span, &msg);
+                Ok(Lrc::new(match kind {
+                    MacroKind::Bang => SyntaxExtension::dummy_bang(self.edition),
+                    MacroKind::Attr => SyntaxExtension::non_macro_attr(true, self.edition),
+                    MacroKind::Derive => SyntaxExtension::dummy_derive(self.edition),
+                }))
+            }
+        }
+    }
+}
+
+impl Resolver for BasicResolver<'_> {
+    fn next_node_id(&mut self) -> NodeId {
+        let id = NodeId::from_u32(self.next_node_id);
+        self.next_node_id += 1;
+        id
+    }
+
+    fn resolve_macro_invocation(
+        &mut self, invoc: &Invocation, eager_expansion_root: ExpnId, force: bool
+    ) -> Result<InvocationRes, Indeterminate> {
+        // Eagerly expanded invocations aren't collected, they belong where their root does.
+        let invoc_id = invoc.expansion_data.id;
+        let collected_id = if self.invocation_scopes.contains_key(&invoc_id) {
+            invoc_id
+        } else {
+            eager_expansion_root
+        };
+        let scope = self.invocation_scopes.get(&collected_id)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(MacroScope::Empty));
+        let parent =
+            self.invocation_parents.get(&collected_id).cloned().unwrap_or_else(ExpnId::root);
+
+        let (path, kind) = match invoc.kind {
+            InvocationKind::Attr { ref attr, .. } => (&attr.path, MacroKind::Attr),
+            InvocationKind::Bang { ref mac, .. } => (&mac.path, MacroKind::Bang),
+            InvocationKind::Derive { ref path, .. } => (path, MacroKind::Derive),
+            InvocationKind::DeriveContainer { ref derives, .. } => {
+                let exts = derives.iter()
+                    .map(|path| self.resolve_macro_path(path, MacroKind::Derive, &scope, force))
+                    .collect::<Result<_, _>>()?;
+                return Ok(InvocationRes::DeriveContainer(exts));
+            }
+        };
+        let descr = Symbol::intern(&path.to_string());
+        if invoc.is_unconfigured() {
+            // The item is removed unless the macro wants to see it, so a path that doesn't
+            // resolve isn't reported.
+            let ext = match &path.segments[..] {
+                [segment] => self.lookup(&scope, segment.ident.name, force)?,
+                _ => None,
+            };
+            match ext {
+                Some(ref ext) if ext.macro_kind() == kind && ext.sees_unconfigured => {}
+                _ => {
+                    let ext = Lrc::new(SyntaxExtension::non_macro_attr(true, self.edition));
+                    invoc_id.set_expn_data(ext.expn_data(parent, invoc.span(), descr));
+                    return Ok(InvocationRes::Single(ext));
+                }
+            }
+        }
+        let ext = self.resolve_macro_path(path, kind, &scope, force)?;
+
+        invoc_id.set_expn_data(ext.expn_data(parent, invoc.span(), descr));
+        Ok(InvocationRes::Single(ext))
+    }
+}
+
+impl ExpansionScopes for BasicResolver<'_> {
+    fn resolve_path_at_expansion(
+        &mut self, path: &ast::Path, ns: PathNamespace, expn_id: ExpnId
+    ) -> Result<bool, Indeterminate> {
+        // Only macros are known, other paths have nothing to resolve to.
+        match (ns, &path.segments[..]) {
+            (PathNamespace::Macro, [segment]) => {
+                let scope = self.invocation_scopes.get(&expn_id)
+                    .cloned()
+                    .unwrap_or_else(|| Rc::new(MacroScope::Empty));
+                Ok(self.lookup(&scope, segment.ident.name, false)?.is_some())
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn visit_ast_fragment_with_placeholders(
+        &mut self, expn_id: ExpnId, fragment: &AstFragment, extra_placeholders: &[NodeId]
+    ) {
+        let scope = self.invocation_scopes.get(&expn_id)
+            .cloned()
+            .unwrap_or_else(|| Rc::new(MacroScope::Empty));
+        let mut collector = ScopeCollector { resolver: self, expn_id, scope };
+        fragment.visit_with(&mut collector);
+        for &id in extra_placeholders {
+            collector.visit_invoc(id);
+        }
+        let output_scope = collector.scope;
+        self.output_scopes.insert(expn_id, output_scope);
+    }
+}
+
+impl MacroRegistry for BasicResolver<'_> {
+    fn register_builtin_macro(&mut self, ident: ast::Ident, ext: SyntaxExtension) {
+        self.builtin_macros.insert(ident.name, Lrc::new(ext));
+    }
+}
+
+impl DeriveRegistry for BasicResolver<'_> {}
+
+/// Records the textual scope of every macro invocation placeholder in an AST fragment.
+struct ScopeCollector<'r, 'a> {
+    resolver: &'r mut BasicResolver<'a>,
+    /// The expansion that produced the fragment.
+    expn_id: ExpnId,
+    scope: Rc<MacroScope>,
+}
+
+impl ScopeCollector<'_, '_> {
+    fn visit_invoc(&mut self, id: NodeId) {
+        let invoc_id = id.placeholder_to_expn_id();
+        self.resolver.invocation_scopes.insert(invoc_id, self.scope.clone());
+        self.resolver.invocation_parents.insert(invoc_id, self.expn_id);
+        let parent = self.scope.clone();
+        self.scope = Rc::new(MacroScope::Invocation { parent, expn_id: invoc_id });
+    }
+}
+
+impl<'ast> Visitor<'ast> for ScopeCollector<'_, '_> {
+    fn visit_item(&mut self, item: &'ast ast::Item) {
+        match item.node {
+            ast::ItemKind::MacroDef(ref def) if def.legacy => {
+                let ext = self.resolver.compile_macro(item);
+                let parent = self.scope.clone();
+                self.scope = Rc::new(MacroScope::Binding { parent, name: item.ident.name, ext });
+            }
+            ast::ItemKind::Mac(..) => self.visit_invoc(item.id),
+            ast::ItemKind::Mod(..) if attr::contains_name(&item.attrs, sym::macro_use) => {
+                visit::walk_item(self, item);
+            }
+            _ => {
+                let scope = self.scope.clone();
+                visit::walk_item(self, item);
+                self.scope = scope;
+            }
+        }
+    }
+
+    fn visit_block(&mut self, block: &'ast ast::Block) {
+        let scope = self.scope.clone();
+        visit::walk_block(self, block);
+        self.scope = scope;
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast ast::TraitItem) {
+        match item.node {
+            ast::TraitItemKind::Macro(..) => self.visit_invoc(item.id),
+            _ => visit::walk_trait_item(self, item),
+        }
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast ast::ImplItem) {
+        match item.node {
+            ast::ImplItemKind::Macro(..) => self.visit_invoc(item.id),
+            _ => visit::walk_impl_item(self, item),
+        }
+    }
+
+    fn visit_foreign_item(&mut self, item: &'ast ast::ForeignItem) {
+        match item.node {
+            ast::ForeignItemKind::Macro(..) => self.visit_invoc(item.id),
+            _ => visit::walk_foreign_item(self, item),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast ast::Stmt) {
+        match stmt.node {
+            ast::StmtKind::Mac(..) => self.visit_invoc(stmt.id),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr.node {
+            ast::ExprKind::Mac(..) => self.visit_invoc(expr.id),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pat(&mut self, pat: &'ast ast::Pat) {
+        match pat.node {
+            ast::PatKind::Mac(..) => self.visit_invoc(pat.id),
+            _ => visit::walk_pat(self, pat),
+        }
+    }
+
+    fn visit_ty(&mut self, ty: &'ast ast::Ty) {
+        match ty.node {
+            ast::TyKind::Mac(..) => self.visit_invoc(ty.id),
+            _ => visit::walk_ty(self, ty),
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &'ast ast::Mac) {}
+}
@@ -0,0 +1,925 @@
+use super::*;
+
+use crate::ext::expand::ExpansionObserver;
+use crate::ext::macro_trace::{MacroTrace, MacroTraceParent};
+use crate::parse;
+use crate::source_map::FilePathMapping;
+use crate::with_default_globals;
+
+use std::mem;
+use std::path::PathBuf;
+
+/// Parses `source` and expands it with a `BasicResolver` knowing the built-in `macros`.
+fn expand_source(sess: &ParseSess, source: &str, macros: Vec<(&str, SyntaxExtension)>,
+                 config: ExpansionConfig<'_>) -> ast::Crate {
+    expand_source_with_trace(sess, source, macros, config).0
+}
+
+/// Like `expand_source`, also returning the invocations traced with
+/// `ExpansionConfig::trace_json`.
+fn expand_source_with_trace(sess: &ParseSess, source: &str,
+                            macros: Vec<(&str, SyntaxExtension)>, config: ExpansionConfig<'_>)
+                            -> (ast::Crate, MacroTrace) {
+    let krate = parse::parse_crate_from_source_str(
+        PathBuf::from("test").into(), source.to_string(), sess,
+    ).unwrap();
+    let default_features = Features::new();
+    let features = config.features.unwrap_or(&default_features);
+    let config = ExpansionConfig { features: Some(features), ..config };
+    let mut resolver = BasicResolver::new(sess, features, sess.edition);
+    for (name, ext) in macros {
+        resolver.register_builtin_macro(ast::Ident::from_str(name), ext);
+    }
+    let mut cx = ExtCtxt::new(sess, config, &mut resolver);
+    let krate = cx.monotonic_expander().expand_crate(krate);
+    (krate, mem::replace(&mut cx.macro_trace, MacroTrace::default()))
+}
+
+#[test]
+fn expands_macros_in_textual_scope() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => { fn f() {} } }\n\
+                      m!();\n\
+                      mod inner { m!(); }\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let krate = expand_local_macros(krate, &sess, ExpansionConfig::default("test".into()));
+
+        let names: Vec<_> = krate.module.items.iter().map(|item| item.ident.to_string()).collect();
+        assert_eq!(names, ["m", "f", "inner"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn macros_defined_by_expansions_are_in_scope_after_them() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! def {\n\
+                          ($m:ident) => { macro_rules! $m { () => { fn f() {} } } }\n\
+                      }\n\
+                      def!(m);\n\
+                      m!();\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let krate = expand_local_macros(krate, &sess, ExpansionConfig::default("test".into()));
+
+        let names: Vec<_> = krate.module.items.iter().map(|item| item.ident.to_string()).collect();
+        assert_eq!(names, ["def", "m", "f"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn macros_out_of_textual_scope_are_unresolved() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "before!();\n\
+                      macro_rules! before { () => {} }\n\
+                      mod a { macro_rules! in_mod { () => {} } }\n\
+                      in_mod!();\n\
+                      #[macro_use] mod b { macro_rules! in_macro_use { () => { fn g() {} } } }\n\
+                      in_macro_use!();\n\
+                      fn f() { macro_rules! in_block { () => {} } }\n\
+                      in_block!();\n\
+                      self::before!();\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let krate = expand_local_macros(krate, &sess, ExpansionConfig::default("test".into()));
+
+        assert!(krate.module.items.iter().any(|item| item.ident.as_str() == "g"));
+        // `before!`, `in_mod!`, `in_block!` and the path with several segments.
+        assert_eq!(sess.span_diagnostic.err_count(), 4);
+    })
+}
+
+#[test]
+fn parameter_macros_pass_their_attributes_on() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! p { ($($name:ident),*) => { $($name: u8),* } }\n\
+                      fn f(#[allow(unused)] p!(a, b), c: u8) {}\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let krate = expand_local_macros(krate, &sess, ExpansionConfig::default("test".into()));
+
+        let params = match krate.module.items[1].node {
+            ast::ItemKind::Fn(ref decl, ..) => &decl.inputs,
+            _ => panic!("expected a function"),
+        };
+        let attrs: Vec<_> = params.iter()
+            .map(|param| param.attrs.iter().map(|attr| attr.path.to_string()).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(attrs, [vec!["allow".to_string()], vec!["allow".to_string()], Vec::new()]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn observers_see_each_expansion() {
+    struct Recorder<'a>(&'a mut Vec<String>);
+
+    impl ExpansionObserver for Recorder<'_> {
+        fn before(&mut self, invoc: &Invocation) {
+            if let InvocationKind::Bang { mac, .. } = &invoc.kind {
+                self.0.push(format!("before {}", mac.path));
+            }
+        }
+
+        fn after(&mut self, _invoc_id: ExpnId, fragment: &AstFragment) {
+            if let AstFragment::Items(items) = fragment {
+                for item in items {
+                    self.0.push(format!("after {}", item.node.descriptive_variant()));
+                }
+            }
+        }
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => { n!(); } }\n\
+                      macro_rules! n { () => { fn f() {} } }\n\
+                      m!();\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let mut events = Vec::new();
+        let config = ExpansionConfig {
+            observers: vec![Box::new(Recorder(&mut events))],
+            ..ExpansionConfig::default("test".into())
+        };
+        expand_local_macros(krate, &sess, config);
+
+        assert_eq!(events, ["before m", "after item", "before n", "after function"]);
+    })
+}
+
+#[test]
+fn expression_attribute_macros_replace_expressions() {
+    use crate::ext::base::SyntaxExtensionKind;
+    use crate::parse::token;
+    use crate::print::pprust;
+    use crate::tests::string_to_stream;
+    use crate::tokenstream::{DelimSpan, TokenStream, TokenTree};
+
+    fn double(_: TokenStream, expr: TokenStream) -> TokenStream {
+        let mut trees = vec![TokenTree::Delimited(DelimSpan::dummy(), token::Paren, expr).into()];
+        string_to_stream("* 2".to_string()).append_to_tree_and_joint_vec(&mut trees);
+        TokenStream::new(trees)
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "fn f() -> u32 { let x = #[double] (1 + 1); #[double] x }\n\
+                      fn g() { #[double] h(); }\n";
+        let mut features = Features::new();
+        features.stmt_expr_attributes = true;
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::ExprAttr(Box::new(double)),
+                                           sess.edition);
+        let config = ExpansionConfig {
+            features: Some(&features),
+            ..ExpansionConfig::default("test".into())
+        };
+        let krate = expand_source(&sess, source, vec![("double", ext)], config);
+
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+        let f = pprust::item_to_string(&krate.module.items[0]);
+        assert_eq!(f.matches("* 2").count(), 2, "{}", f);
+        let g = pprust::item_to_string(&krate.module.items[1]);
+        assert!(g.contains("(h()) * 2;"), "{}", g);
+    })
+}
+
+#[test]
+fn expanders_buffer_lints_at_the_crate_level() {
+    use crate::early_buffered_lints::BufferedEarlyLintId;
+    use crate::ext::base::{MacEager, MacResult, SyntaxExtensionKind};
+    use crate::tokenstream::TokenStream;
+    use smallvec::SmallVec;
+    use syntax_pos::Span;
+
+    fn lint<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                 -> Box<dyn MacResult + 'cx> {
+        cx.buffer_lint(BufferedEarlyLintId::MacroBacktracking, span, "linted");
+        MacEager::items(SmallVec::new())
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "lint!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(lint)),
+                                           sess.edition);
+        expand_source(&sess, source, vec![("lint", ext)],
+                      ExpansionConfig::default("test".into()));
+
+        let lints = sess.buffered_lints.borrow();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].id, ast::CRATE_NODE_ID);
+        assert_eq!(lints[0].msg, "linted");
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn expanders_see_the_attributes_of_enclosing_items() {
+    use crate::early_buffered_lints::BufferedEarlyLintId;
+    use crate::ext::base::{MacEager, MacResult, SyntaxExtensionKind};
+    use crate::print::pprust;
+    use crate::tokenstream::TokenStream;
+    use smallvec::SmallVec;
+    use syntax_pos::Span;
+
+    fn attrs<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                  -> Box<dyn MacResult + 'cx> {
+        let attrs = cx.ancestor_attrs().into_iter().map(pprust::attribute_to_string)
+            .collect::<Vec<_>>().join(" ");
+        cx.buffer_lint(BufferedEarlyLintId::MacroBacktracking, span, &attrs);
+        MacEager::items(SmallVec::new())
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "#![allow(unused)]\n\
+                      attrs!();\n\
+                      #[doc(hidden)] mod m {\n\
+                          fn f() { attrs!(); }\n\
+                          #[inline] fn g() { attrs!(); }\n\
+                      }\n\
+                      fn h() { attrs!(); }\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(attrs)),
+                                           sess.edition);
+        expand_source(&sess, source, vec![("attrs", ext)],
+                      ExpansionConfig::default("test".into()));
+
+        let mut lints: Vec<_> =
+            sess.buffered_lints.borrow().iter().map(|lint| lint.msg.clone()).collect();
+        lints.sort();
+        assert_eq!(lints, [
+            "#![allow(unused)]",
+            "#![allow(unused)]",
+            "#![allow(unused)] #[doc(hidden)]",
+            "#![allow(unused)] #[doc(hidden)] #[inline]",
+        ]);
+    })
+}
+
+#[test]
+fn requested_imports_are_injected_once_per_module() {
+    use crate::ext::base::{MacEager, MacResult, SyntaxExtensionKind};
+    use crate::print::pprust;
+    use crate::tokenstream::TokenStream;
+    use smallvec::SmallVec;
+    use syntax_pos::Span;
+
+    fn uses<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                 -> Box<dyn MacResult + 'cx> {
+        let std = ast::Ident::from_str("std");
+        let path = |name| cx.path(span, vec![std, ast::Ident::from_str(name)]);
+        let (mem, fmt) = (path("mem"), path("fmt"));
+        cx.ensure_import(mem.clone());
+        cx.ensure_import(fmt);
+        cx.ensure_import(mem);
+        MacEager::items(SmallVec::new())
+    }
+
+    fn imports(module: &ast::Mod) -> Vec<String> {
+        module.items.iter().filter_map(|item| match item.node {
+            ast::ItemKind::Use(ref tree) => Some(pprust::path_to_string(&tree.prefix)),
+            _ => None,
+        }).collect()
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "uses!();\n\
+                      mod m { uses!(); mod n {} }\n\
+                      uses!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(uses)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("uses", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        assert_eq!(imports(&krate.module), ["std::mem", "std::fmt"]);
+        let module = |item: &ast::Item| match item.node {
+            ast::ItemKind::Mod(ref module) => module.clone(),
+            _ => panic!("expected a module, found {}", pprust::item_to_string(item)),
+        };
+        let m = module(&krate.module.items[0]);
+        assert_eq!(imports(&m), ["std::mem", "std::fmt"]);
+        assert_eq!(imports(&module(&m.items[0])), Vec::<String>::new());
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn eager_expansion_gives_up_on_undefined_macros() {
+    use crate::ext::base::{DummyResult, MacEager, MacResult, SyntaxExtensionKind};
+    use crate::print::pprust;
+    use crate::tokenstream::TokenStream;
+    use syntax_pos::Span;
+
+    fn eager<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, tts: TokenStream)
+                  -> Box<dyn MacResult + 'cx> {
+        let expr = cx.new_parser_from_tts(tts).parse_expr().unwrap();
+        match cx.eager_expand(AstFragment::Expr(expr)) {
+            Ok(fragment) => MacEager::expr(fragment.make_expr()),
+            Err(Indeterminate) => {
+                cx.span_err(span, "indeterminate");
+                DummyResult::any(span)
+            }
+        }
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! one { () => { 1 } }\n\
+                      fn f() -> u32 { eager!(one!() + 1) }\n\
+                      fn g() -> u32 { eager!(two!()) }\n\
+                      fn h() -> u32 { eager!(one!() + two!()) }\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(eager)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("eager", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        let f = pprust::item_to_string(&krate.module.items[1]);
+        assert!(f.contains("1 + 1"), "{}", f);
+        // Once `one!()` is expanded, `two!()` is reported instead of giving up.
+        let h = pprust::item_to_string(&krate.module.items[3]);
+        assert!(h.contains("1 +"), "{}", h);
+        assert_eq!(sess.span_diagnostic.err_count(), 2);
+    })
+}
+
+#[test]
+fn stable_expansion_ids_ignore_unrelated_items() {
+    struct Recorder<'a>(&'a mut Vec<(String, u32)>);
+
+    impl ExpansionObserver for Recorder<'_> {
+        fn before(&mut self, invoc: &Invocation) {
+            if let InvocationKind::Bang { mac, .. } = &invoc.kind {
+                self.0.push((mac.path.to_string(), invoc.expansion_data.id.as_u32()));
+            }
+        }
+    }
+
+    fn expansion_ids(source: &str) -> Vec<(String, u32)> {
+        let mut ids = Vec::new();
+        with_default_globals(|| {
+            let sess = ParseSess::new(FilePathMapping::empty());
+            let source = format!("macro_rules! m {{ () => {{}} }}\n\
+                                  macro_rules! n {{ () => {{}} }}\n{}", source);
+            let krate =
+                parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                    .unwrap();
+            let config = ExpansionConfig {
+                stable_expn_ids: true,
+                observers: vec![Box::new(Recorder(&mut ids))],
+                ..ExpansionConfig::default("test".into())
+            };
+            expand_local_macros(krate, &sess, config);
+        });
+        ids.sort();
+        ids
+    }
+
+    let before = expansion_ids("m!(); n!(); m!();");
+    assert_eq!(before.len(), 3);
+    assert!(before[0].1 != before[1].1 && before[1].1 != before[2].1);
+    // Moving `n!()` before the invocations of `m` and adding items between them keeps all IDs.
+    let reordered = expansion_ids("n!(); fn f() {} m!(); fn g() {} m!();");
+    assert_eq!(reordered, before);
+    // Adding invocations of another macro keeps the IDs of the existing ones.
+    let added = expansion_ids("n!(); m!(); n!(); m!(); n!();");
+    for id in &before {
+        assert!(added.contains(id), "{:?} not in {:?}", id, added);
+    }
+}
+
+#[test]
+fn cached_macro_rules_matches_transcribe_each_input() {
+    use crate::print::pprust;
+
+    fn expand(macro_rules_cache: bool) -> (Vec<String>, Vec<u32>) {
+        with_default_globals(|| {
+            let sess = ParseSess::new(FilePathMapping::empty());
+            let source = "macro_rules! m { ($f:ident, $($t:tt)*) => { fn $f() { $($t)* } } }\n\
+                          m!(f, 1 + 1); m!(f, 1 + 1); m!(g, 2);\n".to_string();
+            let krate =
+                parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                    .unwrap();
+            let config =
+                ExpansionConfig { macro_rules_cache, ..ExpansionConfig::default("test".into()) };
+            let krate = expand_local_macros(krate, &sess, config);
+            assert_eq!(sess.span_diagnostic.err_count(), 0);
+            let items = &krate.module.items;
+            (items.iter().map(|item| pprust::item_to_string(item)).collect(),
+             items.iter().map(|item| item.ident.span.lo().0).collect())
+        })
+    }
+
+    let (cached, cached_spans) = expand(true);
+    let (uncached, uncached_spans) = expand(false);
+    assert_eq!(cached, uncached);
+    // The identifiers of each expansion come from its own invocation.
+    assert_eq!(cached_spans, uncached_spans);
+    assert_ne!(cached_spans[1], cached_spans[2]);
+}
+
+#[test]
+fn attribute_macros_see_the_source_text_of_items() {
+    use crate::ext::base::{Annotatable, SyntaxExtensionKind};
+    use syntax_pos::Span;
+
+    fn capture(cx: &mut ExtCtxt<'_>, _: Span, _: &ast::MetaItem, item: Annotatable)
+               -> Annotatable {
+        let (text, _, fidelity) = cx.item_source_text(&item).unwrap();
+        cx.emit_artifact(Symbol::intern("source"), format!("{:?}: {}", fidelity, text));
+        item
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "#[capture]\n\
+                      /// Adds one.\n\
+                      fn f(x: u8) -> u8 { x /* plus */ + 1 }\n\
+                      macro_rules! m { () => { #[capture] fn g() {} } }\n\
+                      m!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyAttr(Box::new(capture)),
+                                           sess.edition);
+        expand_source(&sess, source, vec![("capture", ext)],
+                      ExpansionConfig::default("test".into()));
+
+        let artifacts = sess.expansion_artifacts.borrow();
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0].payload,
+                   "Verbatim: /// Adds one.\nfn f(x: u8) -> u8 { x /* plus */ + 1 }");
+        assert!(artifacts[1].payload.starts_with("PrettyPrinted: fn g()"),
+                "{}", artifacts[1].payload);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn attribute_macros_on_the_crate_root_receive_the_crate() {
+    use crate::ast::{VariantData, DUMMY_NODE_ID};
+    use crate::ext::base::{Annotatable, SyntaxExtensionKind};
+    use syntax_pos::Span;
+
+    fn extend(cx: &mut ExtCtxt<'_>, span: Span, _: &ast::MetaItem, item: Annotatable)
+              -> Vec<Annotatable> {
+        let mut krate = item.expect_crate();
+        let unit = || VariantData::Unit(DUMMY_NODE_ID);
+        krate.module.items.push(cx.item_struct(span, ast::Ident::from_str("Added"), unit()));
+        let next = cx.item_struct(span, ast::Ident::from_str("Next"), unit());
+        vec![Annotatable::Crate(krate), Annotatable::Item(next)]
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "#![extend]\n\
+                      #![allow(unused)]\n\
+                      fn f() {}\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyAttr(Box::new(extend)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("extend", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        let attrs: Vec<_> = krate.attrs.iter().map(|attr| attr.path.to_string()).collect();
+        assert_eq!(attrs, ["allow"]);
+        let items: Vec<_> = krate.module.items.iter().map(|item| item.ident.to_string()).collect();
+        assert_eq!(items, ["f", "Added", "Next"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn attribute_macros_can_keep_their_attribute() {
+    use crate::ast::{VariantData, DUMMY_NODE_ID};
+    use crate::ext::base::{Annotatable, SyntaxExtensionKind};
+    use syntax_pos::Span;
+
+    fn generate(cx: &mut ExtCtxt<'_>, span: Span, _: &ast::MetaItem, item: Annotatable)
+                -> Vec<Annotatable> {
+        let unit = VariantData::Unit(DUMMY_NODE_ID);
+        let generated = cx.item_struct(span, ast::Ident::from_str("Generated"), unit);
+        vec![item, Annotatable::Item(generated)]
+    }
+
+    let expand = |keep_attr| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "#[generate] #[inline] fn f() {}\n";
+        let ext = SyntaxExtension {
+            keep_attr,
+            ..SyntaxExtension::default(SyntaxExtensionKind::LegacyAttr(Box::new(generate)),
+                                       sess.edition)
+        };
+        let krate = expand_source(&sess, source, vec![("generate", ext)],
+                                  ExpansionConfig::default("test".into()));
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+
+        krate.module.items.iter().map(|item| {
+            let attrs: Vec<_> = item.attrs.iter().map(|attr| attr.path.to_string()).collect();
+            (item.ident.to_string(), attrs)
+        }).collect::<Vec<_>>()
+    };
+
+    with_default_globals(|| {
+        let strings = |strings: &[&str]| strings.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(expand(true), [
+            ("f".to_string(), strings(&["generate", "inline"])),
+            ("Generated".to_string(), Vec::new()),
+        ]);
+        assert_eq!(expand(false), [
+            ("f".to_string(), strings(&["inline"])),
+            ("Generated".to_string(), Vec::new()),
+        ]);
+    })
+}
+
+#[test]
+fn dry_run_reports_attribute_macro_output_without_using_it() {
+    use crate::ext::base::{Annotatable, SyntaxExtensionKind};
+    use syntax_pos::Span;
+
+    fn generate(cx: &mut ExtCtxt<'_>, span: Span, _: &ast::MetaItem, item: Annotatable)
+                -> Vec<Annotatable> {
+        let unit = cx.ty(span, ast::TyKind::Tup(Vec::new()));
+        let generated = cx.item_fn(span, ast::Ident::from_str("generated"), Vec::new(), unit,
+                                   cx.block(span, Vec::new()));
+        vec![item, Annotatable::Item(generated)]
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "fn before() {}\n\
+                      #[generate]\n\
+                      fn f() {}\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyAttr(Box::new(generate)),
+                                           sess.edition);
+        let config = ExpansionConfig {
+            attr_dry_run: true,
+            ..ExpansionConfig::default("test".into())
+        };
+        let krate = expand_source(&sess, source, vec![("generate", ext)], config);
+
+        let names: Vec<_> = krate.module.items.iter().map(|item| item.ident.to_string()).collect();
+        assert_eq!(names, ["before", "f"]);
+        assert!(krate.module.items[1].attrs.is_empty());
+
+        let artifacts = sess.expansion_artifacts.borrow();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].kind, sym::macro_dry_run);
+        assert_eq!(sess.source_map().span_to_snippet(artifacts[0].span).unwrap(), "#[generate]");
+        assert!(artifacts[0].payload.contains(r#""items":["f","generated"]"#),
+                "{}", artifacts[0].payload);
+        assert!(artifacts[0].payload.contains(r#""errors":0"#), "{}", artifacts[0].payload);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn skipped_macros_are_left_unexpanded() {
+    use crate::print::pprust;
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => { fn f() { n!(); } } }\n\
+                      macro_rules! n { () => { fn g() {} } }\n\
+                      m!();\n\
+                      n!();\n";
+        let mut config = ExpansionConfig::default("test".into());
+        config.skip.insert(Symbol::intern("n"));
+        let krate = expand_source(&sess, source, Vec::new(), config);
+
+        let items: Vec<_> = krate.module.items[2..].iter().map(|item| match item.node {
+            ast::ItemKind::Fn(_, _, _, ref body) => {
+                let stmts: Vec<_> = body.stmts.iter().map(pprust::stmt_to_string).collect();
+                format!("fn {}: {}", item.ident, stmts.join(" "))
+            }
+            ast::ItemKind::Mac(ref mac) => format!("{}!", pprust::path_to_string(&mac.path)),
+            _ => panic!("unexpected item {}", pprust::item_to_string(item)),
+        }).collect();
+        assert_eq!(items, ["fn f: n!();", "n!"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn macro_trace_records_each_invocation() {
+    use crate::ext::base::MacroKind;
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { ($($t:tt)*) => { n!(); } }\n\
+                      macro_rules! n { () => { fn f() {} } }\n\
+                      m!(a b);\n";
+        let config = ExpansionConfig {
+            trace_json: true,
+            ..ExpansionConfig::default("test".into())
+        };
+        let (_, trace) = expand_source_with_trace(&sess, source, Vec::new(), config);
+
+        let events = &trace.events;
+        let paths: Vec<_> = events.iter().map(|event| &event.macro_path[..]).collect();
+        assert_eq!(paths, ["m", "n"]);
+        assert_eq!(events[0].kind, MacroKind::Bang);
+        assert_eq!(events[0].input, "a b");
+        assert_eq!(events[0].call_site, "test:3:1: 3:8");
+        assert_eq!(events[1].fragment_kind, "item");
+        assert!(trace.to_json().starts_with("[{\"macro_path\":\"m\",\"kind\":\"Bang\""));
+    })
+}
+
+#[test]
+fn macro_trace_links_macros_to_the_macros_they_invoke() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => { n!(); n!(); } }\n\
+                      macro_rules! n { () => { fn f() {} } }\n\
+                      m!();\n";
+        let config = ExpansionConfig {
+            trace_json: true,
+            ..ExpansionConfig::default("test".into())
+        };
+        let (_, trace) = expand_source_with_trace(&sess, source, Vec::new(), config);
+
+        let events = &trace.events;
+        let paths: Vec<_> = events.iter().map(|event| &event.macro_path[..]).collect();
+        assert_eq!(paths, ["m", "n", "n"]);
+        assert_eq!(events[0].parent, None);
+        for event in &events[1..] {
+            assert_eq!(event.parent, Some(MacroTraceParent { id: events[0].id, eager: false }));
+        }
+        assert!(trace.to_dot().contains(&format!("n{} -> n{} [style=solid];",
+                                                 events[0].id, events[1].id)));
+    })
+}
+
+#[test]
+fn expanders_stream_items() {
+    use crate::ast::{VariantData, DUMMY_NODE_ID};
+    use crate::ext::base::{MacResult, SyntaxExtensionKind};
+    use crate::ptr::P;
+    use crate::tokenstream::TokenStream;
+    use syntax_pos::Span;
+
+    /// Creates the unit structs `S0` to `S{count - 1}`, only when they are asked for.
+    struct Structs<'cx, 'a> {
+        cx: &'cx mut ExtCtxt<'a>,
+        span: Span,
+        count: usize,
+    }
+
+    impl MacResult for Structs<'_, '_> {
+        fn streams_items(&self) -> bool {
+            true
+        }
+
+        fn for_each_item(mut self: Box<Self>, f: &mut dyn FnMut(&mut ExtCtxt<'_>, P<ast::Item>)) {
+            for i in 0..self.count {
+                let name = ast::Ident::from_str(&format!("S{}", i));
+                let item = self.cx.item_struct(self.span, name, VariantData::Unit(DUMMY_NODE_ID));
+                let before = self.cx.resolver.next_node_id();
+                f(self.cx, item);
+                // The item has been collected, which numbers its nodes, before the next one is
+                // created.
+                let after = self.cx.resolver.next_node_id();
+                assert!(after.as_u32() > before.as_u32() + 1);
+            }
+        }
+    }
+
+    fn structs<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                    -> Box<dyn MacResult + 'cx> {
+        Box::new(Structs { cx, span, count: 1000 })
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "structs!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(structs)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("structs", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        assert_eq!(krate.module.items.len(), 1000);
+        assert_eq!(krate.module.items[999].ident.to_string(), "S999");
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn injected_crate_items_are_added_once() {
+    use crate::ext::base::{MacEager, MacResult, SyntaxExtensionKind};
+    use crate::tokenstream::TokenStream;
+    use smallvec::smallvec;
+    use syntax_pos::Span;
+
+    fn helper<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                   -> Box<dyn MacResult + 'cx> {
+        let unit = cx.ty(span, ast::TyKind::Tup(Vec::new()));
+        let item = cx.item_fn(span, ast::Ident::from_str("helper"), Vec::new(), unit,
+                              cx.block(span, Vec::new()));
+        cx.inject_crate_item(item);
+        MacEager::items(smallvec![])
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "helper!();\n\
+                      mod m { helper!(); }\n\
+                      helper!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(helper)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("helper", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        let names: Vec<_> = krate.module.items.iter().map(|item| item.ident.to_string()).collect();
+        assert_eq!(names, ["m", "helper"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn suppressed_warnings_apply_to_spans_of_the_nested_expansion() {
+    use crate::ast::{VariantData, DUMMY_NODE_ID};
+    use crate::ext::base::{MacEager, MacResult, SuppressedWarning, SyntaxExtensionKind};
+    use crate::tokenstream::TokenStream;
+    use smallvec::smallvec;
+    use syntax_pos::Span;
+
+    fn structs<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, _: TokenStream)
+                    -> Box<dyn MacResult + 'cx> {
+        let unit = || VariantData::Unit(DUMMY_NODE_ID);
+        let quiet = cx.with_suppressed_warnings(&[SuppressedWarning::DeadCode], |cx| {
+            let span = cx.with_def_site_ctxt(span);
+            cx.item_struct(span, ast::Ident::from_str("Quiet"), unit())
+        });
+        let span = cx.with_def_site_ctxt(span);
+        let loud = cx.item_struct(span, ast::Ident::from_str("Loud"), unit());
+        MacEager::items(smallvec![quiet, loud])
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "structs!();\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(structs)),
+                                           sess.edition);
+        let krate = expand_source(&sess, source, vec![("structs", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        let (quiet, loud) = (krate.module.items[0].span, krate.module.items[1].span);
+        assert!(sess.suppresses_warning("dead_code", quiet));
+        assert!(!sess.suppresses_warning("deprecated", quiet));
+        assert!(!sess.suppresses_warning("dead_code", loud));
+    })
+}
+
+#[test]
+fn expanders_ask_whether_macro_paths_resolve() {
+    use crate::ext::base::{MacEager, MacResult, PathNamespace, SyntaxExtensionKind};
+    use crate::parse::token::{self, Token};
+    use crate::tokenstream::{TokenStream, TokenTree};
+    use smallvec::SmallVec;
+    use syntax_pos::Span;
+
+    fn probe<'cx>(cx: &'cx mut ExtCtxt<'_>, span: Span, tts: TokenStream)
+                  -> Box<dyn MacResult + 'cx> {
+        if let Some(TokenTree::Token(Token { kind: token::Ident(name, _), span })) =
+                tts.trees().next() {
+            let path = ast::Path::from_ident(ast::Ident::new(name, span));
+            let found = match cx.path_resolves(&path, PathNamespace::Macro) {
+                Ok(found) => found.to_string(),
+                Err(Indeterminate) => "indeterminate".to_string(),
+            };
+            cx.emit_artifact(Symbol::intern("probe"), format!("{}: {}", name, found));
+        } else {
+            cx.span_err(span, "expected a macro name");
+        }
+        MacEager::items(SmallVec::new())
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => {} }\n\
+                      probe!(m);\n\
+                      probe!(n);\n\
+                      probe!(probe);\n";
+        let ext = SyntaxExtension::default(SyntaxExtensionKind::LegacyBang(Box::new(probe)),
+                                           sess.edition);
+        expand_source(&sess, source, vec![("probe", ext)],
+                      ExpansionConfig::default("test".into()));
+
+        let artifacts = sess.expansion_artifacts.borrow();
+        let results: Vec<_> = artifacts.iter().map(|artifact| &artifact.payload[..]).collect();
+        assert_eq!(results, ["m: true", "n: false", "probe: true"]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn macro_rules_transcription_uses_the_transparency_of_the_extension() {
+    use crate::ext::hygiene::Transparency;
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let def = "macro_rules! m { () => { fn f() {} } }".to_string();
+        let def = parse::parse_crate_from_source_str(PathBuf::from("def").into(), def, &sess)
+            .unwrap().module.items.remove(0);
+        let source = "opaque!();";
+        let ext = macro_rules::compile(&sess, &Features::new(), &def, sess.edition);
+        let ext = SyntaxExtension { transparency: Transparency::Opaque, ..ext };
+        let krate = expand_source(&sess, source, vec![("opaque", ext)],
+                                  ExpansionConfig::default("test".into()));
+
+        let f = &krate.module.items[0];
+        assert_eq!(f.ident.to_string(), "f");
+        assert_eq!(f.ident.span.ctxt().outer_mark_with_data().1, Transparency::Opaque);
+    })
+}
+
+#[test]
+fn failed_expansions_suppress_follow_on_errors() {
+    use syntax_pos::{BytePos, Span};
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { () => { 1 } }\n\
+                      fn f() -> u8 { m!(x) }\n".to_string();
+        let call_lo = source.find("m!(x)").unwrap() as u32;
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let krate = expand_local_macros(krate, &sess, ExpansionConfig::default("test".into()));
+        assert_eq!(sess.span_diagnostic.err_count(), 1);
+
+        let body = match &krate.module.items[1].node {
+            ast::ItemKind::Fn(_, _, _, body) => body,
+            _ => panic!("expected a function"),
+        };
+        let fragment = AstFragment::Stmts(body.stmts.iter().cloned().collect());
+        assert!(fragment.has_errors());
+        let inner = Span::with_root_ctxt(BytePos(call_lo + 3), BytePos(call_lo + 4));
+        assert!(sess.is_in_error_node(inner));
+        assert!(!sess.is_in_error_node(Span::with_root_ctxt(BytePos(0), BytePos(1))));
+    })
+}
+
+#[test]
+fn reported_traces_record_the_arms_tried() {
+    use crate::ext::base::{ArmOutcome, MetaVarBinding, TraceKind};
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m {\n\
+                          (a $x:ident) => {};\n\
+                          ($($y:tt),*) => { fn f() {} };\n\
+                      }\n\
+                      m!(1, 2);\n".to_string();
+        let krate =
+            parse::parse_crate_from_source_str(PathBuf::from("test").into(), source, &sess)
+                .unwrap();
+        let features = Features::new();
+        let config = ExpansionConfig {
+            features: Some(&features),
+            trace_mac: true,
+            ..ExpansionConfig::default("test".into())
+        };
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let mut cx = ExtCtxt::new(&sess, config, &mut resolver);
+        cx.monotonic_expander().expand_crate(krate);
+
+        let arms = cx.reported_traces().iter().find_map(|entry| match &entry.kind {
+            TraceKind::Arms(arms) => Some(arms),
+            _ => None,
+        }).expect("the arms of `m!` should be traced");
+        assert_eq!(arms.len(), 2);
+        match &arms[0].outcome {
+            ArmOutcome::Failed { reason, .. } => {
+                assert_eq!(reason, "no rules expected the token `1`");
+            }
+            ArmOutcome::Matched(_) => panic!("the first arm shouldn't match"),
+        }
+        let bindings = match &arms[1].outcome {
+            ArmOutcome::Matched(bindings) => bindings,
+            ArmOutcome::Failed { reason, .. } => panic!("the second arm failed: {}", reason),
+        };
+        let bindings: Vec<_> = bindings.iter().map(|(name, binding)| {
+            let tokens = match binding {
+                MetaVarBinding::Seq(bindings) => bindings.iter().map(|binding| match binding {
+                    MetaVarBinding::Tokens(tokens) => tokens.to_string(),
+                    MetaVarBinding::Seq(_) => panic!("`$y` isn't nested"),
+                }).collect::<Vec<_>>(),
+                MetaVarBinding::Tokens(_) => panic!("`$y` is a repetition"),
+            };
+            (name.to_string(), tokens)
+        }).collect();
+        assert_eq!(bindings, [("y".to_string(), vec!["1".to_string(), "2".to_string()])]);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
@@ -1,4 +1,5 @@
 use crate::ast::{self, Ident, Generics, Expr, BlockCheckMode, UnOp, PatKind};
+use crate::ast_util::{self, ParamKind};
 use crate::attr;
 use crate::source_map::{dummy_spanned, respan, Spanned};
 use crate::ext::base::ExtCtxt;
@@ -9,6 +10,11 @@ use crate::ThinVec;
 use rustc_target::spec::abi::Abi;
 use syntax_pos::{Pos, Span};
 
+use std::iter;
+
+#[cfg(test)]
+mod tests;
+
 // Left so that Cargo tests don't break, this can be removed once those no longer use it
 pub trait AstBuilder {}
 
@@ -867,6 +873,105 @@ impl<'a> ExtCtxt<'a> {
         self.item_ty_poly(span, name, ty, Generics::default())
     }
 
+    /// Wraps `items` in an anonymous constant, `const _: () = { items };`, so that the names
+    /// they define don't pollute the surrounding module, while impls among them still apply.
+    /// The items can refer to the names of the surrounding module as usual. Underscore
+    /// constants are accepted on every edition, so there's no need for a uniquely named
+    /// constant instead.
+    pub fn anon_const_scope(&self, span: Span, items: Vec<P<ast::Item>>) -> P<ast::Item> {
+        let stmts = items.into_iter().map(|item| self.stmt_item(span, item)).collect();
+        let ty = self.ty(span, ast::TyKind::Tup(Vec::new()));
+        let body = self.expr_block(self.block(span, stmts));
+        self.item_const(span, Ident::new(kw::Underscore, span), ty, body)
+    }
+
+    /// Builds the header of an impl of `trait_path` for the struct, enum or union `item`, with
+    /// no impl items yet. The impl has the generic parameters of the item, without their
+    /// bounds and defaults. Every bound goes in the `where` clause: the bounds of the item,
+    /// followed by `trait_path` and `extra_bounds` for each type parameter, leaving out those
+    /// the parameter already has.
+    pub fn impl_with_bounds(&self,
+                            item: &ast::Item,
+                            trait_path: ast::Path,
+                            extra_bounds: Vec<ast::GenericBound>)
+                            -> P<ast::Item> {
+        let span = item.span;
+        let in_scope = ast_util::collect_generics_in_scope(item);
+
+        let mut params = Vec::new();
+        let mut self_args = Vec::new();
+        let mut predicates = Vec::new();
+        for param in in_scope.params {
+            let mut bounds = param.bounds;
+            let kind = match param.kind {
+                ParamKind::Lifetime => {
+                    self_args.push(ast::GenericArg::Lifetime(self.lifetime(span, param.ident)));
+                    ast::GenericParamKind::Lifetime
+                }
+                ParamKind::Type => {
+                    let trait_bound = self.trait_bound(trait_path.clone());
+                    for bound in iter::once(trait_bound).chain(extra_bounds.iter().cloned()) {
+                        if !bounds.iter().any(|existing| {
+                            ast_util::bounds_eq_unspanned(existing, &bound)
+                        }) {
+                            bounds.push(bound);
+                        }
+                    }
+                    self_args.push(ast::GenericArg::Type(self.ty_ident(span, param.ident)));
+                    ast::GenericParamKind::Type { default: None }
+                }
+                ParamKind::Const => {
+                    self_args.push(ast::GenericArg::Const(self.const_ident(span, param.ident)));
+                    let ty = param.const_ty.expect("const parameter without a type");
+                    ast::GenericParamKind::Const { ty }
+                }
+            };
+
+            if !bounds.is_empty() {
+                predicates.push(match kind {
+                    ast::GenericParamKind::Lifetime => {
+                        ast::WherePredicate::RegionPredicate(ast::WhereRegionPredicate {
+                            span,
+                            lifetime: self.lifetime(span, param.ident),
+                            bounds,
+                        })
+                    }
+                    _ => ast::WherePredicate::BoundPredicate(ast::WhereBoundPredicate {
+                        span,
+                        bound_generic_params: Vec::new(),
+                        bounded_ty: self.ty_ident(span, param.ident),
+                        bounds,
+                    }),
+                });
+            }
+            params.push(ast::GenericParam {
+                id: ast::DUMMY_NODE_ID,
+                ident: param.ident,
+                attrs: ThinVec::new(),
+                bounds: Vec::new(),
+                kind,
+            });
+        }
+        predicates.extend(in_scope.other_predicates);
+
+        let generics = Generics {
+            params,
+            where_clause: ast::WhereClause { predicates, span },
+            span,
+        };
+        let self_ty =
+            self.ty_path(self.path_all(span, false, vec![item.ident], self_args, Vec::new()));
+        self.item(span, Ident::invalid(), Vec::new(), ast::ItemKind::Impl(
+            ast::Unsafety::Normal,
+            ast::ImplPolarity::Positive,
+            ast::Defaultness::Final,
+            generics,
+            Some(self.trait_ref(trait_path)),
+            self_ty,
+            Vec::new(),
+        ))
+    }
+
     pub fn attribute(&self, mi: ast::MetaItem) -> ast::Attribute {
         attr::mk_attr_outer(mi)
     }
@@ -0,0 +1,60 @@
+use super::*;
+
+use crate::ext::basic_resolver::BasicResolver;
+use crate::ext::expand::ExpansionConfig;
+use crate::feature_gate::Features;
+use crate::parse::ParseSess;
+use crate::print::pprust;
+use crate::source_map::FilePathMapping;
+use crate::tests::with_error_checking_parse;
+use crate::with_default_globals;
+
+#[test]
+fn impl_with_bounds_moves_bounds_to_the_where_clause() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let item = with_error_checking_parse(
+            "struct S<'a, T: Clone, U: Default = u8, const N: usize> \
+             where U: Iterator<Item = &'a T>, Vec<T>: Send { t: [&'a T; N], u: U }".to_string(),
+            &sess,
+            |p| p.parse_item(),
+        ).unwrap();
+        // Spanned differently from the bounds of `S`, but the same bound as that of `U`.
+        let extra_bound = with_error_checking_parse("  Default".to_string(), &sess, |p| {
+            p.parse_generic_bounds(None)
+        });
+
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+        let trait_path = cx.path_ident(item.span, Ident::from_str("Clone"));
+        let impl_item = cx.impl_with_bounds(&item, trait_path, extra_bound);
+
+        let generics = match impl_item.node {
+            ast::ItemKind::Impl(_, _, _, ref generics, Some(ref trait_ref), ref self_ty, _) => {
+                assert_eq!(pprust::path_to_string(&trait_ref.path), "Clone");
+                assert_eq!(pprust::ty_to_string(self_ty), "S<'a, T, U, N>");
+                generics
+            }
+            _ => panic!("expected a trait impl, found {}", pprust::item_to_string(&impl_item)),
+        };
+        assert!(generics.params.iter().all(|param| param.bounds.is_empty()));
+        assert_eq!(pprust::generic_params_to_string(&generics.params),
+                   "<'a, T, U, const N : usize>");
+        let predicates: Vec<_> = generics.where_clause.predicates.iter().map(|predicate| {
+            match predicate {
+                ast::WherePredicate::BoundPredicate(predicate) => format!(
+                    "{}: {}",
+                    pprust::ty_to_string(&predicate.bounded_ty),
+                    pprust::bounds_to_string(&predicate.bounds),
+                ),
+                _ => panic!("unexpected predicate"),
+            }
+        }).collect();
+        assert_eq!(predicates, [
+            "T: Clone + Default",
+            "U: Default + Iterator<Item = &'a T> + Clone",
+            "Vec<T>: Send",
+        ]);
+    })
+}
@@ -0,0 +1,96 @@
+//! The diagnostics reported by macro expanders, behind a trait.
+//!
+//! Expanders that report their errors through `ExpansionDiagnostics` rather than through
+//! `ExtCtxt` directly can have their error paths unit tested with `RecordedDiagnostics`, without
+//! setting up a `ParseSess`.
+
+use crate::ext::base::ExtCtxt;
+
+use errors::{DiagnosticBuilder, Level};
+use syntax_pos::MultiSpan;
+
+#[cfg(test)]
+mod tests;
+
+/// A diagnostic reported by a macro expander.
+#[derive(Clone, Debug)]
+pub struct ExpansionDiagnostic {
+    pub level: Level,
+    pub span: MultiSpan,
+    pub message: String,
+    pub notes: Vec<String>,
+}
+
+/// Something macro expanders can report their diagnostics to.
+pub trait ExpansionDiagnostics {
+    fn emit_diagnostic(&mut self, diagnostic: ExpansionDiagnostic);
+
+    fn span_err(&mut self, span: MultiSpan, message: &str) {
+        self.span_err_with_notes(span, message, &[]);
+    }
+
+    fn span_err_with_notes(&mut self, span: MultiSpan, message: &str, notes: &[&str]) {
+        self.emit_diagnostic(ExpansionDiagnostic {
+            level: Level::Error,
+            span,
+            message: message.to_string(),
+            notes: notes.iter().map(|note| note.to_string()).collect(),
+        });
+    }
+
+    fn span_warn(&mut self, span: MultiSpan, message: &str) {
+        self.emit_diagnostic(ExpansionDiagnostic {
+            level: Level::Warning,
+            span,
+            message: message.to_string(),
+            notes: Vec::new(),
+        });
+    }
+}
+
+/// Emits the diagnostics through the session's handler at their own level. Errors and warnings
+/// in error nodes are suppressed like with the rest of `ExtCtxt`'s diagnostics methods.
+impl ExpansionDiagnostics for ExtCtxt<'_> {
+    fn emit_diagnostic(&mut self, diagnostic: ExpansionDiagnostic) {
+        let ExpansionDiagnostic { level, span, message, notes } = diagnostic;
+        let suppressible = match level {
+            Level::Error | Level::Warning => true,
+            _ => false,
+        };
+        if suppressible && self.is_in_error_node(&span) {
+            return;
+        }
+        let mut diag = DiagnosticBuilder::new(&self.parse_sess.span_diagnostic, level, &message);
+        diag.set_span(span);
+        for note in &notes {
+            diag.note(note);
+        }
+        diag.emit();
+    }
+}
+
+/// Records the diagnostics instead of emitting them, for tests.
+#[derive(Debug, Default)]
+pub struct RecordedDiagnostics {
+    pub diagnostics: Vec<ExpansionDiagnostic>,
+}
+
+impl RecordedDiagnostics {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| match diagnostic.level {
+            Level::Bug | Level::Fatal | Level::Error => true,
+            _ => false,
+        })
+    }
+
+    /// The messages of the recorded diagnostics, in the order they were reported.
+    pub fn messages(&self) -> Vec<&str> {
+        self.diagnostics.iter().map(|diagnostic| &diagnostic.message[..]).collect()
+    }
+}
+
+impl ExpansionDiagnostics for RecordedDiagnostics {
+    fn emit_diagnostic(&mut self, diagnostic: ExpansionDiagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
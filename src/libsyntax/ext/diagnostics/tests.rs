@@ -0,0 +1,48 @@
+use super::*;
+
+use crate::ext::basic_resolver::BasicResolver;
+use crate::ext::expand::ExpansionConfig;
+use crate::feature_gate::Features;
+use crate::parse::ParseSess;
+use crate::source_map::FilePathMapping;
+use crate::with_default_globals;
+
+use syntax_pos::DUMMY_SP;
+
+#[test]
+fn ext_ctxt_emits_diagnostics_at_their_level() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let mut cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+        let mut emit = |level| cx.emit_diagnostic(ExpansionDiagnostic {
+            level,
+            span: DUMMY_SP.into(),
+            message: format!("a {}", level),
+            notes: Vec::new(),
+        });
+
+        emit(Level::Warning);
+        emit(Level::Note);
+        emit(Level::Help);
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+        emit(Level::Error);
+        assert_eq!(sess.span_diagnostic.err_count(), 1);
+    })
+}
+
+#[test]
+fn recorded_diagnostics_count_errors_by_level() {
+    let diagnostic = |level| ExpansionDiagnostic {
+        level,
+        span: MultiSpan::new(),
+        message: String::new(),
+        notes: Vec::new(),
+    };
+    let mut diag = RecordedDiagnostics::default();
+    diag.emit_diagnostic(diagnostic(Level::Warning));
+    assert!(!diag.has_errors());
+    diag.emit_diagnostic(diagnostic(Level::Fatal));
+    assert!(diag.has_errors());
+}
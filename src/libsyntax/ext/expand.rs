@@ -1,36 +1,48 @@
 use crate::ast::{self, Block, Ident, LitKind, NodeId, PatKind, Path};
 use crate::ast::{MacStmtStyle, StmtKind, ItemKind};
+use crate::ast_util;
 use crate::attr::{self, HasAttrs};
 use crate::source_map::respan;
 use crate::config::StripUnconfigured;
+use crate::early_buffered_lints::BufferedEarlyLintId;
 use crate::ext::base::*;
-use crate::ext::proc_macro::{collect_derives, MarkAttrs};
-use crate::ext::hygiene::{ExpnId, SyntaxContext, ExpnData, ExpnKind};
+use crate::ext::proc_macro::{collect_derives, DeriveHelper, MarkAttrs};
+use crate::ext::hygiene::{ExpnId, SyntaxContext, ExpnData, ExpnKind, Transparency};
+use crate::ext::macro_trace;
+use crate::ext::pure_macro::{self, PureExpansion};
 use crate::ext::tt::macro_rules::annotate_err_with_kind;
-use crate::ext::placeholders::{placeholder, PlaceholderExpander};
+use crate::ext::validate::{check_dead_attrs, check_item_collisions, validate_output};
+use crate::ext::placeholders::{placeholder, unexpanded, PlaceholderExpander};
 use crate::feature_gate::{self, Features, GateIssue, is_builtin_attr, emit_feature_err};
 use crate::mut_visit::*;
-use crate::parse::{DirectoryOwnership, PResult, ParseSess};
+use crate::parse::{self, DirectoryOwnership, PResult, ParseSess};
 use crate::parse::token;
 use crate::parse::parser::Parser;
 use crate::ptr::P;
-use crate::symbol::{sym, Symbol};
-use crate::tokenstream::{TokenStream, TokenTree};
+use crate::symbol::{self, kw, sym, Symbol};
+use crate::tokenstream::{DelimSpan, TokenStream, TokenTree};
 use crate::visit::{self, Visitor};
 use crate::util::map_in_place::MapInPlace;
 
-use errors::{Applicability, FatalError};
+use errors::{Applicability, Diagnostic, DiagnosticBuilder, FatalError};
+use rustc_serialize::json;
 use smallvec::{smallvec, SmallVec};
-use syntax_pos::{Span, DUMMY_SP, FileName};
+use syntax_pos::{Span, DUMMY_SP, FileName, InnerSpan};
 
-use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::stable_hasher::StableHasher;
 use rustc_data_structures::sync::Lrc;
+use std::collections::VecDeque;
+use std::hash::Hash;
 use std::io::ErrorKind;
 use std::{iter, mem};
 use std::ops::DerefMut;
 use std::rc::Rc;
 use std::path::PathBuf;
 
+#[cfg(test)]
+mod tests;
+
 macro_rules! ast_fragments {
     (
         $($Kind:ident($AstTy:ty) {
@@ -143,6 +155,12 @@ ast_fragments! {
     ForeignItems(SmallVec<[ast::ForeignItem; 1]>) {
         "foreign item"; many fn flat_map_foreign_item; fn visit_foreign_item; fn make_foreign_items;
     }
+    Arms(SmallVec<[ast::Arm; 1]>) {
+        "match arm"; many fn flat_map_arm; fn visit_arm; fn make_arms;
+    }
+    Params(SmallVec<[ast::Param; 1]>) {
+        "parameter"; many fn flat_map_param; fn visit_param; fn make_params;
+    }
 }
 
 impl AstFragmentKind {
@@ -150,12 +168,21 @@ impl AstFragmentKind {
         self.make_from(DummyResult::any(span)).expect("couldn't create a dummy AST fragment")
     }
 
+    /// Like `dummy`, but standing in for a failed invocation in the context of `cx` that was
+    /// given `input`, see `DummyResult::any_in_context`.
+    fn dummy_in_context(self, cx: &ExtCtxt<'_>, span: Span, input: &TokenStream) -> AstFragment {
+        self.make_from(DummyResult::any_in_context(cx, span, input))
+            .expect("couldn't create a dummy AST fragment")
+    }
+
     fn expect_from_annotatables<I: IntoIterator<Item = Annotatable>>(self, items: I)
                                                                      -> AstFragment {
         let mut items = items.into_iter();
         match self {
-            AstFragmentKind::Items =>
-                AstFragment::Items(items.map(Annotatable::expect_item).collect()),
+            AstFragmentKind::Items => AstFragment::Items(items.map(|item| match item {
+                Annotatable::Crate(krate) => crate_root_item(krate),
+                item => item.expect_item(),
+            }).collect()),
             AstFragmentKind::ImplItems =>
                 AstFragment::ImplItems(items.map(Annotatable::expect_impl_item).collect()),
             AstFragmentKind::TraitItems =>
@@ -169,12 +196,21 @@ impl AstFragmentKind {
             ),
             AstFragmentKind::OptExpr =>
                 AstFragment::OptExpr(items.next().map(Annotatable::expect_expr)),
-            AstFragmentKind::Pat | AstFragmentKind::Ty =>
-                panic!("patterns and types aren't annotatable"),
+            AstFragmentKind::Pat | AstFragmentKind::Ty | AstFragmentKind::Arms |
+            AstFragmentKind::Params =>
+                panic!("patterns, types, match arms and parameters aren't annotatable"),
         }
     }
 }
 
+impl AstFragment {
+    /// Whether the fragment contains error nodes, e.g., because it's the dummy fragment a failed
+    /// macro call was replaced with. See `ast_util::error_node_spans`.
+    pub fn has_errors(&self) -> bool {
+        !ast_util::error_node_spans(self).is_empty()
+    }
+}
+
 pub struct Invocation {
     pub kind: InvocationKind,
     fragment_kind: AstFragmentKind,
@@ -193,6 +229,9 @@ pub enum InvocationKind {
         derives: Vec<Path>,
         // We temporarily report errors for attribute macros placed after derives
         after_derive: bool,
+        /// Whether `item` is disabled by a `#[cfg]` following `attr`, in which case it's only
+        /// expanded if the macro opts in with `SyntaxExtension::sees_unconfigured`.
+        unconfigured: bool,
     },
     Derive {
         path: Path,
@@ -207,7 +246,35 @@ pub enum InvocationKind {
     },
 }
 
+impl InvocationKind {
+    /// The tokens naming what the invocation could produce, see `DummyResult::any_in_context`.
+    fn input(&self) -> TokenStream {
+        match self {
+            InvocationKind::Bang { mac, .. } => mac.stream(),
+            InvocationKind::Attr { item, .. } => item_name(item),
+            InvocationKind::Derive { .. } | InvocationKind::DeriveContainer { .. } => {
+                TokenStream::empty()
+            }
+        }
+    }
+}
+
+/// The name of `item` as a token, if it's an impl item, see `DummyResult::any_in_context`.
+fn item_name(item: &Annotatable) -> TokenStream {
+    match item {
+        Annotatable::ImplItem(item) => {
+            let token = token::Ident(item.ident.name, item.ident.is_raw_guess());
+            TokenTree::token(token, item.ident.span).into()
+        }
+        _ => TokenStream::empty(),
+    }
+}
+
 impl Invocation {
+    pub fn fragment_kind(&self) -> AstFragmentKind {
+        self.fragment_kind
+    }
+
     pub fn span(&self) -> Span {
         match &self.kind {
             InvocationKind::Bang { span, .. } => *span,
@@ -216,19 +283,44 @@ impl Invocation {
             InvocationKind::DeriveContainer { item, .. } => item.span(),
         }
     }
+
+    /// Whether the item of an attribute invocation is disabled by a `#[cfg]` following the
+    /// attribute. Resolvers don't report anything about such invocations unless the macro
+    /// opts into seeing the item, since the item is removed otherwise.
+    pub fn is_unconfigured(&self) -> bool {
+        match self.kind {
+            InvocationKind::Attr { unconfigured, .. } => unconfigured,
+            _ => false,
+        }
+    }
+}
+
+/// Hooks into the expansion of individual macro invocations, registered with
+/// `ExpansionConfig::observers`.
+pub trait ExpansionObserver {
+    /// Called when the macro of `invoc` has been resolved, right before it's expanded.
+    fn before(&mut self, _invoc: &Invocation) {}
+
+    /// Called with the output of the expansion `invoc_id`, before the macro invocations in it
+    /// are collected for expansion. For derive containers, the output is the item with its
+    /// `derive` attributes removed, the derives themselves are expanded separately.
+    fn after(&mut self, _invoc_id: ExpnId, _fragment: &AstFragment) {}
 }
 
 pub struct MacroExpander<'a, 'b> {
     pub cx: &'a mut ExtCtxt<'b>,
     monotonic: bool, // cf. `cx.monotonic_expander()`
+    /// The invocations collected while `expand_invoc` streamed the items of an expansion, see
+    /// `MacResult::for_each_item`.
+    streamed_invocations: Option<Vec<Invocation>>,
 }
 
 impl<'a, 'b> MacroExpander<'a, 'b> {
     pub fn new(cx: &'a mut ExtCtxt<'b>, monotonic: bool) -> Self {
-        MacroExpander { cx, monotonic }
+        MacroExpander { cx, monotonic, streamed_invocations: None }
     }
 
-    pub fn expand_crate(&mut self, mut krate: ast::Crate) -> ast::Crate {
+    pub fn expand_crate(&mut self, krate: ast::Crate) -> ast::Crate {
         let mut module = ModuleData {
             mod_path: vec![Ident::from_str(&self.cx.ecfg.crate_name)],
             directory: match self.cx.source_map().span_to_unmapped_path(krate.span) {
@@ -241,39 +333,103 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         self.cx.current_expansion.module = Rc::new(module);
 
         let orig_mod_span = krate.module.inner;
+        let krate_span = krate.span;
+
+        let krate_item = AstFragment::Items(smallvec![crate_root_item(krate)]);
 
-        let krate_item = AstFragment::Items(smallvec![P(ast::Item {
-            attrs: krate.attrs,
-            span: krate.span,
-            node: ast::ItemKind::Mod(krate.module),
-            ident: Ident::invalid(),
-            id: ast::DUMMY_NODE_ID,
-            vis: respan(krate.span.shrink_to_lo(), ast::VisibilityKind::Public),
-            tokens: None,
-        })]);
-
-        match self.fully_expand_fragment(krate_item).make_items().pop().map(P::into_inner) {
+        // Attribute macros applied to the crate root may produce items next to the crate.
+        let strict_ident_eq = self.cx.ecfg.strict_ident_eq;
+        let (expanded, ident_eqs) = symbol::with_strict_ident_eq(strict_ident_eq, || {
+            self.fully_expand_fragment(krate_item)
+        });
+        for (ident, other) in ident_eqs {
+            let msg = format!("identifiers `{}` compared with `==` differ only in hygiene", ident);
+            self.cx.struct_span_warn(ident.span, &msg)
+                .span_note(other.span, "compared to this identifier")
+                .help("use `Ident::eq_hygienic` or `Ident::eq_unhygienic` to state whether \
+                       hygiene matters")
+                .emit();
+        }
+        let (roots, extra_items): (Vec<_>, Vec<_>) = expanded
+            .make_items().into_iter().partition(|item| is_crate_root_item(item));
+        let mut krate = match roots.into_iter().next().map(P::into_inner) {
             Some(ast::Item { attrs, node: ast::ItemKind::Mod(module), .. }) => {
-                krate.attrs = attrs;
-                krate.module = module;
+                ast::Crate { attrs, module, span: krate_span }
             },
             None => {
                 // Resolution failed so we return an empty expansion
-                krate.attrs = vec![];
-                krate.module = ast::Mod {
-                    inner: orig_mod_span,
-                    items: vec![],
-                    inline: true,
-                };
+                ast::Crate {
+                    attrs: vec![],
+                    module: ast::Mod {
+                        inner: orig_mod_span,
+                        items: vec![],
+                        inline: true,
+                    },
+                    span: krate_span,
+                }
             },
             _ => unreachable!(),
         };
+        krate.module.items.extend(extra_items);
+        self.inject_crate_items(&mut krate);
+        self.inject_requested_imports(&mut krate);
+        if self.cx.ecfg.check_item_collisions {
+            check_item_collisions(self.cx, &krate);
+        }
+        if self.cx.ecfg.check_dead_attrs {
+            check_dead_attrs(self.cx, &krate);
+        }
         self.cx.trace_macros_diag();
         krate
     }
 
+    /// Adds the items requested with `ExtCtxt::inject_crate_item` to the crate root, expanding
+    /// them in its scope. The requests are kept, so that the items requested while expanding
+    /// the injected ones are still merged with them.
+    fn inject_crate_items(&mut self, krate: &mut ast::Crate) {
+        if !self.monotonic {
+            return;
+        }
+        let mut injected = 0;
+        while injected < self.cx.injected_crate_items.len() {
+            let items = self.cx.injected_crate_items[injected..].iter().cloned().collect();
+            injected = self.cx.injected_crate_items.len();
+            let orig_expansion_data = self.cx.current_expansion.clone();
+            self.cx.current_expansion.id = self.cx.resolver.get_module_scope(ast::CRATE_NODE_ID);
+            let items = self.fully_expand_fragment(AstFragment::Items(items)).make_items();
+            self.cx.current_expansion = orig_expansion_data;
+            krate.module.items.extend(items);
+        }
+    }
+
+    /// Injects the imports requested with `ExtCtxt::ensure_import` into their modules.
+    fn inject_requested_imports(&mut self, krate: &mut ast::Crate) {
+        if !self.monotonic || self.cx.requested_imports.is_empty() {
+            return;
+        }
+        let mod_path = vec![Symbol::intern(&self.cx.ecfg.crate_name)];
+        let mut injector = ImportInjector { expander: self, mod_path };
+        injector.visit_mod(&mut krate.module);
+        injector.inject(ast::CRATE_NODE_ID, &mut krate.module);
+    }
+
     // Recursively expand all macro invocations in this AST fragment.
     pub fn fully_expand_fragment(&mut self, input_fragment: AstFragment) -> AstFragment {
+        match self.expand_fragment(input_fragment, true) {
+            Ok(fragment) => fragment,
+            Err(Indeterminate) => unreachable!("forced expansion cannot be indeterminate"),
+        }
+    }
+
+    /// Like `fully_expand_fragment`, but gives up if none of the invocations in the fragment can
+    /// be resolved yet, before expanding anything, see `ExtCtxt::eager_expand`.
+    pub fn try_fully_expand_fragment(&mut self, input_fragment: AstFragment)
+                                     -> Result<AstFragment, Indeterminate> {
+        self.expand_fragment(input_fragment, false)
+    }
+
+    fn expand_fragment(&mut self, input_fragment: AstFragment, force_when_stuck: bool)
+                       -> Result<AstFragment, Indeterminate> {
         let orig_expansion_data = self.cx.current_expansion.clone();
         self.cx.current_expansion.depth = 0;
 
@@ -293,39 +449,132 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         let mut expanded_fragments = Vec::new();
         let mut all_derive_placeholders: FxHashMap<ExpnId, Vec<_>> = FxHashMap::default();
         let mut undetermined_invocations = Vec::new();
-        let (mut progress, mut force) = (false, !self.monotonic);
+        let mut unexpanded = FxHashSet::default();
+        let (mut progress, mut force) = (false, !self.monotonic && force_when_stuck);
+        // Once an invocation is expanded, the expansion is kept, so that its diagnostics and the
+        // expansion data recorded by the resolver aren't left behind by discarded output.
+        let mut expanded_any = false;
+        // Pure bang invocations don't depend on the invocations expanded before them, so they
+        // are expanded after the other invocations queued with them, see `pure_macro`.
+        let mut pure_invocations = VecDeque::new();
         loop {
-            let invoc = if let Some(invoc) = invocations.pop() {
-                invoc
+            let (invoc, res) = if let Some(invoc) = invocations.pop() {
+                let is_selected = match &invoc.kind {
+                    InvocationKind::Bang { mac, .. } => self.cx.ecfg.should_expand(&mac.path),
+                    _ => true,
+                };
+                if !is_selected {
+                    progress = true;
+                    let ExpansionData { depth, id: expn_id, .. } = invoc.expansion_data;
+                    self.cx.current_expansion = invoc.expansion_data.clone();
+                    let fragment = self.keep_unexpanded(invoc);
+                    if expanded_fragments.len() < depth {
+                        expanded_fragments.push(Vec::new());
+                    }
+                    expanded_fragments[depth - 1].push((expn_id, fragment));
+                    unexpanded.insert(expn_id);
+                    continue
+                }
+
+                let eager_expansion_root =
+                    if self.monotonic { invoc.expansion_data.id } else { orig_expansion_data.id };
+                let res = symbol::without_strict_ident_eq(|| {
+                    self.cx.resolver.resolve_macro_invocation(&invoc, eager_expansion_root, force)
+                });
+                let res = match res {
+                    Ok(res) => res,
+                    Err(Indeterminate) => {
+                        undetermined_invocations.push(invoc);
+                        continue
+                    }
+                };
+                let is_pure_bang = match (&invoc.kind, &res) {
+                    (InvocationKind::Bang { .. }, InvocationRes::Single(ext)) => ext.is_pure,
+                    _ => false,
+                };
+                if is_pure_bang {
+                    pure_invocations.push_back((invoc, res));
+                    continue
+                }
+                (invoc, res)
+            } else if let Some(pure_invocation) = pure_invocations.pop_front() {
+                pure_invocation
             } else {
                 self.resolve_imports();
                 if undetermined_invocations.is_empty() { break }
+                if !expanded_any && !force_when_stuck {
+                    self.cx.current_expansion = orig_expansion_data;
+                    return Err(Indeterminate);
+                }
                 invocations = mem::take(&mut undetermined_invocations);
                 force = !mem::replace(&mut progress, false);
                 continue
             };
 
-            let eager_expansion_root =
-                if self.monotonic { invoc.expansion_data.id } else { orig_expansion_data.id };
-            let res = match self.cx.resolver.resolve_macro_invocation(
-                &invoc, eager_expansion_root, force
-            ) {
-                Ok(res) => res,
-                Err(Indeterminate) => {
-                    undetermined_invocations.push(invoc);
-                    continue
-                }
-            };
-
             progress = true;
+            expanded_any = true;
             let ExpansionData { depth, id: expn_id, .. } = invoc.expansion_data;
             self.cx.current_expansion = invoc.expansion_data.clone();
+            let eager_root = if self.monotonic { None } else { Some(orig_expansion_data.id) };
+            macro_trace::start(self.cx, &invoc, eager_root);
+            for observer in &mut self.cx.ecfg.observers {
+                observer.before(&invoc);
+            }
 
             // FIXME(jseyfried): Refactor out the following logic
             let (expanded_fragment, new_invocations) = match res {
                 InvocationRes::Single(ext) => {
-                    let fragment = self.expand_invoc(invoc, &ext.kind);
-                    self.collect_invocations(fragment, &[])
+                    // Macros expanded eagerly by a pure macro are checked as part of it.
+                    let outer_pure = self.cx.pure_expansion.clone();
+                    if outer_pure.is_none() && ext.is_pure {
+                        let pure = PureExpansion::new(expn_id, &ext);
+                        self.cx.pure_expansion = Some(Lrc::new(pure));
+                    }
+                    let mut fragment = if !self.check_min_edition(&invoc, &ext) {
+                        let input = invoc.kind.input();
+                        invoc.fragment_kind.dummy_in_context(self.cx, invoc.span(), &input)
+                    } else if invoc.is_unconfigured() && !ext.sees_unconfigured {
+                        // The macro never runs, so its attribute isn't consumed.
+                        match (&invoc.kind, &ext.kind) {
+                            (_, SyntaxExtensionKind::NonMacroAttr { .. }) => {}
+                            (InvocationKind::Attr { attr, .. }, _)
+                                if self.cx.ecfg.check_dead_attrs => {
+                                self.cx.attr_consumption.unconfigured.push(attr.clone());
+                            }
+                            _ => {}
+                        }
+                        invoc.fragment_kind.expect_from_annotatables(iter::empty())
+                    } else if self.cx.ecfg.attr_dry_run && is_dry_runnable(&ext) {
+                        self.dry_run_invoc(invoc, &ext)
+                    } else {
+                        let kept_attr = match invoc.kind {
+                            InvocationKind::Attr { ref attr, .. } if ext.keep_attr => {
+                                Some(attr.clone())
+                            }
+                            _ => None,
+                        };
+                        let mut fragment = self.expand_invoc(invoc, &ext);
+                        if let Some(attr) = kept_attr {
+                            keep_macro_attr(&mut fragment, attr);
+                        }
+                        fragment
+                    };
+                    self.cx.pure_expansion = outer_pure;
+                    if allows_span_source(&ext) {
+                        let mut collector = SpanSourceCollector { cx: self.cx, parent: expn_id };
+                        fragment.mut_visit_with(&mut collector);
+                    }
+                    if self.cx.ecfg.validate_output {
+                        validate_output(self.cx, expn_id, &fragment);
+                    }
+                    self.notify_observers(expn_id, &fragment);
+                    match self.streamed_invocations.take() {
+                        Some(invocations) => {
+                            self.visit_collected_fragment(&fragment, &[]);
+                            (fragment, invocations)
+                        }
+                        None => self.collect_invocations(fragment, &[]),
+                    }
                 }
                 InvocationRes::DeriveContainer(exts) => {
                     let (derives, item) = match invoc.kind {
@@ -352,18 +601,49 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
                     }
 
                     let mut item = self.fully_configure(item);
-                    item.visit_attrs(|attrs| attrs.retain(|a| a.path != sym::derive));
-                    let mut helper_attrs = Vec::new();
+                    let cx = &mut *self.cx;
+                    item.visit_attrs(|attrs| {
+                        // Derives that aren't selected for expansion are kept in the output.
+                        let skipped: Vec<_> = if cx.ecfg.expands_selectively() {
+                            collect_derives(cx, attrs).into_iter()
+                                .filter(|path| !cx.ecfg.should_expand(path))
+                                .collect()
+                        } else {
+                            Vec::new()
+                        };
+                        attrs.retain(|a| a.path != sym::derive);
+                        if !skipped.is_empty() {
+                            attrs.push(derive_attr(skipped));
+                        }
+                    });
+                    let mut helpers = Vec::new();
                     let mut has_copy = false;
-                    for ext in exts {
-                        helper_attrs.extend(&ext.helper_attrs);
+                    for (ext, derive) in exts.iter().zip(&derives) {
+                        helpers.extend(ext.helper_attrs.iter().map(|&name| DeriveHelper {
+                            name,
+                            scope: ext.helper_attr_scopes.get(&name).cloned(),
+                            derive: derive.clone(),
+                        }));
                         has_copy |= ext.is_derive_copy;
                     }
                     // Mark derive helpers inside this item as known and used.
                     // FIXME: This is a hack, derive helpers should be integrated with regular name
                     // resolution instead. For example, helpers introduced by a derive container
                     // can be in scope for all code produced by that container's expansion.
-                    item.visit_with(&mut MarkAttrs(&helper_attrs));
+                    // Helpers used outside of the scope declared by their derive are left
+                    // unknown, so they're reported like any other unknown attribute.
+                    let mut mark_attrs = MarkAttrs::new(&helpers);
+                    item.visit_with(&mut mark_attrs);
+                    if self.cx.ecfg.check_dead_attrs {
+                        self.cx.attr_consumption.consumed.extend(mark_attrs.consumed);
+                    }
+                    for (span, notes) in mark_attrs.misplaced {
+                        self.cx.buffer_lint_with_notes(
+                            BufferedEarlyLintId::MisplacedDeriveHelpers, span,
+                            "derive helper attribute used outside of the scope of its derive",
+                            notes,
+                        );
+                    }
                     if has_copy {
                         self.cx.resolver.add_derives(invoc.expansion_data.id, SpecialDerives::COPY);
                     }
@@ -373,7 +653,8 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
                     derive_placeholders.reserve(derives.len());
                     invocations.reserve(derives.len());
                     for path in derives {
-                        let expn_id = ExpnId::fresh(None);
+                        let name = || Symbol::intern(&path.to_string());
+                        let expn_id = fresh_expn_id(self.cx, expn_id, name, None);
                         derive_placeholders.push(NodeId::placeholder_from_expn_id(expn_id));
                         invocations.push(Invocation {
                             kind: InvocationKind::Derive { path, item: item.clone() },
@@ -386,6 +667,7 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
                     }
                     let fragment = invoc.fragment_kind
                         .expect_from_annotatables(::std::iter::once(item));
+                    self.notify_observers(expn_id, &fragment);
                     self.collect_invocations(fragment, derive_placeholders)
                 }
             };
@@ -405,19 +687,30 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         let mut placeholder_expander = PlaceholderExpander::new(self.cx, self.monotonic);
         while let Some(expanded_fragments) = expanded_fragments.pop() {
             for (expn_id, expanded_fragment) in expanded_fragments.into_iter().rev() {
+                let id = NodeId::placeholder_from_expn_id(expn_id);
+                if unexpanded.contains(&expn_id) {
+                    placeholder_expander.add_unexpanded(id, expanded_fragment);
+                    continue
+                }
                 let derive_placeholders =
                     all_derive_placeholders.remove(&expn_id).unwrap_or_else(Vec::new);
-                placeholder_expander.add(NodeId::placeholder_from_expn_id(expn_id),
-                                         expanded_fragment, derive_placeholders);
+                placeholder_expander.add(id, expanded_fragment, derive_placeholders);
             }
         }
         fragment_with_placeholders.mut_visit_with(&mut placeholder_expander);
-        fragment_with_placeholders
+        Ok(fragment_with_placeholders)
+    }
+
+    fn notify_observers(&mut self, expn_id: ExpnId, fragment: &AstFragment) {
+        macro_trace::finish(self.cx, expn_id);
+        for observer in &mut self.cx.ecfg.observers {
+            observer.after(expn_id, fragment);
+        }
     }
 
     fn resolve_imports(&mut self) {
         if self.monotonic {
-            self.cx.resolver.resolve_imports();
+            symbol::without_strict_ident_eq(|| self.cx.resolver.resolve_imports());
         }
     }
 
@@ -428,7 +721,7 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
     fn collect_invocations(&mut self, mut fragment: AstFragment, extra_placeholders: &[NodeId])
                            -> (AstFragment, Vec<Invocation>) {
         // Resolve `$crate`s in the fragment for pretty-printing.
-        self.cx.resolver.resolve_dollar_crates();
+        symbol::without_strict_ident_eq(|| self.cx.resolver.resolve_dollar_crates());
 
         let invocations = {
             let mut collector = InvocationCollector {
@@ -444,13 +737,27 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
             collector.invocations
         };
 
+        self.visit_collected_fragment(&fragment, extra_placeholders);
+        (fragment, invocations)
+    }
+
+    /// Passes `fragment`, whose invocations have been collected, on to the resolver.
+    fn visit_collected_fragment(&mut self, fragment: &AstFragment, extra_placeholders: &[NodeId]) {
+        // Error nodes are only produced after reporting errors, so there's nothing to look for
+        // otherwise.
+        if self.cx.parse_sess.span_diagnostic.has_errors() {
+            let error_node_spans = ast_util::error_node_spans(fragment);
+            self.cx.parse_sess.error_node_spans.borrow_mut().extend(error_node_spans);
+        }
+
         // FIXME: Merge `extra_placeholders` into the `fragment` as regular placeholders.
         if self.monotonic {
-            self.cx.resolver.visit_ast_fragment_with_placeholders(
-                self.cx.current_expansion.id, &fragment, extra_placeholders);
+            let expn_id = self.cx.current_expansion.id;
+            symbol::without_strict_ident_eq(|| {
+                self.cx.resolver.visit_ast_fragment_with_placeholders(
+                    expn_id, fragment, extra_placeholders);
+            });
         }
-
-        (fragment, invocations)
     }
 
     fn fully_configure(&mut self, item: Annotatable) -> Annotatable {
@@ -482,13 +789,63 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
             Annotatable::Expr(mut expr) => {
                 Annotatable::Expr({ cfg.visit_expr(&mut expr); expr })
             }
+            Annotatable::Crate(mut krate) => {
+                Annotatable::Crate({ cfg.visit_crate(&mut krate); krate })
+            }
+            Annotatable::Field(field) => {
+                Annotatable::Field(cfg.flat_map_struct_field(field).pop().unwrap())
+            }
+            Annotatable::Variant(variant) => {
+                Annotatable::Variant(cfg.flat_map_variant(variant).pop().unwrap())
+            }
+            Annotatable::Param(param) => {
+                Annotatable::Param(cfg.flat_map_param(param).pop().unwrap())
+            }
+            Annotatable::GenericParam(param) => {
+                Annotatable::GenericParam(cfg.flat_map_generic_param(param).pop().unwrap())
+            }
+        }
+    }
+
+    /// Reports an invocation of `ext` from code of an edition older than the extension's
+    /// minimum edition, returning whether the invocation can be expanded.
+    fn check_min_edition(&self, invoc: &Invocation, ext: &SyntaxExtension) -> bool {
+        let min_edition = match ext.min_edition {
+            Some(min_edition) => min_edition,
+            None => return true,
+        };
+        let span = invoc.span();
+        let edition = span.edition();
+        if edition >= min_edition {
+            return true;
+        }
+
+        let name = match &invoc.kind {
+            InvocationKind::Bang { mac, .. } => format!("{}!", mac.path),
+            InvocationKind::Attr { attr, .. } => format!("#[{}]", attr.path),
+            InvocationKind::Derive { path, .. } => format!("#[derive({})]", path),
+            InvocationKind::DeriveContainer { .. } => "derive".to_string(),
+        };
+        let mut err = self.cx.struct_span_err(
+            span,
+            &format!("`{}` can only be used in Rust {} or later", name, min_edition),
+        );
+        err.span_label(span, format!("this code is compiled as Rust {}", edition));
+        err.help(&format!(
+            "pass `--edition {}` to `rustc` or set `edition = \"{}\"` in `Cargo.toml`",
+            min_edition, min_edition,
+        ));
+        if let Some(alternative) = ext.min_edition_alternative {
+            err.help(&format!("`{}` can be used instead in Rust {}", alternative, edition));
         }
+        err.emit();
+        false
     }
 
-    fn expand_invoc(&mut self, invoc: Invocation, ext: &SyntaxExtensionKind) -> AstFragment {
+    fn expand_invoc(&mut self, invoc: Invocation, ext: &Lrc<SyntaxExtension>) -> AstFragment {
         let (fragment_kind, span) = (invoc.fragment_kind, invoc.span());
         if fragment_kind == AstFragmentKind::ForeignItems && !self.cx.ecfg.macros_in_extern() {
-            if let SyntaxExtensionKind::NonMacroAttr { .. } = ext {} else {
+            if let SyntaxExtensionKind::NonMacroAttr { .. } = ext.kind {} else {
                 emit_feature_err(&self.cx.parse_sess, sym::macros_in_extern,
                                  span, GateIssue::Language,
                                  "macro invocations in `extern {}` blocks are experimental");
@@ -510,47 +867,84 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         }
 
         match invoc.kind {
-            InvocationKind::Bang { mac, .. } => match ext {
+            InvocationKind::Bang { mac, .. } => match &ext.kind {
                 SyntaxExtensionKind::Bang(expander) => {
                     self.gate_proc_macro_expansion_kind(span, fragment_kind);
-                    let tok_result = expander.expand(self.cx, span, mac.stream());
+                    let input = mac.stream();
+                    let tok_result = match pure_macro::cached_output(self.cx, ext, &input) {
+                        Some(output) => output,
+                        None => {
+                            let output = expander.expand(self.cx, span, input.clone());
+                            pure_macro::cache_output(self.cx, ext, input, &output);
+                            output
+                        }
+                    };
                     let result =
                         self.parse_ast_fragment(tok_result, fragment_kind, &mac.path, span);
                     self.gate_proc_macro_expansion(span, &result);
+                    self.gate_param_macro_output(span, &result);
                     result
                 }
                 SyntaxExtensionKind::LegacyBang(expander) => {
                     let prev = self.cx.current_expansion.prior_type_ascription;
                     self.cx.current_expansion.prior_type_ascription = mac.prior_type_ascription;
+                    // Streamed items are collected right away, unless something needs to see
+                    // the whole output before that.
+                    let can_stream = fragment_kind == AstFragmentKind::Items &&
+                        !allows_span_source(ext) && !self.cx.ecfg.validate_output &&
+                        self.cx.ecfg.observers.is_empty();
+                    let monotonic = self.monotonic;
                     let tok_result = expander.expand(self.cx, span, mac.stream());
-                    let result = if let Some(result) = fragment_kind.make_from(tok_result) {
+                    let result = if can_stream && tok_result.streams_items() {
+                        let (items, invocations) = collect_streamed_items(tok_result, monotonic);
+                        self.streamed_invocations = Some(invocations);
+                        AstFragment::Items(items)
+                    } else if let Some(result) = fragment_kind.make_from(tok_result) {
                         result
                     } else {
                         let msg = format!("non-{kind} macro in {kind} position: {path}",
                                           kind = fragment_kind.name(), path = mac.path);
                         self.cx.span_err(span, &msg);
                         self.cx.trace_macros_diag();
-                        fragment_kind.dummy(span)
+                        fragment_kind.dummy_in_context(self.cx, span, &mac.stream())
                     };
                     self.cx.current_expansion.prior_type_ascription = prev;
+                    self.gate_param_macro_output(span, &result);
                     result
                 }
                 _ => unreachable!()
             }
-            InvocationKind::Attr { attr, mut item, .. } => match ext {
+            InvocationKind::Attr { attr, mut item, .. } => match &ext.kind {
                 SyntaxExtensionKind::Attr(expander) => {
                     self.gate_proc_macro_attr_item(span, &item);
-                    let item_tok = TokenTree::token(token::Interpolated(Lrc::new(match item {
-                        Annotatable::Item(item) => token::NtItem(item),
-                        Annotatable::TraitItem(item) => token::NtTraitItem(item.into_inner()),
-                        Annotatable::ImplItem(item) => token::NtImplItem(item.into_inner()),
-                        Annotatable::ForeignItem(item) => token::NtForeignItem(item.into_inner()),
-                        Annotatable::Stmt(stmt) => token::NtStmt(stmt.into_inner()),
-                        Annotatable::Expr(expr) => token::NtExpr(expr),
-                    })), DUMMY_SP).into();
-                    let input = self.extract_proc_macro_attr_input(attr.tokens, span);
+                    let is_crate = if let Annotatable::Crate(..) = item { true } else { false };
+                    let item_tok = match item {
+                        Annotatable::Crate(krate) => crate_to_tokens(krate),
+                        item => TokenTree::token(token::Interpolated(Lrc::new(match item {
+                            Annotatable::Item(item) => token::NtItem(item),
+                            Annotatable::TraitItem(item) => token::NtTraitItem(item.into_inner()),
+                            Annotatable::ImplItem(item) => token::NtImplItem(item.into_inner()),
+                            Annotatable::ForeignItem(item) => {
+                                token::NtForeignItem(item.into_inner())
+                            }
+                            Annotatable::Stmt(stmt) => token::NtStmt(stmt.into_inner()),
+                            Annotatable::Expr(expr) => token::NtExpr(expr),
+                            Annotatable::Crate(..) => unreachable!(),
+                            // Attribute macros aren't collected on these yet, see
+                            // `gate_proc_macro_attr_item`.
+                            Annotatable::Field(..) |
+                            Annotatable::Variant(..) |
+                            Annotatable::Param(..) |
+                            Annotatable::GenericParam(..) => unreachable!(),
+                        })), DUMMY_SP).into(),
+                    };
+                    let input = self.extract_proc_macro_attr_input(&attr.args, span);
                     let tok_result = expander.expand(self.cx, span, input, item_tok);
-                    let res = self.parse_ast_fragment(tok_result, fragment_kind, &attr.path, span);
+                    let res = if is_crate {
+                        self.parse_crate_fragment(tok_result, span)
+                    } else {
+                        self.parse_ast_fragment(tok_result, fragment_kind, &attr.path, span)
+                    };
                     self.gate_proc_macro_expansion(span, &res);
                     res
                 }
@@ -562,21 +956,27 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
                         }
                         Err(mut err) => {
                             err.emit();
-                            fragment_kind.dummy(span)
+                            fragment_kind.dummy_in_context(self.cx, span, &item_name(&item))
                         }
                     }
                 }
+                SyntaxExtensionKind::ExprAttr(expander) => {
+                    self.expand_expr_attr(&**expander, attr, item, fragment_kind, span)
+                }
                 SyntaxExtensionKind::NonMacroAttr { mark_used } => {
                     attr::mark_known(&attr);
                     if *mark_used {
                         attr::mark_used(&attr);
+                        if self.cx.ecfg.check_dead_attrs {
+                            self.cx.attr_consumption.consumed.insert(attr.id);
+                        }
                     }
                     item.visit_attrs(|attrs| attrs.push(attr));
                     fragment_kind.expect_from_annotatables(iter::once(item))
                 }
                 _ => unreachable!()
             }
-            InvocationKind::Derive { path, item } => match ext {
+            InvocationKind::Derive { path, item } => match &ext.kind {
                 SyntaxExtensionKind::Derive(expander) |
                 SyntaxExtensionKind::LegacyDerive(expander) => {
                     if !item.derive_allowed() {
@@ -592,16 +992,126 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         }
     }
 
-    fn extract_proc_macro_attr_input(&self, tokens: TokenStream, span: Span) -> TokenStream {
-        let mut trees = tokens.trees();
-        match trees.next() {
-            Some(TokenTree::Delimited(_, _, tts)) => {
-                if trees.next().is_none() {
-                    return tts.into()
+    /// Expands an invocation of a `SyntaxExtensionKind::ExprAttr` macro. Only expressions can be
+    /// annotated, including expression statements and macro invocation statements, whose
+    /// expression is replaced while the statement keeps its semicolon.
+    fn expand_expr_attr(
+        &mut self,
+        expander: &dyn AttrProcMacro,
+        attr: ast::Attribute,
+        item: Annotatable,
+        fragment_kind: AstFragmentKind,
+        span: Span,
+    ) -> AstFragment {
+        let (expr, stmt) = match item {
+            Annotatable::Expr(expr) => (expr, None),
+            Annotatable::Stmt(stmt) => {
+                let ast::Stmt { id, node, span: stmt_span } = stmt.into_inner();
+                match node {
+                    StmtKind::Expr(expr) => (expr, Some((id, stmt_span, false))),
+                    StmtKind::Semi(expr) => (expr, Some((id, stmt_span, true))),
+                    StmtKind::Mac(mac) => {
+                        let (mac, style, attrs) = mac.into_inner();
+                        let expr = P(ast::Expr {
+                            id: ast::DUMMY_NODE_ID,
+                            node: ast::ExprKind::Mac(mac),
+                            span: stmt_span,
+                            attrs,
+                        });
+                        (expr, Some((id, stmt_span, style == MacStmtStyle::Semicolon)))
+                    }
+                    node => {
+                        let stmt = Annotatable::Stmt(P(ast::Stmt { id, node, span: stmt_span }));
+                        return self.reject_expr_attr(&attr, stmt, fragment_kind, span);
+                    }
                 }
             }
-            Some(TokenTree::Token(..)) => {}
-            None => return TokenStream::empty(),
+            item => return self.reject_expr_attr(&attr, item, fragment_kind, span),
+        };
+
+        let input = self.extract_proc_macro_attr_input(&attr.args, span);
+        let expr_tok = TokenTree::token(token::Interpolated(Lrc::new(token::NtExpr(expr))),
+                                        DUMMY_SP);
+        let tok_result = expander.expand(self.cx, span, input, expr_tok.into());
+        let tok_result = with_call_site_if_dummy(tok_result, self.cx.with_call_site_ctxt(span));
+        match stmt {
+            None => self.parse_ast_fragment(tok_result, fragment_kind, &attr.path, span),
+            Some((id, stmt_span, semi)) => {
+                let expr = self.parse_ast_fragment(tok_result, AstFragmentKind::Expr,
+                                                   &attr.path, span).make_expr();
+                let node = if semi { StmtKind::Semi(expr) } else { StmtKind::Expr(expr) };
+                AstFragment::Stmts(smallvec![ast::Stmt { id, node, span: stmt_span }])
+            }
+        }
+    }
+
+    /// Reports an expression attribute macro applied to something else, which is kept without
+    /// the attribute.
+    fn reject_expr_attr(
+        &mut self,
+        attr: &ast::Attribute,
+        item: Annotatable,
+        fragment_kind: AstFragmentKind,
+        span: Span,
+    ) -> AstFragment {
+        let msg = format!("`#[{}]` can only be applied to expressions", attr.path);
+        self.cx.struct_span_err(span, &msg)
+            .span_label(item.span(), format!("this {} is not an expression", item.kind_descr()))
+            .emit();
+        fragment_kind.expect_from_annotatables(iter::once(item))
+    }
+
+    /// Produces the output for a bang macro invocation that isn't selected for expansion (see
+    /// `ExpansionConfig::expand_only`), which is the invocation itself.
+    fn keep_unexpanded(&mut self, invoc: Invocation) -> AstFragment {
+        // The resolver still expects the invocation to be expanded into something, so it gets
+        // an empty fragment, the invocation itself is never seen by it again.
+        self.collect_invocations(invoc.fragment_kind.dummy(invoc.span()), &[]);
+        match invoc.kind {
+            InvocationKind::Bang { mac, span } => unexpanded(invoc.fragment_kind, mac, span),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Expands an attribute or derive invocation without using its output, emitting a
+    /// `macro_dry_run` artifact with a `DryRunReport` of what it would have produced instead.
+    /// Attribute targets are kept as they were written, minus the macro attribute.
+    fn dry_run_invoc(&mut self, invoc: Invocation, ext: &Lrc<SyntaxExtension>) -> AstFragment {
+        let (path, item) = match invoc.kind {
+            InvocationKind::Attr { ref attr, ref item, .. } => {
+                (attr.path.clone(), Some(item.clone()))
+            }
+            InvocationKind::Derive { ref path, .. } => (path.clone(), None),
+            _ => unreachable!(),
+        };
+        let fragment_kind = invoc.fragment_kind;
+        let span = invoc.span();
+
+        let err_count = self.cx.parse_sess.span_diagnostic.err_count();
+        let fragment = self.expand_invoc(invoc, ext);
+        let report = DryRunReport {
+            macro_path: path.to_string(),
+            span: self.cx.source_map().span_to_string(span),
+            fragment_kind: fragment_kind.name().to_string(),
+            items: generated_item_names(&fragment),
+            errors: self.cx.parse_sess.span_diagnostic.err_count() - err_count,
+        };
+        // Recorded at the invocation itself, without going through the call site of whichever
+        // expansion is current.
+        self.cx.parse_sess.expansion_artifacts.borrow_mut().push(ExpansionArtifact {
+            kind: sym::macro_dry_run,
+            payload: json::as_json(&report).to_string(),
+            span,
+        });
+
+        fragment_kind.expect_from_annotatables(item)
+    }
+
+    fn extract_proc_macro_attr_input(&self, args: &ast::AttrArgs, span: Span) -> TokenStream {
+        match args {
+            ast::AttrArgs::Empty => return TokenStream::empty(),
+            ast::AttrArgs::Delimited(.., tokens) => return tokens.clone(),
+            ast::AttrArgs::Eq(..) => {}
         }
         self.cx.span_err(span, "custom attribute invocations must be \
             of the form `#[foo]` or `#[foo(..)]`, the macro name must only be \
@@ -625,6 +1135,15 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
             Annotatable::Expr(_) if self.cx.ecfg.proc_macro_hygiene() => return,
             Annotatable::Stmt(_) => ("statements", sym::proc_macro_hygiene),
             Annotatable::Expr(_) => ("expressions", sym::proc_macro_hygiene),
+            Annotatable::Crate(_) if self.cx.ecfg.proc_macro_hygiene() => return,
+            Annotatable::Crate(_) => ("crates", sym::proc_macro_hygiene),
+            Annotatable::Field(_) |
+            Annotatable::Variant(_) |
+            Annotatable::Param(_) |
+            Annotatable::GenericParam(_) => {
+                self.cx.span_bug(span, "attribute macros on fields, variants and parameters \
+                                        aren't collected yet")
+            }
         };
         emit_feature_err(
             self.cx.parse_sess,
@@ -670,6 +1189,24 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         }
     }
 
+    /// Macros invoked as closure parameters used to be expanded to a single pattern, anything
+    /// else they expand to is gated. Macros invoked as function parameters are gated when
+    /// parsed.
+    fn gate_param_macro_output(&self, span: Span, fragment: &AstFragment) {
+        if let AstFragment::Params(params) = fragment {
+            let is_pattern = match &params[..] {
+                [param] => match param.ty.node {
+                    ast::TyKind::Infer | ast::TyKind::Err => true,
+                    _ => false,
+                },
+                _ => false,
+            };
+            if !is_pattern {
+                self.cx.parse_sess.gated_spans.param_macros.borrow_mut().push(span);
+            }
+        }
+    }
+
     fn gate_proc_macro_expansion_kind(&self, span: Span, kind: AstFragmentKind) {
         let kind = match kind {
             AstFragmentKind::Expr => "expressions",
@@ -677,6 +1214,8 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
             AstFragmentKind::Pat => "patterns",
             AstFragmentKind::Ty => "types",
             AstFragmentKind::Stmts => "statements",
+            AstFragmentKind::Arms => "match arms",
+            AstFragmentKind::Params => "parameters",
             AstFragmentKind::Items => return,
             AstFragmentKind::TraitItems => return,
             AstFragmentKind::ImplItems => return,
@@ -701,20 +1240,463 @@ impl<'a, 'b> MacroExpander<'a, 'b> {
         path: &Path,
         span: Span,
     ) -> AstFragment {
+        let sess = self.cx.parse_sess;
+        let ParsedFragment { fragment, mut errors, leftover } =
+            parse_fragment_from_tts(sess, toks.clone(), kind);
+        let failure = if fragment.is_none() { errors.pop() } else { None };
+        for err in errors {
+            DiagnosticBuilder::new_diagnostic(&sess.span_diagnostic, err).emit();
+        }
+        if let Some(mut err) = leftover {
+            annotate_incomplete_parse(sess, &mut err, path, kind.name(), span);
+            DiagnosticBuilder::new_diagnostic(&sess.span_diagnostic, err).emit();
+        }
+        match fragment {
+            Some(fragment) => fragment,
+            None => {
+                if let Some(err) = failure {
+                    let mut err = DiagnosticBuilder::new_diagnostic(&sess.span_diagnostic, err);
+                    err.set_span(span);
+                    annotate_err_with_kind(&mut err, kind, span);
+                    err.emit();
+                }
+                self.cx.trace_macros_diag();
+                kind.dummy_in_context(self.cx, span, &toks)
+            }
+        }
+    }
+
+    /// Parses the output of an attribute macro applied to the crate root, which consists of the
+    /// crate's inner attributes followed by its items.
+    fn parse_crate_fragment(&mut self, toks: TokenStream, span: Span) -> AstFragment {
         let mut parser = self.cx.new_parser_from_tts(toks);
+        match parser.parse_crate_mod() {
+            Ok(krate) => AstFragment::Items(smallvec![crate_root_item(krate)]),
+            Err(mut err) => {
+                err.set_span(span);
+                err.emit();
+                self.cx.trace_macros_diag();
+                AstFragmentKind::Items.dummy(span)
+            }
+        }
+    }
+}
+
+struct ImportInjector<'a, 'b, 'c> {
+    expander: &'a mut MacroExpander<'b, 'c>,
+    mod_path: Vec<Symbol>,
+}
+
+impl<'a, 'b, 'c> ImportInjector<'a, 'b, 'c> {
+    fn inject(&mut self, id: NodeId, module: &mut ast::Mod) {
+        let imports = match self.expander.cx.requested_imports.remove(&self.mod_path) {
+            Some(imports) => imports,
+            None => return,
+        };
+        let cx = &mut *self.expander.cx;
+        let items = imports.into_iter().map(|path| {
+            let span = path.span;
+            let vis = respan(span.shrink_to_lo(), ast::VisibilityKind::Inherited);
+            let allow = cx.meta_list_item_word(span, sym::unused_imports);
+            let mut item = cx.item_use_simple(span, vis, path);
+            item.attrs.push(cx.attribute(cx.meta_list(span, sym::allow, vec![allow])));
+            item
+        }).collect();
+
+        // Integrate the imports into the existing module structures.
+        let orig_expansion_data = cx.current_expansion.clone();
+        cx.current_expansion.id = cx.resolver.get_module_scope(id);
+        let items = self.expander.fully_expand_fragment(AstFragment::Items(items)).make_items();
+        self.expander.cx.current_expansion = orig_expansion_data;
+        module.items.extend(items);
+    }
+}
+
+impl<'a, 'b, 'c> MutVisitor for ImportInjector<'a, 'b, 'c> {
+    fn flat_map_item(&mut self, mut item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
+        let (id, name) = (item.id, item.ident.name);
+        match item.node {
+            ast::ItemKind::Mod(ref mut module) => {
+                self.mod_path.push(name);
+                self.visit_mod(module);
+                self.inject(id, module);
+                self.mod_path.pop();
+                smallvec![item]
+            }
+            _ => noop_flat_map_item(item, self),
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &mut ast::Mac) {
+        // Macro invocations left after expansion are errors that have already been reported.
+    }
+}
+
+/// Applies the `#[span_source("...", offset, len)]` attributes in macro output, which make
+/// diagnostics pointing into the annotated node point at the given string literal instead,
+/// or at `len` bytes of its contents starting at `offset`. Macros generating code from a
+/// string in their input use this to report errors in the generated code at the string.
+///
+/// The spans of the annotated node are marked with a fresh transparent expansion, which
+/// doesn't affect name resolution, and the expansion is redirected in the source map (see
+/// `SourceMap::redirect_expansion`). The attribute itself is removed.
+/// Whether `#[span_source]` attributes in the output of `ext` take effect. The macro has to opt
+/// in with `#[allow_internal_unstable(span_source)]`, the crates it's used from don't need the
+/// feature.
+fn allows_span_source(ext: &SyntaxExtension) -> bool {
+    ext.allow_internal_unstable.as_ref()
+        .map_or(false, |features| features.contains(&sym::span_source))
+}
+
+struct SpanSourceCollector<'a, 'b> {
+    cx: &'a mut ExtCtxt<'b>,
+    /// The expansion that produced the annotated nodes.
+    parent: ExpnId,
+}
+
+impl<'a, 'b> SpanSourceCollector<'a, 'b> {
+    fn take_span_source(&mut self, node: &mut impl HasAttrs) -> Option<SpanSourceMarker> {
+        let mut span_source = None;
+        node.visit_attrs(|attrs| {
+            if let Some(i) = attrs.iter().position(|attr| attr.check_name(sym::span_source)) {
+                span_source = Some(attrs.remove(i));
+            }
+        });
+        let attr = span_source?;
+        let target = self.span_source_target(&attr)?;
+        let expn_id = ExpnId::fresh(Some(ExpnData {
+            parent: self.parent,
+            ..ExpnData::default(
+                ExpnKind::Macro(MacroKind::Attr, sym::span_source),
+                attr.span,
+                self.cx.parse_sess.edition,
+            )
+        }));
+        self.cx.source_map().redirect_expansion(expn_id, target);
+        Some(SpanSourceMarker(expn_id))
+    }
+
+    fn span_source_target(&mut self, attr: &ast::Attribute) -> Option<Span> {
+        let args = attr.meta_item_list().unwrap_or_default();
+        let arg_kind = |i: usize| args.get(i).and_then(|arg| arg.literal()).map(|lit| &lit.node);
+        let target = match (args.first().and_then(|arg| arg.literal()), arg_kind(1), arg_kind(2)) {
+            (Some(lit), None, None) if args.len() == 1 && lit.node.is_str() => lit.span,
+            (Some(lit), Some(&LitKind::Int(offset, _)), Some(&LitKind::Int(len, _)))
+                    if args.len() == 3 && lit.node.is_str() => {
+                // The offset is relative to the contents of the literal, after the opening
+                // quote and, for raw strings, the `r` and `#`s before it.
+                let snippet = self.cx.source_map().token_text(lit.span);
+                let contents = snippet.as_ref().and_then(|snippet| {
+                    let start = snippet.find('"')? + 1;
+                    let end = snippet.rfind('"')?;
+                    if start <= end { Some((start, end)) } else { None }
+                });
+                let inner = contents.and_then(|(start, end)| {
+                    let lo = (start as u128).checked_add(offset)?;
+                    let hi = lo.checked_add(len)?;
+                    if hi > end as u128 {
+                        return None;
+                    }
+                    Some(InnerSpan::new(lo as usize, hi as usize))
+                });
+                inner.map_or(lit.span, |inner| lit.span.from_inner(inner))
+            }
+            _ => {
+                self.cx.struct_span_err(attr.span, "malformed `span_source` attribute")
+                    .help("the attribute takes a string literal, optionally followed by \
+                           the offset and length of a part of its contents")
+                    .emit();
+                return None;
+            }
+        };
+        Some(target)
+    }
+}
+
+impl<'a, 'b> MutVisitor for SpanSourceCollector<'a, 'b> {
+    fn flat_map_item(&mut self, mut item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
+        if let Some(mut marker) = self.take_span_source(&mut item) {
+            item = noop_flat_map_item(item, &mut marker).expect_one("expected an item");
+        }
+        noop_flat_map_item(item, self)
+    }
+
+    fn flat_map_trait_item(&mut self, mut item: ast::TraitItem) -> SmallVec<[ast::TraitItem; 1]> {
+        if let Some(mut marker) = self.take_span_source(&mut item.attrs) {
+            item = noop_flat_map_trait_item(item, &mut marker).expect_one("expected an item");
+        }
+        noop_flat_map_trait_item(item, self)
+    }
+
+    fn flat_map_impl_item(&mut self, mut item: ast::ImplItem) -> SmallVec<[ast::ImplItem; 1]> {
+        if let Some(mut marker) = self.take_span_source(&mut item.attrs) {
+            item = noop_flat_map_impl_item(item, &mut marker).expect_one("expected an item");
+        }
+        noop_flat_map_impl_item(item, self)
+    }
+
+    fn visit_expr(&mut self, expr: &mut P<ast::Expr>) {
+        if let Some(mut marker) = self.take_span_source(expr) {
+            marker.visit_expr(expr);
+        }
+        noop_visit_expr(expr, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        noop_visit_mac(mac, self)
+    }
+}
+
+/// Marks all spans in a node annotated with `#[span_source]`, see `SpanSourceCollector`.
+struct SpanSourceMarker(ExpnId);
+
+impl MutVisitor for SpanSourceMarker {
+    const VISIT_INTERPOLATED: bool = true;
+
+    fn visit_span(&mut self, span: &mut Span) {
+        *span = span.apply_mark(self.0, Transparency::Transparent);
+    }
+
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        noop_visit_mac(mac, self)
+    }
+}
+
+/// A summary of the output of an attribute or derive macro invoked in dry-run mode.
+#[derive(RustcEncodable)]
+pub struct DryRunReport {
+    pub macro_path: String,
+    /// The location of the invocation.
+    pub span: String,
+    /// What kind of AST fragment the macro produced, e.g., `item`.
+    pub fragment_kind: String,
+    /// The names of all items, including associated and nested items, in the output.
+    pub items: Vec<String>,
+    /// The number of errors reported while expanding the macro.
+    pub errors: usize,
+}
+
+/// Gives the tokens in `stream` that an expander made up without a span, i.e., with `DUMMY_SP`,
+/// the span `call_site`. The other tokens keep their spans, both the ones of the input and the
+/// ones the expander gave a span, e.g., `Span::call_site()` in a procedural macro.
+fn with_call_site_if_dummy(stream: TokenStream, call_site: Span) -> TokenStream {
+    let respan = |span: Span| if span.is_dummy() { call_site } else { span };
+    stream.map(|tree| match tree {
+        TokenTree::Token(mut token) => {
+            token.span = respan(token.span);
+            TokenTree::Token(token)
+        }
+        TokenTree::Delimited(dspan, delim, tts) => {
+            let dspan = DelimSpan::from_pair(respan(dspan.open), respan(dspan.close));
+            TokenTree::Delimited(dspan, delim, with_call_site_if_dummy(tts, call_site))
+        }
+    })
+}
+
+fn is_dry_runnable(ext: &SyntaxExtension) -> bool {
+    match ext.kind {
+        _ if ext.is_builtin => false,
+        SyntaxExtensionKind::Attr(..) | SyntaxExtensionKind::LegacyAttr(..) |
+        SyntaxExtensionKind::Derive(..) | SyntaxExtensionKind::LegacyDerive(..) => true,
+        _ => false,
+    }
+}
+
+/// Puts the attribute of an attribute macro invocation back on the first node of the macro
+/// output, as an inert attribute (see `SyntaxExtension::keep_attr`).
+fn keep_macro_attr(fragment: &mut AstFragment, attr: ast::Attribute) {
+    attr::mark_known(&attr);
+    attr::mark_used(&attr);
+    let keep = |attrs: &mut Vec<ast::Attribute>| attrs.insert(0, attr);
+    match fragment {
+        AstFragment::OptExpr(Some(expr)) | AstFragment::Expr(expr) => expr.visit_attrs(keep),
+        AstFragment::Stmts(stmts) => if let Some(stmt) = stmts.first_mut() {
+            stmt.visit_attrs(keep)
+        }
+        AstFragment::Items(items) => if let Some(item) = items.first_mut() {
+            item.visit_attrs(keep)
+        }
+        AstFragment::TraitItems(items) => if let Some(item) = items.first_mut() {
+            item.visit_attrs(keep)
+        }
+        AstFragment::ImplItems(items) => if let Some(item) = items.first_mut() {
+            item.visit_attrs(keep)
+        }
+        AstFragment::ForeignItems(items) => if let Some(item) = items.first_mut() {
+            item.visit_attrs(keep)
+        }
+        AstFragment::Params(params) => if let Some(param) = params.first_mut() {
+            param.visit_attrs(keep)
+        }
+        AstFragment::OptExpr(None) | AstFragment::Pat(_) | AstFragment::Ty(_) |
+        AstFragment::Arms(_) => {}
+    }
+}
+
+/// Builds a `#[derive]` attribute listing the given traits.
+fn derive_attr(traits: Vec<Path>) -> ast::Attribute {
+    let traits = traits.into_iter().map(|path| ast::NestedMetaItem::MetaItem(ast::MetaItem {
+        span: path.span,
+        path,
+        node: ast::MetaItemKind::Word,
+    })).collect();
+    attr::mk_attr_outer(attr::mk_list_item(Ident::with_dummy_span(sym::derive), traits))
+}
+
+fn generated_item_names(fragment: &AstFragment) -> Vec<String> {
+    struct ItemNames(Vec<String>);
+
+    impl ItemNames {
+        fn push(&mut self, ident: Ident) {
+            if ident.name != kw::Invalid {
+                self.0.push(ident.to_string());
+            }
+        }
+    }
+
+    impl<'ast> Visitor<'ast> for ItemNames {
+        fn visit_item(&mut self, item: &'ast ast::Item) {
+            self.push(item.ident);
+            visit::walk_item(self, item);
+        }
+
+        fn visit_trait_item(&mut self, item: &'ast ast::TraitItem) {
+            self.push(item.ident);
+            visit::walk_trait_item(self, item);
+        }
+
+        fn visit_impl_item(&mut self, item: &'ast ast::ImplItem) {
+            self.push(item.ident);
+            visit::walk_impl_item(self, item);
+        }
+
+        fn visit_foreign_item(&mut self, item: &'ast ast::ForeignItem) {
+            self.push(item.ident);
+            visit::walk_foreign_item(self, item);
+        }
+
+        fn visit_mac(&mut self, _mac: &'ast ast::Mac) {}
+    }
+
+    let mut names = ItemNames(Vec::new());
+    fragment.visit_with(&mut names);
+    names.0
+}
+
+/// Wraps the crate into the item that stands for it during expansion: an unnamed module
+/// carrying the crate attributes.
+fn crate_root_item(krate: ast::Crate) -> P<ast::Item> {
+    P(ast::Item {
+        attrs: krate.attrs,
+        span: krate.span,
+        node: ast::ItemKind::Mod(krate.module),
+        ident: Ident::invalid(),
+        id: ast::DUMMY_NODE_ID,
+        vis: respan(krate.span.shrink_to_lo(), ast::VisibilityKind::Public),
+        tokens: None,
+    })
+}
+
+fn is_crate_root_item(item: &ast::Item) -> bool {
+    match item.node {
+        ast::ItemKind::Mod(_) => item.ident.name == kw::Invalid,
+        _ => false,
+    }
+}
+
+fn crate_from_root_item(item: P<ast::Item>) -> ast::Crate {
+    item.and_then(|item| match item.node {
+        ast::ItemKind::Mod(module) => ast::Crate { module, attrs: item.attrs, span: item.span },
+        _ => unreachable!(),
+    })
+}
+
+/// Converts the crate into the token stream passed to a procedural attribute macro applied to
+/// it: the remaining inner attributes followed by the items.
+fn crate_to_tokens(krate: ast::Crate) -> TokenStream {
+    let mut tts = Vec::new();
+    for attr in krate.attrs {
+        tts.push(TokenTree::token(token::Pound, attr.span));
+        if attr.style == ast::AttrStyle::Inner {
+            tts.push(TokenTree::token(token::Not, attr.span));
+        }
+        let delim_span = DelimSpan::from_single(attr.span);
+        tts.push(TokenTree::Delimited(delim_span, token::Bracket, attr.tokens()));
+    }
+    for item in krate.module.items {
+        tts.push(TokenTree::token(token::Interpolated(Lrc::new(token::NtItem(item))), DUMMY_SP));
+    }
+    tts.into_iter().collect()
+}
+
+/// The result of `parse_fragment_from_tts`.
+pub struct ParsedFragment {
+    /// The fragment, or `None` if parsing failed.
+    pub fragment: Option<AstFragment>,
+    /// The errors reported while parsing, in order, including the ones the parser recovered
+    /// from. If parsing failed, the last one is the error that stopped it.
+    pub errors: Vec<Diagnostic>,
+    /// The error about the tokens left over after the fragment, if there are any.
+    pub leftover: Option<Diagnostic>,
+}
+
+/// Parses `tts` as a fragment of the given kind, the way the expander interprets the output of a
+/// macro invoked in such a position. All of the tokens have to be consumed. None of the errors
+/// are emitted, they are returned to the caller instead.
+pub fn parse_fragment_from_tts(
+    sess: &ParseSess,
+    tts: TokenStream,
+    kind: AstFragmentKind,
+) -> ParsedFragment {
+    let mut parser = parse::stream_to_parser(sess, tts, None);
+    let mut leftover = Vec::new();
+    // The error that stops the parser is emitted last, so it's captured after the others.
+    let (fragment, errors) = sess.span_diagnostic.capture_errors(|| {
         match parser.parse_ast_fragment(kind, false) {
             Ok(fragment) => {
-                parser.ensure_complete_parse(path, kind.name(), span);
-                fragment
+                if let Some(err) = parser.incomplete_parse_err() {
+                    err.buffer(&mut leftover);
+                }
+                Some(fragment)
             }
             Err(mut err) => {
-                err.set_span(span);
-                annotate_err_with_kind(&mut err, kind, span);
                 err.emit();
-                self.cx.trace_macros_diag();
-                kind.dummy(span)
+                None
             }
         }
+    });
+    ParsedFragment { fragment, errors, leftover: leftover.pop() }
+}
+
+/// Explains an error about the tokens left over after parsing the output of the macro at
+/// `macro_path`, invoked at `span` in a position of the given kind.
+fn annotate_incomplete_parse(
+    sess: &ParseSess,
+    err: &mut Diagnostic,
+    macro_path: &Path,
+    kind_name: &str,
+    span: Span,
+) {
+    err.span_label(span, "caused by the macro expansion here");
+    let msg = format!(
+        "the usage of `{}!` is likely invalid in {} context",
+        macro_path,
+        kind_name,
+    );
+    err.note(&msg);
+    let semi_span = sess.source_map().next_point(span);
+
+    let semi_full_span = semi_span.to(sess.source_map().next_point(semi_span));
+    match sess.source_map().span_to_snippet(semi_full_span) {
+        Ok(ref snippet) if &snippet[..] != ";" && kind_name == "expression" => {
+            err.span_suggestion(
+                semi_span,
+                "you might be missing a semicolon here",
+                ";".to_owned(),
+                Applicability::MaybeIncorrect,
+            );
+        }
+        _ => {}
     }
 }
 
@@ -750,6 +1732,23 @@ impl<'a> Parser<'a> {
                 }
                 AstFragment::ForeignItems(items)
             }
+            AstFragmentKind::Arms => {
+                let mut arms = SmallVec::new();
+                while self.token != token::Eof {
+                    arms.push(self.parse_arm()?);
+                }
+                AstFragment::Arms(arms)
+            }
+            AstFragmentKind::Params => {
+                let mut params = SmallVec::new();
+                while self.token != token::Eof {
+                    params.push(self.parse_fn_block_param()?);
+                    if !self.eat(&token::Comma) {
+                        break;
+                    }
+                }
+                AstFragment::Params(params)
+            }
             AstFragmentKind::Stmts => {
                 let mut stmts = SmallVec::new();
                 while self.token != token::Eof &&
@@ -774,39 +1773,70 @@ impl<'a> Parser<'a> {
         })
     }
 
-    pub fn ensure_complete_parse(&mut self, macro_path: &Path, kind_name: &str, span: Span) {
-        if self.token != token::Eof {
-            let msg = format!("macro expansion ignores token `{}` and any following",
-                              self.this_token_to_string());
-            // Avoid emitting backtrace info twice.
-            let def_site_span = self.token.span.with_ctxt(SyntaxContext::root());
-            let mut err = self.diagnostic().struct_span_err(def_site_span, &msg);
-            err.span_label(span, "caused by the macro expansion here");
-            let msg = format!(
-                "the usage of `{}!` is likely invalid in {} context",
-                macro_path,
-                kind_name,
-            );
-            err.note(&msg);
-            let semi_span = self.sess.source_map().next_point(span);
+    /// Returns the error about the tokens left over after parsing a fragment, if there are any.
+    fn incomplete_parse_err(&self) -> Option<DiagnosticBuilder<'a>> {
+        if self.token == token::Eof {
+            return None;
+        }
+        let msg = format!("macro expansion ignores token `{}` and any following",
+                          self.this_token_to_string());
+        // Avoid emitting backtrace info twice.
+        let def_site_span = self.token.span.with_ctxt(SyntaxContext::root());
+        Some(self.diagnostic().struct_span_err(def_site_span, &msg))
+    }
 
-            let semi_full_span = semi_span.to(self.sess.source_map().next_point(semi_span));
-            match self.sess.source_map().span_to_snippet(semi_full_span) {
-                Ok(ref snippet) if &snippet[..] != ";" && kind_name == "expression" => {
-                    err.span_suggestion(
-                        semi_span,
-                        "you might be missing a semicolon here",
-                        ";".to_owned(),
-                        Applicability::MaybeIncorrect,
-                    );
-                }
-                _ => {}
-            }
+    pub fn ensure_complete_parse(&mut self, macro_path: &Path, kind_name: &str, span: Span) {
+        if let Some(mut err) = self.incomplete_parse_err() {
+            annotate_incomplete_parse(self.sess, &mut err, macro_path, kind_name, span);
             err.emit();
         }
     }
 }
 
+/// Allocates the ID of an invocation of the macro at `path` found in the output of `parent`.
+///
+/// With `ExpansionConfig::stable_expn_ids`, the ID is derived from `parent`, `path` and the number
+/// of invocations of `path` found in the output of `parent` so far, so that adding, removing or
+/// reordering other invocations doesn't change it.
+fn fresh_expn_id(cx: &mut ExtCtxt<'_>, parent: ExpnId, path: impl FnOnce() -> Symbol,
+                 expn_data: Option<ExpnData>) -> ExpnId {
+    if !cx.ecfg.stable_expn_ids {
+        return ExpnId::fresh(expn_data);
+    }
+    let path = path();
+    let index = cx.stable_expn_indices.entry((parent, path)).or_insert(0);
+    let mut hasher = StableHasher::new();
+    parent.as_u32().hash(&mut hasher);
+    path.as_str().hash(&mut hasher);
+    index.hash(&mut hasher);
+    *index += 1;
+    ExpnId::fresh_stable(hasher.finish(), expn_data)
+}
+
+/// Collects the invocations in each item of `result` as soon as it's created, see
+/// `MacResult::for_each_item`.
+fn collect_streamed_items(result: Box<dyn MacResult + '_>, monotonic: bool)
+                          -> (SmallVec<[P<ast::Item>; 1]>, Vec<Invocation>) {
+    let mut items = SmallVec::new();
+    let mut invocations = Vec::new();
+    result.for_each_item(&mut |cx, item| {
+        // Resolve the `$crate`s of the item for pretty-printing, like `collect_invocations`.
+        symbol::without_strict_ident_eq(|| cx.resolver.resolve_dollar_crates());
+        let mut collector = InvocationCollector {
+            cfg: StripUnconfigured {
+                sess: cx.parse_sess,
+                features: cx.ecfg.features,
+            },
+            cx,
+            invocations: mem::take(&mut invocations),
+            monotonic,
+        };
+        items.extend(collector.flat_map_item(item));
+        invocations = collector.invocations;
+    });
+    (items, invocations)
+}
+
 struct InvocationCollector<'a, 'b> {
     cx: &'a mut ExtCtxt<'b>,
     cfg: StripUnconfigured<'a>,
@@ -829,7 +1859,14 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
             }),
             _ => None,
         };
-        let expn_id = ExpnId::fresh(expn_data);
+        let path = || match &kind {
+            InvocationKind::Bang { mac, .. } => Symbol::intern(&mac.path.to_string()),
+            InvocationKind::Attr { attr, .. } => Symbol::intern(&attr.path.to_string()),
+            InvocationKind::Derive { path, .. } => Symbol::intern(&path.to_string()),
+            InvocationKind::DeriveContainer { .. } => sym::derive,
+        };
+        let parent = self.cx.current_expansion.id;
+        let expn_id = fresh_expn_id(self.cx, parent, path, expn_data);
         self.invocations.push(Invocation {
             kind,
             fragment_kind,
@@ -851,14 +1888,44 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
                     derives: Vec<Path>,
                     item: Annotatable,
                     kind: AstFragmentKind,
-                    after_derive: bool)
+                    after_derive: bool,
+                    unconfigured: bool)
                     -> AstFragment {
         self.collect(kind, match attr {
-            Some(attr) => InvocationKind::Attr { attr, item, derives, after_derive },
+            Some(attr) => InvocationKind::Attr { attr, item, derives, after_derive, unconfigured },
             None => InvocationKind::DeriveContainer { derives, item },
         })
     }
 
+    /// Adds `attrs` to the attributes in scope for the invocations collected from now on,
+    /// returning the attributes that were in scope before.
+    fn push_ancestor_attrs(&mut self, attrs: &[ast::Attribute]) -> Option<Rc<AncestorAttrs>> {
+        let orig_attrs = self.cx.current_expansion.ancestor_attrs.clone();
+        if !attrs.is_empty() {
+            self.cx.current_expansion.ancestor_attrs =
+                Some(Rc::new(AncestorAttrs { attrs: attrs.to_vec(), parent: orig_attrs.clone() }));
+        }
+        orig_attrs
+    }
+
+    fn walk_item(&mut self, item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
+        let orig_attrs = self.push_ancestor_attrs(&item.attrs);
+        let in_trait_impl = match item.node {
+            ast::ItemKind::Impl(.., Some(_), _, _) => true,
+            _ => false,
+        };
+        let orig_in_trait_impl =
+            mem::replace(&mut self.cx.current_expansion.in_trait_impl, in_trait_impl);
+        let result = noop_flat_map_item(item, self);
+        self.cx.current_expansion.ancestor_attrs = orig_attrs;
+        self.cx.current_expansion.in_trait_impl = orig_in_trait_impl;
+        result
+    }
+
+    fn is_attr_invoc(&self, attr: &ast::Attribute) -> bool {
+        !attr::is_known(attr) && !is_builtin_attr(attr) && self.cx.ecfg.should_expand(&attr.path)
+    }
+
     fn find_attr_invoc(&self, attrs: &mut Vec<ast::Attribute>, after_derive: &mut bool)
                        -> Option<ast::Attribute> {
         let attr = attrs.iter()
@@ -866,7 +1933,7 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
                             if a.path == sym::derive {
                                 *after_derive = true;
                             }
-                            !attr::is_known(a) && !is_builtin_attr(a)
+                            self.is_attr_invoc(a)
                         })
                         .map(|i| attrs.remove(i));
         if let Some(attr) = &attr {
@@ -891,6 +1958,8 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
             attr = self.find_attr_invoc(&mut attrs, &mut after_derive);
             traits = collect_derives(&mut self.cx, &mut attrs);
         });
+        let ecfg = &self.cx.ecfg;
+        traits.retain(|path| ecfg.should_expand(path));
 
         (attr, traits, after_derive)
     }
@@ -913,6 +1982,20 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
         self.cfg.configure(node)
     }
 
+    /// Like `configure`, but keeps a node disabled by a `#[cfg]` if a macro attribute precedes
+    /// that `#[cfg]`, returning whether the node is disabled. The macro may want to see it,
+    /// which is only known once the macro is resolved, see `SyntaxExtension::sees_unconfigured`.
+    fn configure_annotated<T: HasAttrs>(&mut self, mut node: T) -> Option<(T, bool)> {
+        self.cfg.process_cfg_attrs(&mut node);
+        match self.cfg.first_disabling_cfg(node.attrs()) {
+            None => Some((node, false)),
+            Some(cfg_index) if node.attrs()[..cfg_index].iter().any(|a| self.is_attr_invoc(a)) => {
+                Some((node, true))
+            }
+            Some(_) => None,
+        }
+    }
+
     // Detect use of feature-gated or invalid attributes on macro invocations
     // since they will not be detected after macro expansion.
     fn check_attributes(&mut self, attrs: &[ast::Attribute]) {
@@ -940,64 +2023,102 @@ impl<'a, 'b> InvocationCollector<'a, 'b> {
 }
 
 impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
+    // Macro calls can be interpolated into the tokens of attributes, e.g., `#[doc = $e]` with
+    // an `$e:expr` bound to `concat!(..)`, and have to be collected from there.
+    const VISIT_INTERPOLATED: bool = true;
+
     fn visit_expr(&mut self, expr: &mut P<ast::Expr>) {
         self.cfg.configure_expr(expr);
-        visit_clobber(expr.deref_mut(), |mut expr| {
-            self.cfg.configure_expr_kind(&mut expr.node);
+        self.cfg.configure_expr_kind(&mut expr.node);
 
-            // ignore derives so they remain unused
-            let (attr, after_derive) = self.classify_nonitem(&mut expr);
+        // ignore derives so they remain unused
+        let (attr, after_derive) = self.classify_nonitem(&mut **expr);
 
-            if attr.is_some() {
-                // Collect the invoc regardless of whether or not attributes are permitted here
-                // expansion will eat the attribute so it won't error later.
-                attr.as_ref().map(|a| self.cfg.maybe_emit_expr_attr_err(a));
+        if attr.is_some() {
+            // Collect the invoc regardless of whether or not attributes are permitted here
+            // expansion will eat the attribute so it won't error later.
+            attr.as_ref().map(|a| self.cfg.maybe_emit_expr_attr_err(a));
 
-                // AstFragmentKind::Expr requires the macro to emit an expression.
-                return self.collect_attr(attr, vec![], Annotatable::Expr(P(expr)),
-                                          AstFragmentKind::Expr, after_derive)
+            // AstFragmentKind::Expr requires the macro to emit an expression.
+            visit_clobber(expr, |expr| {
+                self.collect_attr(attr, vec![], Annotatable::Expr(expr),
+                                  AstFragmentKind::Expr, after_derive, false)
                     .make_expr()
-                    .into_inner()
-            }
+            });
+            return;
+        }
 
-            if let ast::ExprKind::Mac(mac) = expr.node {
-                self.check_attributes(&expr.attrs);
-                self.collect_bang(mac, expr.span, AstFragmentKind::Expr)
-                    .make_expr()
-                    .into_inner()
-            } else {
-                noop_visit_expr(&mut expr, self);
-                expr
-            }
-        });
+        if let ast::ExprKind::Mac(..) = expr.node {
+            self.check_attributes(&expr.attrs);
+            let mac = match mem::replace(&mut expr.node, ast::ExprKind::Err) {
+                ast::ExprKind::Mac(mac) => mac,
+                _ => unreachable!(),
+            };
+            *expr = self.collect_bang(mac, expr.span, AstFragmentKind::Expr).make_expr();
+        } else {
+            noop_visit_expr(expr, self);
+        }
     }
 
     fn filter_map_expr(&mut self, expr: P<ast::Expr>) -> Option<P<ast::Expr>> {
-        let expr = configure!(self, expr);
-        expr.filter_map(|mut expr| {
-            self.cfg.configure_expr_kind(&mut expr.node);
+        let mut expr = configure!(self, expr);
+        self.cfg.configure_expr_kind(&mut expr.node);
 
-            // Ignore derives so they remain unused.
-            let (attr, after_derive) = self.classify_nonitem(&mut expr);
+        // Ignore derives so they remain unused.
+        let (attr, after_derive) = self.classify_nonitem(&mut *expr);
 
-            if attr.is_some() {
-                attr.as_ref().map(|a| self.cfg.maybe_emit_expr_attr_err(a));
+        if attr.is_some() {
+            attr.as_ref().map(|a| self.cfg.maybe_emit_expr_attr_err(a));
 
-                return self.collect_attr(attr, vec![], Annotatable::Expr(P(expr)),
-                                         AstFragmentKind::OptExpr, after_derive)
-                    .make_opt_expr()
-                    .map(|expr| expr.into_inner())
-            }
+            return self.collect_attr(attr, vec![], Annotatable::Expr(expr),
+                                     AstFragmentKind::OptExpr, after_derive, false)
+                .make_opt_expr();
+        }
 
-            if let ast::ExprKind::Mac(mac) = expr.node {
-                self.check_attributes(&expr.attrs);
-                self.collect_bang(mac, expr.span, AstFragmentKind::OptExpr)
-                    .make_opt_expr()
-                    .map(|expr| expr.into_inner())
-            } else {
-                Some({ noop_visit_expr(&mut expr, self); expr })
-            }
-        })
+        if let ast::ExprKind::Mac(..) = expr.node {
+            self.check_attributes(&expr.attrs);
+            let mac = match mem::replace(&mut expr.node, ast::ExprKind::Err) {
+                ast::ExprKind::Mac(mac) => mac,
+                _ => unreachable!(),
+            };
+            self.collect_bang(mac, expr.span, AstFragmentKind::OptExpr).make_opt_expr()
+        } else {
+            Some(expr.map_in_place(|expr| noop_visit_expr(expr, self)))
+        }
+    }
+
+    fn flat_map_arm(&mut self, arm: ast::Arm) -> SmallVec<[ast::Arm; 1]> {
+        if arm.mac().is_none() {
+            return noop_flat_map_arm(arm, self);
+        }
+
+        self.check_attributes(&arm.attrs);
+        let ast::Arm { body, span, .. } = arm;
+        match body.into_inner().node {
+            ast::ExprKind::Mac(mac) =>
+                self.collect_bang(mac, span, AstFragmentKind::Arms).make_arms(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn flat_map_param(&mut self, param: ast::Param) -> SmallVec<[ast::Param; 1]> {
+        if param.mac().is_none() {
+            return noop_flat_map_param(param, self);
+        }
+
+        self.check_attributes(&param.attrs);
+        let ast::Param { attrs, pat, .. } = param;
+        let pat = pat.into_inner();
+        let mut placeholders = match pat.node {
+            PatKind::Mac(mac) =>
+                self.collect_bang(mac, pat.span, AstFragmentKind::Params).make_params(),
+            _ => unreachable!(),
+        };
+        // `PlaceholderExpander` moves the attributes onto each of the expanded parameters.
+        for placeholder in &mut placeholders {
+            placeholder.attrs = attrs.clone();
+        }
+        placeholders
     }
 
     fn visit_pat(&mut self, pat: &mut P<ast::Pat>) {
@@ -1007,19 +2128,20 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
             _ => return noop_visit_pat(pat, self),
         }
 
-        visit_clobber(pat, |mut pat| {
-            match mem::replace(&mut pat.node, PatKind::Wild) {
-                PatKind::Mac(mac) =>
-                    self.collect_bang(mac, pat.span, AstFragmentKind::Pat).make_pat(),
-                _ => unreachable!(),
-            }
-        });
+        let mac = match mem::replace(&mut pat.node, PatKind::Wild) {
+            PatKind::Mac(mac) => mac,
+            _ => unreachable!(),
+        };
+        *pat = self.collect_bang(mac, pat.span, AstFragmentKind::Pat).make_pat();
     }
 
     fn flat_map_stmt(&mut self, stmt: ast::Stmt) -> SmallVec<[ast::Stmt; 1]> {
-        let mut stmt = configure!(self, stmt);
+        let (mut stmt, unconfigured) = configure!(self, stmt, annotated);
 
         // we'll expand attributes on expressions separately
+        if unconfigured && stmt.is_expr() {
+            return SmallVec::new();
+        }
         if !stmt.is_expr() {
             let (attr, derives, after_derive) = if stmt.is_item() {
                 self.classify_item(&mut stmt)
@@ -1032,9 +2154,13 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
 
             if attr.is_some() || !derives.is_empty() {
                 return self.collect_attr(attr, derives, Annotatable::Stmt(P(stmt)),
-                                         AstFragmentKind::Stmts, after_derive).make_stmts();
+                                         AstFragmentKind::Stmts, after_derive, unconfigured)
+                    .make_stmts();
             }
         }
+        if unconfigured {
+            return SmallVec::new();
+        }
 
         if let StmtKind::Mac(mac) = stmt.node {
             let (mac, style, attrs) = mac.into_inner();
@@ -1069,12 +2195,18 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
     }
 
     fn flat_map_item(&mut self, item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
-        let mut item = configure!(self, item);
+        let (mut item, unconfigured) = configure!(self, item, annotated);
 
         let (attr, traits, after_derive) = self.classify_item(&mut item);
         if attr.is_some() || !traits.is_empty() {
-            return self.collect_attr(attr, traits, Annotatable::Item(item),
-                                     AstFragmentKind::Items, after_derive).make_items();
+            let item = if is_crate_root_item(&item) {
+                Annotatable::Crate(crate_from_root_item(item))
+            } else {
+                Annotatable::Item(item)
+            };
+            return self.collect_attr(attr, traits, item, AstFragmentKind::Items, after_derive,
+                                     unconfigured)
+                .make_items();
         }
 
         match item.node {
@@ -1089,7 +2221,7 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
             }
             ast::ItemKind::Mod(ast::Mod { inner, .. }) => {
                 if item.ident == Ident::invalid() {
-                    return noop_flat_map_item(item, self);
+                    return self.walk_item(item);
                 }
 
                 let orig_directory_ownership = self.cx.current_expansion.directory_ownership;
@@ -1129,23 +2261,24 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
 
                 let orig_module =
                     mem::replace(&mut self.cx.current_expansion.module, Rc::new(module));
-                let result = noop_flat_map_item(item, self);
+                let result = self.walk_item(item);
                 self.cx.current_expansion.module = orig_module;
                 self.cx.current_expansion.directory_ownership = orig_directory_ownership;
                 result
             }
 
-            _ => noop_flat_map_item(item, self),
+            _ => self.walk_item(item),
         }
     }
 
     fn flat_map_trait_item(&mut self, item: ast::TraitItem) -> SmallVec<[ast::TraitItem; 1]> {
-        let mut item = configure!(self, item);
+        let (mut item, unconfigured) = configure!(self, item, annotated);
 
         let (attr, traits, after_derive) = self.classify_item(&mut item);
         if attr.is_some() || !traits.is_empty() {
             return self.collect_attr(attr, traits, Annotatable::TraitItem(P(item)),
-                                     AstFragmentKind::TraitItems, after_derive).make_trait_items()
+                                     AstFragmentKind::TraitItems, after_derive, unconfigured)
+                .make_trait_items()
         }
 
         match item.node {
@@ -1154,17 +2287,23 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
                 self.check_attributes(&attrs);
                 self.collect_bang(mac, span, AstFragmentKind::TraitItems).make_trait_items()
             }
-            _ => noop_flat_map_trait_item(item, self),
+            _ => {
+                let orig_attrs = self.push_ancestor_attrs(&item.attrs);
+                let result = noop_flat_map_trait_item(item, self);
+                self.cx.current_expansion.ancestor_attrs = orig_attrs;
+                result
+            }
         }
     }
 
     fn flat_map_impl_item(&mut self, item: ast::ImplItem) -> SmallVec<[ast::ImplItem; 1]> {
-        let mut item = configure!(self, item);
+        let (mut item, unconfigured) = configure!(self, item, annotated);
 
         let (attr, traits, after_derive) = self.classify_item(&mut item);
         if attr.is_some() || !traits.is_empty() {
             return self.collect_attr(attr, traits, Annotatable::ImplItem(P(item)),
-                                     AstFragmentKind::ImplItems, after_derive).make_impl_items();
+                                     AstFragmentKind::ImplItems, after_derive, unconfigured)
+                .make_impl_items();
         }
 
         match item.node {
@@ -1173,7 +2312,12 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
                 self.check_attributes(&attrs);
                 self.collect_bang(mac, span, AstFragmentKind::ImplItems).make_impl_items()
             }
-            _ => noop_flat_map_impl_item(item, self),
+            _ => {
+                let orig_attrs = self.push_ancestor_attrs(&item.attrs);
+                let result = noop_flat_map_impl_item(item, self);
+                self.cx.current_expansion.ancestor_attrs = orig_attrs;
+                result
+            }
         }
     }
 
@@ -1183,28 +2327,27 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
             _ => return noop_visit_ty(ty, self),
         };
 
-        visit_clobber(ty, |mut ty| {
-            match mem::replace(&mut ty.node, ast::TyKind::Err) {
-                ast::TyKind::Mac(mac) =>
-                    self.collect_bang(mac, ty.span, AstFragmentKind::Ty).make_ty(),
-                _ => unreachable!(),
-            }
-        });
+        let mac = match mem::replace(&mut ty.node, ast::TyKind::Err) {
+            ast::TyKind::Mac(mac) => mac,
+            _ => unreachable!(),
+        };
+        *ty = self.collect_bang(mac, ty.span, AstFragmentKind::Ty).make_ty();
     }
 
     fn visit_foreign_mod(&mut self, foreign_mod: &mut ast::ForeignMod) {
-        self.cfg.configure_foreign_mod(foreign_mod);
+        // The foreign items are configured one by one, see `flat_map_foreign_item`.
         noop_visit_foreign_mod(foreign_mod, self);
     }
 
-    fn flat_map_foreign_item(&mut self, mut foreign_item: ast::ForeignItem)
+    fn flat_map_foreign_item(&mut self, foreign_item: ast::ForeignItem)
         -> SmallVec<[ast::ForeignItem; 1]>
     {
+        let (mut foreign_item, unconfigured) = configure!(self, foreign_item, annotated);
         let (attr, traits, after_derive) = self.classify_item(&mut foreign_item);
 
         if attr.is_some() || !traits.is_empty() {
             return self.collect_attr(attr, traits, Annotatable::ForeignItem(P(foreign_item)),
-                                     AstFragmentKind::ForeignItems, after_derive)
+                                     AstFragmentKind::ForeignItems, after_derive, unconfigured)
                                      .make_foreign_items();
         }
 
@@ -1356,7 +2499,7 @@ impl<'a, 'b> MutVisitor for InvocationCollector<'a, 'b> {
                 id: at.id,
                 style: at.style,
                 path: meta.path,
-                tokens: meta.node.tokens(meta.span),
+                args: meta.node.attr_args(meta.span),
                 is_sugared_doc: false,
             };
         } else {
@@ -1382,9 +2525,59 @@ pub struct ExpansionConfig<'feat> {
     pub features: Option<&'feat Features>,
     pub recursion_limit: usize,
     pub trace_mac: bool,
+    /// The maximum number of tokens printed for a token stream in `trace_macros!` notes and
+    /// their diffs, `log_syntax!` output and the inputs recorded in the macro trace, longer
+    /// streams have their middle elided. `None` prints streams in full.
+    pub print_token_limit: Option<usize>,
+    /// In `trace_macros!` output, follow the output of each traced invocation with its
+    /// differences from the input, see `tokenstream::diff`.
+    pub trace_diff: bool,
+    /// Invoke non-builtin attribute and derive macros without using their output, emitting a
+    /// `macro_dry_run` artifact that summarizes it instead.
+    pub attr_dry_run: bool,
+    /// If set, only invocations of the macros named here are expanded, all other invocations
+    /// are left in the AST as is. Macros are named either by the last segment of their path or
+    /// by the whole path, e.g. `Debug` or `std::fmt::Debug`.
+    ///
+    /// The resulting AST still contains macro invocations, so it's only useful for inspecting
+    /// (e.g. pretty-printing) expansion results and can't be compiled any further.
+    pub expand_only: Option<FxHashSet<Symbol>>,
+    /// Invocations of the macros named here are left in the AST unexpanded, the same way as
+    /// the ones not listed in `expand_only`.
+    pub skip: FxHashSet<Symbol>,
     pub should_test: bool, // If false, strip `#[test]` nodes
     pub single_step: bool,
     pub keep_macs: bool,
+    /// Warn about `==` comparisons of identifiers that differ only in hygiene made during
+    /// expansion, outside of name resolution, see `syntax_pos::symbol::with_strict_ident_eq`.
+    pub strict_ident_eq: bool,
+    /// Check the output of each macro right after it's expanded, warning about problems that
+    /// would otherwise only be found later without pointing at the responsible macro, see
+    /// `ext::validate`.
+    pub validate_output: bool,
+    /// Once the crate is expanded, warn about items with the same name in the same module that
+    /// were generated by different macro invocations, see `ext::validate`.
+    pub check_item_collisions: bool,
+    /// Once the crate is expanded, warn about attributes that no extension consumed, see
+    /// `ext::validate`.
+    pub check_dead_attrs: bool,
+    /// Derive the IDs of macro invocations from their position in the expansion tree instead of
+    /// allocating them in order, so that re-expanding a changed item doesn't change the IDs of
+    /// the expansions of the other items, see `ExpnId::fresh_stable`.
+    pub stable_expn_ids: bool,
+    /// Remember which arm of a `macro_rules!` macro matched an input, so that invoking the macro
+    /// with the same tokens again skips matching, see `ext::tt::match_cache`.
+    pub macro_rules_cache: bool,
+    /// Reuse the output of a pure procedural macro invoked with the same tokens as before instead
+    /// of invoking it again, see `ext::pure_macro`.
+    pub pure_macro_cache: bool,
+    /// Share the token streams produced by macros with identical ones produced before, see
+    /// `ExtCtxt::token_interner`.
+    pub intern_token_streams: bool,
+    /// Record every expanded macro invocation in `ExtCtxt::macro_trace`, see `ext::macro_trace`.
+    pub trace_json: bool,
+    /// Observers notified before and after each macro invocation is expanded, in order.
+    pub observers: Vec<Box<dyn ExpansionObserver + 'feat>>,
 }
 
 impl<'feat> ExpansionConfig<'feat> {
@@ -1394,12 +2587,45 @@ impl<'feat> ExpansionConfig<'feat> {
             features: None,
             recursion_limit: 1024,
             trace_mac: false,
+            print_token_limit: None,
+            trace_diff: false,
+            attr_dry_run: false,
+            expand_only: None,
+            skip: FxHashSet::default(),
             should_test: false,
             single_step: false,
             keep_macs: false,
+            strict_ident_eq: false,
+            validate_output: false,
+            check_item_collisions: false,
+            check_dead_attrs: false,
+            stable_expn_ids: false,
+            macro_rules_cache: false,
+            pure_macro_cache: true,
+            intern_token_streams: false,
+            trace_json: false,
+            observers: Vec::new(),
         }
     }
 
+    /// Whether only some of the macro invocations are expanded, see `expand_only` and `skip`.
+    pub fn expands_selectively(&self) -> bool {
+        self.expand_only.is_some() || !self.skip.is_empty()
+    }
+
+    /// Whether invocations of the macro at `path` are expanded, see `expand_only` and `skip`.
+    pub fn should_expand(&self, path: &Path) -> bool {
+        if !self.expands_selectively() {
+            return true;
+        }
+        let full_name = Symbol::intern(&path.to_string());
+        let is_named_in = |names: &FxHashSet<Symbol>| {
+            names.contains(&full_name) ||
+                path.segments.last().map_or(false, |segment| names.contains(&segment.ident.name))
+        };
+        self.expand_only.as_ref().map_or(true, is_named_in) && !is_named_in(&self.skip)
+    }
+
     fn macros_in_extern(&self) -> bool {
         self.features.map_or(false, |features| features.macros_in_extern)
     }
@@ -0,0 +1,118 @@
+use super::*;
+
+use crate::source_map::FilePathMapping;
+use crate::tests::string_to_stream;
+use crate::with_default_globals;
+
+fn parse(source: &str, kind: AstFragmentKind) -> ParsedFragment {
+    let sess = ParseSess::new(FilePathMapping::empty());
+    let parsed = parse_fragment_from_tts(&sess, string_to_stream(source.to_string()), kind);
+    assert_eq!(sess.span_diagnostic.err_count(), 0);
+    parsed
+}
+
+/// Returns the fragment if it was parsed without any error, or all of the errors.
+fn parse_as(source: &str, kind: AstFragmentKind) -> Result<AstFragment, Vec<Diagnostic>> {
+    let ParsedFragment { fragment, mut errors, leftover } = parse(source, kind);
+    errors.extend(leftover);
+    match fragment {
+        Some(fragment) if errors.is_empty() => Ok(fragment),
+        _ => Err(errors),
+    }
+}
+
+#[test]
+fn fragments_of_each_kind_are_parsed() {
+    with_default_globals(|| {
+        match parse_as("fn f() {} struct S;", AstFragmentKind::Items) {
+            Ok(AstFragment::Items(items)) => assert_eq!(items.len(), 2),
+            _ => panic!("expected items"),
+        }
+        match parse_as("let x = 1; x + 1", AstFragmentKind::Stmts) {
+            Ok(AstFragment::Stmts(stmts)) => assert_eq!(stmts.len(), 2),
+            _ => panic!("expected statements"),
+        }
+        match parse_as("", AstFragmentKind::OptExpr) {
+            Ok(AstFragment::OptExpr(None)) => {}
+            _ => panic!("expected no expression"),
+        }
+        assert!(parse_as("Vec<u8>", AstFragmentKind::Ty).is_ok());
+        match parse_as("0 => 1, 1 | 2 => { 2 } _ => 3", AstFragmentKind::Arms) {
+            Ok(AstFragment::Arms(arms)) => assert_eq!(arms.len(), 3),
+            _ => panic!("expected match arms"),
+        }
+        match parse_as("a: u8, (b, c), _: &str,", AstFragmentKind::Params) {
+            Ok(AstFragment::Params(params)) => assert_eq!(params.len(), 3),
+            _ => panic!("expected parameters"),
+        }
+    })
+}
+
+#[test]
+fn leftover_tokens_are_an_error() {
+    with_default_globals(|| {
+        let diagnostics = parse_as("1 2", AstFragmentKind::Expr).err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message(), "macro expansion ignores token `2` and any following");
+    })
+}
+
+#[test]
+fn parse_errors_are_returned() {
+    with_default_globals(|| {
+        assert!(parse_as("struct", AstFragmentKind::Items).is_err());
+        assert!(parse_as("1 +", AstFragmentKind::Expr).is_err());
+    })
+}
+
+#[test]
+fn recovered_errors_are_returned() {
+    with_default_globals(|| {
+        let parsed = parse("\"a\"x + 1", AstFragmentKind::Expr);
+        assert!(parsed.fragment.is_some());
+        assert!(parsed.leftover.is_none());
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].message(), "suffixes on a string literal are invalid");
+
+        let parsed = parse("\"a\"x +", AstFragmentKind::Expr);
+        assert!(parsed.fragment.is_none());
+        assert_eq!(parsed.errors.len(), 2);
+        assert_eq!(parsed.errors[0].message(), "suffixes on a string literal are invalid");
+    })
+}
+
+#[test]
+fn selected_macros() {
+    with_default_globals(|| {
+        let path = |names: &[&str]| ast::Path {
+            span: DUMMY_SP,
+            segments: names.iter()
+                .map(|name| ast::PathSegment::from_ident(Ident::from_str(name)))
+                .collect(),
+        };
+        let names = |names: &[&str]| -> FxHashSet<Symbol> {
+            names.iter().map(|name| Symbol::intern(name)).collect()
+        };
+
+        let mut ecfg = ExpansionConfig::default("test".to_string());
+        assert!(!ecfg.expands_selectively());
+        assert!(ecfg.should_expand(&path(&["m"])));
+
+        ecfg.expand_only = Some(names(&["m", "a::n"]));
+        assert!(ecfg.expands_selectively());
+        assert!(ecfg.should_expand(&path(&["m"])));
+        assert!(ecfg.should_expand(&path(&["b", "m"])));
+        assert!(ecfg.should_expand(&path(&["a", "n"])));
+        assert!(!ecfg.should_expand(&path(&["n"])));
+        assert!(!ecfg.should_expand(&path(&["o"])));
+
+        ecfg.skip = names(&["b::m"]);
+        assert!(ecfg.should_expand(&path(&["m"])));
+        assert!(!ecfg.should_expand(&path(&["b", "m"])));
+
+        ecfg.expand_only = None;
+        assert!(ecfg.expands_selectively());
+        assert!(ecfg.should_expand(&path(&["o"])));
+        assert!(!ecfg.should_expand(&path(&["b", "m"])));
+    })
+}
@@ -0,0 +1,238 @@
+//! Parsing of `format!`-style format strings with `fmt_macros`, mapping the spans of their
+//! parts into the string literal they're written in. Formatting macros other than the built-in
+//! `format_args!` use this to accept the same syntax and report errors the same way.
+
+use crate::ast::StrStyle;
+use crate::ext::base::ExtCtxt;
+use crate::symbol::Symbol;
+
+use syntax_pos::{InnerSpan, Span};
+
+pub use fmt_macros::{Alignment, Argument, Count, Flag, FormatSpec, Piece, Position};
+
+#[cfg(test)]
+mod tests;
+
+/// A format string and the pieces it was parsed into.
+pub struct FormatString<'a> {
+    /// The pieces of the format string, up to the first error.
+    pub pieces: Vec<Piece<'a>>,
+    /// The spans of the `{...}` of the arguments in `pieces`, in order.
+    pub arg_spans: Vec<Span>,
+    /// The first error in the format string, which ends the `pieces`.
+    pub error: Option<FormatStringError>,
+    /// Whether the format string is written as a string literal, rather than produced by a
+    /// macro, e.g., by `concat!`, so that the spans within it point at its parts.
+    pub is_literal: bool,
+    /// The span of the format string.
+    pub span: Span,
+}
+
+/// An error in a format string, with spans within it.
+pub struct FormatStringError {
+    pub description: String,
+    pub note: Option<String>,
+    pub label: String,
+    pub span: Span,
+    pub secondary_label: Option<(String, Span)>,
+}
+
+impl<'a> FormatString<'a> {
+    /// Parses `fmt_str`, a string literal in the `style` at `span`. `snippet` is the source text
+    /// of the literal, used to map positions in its (unescaped) contents to spans in the
+    /// source. `append_newline` tells that a newline was appended to the contents as by
+    /// `println!`, and isn't found in the source.
+    pub fn parse(
+        fmt_str: &'a str,
+        style: StrStyle,
+        span: Span,
+        snippet: Option<&str>,
+        append_newline: bool,
+    ) -> FormatString<'a> {
+        let is_literal = snippet.map_or(false, |s| s.starts_with("\"") || s.starts_with("r#"));
+        let str_style = match style {
+            StrStyle::Cooked => None,
+            StrStyle::Raw(raw) => Some(raw as usize),
+        };
+        let skips = match snippet {
+            Some(snippet) if is_literal => {
+                let r_start = str_style.map(|r| r + 1).unwrap_or(0);
+                let r_end = str_style.map(|r| r).unwrap_or(0);
+                let s = &snippet[r_start + 1..snippet.len() - r_end - 1];
+                find_skips(s, str_style.is_some())
+            }
+            _ => vec![],
+        };
+
+        let mut parser = fmt_macros::Parser::new(fmt_str, str_style, skips, append_newline);
+        let mut pieces = Vec::new();
+        while let Some(piece) = parser.next() {
+            if !parser.errors.is_empty() {
+                break;
+            }
+            pieces.push(piece);
+        }
+        let error = if parser.errors.is_empty() {
+            None
+        } else {
+            let err = parser.errors.remove(0);
+            Some(FormatStringError {
+                description: err.description,
+                note: err.note,
+                label: err.label,
+                span: span.from_inner(err.span),
+                secondary_label: err.secondary_label
+                    .map(|(label, inner)| (label, span.from_inner(inner))),
+            })
+        };
+        let arg_spans = parser.arg_places.iter().map(|&inner| span.from_inner(inner)).collect();
+
+        FormatString { pieces, arg_spans, error, is_literal, span }
+    }
+
+    /// The span of a part of the format string, e.g., the `width_span` of a `FormatSpec`.
+    pub fn inner_span(&self, inner: InnerSpan) -> Span {
+        self.span.from_inner(inner)
+    }
+
+    /// The names used in the format string, as arguments or as counts, that aren't among
+    /// `explicit`, the names of the arguments passed along with it. Each of them is returned
+    /// once, with the span of its first use, e.g., for macros capturing them implicitly from
+    /// the surrounding scope.
+    pub fn implicit_captures(&self, explicit: &[Symbol]) -> Vec<(Symbol, Span)> {
+        let mut captures: Vec<(Symbol, Span)> = Vec::new();
+        let mut capture = |name: Symbol, span: Span| {
+            if !explicit.contains(&name) && !captures.iter().any(|&(n, _)| n == name) {
+                captures.push((name, span));
+            }
+        };
+        let args = self.pieces.iter().filter_map(|piece| match piece {
+            Piece::NextArgument(arg) => Some(arg),
+            Piece::String(_) => None,
+        });
+        for (i, arg) in args.enumerate() {
+            let arg_span = self.arg_spans.get(i).cloned().unwrap_or(self.span);
+            if let Position::ArgumentNamed(name) = arg.position {
+                capture(name, arg_span);
+            }
+            let counts = [
+                (arg.format.width, arg.format.width_span),
+                (arg.format.precision, arg.format.precision_span),
+            ];
+            for &(count, inner) in &counts {
+                if let Count::CountIsName(name) = count {
+                    capture(name, inner.map_or(arg_span, |inner| self.inner_span(inner)));
+                }
+            }
+        }
+        captures
+    }
+}
+
+impl FormatStringError {
+    /// Reports the error as `format_args!` does.
+    pub fn emit(self, cx: &ExtCtxt<'_>) {
+        let mut err = cx.struct_span_err(
+            self.span,
+            &format!("invalid format string: {}", self.description),
+        );
+        err.span_label(self.span, self.label + " in format string");
+        if let Some(note) = self.note {
+            err.note(&note);
+        }
+        if let Some((label, span)) = self.secondary_label {
+            err.span_label(span, label);
+        }
+        err.emit();
+    }
+}
+
+/// Finds the indices of all characters that have been processed and differ between the actual
+/// written code (code snippet) and the `InternedString` that get's processed in the `Parser`
+/// in order to properly synthethise the intra-string `Span`s for error diagnostics.
+fn find_skips(snippet: &str, is_raw: bool) -> Vec<usize> {
+    let mut eat_ws = false;
+    let mut s = snippet.chars().enumerate().peekable();
+    let mut skips = vec![];
+    while let Some((pos, c)) = s.next() {
+        match (c, s.peek()) {
+            // skip whitespace and empty lines ending in '\\'
+            ('\\', Some((next_pos, '\n'))) if !is_raw => {
+                eat_ws = true;
+                skips.push(pos);
+                skips.push(*next_pos);
+                let _ = s.next();
+            }
+            ('\\', Some((next_pos, '\n'))) |
+            ('\\', Some((next_pos, 'n'))) |
+            ('\\', Some((next_pos, 't'))) if eat_ws => {
+                skips.push(pos);
+                skips.push(*next_pos);
+                let _ = s.next();
+            }
+            (' ', _) |
+            ('\n', _) |
+            ('\t', _) if eat_ws => {
+                skips.push(pos);
+            }
+            ('\\', Some((next_pos, 'n'))) |
+            ('\\', Some((next_pos, 't'))) |
+            ('\\', Some((next_pos, '0'))) |
+            ('\\', Some((next_pos, '\\'))) |
+            ('\\', Some((next_pos, '\''))) |
+            ('\\', Some((next_pos, '\"'))) => {
+                skips.push(*next_pos);
+                let _ = s.next();
+            }
+            ('\\', Some((_, 'x'))) if !is_raw => {
+                for _ in 0..3 {  // consume `\xAB` literal
+                    if let Some((pos, _)) = s.next() {
+                        skips.push(pos);
+                    } else {
+                        break;
+                    }
+                }
+            }
+            ('\\', Some((_, 'u'))) if !is_raw => {
+                if let Some((pos, _)) = s.next() {
+                    skips.push(pos);
+                }
+                if let Some((next_pos, next_c)) = s.next() {
+                    if next_c == '{' {
+                        skips.push(next_pos);
+                        let mut i = 0;  // consume up to 6 hexanumeric chars + closing `}`
+                        while let (Some((next_pos, c)), true) = (s.next(), i < 7) {
+                            if c.is_digit(16) {
+                                skips.push(next_pos);
+                            } else if c == '}' {
+                                skips.push(next_pos);
+                                break;
+                            } else {
+                                break;
+                            }
+                            i += 1;
+                        }
+                    } else if next_c.is_digit(16) {
+                        skips.push(next_pos);
+                        // We suggest adding `{` and `}` when appropriate, accept it here as if
+                        // it were correct
+                        let mut i = 0;  // consume up to 6 hexanumeric chars
+                        while let (Some((next_pos, c)), _) = (s.next(), i < 6) {
+                            if c.is_digit(16) {
+                                skips.push(next_pos);
+                            } else {
+                                break;
+                            }
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            _ if eat_ws => {  // `take_while(|c| c.is_whitespace())`
+                eat_ws = false;
+            }
+            _ => {}
+        }
+    }
+    skips
+}
@@ -0,0 +1,37 @@
+use super::*;
+
+use crate::with_default_globals;
+
+use syntax_pos::BytePos;
+
+#[test]
+fn spans_point_into_the_literal() {
+    with_default_globals(|| {
+        // The literal `"a\n{x:>w$} {}"` at 10..25.
+        let span = Span::with_root_ctxt(BytePos(10), BytePos(25));
+        let snippet = r#""a\n{x:>w$} {}""#;
+        let fmt = FormatString::parse("a\n{x:>w$} {}", StrStyle::Cooked, span, Some(snippet),
+                                      false);
+        assert!(fmt.error.is_none());
+        assert!(fmt.is_literal);
+        assert_eq!(fmt.pieces.len(), 4);
+        assert_eq!(fmt.arg_spans, [
+            Span::with_root_ctxt(BytePos(14), BytePos(21)),
+            Span::with_root_ctxt(BytePos(22), BytePos(24)),
+        ]);
+
+        let captures = fmt.implicit_captures(&[Symbol::intern("w")]);
+        assert_eq!(captures, [(Symbol::intern("x"), fmt.arg_spans[0])]);
+    })
+}
+
+#[test]
+fn errors_point_into_the_literal() {
+    with_default_globals(|| {
+        let span = Span::with_root_ctxt(BytePos(0), BytePos(5));
+        let fmt = FormatString::parse("{:?", StrStyle::Cooked, span, Some(r#""{:?""#), false);
+        let error = fmt.error.expect("expected an error");
+        assert_eq!(error.description, "expected `'}'` but string was terminated");
+        assert!(span.contains(error.span));
+    })
+}
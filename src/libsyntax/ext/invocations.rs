@@ -0,0 +1,103 @@
+//! Enumeration of the macro invocations written in a crate, without resolving or expanding them.
+//!
+//! This is meant for build tooling and analyzers that need to know which macros a crate uses
+//! and where, e.g., to find out which procedural macro crates it needs, without setting up a
+//! resolver. Since nothing is resolved, every attribute that isn't built-in or a tool attribute
+//! is assumed to be a macro attribute, and `#[cfg]`s and `#[cfg_attr]`s aren't evaluated, so
+//! invocations in disabled code are included while attributes inside `#[cfg_attr]` are not.
+//! Invocations in the arguments of other macros are only known once those are expanded, so
+//! they aren't included either.
+
+use crate::ast::{self, Ident};
+use crate::ext::base::MacroKind;
+use crate::feature_gate::is_builtin_attr;
+use crate::symbol::sym;
+use crate::tokenstream::TokenStream;
+use crate::visit::{self, Visitor};
+
+use syntax_pos::Span;
+
+#[cfg(test)]
+mod tests;
+
+/// A macro invocation found by `collect_invocations`.
+#[derive(Clone, Debug)]
+pub struct InvocationInfo {
+    pub kind: MacroKind,
+    pub path: ast::Path,
+    pub span: Span,
+    /// The tokens passed to the macro: those between the delimiters of a bang macro call, or
+    /// those following the path of a macro attribute. Empty for derives.
+    pub args: TokenStream,
+    /// The path of the module the invocation is in, relative to the crate root.
+    pub module: Vec<Ident>,
+}
+
+/// Returns the macro invocations written in `krate`, ordered by their spans, see the module docs.
+pub fn collect_invocations(krate: &ast::Crate) -> Vec<InvocationInfo> {
+    let mut collector = InvocationCollector {
+        invocations: Vec::new(),
+        module: Vec::new(),
+        entered_module: None,
+    };
+    visit::walk_crate(&mut collector, krate);
+    collector.invocations.sort_by_key(|invoc| invoc.span.lo());
+    collector.invocations
+}
+
+struct InvocationCollector {
+    invocations: Vec<InvocationInfo>,
+    module: Vec<Ident>,
+    /// The name of the module item being visited, until its contents are.
+    entered_module: Option<Ident>,
+}
+
+impl InvocationCollector {
+    fn push(&mut self, kind: MacroKind, path: ast::Path, span: Span, args: TokenStream) {
+        let module = self.module.clone();
+        self.invocations.push(InvocationInfo { kind, path, span, args, module });
+    }
+}
+
+impl<'a> Visitor<'a> for InvocationCollector {
+    fn visit_item(&mut self, item: &'a ast::Item) {
+        if let ast::ItemKind::Mod(..) = item.node {
+            self.entered_module = Some(item.ident);
+        }
+        visit::walk_item(self, item);
+    }
+
+    fn visit_mod(&mut self, m: &'a ast::Mod, _: Span, _: &[ast::Attribute], _: ast::NodeId) {
+        match self.entered_module.take() {
+            Some(ident) => {
+                self.module.push(ident);
+                visit::walk_mod(self, m);
+                self.module.pop();
+            }
+            None => visit::walk_mod(self, m),
+        }
+    }
+
+    fn visit_attribute(&mut self, attr: &'a ast::Attribute) {
+        if attr.is_sugared_doc || is_builtin_attr(attr) && attr.path != sym::derive {
+            return;
+        }
+        let segments = &attr.path.segments;
+        if segments.len() > 1 && [sym::clippy, sym::rustfmt].contains(&segments[0].ident.name) {
+            return;
+        }
+        if attr.path != sym::derive {
+            self.push(MacroKind::Attr, attr.path.clone(), attr.span, attr.args.outer_tokens());
+            return;
+        }
+        for nested in attr.meta_item_list().unwrap_or_default() {
+            if let Some(meta) = nested.meta_item() {
+                self.push(MacroKind::Derive, meta.path.clone(), meta.span, TokenStream::empty());
+            }
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &'a ast::Mac) {
+        self.push(MacroKind::Bang, mac.path.clone(), mac.span, mac.tts.clone());
+    }
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+use crate::print::pprust;
+use crate::tests::string_to_crate;
+use crate::with_default_globals;
+
+#[test]
+fn invocations_are_collected_with_their_modules() {
+    with_default_globals(|| {
+        let krate = string_to_crate(r#"
+            #[derive(Debug, Clone)]
+            #[my_attr(x)]
+            struct S;
+
+            mod m {
+                #[rustfmt::skip]
+                #[inline]
+                fn f() { println!("{}", 1); }
+            }
+        "#.to_string());
+
+        let invocations: Vec<_> = collect_invocations(&krate).into_iter().map(|invoc| {
+            let module: Vec<_> = invoc.module.iter().map(|ident| ident.to_string()).collect();
+            let args = pprust::tts_to_string(invoc.args);
+            (invoc.kind, invoc.path.to_string(), args, module.join("::"))
+        }).collect();
+        assert_eq!(invocations, [
+            (MacroKind::Derive, "Debug".to_string(), String::new(), String::new()),
+            (MacroKind::Derive, "Clone".to_string(), String::new(), String::new()),
+            (MacroKind::Attr, "my_attr".to_string(), "(x)".to_string(), String::new()),
+            (MacroKind::Bang, "println".to_string(), "\"{}\", 1".to_string(), "m".to_string()),
+        ]);
+    })
+}
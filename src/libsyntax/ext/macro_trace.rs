@@ -0,0 +1,148 @@
+//! A machine-readable trace of macro expansion.
+//!
+//! With `ExpansionConfig::trace_json`, every expanded macro invocation is recorded in
+//! `ExtCtxt::macro_trace`, along with the expansion it came from. Once the crate is expanded,
+//! the trace can be stored as JSON, or as a graph in the DOT format of Graphviz. Unlike the
+//! notes of `trace_macros!`, the trace is meant for tools, e.g. to find the macros that take
+//! the most time to expand, or to see which macros invoke which other macros.
+
+use crate::ext::base::{ExtCtxt, MacroKind};
+use crate::ext::expand::{Invocation, InvocationKind};
+use crate::ext::hygiene::ExpnId;
+use crate::print::pprust;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_serialize::json;
+
+use std::fmt::Write;
+use std::time::Instant;
+
+/// What the trace records about a single macro invocation.
+#[derive(RustcEncodable, Clone, Debug, PartialEq)]
+pub struct MacroTraceEvent {
+    /// The path of the macro, as written.
+    pub macro_path: String,
+    pub kind: MacroKind,
+    pub call_site: String,
+    /// The tokens passed to the macro: the arguments of a bang macro, or the arguments of an
+    /// attribute macro after its path, elided past `ExpansionConfig::print_token_limit`.
+    pub input: String,
+    /// The source text of the item an attribute or derive macro is applied to, see
+    /// `ExtCtxt::item_source_text`.
+    pub item: Option<String>,
+    /// The kind of AST fragment the output is parsed as.
+    pub fragment_kind: String,
+    /// The time spent expanding the invocation, in microseconds. Macro invocations in the
+    /// output are expanded later and recorded as events of their own.
+    pub duration_us: u64,
+    /// The ID of the expansion, see `ExpnId::as_u32`.
+    pub id: u32,
+    /// The expansion the invocation came from, unless it was written in the input crate.
+    pub parent: Option<MacroTraceParent>,
+}
+
+/// The traced expansion an invocation came from.
+#[derive(RustcEncodable, Clone, Copy, Debug, PartialEq)]
+pub struct MacroTraceParent {
+    pub id: u32,
+    /// Whether the parent expanded the invocation eagerly while it was expanded itself, e.g.,
+    /// `concat!` expanding the macro calls among its arguments, rather than producing it as
+    /// part of its output.
+    pub eager: bool,
+}
+
+/// The invocations expanded so far, in the order their expansion started.
+#[derive(Default)]
+pub struct MacroTrace {
+    pub events: Vec<MacroTraceEvent>,
+    /// The index of the event of each traced expansion.
+    indices: FxHashMap<ExpnId, usize>,
+    /// Events whose expansion hasn't finished yet, with the time it started.
+    pending: FxHashMap<ExpnId, (usize, Instant)>,
+}
+
+impl MacroTrace {
+    pub fn to_json(&self) -> String {
+        json::as_json(&self.events).to_string()
+    }
+
+    /// Renders the invocations as a graph in the DOT format, with an edge from each invocation
+    /// to the ones it produced. Edges to invocations expanded eagerly are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph expansions {\n");
+        for event in &self.events {
+            let label = format!("{} `{}`\n{}", event.kind.descr(), event.macro_path,
+                                event.call_site);
+            writeln!(dot, "    n{} [label={:?}];", event.id, label).unwrap();
+        }
+        for event in &self.events {
+            if let Some(parent) = event.parent {
+                let style = if parent.eager { "dashed" } else { "solid" };
+                writeln!(dot, "    n{} -> n{} [style={}];", parent.id, event.id, style).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Records the start of the expansion of `invoc`, if tracing is enabled. `eager_root` is the
+/// expansion that expands `invoc` eagerly, if any, see `MacroExpander::fully_expand_fragment`.
+crate fn start(cx: &mut ExtCtxt<'_>, invoc: &Invocation, eager_root: Option<ExpnId>) {
+    if !cx.ecfg.trace_json {
+        return;
+    }
+    let max_tokens = cx.ecfg.print_token_limit;
+    let (macro_path, kind, input, item) = match &invoc.kind {
+        InvocationKind::Bang { mac, .. } => {
+            let input = pprust::tts_to_string_truncated(mac.stream(), max_tokens);
+            (mac.path.to_string(), MacroKind::Bang, input, None)
+        }
+        InvocationKind::Attr { attr, item, .. } => {
+            let input = pprust::tts_to_string_truncated(attr.args.outer_tokens(), max_tokens);
+            (attr.path.to_string(), MacroKind::Attr, input, Some(item))
+        }
+        InvocationKind::Derive { path, item } => {
+            (path.to_string(), MacroKind::Derive, String::new(), Some(item))
+        }
+        // The derives of a container are traced as separate invocations.
+        InvocationKind::DeriveContainer { .. } => return,
+    };
+    let expn_id = invoc.expansion_data.id;
+    let trace = &cx.macro_trace;
+    // Skip the expansions that aren't traced, e.g., the ones created by
+    // `ExtCtxt::with_suppressed_warnings`.
+    let mut parent = expn_id.expn_data().parent;
+    while parent != ExpnId::root() && !trace.indices.contains_key(&parent) {
+        parent = parent.expn_data().parent;
+    }
+    let parent = if parent == ExpnId::root() {
+        None
+    } else {
+        Some(MacroTraceParent { id: parent.as_u32(), eager: Some(parent) == eager_root })
+    };
+    let event = MacroTraceEvent {
+        macro_path,
+        kind,
+        call_site: cx.source_map().span_to_string(invoc.span()),
+        input,
+        item: item.and_then(|item| cx.item_source_text(item)).map(|(text, ..)| text),
+        fragment_kind: invoc.fragment_kind().name().to_string(),
+        duration_us: 0,
+        id: expn_id.as_u32(),
+        parent,
+    };
+    let trace = &mut cx.macro_trace;
+    trace.indices.insert(expn_id, trace.events.len());
+    trace.pending.insert(expn_id, (trace.events.len(), Instant::now()));
+    trace.events.push(event);
+}
+
+/// Records the end of the expansion `expn_id`, if its start was recorded.
+crate fn finish(cx: &mut ExtCtxt<'_>, expn_id: ExpnId) {
+    if let Some((index, started)) = cx.macro_trace.pending.remove(&expn_id) {
+        let elapsed = started.elapsed();
+        cx.macro_trace.events[index].duration_us =
+            elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros());
+    }
+}
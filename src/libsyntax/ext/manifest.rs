@@ -0,0 +1,68 @@
+//! Serializable summaries of the macros defined by a crate.
+//!
+//! A `MacroManifest` is produced from the `SyntaxExtension`s of a compilation session and can
+//! be stored as JSON, so that tools like documentation generators or IDEs can enumerate the
+//! macros of a dependency without decoding its crate metadata.
+
+use crate::attr::{Deprecation, Stability};
+use crate::edition::Edition;
+use crate::ext::base::{MacroKind, SyntaxExtension, SyntaxExtensionKind};
+use crate::symbol::Symbol;
+
+use rustc_serialize::json;
+
+#[cfg(test)]
+mod tests;
+
+/// The macros defined by a crate.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct MacroManifest {
+    pub crate_name: String,
+    pub macros: Vec<MacroSummary>,
+}
+
+/// What a `MacroManifest` records about a single macro.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct MacroSummary {
+    pub name: Symbol,
+    pub kind: MacroKind,
+    /// Names of helper attributes registered by the macro.
+    pub helper_attrs: Vec<Symbol>,
+    pub stability: Option<Stability>,
+    pub deprecation: Option<Deprecation>,
+    /// Edition of the crate in which the macro is defined.
+    pub edition: Edition,
+    /// The matchers of the arms of a `macro_rules!` macro, as written.
+    pub matchers: Vec<String>,
+}
+
+impl MacroManifest {
+    pub fn new(crate_name: String) -> MacroManifest {
+        MacroManifest { crate_name, macros: Vec::new() }
+    }
+
+    /// Records the macro called `name` that is defined by `ext`.
+    pub fn add(&mut self, name: Symbol, ext: &SyntaxExtension) {
+        let matchers = match ext.kind {
+            SyntaxExtensionKind::LegacyBang(ref expander) => expander.matchers(),
+            _ => Vec::new(),
+        };
+        self.macros.push(MacroSummary {
+            name,
+            kind: ext.macro_kind(),
+            helper_attrs: ext.helper_attrs.clone(),
+            stability: ext.stability,
+            deprecation: ext.deprecation.clone(),
+            edition: ext.edition,
+            matchers,
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        json::as_json(self).to_string()
+    }
+
+    pub fn from_json(s: &str) -> Result<MacroManifest, json::DecoderError> {
+        json::decode(s)
+    }
+}
@@ -0,0 +1,25 @@
+use super::*;
+
+use crate::ext::tt::macro_rules;
+use crate::feature_gate::Features;
+use crate::parse::ParseSess;
+use crate::source_map::FilePathMapping;
+use crate::tests::with_error_checking_parse;
+use crate::with_default_globals;
+
+#[test]
+fn manifest_round_trips_through_json() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let source = "macro_rules! m { ($e:expr) => { $e }; () => { 0 } }".to_string();
+        let item = with_error_checking_parse(source, &ps, |p| p.parse_item()).unwrap();
+        let ext = macro_rules::compile(&ps, &Features::new(), &item, Edition::Edition2018);
+
+        let mut manifest = MacroManifest::new("krate".to_string());
+        manifest.add(item.ident.name, &ext);
+        assert_eq!(manifest.macros[0].kind, MacroKind::Bang);
+        assert_eq!(manifest.macros[0].matchers.len(), 2);
+
+        assert_eq!(MacroManifest::from_json(&manifest.to_json()).unwrap(), manifest);
+    })
+}
@@ -1,5 +1,6 @@
 use crate::ast::{self, NodeId};
-use crate::source_map::{DUMMY_SP, dummy_spanned};
+use crate::attr::HasAttrs;
+use crate::source_map::{DUMMY_SP, Span, dummy_spanned};
 use crate::ext::base::ExtCtxt;
 use crate::ext::expand::{AstFragment, AstFragmentKind};
 use crate::tokenstream::TokenStream;
@@ -12,61 +13,80 @@ use smallvec::{smallvec, SmallVec};
 use rustc_data_structures::fx::FxHashMap;
 
 pub fn placeholder(kind: AstFragmentKind, id: ast::NodeId) -> AstFragment {
-    fn mac_placeholder() -> ast::Mac {
-        ast::Mac {
-            path: ast::Path { span: DUMMY_SP, segments: Vec::new() },
-            tts: TokenStream::empty().into(),
-            delim: ast::MacDelimiter::Brace,
-            span: DUMMY_SP,
-            prior_type_ascription: None,
-        }
-    }
+    let mac = ast::Mac {
+        path: ast::Path { span: DUMMY_SP, segments: Vec::new() },
+        tts: TokenStream::empty().into(),
+        delim: ast::MacDelimiter::Brace,
+        span: DUMMY_SP,
+        prior_type_ascription: None,
+    };
+    mac_fragment(kind, id, mac, DUMMY_SP)
+}
+
+/// Builds a fragment consisting of the macro invocation `mac` alone, used to keep invocations
+/// that aren't expanded in the AST.
+pub fn unexpanded(kind: AstFragmentKind, mac: ast::Mac, span: Span) -> AstFragment {
+    mac_fragment(kind, ast::DUMMY_NODE_ID, mac, span)
+}
 
+fn mac_fragment(kind: AstFragmentKind, id: ast::NodeId, mac: ast::Mac, span: Span)
+                -> AstFragment {
     let ident = ast::Ident::invalid();
     let attrs = Vec::new();
     let generics = ast::Generics::default();
     let vis = dummy_spanned(ast::VisibilityKind::Inherited);
-    let span = DUMMY_SP;
-    let expr_placeholder = || P(ast::Expr {
+    let expr = |mac| P(ast::Expr {
         id, span,
         attrs: ThinVec::new(),
-        node: ast::ExprKind::Mac(mac_placeholder()),
+        node: ast::ExprKind::Mac(mac),
     });
 
     match kind {
-        AstFragmentKind::Expr => AstFragment::Expr(expr_placeholder()),
-        AstFragmentKind::OptExpr => AstFragment::OptExpr(Some(expr_placeholder())),
+        AstFragmentKind::Expr => AstFragment::Expr(expr(mac)),
+        AstFragmentKind::OptExpr => AstFragment::OptExpr(Some(expr(mac))),
         AstFragmentKind::Items => AstFragment::Items(smallvec![P(ast::Item {
             id, span, ident, vis, attrs,
-            node: ast::ItemKind::Mac(mac_placeholder()),
+            node: ast::ItemKind::Mac(mac),
             tokens: None,
         })]),
         AstFragmentKind::TraitItems => AstFragment::TraitItems(smallvec![ast::TraitItem {
             id, span, ident, attrs, generics,
-            node: ast::TraitItemKind::Macro(mac_placeholder()),
+            node: ast::TraitItemKind::Macro(mac),
             tokens: None,
         }]),
         AstFragmentKind::ImplItems => AstFragment::ImplItems(smallvec![ast::ImplItem {
             id, span, ident, vis, attrs, generics,
-            node: ast::ImplItemKind::Macro(mac_placeholder()),
+            node: ast::ImplItemKind::Macro(mac),
             defaultness: ast::Defaultness::Final,
             tokens: None,
         }]),
         AstFragmentKind::ForeignItems =>
             AstFragment::ForeignItems(smallvec![ast::ForeignItem {
                 id, span, ident, vis, attrs,
-                node: ast::ForeignItemKind::Macro(mac_placeholder()),
+                node: ast::ForeignItemKind::Macro(mac),
             }]),
         AstFragmentKind::Pat => AstFragment::Pat(P(ast::Pat {
-            id, span, node: ast::PatKind::Mac(mac_placeholder()),
+            id, span, node: ast::PatKind::Mac(mac),
         })),
         AstFragmentKind::Ty => AstFragment::Ty(P(ast::Ty {
-            id, span, node: ast::TyKind::Mac(mac_placeholder()),
+            id, span, node: ast::TyKind::Mac(mac),
         })),
         AstFragmentKind::Stmts => AstFragment::Stmts(smallvec![{
-            let mac = P((mac_placeholder(), ast::MacStmtStyle::Braces, ThinVec::new()));
+            let mac = P((mac, ast::MacStmtStyle::Braces, ThinVec::new()));
             ast::Stmt { id, span, node: ast::StmtKind::Mac(mac) }
         }]),
+        AstFragmentKind::Arms => AstFragment::Arms(smallvec![ast::Arm {
+            id, span, attrs,
+            pats: Vec::new(),
+            guard: None,
+            body: expr(mac),
+        }]),
+        AstFragmentKind::Params => AstFragment::Params(smallvec![ast::Param {
+            id, span,
+            attrs: ThinVec::new(),
+            pat: P(ast::Pat { id, span, node: ast::PatKind::Mac(mac) }),
+            ty: P(ast::Ty { id, span, node: ast::TyKind::Infer }),
+        }]),
     }
 }
 
@@ -99,12 +119,21 @@ impl<'a, 'b> PlaceholderExpander<'a, 'b> {
         self.expanded_fragments.insert(id, fragment);
     }
 
+    /// Like `add`, but `fragment` is inserted as is, even if it contains macro invocations.
+    pub fn add_unexpanded(&mut self, id: ast::NodeId, fragment: AstFragment) {
+        self.expanded_fragments.insert(id, fragment);
+    }
+
     fn remove(&mut self, id: ast::NodeId) -> AstFragment {
         self.expanded_fragments.remove(&id).unwrap()
     }
 }
 
 impl<'a, 'b> MutVisitor for PlaceholderExpander<'a, 'b> {
+    // The placeholders of the macro calls `InvocationCollector` collects from interpolated
+    // nonterminals have to be replaced there too.
+    const VISIT_INTERPOLATED: bool = true;
+
     fn flat_map_item(&mut self, item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
         match item.node {
             ast::ItemKind::Mac(_) => return self.remove(item.id).make_items(),
@@ -136,6 +165,31 @@ impl<'a, 'b> MutVisitor for PlaceholderExpander<'a, 'b> {
         }
     }
 
+    fn flat_map_arm(&mut self, arm: ast::Arm) -> SmallVec<[ast::Arm; 1]> {
+        match arm.mac() {
+            Some(_) => self.remove(arm.id).make_arms(),
+            None => noop_flat_map_arm(arm, self),
+        }
+    }
+
+    fn flat_map_param(&mut self, param: ast::Param) -> SmallVec<[ast::Param; 1]> {
+        match param.mac() {
+            Some(_) => {
+                // The attributes of the invocation apply to each parameter it expanded to.
+                let mut params = self.remove(param.id).make_params();
+                if !param.attrs.is_empty() {
+                    for expanded in &mut params {
+                        expanded.attrs.visit_attrs(|attrs| {
+                            attrs.splice(0..0, param.attrs.iter().cloned());
+                        });
+                    }
+                }
+                params
+            }
+            None => noop_flat_map_param(param, self),
+        }
+    }
+
     fn visit_expr(&mut self, expr: &mut P<ast::Expr>) {
         match expr.node {
             ast::ExprKind::Mac(_) => *expr = self.remove(expr.id).make_expr(),
@@ -193,7 +247,8 @@ impl<'a, 'b> MutVisitor for PlaceholderExpander<'a, 'b> {
     fn visit_mod(&mut self, module: &mut ast::Mod) {
         noop_visit_mod(module, self);
         module.items.retain(|item| match item.node {
-            ast::ItemKind::Mac(_) if !self.cx.ecfg.keep_macs => false, // remove macro definitions
+            // remove macro definitions
+            ast::ItemKind::Mac(_) => self.cx.ecfg.keep_macs || self.cx.ecfg.expands_selectively(),
             _ => true,
         });
     }
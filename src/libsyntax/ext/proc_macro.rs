@@ -1,17 +1,18 @@
 use crate::ast::{self, ItemKind, Attribute, Mac};
-use crate::attr::{mark_used, mark_known};
-use crate::errors::{Applicability, FatalError};
+use crate::attr::{self, mark_used, mark_known};
+use crate::errors::{DiagnosticBuilder, FatalError};
 use crate::ext::base::{self, *};
 use crate::ext::proc_macro_server;
 use crate::parse::{self, token};
-use crate::parse::parser::PathStyle;
 use crate::symbol::sym;
 use crate::tokenstream::{self, TokenStream};
-use crate::visit::Visitor;
+use crate::visit::{self, Visitor};
 
 use rustc_data_structures::sync::Lrc;
 use syntax_pos::{Span, DUMMY_SP};
 
+use std::mem;
+
 const EXEC_STRATEGY: proc_macro::bridge::server::SameThread =
     proc_macro::bridge::server::SameThread;
 
@@ -93,7 +94,12 @@ impl MultiItemModifier for ProcMacroDerive {
             Annotatable::TraitItem(_) |
             Annotatable::ForeignItem(_) |
             Annotatable::Stmt(_) |
-            Annotatable::Expr(_) => {
+            Annotatable::Expr(_) |
+            Annotatable::Crate(_) |
+            Annotatable::Field(_) |
+            Annotatable::Variant(_) |
+            Annotatable::Param(_) |
+            Annotatable::GenericParam(_) => {
                 ecx.span_err(span, "proc-macro derives may only be \
                                     applied to a struct, enum, or union");
                 return Vec::new()
@@ -160,16 +166,112 @@ impl MultiItemModifier for ProcMacroDerive {
     }
 }
 
-crate struct MarkAttrs<'a>(crate &'a [ast::Name]);
+/// A helper attribute registered by one of the derives applied to an item.
+crate struct DeriveHelper {
+    crate name: ast::Name,
+    /// Where the helper is used, `None` if it's used anywhere in the item.
+    crate scope: Option<HelperAttrScope>,
+    /// The derive registering the helper.
+    crate derive: ast::Path,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HelperAttrPosition {
+    Item,
+    Field,
+    Variant,
+    Other,
+}
+
+/// Marks the helper attributes of the derives applied to an item as known and used, except for
+/// the ones used outside of the scope declared by their derives, which are collected instead.
+crate struct MarkAttrs<'a> {
+    helpers: &'a [DeriveHelper],
+    /// The helpers used in their scope, which the derives consume.
+    crate consumed: Vec<ast::AttrId>,
+    /// The spans of the helpers used outside of their scope, with notes on where they're used.
+    crate misplaced: Vec<(Span, Vec<String>)>,
+    position: HelperAttrPosition,
+    /// Whether the derived item is being visited, as opposed to an item nested in it.
+    in_item: bool,
+}
+
+impl<'a> MarkAttrs<'a> {
+    crate fn new(helpers: &'a [DeriveHelper]) -> Self {
+        MarkAttrs {
+            helpers,
+            consumed: Vec::new(),
+            misplaced: Vec::new(),
+            position: HelperAttrPosition::Item,
+            in_item: false,
+        }
+    }
+
+    fn with_position(&mut self, position: HelperAttrPosition, f: impl FnOnce(&mut Self)) {
+        let old_position = mem::replace(&mut self.position, position);
+        f(self);
+        self.position = old_position;
+    }
+
+    fn in_scope(&self, scope: Option<HelperAttrScope>) -> bool {
+        scope.map_or(true, |scope| match self.position {
+            HelperAttrPosition::Item => scope.item,
+            HelperAttrPosition::Field => scope.fields,
+            HelperAttrPosition::Variant => scope.variants,
+            HelperAttrPosition::Other => false,
+        })
+    }
+}
+
+impl<'a> Visitor<'a> for MarkAttrs<'_> {
+    fn visit_item(&mut self, item: &'a ast::Item) {
+        if self.in_item {
+            self.with_position(HelperAttrPosition::Other, |this| visit::walk_item(this, item));
+        } else {
+            self.in_item = true;
+            visit::walk_item(self, item);
+        }
+    }
+
+    fn visit_struct_field(&mut self, field: &'a ast::StructField) {
+        self.with_position(HelperAttrPosition::Field, |this| visit::walk_struct_field(this, field));
+    }
+
+    fn visit_variant(&mut self, variant: &'a ast::Variant) {
+        self.with_position(HelperAttrPosition::Variant, |this| visit::walk_variant(this, variant));
+    }
+
+    fn visit_generic_param(&mut self, param: &'a ast::GenericParam) {
+        self.with_position(HelperAttrPosition::Other, |this| {
+            visit::walk_generic_param(this, param)
+        });
+    }
+
+    fn visit_anon_const(&mut self, constant: &'a ast::AnonConst) {
+        self.with_position(HelperAttrPosition::Other, |this| {
+            visit::walk_anon_const(this, constant)
+        });
+    }
 
-impl<'a> Visitor<'a> for MarkAttrs<'a> {
     fn visit_attribute(&mut self, attr: &Attribute) {
-        if let Some(ident) = attr.ident() {
-            if self.0.contains(&ident.name) {
-                mark_used(attr);
-                mark_known(attr);
-            }
+        let name = match attr.ident() {
+            Some(ident) => ident.name,
+            None => return,
+        };
+        let helpers: Vec<_> = self.helpers.iter().filter(|helper| helper.name == name).collect();
+        if helpers.is_empty() {
+            return;
         }
+        if helpers.iter().any(|helper| self.in_scope(helper.scope)) {
+            mark_used(attr);
+            mark_known(attr);
+            self.consumed.push(attr.id);
+            return;
+        }
+        let notes = helpers.iter().filter_map(|helper| helper.scope.map(|scope| {
+            format!("`#[derive({})]` only accepts `{}` on {}", helper.derive, name, scope.descr())
+        })).collect();
+        self.misplaced.push((attr.span, notes));
     }
 
     fn visit_mac(&mut self, _mac: &Mac) {}
@@ -186,25 +288,14 @@ crate fn collect_derives(cx: &mut ExtCtxt<'_>, attrs: &mut Vec<ast::Attribute>)
         if attr.path != sym::derive {
             return true;
         }
-        if !attr.is_meta_item_list() {
-            cx.struct_span_err(attr.span, "malformed `derive` attribute input")
-                .span_suggestion(
-                    attr.span,
-                    "missing traits to be derived",
-                    "#[derive(Trait1, Trait2, ...)]".to_owned(),
-                    Applicability::HasPlaceholders,
-                ).emit();
-            return false;
-        }
-
-        match attr.parse_list(cx.parse_sess,
-                              |parser| parser.parse_path_allowing_meta(PathStyle::Mod)) {
+        match attr::parse_derives(cx.parse_sess, attr) {
             Ok(traits) => {
-                result.extend(traits);
+                result.extend(traits.into_iter().map(|(path, _)| path));
                 true
             }
-            Err(mut e) => {
-                e.emit();
+            Err(diagnostic) => {
+                let handler = &cx.parse_sess.span_diagnostic;
+                DiagnosticBuilder::new_diagnostic(handler, diagnostic).emit();
                 false
             }
         }
@@ -1,8 +1,9 @@
 use crate::ast;
 use crate::ext::base::ExtCtxt;
+use crate::ext::pure_macro::{self, PureExpansion, TrackedRead};
 use crate::parse::{self, token, ParseSess};
 use crate::parse::lexer::comments;
-use crate::tokenstream::{self, DelimSpan, IsJoint::*, TokenStream, TreeAndJoint};
+use crate::tokenstream::{self, DelimSpan, IsJoint::*, TokenInterner, TokenStream, TreeAndJoint};
 
 use errors::{Diagnostic, DiagnosticBuilder};
 use rustc_data_structures::sync::Lrc;
@@ -12,7 +13,10 @@ use syntax_pos::symbol::{kw, sym, Symbol};
 use proc_macro::{Delimiter, Level, LineColumn, Spacing};
 use proc_macro::bridge::{server, TokenTree};
 use std::{ascii, panic};
+use std::cell::RefCell;
+use std::env;
 use std::ops::Bound;
+use std::path::Path;
 
 trait FromInternal<T> {
     fn from_internal(x: T) -> Self;
@@ -355,6 +359,10 @@ pub(crate) struct Rustc<'a> {
     sess: &'a ParseSess,
     def_site: Span,
     call_site: Span,
+    /// The interner of `ExtCtxt`, if `ExpansionConfig::intern_token_streams` is set.
+    interner: Option<&'a RefCell<TokenInterner>>,
+    /// The expansion of the pure macro being expanded, see `ExtCtxt::pure_expansion`.
+    pure: Option<Lrc<PureExpansion>>,
 }
 
 impl<'a> Rustc<'a> {
@@ -364,6 +372,20 @@ impl<'a> Rustc<'a> {
             sess: cx.parse_sess,
             def_site: cx.with_def_site_ctxt(expn_data.def_site),
             call_site: cx.with_call_site_ctxt(expn_data.call_site),
+            interner: if cx.ecfg.intern_token_streams { Some(&cx.token_interner) } else { None },
+            pure: cx.pure_expansion.clone(),
+        }
+    }
+
+    fn track_read(&self, read: TrackedRead<'_>) {
+        let pure = self.pure.as_ref().map(|pure| &**pure);
+        pure_macro::track_read(self.sess, pure, read, self.call_site);
+    }
+
+    fn intern(&self, stream: TokenStream) -> TokenStream {
+        match self.interner {
+            Some(interner) => interner.borrow_mut().intern(stream),
+            None => stream,
         }
     }
 
@@ -397,12 +419,13 @@ impl server::TokenStream for Rustc<'_> {
         stream.is_empty()
     }
     fn from_str(&mut self, src: &str) -> Self::TokenStream {
-        parse::parse_stream_from_source_str(
+        let stream = parse::parse_stream_from_source_str(
             FileName::proc_macro_source_code(src),
             src.to_string(),
             self.sess,
             Some(self.call_site),
-        )
+        );
+        self.intern(stream)
     }
     fn to_string(&mut self, stream: &Self::TokenStream) -> String {
         stream.to_string()
@@ -429,7 +452,9 @@ impl server::TokenStreamBuilder for Rustc<'_> {
         builder.push(stream);
     }
     fn build(&mut self, builder: Self::TokenStreamBuilder) -> Self::TokenStream {
-        builder.build()
+        // Procedural macros often build the same groups over and over, e.g., the body of a
+        // method generated for every field of a struct.
+        self.intern(builder.build())
     }
 }
 
@@ -547,6 +572,9 @@ impl server::Literal for Rustc<'_> {
         for ch in string.chars() {
             escaped.extend(ch.escape_debug());
         }
+        if self.sess.literal_too_long(self.call_site, escaped.len(), token::Str) {
+            return self.lit(token::Err, kw::Invalid, None);
+        }
         self.lit(token::Str, Symbol::intern(&escaped), None)
     }
     fn character(&mut self, ch: char) -> Self::Literal {
@@ -561,6 +589,9 @@ impl server::Literal for Rustc<'_> {
             .flat_map(ascii::escape_default)
             .map(Into::<char>::into)
             .collect::<String>();
+        if self.sess.literal_too_long(self.call_site, string.len(), token::ByteStr) {
+            return self.lit(token::Err, kw::Invalid, None);
+        }
         self.lit(token::ByteStr, Symbol::intern(&string), None)
     }
     fn span(&mut self, literal: &Self::Literal) -> Self::Span {
@@ -623,6 +654,13 @@ impl server::SourceFile for Rustc<'_> {
     fn is_real(&mut self, file: &Self::SourceFile) -> bool {
         file.is_real_file()
     }
+    fn track_env_var(&mut self, var: &str) -> Option<String> {
+        self.track_read(TrackedRead::EnvVar(var));
+        env::var(var).ok()
+    }
+    fn track_path(&mut self, path: &str) {
+        self.track_read(TrackedRead::File(Path::new(path)));
+    }
 }
 
 impl server::MultiSpan for Rustc<'_> {
@@ -650,6 +688,9 @@ impl server::Diagnostic for Rustc<'_> {
         diag.sub(level.to_internal(), msg, MultiSpan::from_spans(spans), None);
     }
     fn emit(&mut self, diag: Self::Diagnostic) {
+        if let Some(pure) = &self.pure {
+            pure.note_diagnostic();
+        }
         DiagnosticBuilder::new_diagnostic(&self.sess.span_diagnostic, diag).emit()
     }
 }
@@ -703,4 +744,7 @@ impl server::Span for Rustc<'_> {
     fn source_text(&mut self,  span: Self::Span) -> Option<String> {
         self.sess.source_map().span_to_snippet(span).ok()
     }
+    fn leading_comments(&mut self, span: Self::Span) -> Vec<String> {
+        self.sess.leading_comments(span).into_iter().map(|comment| comment.text).collect()
+    }
 }
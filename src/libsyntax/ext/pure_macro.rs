@@ -0,0 +1,212 @@
+//! Pure macros, whose output only depends on their input.
+//!
+//! A macro declares itself pure with `#[rustc_pure_macro]`, see `SyntaxExtension::is_pure`: it
+//! doesn't read files or environment variables, and it expands the same input to the same
+//! output. The declaration is checked while the macro runs: a file or variable read through
+//! the tracked IO hooks (`ExtCtxt::tracked_env_var`, `ExtCtxt::track_file` and
+//! `proc_macro::tracked`) during its expansion, including the expansions it performs eagerly,
+//! is reported as an error pointing at the definition of the macro.
+//!
+//! Pure procedural bang macros have their output cached: invoking the macro again with the
+//! same tokens reuses the output of the first invocation, with its spans moved over to the new
+//! invocation, instead of running the macro. The cache holds a bounded number of outputs.
+//!
+//! Since a pure invocation doesn't depend on the invocations expanded before it, it doesn't
+//! have to be expanded in order: `MacroExpander` resolves pure bang invocations as usual, but
+//! expands them only once the other invocations queued with them have been expanded. Impure
+//! invocations keep their order relative to each other, and so do pure ones.
+
+use crate::ext::base::{ExtCtxt, SyntaxExtension};
+use crate::ext::hygiene::ExpnId;
+use crate::ext::tt::match_cache::{input_hash, preorder};
+use crate::mut_visit::{self, MutVisitor};
+use crate::parse::ParseSess;
+use crate::parse::token::{self, Token};
+use crate::tokenstream::{TokenStream, TokenTree};
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::sync::Lrc;
+use syntax_pos::Span;
+use syntax_pos::symbol::Symbol;
+
+use std::cell::Cell;
+use std::path::Path;
+
+/// The expansion of a pure macro that is in progress, see `ExtCtxt::pure_expansion`.
+pub struct PureExpansion {
+    /// The name of the macro, as invoked.
+    name: Symbol,
+    def_span: Span,
+    /// Whether the expansion emitted diagnostics or read anything, which keeps its output out
+    /// of the cache.
+    uncacheable: Cell<bool>,
+}
+
+impl PureExpansion {
+    crate fn new(expn_id: ExpnId, ext: &SyntaxExtension) -> PureExpansion {
+        PureExpansion {
+            name: expn_id.expn_data().kind.descr(),
+            def_span: ext.span,
+            uncacheable: Cell::new(false),
+        }
+    }
+
+    /// Records that the expansion emitted a diagnostic, which a cached output wouldn't repeat.
+    crate fn note_diagnostic(&self) {
+        self.uncacheable.set(true);
+    }
+}
+
+/// A read of the environment or of a file through one of the tracked IO hooks.
+pub enum TrackedRead<'a> {
+    EnvVar(&'a str),
+    File(&'a Path),
+}
+
+/// Reports `read`, made at `span`, if it's made during the expansion of a pure macro.
+crate fn track_read(sess: &ParseSess, pure: Option<&PureExpansion>, read: TrackedRead<'_>,
+                    span: Span) {
+    let pure = match pure {
+        Some(pure) => pure,
+        None => return,
+    };
+    pure.uncacheable.set(true);
+    let read = match read {
+        TrackedRead::EnvVar(var) => format!("the environment variable `{}`", var),
+        TrackedRead::File(path) => format!("the file `{}`", path.display()),
+    };
+    sess.span_diagnostic
+        .struct_span_err(span, &format!("pure macro `{}` reads {}", pure.name, read))
+        .span_note(pure.def_span, "the macro is declared pure here")
+        .emit();
+}
+
+/// The number of outputs `PureOutputCache` holds at most.
+const MAX_OUTPUTS: usize = 4096;
+
+/// The outputs of the pure procedural bang macros expanded so far, up to `MAX_OUTPUTS`.
+#[derive(Default)]
+pub struct PureOutputCache {
+    /// The outputs, keyed by a hash of the input tokens, see `match_cache::input_hash`.
+    entries: FxHashMap<u64, Vec<CachedOutput>>,
+    len: usize,
+}
+
+struct CachedOutput {
+    /// Keeps the macro alive, so that it can be compared by address.
+    ext: Lrc<SyntaxExtension>,
+    input: TokenStream,
+    /// The spans the macro saw as `Span::call_site()` and `Span::def_site()`.
+    call_site: Span,
+    def_site: Span,
+    output: TokenStream,
+}
+
+/// Returns the output of an earlier invocation of `ext` with the same tokens as `input`, with
+/// the spans of that invocation replaced by the corresponding ones of the current invocation.
+crate fn cached_output(cx: &ExtCtxt<'_>, ext: &Lrc<SyntaxExtension>, input: &TokenStream)
+                       -> Option<TokenStream> {
+    if !ext.is_pure || !cx.ecfg.pure_macro_cache {
+        return None;
+    }
+    let (call_site, def_site) = proc_macro_sites(cx);
+    let entries = cx.pure_output_cache.entries.get(&input_hash(input)?)?;
+    entries.iter()
+        .filter(|entry| Lrc::ptr_eq(&entry.ext, ext) && entry.input.eq_unspanned(input))
+        .find_map(|entry| {
+            let mut spans = input_spans(&entry.input, input)?;
+            spans.insert(entry.call_site, call_site);
+            spans.insert(entry.def_site, def_site);
+            remap_spans(&entry.output, &spans)
+        })
+}
+
+/// Remembers the output of the invocation of `ext` being expanded, if `ext` is pure, the
+/// invocation didn't read anything or emit diagnostics and the cache isn't full. Outputs whose
+/// spans don't all come from the input, the call site or the definition site can't be moved to
+/// another invocation, so they aren't cached.
+crate fn cache_output(cx: &mut ExtCtxt<'_>, ext: &Lrc<SyntaxExtension>, input: TokenStream,
+                      output: &TokenStream) {
+    if !ext.is_pure || !cx.ecfg.pure_macro_cache || cx.pure_output_cache.len >= MAX_OUTPUTS {
+        return;
+    }
+    if cx.pure_expansion.as_ref().map_or(true, |pure| pure.uncacheable.get()) {
+        return;
+    }
+    let hash = match input_hash(&input) {
+        Some(hash) => hash,
+        None => return,
+    };
+    let (call_site, def_site) = proc_macro_sites(cx);
+    let mut spans = match input_spans(&input, &input) {
+        Some(spans) => spans,
+        None => return,
+    };
+    spans.insert(call_site, call_site);
+    spans.insert(def_site, def_site);
+    if remap_spans(output, &spans).is_none() {
+        return;
+    }
+
+    let output = output.clone();
+    let entry = CachedOutput { ext: ext.clone(), input, call_site, def_site, output };
+    cx.pure_output_cache.len += 1;
+    cx.pure_output_cache.entries.entry(hash).or_default().push(entry);
+}
+
+/// The spans of the current invocation that a procedural macro sees as `Span::call_site()` and
+/// `Span::def_site()`, see `proc_macro_server::Rustc::new`.
+fn proc_macro_sites(cx: &ExtCtxt<'_>) -> (Span, Span) {
+    let expn_data = cx.current_expansion.id.expn_data();
+    (cx.with_call_site_ctxt(expn_data.call_site), cx.with_def_site_ctxt(expn_data.def_site))
+}
+
+/// Maps the spans of the trees of `old` to the spans of the corresponding trees of `new`, which
+/// must have the same tokens. Returns `None` if a span of `old` would map to two different
+/// spans, e.g. because the same span was given to several tokens.
+fn input_spans(old: &TokenStream, new: &TokenStream) -> Option<FxHashMap<Span, Span>> {
+    let mut spans = FxHashMap::default();
+    let mut insert = |old: Span, new: Span| *spans.entry(old).or_insert(new) == new;
+    for (old, new) in preorder(old).iter().zip(&preorder(new)) {
+        let consistent = match (old, new) {
+            (TokenTree::Delimited(old, ..), TokenTree::Delimited(new, ..)) => {
+                insert(old.open, new.open) && insert(old.close, new.close)
+            }
+            (old, new) => insert(old.span(), new.span()),
+        };
+        if !consistent {
+            return None;
+        }
+    }
+    Some(spans)
+}
+
+/// Replaces the spans of `stream` according to `spans`. Returns `None` if a span of `stream`
+/// isn't mapped or the stream contains interpolated tokens, whose spans aren't visited.
+fn remap_spans(stream: &TokenStream, spans: &FxHashMap<Span, Span>) -> Option<TokenStream> {
+    struct SpanRemapper<'a> {
+        spans: &'a FxHashMap<Span, Span>,
+        complete: bool,
+    }
+
+    impl MutVisitor for SpanRemapper<'_> {
+        fn visit_token(&mut self, token: &mut Token) {
+            if let token::Interpolated(..) = token.kind {
+                self.complete = false;
+            }
+            mut_visit::noop_visit_token(token, self);
+        }
+
+        fn visit_span(&mut self, span: &mut Span) {
+            match self.spans.get(span) {
+                Some(&new) => *span = new,
+                None => self.complete = false,
+            }
+        }
+    }
+
+    let mut stream = stream.clone();
+    let mut remapper = SpanRemapper { spans, complete: true };
+    remapper.visit_tts(&mut stream);
+    if remapper.complete { Some(stream) } else { None }
+}
@@ -0,0 +1,201 @@
+//! Detects `macro_rules!` matchers whose repetitions can match the same input in exponentially
+//! many ways.
+//!
+//! The macro parser never commits to a single parse of a repetition: whenever a token could
+//! either continue a repetition or end it, both alternatives are kept alive until the input
+//! decides between them. This is cheap for most matchers, but when a repetition ends with a
+//! nested repetition and both of them are continued by the same token, every occurrence of that
+//! token doubles the number of ways the input can be split between the two:
+//!
+//! ```
+//! macro_rules! foo {
+//!     ($($(x),+),+ ;) => {};
+//!     ($($t:tt)*) => {};
+//! }
+//! foo!(x, x, x, x);
+//! ```
+//!
+//! Here the first arm can group `x, x, x, x` as `[x, x, x, x]`, `[x], [x, x, x]`,
+//! `[x, x], [x], [x]` and so on, eight ways in total. None of them is followed by `;`, so the
+//! arm fails and the second one is tried, but only after the parser has tracked all of them.
+//! An input that ends up matching in several ways is rejected as ambiguous, so the cost shows
+//! in the arms that fail, which can take a surprisingly long time on long inputs.
+//!
+//! The check is opt-in through the `macro_backtracking` lint, which is allowed by default.
+
+use crate::ast::NodeId;
+use crate::early_buffered_lints::BufferedEarlyLintId;
+use crate::ext::tt::quoted::{KleeneOp, SequenceRepetition, TokenTree};
+use crate::parse::token;
+use crate::parse::ParseSess;
+use crate::print::pprust;
+use crate::symbol::{sym, Symbol};
+
+use syntax_pos::{MultiSpan, Span};
+
+/// The number of repetitions used by the largest example attached to a report.
+const MAX_EXAMPLE_LEN: usize = 4;
+
+/// Checks every arm of a `macro_rules!` definition and buffers a `macro_backtracking` lint for
+/// each pair of nested repetitions that can match the same input in many ways.
+pub fn check_matchers(sess: &ParseSess, node_id: NodeId, lhses: &[TokenTree]) {
+    for lhs in lhses {
+        check_tts(sess, node_id, lhs.span(), lhs_tts(lhs));
+    }
+}
+
+fn lhs_tts(lhs: &TokenTree) -> &[TokenTree] {
+    match *lhs {
+        TokenTree::Delimited(_, ref delimited) => &delimited.tts,
+        _ => std::slice::from_ref(lhs),
+    }
+}
+
+fn check_tts(sess: &ParseSess, node_id: NodeId, arm_span: Span, tts: &[TokenTree]) {
+    for tt in tts {
+        match *tt {
+            TokenTree::Delimited(_, ref delimited) => {
+                check_tts(sess, node_id, arm_span, &delimited.tts);
+            }
+            TokenTree::Sequence(span, ref outer) => {
+                if let Some(TokenTree::Sequence(inner_span, inner)) = outer.tts.last() {
+                    if let Some(continuation) = shared_continuation(outer, inner) {
+                        report(sess, node_id, arm_span, span.entire(), inner_span.entire(),
+                               inner, &continuation);
+                    }
+                }
+                check_tts(sess, node_id, arm_span, &outer.tts);
+            }
+            TokenTree::Token(..) | TokenTree::MetaVar(..) | TokenTree::MetaVarDecl(..) => {}
+        }
+    }
+}
+
+/// The token that may follow an iteration of a repetition to start the next one: its
+/// separator if there is one, or the first token of its body otherwise.
+fn continuation(seq: &SequenceRepetition) -> Option<TokenTree> {
+    if seq.kleene.op == KleeneOp::ZeroOrOne {
+        return None;
+    }
+    match seq.separator {
+        Some(ref sep) => Some(TokenTree::Token(sep.clone())),
+        None => match seq.tts.first() {
+            Some(TokenTree::Delimited(span, delimited)) => Some(delimited.open_tt(span.open)),
+            Some(tt @ TokenTree::Token(..)) | Some(tt @ TokenTree::MetaVarDecl(..)) => {
+                Some(tt.clone())
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Returns the continuation of `inner` if it can also continue `outer`, that is, if the parser
+/// can't tell from the next token whether `inner` goes on or a new iteration of `outer` starts.
+fn shared_continuation(
+    outer: &SequenceRepetition,
+    inner: &SequenceRepetition,
+) -> Option<TokenTree> {
+    let outer_continuation = continuation(outer)?;
+    let inner_continuation = continuation(inner)?;
+    if may_overlap(&outer_continuation, &inner_continuation) {
+        Some(inner_continuation)
+    } else {
+        None
+    }
+}
+
+fn may_overlap(a: &TokenTree, b: &TokenTree) -> bool {
+    match (a, b) {
+        (TokenTree::Token(a), TokenTree::Token(b)) => a.kind == b.kind,
+        (TokenTree::MetaVarDecl(_, _, frag), _) | (_, TokenTree::MetaVarDecl(_, _, frag))
+            if frag.name == sym::tt => true,
+        (TokenTree::MetaVarDecl(_, _, a), TokenTree::MetaVarDecl(_, _, b)) => a.name == b.name,
+        (TokenTree::MetaVarDecl(_, _, frag), TokenTree::Token(token))
+        | (TokenTree::Token(token), TokenTree::MetaVarDecl(_, _, frag)) => {
+            frag.name == sym::ident && token.is_ident()
+        }
+        _ => false,
+    }
+}
+
+fn report(
+    sess: &ParseSess,
+    node_id: NodeId,
+    arm_span: Span,
+    outer_span: Span,
+    inner_span: Span,
+    inner: &SequenceRepetition,
+    continuation: &TokenTree,
+) {
+    let mut span = MultiSpan::from_span(arm_span);
+    span.push_span_label(outer_span, "this repetition...".into());
+    span.push_span_label(
+        inner_span,
+        "...ends with a repetition that is continued by the same token".into(),
+    );
+
+    let mut notes = vec![format!(
+        "after each iteration of the inner repetition, `{}` may either continue it or start a \
+         new iteration of the outer one, so the macro parser has to track every way of \
+         splitting the input between them",
+        example_tt(continuation),
+    )];
+    let sample = example_tts(&inner.tts);
+    let joiner = match inner.separator {
+        Some(ref sep) => format!("{} ", pprust::token_to_string(sep)),
+        None => " ".to_string(),
+    };
+    for len in 2..=MAX_EXAMPLE_LEN {
+        let input = vec![sample.as_str(); len].join(&joiner);
+        notes.push(format!("`{}` can be matched in {} ways", input, 1 << (len - 1)));
+    }
+    notes.push("each additional repetition doubles the work needed to match an invocation"
+        .to_string());
+
+    sess.buffer_lint_with_notes(
+        BufferedEarlyLintId::MacroBacktracking,
+        span,
+        node_id,
+        "nested repetitions can match the same input in exponentially many ways",
+        notes,
+    );
+}
+
+/// Renders an input that matches `tts`, taking a single iteration of every repetition.
+fn example_tts(tts: &[TokenTree]) -> String {
+    tts.iter().map(example_tt).collect::<Vec<_>>().join(" ")
+}
+
+fn example_tt(tt: &TokenTree) -> String {
+    match *tt {
+        TokenTree::Token(ref token) => pprust::token_to_string(token),
+        TokenTree::Delimited(_, ref delimited) => {
+            let inner = example_tts(&delimited.tts);
+            match delimited.delim {
+                token::NoDelim => inner,
+                delim => format!(
+                    "{}{}{}",
+                    pprust::token_kind_to_string(&token::OpenDelim(delim)),
+                    inner,
+                    pprust::token_kind_to_string(&token::CloseDelim(delim)),
+                ),
+            }
+        }
+        TokenTree::Sequence(_, ref seq) => example_tts(&seq.tts),
+        TokenTree::MetaVar(_, name) => format!("${}", name),
+        TokenTree::MetaVarDecl(_, _, frag) => example_fragment(frag.name).to_string(),
+    }
+}
+
+fn example_fragment(frag: Symbol) -> &'static str {
+    match frag {
+        sym::block => "{}",
+        sym::item => "struct S;",
+        sym::lifetime => "'a",
+        sym::literal | sym::expr | sym::stmt => "0",
+        sym::pat => "_",
+        sym::ty => "u8",
+        sym::vis => "pub",
+        _ => "a",
+    }
+}
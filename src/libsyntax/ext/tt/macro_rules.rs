@@ -1,13 +1,17 @@
 use crate::ast;
 use crate::attr::{self, TransparencyError};
+use crate::early_buffered_lints::BufferedEarlyLintId;
 use crate::edition::Edition;
+use crate::ext::base::{ArmOutcome, ArmTrace, MetaVarBinding, TraceEntry, TraceKind};
 use crate::ext::base::{DummyResult, ExtCtxt, MacResult, TTMacroExpander};
-use crate::ext::base::{SyntaxExtension, SyntaxExtensionKind};
+use crate::ext::base::{MacroExportOptions, SyntaxExtension, SyntaxExtensionKind};
 use crate::ext::expand::{AstFragment, AstFragmentKind};
+use crate::ext::tt::macro_backtracking;
 use crate::ext::tt::macro_check;
+use crate::ext::tt::match_cache::MatchCache;
 use crate::ext::tt::macro_parser::{parse, parse_failure_msg};
 use crate::ext::tt::macro_parser::{Error, Failure, Success};
-use crate::ext::tt::macro_parser::{MatchedNonterminal, MatchedSeq};
+use crate::ext::tt::macro_parser::{MatchedNonterminal, MatchedSeq, NamedMatch};
 use crate::ext::tt::quoted;
 use crate::ext::tt::transcribe::transcribe;
 use crate::feature_gate::Features;
@@ -15,13 +19,13 @@ use crate::parse::parser::Parser;
 use crate::parse::token::TokenKind::*;
 use crate::parse::token::{self, NtTT, Token};
 use crate::parse::{Directory, ParseSess};
+use crate::print::pprust;
 use crate::symbol::{kw, sym, Symbol};
 use crate::tokenstream::{DelimSpan, TokenStream, TokenTree};
 
 use errors::{DiagnosticBuilder, FatalError};
 use log::debug;
-use syntax_pos::hygiene::Transparency;
-use syntax_pos::Span;
+use syntax_pos::{MultiSpan, Span};
 
 use rustc_data_structures::fx::FxHashMap;
 use std::borrow::Cow;
@@ -31,6 +35,9 @@ use std::slice;
 use errors::Applicability;
 use rustc_data_structures::sync::Lrc;
 
+#[cfg(test)]
+mod tests;
+
 const VALID_FRAGMENT_NAMES_MSG: &str = "valid fragment specifiers are \
                                         `ident`, `block`, `stmt`, `expr`, `pat`, `ty`, `lifetime`, \
                                         `literal`, `path`, `meta`, `tt`, `item` and `vis`";
@@ -130,10 +137,14 @@ impl<'a> ParserAnyMacro<'a> {
 struct MacroRulesMacroExpander {
     name: ast::Ident,
     span: Span,
-    transparency: Transparency,
     lhses: Vec<quoted::TokenTree>,
     rhses: Vec<quoted::TokenTree>,
+    /// The arm matchers as written, for introspection.
+    matchers: Vec<String>,
     valid: bool,
+    /// Indices of arms whose use is deprecated through `#[macro_export(deprecated_arms(...))]`.
+    deprecated_arms: Vec<usize>,
+    cache: MatchCache,
 }
 
 impl TTMacroExpander for MacroRulesMacroExpander {
@@ -144,17 +155,42 @@ impl TTMacroExpander for MacroRulesMacroExpander {
         input: TokenStream,
     ) -> Box<dyn MacResult + 'cx> {
         if !self.valid {
-            return DummyResult::any(sp);
+            return DummyResult::any_in_context(cx, sp, &input);
         }
         generic_extension(
-            cx, sp, self.span, self.name, self.transparency, input, &self.lhses, &self.rhses
+            cx,
+            sp,
+            self.span,
+            self.name,
+            input,
+            &self.lhses,
+            &self.rhses,
+            &self.deprecated_arms,
+            &self.cache,
         )
     }
+
+    fn matchers(&self) -> Vec<String> {
+        self.matchers.clone()
+    }
+}
+
+fn trace_macros_note(cx: &mut ExtCtxt<'_>, sp: Span, macro_name: ast::Ident, kind: TraceKind) {
+    let span = sp.macro_backtrace().last().map(|trace| trace.call_site).unwrap_or(sp);
+    cx.expansions.push(TraceEntry { span, macro_name, kind });
 }
 
-fn trace_macros_note(cx: &mut ExtCtxt<'_>, sp: Span, message: String) {
-    let sp = sp.macro_backtrace().last().map(|trace| trace.call_site).unwrap_or(sp);
-    cx.expansions.entry(sp).or_default().push(message);
+/// Converts what a metavariable matched into the token form recorded by `trace_macros!`.
+fn meta_var_binding(sess: &ParseSess, sp: Span, m: &NamedMatch) -> MetaVarBinding {
+    match *m {
+        MatchedNonterminal(ref nt) => MetaVarBinding::Tokens(match **nt {
+            NtTT(ref tt) => tt.clone().into(),
+            ref nt => nt.to_tokenstream(sess, sp),
+        }),
+        MatchedSeq(ref matches, _) => {
+            MetaVarBinding::Seq(matches.iter().map(|m| meta_var_binding(sess, sp, m)).collect())
+        }
+    }
 }
 
 /// Given `lhses` and `rhses`, this is the new macro we create
@@ -163,17 +199,29 @@ fn generic_extension<'cx>(
     sp: Span,
     def_span: Span,
     name: ast::Ident,
-    transparency: Transparency,
     arg: TokenStream,
     lhses: &[quoted::TokenTree],
     rhses: &[quoted::TokenTree],
+    deprecated_arms: &[usize],
+    cache: &MatchCache,
 ) -> Box<dyn MacResult + 'cx> {
-    if cx.trace_macros() {
-        trace_macros_note(cx, sp, format!("expanding `{}! {{ {} }}`", name, arg));
+    let trace = cx.traces_current_invocation();
+    if trace {
+        trace_macros_note(cx, sp, name, TraceKind::Expanding(arg.clone()));
+    }
+
+    // Traced invocations go through matching to report on each arm.
+    let cache = if cx.ecfg.macro_rules_cache && !trace { Some(cache) } else { None };
+    if let Some((i, named_matches)) = cache.and_then(|cache| cache.lookup(&arg, sp.edition())) {
+        return expand_arm(
+            cx, sp, name, trace, i, &lhses[i], &rhses[i], deprecated_arms,
+            named_matches,
+        );
     }
 
     // Which arm's failure should we report? (the one furthest along)
     let mut best_failure: Option<(Token, &str)> = None;
+    let mut arm_traces = Vec::new();
 
     for (i, lhs) in lhses.iter().enumerate() {
         // try each arm's matchers
@@ -184,63 +232,50 @@ fn generic_extension<'cx>(
 
         match TokenTree::parse(cx, lhs_tt, arg.clone()) {
             Success(named_matches) => {
-                let rhs = match rhses[i] {
-                    // ignore delimiters
-                    quoted::TokenTree::Delimited(_, ref delimed) => delimed.tts.clone(),
-                    _ => cx.span_bug(sp, "malformed macro rhs"),
-                };
-                let arm_span = rhses[i].span();
-
-                let rhs_spans = rhs.iter().map(|t| t.span()).collect::<Vec<_>>();
-                // rhs has holes ( `$id` and `$(...)` that need filled)
-                let mut tts = transcribe(cx, &named_matches, rhs, transparency);
-
-                // Replace all the tokens for the corresponding positions in the macro, to maintain
-                // proper positions in error reporting, while maintaining the macro_backtrace.
-                if rhs_spans.len() == tts.len() {
-                    tts = tts.map_enumerated(|i, mut tt| {
-                        let mut sp = rhs_spans[i];
-                        sp = sp.with_ctxt(tt.span().ctxt());
-                        tt.set_span(sp);
-                        tt
+                if trace {
+                    let mut bindings = named_matches
+                        .iter()
+                        .map(|(name, m)| (*name, meta_var_binding(cx.parse_sess, sp, m)))
+                        .collect::<Vec<_>>();
+                    bindings.sort_by_key(|(name, _)| name.span.lo());
+                    arm_traces.push(ArmTrace {
+                        span: lhs.span(),
+                        outcome: ArmOutcome::Matched(bindings),
                     });
+                    trace_macros_note(cx, sp, name, TraceKind::Arms(arm_traces));
                 }
 
-                if cx.trace_macros() {
-                    trace_macros_note(cx, sp, format!("to `{}`", tts));
+                if let Some(cache) = cache {
+                    cache.insert(&arg, sp.edition(), i, &named_matches);
+                }
+                return expand_arm(
+                    cx, sp, name, trace, i, lhs, &rhses[i], deprecated_arms,
+                    named_matches,
+                );
+            }
+            Failure(token, msg) => {
+                if trace {
+                    arm_traces.push(ArmTrace {
+                        span: lhs.span(),
+                        outcome: ArmOutcome::Failed {
+                            span: token.span.substitute_dummy(sp),
+                            reason: parse_failure_msg(&token),
+                        },
+                    });
+                }
+                match best_failure {
+                    Some((ref best_token, _)) if best_token.span.lo() >= token.span.lo() => {}
+                    _ => best_failure = Some((token, msg)),
                 }
-
-                let directory = Directory {
-                    path: Cow::from(cx.current_expansion.module.directory.as_path()),
-                    ownership: cx.current_expansion.directory_ownership,
-                };
-                let mut p = Parser::new(cx.parse_sess(), tts, Some(directory), true, false, None);
-                p.root_module_name =
-                    cx.current_expansion.module.mod_path.last().map(|id| id.as_str().to_string());
-                p.last_type_ascription = cx.current_expansion.prior_type_ascription;
-
-                p.process_potential_macro_variable();
-                // Let the context choose how to interpret the result.
-                // Weird, but useful for X-macros.
-                return Box::new(ParserAnyMacro {
-                    parser: p,
-
-                    // Pass along the original expansion site and the name of the macro
-                    // so we can print a useful error message if the parse of the expanded
-                    // macro leaves unparsed tokens.
-                    site_span: sp,
-                    macro_ident: name,
-                    arm_span,
-                });
             }
-            Failure(token, msg) => match best_failure {
-                Some((ref best_token, _)) if best_token.span.lo() >= token.span.lo() => {}
-                _ => best_failure = Some((token, msg)),
-            },
             Error(err_sp, ref msg) => cx.span_fatal(err_sp.substitute_dummy(sp), &msg[..]),
         }
     }
 
+    if trace {
+        trace_macros_note(cx, sp, name, TraceKind::Arms(arm_traces));
+    }
+
     let (token, label) = best_failure.expect("ran no matchers");
     let span = token.span.substitute_dummy(sp);
     let mut err = cx.struct_span_err(span, &parse_failure_msg(&token));
@@ -276,7 +311,79 @@ fn generic_extension<'cx>(
     }
     err.emit();
     cx.trace_macros_diag();
-    DummyResult::any(sp)
+    DummyResult::any_in_context(cx, sp, &arg)
+}
+
+/// Transcribes `rhs`, the arm of the macro `name` that matched as `named_matches`, and sets up
+/// the parser that will interpret the output.
+fn expand_arm<'cx>(
+    cx: &'cx mut ExtCtxt<'_>,
+    sp: Span,
+    name: ast::Ident,
+    trace: bool,
+    arm: usize,
+    lhs: &quoted::TokenTree,
+    rhs: &quoted::TokenTree,
+    deprecated_arms: &[usize],
+    named_matches: FxHashMap<ast::Ident, NamedMatch>,
+) -> Box<dyn MacResult + 'cx> {
+    let rhs_tts = match *rhs {
+        // ignore delimiters
+        quoted::TokenTree::Delimited(_, ref delimed) => delimed.tts.clone(),
+        _ => cx.span_bug(sp, "malformed macro rhs"),
+    };
+    let arm_span = rhs.span();
+
+    if deprecated_arms.contains(&arm) {
+        let mut span = MultiSpan::from_span(sp);
+        if !lhs.span().is_dummy() {
+            span.push_span_label(lhs.span(), "deprecated arm defined here".to_string());
+        }
+        let msg = format!("use of deprecated arm {} of macro `{}`", arm + 1, name);
+        cx.buffer_lint(BufferedEarlyLintId::DeprecatedMacroArm, span, &msg);
+    }
+
+    let rhs_spans = rhs_tts.iter().map(|t| t.span()).collect::<Vec<_>>();
+    // rhs has holes ( `$id` and `$(...)` that need filled)
+    let mut tts = transcribe(cx, &named_matches, rhs_tts);
+
+    // Replace all the tokens for the corresponding positions in the macro, to maintain
+    // proper positions in error reporting, while maintaining the macro_backtrace.
+    if rhs_spans.len() == tts.len() {
+        tts = tts.map_enumerated(|i, mut tt| {
+            let mut sp = rhs_spans[i];
+            sp = sp.with_ctxt(tt.span().ctxt());
+            tt.set_span(sp);
+            tt
+        });
+    }
+
+    if trace {
+        trace_macros_note(cx, sp, name, TraceKind::To(tts.clone()));
+    }
+
+    let directory = Directory {
+        path: Cow::from(cx.current_expansion.module.directory.as_path()),
+        ownership: cx.current_expansion.directory_ownership,
+    };
+    let mut p = Parser::new(cx.parse_sess(), tts, Some(directory), true, false, None);
+    p.root_module_name =
+        cx.current_expansion.module.mod_path.last().map(|id| id.as_str().to_string());
+    p.last_type_ascription = cx.current_expansion.prior_type_ascription;
+
+    p.process_potential_macro_variable();
+    // Let the context choose how to interpret the result.
+    // Weird, but useful for X-macros.
+    Box::new(ParserAnyMacro {
+        parser: p,
+
+        // Pass along the original expansion site and the name of the macro
+        // so we can print a useful error message if the parse of the expanded
+        // macro leaves unparsed tokens.
+        site_span: sp,
+        macro_ident: name,
+        arm_span,
+    })
 }
 
 // Note that macro-by-example's input is also matched against a token tree:
@@ -355,6 +462,7 @@ pub fn compile(
     };
 
     let mut valid = true;
+    let mut matchers = Vec::new();
 
     // Extract the arguments:
     let lhses = match argument_map[&lhs_nm] {
@@ -363,6 +471,7 @@ pub fn compile(
             .map(|m| {
                 if let MatchedNonterminal(ref nt) = *m {
                     if let NtTT(ref tt) = **nt {
+                        matchers.push(pprust::tt_to_string(tt.clone()));
                         let tt = quoted::parse(
                             tt.clone().into(),
                             true,
@@ -374,7 +483,7 @@ pub fn compile(
                         )
                         .pop()
                         .unwrap();
-                        valid &= check_lhs_nt_follows(sess, features, &def.attrs, &tt);
+                        valid &= check_lhs_nt_follows(sess, &tt);
                         return tt;
                     }
                 }
@@ -421,6 +530,7 @@ pub fn compile(
     // We use CRATE_NODE_ID instead of `def.id` otherwise we may emit buffered lints for a node id
     // that is not lint-checked and trigger the "failed to process buffered lint here" bug.
     valid &= macro_check::check_meta_variables(sess, ast::CRATE_NODE_ID, def.span, &lhses, &rhses);
+    macro_backtracking::check_matchers(sess, ast::CRATE_NODE_ID, &lhses);
 
     let (transparency, transparency_error) = attr::find_transparency(&def.attrs, body.legacy);
     match transparency_error {
@@ -431,11 +541,25 @@ pub fn compile(
         None => {}
     }
 
+    let export_options = MacroExportOptions::from_attrs(sess, &def.attrs);
+    let deprecated_arms =
+        export_options.as_ref().map_or(Vec::new(), |o| o.deprecated_arms.clone());
+    for &arm in &deprecated_arms {
+        if arm >= lhses.len() {
+            diag.span_err(
+                def.span,
+                &format!("`deprecated_arms` refers to arm {}, but this macro only has {} arms",
+                         arm + 1, lhses.len()),
+            );
+        }
+    }
+
     let expander: Box<_> = Box::new(MacroRulesMacroExpander {
-        name: def.ident, span: def.span, transparency, lhses, rhses, valid
+        name: def.ident, span: def.span, lhses, rhses, matchers, valid,
+        deprecated_arms, cache: MatchCache::default(),
     });
 
-    SyntaxExtension::new(
+    let ext = SyntaxExtension::new(
         sess,
         SyntaxExtensionKind::LegacyBang(expander),
         def.span,
@@ -443,19 +567,24 @@ pub fn compile(
         edition,
         def.ident.name,
         &def.attrs,
-    )
+    );
+    // `#[macro_export(edition = "...")]` is checked like `#[rustc_min_edition]`.
+    let export_edition = export_options.as_ref().and_then(|options| options.edition);
+    let min_edition =
+        if export_edition > ext.min_edition { export_edition } else { ext.min_edition };
+    SyntaxExtension {
+        transparency,
+        min_edition,
+        export_options,
+        ..ext
+    }
 }
 
-fn check_lhs_nt_follows(
-    sess: &ParseSess,
-    features: &Features,
-    attrs: &[ast::Attribute],
-    lhs: &quoted::TokenTree,
-) -> bool {
+fn check_lhs_nt_follows(sess: &ParseSess, lhs: &quoted::TokenTree) -> bool {
     // lhs is going to be like TokenTree::Delimited(...), where the
     // entire lhs is those tts. Or, it can be a "bare sequence", not wrapped in parens.
     if let quoted::TokenTree::Delimited(_, ref tts) = *lhs {
-        check_matcher(sess, features, attrs, &tts.tts)
+        check_matcher(sess, &tts.tts)
     } else {
         let msg = "invalid macro matcher; matchers must be contained in balanced delimiters";
         sess.span_diagnostic.span_err(lhs.span(), msg);
@@ -510,17 +639,97 @@ fn check_rhs(sess: &ParseSess, rhs: &quoted::TokenTree) -> bool {
     false
 }
 
-fn check_matcher(
-    sess: &ParseSess,
-    features: &Features,
-    attrs: &[ast::Attribute],
-    matcher: &[quoted::TokenTree],
-) -> bool {
+fn check_matcher(sess: &ParseSess, matcher: &[quoted::TokenTree]) -> bool {
+    let issues = check_follow_sets(matcher);
+    for issue in &issues {
+        issue.emit(sess);
+    }
+    issues.is_empty()
+}
+
+/// A violation of the FOLLOW-set rules for `macro_rules!` matchers.
+#[derive(Clone, Debug)]
+pub enum FollowSetIssue {
+    /// A metavariable uses a fragment specifier that doesn't exist.
+    InvalidFragment { span: Span, frag: ast::Ident },
+    /// A fragment may be followed by a token outside of its FOLLOW set.
+    NotInFollow {
+        /// The span of the offending token.
+        span: Span,
+        /// The name of the metavariable, e.g., `e` in `$e:expr`.
+        name: ast::Ident,
+        /// The fragment specifier of the metavariable.
+        frag: ast::Ident,
+        /// The offending token, as written in the matcher.
+        next: String,
+        /// Whether `next` always follows the fragment, rather than only on some paths.
+        always: bool,
+        /// Descriptions of the tokens that are allowed to follow `frag`.
+        allowed: &'static [&'static str],
+    },
+}
+
+impl FollowSetIssue {
+    pub fn span(&self) -> Span {
+        match *self {
+            FollowSetIssue::InvalidFragment { span, .. } |
+            FollowSetIssue::NotInFollow { span, .. } => span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            FollowSetIssue::InvalidFragment { frag, .. } => {
+                format!("invalid fragment specifier `{}`", frag)
+            }
+            FollowSetIssue::NotInFollow { name, frag, next, always, .. } => format!(
+                "`${name}:{frag}` {may_be} followed by `{next}`, which \
+                 is not allowed for `{frag}` fragments",
+                name = name,
+                frag = frag,
+                next = next,
+                may_be = if *always { "is" } else { "may be" },
+            ),
+        }
+    }
+
+    fn emit(&self, sess: &ParseSess) {
+        let mut err = sess.span_diagnostic.struct_span_err(self.span(), &self.message());
+        match self {
+            FollowSetIssue::InvalidFragment { .. } => {
+                err.help(VALID_FRAGMENT_NAMES_MSG);
+            }
+            FollowSetIssue::NotInFollow { span, frag, allowed, .. } => {
+                err.span_label(*span, format!("not allowed after `{}` fragments", frag));
+                match *allowed {
+                    &[] => {}
+                    &[t] => {
+                        err.note(&format!("only {} is allowed after `{}` fragments", t, frag));
+                    }
+                    ts => {
+                        err.note(&format!(
+                            "allowed there are: {} or {}",
+                            ts[..ts.len() - 1].join(", "),
+                            ts[ts.len() - 1],
+                        ));
+                    }
+                }
+            }
+        }
+        err.emit();
+    }
+}
+
+/// Checks the FOLLOW-set rules on `matcher`, the contents of the left-hand side of a
+/// `macro_rules!` arm as produced by `quoted::parse`, and returns every violation found
+/// instead of reporting it. The check only depends on the matcher, so tools can run it without
+/// a parsing session, e.g., on matchers they build themselves.
+pub fn check_follow_sets(matcher: &[quoted::TokenTree]) -> Vec<FollowSetIssue> {
     let first_sets = FirstSets::new(matcher);
     let empty_suffix = TokenSet::empty();
-    let err = sess.span_diagnostic.err_count();
-    check_matcher_core(sess, features, attrs, &first_sets, matcher, &empty_suffix);
-    err == sess.span_diagnostic.err_count()
+    let mut issues = Vec::new();
+    check_matcher_core(&first_sets, matcher, &empty_suffix, &mut issues);
+    issues
 }
 
 // `The FirstSets` for a matcher is a mapping from subsequences in the
@@ -763,13 +972,13 @@ impl TokenSet {
 //
 // Requires that `first_sets` is pre-computed for `matcher`;
 // see `FirstSets::new`.
+//
+// Violations are appended to `issues`.
 fn check_matcher_core(
-    sess: &ParseSess,
-    features: &Features,
-    attrs: &[ast::Attribute],
     first_sets: &FirstSets,
     matcher: &[quoted::TokenTree],
     follow: &TokenSet,
+    issues: &mut Vec<FollowSetIssue>,
 ) -> TokenSet {
     use quoted::TokenTree;
 
@@ -800,12 +1009,8 @@ fn check_matcher_core(
         match *token {
             TokenTree::Token(..) | TokenTree::MetaVar(..) | TokenTree::MetaVarDecl(..) => {
                 let can_be_followed_by_any;
-                if let Err(bad_frag) = has_legal_fragment_specifier(sess, features, attrs, token) {
-                    let msg = format!("invalid fragment specifier `{}`", bad_frag);
-                    sess.span_diagnostic
-                        .struct_span_err(token.span(), &msg)
-                        .help(VALID_FRAGMENT_NAMES_MSG)
-                        .emit();
+                if let Err(frag) = has_legal_fragment_specifier(token) {
+                    issues.push(FollowSetIssue::InvalidFragment { span: token.span(), frag });
                     // (This eliminates false positives and duplicates
                     // from error messages.)
                     can_be_followed_by_any = true;
@@ -826,7 +1031,7 @@ fn check_matcher_core(
             }
             TokenTree::Delimited(span, ref d) => {
                 let my_suffix = TokenSet::singleton(d.close_tt(span.close));
-                check_matcher_core(sess, features, attrs, first_sets, &d.tts, &my_suffix);
+                check_matcher_core(first_sets, &d.tts, &my_suffix, issues);
                 // don't track non NT tokens
                 last.replace_with_irrelevant();
 
@@ -858,8 +1063,7 @@ fn check_matcher_core(
                 // At this point, `suffix_first` is built, and
                 // `my_suffix` is some TokenSet that we can use
                 // for checking the interior of `seq_rep`.
-                let next =
-                    check_matcher_core(sess, features, attrs, first_sets, &seq_rep.tts, my_suffix);
+                let next = check_matcher_core(first_sets, &seq_rep.tts, my_suffix, issues);
                 if next.maybe_empty {
                     last.add_all(&next);
                 } else {
@@ -877,66 +1081,28 @@ fn check_matcher_core(
         // Now `last` holds the complete set of NT tokens that could
         // end the sequence before SUFFIX. Check that every one works with `suffix`.
         'each_last: for token in &last.tokens {
-            if let TokenTree::MetaVarDecl(_, ref name, ref frag_spec) = *token {
+            if let TokenTree::MetaVarDecl(_, name, frag_spec) = *token {
                 for next_token in &suffix_first.tokens {
                     match is_in_follow(next_token, &frag_spec.as_str()) {
-                        IsInFollow::Invalid(msg, help) => {
-                            sess.span_diagnostic
-                                .struct_span_err(next_token.span(), &msg)
-                                .help(help)
-                                .emit();
+                        IsInFollow::Invalid => {
+                            issues.push(FollowSetIssue::InvalidFragment {
+                                span: next_token.span(),
+                                frag: frag_spec,
+                            });
                             // don't bother reporting every source of
                             // conflict for a particular element of `last`.
                             continue 'each_last;
                         }
                         IsInFollow::Yes => {}
                         IsInFollow::No(possible) => {
-                            let may_be = if last.tokens.len() == 1 && suffix_first.tokens.len() == 1
-                            {
-                                "is"
-                            } else {
-                                "may be"
-                            };
-
-                            let sp = next_token.span();
-                            let mut err = sess.span_diagnostic.struct_span_err(
-                                sp,
-                                &format!(
-                                    "`${name}:{frag}` {may_be} followed by `{next}`, which \
-                                     is not allowed for `{frag}` fragments",
-                                    name = name,
-                                    frag = frag_spec,
-                                    next = quoted_tt_to_string(next_token),
-                                    may_be = may_be
-                                ),
-                            );
-                            err.span_label(
-                                sp,
-                                format!("not allowed after `{}` fragments", frag_spec),
-                            );
-                            let msg = "allowed there are: ";
-                            match possible {
-                                &[] => {}
-                                &[t] => {
-                                    err.note(&format!(
-                                        "only {} is allowed after `{}` fragments",
-                                        t, frag_spec,
-                                    ));
-                                }
-                                ts => {
-                                    err.note(&format!(
-                                        "{}{} or {}",
-                                        msg,
-                                        ts[..ts.len() - 1]
-                                            .iter()
-                                            .map(|s| *s)
-                                            .collect::<Vec<_>>()
-                                            .join(", "),
-                                        ts[ts.len() - 1],
-                                    ));
-                                }
-                            }
-                            err.emit();
+                            issues.push(FollowSetIssue::NotInFollow {
+                                span: next_token.span(),
+                                name,
+                                frag: frag_spec,
+                                next: quoted_tt_to_string(next_token),
+                                always: last.tokens.len() == 1 && suffix_first.tokens.len() == 1,
+                                allowed: possible,
+                            });
                         }
                     }
                 }
@@ -982,7 +1148,7 @@ fn frag_can_be_followed_by_any(frag: &str) -> bool {
 enum IsInFollow {
     Yes,
     No(&'static [&'static str]),
-    Invalid(String, &'static str),
+    Invalid,
 }
 
 /// Returns `true` if `frag` can legally be followed by the token `tok`. For
@@ -1100,42 +1266,27 @@ fn is_in_follow(tok: &quoted::TokenTree, frag: &str) -> IsInFollow {
                 }
             }
             "" => IsInFollow::Yes, // kw::Invalid
-            _ => IsInFollow::Invalid(
-                format!("invalid fragment specifier `{}`", frag),
-                VALID_FRAGMENT_NAMES_MSG,
-            ),
+            _ => IsInFollow::Invalid,
         }
     }
 }
 
-fn has_legal_fragment_specifier(
-    sess: &ParseSess,
-    features: &Features,
-    attrs: &[ast::Attribute],
-    tok: &quoted::TokenTree,
-) -> Result<(), String> {
+fn has_legal_fragment_specifier(tok: &quoted::TokenTree) -> Result<(), ast::Ident> {
     debug!("has_legal_fragment_specifier({:?})", tok);
-    if let quoted::TokenTree::MetaVarDecl(_, _, ref frag_spec) = *tok {
-        let frag_span = tok.span();
-        if !is_legal_fragment_specifier(sess, features, attrs, frag_spec.name, frag_span) {
-            return Err(frag_spec.to_string());
+    if let quoted::TokenTree::MetaVarDecl(_, _, frag_spec) = *tok {
+        if !is_legal_fragment_specifier(frag_spec.name) {
+            return Err(frag_spec);
         }
     }
     Ok(())
 }
 
-fn is_legal_fragment_specifier(
-    _sess: &ParseSess,
-    _features: &Features,
-    _attrs: &[ast::Attribute],
-    frag_name: Symbol,
-    _frag_span: Span,
-) -> bool {
+fn is_legal_fragment_specifier(frag_name: Symbol) -> bool {
     /*
-     * If new fragment specifiers are invented in nightly, `_sess`,
-     * `_features`, `_attrs`, and `_frag_span` will be useful here
-     * for checking against feature gates. See past versions of
-     * this function.
+     * If new fragment specifiers are invented in nightly, they will
+     * need to be checked against feature gates with the features and
+     * attributes of the macro, reported separately from the FOLLOW-set
+     * issues. See past versions of this function.
      */
     match frag_name {
         sym::item
@@ -0,0 +1,58 @@
+use super::*;
+
+use crate::source_map::FilePathMapping;
+use crate::tests::string_to_stream;
+use crate::with_default_globals;
+
+/// Checks `matcher`, parsed as the left-hand side of a `macro_rules!` arm.
+fn check(matcher: &str) -> Vec<FollowSetIssue> {
+    let sess = ParseSess::new(FilePathMapping::empty());
+    let stream = string_to_stream(matcher.to_string());
+    let matcher = quoted::parse(stream, true, &sess, &Features::new(), &[],
+                                Edition::Edition2015, ast::DUMMY_NODE_ID);
+    assert!(!sess.span_diagnostic.has_errors());
+    check_follow_sets(&matcher)
+}
+
+#[test]
+fn follow_sets_accept_allowed_tokens() {
+    with_default_globals(|| {
+        assert!(check("$a:expr, $b:ident").is_empty());
+        assert!(check("$($t:ty),* => $p:pat").is_empty());
+        assert!(check("$i:ident $j:ident").is_empty());
+    })
+}
+
+#[test]
+fn follow_sets_report_disallowed_tokens() {
+    with_default_globals(|| {
+        let issues = check("$a:expr $b:ident");
+        assert_eq!(issues.len(), 1);
+        match &issues[0] {
+            FollowSetIssue::NotInFollow { name, frag, next, always, allowed, .. } => {
+                assert_eq!((name.to_string(), frag.to_string()), ("a".into(), "expr".into()));
+                assert_eq!(next, "$b:ident");
+                assert!(always);
+                assert_eq!(*allowed, &["`=>`", "`,`", "`;`"]);
+            }
+            issue => panic!("expected a token outside of the FOLLOW set, found {:?}", issue),
+        }
+        assert_eq!(issues[0].message(), "`$a:expr` is followed by `$b:ident`, which is not \
+                                         allowed for `expr` fragments");
+
+        // The expression is only followed by `+` if the optional sequence is present.
+        let issues = check("$a:expr $(+)? ;");
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message().starts_with("`$a:expr` may be followed by `+`"),
+                "{}", issues[0].message());
+    })
+}
+
+#[test]
+fn follow_sets_report_invalid_fragments() {
+    with_default_globals(|| {
+        let issues = check("$a:expression");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message(), "invalid fragment specifier `expression`");
+    })
+}
@@ -0,0 +1,253 @@
+//! Memoization of `macro_rules!` matching.
+//!
+//! Crates that invoke a macro with the same tokens many times over (generated code, test
+//! matrices) would otherwise match the input against the arms of the macro every time. The
+//! cache of a macro remembers which arm matched an input and where in the input each
+//! metavariable matched, so that matching the same tokens again is a lookup.
+//!
+//! Transcription still runs for every invocation, from the tokens of that invocation, which
+//! marks the output with the fresh expansion of the invocation and gives it its own spans.
+//! This is also why only `tt`, `ident` and `lifetime` fragments are cached: they are tokens of
+//! the input, while other fragments are parsed ASTs that can't be moved to another input.
+//!
+//! Inputs only match a cached one if their tokens also have the same hygiene contexts, and so
+//! the same editions, which matching may depend on. The cache is off by default, see
+//! `ExpansionConfig::macro_rules_cache`, and holds a bounded number of inputs per macro.
+
+use crate::ast::Ident;
+use crate::edition::Edition;
+use crate::ext::tt::macro_parser::{MatchedNonterminal, MatchedSeq, NamedMatch};
+use crate::parse::token::{self, Nonterminal, Token};
+use crate::tokenstream::{DelimSpan, TokenStream, TokenTree};
+
+use rustc_data_structures::fx::{FxHashMap, FxHasher};
+use rustc_data_structures::sync::{Lock, Lrc};
+use syntax_pos::Span;
+
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+#[cfg(test)]
+mod tests;
+
+/// The number of inputs the cache of a macro holds at most.
+const MAX_ENTRIES: usize = 1024;
+
+/// The matches of a single macro, keyed by a hash of the input tokens.
+#[derive(Default)]
+pub struct MatchCache {
+    entries: Lock<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    map: FxHashMap<u64, Vec<CacheEntry>>,
+    len: usize,
+}
+
+struct CacheEntry {
+    input: TokenStream,
+    edition: Edition,
+    arm: usize,
+    matches: FxHashMap<Ident, CachedMatch>,
+}
+
+/// A `NamedMatch` with the matched tokens replaced by their positions in the input, counting
+/// the token trees of the input in preorder.
+enum CachedMatch {
+    Seq(Vec<CachedMatch>),
+    Tree(usize),
+    Ident(usize, bool),
+    Lifetime(usize),
+}
+
+impl MatchCache {
+    /// Returns the arm that matched `input` before and the matches of its metavariables,
+    /// pointing into `input`.
+    crate fn lookup(&self, input: &TokenStream, edition: Edition)
+                    -> Option<(usize, FxHashMap<Ident, NamedMatch>)> {
+        let hash = hash_tokens(input, true)?;
+        let entries = self.entries.lock();
+        let entry = entries.map.get(&hash)?.iter().find(|entry| {
+            entry.edition == edition && eq_hygienic(&entry.input, input)
+        })?;
+        let trees = preorder(input);
+        let matches = entry.matches.iter()
+            .map(|(&name, cached)| (name, named_match(&trees, cached)))
+            .collect();
+        Some((entry.arm, matches))
+    }
+
+    /// Remembers that `arm` matched `input` with `matches`, unless some match can't be cached
+    /// or the cache is full.
+    crate fn insert(&self, input: &TokenStream, edition: Edition, arm: usize,
+                    matches: &FxHashMap<Ident, NamedMatch>) {
+        if self.entries.lock().len >= MAX_ENTRIES {
+            return;
+        }
+        let hash = match hash_tokens(input, true) {
+            Some(hash) => hash,
+            None => return,
+        };
+        let trees = preorder(input);
+        let mut positions = FxHashMap::default();
+        for (i, tree) in trees.iter().enumerate() {
+            positions.entry(tree.span()).or_insert(i);
+        }
+        let matches = matches.iter()
+            .map(|(&name, m)| Some((name, cached_match(&trees, &positions, m)?)))
+            .collect::<Option<_>>();
+        if let Some(matches) = matches {
+            let entry = CacheEntry { input: input.clone(), edition, arm, matches };
+            let mut entries = self.entries.lock();
+            entries.len += 1;
+            entries.map.entry(hash).or_default().push(entry);
+        }
+    }
+}
+
+/// Hashes the tokens of `input` regardless of their spans. Inputs with interpolated tokens
+/// aren't cached, so `None` is returned for them.
+crate fn input_hash(input: &TokenStream) -> Option<u64> {
+    hash_tokens(input, false)
+}
+
+/// Hashes the tokens of `stream`, and their hygiene contexts if `hygiene` is set.
+fn hash_tokens(stream: &TokenStream, hygiene: bool) -> Option<u64> {
+    fn hash_trees(stream: &TokenStream, hygiene: bool, hasher: &mut FxHasher) -> Option<()> {
+        for tree in stream.trees() {
+            match tree {
+                TokenTree::Token(token) => {
+                    hash_token_kind(&token.kind, hasher)?;
+                    if hygiene {
+                        token.span.ctxt().hash(hasher);
+                    }
+                }
+                TokenTree::Delimited(span, delim, tts) => {
+                    delim.hash(hasher);
+                    if hygiene {
+                        span.open.ctxt().hash(hasher);
+                        span.close.ctxt().hash(hasher);
+                    }
+                    hash_trees(&tts, hygiene, hasher)?;
+                    0u8.hash(hasher);
+                }
+            }
+        }
+        Some(())
+    }
+
+    let mut hasher = FxHasher::default();
+    hash_trees(stream, hygiene, &mut hasher)?;
+    Some(hasher.finish())
+}
+
+/// Hashes a token kind, consistently with its `PartialEq` implementation.
+fn hash_token_kind(kind: &token::TokenKind, hasher: &mut FxHasher) -> Option<()> {
+    mem::discriminant(kind).hash(hasher);
+    match *kind {
+        token::Interpolated(..) => return None,
+        token::BinOp(op) | token::BinOpEq(op) => op.hash(hasher),
+        token::OpenDelim(delim) | token::CloseDelim(delim) => delim.hash(hasher),
+        token::Literal(lit) => {
+            mem::discriminant(&lit.kind).hash(hasher);
+            lit.symbol.hash(hasher);
+            lit.suffix.hash(hasher);
+        }
+        token::Ident(name, is_raw) => {
+            name.hash(hasher);
+            is_raw.hash(hasher);
+        }
+        token::Lifetime(name) | token::DocComment(name) | token::Shebang(name) |
+        token::Unknown(name) => name.hash(hasher),
+        _ => {}
+    }
+    Some(())
+}
+
+/// Compares the tokens of two streams and their hygiene contexts, ignoring the rest of their
+/// spans.
+fn eq_hygienic(a: &TokenStream, b: &TokenStream) -> bool {
+    let (mut a, mut b) = (a.trees(), b.trees());
+    for (a, b) in a.by_ref().zip(b.by_ref()) {
+        let eq = match (a, b) {
+            (TokenTree::Token(a), TokenTree::Token(b)) => {
+                a.kind == b.kind && a.span.ctxt() == b.span.ctxt()
+            }
+            (TokenTree::Delimited(a_span, a_delim, a_tts),
+             TokenTree::Delimited(b_span, b_delim, b_tts)) => {
+                a_delim == b_delim &&
+                a_span.open.ctxt() == b_span.open.ctxt() &&
+                a_span.close.ctxt() == b_span.close.ctxt() &&
+                eq_hygienic(&a_tts, &b_tts)
+            }
+            _ => false,
+        };
+        if !eq {
+            return false;
+        }
+    }
+    a.next().is_none() && b.next().is_none()
+}
+
+/// The token trees of `stream` and all the trees nested in them, in preorder.
+crate fn preorder(stream: &TokenStream) -> Vec<TokenTree> {
+    fn push_trees(stream: &TokenStream, trees: &mut Vec<TokenTree>) {
+        for tree in stream.trees() {
+            trees.push(tree.clone());
+            if let TokenTree::Delimited(_, _, tts) = tree {
+                push_trees(&tts, trees);
+            }
+        }
+    }
+
+    let mut trees = Vec::new();
+    push_trees(stream, &mut trees);
+    trees
+}
+
+/// Converts a match into positions in `trees`, given the position of the first tree with each
+/// span. Returns `None` if the matched tokens can't be found, e.g. because their span was also
+/// given to an earlier tree.
+fn cached_match(trees: &[TokenTree], positions: &FxHashMap<Span, usize>, m: &NamedMatch)
+                -> Option<CachedMatch> {
+    let position = |span: Span, tree: &TokenTree| {
+        positions.get(&span).cloned().filter(|&i| trees[i].eq_unspanned(tree))
+    };
+    match m {
+        MatchedSeq(matches, _) => {
+            matches.iter().map(|m| cached_match(trees, positions, m)).collect::<Option<_>>()
+                .map(CachedMatch::Seq)
+        }
+        MatchedNonterminal(nt) => match **nt {
+            Nonterminal::NtTT(ref tree) => position(tree.span(), tree).map(CachedMatch::Tree),
+            Nonterminal::NtIdent(ident, is_raw) => {
+                let tree = TokenTree::token(token::Ident(ident.name, is_raw), ident.span);
+                position(ident.span, &tree).map(|i| CachedMatch::Ident(i, is_raw))
+            }
+            Nonterminal::NtLifetime(ident) => {
+                let tree = TokenTree::token(token::Lifetime(ident.name), ident.span);
+                position(ident.span, &tree).map(CachedMatch::Lifetime)
+            }
+            _ => None,
+        },
+    }
+}
+
+fn named_match(trees: &[TokenTree], cached: &CachedMatch) -> NamedMatch {
+    let token_ident = |i: usize| match trees[i] {
+        TokenTree::Token(Token { kind: token::Ident(name, _), span }) |
+        TokenTree::Token(Token { kind: token::Lifetime(name), span }) => Ident::new(name, span),
+        _ => panic!("cached match doesn't point at an identifier"),
+    };
+    let nt = match *cached {
+        CachedMatch::Seq(ref matches) => {
+            let matches = matches.iter().map(|cached| named_match(trees, cached)).collect();
+            return MatchedSeq(Lrc::new(matches), DelimSpan::dummy());
+        }
+        CachedMatch::Tree(i) => Nonterminal::NtTT(trees[i].clone()),
+        CachedMatch::Ident(i, is_raw) => Nonterminal::NtIdent(token_ident(i), is_raw),
+        CachedMatch::Lifetime(i) => Nonterminal::NtLifetime(token_ident(i)),
+    };
+    MatchedNonterminal(Lrc::new(nt))
+}
@@ -0,0 +1,42 @@
+use super::*;
+
+use crate::ext::hygiene::{ExpnData, ExpnId, ExpnKind, MacroKind, Transparency};
+use crate::symbol::{sym, Symbol};
+use crate::with_default_globals;
+
+use syntax_pos::DUMMY_SP;
+
+fn ident_stream(name: &str, span: Span) -> TokenStream {
+    TokenTree::token(token::Ident(Symbol::intern(name), false), span).into()
+}
+
+#[test]
+fn inputs_differing_in_hygiene_are_cached_separately() {
+    with_default_globals(|| {
+        let kind = ExpnKind::Macro(MacroKind::Bang, sym::format_args);
+        let expn_id = ExpnId::fresh(Some(ExpnData::default(kind, DUMMY_SP, Edition::Edition2015)));
+        let outside = ident_stream("x", DUMMY_SP);
+        let inside = ident_stream("x", DUMMY_SP.apply_mark(expn_id, Transparency::Opaque));
+        assert_eq!(input_hash(&outside), input_hash(&inside));
+
+        let cache = MatchCache::default();
+        cache.insert(&outside, Edition::Edition2015, 1, &FxHashMap::default());
+        assert_eq!(cache.lookup(&outside, Edition::Edition2015).map(|(arm, _)| arm), Some(1));
+        assert!(cache.lookup(&outside, Edition::Edition2018).is_none());
+        assert!(cache.lookup(&inside, Edition::Edition2015).is_none());
+    })
+}
+
+#[test]
+fn cache_is_bounded() {
+    with_default_globals(|| {
+        let cache = MatchCache::default();
+        for i in 0..=MAX_ENTRIES {
+            let input = ident_stream(&format!("x{}", i), DUMMY_SP);
+            cache.insert(&input, Edition::Edition2015, 0, &FxHashMap::default());
+        }
+        assert!(cache.lookup(&ident_stream("x0", DUMMY_SP), Edition::Edition2015).is_some());
+        let last = ident_stream(&format!("x{}", MAX_ENTRIES), DUMMY_SP);
+        assert!(cache.lookup(&last, Edition::Edition2015).is_none());
+    })
+}
@@ -19,6 +19,8 @@ use std::mem;
 struct Marker(ExpnId, Transparency);
 
 impl MutVisitor for Marker {
+    const VISIT_INTERPOLATED: bool = true;
+
     fn visit_span(&mut self, span: &mut Span) {
         *span = span.apply_mark(self.0, self.1)
     }
@@ -90,7 +92,6 @@ pub(super) fn transcribe(
     cx: &ExtCtxt<'_>,
     interp: &FxHashMap<Ident, NamedMatch>,
     src: Vec<quoted::TokenTree>,
-    transparency: Transparency,
 ) -> TokenStream {
     // Nothing for us to transcribe...
     if src.is_empty() {
@@ -119,7 +120,8 @@ pub(super) fn transcribe(
     // again, and we are done transcribing.
     let mut result: Vec<TreeAndJoint> = Vec::new();
     let mut result_stack = Vec::new();
-    let mut marker = Marker(cx.current_expansion.id, transparency);
+    let expn_id = cx.current_expansion.id;
+    let mut marker = Marker(expn_id, expn_id.expn_data().default_transparency);
 
     loop {
         // Look at the last frame on the stack.
@@ -156,14 +158,19 @@ pub(super) fn transcribe(
                 // done. Otherwise, we unwind the result_stack to append what we have produced to
                 // any previous results.
                 Frame::Delimited { forest, span, .. } => {
+                    // Share the trees with an identical stream produced before, e.g., by an
+                    // earlier repetition of the same sequence.
+                    let mut stream = TokenStream::new(result);
+                    if cx.ecfg.intern_token_streams {
+                        stream = cx.token_interner.borrow_mut().intern(stream);
+                    }
                     if result_stack.is_empty() {
                         // No results left to compute! We are back at the top-level.
-                        return TokenStream::new(result);
+                        return stream;
                     }
 
                     // Step back into the parent Delimited.
-                    let tree =
-                        TokenTree::Delimited(span, forest.delim, TokenStream::new(result).into());
+                    let tree = TokenTree::Delimited(span, forest.delim, stream);
                     result = result_stack.pop().unwrap();
                     result.push(tree.into());
                 }
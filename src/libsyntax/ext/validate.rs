@@ -0,0 +1,296 @@
+//! Checks of macro output done right after the expansion producing it, enabled by
+//! `ExpansionConfig::validate_output`, and once the whole crate is expanded, enabled by
+//! `ExpansionConfig::check_item_collisions` and `ExpansionConfig::check_dead_attrs`.
+//!
+//! Most problems with the output of a macro are only found by later passes, and are reported
+//! without any indication of which macro produced the offending code. The checks here report
+//! some of them, as warnings, while it's still known which macro is responsible.
+
+use crate::ast;
+use crate::attr;
+use crate::ext::base::{ExtCtxt, MacroKind};
+use crate::ext::expand::AstFragment;
+use crate::ext::hygiene::{ExpnData, ExpnId, ExpnKind};
+use crate::feature_gate::is_builtin_attr;
+use crate::source_map::Span;
+use crate::symbol::kw;
+use crate::visit::{self, Visitor};
+
+use errors::DiagnosticBuilder;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use syntax_pos::DUMMY_SP;
+
+use std::collections::hash_map::Entry;
+
+/// Checks `fragment`, freshly produced by the expansion `expn_id`, and warns about the problems
+/// found, attributing them to the macro that was expanded.
+crate fn validate_output(cx: &ExtCtxt<'_>, expn_id: ExpnId, fragment: &AstFragment) {
+    let mut validator = OutputValidator { cx, expn_data: expn_id.expn_data() };
+    match fragment {
+        AstFragment::Items(items) => validator.check_items(items.iter().map(|item| &**item), false),
+        AstFragment::Stmts(stmts) => validator.check_items(stmt_items(stmts), true),
+        _ => {}
+    }
+    fragment.visit_with(&mut validator);
+}
+
+fn stmt_items<'a>(stmts: &'a [ast::Stmt]) -> impl Iterator<Item = &'a ast::Item> {
+    stmts.iter().filter_map(|stmt| match stmt.node {
+        ast::StmtKind::Item(ref item) => Some(&**item),
+        _ => None,
+    })
+}
+
+fn macro_descr(expn_data: &ExpnData) -> String {
+    match expn_data.kind {
+        ExpnKind::Macro(MacroKind::Bang, name) => format!("`{}!`", name),
+        ExpnKind::Macro(MacroKind::Attr, name) => format!("`#[{}]`", name),
+        ExpnKind::Macro(MacroKind::Derive, name) => format!("`#[derive({})]`", name),
+        ref kind => format!("`{}`", kind.descr()),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Namespace {
+    Type,
+    Value,
+    /// Names introduced by `use` items, which are only compared with each other since what
+    /// they import isn't known yet.
+    Import,
+}
+
+fn namespace(item: &ast::ItemKind) -> Option<Namespace> {
+    match item {
+        ast::ItemKind::ExternCrate(..) |
+        ast::ItemKind::Mod(..) |
+        ast::ItemKind::TyAlias(..) |
+        ast::ItemKind::OpaqueTy(..) |
+        ast::ItemKind::Enum(..) |
+        ast::ItemKind::Struct(..) |
+        ast::ItemKind::Union(..) |
+        ast::ItemKind::Trait(..) |
+        ast::ItemKind::TraitAlias(..) => Some(Namespace::Type),
+        ast::ItemKind::Static(..) |
+        ast::ItemKind::Const(..) |
+        ast::ItemKind::Fn(..) => Some(Namespace::Value),
+        _ => None,
+    }
+}
+
+struct OutputValidator<'a, 'b> {
+    cx: &'a ExtCtxt<'b>,
+    expn_data: ExpnData,
+}
+
+impl OutputValidator<'_, '_> {
+    /// Checks the items defined together in a module or a block.
+    fn check_items<'i>(&self, items: impl Iterator<Item = &'i ast::Item>, in_block: bool) {
+        let mut defined = FxHashMap::default();
+        for item in items {
+            if in_block && item.vis.node.is_pub() {
+                let msg = format!("`pub` has no effect on an item in a block, in the output of {}",
+                                  self.macro_descr());
+                self.emit(self.cx.struct_span_warn(item.vis.span, &msg));
+            }
+            let ns = match namespace(&item.node) {
+                Some(ns) if item.ident.name != kw::Underscore => ns,
+                _ => continue,
+            };
+            match defined.entry((ns, item.ident.modern())) {
+                Entry::Vacant(entry) => {
+                    entry.insert(item.span);
+                }
+                Entry::Occupied(entry) => {
+                    let msg = format!("`{}` is defined multiple times in the output of {}",
+                                      item.ident, self.macro_descr());
+                    let mut err = self.cx.struct_span_warn(item.span, &msg);
+                    err.span_label(*entry.get(), format!("previous definition of `{}` here",
+                                                         item.ident));
+                    err.span_label(item.span, format!("`{}` redefined here", item.ident));
+                    self.emit(err);
+                }
+            }
+        }
+    }
+
+    fn macro_descr(&self) -> String {
+        macro_descr(&self.expn_data)
+    }
+
+    fn emit(&self, mut err: DiagnosticBuilder<'_>) {
+        if self.expn_data.def_site != DUMMY_SP {
+            err.span_note(self.expn_data.def_site, "the macro is defined here");
+        }
+        err.emit();
+    }
+}
+
+impl<'a> Visitor<'a> for OutputValidator<'_, '_> {
+    fn visit_mod(&mut self, m: &'a ast::Mod, _: syntax_pos::Span, _: &[ast::Attribute],
+                 _: ast::NodeId) {
+        self.check_items(m.items.iter().map(|item| &**item), false);
+        visit::walk_mod(self, m);
+    }
+
+    fn visit_block(&mut self, block: &'a ast::Block) {
+        self.check_items(stmt_items(&block.stmts), true);
+        visit::walk_block(self, block);
+    }
+
+    fn visit_mac(&mut self, _: &'a ast::Mac) {
+        // Macro invocations in the output are checked once they are expanded.
+    }
+}
+
+/// Checks the fully expanded `krate` for items with the same name in the same module that were
+/// generated by different macro invocations. Those are reported as plain redefinitions later,
+/// without naming either invocation.
+crate fn check_item_collisions(cx: &ExtCtxt<'_>, krate: &ast::Crate) {
+    visit::walk_crate(&mut CollisionChecker { cx }, krate);
+}
+
+struct CollisionChecker<'a, 'b> {
+    cx: &'a ExtCtxt<'b>,
+}
+
+impl CollisionChecker<'_, '_> {
+    /// Checks the items defined together in a module or a block.
+    fn check_items<'i>(&self, items: impl Iterator<Item = &'i ast::Item>) {
+        let mut generated: FxHashMap<_, (ExpnId, Span)> = FxHashMap::default();
+        for item in items {
+            let expn_id = item.span.ctxt().outer_expn();
+            if expn_id == ExpnId::root() {
+                continue;
+            }
+            let (ns, ident) = match item.node {
+                ast::ItemKind::Use(ref tree) => match tree.kind {
+                    ast::UseTreeKind::Simple(..) => (Namespace::Import, tree.ident()),
+                    _ => continue,
+                },
+                ref node => match namespace(node) {
+                    Some(ns) => (ns, item.ident),
+                    None => continue,
+                },
+            };
+            if ident.name == kw::Underscore {
+                continue;
+            }
+            match generated.entry((ns, ident.modern())) {
+                Entry::Vacant(entry) => {
+                    entry.insert((expn_id, item.span));
+                }
+                Entry::Occupied(entry) => {
+                    let (prev_expn_id, prev_span) = *entry.get();
+                    if prev_expn_id != expn_id {
+                        self.report(ns, ident, (prev_expn_id, prev_span), (expn_id, item.span));
+                    }
+                }
+            }
+        }
+    }
+
+    fn report(&self, ns: Namespace, ident: ast::Ident, prev: (ExpnId, Span), new: (ExpnId, Span)) {
+        let (prev_data, new_data) = (prev.0.expn_data(), new.0.expn_data());
+        let (prev_descr, new_descr) = (macro_descr(&prev_data), macro_descr(&new_data));
+        let msg = format!("`{}` is generated by both {} and {}", ident, prev_descr, new_descr);
+        let mut err = self.cx.struct_span_warn(new.1, &msg);
+        err.span_label(prev.1, format!("previous definition of `{}` here", ident));
+        err.span_label(new.1, format!("`{}` redefined here", ident));
+        err.span_note(prev_data.call_site,
+                      &format!("first generated by this invocation of {}", prev_descr));
+        err.span_note(new_data.call_site,
+                      &format!("then generated by this invocation of {}", new_descr));
+        if ns == Namespace::Import {
+            err.help("if both macros need this import, they can request it instead of generating \
+                      it, so that it is only added once per module");
+        } else {
+            err.help("if both macros need this item, they can request it at the crate root instead \
+                      of generating it, so that it is only added once");
+        }
+        err.emit();
+    }
+}
+
+impl<'a> Visitor<'a> for CollisionChecker<'_, '_> {
+    fn visit_mod(&mut self, m: &'a ast::Mod, _: Span, _: &[ast::Attribute], _: ast::NodeId) {
+        self.check_items(m.items.iter().map(|item| &**item));
+        visit::walk_mod(self, m);
+    }
+
+    fn visit_block(&mut self, block: &'a ast::Block) {
+        self.check_items(stmt_items(&block.stmts));
+        visit::walk_block(self, block);
+    }
+
+    fn visit_mac(&mut self, _: &'a ast::Mac) {
+        // Invocations left unexpanded, e.g., after errors.
+    }
+}
+
+/// The attributes consumed by extensions during expansion, recorded for `check_dead_attrs` if
+/// `ExpansionConfig::check_dead_attrs` is enabled.
+///
+/// Attribute macros and derives take their own attribute out of the AST, so only the attributes
+/// that stay in it need to be recorded: the helper attributes of derives and the resolved tool
+/// attributes.
+#[derive(Default)]
+pub struct AttrConsumption {
+    crate consumed: FxHashSet<ast::AttrId>,
+    /// The attributes of macros that never ran because their item is disabled by a `#[cfg]`
+    /// following the attribute, see `SyntaxExtension::sees_unconfigured`.
+    crate unconfigured: Vec<ast::Attribute>,
+}
+
+/// Warns about the attributes that no extension consumed during expansion, see
+/// `AttrConsumption`.
+///
+/// These are the attributes of macros that never ran because their item is disabled, and the
+/// attributes left in the expanded crate that are meant for a macro that never ran, e.g., a
+/// helper of a derive that isn't applied to the item or whose name is misspelled. Unknown
+/// attributes are an error anyway without `#![feature(custom_attribute)]`, so they are only
+/// reported with it.
+crate fn check_dead_attrs(cx: &ExtCtxt<'_>, krate: &ast::Crate) {
+    for attr in &cx.attr_consumption.unconfigured {
+        let msg = format!("attribute `{}` is not consumed by any macro", attr.path);
+        cx.struct_span_warn(attr.span, &msg)
+            .note(&format!("`{}` never ran, because the item is disabled by a `#[cfg]` and the \
+                            macro doesn't see disabled items", attr.path))
+            .emit();
+    }
+    let report_unknown = cx.ecfg.features.map_or(false, |features| features.custom_attribute);
+    visit::walk_crate(&mut DeadAttrChecker { cx, report_unknown }, krate);
+}
+
+struct DeadAttrChecker<'a, 'b> {
+    cx: &'a ExtCtxt<'b>,
+    report_unknown: bool,
+}
+
+impl<'a> Visitor<'a> for DeadAttrChecker<'_, '_> {
+    fn visit_attribute(&mut self, attr: &'a ast::Attribute) {
+        // Multi-segment paths are tool attributes or attribute macros, which are resolved.
+        if attr.is_sugared_doc || attr.path.segments.len() != 1 ||
+           is_builtin_attr(attr) || self.cx.attr_consumption.consumed.contains(&attr.id) {
+            return;
+        }
+        let is_known = attr::is_known(attr);
+        if !is_known && !self.report_unknown {
+            return;
+        }
+        let msg = format!("attribute `{}` is not consumed by any macro", attr.path);
+        let mut err = self.cx.struct_span_warn(attr.span, &msg);
+        if is_known {
+            err.note(&format!("`{}` is accepted as an inert attribute here, but none of the \
+                               macros expanded for this code read it", attr.path));
+        } else {
+            err.note(&format!("no macro expanded for this code knows about `{}`", attr.path));
+        }
+        err.help("attributes meant for a derive macro are only read if the derive is applied \
+                  to the same item");
+        err.emit();
+    }
+
+    fn visit_mac(&mut self, _: &'a ast::Mac) {
+        // Invocations left unexpanded, e.g., after errors.
+    }
+}
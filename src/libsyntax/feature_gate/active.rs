@@ -532,6 +532,19 @@ declare_features! (
     /// Allows the use of or-patterns, e.g. `0 | 1`.
     (active, or_patterns, "1.38.0", Some(54883), None),
 
+    /// Allows macros to point diagnostics in their output at their input with `#[span_source]`.
+    (active, span_source, "1.38.0", None, None),
+
+    /// Allows `#[deprecated(suggestion = "...")]` on macros.
+    (active, deprecated_suggestion, "1.38.0", None, None),
+
+    /// Allows macro invocations in `match` arm position, expanding to arms.
+    (active, macros_in_arm_position, "1.38.0", None, None),
+
+    /// Allows macro invocations in function parameter position, and macros in closure
+    /// parameter position to expand to anything but a single pattern.
+    (active, macros_in_param_position, "1.38.0", None, None),
+
     // -------------------------------------------------------------------------
     // feature-group-end: actual feature gates
     // -------------------------------------------------------------------------
@@ -194,7 +194,13 @@ pub const BUILTIN_ATTRIBUTES: &[BuiltinAttribute] = &[
     // FIXME(#14407)
     ungated!(macro_use, Normal, template!(Word, List: "name1, name2, ...")),
     ungated!(macro_escape, Normal, template!(Word)), // Deprecated synonym for `macro_use`.
-    ungated!(macro_export, Normal, template!(Word, List: "local_inner_macros")),
+    ungated!(
+        macro_export, Normal,
+        template!(
+            Word,
+            List: r#"local_inner_macros|hidden|deprecated_arms(N, ...)|edition = "...""#
+        ),
+    ),
     ungated!(proc_macro, Normal, template!(Word)),
     ungated!(
         proc_macro_derive, Normal,
@@ -299,6 +305,10 @@ pub const BUILTIN_ATTRIBUTES: &[BuiltinAttribute] = &[
         "`#[thread_local]` is an experimental feature, and does not currently handle destructors",
     ),
     gated!(no_core, CrateLevel, template!(Word), experimental!(no_core)),
+    gated!(
+        span_source, Whitelisted, template!(List: r#""...", /*opt*/ offset, len"#),
+        experimental!(span_source),
+    ),
     // RFC 2412
     gated!(
         optimize, Whitelisted, template!(List: "size|speed"), optimize_attribute,
@@ -408,6 +418,36 @@ pub const BUILTIN_ATTRIBUTES: &[BuiltinAttribute] = &[
         template!(NameValueStr: "transparent|semitransparent|opaque"),
         "used internally for testing macro hygiene",
     ),
+    rustc_attr!(
+        rustc_min_edition, Whitelisted,
+        template!(List: r#"edition = "...", /*opt*/ alternative = "...""#),
+        "the `#[rustc_min_edition]` attribute is just used to test edition-gated macros",
+    ),
+    rustc_attr!(
+        rustc_keep_macro_attr, Whitelisted, template!(Word),
+        "the `#[rustc_keep_macro_attr]` attribute keeps the attribute of an attribute macro \
+        on its output",
+    ),
+    rustc_attr!(
+        rustc_sees_unconfigured, Whitelisted, template!(Word),
+        "the `#[rustc_sees_unconfigured]` attribute lets an attribute macro see items \
+        disabled by a `#[cfg]` following its attribute",
+    ),
+    rustc_attr!(
+        rustc_pure_macro, Whitelisted, template!(Word),
+        "the `#[rustc_pure_macro]` attribute declares that a macro doesn't read files or \
+        environment variables and expands the same input to the same output",
+    ),
+    rustc_attr!(
+        rustc_expr_attr, Whitelisted, template!(Word),
+        "the `#[rustc_expr_attr]` attribute makes an attribute macro apply to expressions, \
+        including `if` expressions",
+    ),
+    rustc_attr!(
+        rustc_helper_attr_scope, Whitelisted, template!(List: "helper(item, fields, variants)"),
+        "the `#[rustc_helper_attr_scope]` attribute restricts where the helper attributes of a \
+        derive macro are used",
+    ),
 
     // ==========================================================================
     // Internal attributes, Diagnostics related:
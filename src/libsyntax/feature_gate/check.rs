@@ -11,10 +11,9 @@ use crate::attr::{self, check_builtin_attribute};
 use crate::source_map::Spanned;
 use crate::edition::{ALL_EDITIONS, Edition};
 use crate::visit::{self, FnKind, Visitor};
-use crate::parse::{token, ParseSess};
+use crate::parse::ParseSess;
 use crate::parse::parser::Parser;
 use crate::symbol::{Symbol, sym};
-use crate::tokenstream::TokenTree;
 
 use errors::{Applicability, DiagnosticBuilder, Handler};
 use rustc_data_structures::fx::FxHashMap;
@@ -312,6 +311,21 @@ impl<'a> PostExpansionVisitor<'a> {
             Abi::System => {}
         }
     }
+
+    /// Warns about the `suggestion` of a `#[deprecated]` attribute among `attrs`, which are the
+    /// attributes of something other than a function-like macro.
+    fn check_ignored_deprecation_suggestion(&self, attrs: &[ast::Attribute]) {
+        for attr in attr::filter_by_name(attrs, sym::deprecated) {
+            let list = attr.meta_item_list().unwrap_or_default();
+            if let Some(suggestion) = list.iter().find(|item| item.check_name(sym::suggestion)) {
+                self.context.parse_sess.span_diagnostic
+                    .struct_span_warn(suggestion.span(), "`suggestion` is ignored on this item")
+                    .note("replacements are only suggested for invocations of function-like \
+                           macros")
+                    .emit();
+            }
+        }
+    }
 }
 
 impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
@@ -349,15 +363,21 @@ impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
             }
         }
 
+        if attr.check_name(sym::deprecated) {
+            let list = attr.meta_item_list().unwrap_or_default();
+            if let Some(suggestion) = list.iter().find(|item| item.check_name(sym::suggestion)) {
+                gate_feature_post!(&self, deprecated_suggestion, suggestion.span(),
+                                   "suggestions on deprecated items are experimental");
+            }
+        }
+
         match attr_info {
             // `rustc_dummy` doesn't have any restrictions specific to built-in attributes.
             Some(&(name, _, template, _)) if name != sym::rustc_dummy =>
                 check_builtin_attribute(self.context.parse_sess, attr, name, template),
-            _ => if let Some(TokenTree::Token(token)) = attr.tokens.trees().next() {
-                if token == token::Eq {
-                    // All key-value attributes are restricted to meta-item syntax.
-                    attr.parse_meta(self.context.parse_sess).map_err(|mut err| err.emit()).ok();
-                }
+            _ => if let ast::AttrArgs::Eq(..) = attr.args {
+                // All key-value attributes are restricted to meta-item syntax.
+                attr.parse_meta(self.context.parse_sess).map_err(|mut err| err.emit()).ok();
             }
         }
     }
@@ -479,6 +499,13 @@ impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
             _ => {}
         }
 
+        // Replacements are only suggested for invocations of function-like macros.
+        match i.node {
+            ast::ItemKind::MacroDef(..) => {}
+            ast::ItemKind::Fn(..) if attr::contains_name(&i.attrs, sym::proc_macro) => {}
+            _ => self.check_ignored_deprecation_suggestion(&i.attrs),
+        }
+
         visit::walk_item(self, i);
     }
 
@@ -502,6 +529,7 @@ impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
             }
             ast::ForeignItemKind::Macro(..) => {}
         }
+        self.check_ignored_deprecation_suggestion(&i.attrs);
 
         visit::walk_foreign_item(self, i)
     }
@@ -665,6 +693,7 @@ impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
             }
             _ => {}
         }
+        self.check_ignored_deprecation_suggestion(&ti.attrs);
         visit::walk_trait_item(self, ti)
     }
 
@@ -697,6 +726,7 @@ impl<'a> Visitor<'a> for PostExpansionVisitor<'a> {
             }
             _ => {}
         }
+        self.check_ignored_deprecation_suggestion(&ii.attrs);
         visit::walk_impl_item(self, ii)
     }
 
@@ -897,6 +927,10 @@ pub fn check_crate(krate: &ast::Crate,
     gate_all!(async_closure, "async closures are unstable");
     gate_all!(yields, generators, "yield syntax is experimental");
     gate_all!(or_patterns, "or-patterns syntax is experimental");
+    gate_all!(arm_macros, macros_in_arm_position,
+              "macro invocations in `match` arm position are unstable");
+    gate_all!(param_macros, macros_in_param_position,
+              "macro invocations in parameter position are unstable");
 
     let visitor = &mut PostExpansionVisitor {
         context: &ctx,
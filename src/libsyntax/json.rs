@@ -25,6 +25,9 @@ use std::sync::{Arc, Mutex};
 
 use rustc_serialize::json::{as_json, as_pretty_json};
 
+#[cfg(test)]
+mod tests;
+
 pub struct JsonEmitter {
     dst: Box<dyn Write + Send>,
     registry: Option<Registry>,
@@ -103,6 +106,18 @@ impl Emitter for JsonEmitter {
             panic!("failed to print notification: {:?}", e);
         }
     }
+
+    fn emit_expansion_artifact(&mut self, kind: &str, payload: &str) {
+        let data = ExpansionArtifact { expansion_artifact: kind, payload };
+        let result = if self.pretty {
+            writeln!(&mut self.dst, "{}", as_pretty_json(&data))
+        } else {
+            writeln!(&mut self.dst, "{}", as_json(&data))
+        };
+        if let Err(e) = result {
+            panic!("failed to print expansion artifact: {:?}", e);
+        }
+    }
 }
 
 // The following data types are provided just for serialisation.
@@ -188,6 +203,13 @@ struct ArtifactNotification<'a> {
     emit: &'a str,
 }
 
+#[derive(RustcEncodable)]
+struct ExpansionArtifact<'a> {
+    /// What kind of artifact the macro recorded, see `ExtCtxt::emit_artifact`.
+    expansion_artifact: &'a str,
+    payload: &'a str,
+}
+
 impl Diagnostic {
     fn from_diagnostic_builder(db: &DiagnosticBuilder<'_>,
                                je: &JsonEmitter)
@@ -0,0 +1,24 @@
+use super::*;
+
+use crate::tests::Shared;
+use crate::with_default_globals;
+
+use errors::{ColorConfig, Handler};
+
+#[test]
+fn expansion_artifacts_are_written_on_their_own_line() {
+    with_default_globals(|| {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let source_map = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let json_rendered = HumanReadableErrorType::Default(ColorConfig::Never);
+        let emitter = JsonEmitter::new(Box::new(Shared { data: output.clone() }), None,
+                                       source_map, false, json_rendered);
+        let handler = Handler::with_emitter(true, None, Box::new(emitter));
+
+        handler.emit_expansion_artifact("macro_dry_run", r#"{"items":["f"]}"#);
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(output, concat!(r#"{"expansion_artifact":"macro_dry_run","#,
+                                   r#""payload":"{\"items\":[\"f\"]}"}"#, "\n"));
+    })
+}
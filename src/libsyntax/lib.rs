@@ -14,11 +14,13 @@
 #![feature(crate_visibility_modifier)]
 #![feature(label_break_value)]
 #![feature(mem_take)]
+#![feature(never_type)]
 #![feature(nll)]
 #![feature(proc_macro_diagnostic)]
 #![feature(proc_macro_internals)]
 #![feature(proc_macro_span)]
 #![feature(rustc_diagnostic_macros)]
+#![feature(specialization)]
 #![feature(try_trait)]
 #![feature(unicode_internals)]
 
@@ -131,15 +133,19 @@ pub mod diagnostics {
 pub mod error_codes;
 
 pub mod util {
+    pub mod iterative_drop;
     pub mod lev_distance;
     pub mod node_count;
     pub mod parser;
     pub mod map_in_place;
+    pub mod spanless_eq;
 }
 
 pub mod json;
 
 pub mod ast;
+pub mod ast_hash;
+pub mod ast_util;
 pub mod attr;
 pub mod source_map;
 #[macro_use]
@@ -149,6 +155,7 @@ pub mod feature_gate;
 pub mod mut_visit;
 pub mod parse;
 pub mod ptr;
+pub mod rewrite;
 pub mod show_span;
 pub use syntax_pos::edition;
 pub use syntax_pos::symbol;
@@ -158,25 +165,37 @@ pub mod visit;
 pub mod print {
     pub mod pp;
     pub mod pprust;
+    pub mod round_trip;
     mod helpers;
 }
 
 pub mod ext {
     mod placeholders;
     mod proc_macro_server;
+    mod validate;
 
     pub use syntax_pos::hygiene;
+    pub use invocations::{collect_invocations, InvocationInfo};
     pub mod allocator;
     pub mod base;
+    pub mod basic_resolver;
     pub mod build;
+    pub mod diagnostics;
     pub mod expand;
+    pub mod format_string;
+    pub mod invocations;
+    pub mod macro_trace;
+    pub mod manifest;
     pub mod proc_macro;
+    pub mod pure_macro;
 
     pub mod tt {
         pub mod transcribe;
+        pub mod macro_backtracking;
         pub mod macro_check;
         pub mod macro_parser;
         pub mod macro_rules;
+        pub mod match_cache;
         pub mod quoted;
     }
 }
@@ -70,6 +70,14 @@ pub trait MutVisitor: Sized {
     // field access version will continue working and it would be easy to
     // forget to add handling for it.
 
+    /// Whether tokens are visited down into the AST nodes of interpolated nonterminals, see
+    /// `noop_visit_interpolated`.
+    ///
+    /// Nonterminals are shared between token streams, so they are cloned to be visited. Only
+    /// visitors that rewrite every span or identifier of a token stream, e.g., to change its
+    /// hygiene, need to opt into this; by default, interpolated tokens are left alone.
+    const VISIT_INTERPOLATED: bool = false;
+
     fn visit_crate(&mut self, c: &mut Crate) {
         noop_visit_crate(c, self)
     }
@@ -552,9 +560,31 @@ pub fn noop_visit_local<T: MutVisitor>(local: &mut P<Local>, vis: &mut T) {
 }
 
 pub fn noop_visit_attribute<T: MutVisitor>(attr: &mut Attribute, vis: &mut T) {
-    let Attribute { id: _, style: _, path, tokens, is_sugared_doc: _, span } = attr;
+    let Attribute { id: _, style: _, path, args, is_sugared_doc: _, span } = attr;
     vis.visit_path(path);
-    vis.visit_tts(tokens);
+    match args {
+        AttrArgs::Empty => {}
+        AttrArgs::Delimited(DelimSpan { open, close }, _delim, tokens) => {
+            vis.visit_span(open);
+            vis.visit_span(close);
+            vis.visit_tts(tokens);
+        }
+        AttrArgs::Eq(eq_span, expr) => {
+            vis.visit_span(eq_span);
+            // A value that isn't a literal comes from an interpolated expression, e.g., the
+            // `$doc` of `#[doc = $doc]`, which is only visited by visitors that visit
+            // interpolated tokens.
+            let Expr { node, span, .. } = &mut **expr;
+            if let ExprKind::Lit(lit) = node {
+                vis.visit_span(&mut lit.span);
+                vis.visit_span(span);
+            } else if T::VISIT_INTERPOLATED {
+                vis.visit_expr(expr);
+            } else {
+                vis.visit_span(span);
+            }
+        }
+    }
     vis.visit_span(span);
 }
 
@@ -617,7 +647,8 @@ pub fn noop_visit_tts<T: MutVisitor>(TokenStream(tts): &mut TokenStream, vis: &m
     })
 }
 
-// Apply ident visitor if it's an ident, apply other visits to interpolated nodes.
+// Apply ident visitor if it's an ident, apply other visits to interpolated nodes (only for
+// visitors with `VISIT_INTERPOLATED`).
 // In practice the ident part is not actually used by specific visitors right now,
 // but there's a test below checking that it works.
 pub fn noop_visit_token<T: MutVisitor>(t: &mut Token, vis: &mut T) {
@@ -630,7 +661,7 @@ pub fn noop_visit_token<T: MutVisitor>(t: &mut Token, vis: &mut T) {
             *span = ident.span;
             return; // avoid visiting the span for the second time
         }
-        token::Interpolated(nt) => {
+        token::Interpolated(nt) if T::VISIT_INTERPOLATED => {
             let mut nt = Lrc::make_mut(nt);
             vis.visit_interpolated(&mut nt);
         }
@@ -640,6 +671,7 @@ pub fn noop_visit_token<T: MutVisitor>(t: &mut Token, vis: &mut T) {
 }
 
 /// Apply visitor to elements of interpolated nodes.
+/// Only called for visitors with `MutVisitor::VISIT_INTERPOLATED`.
 //
 // N.B., this can occur only when applying a visitor to partially expanded
 // code, where parsed pieces have gotten implanted ito *other* macro
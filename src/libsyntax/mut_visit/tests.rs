@@ -1,7 +1,9 @@
 use super::*;
 
 use crate::ast::{self, Ident};
-use crate::tests::{string_to_crate, matches_codepattern};
+use crate::parse::ParseSess;
+use crate::source_map::FilePathMapping;
+use crate::tests::{string_to_crate, matches_codepattern, with_error_checking_parse};
 use crate::print::pprust;
 use crate::mut_visit;
 use crate::with_default_globals;
@@ -69,3 +71,59 @@ macro_rules! assert_pred {
             "macro_rules! zz{(zz$zz:zz$(zz $zz:zz)zz+=>(zz$(zz$zz$zz)+))}".to_string());
     })
 }
+
+// ...and inside interpolated nonterminals, but only on request
+#[test] fn ident_transformation_in_interpolated () {
+    struct ToZzInterpolatedIdentMutVisitor;
+
+    impl MutVisitor for ToZzInterpolatedIdentMutVisitor {
+        const VISIT_INTERPOLATED: bool = true;
+
+        fn visit_ident(&mut self, ident: &mut ast::Ident) {
+            *ident = Ident::from_str("zz");
+        }
+        fn visit_mac(&mut self, mac: &mut ast::Mac) {
+            mut_visit::noop_visit_mac(mac, self)
+        }
+    }
+
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let expr = with_error_checking_parse("a + f!(b)".to_string(), &ps, |p| p.parse_expr());
+        let nt = Lrc::new(token::NtExpr(expr));
+        let stream = || -> TokenStream {
+            TokenTree::token(token::Interpolated(nt.clone()), syntax_pos::DUMMY_SP).into()
+        };
+
+        let mut untouched = stream();
+        ToZzIdentMutVisitor.visit_tts(&mut untouched);
+        assert_eq!(pprust::tts_to_string(untouched), "a + f!(b)");
+
+        let mut visited = stream();
+        ToZzInterpolatedIdentMutVisitor.visit_tts(&mut visited);
+        assert_eq!(pprust::tts_to_string(visited), "zz + zz!(zz)");
+        // The nonterminal is shared, it's cloned to be visited.
+        assert_eq!(pprust::tts_to_string(stream()), "a + f!(b)");
+    })
+}
+
+// attribute values that aren't literals are only visited like interpolated expressions
+#[test] fn ident_transformation_in_attribute_values () {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let expr = with_error_checking_parse("b + c".to_string(), &ps, |p| p.parse_expr());
+        let mut krate = string_to_crate("#[a = 1] fn d() {}".to_string());
+        let attr = &mut krate.module.items[0].attrs[0];
+        if let ast::AttrArgs::Eq(_, value) = &mut attr.args {
+            *value = expr;
+        }
+
+        let mut zz_visitor = ToZzIdentMutVisitor;
+        zz_visitor.visit_crate(&mut krate);
+        assert_pred!(
+            matches_codepattern,
+            "matches_codepattern",
+            pprust::to_string(|s| fake_print_crate(s, &krate)),
+            "#[zz = b + c]fn zz(){}".to_string());
+    })
+}
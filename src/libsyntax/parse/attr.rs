@@ -3,11 +3,11 @@ use crate::ast;
 use crate::parse::{SeqSep, PResult};
 use crate::parse::token::{self, Nonterminal, DelimToken};
 use crate::parse::parser::{Parser, TokenType, PathStyle};
-use crate::tokenstream::{TokenStream, TokenTree};
+use crate::tokenstream::TokenTree;
 use crate::source_map::Span;
+use crate::ThinVec;
 
 use log::debug;
-use smallvec::smallvec;
 
 #[derive(Debug)]
 enum InnerAttributeParsePolicy<'a> {
@@ -97,7 +97,7 @@ impl<'a> Parser<'a> {
         debug!("parse_attribute_with_inner_parse_policy: inner_parse_policy={:?} self.token={:?}",
                inner_parse_policy,
                self.token);
-        let (span, path, tokens, style) = match self.token.kind {
+        let (span, path, args, style) = match self.token.kind {
             token::Pound => {
                 let lo = self.token.span;
                 self.bump();
@@ -114,7 +114,7 @@ impl<'a> Parser<'a> {
                 };
 
                 self.expect(&token::OpenDelim(token::Bracket))?;
-                let (path, tokens) = self.parse_meta_item_unrestricted()?;
+                let (path, args) = self.parse_meta_item_unrestricted()?;
                 self.expect(&token::CloseDelim(token::Bracket))?;
                 let hi = self.prev_span;
 
@@ -149,7 +149,7 @@ impl<'a> Parser<'a> {
                     }
                 }
 
-                (attr_sp, path, tokens, style)
+                (attr_sp, path, args, style)
             }
             _ => {
                 let token_str = self.this_token_to_string();
@@ -161,22 +161,21 @@ impl<'a> Parser<'a> {
             id: attr::mk_attr_id(),
             style,
             path,
-            tokens,
+            args,
             is_sugared_doc: false,
             span,
         })
     }
 
-    /// Parse an inner part of attribute - path and following tokens.
-    /// The tokens must be either a delimited token stream, or empty token stream,
+    /// Parse an inner part of attribute - path and following arguments.
+    /// The arguments must be either a delimited token stream, or nothing,
     /// or the "legacy" key-value form.
     /// PATH `(` TOKEN_STREAM `)`
     /// PATH `[` TOKEN_STREAM `]`
     /// PATH `{` TOKEN_STREAM `}`
     /// PATH
-    /// PATH `=` TOKEN_TREE
-    /// The delimiters or `=` are still put into the resulting token stream.
-    crate fn parse_meta_item_unrestricted(&mut self) -> PResult<'a, (ast::Path, TokenStream)> {
+    /// PATH `=` LITERAL
+    crate fn parse_meta_item_unrestricted(&mut self) -> PResult<'a, (ast::Path, ast::AttrArgs)> {
         let meta = match self.token.kind {
             token::Interpolated(ref nt) => match **nt {
                 Nonterminal::NtMeta(ref meta) => Some(meta.clone()),
@@ -186,34 +185,43 @@ impl<'a> Parser<'a> {
         };
         Ok(if let Some(meta) = meta {
             self.bump();
-            (meta.path, meta.node.tokens(meta.span))
+            (meta.path, meta.node.attr_args(meta.span))
         } else {
             let path = self.parse_path(PathStyle::Mod)?;
-            let tokens = if self.check(&token::OpenDelim(DelimToken::Paren)) ||
+            let args = if self.check(&token::OpenDelim(DelimToken::Paren)) ||
                self.check(&token::OpenDelim(DelimToken::Bracket)) ||
                self.check(&token::OpenDelim(DelimToken::Brace)) {
-                   self.parse_token_tree().into()
-            } else if self.eat(&token::Eq) {
-                let eq = TokenTree::token(token::Eq, self.prev_span);
-                let mut is_interpolated_expr = false;
-                if let token::Interpolated(nt) = &self.token.kind {
-                    if let token::NtExpr(..) = **nt {
-                        is_interpolated_expr = true;
+                match self.parse_token_tree() {
+                    TokenTree::Delimited(dspan, delim, tokens) => {
+                        let delim = ast::MacDelimiter::from_token(delim).unwrap();
+                        ast::AttrArgs::Delimited(dspan, delim, tokens)
                     }
+                    _ => unreachable!(),
                 }
-                let tokens = if is_interpolated_expr {
+            } else if self.eat(&token::Eq) {
+                let eq_span = self.prev_span;
+                let interpolated_expr = match &self.token.kind {
+                    token::Interpolated(nt) => match **nt {
+                        token::NtExpr(ref expr) => Some(expr.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let expr = if let Some(expr) = interpolated_expr {
                     // We need to accept arbitrary interpolated expressions to continue
                     // supporting things like `doc = $expr` that work on stable.
                     // Non-literal interpolated expressions are rejected after expansion.
-                    self.parse_token_tree().into()
+                    self.bump();
+                    expr
                 } else {
-                    self.parse_unsuffixed_lit()?.tokens()
+                    let lit = self.parse_unsuffixed_lit()?;
+                    self.mk_expr(lit.span, ast::ExprKind::Lit(lit), ThinVec::new())
                 };
-                TokenStream::from_streams(smallvec![eq.into(), tokens])
+                ast::AttrArgs::Eq(eq_span, expr)
             } else {
-                TokenStream::empty()
+                ast::AttrArgs::Empty
             };
-            (path, tokens)
+            (path, args)
         })
     }
 
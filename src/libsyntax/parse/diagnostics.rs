@@ -1,6 +1,6 @@
 use crate::ast::{
     self, Param, BinOpKind, BindingMode, BlockCheckMode, Expr, ExprKind, Ident, Item, ItemKind,
-    Mutability, Pat, PatKind, PathSegment, QSelf, Ty, TyKind, VariantData,
+    Mac, MacDelimiter, Mutability, Pat, PatKind, PathSegment, QSelf, Ty, TyKind, VariantData,
 };
 use crate::feature_gate::{feature_err, UnstableFeatures};
 use crate::parse::{SeqSep, PResult, Parser, ParseSess};
@@ -8,7 +8,7 @@ use crate::parse::parser::{BlockMode, PathStyle, SemiColonMode, TokenType, Token
 use crate::parse::token::{self, TokenKind};
 use crate::print::pprust;
 use crate::ptr::P;
-use crate::symbol::{kw, sym};
+use crate::symbol::{kw, sym, EditionKeywords};
 use crate::ThinVec;
 use crate::util::parser::AssocOp;
 use errors::{Applicability, DiagnosticBuilder, DiagnosticId};
@@ -209,6 +209,10 @@ impl<'a> Parser<'a> {
         );
         if let token::Ident(name, false) = self.token.kind {
             if Ident::new(name, self.token.span).is_raw_guess() {
+                if let Some(keyword) = EditionKeywords::get(name) {
+                    err.note(&format!("`{}` is a keyword since the {} edition",
+                                      name, keyword.edition));
+                }
                 err.span_suggestion(
                     self.token.span,
                     "you can escape reserved keywords to use them as identifiers",
@@ -332,7 +336,7 @@ impl<'a> Parser<'a> {
         );
         let sm = self.sess.source_map();
         match (sm.lookup_line(self.token.span.lo()), sm.lookup_line(sp.lo())) {
-            (Ok(ref a), Ok(ref b)) if a.line != b.line && is_semi_suggestable => {
+            (Ok(ref a), Ok(ref b)) if a.line != b.line && is_semi_suggestable && self.recover => {
                 // The spans are in different lines, expected `;` and found `let` or `return`.
                 // High likelihood that it is only a missing `;`.
                 err.span_suggestion_short(
@@ -772,6 +776,11 @@ impl<'a> Parser<'a> {
                         name
                     ));
                 }
+                if let ItemKind::Mac(Mac { delim: MacDelimiter::Brace, .. }) = previous_item.node {
+                    err.help(
+                        "macro invocations delimited with braces are not followed by a semicolon",
+                    );
+                }
             }
             err.emit();
             true
@@ -1019,6 +1028,9 @@ impl<'a> Parser<'a> {
         tokens: &[TokenKind],
         mut err: DiagnosticBuilder<'a>,
     ) -> PResult<'a, bool> {
+        if !self.recover {
+            return Err(err);
+        }
         let mut pos = None;
         // we want to use the last closing delim that would apply
         for (i, unmatched) in self.unclosed_delims.iter().enumerate().rev() {
@@ -1079,6 +1091,88 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Recovers from attributes and repeated `pub`s after the visibility `vis` of an item
+    /// starting at `lo`, e.g., `pub #[inline] fn f() {}` or `pub pub fn f() {}`. See
+    /// `recover_misplaced_attrs`.
+    crate fn recover_misplaced_attrs_and_vis(
+        &mut self,
+        lo: Span,
+        vis: &ast::Visibility,
+        attrs: &mut Vec<ast::Attribute>,
+    ) -> PResult<'a, ()> {
+        if let ast::VisibilityKind::Inherited = vis.node {
+            return Ok(());
+        }
+        let vis_text = self.sess.source_map().token_text(vis.span)
+            .map_or_else(|| "pub".to_string(), |text| text.to_string());
+        loop {
+            if self.check(&token::Pound) {
+                self.recover_misplaced_attrs(lo, &vis_text, attrs)?;
+            } else if self.token.is_keyword(kw::Pub) {
+                let dup = self.parse_visibility(false)?;
+                let same_vis = self.sess.source_map().token_text(dup.span)
+                    .map_or(false, |text| *text == vis_text);
+                let applicability = if same_vis {
+                    Applicability::MachineApplicable
+                } else {
+                    Applicability::MaybeIncorrect
+                };
+                let dup_span = dup.span.with_hi(self.token.span.lo());
+                self.struct_span_err(dup_span, "duplicate visibility qualifier")
+                    .span_label(vis.span, "visibility first given here")
+                    .span_suggestion(
+                        dup_span,
+                        "remove the duplicate",
+                        String::new(),
+                        applicability,
+                    )
+                    .emit();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Recovers from outer attributes following `prev`, e.g., the visibility or the keyword of
+    /// an item starting at `lo`, like in `fn #[inline] f() {}`. The attributes are appended to
+    /// `attrs`, so they still apply to the item, attribute macros included, and moving them to
+    /// the start of the item is suggested.
+    crate fn recover_misplaced_attrs(
+        &mut self,
+        lo: Span,
+        prev: &str,
+        attrs: &mut Vec<ast::Attribute>,
+    ) -> PResult<'a, ()> {
+        if !self.check(&token::Pound) {
+            return Ok(());
+        }
+        let misplaced = self.parse_outer_attributes()?;
+        let span = misplaced[0].span.to(misplaced[misplaced.len() - 1].span);
+        let (msg, noun) = if misplaced.len() == 1 {
+            ("an attribute", "attribute")
+        } else {
+            ("attributes", "attributes")
+        };
+        let mut err = self.struct_span_err(
+            span,
+            &format!("{} must come before `{}`", msg, prev),
+        );
+        err.span_label(span, format!("not allowed after `{}`", prev));
+        if let Ok(snippet) = self.span_to_snippet(span) {
+            err.multipart_suggestion(
+                &format!("move the {} to the start of the item", noun),
+                vec![
+                    (span.with_hi(self.token.span.lo()), String::new()),
+                    (lo.shrink_to_lo(), format!("{} ", snippet)),
+                ],
+                Applicability::MachineApplicable,
+            );
+        }
+        err.emit();
+        attrs.extend(misplaced);
+        Ok(())
+    }
+
     // Eat tokens until we can be relatively sure we reached the end of the
     // statement. This is something of a best-effort heuristic.
     //
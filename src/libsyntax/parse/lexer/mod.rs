@@ -1,14 +1,17 @@
 use crate::parse::ParseSess;
 use crate::parse::token::{self, Token, TokenKind};
-use crate::symbol::{sym, Symbol};
+use crate::symbol::{kw, sym, EditionKeywords, Symbol};
 use crate::parse::unescape_error_reporting::{emit_unescape_error, push_escaped_char};
+use crate::tokenstream::IsJoint;
 
 use errors::{FatalError, DiagnosticBuilder};
-use syntax_pos::{BytePos, Pos, Span};
+use syntax_pos::{BytePos, FileName, Pos, Span, DUMMY_SP};
 use rustc_lexer::Base;
 use rustc_lexer::unescape;
 
+use std::cell::Cell;
 use std::char;
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use rustc_data_structures::sync::Lrc;
 use log::debug;
@@ -20,13 +23,38 @@ pub mod comments;
 mod tokentrees;
 mod unicode_chars;
 
+/// A close delimiter that doesn't match the innermost open delimiter, or, as found by
+/// `parse::detect_unclosed_delims`, an open delimiter left unclosed at the end of the file
+/// (with `found_delim` being `NoDelim`) or a close delimiter without any open one (with
+/// `expected_delim` being `NoDelim`).
 #[derive(Clone, Debug)]
 pub struct UnmatchedBrace {
     pub expected_delim: token::DelimToken,
     pub found_delim: token::DelimToken,
     pub found_span: Span,
     pub unclosed_span: Option<Span>,
+    /// An enclosing open delimiter that the found close delimiter was likely meant for,
+    /// judging by their indentation.
     pub candidate_span: Option<Span>,
+    /// An empty span where inserting the expected close delimiter would likely fix the
+    /// mismatch, if one can be guessed.
+    pub insertion_point: Option<Span>,
+}
+
+/// A delimiter that is still open at the end of a file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnclosedDelim {
+    pub delim: token::DelimToken,
+    pub open_span: Span,
+}
+
+/// An ordinary comment, kept as trivia of the token following it if
+/// `ParseSess::preserve_comments` is set.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CommentTrivia {
+    /// The text of the comment, including `//`, or `/*` and `*/`.
+    pub text: String,
+    pub span: Span,
 }
 
 pub struct StringReader<'a> {
@@ -40,6 +68,11 @@ pub struct StringReader<'a> {
     /// Source text to tokenize.
     src: Lrc<String>,
     override_span: Option<Span>,
+    /// Whether a fatal error stops lexing, making the rest of the source lex as `Eof`, rather
+    /// than unwinding. Set for the reader of `StringReader::tokens`.
+    stop_on_fatal: bool,
+    /// Whether lexing was stopped by a fatal error, see `stop_on_fatal`.
+    stopped: Cell<bool>,
 }
 
 impl<'a> StringReader<'a> {
@@ -60,9 +93,13 @@ impl<'a> StringReader<'a> {
             end_src_index: src.len(),
             src,
             override_span,
+            stop_on_fatal: false,
+            stopped: Cell::new(false),
         }
     }
 
+    /// Lexes the source text at `span`, and only that text: the first token starts at
+    /// `span.lo()`, not at the start of the file.
     pub fn retokenize(sess: &'a ParseSess, mut span: Span) -> Self {
         let begin = sess.source_map().lookup_byte_offset(span.lo());
         let end = sess.source_map().lookup_byte_offset(span.hi());
@@ -75,12 +112,30 @@ impl<'a> StringReader<'a> {
         let mut sr = StringReader::new(sess, begin.sf, None);
 
         // Seek the lexer to the right byte range.
+        sr.pos = span.lo();
         sr.end_src_index = sr.src_index(span.hi());
 
         sr
     }
 
 
+    /// Lexes `src` as a new file of the source map of `sess`, named `tokens`. The errors found
+    /// are captured from `sess` instead of being emitted.
+    ///
+    /// Whitespace and comments are returned as tokens. Errors are returned before the tokens in
+    /// which they were found; lexing stops after a fatal error, and the token in which it was
+    /// found isn't returned. The token spacing tells whether a token is immediately followed by
+    /// the next non-trivia token.
+    ///
+    /// Like any use of `Symbol`, this must be called within `syntax::with_globals`.
+    pub fn tokens(sess: &'a ParseSess, src: &str) -> Tokens<'a> {
+        let name = FileName::Custom("tokens".to_string());
+        let source_file = sess.source_map().new_source_file(name, src.to_string());
+        let mut reader = StringReader::new(sess, source_file, None);
+        reader.stop_on_fatal = true;
+        Tokens { reader, prev: None, queue: VecDeque::new(), done: false }
+    }
+
     fn mk_sp(&self, lo: BytePos, hi: BytePos) -> Span {
         self.override_span.unwrap_or_else(|| Span::with_root_ctxt(lo, hi))
     }
@@ -93,7 +148,7 @@ impl<'a> StringReader<'a> {
         let start_src_index = self.src_index(self.pos);
         let text: &str = &self.src[start_src_index..self.end_src_index];
 
-        if text.is_empty() {
+        if text.is_empty() || self.stopped.get() {
             let span = self.mk_sp(self.pos, self.pos);
             return Token::new(token::Eof, span);
         }
@@ -134,6 +189,16 @@ impl<'a> StringReader<'a> {
         self.sess.span_diagnostic.span_fatal(sp, m)
     }
 
+    /// Stops lexing after `err`, a reported fatal error, by unwinding, or with
+    /// `stop_on_fatal`, by making the rest of the source lex as `Eof`. In the latter case, the
+    /// caller still finishes the current token, as an error if need be.
+    fn stop_after(&self, err: FatalError) {
+        if !self.stop_on_fatal {
+            err.raise();
+        }
+        self.stopped.set(true);
+    }
+
     /// Report a lexical error with a given span.
     fn err_span(&self, sp: Span, m: &str) {
         self.sess.span_diagnostic.struct_span_err(sp, m).emit();
@@ -200,7 +265,7 @@ impl<'a> StringReader<'a> {
                         "unterminated block comment"
                     };
                     let last_bpos = self.pos;
-                    self.fatal_span_(start, last_bpos, msg).raise();
+                    self.stop_after(self.fatal_span_(start, last_bpos, msg));
                 }
 
                 let tok = if is_doc_comment {
@@ -228,7 +293,9 @@ impl<'a> StringReader<'a> {
                     if !sym.can_be_raw() {
                         self.err_span(span, &format!("`{}` cannot be a raw identifier", sym));
                     }
-                    self.sess.raw_identifier_spans.borrow_mut().push(span);
+                    if EditionKeywords::get(sym).is_some() {
+                        self.sess.raw_identifier_spans.borrow_mut().push(span);
+                    }
                 }
                 token::Ident(sym, is_raw_ident)
             }
@@ -327,9 +394,10 @@ impl<'a> StringReader<'a> {
         match kind {
             rustc_lexer::LiteralKind::Char { terminated } => {
                 if !terminated {
-                    self.fatal_span_(start, suffix_start,
-                                     "unterminated character literal".into())
-                        .raise()
+                    let msg = "unterminated character literal";
+                    let err = self.fatal_span_(start, suffix_start, msg);
+                    self.stop_after(err);
+                    return (token::Err, kw::Invalid);
                 }
                 let content_start = start + BytePos(1);
                 let content_end = suffix_start - BytePos(1);
@@ -339,9 +407,10 @@ impl<'a> StringReader<'a> {
             },
             rustc_lexer::LiteralKind::Byte { terminated } => {
                 if !terminated {
-                    self.fatal_span_(start + BytePos(1), suffix_start,
-                                     "unterminated byte constant".into())
-                        .raise()
+                    let msg = "unterminated byte constant";
+                    let err = self.fatal_span_(start + BytePos(1), suffix_start, msg);
+                    self.stop_after(err);
+                    return (token::Err, kw::Invalid);
                 }
                 let content_start = start + BytePos(2);
                 let content_end = suffix_start - BytePos(1);
@@ -351,24 +420,34 @@ impl<'a> StringReader<'a> {
             },
             rustc_lexer::LiteralKind::Str { terminated } => {
                 if !terminated {
-                    self.fatal_span_(start, suffix_start,
-                                     "unterminated double quote string".into())
-                        .raise()
+                    let msg = "unterminated double quote string";
+                    let err = self.fatal_span_(start, suffix_start, msg);
+                    self.stop_after(err);
+                    return (token::Err, kw::Invalid);
                 }
                 let content_start = start + BytePos(1);
                 let content_end = suffix_start - BytePos(1);
+                if self.literal_too_long(start, suffix_start, content_start, content_end,
+                                         token::Str) {
+                    return (token::Err, kw::Invalid);
+                }
                 self.validate_str_escape(content_start, content_end);
                 let id = self.symbol_from_to(content_start, content_end);
                 (token::Str, id)
             }
             rustc_lexer::LiteralKind::ByteStr { terminated } => {
                 if !terminated {
-                    self.fatal_span_(start + BytePos(1), suffix_start,
-                                     "unterminated double quote byte string".into())
-                        .raise()
+                    let msg = "unterminated double quote byte string";
+                    let err = self.fatal_span_(start + BytePos(1), suffix_start, msg);
+                    self.stop_after(err);
+                    return (token::Err, kw::Invalid);
                 }
                 let content_start = start + BytePos(2);
                 let content_end = suffix_start - BytePos(1);
+                if self.literal_too_long(start, suffix_start, content_start, content_end,
+                                         token::ByteStr) {
+                    return (token::Err, kw::Invalid);
+                }
                 self.validate_byte_str_escape(content_start, content_end);
                 let id = self.symbol_from_to(content_start, content_end);
                 (token::ByteStr, id)
@@ -376,14 +455,23 @@ impl<'a> StringReader<'a> {
             rustc_lexer::LiteralKind::RawStr { n_hashes, started, terminated } => {
                 if !started {
                     self.report_non_started_raw_string(start);
+                    return (token::Err, kw::Invalid);
                 }
                 if !terminated {
-                    self.report_unterminated_raw_string(start, n_hashes)
+                    self.report_unterminated_raw_string(start, n_hashes);
+                    return (token::Err, kw::Invalid);
                 }
-                let n_hashes: u16 = self.restrict_n_hashes(start, n_hashes);
+                let n_hashes: u16 = match self.restrict_n_hashes(start, n_hashes) {
+                    Some(n_hashes) => n_hashes,
+                    None => return (token::Err, kw::Invalid),
+                };
                 let n = u32::from(n_hashes);
                 let content_start = start + BytePos(2 + n);
                 let content_end = suffix_start - BytePos(1 + n);
+                if self.literal_too_long(start, suffix_start, content_start, content_end,
+                                         token::Str) {
+                    return (token::Err, kw::Invalid);
+                }
                 self.validate_raw_str_escape(content_start, content_end);
                 let id = self.symbol_from_to(content_start, content_end);
                 (token::StrRaw(n_hashes), id)
@@ -391,14 +479,23 @@ impl<'a> StringReader<'a> {
             rustc_lexer::LiteralKind::RawByteStr { n_hashes, started, terminated } => {
                 if !started {
                     self.report_non_started_raw_string(start);
+                    return (token::Err, kw::Invalid);
                 }
                 if !terminated {
-                    self.report_unterminated_raw_string(start, n_hashes)
+                    self.report_unterminated_raw_string(start, n_hashes);
+                    return (token::Err, kw::Invalid);
                 }
-                let n_hashes: u16 = self.restrict_n_hashes(start, n_hashes);
+                let n_hashes: u16 = match self.restrict_n_hashes(start, n_hashes) {
+                    Some(n_hashes) => n_hashes,
+                    None => return (token::Err, kw::Invalid),
+                };
                 let n = u32::from(n_hashes);
                 let content_start = start + BytePos(3 + n);
                 let content_end = suffix_start - BytePos(1 + n);
+                if self.literal_too_long(start, suffix_start, content_start, content_end,
+                                         token::ByteStr) {
+                    return (token::Err, kw::Invalid);
+                }
                 self.validate_raw_byte_str_escape(content_start, content_end);
                 let id = self.symbol_from_to(content_start, content_end);
                 (token::ByteStrRaw(n_hashes), id)
@@ -486,7 +583,7 @@ impl<'a> StringReader<'a> {
         }
     }
 
-    fn report_non_started_raw_string(&self, start: BytePos) -> ! {
+    fn report_non_started_raw_string(&self, start: BytePos) {
         let bad_char = self.str_from(start).chars().last().unwrap();
         self
             .struct_fatal_span_char(
@@ -497,10 +594,10 @@ impl<'a> StringReader<'a> {
                 bad_char,
             )
             .emit();
-        FatalError.raise()
+        self.stop_after(FatalError)
     }
 
-    fn report_unterminated_raw_string(&self, start: BytePos, n_hashes: usize) -> ! {
+    fn report_unterminated_raw_string(&self, start: BytePos, n_hashes: usize) {
         let mut err = self.struct_span_fatal(
             start, start,
             "unterminated raw string",
@@ -516,21 +613,37 @@ impl<'a> StringReader<'a> {
         }
 
         err.emit();
-        FatalError.raise()
+        self.stop_after(FatalError)
     }
 
-    fn restrict_n_hashes(&self, start: BytePos, n_hashes: usize) -> u16 {
+    fn restrict_n_hashes(&self, start: BytePos, n_hashes: usize) -> Option<u16> {
         match n_hashes.try_into() {
-            Ok(n_hashes) => n_hashes,
+            Ok(n_hashes) => Some(n_hashes),
             Err(_) => {
-                self.fatal_span_(start,
-                                 self.pos,
-                                 "too many `#` symbols: raw strings may be \
-                                  delimited by up to 65535 `#` symbols").raise();
+                self.stop_after(self.fatal_span_(start,
+                                                 self.pos,
+                                                 "too many `#` symbols: raw strings may be \
+                                                  delimited by up to 65535 `#` symbols"));
+                None
             }
         }
     }
 
+    /// Reports string literals with more than `ParseSess::max_literal_len` bytes of contents.
+    /// The caller turns them into error literals, so their contents are never validated or
+    /// interned.
+    fn literal_too_long(
+        &self,
+        start: BytePos,
+        end: BytePos,
+        content_start: BytePos,
+        content_end: BytePos,
+        kind: token::LitKind,
+    ) -> bool {
+        let len = (content_end - content_start).to_usize();
+        self.sess.literal_too_long(self.mk_sp(start, end), len, kind)
+    }
+
     fn validate_char_escape(&self, content_start: BytePos, content_end: BytePos) {
         let lit = self.str_from_to(content_start, content_end);
         if let Err((off, err)) = unescape::unescape_char(lit) {
@@ -643,6 +756,64 @@ impl<'a> StringReader<'a> {
     }
 }
 
+/// An error found by `StringReader::tokens`.
+#[derive(Clone, Debug)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+    /// Whether the lexer had to stop after this error.
+    pub is_fatal: bool,
+}
+
+/// The iterator returned by `StringReader::tokens`.
+pub struct Tokens<'a> {
+    reader: StringReader<'a>,
+    /// The last token lexed, held back until the next one determines its spacing.
+    prev: Option<Token>,
+    queue: VecDeque<Result<(Token, IsJoint), LexError>>,
+    done: bool,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<(Token, IsJoint), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.queue.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+
+            let sess = self.reader.sess;
+            let reader = &mut self.reader;
+            let (token, errors) = sess.span_diagnostic.capture_errors(|| reader.next_token());
+            let stopped = self.reader.stopped.get();
+            let is_joint = match token.kind {
+                _ if stopped => IsJoint::NonJoint,
+                token::Whitespace | token::Comment | token::Eof => IsJoint::NonJoint,
+                _ => IsJoint::Joint,
+            };
+            if let Some(prev) = self.prev.take() {
+                self.queue.push_back(Ok((prev, is_joint)));
+            }
+            // With `stopped`, the last error is the fatal one.
+            let last = errors.len().saturating_sub(1);
+            self.queue.extend(errors.into_iter().enumerate().map(|(i, diag)| Err(LexError {
+                span: diag.span.primary_span().unwrap_or(DUMMY_SP),
+                message: diag.message(),
+                is_fatal: stopped && i == last,
+            })));
+            match token.kind {
+                _ if stopped => self.done = true,
+                token::Eof => self.done = true,
+                _ => self.prev = Some(token),
+            }
+        }
+    }
+}
+
 fn is_doc_comment(s: &str) -> bool {
     let res = (s.starts_with("///") && *s.as_bytes().get(3).unwrap_or(&b' ') != b'/') ||
               s.starts_with("//!");
@@ -65,6 +65,25 @@ fn t1() {
     })
 }
 
+#[test]
+fn retokenize_lexes_only_the_span() {
+    with_default_globals(|| {
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let sh = mk_sess(sm.clone());
+        let sf = sm.new_source_file(PathBuf::from("retokenize").into(), "a + b * c".to_string());
+        let span = Span::with_root_ctxt(sf.start_pos + BytePos(4), sf.start_pos + BytePos(9));
+        let mut string_reader = StringReader::retokenize(&sh, span);
+        let tok = string_reader.next_token();
+        assert_eq!(tok.kind, mk_ident("b"));
+        assert_eq!(tok.span.lo(), span.lo());
+        assert_eq!(string_reader.next_token(), token::Whitespace);
+        assert_eq!(string_reader.next_token(), token::BinOp(token::Star));
+        assert_eq!(string_reader.next_token(), token::Whitespace);
+        assert_eq!(string_reader.next_token().kind, mk_ident("c"));
+        assert_eq!(string_reader.next_token(), token::Eof);
+    })
+}
+
 // check that the given reader produces the desired stream
 // of tokens (stop checking after exhausting the expected vec)
 fn check_tokenization(mut string_reader: StringReader<'_>, expected: Vec<TokenKind>) {
@@ -265,3 +284,135 @@ fn crlf_comments() {
         assert_eq!(lexer.next_token(), token::DocComment(Symbol::intern("/// test")));
     })
 }
+
+#[test]
+fn tokens_returns_errors() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let items: Vec<_> = StringReader::tokens(&sess, "a \"\\q\" \"b").collect();
+        assert_eq!(items.len(), 6);
+        match &items[0] {
+            Ok((token, IsJoint::NonJoint)) => {
+                assert_eq!(token.kind, mk_ident("a"));
+                assert_eq!(token.span, Span::with_root_ctxt(BytePos(0), BytePos(1)));
+            }
+            item => panic!("unexpected item: {:?}", item),
+        }
+        match &items[2] {
+            Err(err) => assert!(!err.is_fatal),
+            item => panic!("unexpected item: {:?}", item),
+        }
+        match &items[3] {
+            Ok((token, IsJoint::NonJoint)) => assert_eq!(token.kind, token::Literal(token::Lit {
+                kind: token::Str,
+                symbol: Symbol::intern("\\q"),
+                suffix: None,
+            })),
+            item => panic!("unexpected item: {:?}", item),
+        }
+        match &items[5] {
+            Err(err) => {
+                assert!(err.is_fatal);
+                assert_eq!(err.message, "unterminated double quote string");
+            }
+            item => panic!("unexpected item: {:?}", item),
+        }
+    })
+}
+
+#[test]
+fn unclosed_delims_are_recorded() {
+    with_default_globals(|| {
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let sh = mk_sess(sm.clone());
+        let (_, unmatched) = setup(&sm, &sh, "fn f() { g([1, 2]\n".to_string()).into_token_trees();
+        assert!(unmatched.is_empty());
+        let unclosed = sh.unclosed_delims.borrow();
+        let delims: Vec<_> = unclosed.iter().map(|unclosed| unclosed.delim).collect();
+        assert_eq!(delims, [token::Brace, token::Paren]);
+        assert_eq!(unclosed[0].open_span, Span::with_root_ctxt(BytePos(7), BytePos(8)));
+    })
+}
+
+#[test]
+fn unclosed_delims_are_detected() {
+    with_default_globals(|| {
+        let sp = |lo, hi| Span::with_root_ctxt(BytePos(lo), BytePos(hi));
+
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let sess = mk_sess(sm.clone());
+        let source = "fn f() {\n    if x {\n        g();\n}\n".to_string();
+        let unmatched = crate::parse::detect_unclosed_delims(
+            PathBuf::from("a.rs").into(), source, &sess,
+        );
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].expected_delim, token::Brace);
+        assert_eq!(unmatched[0].found_delim, token::NoDelim);
+        assert_eq!(unmatched[0].unclosed_span, Some(sp(7, 8)));
+        assert_eq!(unmatched[0].insertion_point, Some(sp(33, 33)));
+        assert!(!sess.span_diagnostic.has_errors());
+
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let sess = mk_sess(sm.clone());
+        let source = "fn f() {\n    g(1;\n}\n".to_string();
+        let unmatched = crate::parse::detect_unclosed_delims(
+            PathBuf::from("b.rs").into(), source, &sess,
+        );
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].expected_delim, token::Paren);
+        assert_eq!(unmatched[0].found_delim, token::Brace);
+        assert_eq!(unmatched[0].candidate_span, Some(sp(7, 8)));
+        assert_eq!(unmatched[0].insertion_point, Some(sp(18, 18)));
+    })
+}
+
+#[test]
+fn overlong_literals_are_rejected() {
+    with_default_globals(|| {
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let mut sh = mk_sess(sm.clone());
+        sh.max_literal_len = Some(4);
+        let lit = |src: &str| match setup(&sm, &sh, src.to_string()).next_token().kind {
+            token::Literal(lit) => lit.kind,
+            _ => panic!("expected a literal"),
+        };
+        assert_eq!(lit("\"abcd\""), token::Str);
+        assert!(!sh.span_diagnostic.has_errors());
+        assert_eq!(lit("b\"abcde\""), token::Err);
+        assert_eq!(lit("r#\"abcde\"#"), token::Err);
+        assert_eq!(sh.span_diagnostic.err_count(), 2);
+    })
+}
+
+#[test]
+fn comments_are_preserved_as_trivia() {
+    with_default_globals(|| {
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let mut sh = mk_sess(sm.clone());
+        sh.preserve_comments = true;
+        let src = "// a\n/* b */ fn f() { /// doc\n x }".to_string();
+        let (stream, _) = setup(&sm, &sh, src).into_token_trees();
+        let stream = stream.unwrap();
+        let fn_span = stream.trees().next().unwrap().span();
+        let comments: Vec<_> = sh.leading_comments(fn_span).into_iter()
+            .map(|comment| comment.text)
+            .collect();
+        assert_eq!(comments, ["// a", "/* b */"]);
+        // Doc comments are tokens of their own.
+        assert_eq!(sh.comment_trivia.borrow().len(), 1);
+    })
+}
+
+#[test]
+fn raw_edition_keywords_are_recorded() {
+    with_default_globals(|| {
+        let sm = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+        let sh = mk_sess(sm.clone());
+        let mut string_reader = setup(&sm, &sh, "r#async r#foo r#dyn".to_string());
+        while string_reader.next_token() != token::Eof {}
+        let spans: Vec<_> = sh.raw_identifier_spans.borrow().iter()
+            .map(|span| (span.lo(), span.hi()))
+            .collect();
+        assert_eq!(spans, [(BytePos(0), BytePos(7)), (BytePos(14), BytePos(19))]);
+    })
+}
@@ -1,25 +1,70 @@
 use syntax_pos::Span;
 
+use std::mem;
+
 use crate::print::pprust::token_to_string;
-use crate::parse::lexer::{StringReader, UnmatchedBrace};
+use crate::parse::lexer::{CommentTrivia, StringReader, UnclosedDelim, UnmatchedBrace};
 use crate::parse::token::{self, Token};
 use crate::parse::PResult;
 use crate::tokenstream::{DelimSpan, IsJoint::{self, *}, TokenStream, TokenTree, TreeAndJoint};
 
 impl<'a> StringReader<'a> {
     crate fn into_token_trees(self) -> (PResult<'a, TokenStream>, Vec<UnmatchedBrace>) {
-        let mut tt_reader = TokenTreesReader {
-            string_reader: self,
-            token: Token::dummy(),
-            joint_to_prev: Joint,
-            open_braces: Vec::new(),
-            unmatched_braces: Vec::new(),
-            matching_delim_spans: Vec::new(),
-            last_unclosed_found_span: None,
-        };
+        let mut tt_reader = TokenTreesReader::new(self);
         let res = tt_reader.parse_all_token_trees();
+        // Delimiters aren't popped once the end of the file is reached, so anything left on the
+        // stack was reported as un-closed.
+        if !tt_reader.open_braces.is_empty() {
+            let unclosed = tt_reader.open_braces.iter()
+                .map(|&(delim, open_span)| UnclosedDelim { delim, open_span });
+            tt_reader.string_reader.sess.unclosed_delims.borrow_mut().extend(unclosed);
+        }
         (res, tt_reader.unmatched_braces)
     }
+
+    /// Reads the token trees only to collect all mismatched delimiters, including those
+    /// `into_token_trees` reports as errors, see `parse::detect_unclosed_delims`.
+    crate fn into_unmatched_delims(self) -> Vec<UnmatchedBrace> {
+        let mut tt_reader = TokenTreesReader::new(self);
+        tt_reader.report_unclosed = false;
+        let res = tt_reader.parse_all_token_trees();
+        let mut unmatched = mem::replace(&mut tt_reader.unmatched_braces, Vec::new());
+        if let Err(mut err) = res {
+            err.cancel();
+            if let token::CloseDelim(delim) = tt_reader.token.kind {
+                unmatched.push(UnmatchedBrace {
+                    expected_delim: token::NoDelim,
+                    found_delim: delim,
+                    found_span: tt_reader.token.span,
+                    unclosed_span: None,
+                    candidate_span: None,
+                    insertion_point: None,
+                });
+            }
+        }
+
+        // The delimiters left open at the end of the file, innermost first. The innermost one
+        // is likely meant to be closed before a close delimiter that matched it with different
+        // indentation, if there's one, and the others at the end of the file.
+        let eof_span = tt_reader.token.span;
+        let misindented_close = tt_reader.misindented_delims().map(|(_, close_sp)| close_sp);
+        let innermost = tt_reader.open_braces.len().checked_sub(1);
+        for (i, &(delim, open_span)) in tt_reader.open_braces.iter().enumerate().rev() {
+            let insertion_point = match misindented_close {
+                Some(close_sp) if Some(i) == innermost => close_sp.shrink_to_lo(),
+                _ => eof_span,
+            };
+            unmatched.push(UnmatchedBrace {
+                expected_delim: delim,
+                found_delim: token::NoDelim,
+                found_span: eof_span,
+                unclosed_span: Some(open_span),
+                candidate_span: None,
+                insertion_point: Some(insertion_point),
+            });
+        }
+        unmatched
+    }
 }
 
 struct TokenTreesReader<'a> {
@@ -34,9 +79,48 @@ struct TokenTreesReader<'a> {
     /// Used only for error recovery when arriving to EOF with mismatched braces.
     matching_delim_spans: Vec<(token::DelimToken, Span, Span)>,
     last_unclosed_found_span: Option<Span>,
+    /// Whether to emit the error for delimiters left open at the end of the file.
+    report_unclosed: bool,
+    /// The comments since the last token, with `ParseSess::preserve_comments`.
+    comments: Vec<CommentTrivia>,
 }
 
 impl<'a> TokenTreesReader<'a> {
+    fn new(string_reader: StringReader<'a>) -> Self {
+        TokenTreesReader {
+            string_reader,
+            token: Token::dummy(),
+            joint_to_prev: Joint,
+            open_braces: Vec::new(),
+            unmatched_braces: Vec::new(),
+            matching_delim_spans: Vec::new(),
+            last_unclosed_found_span: None,
+            report_unclosed: true,
+            comments: Vec::new(),
+        }
+    }
+
+    /// The last open and first close delimiter matched with each other, of the same kind as the
+    /// innermost open delimiter, that have different indentation. Reaching the end of the file
+    /// with open delimiters, these are probably not meant to be matched with each other.
+    fn misindented_delims(&self) -> Option<(Span, Span)> {
+        let sm = self.string_reader.sess.source_map();
+        let (delim, _) = self.open_braces.last()?;
+        // These are in reverse order as they get inserted on close, but we want the last open
+        // and the first close.
+        self.matching_delim_spans.iter()
+            .filter(|(d, open_sp, close_sp)| {
+                if let Some(close_padding) = sm.span_to_margin(*close_sp) {
+                    if let Some(open_padding) = sm.span_to_margin(*open_sp) {
+                        return delim == d && close_padding != open_padding;
+                    }
+                }
+                false
+            })
+            .next()
+            .map(|&(_, open_sp, close_sp)| (open_sp, close_sp))
+    }
+
     // Parse a stream of tokens into a list of `TokenTree`s, up to an `Eof`.
     fn parse_all_token_trees(&mut self) -> PResult<'a, TokenStream> {
         let mut buf = TokenStreamBuilder::default();
@@ -60,7 +144,11 @@ impl<'a> TokenTreesReader<'a> {
             match self.parse_token_tree() {
                 Ok(tree) => buf.push(tree),
                 Err(mut e) => {
-                    e.emit();
+                    if self.report_unclosed {
+                        e.emit();
+                    } else {
+                        e.cancel();
+                    }
                     return buf.into_token_stream();
                 }
             }
@@ -78,26 +166,12 @@ impl<'a> TokenTreesReader<'a> {
                     err.span_label(sp, "un-closed delimiter");
                 }
 
-                if let Some((delim, _)) = self.open_braces.last() {
-                    if let Some((_, open_sp, close_sp)) = self.matching_delim_spans.iter()
-                        .filter(|(d, open_sp, close_sp)| {
-                            if let Some(close_padding) = sm.span_to_margin(*close_sp) {
-                                if let Some(open_padding) = sm.span_to_margin(*open_sp) {
-                                    return delim == d && close_padding != open_padding;
-                                }
-                            }
-                            false
-                        }).next()  // these are in reverse order as they get inserted on close, but
-                    {              // we want the last open/first close
-                        err.span_label(
-                            *open_sp,
-                            "this delimiter might not be properly closed...",
-                        );
-                        err.span_label(
-                            *close_sp,
-                            "...as it matches this but it has different indentation",
-                        );
-                    }
+                if let Some((open_sp, close_sp)) = self.misindented_delims() {
+                    err.span_label(open_sp, "this delimiter might not be properly closed...");
+                    err.span_label(
+                        close_sp,
+                        "...as it matches this but it has different indentation",
+                    );
                 }
                 Err(err)
             },
@@ -163,6 +237,9 @@ impl<'a> TokenTreesReader<'a> {
                                 found_span: self.token.span,
                                 unclosed_span: unclosed_delimiter,
                                 candidate_span: candidate,
+                                // With a likely candidate, the delimiters opened after it are
+                                // the ones left unclosed.
+                                insertion_point: candidate.map(|_| self.token.span.shrink_to_lo()),
                             });
                         } else {
                             self.open_braces.pop();
@@ -217,10 +294,23 @@ impl<'a> TokenTreesReader<'a> {
         loop {
             let token = self.string_reader.next_token();
             match token.kind {
+                // Comments in reparsed text all have the same override span, which they can't
+                // be looked up by.
+                token::Comment if self.string_reader.sess.preserve_comments &&
+                                  self.string_reader.override_span.is_none() => {
+                    self.joint_to_prev = NonJoint;
+                    let text = self.string_reader.str_from_to(token.span.lo(), token.span.hi());
+                    self.comments.push(CommentTrivia { text: text.to_string(), span: token.span });
+                }
                 token::Whitespace | token::Comment | token::Shebang(_) | token::Unknown(_) => {
                     self.joint_to_prev = NonJoint;
                 }
                 _ => {
+                    if !self.comments.is_empty() {
+                        let comments = mem::replace(&mut self.comments, Vec::new());
+                        let mut trivia = self.string_reader.sess.comment_trivia.borrow_mut();
+                        trivia.insert(token.span.lo(), comments);
+                    }
                     self.token = token;
                     return;
                 }
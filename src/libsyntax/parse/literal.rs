@@ -2,7 +2,7 @@
 
 use crate::ast::{self, Lit, LitKind};
 use crate::parse::parser::Parser;
-use crate::parse::PResult;
+use crate::parse::{ParseSess, PResult, UnknownSuffix};
 use crate::parse::token::{self, Token, TokenKind};
 use crate::print::pprust;
 use crate::symbol::{kw, sym, Symbol};
@@ -286,11 +286,34 @@ impl Lit {
         Lit { token: node.to_lit_token(), node, span }
     }
 
+    /// Creates a byte string literal whose token, the escaped bytes, is only built by
+    /// `Lit::token` when it's needed. `include_bytes!` creates large literals, which are rarely
+    /// printed or turned back into tokens.
+    pub fn from_byte_blob(bytes: Lrc<Vec<u8>>, span: Span) -> Lit {
+        let token = token::Lit::new(token::ByteStr, kw::Invalid, None);
+        Lit { token, node: LitKind::ByteStr(bytes), span }
+    }
+
+    /// The literal token, as written or as recovered from the semantic literal.
+    pub fn token(&self) -> token::Lit {
+        match self.node {
+            // An empty byte string needs no escaping either.
+            LitKind::ByteStr(_) if self.token.symbol == kw::Invalid => self.node.to_lit_token(),
+            _ => self.token,
+        }
+    }
+
+    /// The suffix of the literal as written, including suffixes accepted by
+    /// `ParseSess::unknown_suffix_policy` that don't affect its value.
+    pub fn suffix(&self) -> Option<Symbol> {
+        self.token.suffix
+    }
+
     /// Losslessly convert an AST literal into a token stream.
     crate fn tokens(&self) -> TokenStream {
         let token = match self.token.kind {
             token::Bool => token::Ident(self.token.symbol, false),
-            _ => token::Literal(self.token),
+            _ => token::Literal(self.token()),
         };
         TokenTree::token(token, self.span).into()
     }
@@ -340,7 +363,19 @@ impl<'a> Parser<'a> {
             Err(err) => {
                 let (lit, span) = (token.expect_lit(), token.span);
                 self.bump();
-                err.report(&self.sess.span_diagnostic, lit, span);
+                match unknown_suffix_action(self.sess, &err, lit) {
+                    UnknownSuffix::Reject => err.report(&self.sess.span_diagnostic, lit, span),
+                    UnknownSuffix::Error(msg) => {
+                        self.struct_span_err(span, &msg).emit();
+                    }
+                    UnknownSuffix::Accept => {
+                        let unsuffixed = token::Lit::new(lit.kind, lit.symbol, None);
+                        match LitKind::from_lit_token(unsuffixed) {
+                            Ok(node) => return Ok(Lit { token: lit, node, span }),
+                            Err(err) => err.report(&self.sess.span_diagnostic, unsuffixed, span),
+                        }
+                    }
+                }
                 // Pack possible quotes and prefixes from the original literal into
                 // the error literal's symbol so they can be pretty-printed faithfully.
                 let suffixless_lit = token::Lit::new(lit.kind, lit.symbol, None);
@@ -352,6 +387,19 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// What the session's `unknown_suffix_policy` does with a literal that failed to convert with
+/// `err`. Errors other than unknown suffixes are always reported.
+fn unknown_suffix_action(sess: &ParseSess, err: &LitError, lit: token::Lit) -> UnknownSuffix {
+    match err {
+        LitError::InvalidSuffix | LitError::InvalidIntSuffix | LitError::InvalidFloatSuffix => {}
+        _ => return UnknownSuffix::Reject,
+    }
+    match (&sess.unknown_suffix_policy, lit.suffix) {
+        (Some(policy), Some(suffix)) => policy(lit.kind, suffix),
+        _ => UnknownSuffix::Reject,
+    }
+}
+
 crate fn expect_no_suffix(diag: &Handler, sp: Span, kind: &str, suffix: Option<Symbol>) {
     if let Some(suf) = suffix {
         let mut err = if kind == "a tuple index" &&
@@ -2,24 +2,27 @@
 
 use crate::ast::{self, CrateConfig, NodeId};
 use crate::early_buffered_lints::{BufferedEarlyLint, BufferedEarlyLintId};
+use crate::ext::base::{ExpansionArtifact, SuppressedWarning};
 use crate::source_map::{SourceMap, FilePathMapping};
 use crate::feature_gate::UnstableFeatures;
 use crate::parse::parser::Parser;
 use crate::parse::parser::emit_unclosed_delims;
-use crate::parse::token::TokenKind;
+use crate::parse::token::{self, TokenKind};
 use crate::tokenstream::{TokenStream, TokenTree};
 use crate::diagnostics::plugin::ErrorMap;
 use crate::print::pprust;
+use crate::ptr::P;
 use crate::symbol::Symbol;
 
 use errors::{Applicability, FatalError, Level, Handler, ColorConfig, Diagnostic, DiagnosticBuilder};
-use rustc_data_structures::sync::{Lrc, Lock, Once};
-use syntax_pos::{Span, SourceFile, FileName, MultiSpan};
+use rustc_data_structures::sync::{self, Lrc, Lock, Once};
+use syntax_pos::{BytePos, Span, SourceFile, FileName, MultiSpan, DUMMY_SP};
 use syntax_pos::edition::Edition;
 use syntax_pos::hygiene::ExpnId;
 
 use rustc_data_structures::fx::{FxHashSet, FxHashMap};
 use std::borrow::Cow;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::str;
 
@@ -30,6 +33,7 @@ mod tests;
 pub mod parser;
 pub mod attr;
 pub mod lexer;
+pub mod reparse;
 pub mod token;
 
 crate mod classify;
@@ -39,6 +43,9 @@ crate mod unescape_error_reporting;
 
 pub type PResult<'a, T> = Result<T, DiagnosticBuilder<'a>>;
 
+/// The default for `ParseSess::max_nesting_depth`.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 512;
+
 /// Collected spans during parsing for places where a certain feature was
 /// used and should be feature gated accordingly in `check_crate`.
 #[derive(Default)]
@@ -53,8 +60,27 @@ pub struct GatedSpans {
     pub yields: Lock<Vec<Span>>,
     /// Spans collected for gating `or_patterns`, e.g. `Some(Foo | Bar)`.
     pub or_patterns: Lock<Vec<Span>>,
+    /// Spans collected for gating `macros_in_arm_position`, e.g. `match x { arms!() }`.
+    pub arm_macros: Lock<Vec<Span>>,
+    /// Spans collected for gating `macros_in_param_position`, e.g. `fn f(params!()) {}`.
+    pub param_macros: Lock<Vec<Span>>,
+}
+
+/// What to do with a literal whose suffix the language doesn't know, e.g., `10px`.
+pub enum UnknownSuffix {
+    /// Report the usual "invalid suffix" error.
+    Reject,
+    /// Report an error with the given message instead.
+    Error(String),
+    /// Accept the literal with the value it would have without the suffix. The suffix is kept
+    /// in the literal's token, see `ast::Lit::suffix`.
+    Accept,
 }
 
+/// Decides what to do with a literal of the given kind with the given unknown suffix.
+pub type UnknownSuffixPolicy =
+    dyn Fn(token::LitKind, Symbol) -> UnknownSuffix + sync::Send + sync::Sync;
+
 /// Info about a parsing session.
 pub struct ParseSess {
     pub span_diagnostic: Handler,
@@ -62,7 +88,8 @@ pub struct ParseSess {
     pub config: CrateConfig,
     pub edition: Edition,
     pub missing_fragment_specifiers: Lock<FxHashSet<Span>>,
-    /// Places where raw identifiers were used. This is used for feature-gating raw identifiers.
+    /// Places where raw identifiers were used for edition-specific keywords, see
+    /// `EditionKeywords`. This is used by the `keyword_idents` lint not to report them.
     pub raw_identifier_spans: Lock<Vec<Span>>,
     /// The registered diagnostics codes.
     crate registered_diagnostics: Lock<ErrorMap>,
@@ -76,6 +103,33 @@ pub struct ParseSess {
     pub ambiguous_block_expr_parse: Lock<FxHashMap<Span, Span>>,
     pub injected_crate_name: Once<Symbol>,
     pub gated_spans: GatedSpans,
+    /// The maximum nesting depth of expressions, types and patterns accepted by the parser.
+    /// Deeper input is rejected with an "expression too deeply nested" error instead of
+    /// exhausting the stack.
+    pub max_nesting_depth: usize,
+    /// The maximum length in bytes of the contents of string and byte string literals, set with
+    /// `-Z literal-size-limit`, see `literal_too_long`. There's no limit by default.
+    pub max_literal_len: Option<usize>,
+    /// Artifacts emitted by macros through `ExtCtxt::emit_artifact`.
+    pub expansion_artifacts: Lock<Vec<ExpansionArtifact>>,
+    /// The delimiters left open at the end of the files lexed so far, outermost first, for
+    /// tools that point them out themselves.
+    pub unclosed_delims: Lock<Vec<lexer::UnclosedDelim>>,
+    /// Lets literals with unknown suffixes through, e.g., for the numbers of a DSL embedded in
+    /// macro input. Without a policy, they are always reported as errors.
+    pub unknown_suffix_policy: Option<Lrc<UnknownSuffixPolicy>>,
+    /// The spans of the `ExprKind::Err` and `TyKind::Err` nodes left in the expanded crate.
+    /// Errors have already been reported for them, so further diagnostics within them are
+    /// follow-on errors, see `is_in_error_node`.
+    pub error_node_spans: Lock<Vec<Span>>,
+    /// The warnings suppressed in expansions with `ExtCtxt::with_suppressed_warnings`.
+    pub warning_suppressions: Lock<FxHashMap<ExpnId, Vec<SuppressedWarning>>>,
+    /// Makes the lexer record the ordinary comments of source files in `comment_trivia`
+    /// instead of dropping them, so that tools and attribute macros can get them back.
+    pub preserve_comments: bool,
+    /// The comments recorded with `preserve_comments`, keyed by the position of the token
+    /// they precede, see `leading_comments`.
+    pub comment_trivia: Lock<FxHashMap<BytePos, Vec<lexer::CommentTrivia>>>,
 }
 
 impl ParseSess {
@@ -103,6 +157,15 @@ impl ParseSess {
             ambiguous_block_expr_parse: Lock::new(FxHashMap::default()),
             injected_crate_name: Once::new(),
             gated_spans: GatedSpans::default(),
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            max_literal_len: None,
+            expansion_artifacts: Lock::new(Vec::new()),
+            unclosed_delims: Lock::new(Vec::new()),
+            unknown_suffix_policy: None,
+            error_node_spans: Lock::new(Vec::new()),
+            warning_suppressions: Lock::new(FxHashMap::default()),
+            preserve_comments: false,
+            comment_trivia: Lock::new(FxHashMap::default()),
         }
     }
 
@@ -111,22 +174,86 @@ impl ParseSess {
         &self.source_map
     }
 
+    /// The comments right before the token starting at `sp`, in source order, if
+    /// `preserve_comments` was set when the token was lexed. Tokens keep their spans in the
+    /// streams they are moved to, so this also works for the tokens passed to macros.
+    pub fn leading_comments(&self, sp: Span) -> Vec<lexer::CommentTrivia> {
+        self.comment_trivia.borrow().get(&sp.lo()).cloned().unwrap_or_default()
+    }
+
+    /// Whether `sp` lies within one of the `error_node_spans`.
+    pub fn is_in_error_node(&self, sp: Span) -> bool {
+        self.error_node_spans.borrow().iter().any(|error_sp| error_sp.contains(sp))
+    }
+
+    /// Reports a string or byte string literal of the given kind at `span`, with `len` bytes of
+    /// contents, if it's longer than `max_literal_len`. Whoever creates the literal replaces it
+    /// with an error literal if it's too long, so that its contents are never interned.
+    pub fn literal_too_long(&self, span: Span, len: usize, kind: token::LitKind) -> bool {
+        let limit = match self.max_literal_len {
+            Some(limit) if len > limit => limit,
+            _ => return false,
+        };
+        let (descr, include_macro, data) = match kind {
+            token::ByteStr | token::ByteStrRaw(..) => ("byte string", "include_bytes", "data"),
+            _ => ("string", "include_str", "text"),
+        };
+        self.span_diagnostic
+            .struct_span_err(span, &format!("{} literal is too long", descr))
+            .note(&format!("the literal is {} bytes long, but at most {} bytes are allowed",
+                           len, limit))
+            .help(&format!("move the {} into a separate file and include it with `{}!`",
+                           data, include_macro))
+            .emit();
+        true
+    }
+
     pub fn buffer_lint<S: Into<MultiSpan>>(&self,
         lint_id: BufferedEarlyLintId,
         span: S,
         id: NodeId,
         msg: &str,
+    ) {
+        self.buffer_lint_with_notes(lint_id, span, id, msg, Vec::new());
+    }
+
+    pub fn buffer_lint_with_notes<S: Into<MultiSpan>>(&self,
+        lint_id: BufferedEarlyLintId,
+        span: S,
+        id: NodeId,
+        msg: &str,
+        notes: Vec<String>,
     ) {
         self.buffered_lints.with_lock(|buffered_lints| {
             buffered_lints.push(BufferedEarlyLint{
                 span: span.into(),
                 id,
                 msg: msg.into(),
+                notes,
                 lint_id,
             });
         });
     }
 
+    /// Whether the warnings of the lint `lint_name` are suppressed at `span`, because the span
+    /// originates in an expansion made by `ExtCtxt::with_suppressed_warnings`.
+    pub fn suppresses_warning(&self, lint_name: &str, span: Span) -> bool {
+        let suppressions = self.warning_suppressions.borrow();
+        if suppressions.is_empty() {
+            return false;
+        }
+        let mut expn_id = span.ctxt().outer_expn();
+        while expn_id != ExpnId::root() {
+            if let Some(warnings) = suppressions.get(&expn_id) {
+                if warnings.iter().any(|warning| warning.lint_name() == lint_name) {
+                    return true;
+                }
+            }
+            expn_id = expn_id.expn_data().parent;
+        }
+        false
+    }
+
     /// Extend an error with a suggestion to wrap an expression with parentheses to allow the
     /// parser to continue parsing the following operation as part of the same expression.
     pub fn expr_parentheses_needed(
@@ -162,6 +289,60 @@ pub enum DirectoryOwnership {
     UnownedViaMod(bool /* legacy warnings? */),
 }
 
+/// The body of a function or method skipped by a parser with `Parser::skip_fn_bodies`.
+///
+/// The AST keeps an empty block with the span of the skipped body in its place, and the inner
+/// attributes of the body, so tools that only look at the structure of items, e.g., symbol
+/// indexes, don't pay for parsing the statements. Such a crate must not be compiled.
+#[derive(Clone, Debug)]
+pub struct SkippedBody {
+    /// The span of the body, from its opening brace to its closing one.
+    pub span: Span,
+    /// The body as a single brace-delimited tree, without its inner attributes.
+    pub tokens: TokenStream,
+}
+
+impl SkippedBody {
+    /// Parses the statements of the body that was skipped.
+    pub fn parse<'a>(&self, sess: &'a ParseSess) -> PResult<'a, P<ast::Block>> {
+        let mut parser = stream_to_parser(sess, self.tokens.clone(), None);
+        let (_, body) = parser.parse_inner_attrs_and_block()?;
+        parser.expect(&token::Eof)?;
+        Ok(body)
+    }
+}
+
+/// The bodies skipped while parsing, by their span.
+#[derive(Clone, Debug, Default)]
+pub struct SkippedBodies {
+    bodies: FxHashMap<Span, SkippedBody>,
+}
+
+impl SkippedBodies {
+    /// Returns the skipped body that `body` stands for, if it was skipped.
+    pub fn get(&self, body: &ast::Block) -> Option<&SkippedBody> {
+        self.bodies.get(&body.span)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SkippedBody> {
+        self.bodies.values()
+    }
+}
+
+impl iter::FromIterator<SkippedBody> for SkippedBodies {
+    fn from_iter<I: IntoIterator<Item = SkippedBody>>(iter: I) -> Self {
+        SkippedBodies { bodies: iter.into_iter().map(|body| (body.span, body)).collect() }
+    }
+}
+
 // a bunch of utility functions of the form parse_<thing>_from_<source>
 // where <thing> includes crate, expr, item, stmt, tts, and one that
 // uses a HOF to parse anything, and <source> includes file and
@@ -183,6 +364,26 @@ pub fn parse_crate_from_source_str(name: FileName, source: String, sess: &ParseS
     new_parser_from_source_str(sess, name, source).parse_crate_mod()
 }
 
+/// Parses the crate in `input` and the modules it loads, skipping the bodies of functions and
+/// methods, see `SkippedBody`.
+pub fn parse_crate_signatures_from_file<'a>(input: &Path, sess: &'a ParseSess)
+                                            -> PResult<'a, (ast::Crate, SkippedBodies)> {
+    let mut parser = new_parser_from_file(sess, input);
+    parser.skip_fn_bodies = true;
+    let krate = parser.parse_crate_mod()?;
+    Ok((krate, parser.take_skipped_bodies()))
+}
+
+/// Parses a crate from a source string, skipping the bodies of functions and methods, see
+/// `SkippedBody`.
+pub fn parse_crate_signatures_from_source_str(name: FileName, source: String, sess: &ParseSess)
+                                              -> PResult<'_, (ast::Crate, SkippedBodies)> {
+    let mut parser = new_parser_from_source_str(sess, name, source);
+    parser.skip_fn_bodies = true;
+    let krate = parser.parse_crate_mod()?;
+    Ok((krate, parser.take_skipped_bodies()))
+}
+
 pub fn parse_crate_attrs_from_source_str(name: FileName, source: String, sess: &ParseSess)
                                              -> PResult<'_, Vec<ast::Attribute>> {
     new_parser_from_source_str(sess, name, source).parse_inner_attributes()
@@ -203,6 +404,19 @@ pub fn parse_stream_from_source_str(
     stream
 }
 
+/// Finds the mismatched delimiters in `source` with the heuristics the lexer uses for its
+/// errors, and guesses where close delimiters are missing, for tools that fix them up, e.g.,
+/// editors auto-closing blocks. The un-closed delimiter errors aren't reported, but other
+/// lexer errors are reported to `sess` as when parsing.
+pub fn detect_unclosed_delims(
+    name: FileName,
+    source: String,
+    sess: &ParseSess,
+) -> Vec<lexer::UnmatchedBrace> {
+    let source_file = sess.source_map().new_source_file(name, source);
+    lexer::StringReader::new(sess, source_file, None).into_unmatched_delims()
+}
+
 /// Creates a new parser from a source string.
 pub fn new_parser_from_source_str(sess: &ParseSess, name: FileName, source: String) -> Parser<'_> {
     panictry_buffer!(&sess.span_diagnostic, maybe_new_parser_from_source_str(sess, name, source))
@@ -380,6 +594,48 @@ pub fn stream_to_parser_with_base_dir<'a>(
     Parser::new(sess, stream, Some(base_dir), true, false, None)
 }
 
+/// Checks whether `tokens` are exactly one expression, without reporting anything. On failure,
+/// returns the span of the first error the parser found.
+pub fn is_valid_expr(sess: &ParseSess, tokens: TokenStream) -> Result<(), Span> {
+    parses_silently(sess, tokens, |parser| parser.parse_expr().map(drop))
+}
+
+/// Like `is_valid_expr`, for a type.
+pub fn is_valid_ty(sess: &ParseSess, tokens: TokenStream) -> Result<(), Span> {
+    parses_silently(sess, tokens, |parser| parser.parse_ty().map(drop))
+}
+
+/// Like `is_valid_expr`, for a meta item, e.g., `name`, `name = "value"` or `name(list)`.
+pub fn is_valid_meta_item(sess: &ParseSess, tokens: TokenStream) -> Result<(), Span> {
+    parses_silently(sess, tokens, |parser| parser.parse_meta_item().map(drop))
+}
+
+/// Runs `f` on a parser for `tokens` with recovery turned off, capturing the errors reported to
+/// `sess` instead of emitting them. Errors the parser reports before returning one count as
+/// failures too, and so do leftover tokens. The spans of unstable syntax may still be recorded
+/// in `sess.gated_spans`.
+fn parses_silently<F>(sess: &ParseSess, tokens: TokenStream, f: F) -> Result<(), Span>
+    where F: for<'b> FnOnce(&mut Parser<'b>) -> PResult<'b, ()>
+{
+    let (result, errors) = sess.span_diagnostic.capture_errors(|| {
+        let mut parser = stream_to_parser(sess, tokens, None);
+        parser.recover = false;
+        match f(&mut parser) {
+            Ok(()) if parser.token == token::Eof => Ok(()),
+            Ok(()) => Err(parser.token.span),
+            Err(mut err) => {
+                let span = err.span.primary_span().unwrap_or(parser.token.span);
+                err.cancel();
+                Err(span)
+            }
+        }
+    });
+    match errors.first() {
+        Some(diag) => Err(diag.span.primary_span().unwrap_or(DUMMY_SP)),
+        None => result,
+    }
+}
+
 /// A sequence separator.
 pub struct SeqSep {
     /// The separator token.
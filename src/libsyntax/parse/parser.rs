@@ -11,23 +11,24 @@ mod stmt;
 mod generics;
 
 use crate::ast::{self, AttrStyle, Attribute, Param, BindingMode, StrStyle, SelfKind};
-use crate::ast::{FnDecl, Ident, IsAsync, MacDelimiter, Mutability, TyKind};
-use crate::ast::{Visibility, VisibilityKind, Unsafety, CrateSugar};
+use crate::ast::{FnDecl, Ident, IsAsync, MacDelimiter, MacPosition, MacSemicolon, Mutability};
+use crate::ast::{TyKind, Visibility, VisibilityKind, Unsafety, CrateSugar};
 use crate::source_map::{self, respan};
 use crate::parse::{SeqSep, literal, token};
 use crate::parse::lexer::UnmatchedBrace;
 use crate::parse::lexer::comments::{doc_comment_style, strip_doc_comment_decoration};
 use crate::parse::token::{Token, TokenKind, DelimToken};
-use crate::parse::{ParseSess, Directory, DirectoryOwnership};
+use crate::parse::{ParseSess, Directory, DirectoryOwnership, SkippedBodies, SkippedBody};
 use crate::print::pprust;
 use crate::ptr::P;
 use crate::parse::PResult;
 use crate::ThinVec;
 use crate::tokenstream::{self, DelimSpan, TokenTree, TokenStream, TreeAndJoint};
-use crate::symbol::{kw, sym, Symbol};
+use crate::symbol::{kw, sym, EditionKeywords, Symbol};
 use crate::parse::diagnostics::{Error, dummy_arg};
 
 use errors::{Applicability, DiagnosticId, FatalError};
+use rustc_data_structures::stack::ensure_sufficient_stack;
 use rustc_target::spec::abi::{self, Abi};
 use syntax_pos::{Span, BytePos, DUMMY_SP, FileName};
 use log::debug;
@@ -136,6 +137,13 @@ pub struct Parser<'a> {
     desugar_doc_comments: bool,
     /// `true` we should configure out of line modules as we parse.
     pub cfg_mods: bool,
+    /// `true` to skip the bodies of functions and methods, see `SkippedBody`.
+    pub skip_fn_bodies: bool,
+    /// The bodies skipped so far, see `take_skipped_bodies`.
+    crate skipped_bodies: Vec<SkippedBody>,
+    /// `false` to return the errors the parser would otherwise report and recover from, e.g.
+    /// a missing semicolon or a mismatched closing delimiter.
+    pub recover: bool,
     /// This field is used to keep track of how many left angle brackets we have seen. This is
     /// required in order to detect extra leading left angle brackets (`<` characters) and error
     /// appropriately.
@@ -151,6 +159,9 @@ pub struct Parser<'a> {
     crate last_type_ascription: Option<(Span, bool /* likely path typo */)>,
     /// If present, this `Parser` is not parsing Rust code but rather a macro call.
     crate subparser_name: Option<&'static str>,
+    /// Current nesting depth of expressions, types and patterns, checked against
+    /// `ParseSess::max_nesting_depth`.
+    nesting_depth: usize,
 }
 
 impl<'a> Drop for Parser<'a> {
@@ -170,12 +181,35 @@ crate struct TokenCursor {
 crate struct TokenCursorFrame {
     crate delim: token::DelimToken,
     crate span: DelimSpan,
-    crate open_delim: bool,
+    /// The opening delimiter, computed once when the frame is entered and taken when it is
+    /// emitted. Always `None` for `NoDelim` frames.
+    open_tt: Option<TokenTree>,
     crate tree_cursor: tokenstream::Cursor,
-    crate close_delim: bool,
+    /// The closing delimiter, see `open_tt`.
+    close_tt: Option<TokenTree>,
     crate last_token: LastToken,
 }
 
+/// The position of a parser, saved with `Parser::checkpoint` to go back to with
+/// `Parser::rollback`.
+#[derive(Clone)]
+pub struct ParserCheckpoint {
+    token: Token,
+    meta_var_span: Option<Span>,
+    prev_span: Span,
+    prev_token_kind: PrevTokenKind,
+    token_cursor: TokenCursor,
+    unmatched_angle_bracket_count: u32,
+    max_angle_bracket_count: u32,
+    unclosed_delims: usize,
+    last_unexpected_token_span: Option<Span>,
+    last_type_ascription: Option<(Span, bool)>,
+    nesting_depth: usize,
+    skipped_bodies: usize,
+    /// The number of errors emitted when the checkpoint was saved.
+    err_count: usize,
+}
+
 /// This is used in `TokenCursorFrame` above to track tokens that are consumed
 /// by the parser, and then that's transitively used to record the tokens that
 /// each parse AST item is created with.
@@ -200,13 +234,23 @@ crate enum LastToken {
 }
 
 impl TokenCursorFrame {
-    fn new(span: DelimSpan, delim: DelimToken, tts: &TokenStream) -> Self {
+    /// Creates a frame iterating over `tts`, taking over the reference to the stream instead
+    /// of cloning it.
+    fn new(span: DelimSpan, delim: DelimToken, tts: TokenStream) -> Self {
+        let (open_tt, close_tt) = if delim == token::NoDelim {
+            (None, None)
+        } else {
+            (
+                Some(TokenTree::open_tt(span.open, delim)),
+                Some(TokenTree::close_tt(span.close, delim)),
+            )
+        };
         TokenCursorFrame {
             delim,
             span,
-            open_delim: delim == token::NoDelim,
-            tree_cursor: tts.clone().into_trees(),
-            close_delim: delim == token::NoDelim,
+            open_tt,
+            tree_cursor: tts.into_trees(),
+            close_tt,
             last_token: LastToken::Was(None),
         }
     }
@@ -215,14 +259,12 @@ impl TokenCursorFrame {
 impl TokenCursor {
     fn next(&mut self) -> Token {
         loop {
-            let tree = if !self.frame.open_delim {
-                self.frame.open_delim = true;
-                TokenTree::open_tt(self.frame.span.open, self.frame.delim)
+            let tree = if let Some(open_tt) = self.frame.open_tt.take() {
+                open_tt
             } else if let Some(tree) = self.frame.tree_cursor.next() {
                 tree
-            } else if !self.frame.close_delim {
-                self.frame.close_delim = true;
-                TokenTree::close_tt(self.frame.span.close, self.frame.delim)
+            } else if let Some(close_tt) = self.frame.close_tt.take() {
+                close_tt
             } else if let Some(frame) = self.stack.pop() {
                 self.frame = frame;
                 continue
@@ -230,15 +272,26 @@ impl TokenCursor {
                 return Token::new(token::Eof, DUMMY_SP);
             };
 
-            match self.frame.last_token {
-                LastToken::Collecting(ref mut v) => v.push(tree.clone().into()),
-                LastToken::Was(ref mut t) => *t = Some(tree.clone().into()),
-            }
-
             match tree {
-                TokenTree::Token(token) => return token,
+                TokenTree::Token(token) => {
+                    let tree = TokenTree::Token(token.clone()).into();
+                    match self.frame.last_token {
+                        LastToken::Collecting(ref mut v) => v.push(tree),
+                        LastToken::Was(ref mut t) => *t = Some(tree),
+                    }
+                    return token;
+                }
                 TokenTree::Delimited(sp, delim, tts) => {
-                    let frame = TokenCursorFrame::new(sp, delim, &tts);
+                    // Only a collecting frame needs its own reference to the stream. Otherwise,
+                    // the frame remembers its last tree for a collection starting at the current
+                    // token, which is now the opening delimiter remembered by the new frame.
+                    match self.frame.last_token {
+                        LastToken::Collecting(ref mut v) => {
+                            v.push(TokenTree::Delimited(sp, delim, tts.clone()).into())
+                        }
+                        LastToken::Was(ref mut t) => *t = None,
+                    }
+                    let frame = TokenCursorFrame::new(sp, delim, tts);
                     self.stack.push(mem::replace(&mut self.frame, frame));
                 }
             }
@@ -283,12 +336,12 @@ impl TokenCursor {
         self.stack.push(mem::replace(&mut self.frame, TokenCursorFrame::new(
             delim_span,
             token::NoDelim,
-            &if doc_comment_style(&name.as_str()) == AttrStyle::Inner {
+            if doc_comment_style(&name.as_str()) == AttrStyle::Inner {
                 [TokenTree::token(token::Pound, sp), TokenTree::token(token::Not, sp), body]
-                    .iter().cloned().collect::<TokenStream>().into()
+                    .iter().cloned().collect::<TokenStream>()
             } else {
                 [TokenTree::token(token::Pound, sp), body]
-                    .iter().cloned().collect::<TokenStream>().into()
+                    .iter().cloned().collect::<TokenStream>()
             },
         )));
 
@@ -356,18 +409,22 @@ impl<'a> Parser<'a> {
                 frame: TokenCursorFrame::new(
                     DelimSpan::dummy(),
                     token::NoDelim,
-                    &tokens.into(),
+                    tokens,
                 ),
                 stack: Vec::new(),
             },
             desugar_doc_comments,
             cfg_mods: true,
+            skip_fn_bodies: false,
+            recover: true,
+            skipped_bodies: Vec::new(),
             unmatched_angle_bracket_count: 0,
             max_angle_bracket_count: 0,
             unclosed_delims: Vec::new(),
             last_unexpected_token_span: None,
             last_type_ascription: None,
             subparser_name,
+            nesting_depth: 0,
         };
 
         parser.token = parser.next_tok();
@@ -458,7 +515,7 @@ impl<'a> Parser<'a> {
         } else if inedible.contains(&self.token.kind) {
             // leave it in the input
             Ok(false)
-        } else if self.last_unexpected_token_span == Some(self.token.span) {
+        } else if self.recover && self.last_unexpected_token_span == Some(self.token.span) {
             FatalError.raise();
         } else {
             self.expected_one_of_not_found(edible, inedible)
@@ -527,6 +584,22 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Like `check_keyword`, for a keyword from `EditionKeywords`. Before the edition that
+    /// reserves it, the keyword is an identifier and is neither checked nor expected.
+    fn check_edition_keyword(&mut self, kw: Symbol) -> bool {
+        EditionKeywords::is_keyword_at(kw, self.token.span) && self.check_keyword(kw)
+    }
+
+    /// Like `eat_keyword`, for a keyword from `EditionKeywords`.
+    fn eat_edition_keyword(&mut self, kw: Symbol) -> bool {
+        if self.check_edition_keyword(kw) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
     fn eat_keyword_noexpect(&mut self, kw: Symbol) -> bool {
         if self.token.is_keyword(kw) {
             self.bump();
@@ -917,15 +990,68 @@ impl<'a> Parser<'a> {
             return f(&self.token);
         }
 
+        // Plain tokens are passed to `f` by reference, so looking ahead never clones a tree.
         let frame = &self.token_cursor.frame;
-        f(&match frame.tree_cursor.look_ahead(dist - 1) {
-            Some(tree) => match tree {
-                TokenTree::Token(token) => token,
-                TokenTree::Delimited(dspan, delim, _) =>
-                    Token::new(token::OpenDelim(delim), dspan.open),
-            }
-            None => Token::new(token::CloseDelim(frame.delim), frame.span.close)
-        })
+        match frame.tree_cursor.look_ahead(dist - 1) {
+            Some(TokenTree::Token(token)) => f(token),
+            Some(TokenTree::Delimited(dspan, delim, _)) =>
+                f(&Token::new(token::OpenDelim(*delim), dspan.open)),
+            None => f(&Token::new(token::CloseDelim(frame.delim), frame.span.close)),
+        }
+    }
+
+    /// Saves the position of the parser, so that it can try to parse one grammar and go back to
+    /// parse another one with `rollback` if the first one doesn't match.
+    ///
+    /// Unlike cloning the parser, this only copies the cursors of the delimited groups the
+    /// parser is in, except while the parser collects the tokens of an item.
+    pub fn checkpoint(&self) -> ParserCheckpoint {
+        ParserCheckpoint {
+            token: self.token.clone(),
+            meta_var_span: self.meta_var_span,
+            prev_span: self.prev_span,
+            prev_token_kind: self.prev_token_kind,
+            token_cursor: self.token_cursor.clone(),
+            unmatched_angle_bracket_count: self.unmatched_angle_bracket_count,
+            max_angle_bracket_count: self.max_angle_bracket_count,
+            unclosed_delims: self.unclosed_delims.len(),
+            last_unexpected_token_span: self.last_unexpected_token_span,
+            last_type_ascription: self.last_type_ascription,
+            nesting_depth: self.nesting_depth,
+            skipped_bodies: self.skipped_bodies.len(),
+            err_count: self.sess.span_diagnostic.err_count(),
+        }
+    }
+
+    /// Moves the parser back to `checkpoint`. The same checkpoint can be rolled back to any
+    /// number of times.
+    ///
+    /// Errors can't be taken back once they are emitted, so a speculative parse should cancel
+    /// the errors it gets rather than emit them; `errors_since` tells whether any were emitted.
+    pub fn rollback(&mut self, checkpoint: &ParserCheckpoint) {
+        self.token = checkpoint.token.clone();
+        self.meta_var_span = checkpoint.meta_var_span;
+        self.prev_span = checkpoint.prev_span;
+        self.prev_token_kind = checkpoint.prev_token_kind;
+        self.token_cursor = checkpoint.token_cursor.clone();
+        self.unmatched_angle_bracket_count = checkpoint.unmatched_angle_bracket_count;
+        self.max_angle_bracket_count = checkpoint.max_angle_bracket_count;
+        self.unclosed_delims.truncate(checkpoint.unclosed_delims);
+        self.last_unexpected_token_span = checkpoint.last_unexpected_token_span;
+        self.last_type_ascription = checkpoint.last_type_ascription;
+        self.nesting_depth = checkpoint.nesting_depth;
+        self.skipped_bodies.truncate(checkpoint.skipped_bodies);
+        self.expected_tokens.clear();
+    }
+
+    /// Returns the bodies skipped with `skip_fn_bodies` since the last call.
+    pub fn take_skipped_bodies(&mut self) -> SkippedBodies {
+        mem::take(&mut self.skipped_bodies).into_iter().collect()
+    }
+
+    /// Returns the number of errors emitted since `checkpoint` was saved.
+    pub fn errors_since(&self, checkpoint: &ParserCheckpoint) -> usize {
+        self.sess.span_diagnostic.err_count().saturating_sub(checkpoint.err_count)
     }
 
     /// Returns whether any of the given keywords are `dist` tokens ahead of the current one.
@@ -990,6 +1116,18 @@ impl<'a> Parser<'a> {
             debug!("parse_param_general parse_pat (is_name_required:{})", is_name_required);
 
             let pat = self.parse_fn_param_pat()?;
+
+            // A lone macro invocation followed by the end of the parameter, rather than by a
+            // `:`, expands to parameters.
+            let is_mac = match pat.node { ast::PatKind::Mac(..) => true, _ => false };
+            if is_mac && (self.token == token::Comma ||
+                          self.token == token::CloseDelim(token::Paren)) {
+                self.sess.gated_spans.param_macros.borrow_mut().push(pat.span);
+                let ty = P(ast::Ty { id: ast::DUMMY_NODE_ID, node: TyKind::Infer, span: pat.span });
+                let span = lo.to(self.prev_span);
+                return Ok(Param { attrs: attrs.into(), id: ast::DUMMY_NODE_ID, pat, span, ty });
+            }
+
             if let Err(mut err) = self.expect(&token::Colon) {
                 if let Some(ident) = self.parameter_without_type(
                     &mut err,
@@ -1186,6 +1324,29 @@ impl<'a> Parser<'a> {
 
     }
 
+    /// Evaluates the closure one nesting level deeper.
+    ///
+    /// Emits a fatal error instead of recursing if `ParseSess::max_nesting_depth` would be
+    /// exceeded, and grows the stack on demand so that input below the limit never overflows it.
+    fn with_nesting<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> PResult<'a, T>,
+    ) -> PResult<'a, T> {
+        let limit = self.sess.max_nesting_depth;
+        if self.nesting_depth >= limit {
+            let mut err = self.span_fatal(self.token.span, "expression too deeply nested");
+            err.span_label(self.token.span, "nesting limit reached here");
+            err.note(&format!("the parser accepts at most {} levels of nesting", limit));
+            err.help("consider splitting the expression, or raise the limit with \
+                      `-Z parser-nesting-limit`");
+            return Err(err);
+        }
+        self.nesting_depth += 1;
+        let r = ensure_sufficient_stack(|| f(self));
+        self.nesting_depth -= 1;
+        r
+    }
+
     fn parse_fn_params(&mut self, named_params: bool, allow_c_variadic: bool)
                      -> PResult<'a, (Vec<Param> , bool)> {
         let sp = self.token.span;
@@ -1653,6 +1814,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Eats the semicolon following a macro invocation in item position delimited with `delim`,
+    /// reporting it missing if it's required.
+    fn expect_item_mac_semicolon(&mut self, delim: MacDelimiter) {
+        let required = MacPosition::Item.semicolon(delim) == MacSemicolon::Required;
+        if required && !self.eat(&token::Semi) {
+            self.report_invalid_macro_expansion_item();
+        }
+    }
+
     fn report_invalid_macro_expansion_item(&self) {
         self.struct_span_err(
             self.prev_span,
@@ -16,7 +16,7 @@ use crate::parse::token::{self, Token};
 use crate::parse::diagnostics::{Error};
 use crate::print::pprust;
 use crate::source_map::{self, Span};
-use crate::symbol::{kw, sym};
+use crate::symbol::{kw, sym, EditionKeywords};
 use crate::util::parser::{AssocOp, Fixity, prec_let_scrutinee_needs_par};
 
 use std::mem;
@@ -390,9 +390,19 @@ impl<'a> Parser<'a> {
     }
 
     /// Parse a prefix-unary-operator expr
+    ///
+    /// Every nested expression is parsed through here, so this is where the nesting depth of
+    /// expressions is counted.
     fn parse_prefix_expr(
         &mut self,
         already_parsed_attrs: Option<ThinVec<Attribute>>
+    ) -> PResult<'a, P<Expr>> {
+        self.with_nesting(|this| this.parse_prefix_expr_(already_parsed_attrs))
+    }
+
+    fn parse_prefix_expr_(
+        &mut self,
+        already_parsed_attrs: Option<ThinVec<Attribute>>
     ) -> PResult<'a, P<Expr>> {
         let attrs = self.parse_or_use_outer_attributes(already_parsed_attrs)?;
         let lo = self.token.span;
@@ -607,14 +617,6 @@ impl<'a> Parser<'a> {
             expr.map(|mut expr| {
                 attrs.extend::<Vec<_>>(expr.attrs.into());
                 expr.attrs = attrs;
-                match expr.node {
-                    ExprKind::If(..) if !expr.attrs.is_empty() => {
-                        // Just point to the first attribute in there...
-                        let span = expr.attrs[0].span;
-                        self.span_err(span, "attributes are not yet allowed on `if` expressions");
-                    }
-                    _ => {}
-                }
                 expr
             })
         )
@@ -712,7 +714,7 @@ impl<'a> Parser<'a> {
 
     /// Assuming we have just parsed `.`, continue parsing into an expression.
     fn parse_dot_suffix(&mut self, self_arg: P<Expr>, lo: Span) -> PResult<'a, P<Expr>> {
-        if self.token.span.rust_2018() && self.eat_keyword(kw::Await) {
+        if self.eat_edition_keyword(kw::Await) {
             return self.mk_await_expr(self_arg, lo);
         }
 
@@ -991,9 +993,10 @@ impl<'a> Parser<'a> {
                     return self.parse_try_block(lo, attrs);
                 }
 
-                // Span::rust_2018() is somewhat expensive; don't get it repeatedly.
-                let is_span_rust_2018 = self.token.span.rust_2018();
-                if is_span_rust_2018 && self.check_keyword(kw::Async) {
+                // Span::edition() is somewhat expensive; don't get it repeatedly.
+                let edition = self.token.span.edition();
+                if EditionKeywords::is_keyword_in(kw::Async, edition) &&
+                   self.check_keyword(kw::Async) {
                     return if self.is_async_block() { // check for `async {` and `async move {`
                         self.parse_async_block(attrs)
                     } else {
@@ -1033,7 +1036,8 @@ impl<'a> Parser<'a> {
                     self.sess.gated_spans.yields.borrow_mut().push(span);
                 } else if self.eat_keyword(kw::Let) {
                     return self.parse_let_expr(attrs);
-                } else if is_span_rust_2018 && self.eat_keyword(kw::Await) {
+                } else if EditionKeywords::is_keyword_in(kw::Await, edition) &&
+                          self.eat_keyword(kw::Await) {
                     let (await_hi, e_kind) = self.parse_incorrect_await_syntax(lo, self.prev_span)?;
                     hi = await_hi;
                     ex = e_kind;
@@ -1109,7 +1113,7 @@ impl<'a> Parser<'a> {
             Movability::Movable
         };
 
-        let asyncness = if self.token.span.rust_2018() {
+        let asyncness = if EditionKeywords::is_keyword_at(kw::Async, self.token.span) {
             self.parse_asyncness()
         } else {
             IsAsync::NotAsync
@@ -1177,7 +1181,7 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses a parameter in a lambda header (e.g., `|arg, arg|`).
-    fn parse_fn_block_param(&mut self) -> PResult<'a, Param> {
+    crate fn parse_fn_block_param(&mut self) -> PResult<'a, Param> {
         let lo = self.token.span;
         let attrs = self.parse_param_attributes()?;
         let pat = self.parse_pat(PARAM_EXPECTED)?;
@@ -1390,11 +1394,49 @@ impl<'a> Parser<'a> {
         return Ok(self.mk_expr(lo.to(hi), ExprKind::Match(discriminant, arms), attrs));
     }
 
+    /// Parses the arms of a `match` expression, e.g., `0 => a, _ => { b }`, up to the end of the
+    /// input, for macros that take arms as arguments.
+    pub fn parse_arm_list(&mut self) -> PResult<'a, Vec<Arm>> {
+        let mut arms = Vec::new();
+        while self.token != token::Eof {
+            arms.push(self.parse_arm()?);
+        }
+        Ok(arms)
+    }
+
     crate fn parse_arm(&mut self) -> PResult<'a, Arm> {
         let attrs = self.parse_outer_attributes()?;
         let lo = self.token.span;
         // FIXME(or_patterns, Centril | dlrobertson): use `parse_top_pat` instead.
-        let pat = self.parse_top_pat_unpack(GateOr::No)?;
+        let mut pat = self.parse_top_pat_unpack(GateOr::No)?;
+
+        // A lone macro invocation followed by the end of the arm, rather than by a guard or
+        // `=>`, expands to arms.
+        let is_mac = match &pat[..] {
+            [pat] => match pat.node { ast::PatKind::Mac(..) => true, _ => false },
+            _ => false,
+        };
+        let at_arm_end = self.token == token::Comma ||
+                         self.token == token::CloseDelim(token::Brace) ||
+                         self.token == token::Eof;
+        if is_mac && at_arm_end {
+            let pat = pat.pop().unwrap().into_inner();
+            let mac = match pat.node {
+                ast::PatKind::Mac(mac) => mac,
+                _ => unreachable!(),
+            };
+            self.sess.gated_spans.arm_macros.borrow_mut().push(pat.span);
+            self.eat(&token::Comma);
+            return Ok(ast::Arm {
+                attrs,
+                pats: Vec::new(),
+                guard: None,
+                body: self.mk_expr(pat.span, ExprKind::Mac(mac), ThinVec::new()),
+                span: lo.to(self.prev_span),
+                id: ast::DUMMY_NODE_ID,
+            });
+        }
+
         let guard = if self.eat_keyword(kw::If) {
             Some(self.parse_expr()?)
         } else {
@@ -1410,8 +1452,9 @@ impl<'a> Parser<'a> {
                 err
             })?;
 
+        // The arms produced by a macro end with the end of its output instead of a `}`.
         let require_comma = classify::expr_requires_semi_to_be_stmt(&expr)
-            && self.token != token::CloseDelim(token::Brace);
+            && self.token != token::CloseDelim(token::Brace) && self.token != token::Eof;
 
         let hi = self.token.span;
 
@@ -1492,7 +1535,7 @@ impl<'a> Parser<'a> {
     fn is_try_block(&self) -> bool {
         self.token.is_keyword(kw::Try) &&
         self.look_ahead(1, |t| *t == token::OpenDelim(token::Brace)) &&
-        self.token.span.rust_2018() &&
+        EditionKeywords::is_keyword_at(kw::Try, self.token.span) &&
         // prevent `while try {} {}`, `if try {} {} else {}`, etc.
         !self.restrictions.contains(Restrictions::NO_STRUCT_LITERAL)
     }
@@ -155,6 +155,22 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses generic parameters, e.g., `<'a, T: Clone>`, making up the whole input, for macros
+    /// that take the generics of an item as arguments. An empty input has no parameters.
+    pub fn parse_generics_only(&mut self) -> PResult<'a, ast::Generics> {
+        let generics = self.parse_generics()?;
+        self.expect(&token::Eof)?;
+        Ok(generics)
+    }
+
+    /// Parses a where-clause, e.g., `where T: Clone`, making up the whole input, for macros
+    /// that take the bounds of an item as arguments. An empty input has no predicates.
+    pub fn parse_where_clause_only(&mut self) -> PResult<'a, WhereClause> {
+        let where_clause = self.parse_where_clause()?;
+        self.expect(&token::Eof)?;
+        Ok(where_clause)
+    }
+
     /// Parses an optional where-clause and places it in `generics`.
     ///
     /// ```ignore (only-for-syntax-highlight)
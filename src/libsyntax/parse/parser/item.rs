@@ -10,12 +10,13 @@ use crate::ast::{Visibility, VisibilityKind, Mutability, FnDecl, FnHeader};
 use crate::ast::{ForeignItem, ForeignItemKind};
 use crate::ast::{Ty, TyKind, GenericBounds, TraitRef};
 use crate::ast::{EnumDef, VariantData, StructField, AnonConst};
-use crate::ast::{Mac, MacDelimiter};
+use crate::ast::{Block, BlockCheckMode, Mac};
 use crate::ext::base::DummyResult;
-use crate::parse::token;
+use crate::parse::{token, SkippedBody};
+use crate::parse::token::TokenKind;
 use crate::parse::parser::maybe_append;
 use crate::parse::diagnostics::{Error};
-use crate::tokenstream::{TokenTree, TokenStream};
+use crate::tokenstream::{DelimSpan, TokenTree, TokenStream};
 use crate::source_map::{respan, Span, Spanned};
 use crate::symbol::{kw, sym};
 
@@ -70,11 +71,10 @@ impl<'a> Parser<'a> {
         // `tokens` if we detect any inner attributes. This should help keep
         // expansion correct, but we should fix this bug one day!
         Ok(ret.map(|item| {
-            item.map(|mut i| {
+            item.map_in_place(|i| {
                 if !i.attrs.iter().any(|attr| attr.style == AttrStyle::Inner) {
                     i.tokens = Some(tokens);
                 }
-                i
             })
         }))
     }
@@ -82,7 +82,7 @@ impl<'a> Parser<'a> {
     /// Parses one of the items allowed by the flags.
     fn parse_item_implementation(
         &mut self,
-        attrs: Vec<Attribute>,
+        mut attrs: Vec<Attribute>,
         macros_allowed: bool,
         attributes_allowed: bool,
     ) -> PResult<'a, Option<P<Item>>> {
@@ -97,6 +97,7 @@ impl<'a> Parser<'a> {
         let lo = self.token.span;
 
         let visibility = self.parse_visibility(false)?;
+        self.recover_misplaced_attrs_and_vis(lo, &visibility, &mut attrs)?;
 
         if self.eat_keyword(kw::Use) {
             // USE ITEM
@@ -269,6 +270,7 @@ impl<'a> Parser<'a> {
             // FUNCTION ITEM
             self.bump();
             let fn_span = self.prev_span;
+            self.recover_misplaced_attrs(lo, "fn", &mut attrs)?;
             let (ident, item_, extra_attrs) =
                 self.parse_item_fn(Unsafety::Normal,
                                    respan(fn_span, IsAsync::NotAsync),
@@ -310,6 +312,7 @@ impl<'a> Parser<'a> {
         }
         if self.eat_keyword(kw::Mod) {
             // MODULE ITEM
+            self.recover_misplaced_attrs(lo, "mod", &mut attrs)?;
             let (ident, item_, extra_attrs) =
                 self.parse_item_mod(&attrs[..])?;
             let prev_span = self.prev_span;
@@ -337,6 +340,7 @@ impl<'a> Parser<'a> {
         }
         if self.eat_keyword(kw::Enum) {
             // ENUM ITEM
+            self.recover_misplaced_attrs(lo, "enum", &mut attrs)?;
             let (ident, item_, extra_attrs) = self.parse_item_enum()?;
             let prev_span = self.prev_span;
             let item = self.mk_item(lo.to(prev_span),
@@ -358,6 +362,7 @@ impl<'a> Parser<'a> {
                 IsAuto::Yes
             };
             // TRAIT ITEM
+            self.recover_misplaced_attrs(lo, "trait", &mut attrs)?;
             let (ident, item_, extra_attrs) =
                 self.parse_item_trait(is_auto, Unsafety::Normal)?;
             let prev_span = self.prev_span;
@@ -370,6 +375,7 @@ impl<'a> Parser<'a> {
         }
         if self.eat_keyword(kw::Struct) {
             // STRUCT ITEM
+            self.recover_misplaced_attrs(lo, "struct", &mut attrs)?;
             let (ident, item_, extra_attrs) = self.parse_item_struct()?;
             let prev_span = self.prev_span;
             let item = self.mk_item(lo.to(prev_span),
@@ -382,6 +388,7 @@ impl<'a> Parser<'a> {
         if self.is_union_item() {
             // UNION ITEM
             self.bump();
+            self.recover_misplaced_attrs(lo, "union", &mut attrs)?;
             let (ident, item_, extra_attrs) = self.parse_item_union()?;
             let prev_span = self.prev_span;
             let item = self.mk_item(lo.to(prev_span),
@@ -456,7 +463,7 @@ impl<'a> Parser<'a> {
                         sp, &suggestion, format!(" {} ", kw), Applicability::MachineApplicable
                     );
                 } else {
-                    if let Ok(snippet) = self.span_to_snippet(ident_sp) {
+                    if let Some(snippet) = self.sess.source_map().token_text(ident_sp) {
                         err.span_suggestion(
                             full_sp,
                             "if you meant to call a macro, try",
@@ -525,9 +532,7 @@ impl<'a> Parser<'a> {
             let path = self.parse_path(PathStyle::Mod)?;
             self.expect(&token::Not)?;
             let (delim, tts) = self.expect_delimited_token_tree()?;
-            if delim != MacDelimiter::Brace && !self.eat(&token::Semi) {
-                self.report_invalid_macro_expansion_item();
-            }
+            self.expect_item_mac_semicolon(delim);
 
             let hi = self.prev_span;
             let mac = Mac {
@@ -601,9 +606,7 @@ impl<'a> Parser<'a> {
 
             // eat a matched-delimiter token tree:
             let (delim, tts) = self.expect_delimited_token_tree()?;
-            if delim != MacDelimiter::Brace {
-                self.expect(&token::Semi)?;
-            }
+            self.expect_item_mac_semicolon(delim);
 
             Ok(Some(Mac {
                 path,
@@ -865,7 +868,7 @@ impl<'a> Parser<'a> {
             })?;
             generics.where_clause = self.parse_where_clause()?;
             *at_end = true;
-            let (inner_attrs, body) = self.parse_inner_attrs_and_block()?;
+            let (inner_attrs, body) = self.parse_fn_body()?;
             let header = ast::FnHeader { abi, unsafety, constness, asyncness };
             Ok((ident, inner_attrs, generics, ast::ImplItemKind::Method(
                 ast::MethodSig { header, decl },
@@ -1064,7 +1067,7 @@ impl<'a> Parser<'a> {
                 token::OpenDelim(token::Brace) => {
                     debug!("parse_trait_methods(): parsing provided method");
                     *at_end = true;
-                    let (inner_attrs, body) = self.parse_inner_attrs_and_block()?;
+                    let (inner_attrs, body) = self.parse_fn_body()?;
                     attrs.extend(inner_attrs.iter().cloned());
                     Some(body)
                 }
@@ -1277,11 +1280,41 @@ impl<'a> Parser<'a> {
         let allow_c_variadic = abi == Abi::C && unsafety == Unsafety::Unsafe;
         let decl = self.parse_fn_decl(allow_c_variadic)?;
         generics.where_clause = self.parse_where_clause()?;
-        let (inner_attrs, body) = self.parse_inner_attrs_and_block()?;
+        let (inner_attrs, body) = self.parse_fn_body()?;
         let header = FnHeader { unsafety, asyncness, constness, abi };
         Ok((ident, ItemKind::Fn(decl, header, generics, body), Some(inner_attrs)))
     }
 
+    /// Parses the body of a function or method with its inner attributes. With
+    /// `skip_fn_bodies`, the statements are skipped instead and the body is an empty block,
+    /// see `SkippedBody`.
+    fn parse_fn_body(&mut self) -> PResult<'a, (Vec<Attribute>, P<Block>)> {
+        if !self.skip_fn_bodies || self.token != token::OpenDelim(token::Brace) {
+            return self.parse_inner_attrs_and_block();
+        }
+
+        let lo = self.token.span;
+        self.bump();
+        let inner_attrs = self.parse_inner_attributes()?;
+        let mut trees = Vec::new();
+        while self.token != token::CloseDelim(token::Brace) && self.token != token::Eof {
+            trees.push(self.parse_token_tree());
+        }
+        self.expect(&token::CloseDelim(token::Brace))?;
+
+        let span = lo.to(self.prev_span);
+        let delim_span = DelimSpan::from_pair(lo, self.prev_span);
+        let tokens = TokenTree::Delimited(delim_span, token::Brace, trees.into_iter().collect());
+        self.skipped_bodies.push(SkippedBody { span, tokens: tokens.into() });
+        let body = P(Block {
+            stmts: Vec::new(),
+            id: ast::DUMMY_NODE_ID,
+            rules: BlockCheckMode::Default,
+            span,
+        });
+        Ok((inner_attrs, body))
+    }
+
     /// Parses the name and optional generic types of a function header.
     fn parse_fn_header(&mut self) -> PResult<'a, (Ident, ast::Generics)> {
         let id = self.parse_ident()?;
@@ -1537,8 +1570,24 @@ impl<'a> Parser<'a> {
 
     /// Parses the part of an enum declaration following the `{`.
     fn parse_enum_def(&mut self, _generics: &ast::Generics) -> PResult<'a, EnumDef> {
+        let variants = self.parse_variants(&token::CloseDelim(token::Brace))?;
+        self.expect(&token::CloseDelim(token::Brace))?;
+
+        Ok(ast::EnumDef { variants })
+    }
+
+    /// Parses the comma-separated variants of an enum, e.g., `A, B(u8), C { x: u8 } = 3`, up to
+    /// the end of the input, for macros that take the variants of an enum as arguments.
+    pub fn parse_variant_list(&mut self) -> PResult<'a, Vec<ast::Variant>> {
+        let variants = self.parse_variants(&token::Eof)?;
+        self.expect(&token::Eof)?;
+        Ok(variants)
+    }
+
+    /// Parses variants until `end`, or until one of them isn't followed by a comma.
+    fn parse_variants(&mut self, end: &TokenKind) -> PResult<'a, Vec<ast::Variant>> {
         let mut variants = Vec::new();
-        while self.token != token::CloseDelim(token::Brace) {
+        while self.token != *end {
             let variant_attrs = self.parse_outer_attributes()?;
             let vlo = self.token.span;
 
@@ -1593,9 +1642,7 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        self.expect(&token::CloseDelim(token::Brace))?;
-
-        Ok(ast::EnumDef { variants })
+        Ok(variants)
     }
 
     /// Parses `struct Foo { ... }`.
@@ -1733,6 +1780,16 @@ impl<'a> Parser<'a> {
         }).map(|(r, _)| r)
     }
 
+    /// Parses the comma-separated named fields of a struct, e.g., `a: u8, pub b: T`, up to the
+    /// end of the input, for macros that take the fields of a struct as arguments.
+    pub fn parse_field_def_list(&mut self) -> PResult<'a, Vec<StructField>> {
+        let mut fields = Vec::new();
+        while self.token != token::Eof {
+            fields.push(self.parse_struct_decl_field()?);
+        }
+        Ok(fields)
+    }
+
     /// Parses an element of a struct declaration.
     fn parse_struct_decl_field(&mut self) -> PResult<'a, StructField> {
         let attrs = self.parse_outer_attributes()?;
@@ -1756,7 +1813,8 @@ impl<'a> Parser<'a> {
             token::Comma => {
                 self.bump();
             }
-            token::CloseDelim(token::Brace) => {}
+            // The fields parsed by `parse_field_def_list` end with the end of the input.
+            token::CloseDelim(token::Brace) | token::Eof => {}
             token::DocComment(_) => {
                 let previous_span = self.prev_span;
                 let mut err = self.span_fatal_err(self.token.span, Error::UselessDocComment);
@@ -1866,9 +1924,7 @@ impl<'a> Parser<'a> {
 
             let ident = self.parse_ident()?;
             let (delim, tokens) = self.expect_delimited_token_tree()?;
-            if delim != MacDelimiter::Brace && !self.eat(&token::Semi) {
-                self.report_invalid_macro_expansion_item();
-            }
+            self.expect_item_mac_semicolon(delim);
 
             (ident, ast::MacroDef { tokens, legacy: true })
         } else {
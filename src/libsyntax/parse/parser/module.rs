@@ -302,10 +302,12 @@ impl<'a> Parser<'a> {
         let mut p0 =
             new_sub_parser_from_file(self.sess, &path, directory_ownership, Some(name), id_sp);
         p0.cfg_mods = self.cfg_mods;
+        p0.skip_fn_bodies = self.skip_fn_bodies;
         let mod_inner_lo = p0.token.span;
         let mod_attrs = p0.parse_inner_attributes()?;
         let mut m0 = p0.parse_mod_items(&token::Eof, mod_inner_lo)?;
         m0.inline = false;
+        self.skipped_bodies.append(&mut p0.skipped_bodies);
         self.sess.included_mod_stack.borrow_mut().pop();
         Ok((m0, mod_attrs))
     }
@@ -233,6 +233,14 @@ impl<'a> Parser<'a> {
         &mut self,
         allow_range_pat: bool,
         expected: Expected,
+    ) -> PResult<'a, P<Pat>> {
+        self.with_nesting(|this| this.parse_pat_with_range_pat_(allow_range_pat, expected))
+    }
+
+    fn parse_pat_with_range_pat_(
+        &mut self,
+        allow_range_pat: bool,
+        expected: Expected,
     ) -> PResult<'a, P<Pat>> {
         maybe_recover_from_interpolated_ty_qpath!(self, true);
         maybe_whole!(self, NtPat, |x| x);
@@ -7,7 +7,7 @@ use crate::ast::{TraitBoundModifier, TraitObjectSyntax, GenericBound, GenericBou
 use crate::ast::{Mutability, AnonConst, FnDecl, Mac};
 use crate::parse::token::{self, Token};
 use crate::source_map::Span;
-use crate::symbol::{kw};
+use crate::symbol::{kw, EditionKeywords};
 
 use rustc_target::spec::abi::Abi;
 
@@ -50,6 +50,13 @@ impl<'a> Parser<'a> {
 
     pub(super) fn parse_ty_common(&mut self, allow_plus: bool, allow_qpath_recovery: bool,
                        allow_c_variadic: bool) -> PResult<'a, P<Ty>> {
+        self.with_nesting(|this| {
+            this.parse_ty_common_(allow_plus, allow_qpath_recovery, allow_c_variadic)
+        })
+    }
+
+    fn parse_ty_common_(&mut self, allow_plus: bool, allow_qpath_recovery: bool,
+                        allow_c_variadic: bool) -> PResult<'a, P<Ty>> {
         maybe_recover_from_interpolated_ty_qpath!(self, allow_qpath_recovery);
         maybe_whole!(self, NtTy, |x| x);
 
@@ -152,7 +159,7 @@ impl<'a> Parser<'a> {
             impl_dyn_multi = bounds.len() > 1 || self.prev_token_kind == PrevTokenKind::Plus;
             TyKind::ImplTrait(ast::DUMMY_NODE_ID, bounds)
         } else if self.check_keyword(kw::Dyn) &&
-                  (self.token.span.rust_2018() ||
+                  (EditionKeywords::is_keyword_at(kw::Dyn, self.token.span) ||
                    self.look_ahead(1, |t| t.can_begin_bound() &&
                                           !can_continue_type_after_non_fn_ident(t))) {
             self.bump(); // `dyn`
@@ -0,0 +1,179 @@
+//! Reparsing of a crate after edits to its source text.
+//!
+//! Instead of parsing the whole crate again, only the top-level items touched by the edits are
+//! lexed and parsed again, from a new version of their source file, and spliced into the
+//! crate in place of the old ones. This is the core loop of an interactive tool that keeps an
+//! AST in sync with an editor buffer.
+
+use crate::ast::{self, NodeId};
+use crate::parse::{self, lexer, token, Directory, DirectoryOwnership, ParseSess};
+use crate::ptr::P;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::stable_hasher::StableHasher;
+use rustc_data_structures::sync::Lrc;
+use syntax_pos::{BytePos, FileName, SourceFile, Span};
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+#[cfg(test)]
+mod tests;
+
+/// A replacement of the source text at `span` with `text`.
+#[derive(Clone, Debug)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ReparseResult {
+    /// The `NodeId`s of the items that were replaced by reparsed ones, in crate order.
+    pub changed: Vec<NodeId>,
+    /// The edits that couldn't be applied by reparsing single items, because they aren't
+    /// contained in a top-level item, overlap with other edits, or leave their item with errors.
+    /// Errors are reported as usual. The crate needs to be parsed as a whole to apply these.
+    pub unapplied: Vec<TextEdit>,
+}
+
+/// Applies `edits` to the source of `krate`, reparsing the top-level items they touch.
+///
+/// An edited item may turn into any number of items, e.g. when an edit splits it in two. The
+/// new items have `DUMMY_NODE_ID`s and spans into a new source file holding the edited text.
+pub fn apply_edits(krate: &mut ast::Crate, sess: &ParseSess, mut edits: Vec<TextEdit>)
+                   -> ReparseResult {
+    let mut result = ReparseResult::default();
+
+    // Assign the edits to the items containing them, keyed by the index of the item.
+    edits.sort_by_key(|edit| edit.span.lo());
+    let mut affected: BTreeMap<usize, Vec<TextEdit>> = BTreeMap::new();
+    let mut last_hi = BytePos(0);
+    for edit in edits {
+        let index = krate.module.items.iter()
+            .position(|item| extent(item).contains(edit.span));
+        match index {
+            Some(index) if edit.span.lo() >= last_hi => {
+                last_hi = edit.span.hi();
+                affected.entry(index).or_default().push(edit);
+            }
+            _ => result.unapplied.push(edit),
+        }
+    }
+
+    // Create the new version of each file with edited items. Edits that are left unapplied
+    // don't go into it, so that the unchanged items still match their text.
+    let source_map = sess.source_map();
+    let mut files = FxHashMap::default();
+    for edits in affected.values() {
+        let file = source_map.lookup_byte_offset(edits[0].span.lo()).sf;
+        files.entry(file.start_pos).or_insert_with(|| (file, Vec::new())).1.extend(edits);
+    }
+    let edited_files: FxHashMap<BytePos, Lrc<SourceFile>> = files.into_iter()
+        .map(|(start_pos, (file, edits))| (start_pos, edited_file(sess, &file, &edits)))
+        .collect();
+
+    // Reparse the edited items, from the last one so that the indices of the others stay valid.
+    for (&index, edits) in affected.iter().rev() {
+        let old_item = &krate.module.items[index];
+        let old_extent = extent(old_item);
+        let file = source_map.lookup_byte_offset(old_extent.lo()).sf;
+        let edited_file = &edited_files[&file.start_pos];
+
+        // Edits in the same file before the item move it, its own edits change its length.
+        let shift = |pos: BytePos, edits: &mut dyn Iterator<Item = &TextEdit>| {
+            edits.fold(pos.0 as i64 - file.start_pos.0 as i64, |pos, edit| {
+                pos + edit.text.len() as i64 - (edit.span.hi() - edit.span.lo()).0 as i64
+            })
+        };
+        let preceding = affected.range(..index).flat_map(|(_, edits)| edits)
+            .filter(|edit| file.contains(edit.span.lo()));
+        let lo = shift(old_extent.lo(), &mut preceding.clone());
+        let hi = shift(old_extent.hi(), &mut preceding.chain(edits));
+        let span = old_extent.with_lo(edited_file.start_pos + BytePos(lo as u32))
+            .with_hi(edited_file.start_pos + BytePos(hi as u32));
+
+        match parse_items(sess, &file.name, span) {
+            Some(items) => {
+                result.changed.push(old_item.id);
+                krate.module.items.splice(index..=index, items);
+            }
+            None => result.unapplied.extend(edits.iter().cloned()),
+        }
+    }
+    result.changed.reverse();
+    result
+}
+
+/// The span of `item` including its outer attributes.
+fn extent(item: &ast::Item) -> Span {
+    item.attrs.iter().fold(item.span, |span, attr| span.to(attr.span))
+}
+
+/// Registers the text of `file` with `edits` applied as a new source file.
+fn edited_file(sess: &ParseSess, file: &SourceFile, edits: &[&TextEdit]) -> Lrc<SourceFile> {
+    let src = file.src.as_ref().expect("cannot reparse a file without its source");
+    let mut text = String::with_capacity(src.len());
+    let mut pos = 0;
+    for edit in edits {
+        let lo = (edit.span.lo() - file.start_pos).0 as usize;
+        text.push_str(&src[pos..lo]);
+        text.push_str(&edit.text);
+        pos = (edit.span.hi() - file.start_pos).0 as usize;
+    }
+    text.push_str(&src[pos..]);
+
+    // Files are looked up by name, so every version needs a name of its own.
+    let mut hasher = StableHasher::<u64>::new();
+    text.hash(&mut hasher);
+    let name = FileName::Custom(format!("{} (edited {:016x})", file.name, hasher.finish()));
+    sess.source_map().new_source_file(name, text)
+}
+
+/// Lexes and parses the items at `span`. Returns `None` if that reports errors.
+fn parse_items(sess: &ParseSess, original_name: &FileName, span: Span)
+               -> Option<Vec<P<ast::Item>>> {
+    let errors = sess.span_diagnostic.err_count();
+    let (stream, unmatched_braces) = lexer::StringReader::retokenize(sess, span)
+        .into_token_trees();
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(mut err) => {
+            err.emit();
+            return None;
+        }
+    };
+
+    // Out-of-line modules are still looked up next to the original file.
+    let mut parser = match original_name {
+        FileName::Real(path) => {
+            let directory = Directory {
+                path: Cow::Owned(path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default()),
+                ownership: DirectoryOwnership::Owned { relative: None },
+            };
+            parse::stream_to_parser_with_base_dir(sess, stream, directory)
+        }
+        _ => parse::stream_to_parser(sess, stream, None),
+    };
+    parser.unclosed_delims = unmatched_braces;
+
+    let mut items = Vec::new();
+    while parser.token != token::Eof {
+        match parser.parse_item() {
+            Ok(Some(item)) => items.push(item),
+            Ok(None) => {
+                let msg = format!("expected item, found {}", parser.this_token_descr());
+                parser.struct_span_err(parser.token.span, &msg).emit();
+                break;
+            }
+            Err(mut err) => {
+                err.emit();
+                break;
+            }
+        }
+    }
+    drop(parser);
+
+    if sess.span_diagnostic.err_count() == errors { Some(items) } else { None }
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+use crate::print::pprust;
+use crate::source_map::FilePathMapping;
+use crate::with_default_globals;
+
+use std::path::PathBuf;
+
+fn parse(sess: &ParseSess, source: &str) -> ast::Crate {
+    let mut krate = parse::parse_crate_from_source_str(
+        PathBuf::from("test").into(), source.to_string(), sess
+    ).unwrap();
+    for (i, item) in krate.module.items.iter_mut().enumerate() {
+        item.id = NodeId::from_usize(i);
+    }
+    krate
+}
+
+fn edit(krate: &ast::Crate, lo: u32, hi: u32, text: &str) -> TextEdit {
+    let base = krate.module.items[0].span.lo();
+    let span = krate.span.with_lo(base + BytePos(lo)).with_hi(base + BytePos(hi));
+    TextEdit { span, text: text.to_string() }
+}
+
+#[test]
+fn only_edited_items_are_replaced() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let mut krate = parse(&sess, "fn a() {}\nfn b() { 1 }\nfn c() {}\n");
+
+        // Replace `1` in `b` and split `c` in two.
+        let edits = vec![edit(&krate, 19, 20, "2 + 3"), edit(&krate, 32, 32, " fn d() {}")];
+        let result = apply_edits(&mut krate, &sess, edits);
+
+        assert_eq!(result.changed, [NodeId::from_usize(1), NodeId::from_usize(2)]);
+        assert!(result.unapplied.is_empty());
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+        let items: Vec<_> = krate.module.items.iter().map(|item| pprust::item_to_string(item))
+            .collect();
+        assert_eq!(items.len(), 4);
+        assert_eq!(krate.module.items[0].id, NodeId::from_usize(0));
+        assert!(items[1].contains("2 + 3"), "{}", items[1]);
+        assert!(items[3].starts_with("fn d()"), "{}", items[3]);
+    })
+}
+
+#[test]
+fn edits_outside_of_items_are_not_applied() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let mut krate = parse(&sess, "fn a() {}\n\nfn b() {}\n");
+
+        let result = apply_edits(&mut krate, &sess, vec![edit(&krate, 10, 10, "fn c() {}")]);
+
+        assert!(result.changed.is_empty());
+        assert_eq!(result.unapplied.len(), 1);
+        assert_eq!(krate.module.items.len(), 2);
+    })
+}
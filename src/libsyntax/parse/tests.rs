@@ -337,3 +337,227 @@ fn non_pattern_whitespace() {
     assert_eq!(matches_codepattern("\u{205F}a   b","ab"), false);
     assert_eq!(matches_codepattern("a  \u{3000}b","ab"), false);
 }
+
+#[test]
+fn nesting_depth_limit() {
+    with_default_globals(|| {
+        let mut sess = ParseSess::new(FilePathMapping::empty());
+        sess.max_nesting_depth = 32;
+        let nested = |depth| format!("{}0{}", "(".repeat(depth), ")".repeat(depth));
+
+        // Each pair of parentheses is one level, the outermost expression is another one.
+        let mut parser = new_parser_from_source_str(&sess, PathBuf::from("a").into(), nested(31));
+        assert!(parser.parse_expr().is_ok());
+
+        let mut parser = new_parser_from_source_str(&sess, PathBuf::from("b").into(), nested(32));
+        let mut err = parser.parse_expr().unwrap_err();
+        assert_eq!(err.message(), "expression too deeply nested");
+        err.cancel();
+    })
+}
+
+#[test]
+fn unknown_suffix_policy_accepts_literals() {
+    with_default_globals(|| {
+        let mut sess = ParseSess::new(FilePathMapping::empty());
+        let policy: Lrc<UnknownSuffixPolicy> = Lrc::new(|_, suffix: Symbol| {
+            if suffix.as_str() == "px" { UnknownSuffix::Accept } else { UnknownSuffix::Reject }
+        });
+        sess.unknown_suffix_policy = Some(policy);
+
+        let expr = with_error_checking_parse("10px".to_string(), &sess, |p| p.parse_expr());
+        match expr.node {
+            ast::ExprKind::Lit(ref lit) => {
+                assert_eq!(lit.node, ast::LitKind::Int(10, ast::LitIntType::Unsuffixed));
+                assert_eq!(lit.suffix(), Some(Symbol::intern("px")));
+            }
+            _ => panic!("expected a literal, found `{}`", pprust::expr_to_string(&expr)),
+        }
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn reparsed_nonterminals_keep_invocation_tokens() {
+    fn find_args(tokens: TokenStream) -> Option<TokenStream> {
+        let mut after_not = false;
+        for tree in tokens.trees() {
+            match tree {
+                TokenTree::Delimited(_, _, tts) if after_not => return Some(tts),
+                TokenTree::Delimited(_, _, tts) => {
+                    if let Some(args) = find_args(tts) {
+                        return Some(args);
+                    }
+                    after_not = false;
+                }
+                TokenTree::Token(tok) => after_not = tok.kind == token::Not,
+            }
+        }
+        None
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "fn f() { foo!(a   + b); }".to_string();
+        let mut item = parse_item_from_source_str(PathBuf::from("source").into(), source, &sess)
+            .unwrap().unwrap();
+        // Without the cached tokens, the item has to be pretty-printed.
+        item.tokens = None;
+        let tokens = token::NtItem(item).to_tokenstream(&sess, syntax_pos::DUMMY_SP);
+        let args = find_args(tokens).expect("the invocation should be in the tokens");
+        assert_eq!(args.trees().next().unwrap().span(), sp(14, 15));
+        assert_eq!(pprust::tts_to_string(args), "a + b");
+        assert_eq!(sess.span_diagnostic.err_count(), 0);
+    })
+}
+
+#[test]
+fn validity_predicates_are_silent() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let stream = |s: &str| string_to_stream(s.to_string());
+
+        assert_eq!(is_valid_expr(&sess, stream("f(x) + 1")), Ok(()));
+        assert_eq!(is_valid_expr(&sess, stream("a b")), Err(sp(2, 3)));
+        assert_eq!(is_valid_ty(&sess, stream("Vec<(u8, &'a str)>")), Ok(()));
+        assert!(is_valid_ty(&sess, stream("1 + 2")).is_err());
+        assert_eq!(is_valid_meta_item(&sess, stream("a(b, c = \"d\")")), Ok(()));
+        assert_eq!(is_valid_meta_item(&sess, stream("a = b")), Err(sp(4, 5)));
+        // The missing semicolon would be recovered from with recovery on.
+        assert!(is_valid_expr(&sess, stream("{ let a = 1\n let b = 2; }")).is_err());
+        assert!(!sess.span_diagnostic.has_errors());
+    })
+}
+
+#[test]
+fn rollback_restores_checkpoint() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "foo(bar) baz".to_string();
+        let mut p = new_parser_from_source_str(&sess, PathBuf::from("bogofile").into(), source);
+        let checkpoint = p.checkpoint();
+
+        // Leave the parser inside the parentheses.
+        p.bump();
+        p.bump();
+        assert_eq!(p.token, token::Ident(Name::intern("bar"), false));
+        p.rollback(&checkpoint);
+        assert_eq!(p.token, token::Ident(Name::intern("foo"), false));
+
+        let expr = p.parse_expr().unwrap();
+        assert_eq!(pprust::expr_to_string(&expr), "foo(bar)");
+        assert_eq!(p.token, token::Ident(Name::intern("baz"), false));
+        p.rollback(&checkpoint);
+        let ty = p.parse_ty().unwrap();
+        assert_eq!(pprust::ty_to_string(&ty), "foo(bar)");
+        assert_eq!(p.errors_since(&checkpoint), 0);
+    })
+}
+
+#[test]
+fn skipped_bodies_parse_lazily() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "fn f() -> u8 { #![allow(unused)] let x = 1; x }\n\
+                      impl S { fn g(&self) { h(|| {}) } }".to_string();
+        let name = PathBuf::from("bogofile").into();
+        let (krate, skipped) = parse_crate_signatures_from_source_str(name, source, &sess).unwrap();
+        assert_eq!(skipped.len(), 2);
+
+        let (attrs, body) = match &krate.module.items[0].node {
+            ast::ItemKind::Fn(_, _, _, body) => (&krate.module.items[0].attrs, body),
+            _ => panic!("expected a function"),
+        };
+        assert!(body.stmts.is_empty());
+        assert_eq!(attrs.len(), 1);
+        assert!(attrs[0].check_name(sym::allow));
+
+        let parsed = skipped.get(body).unwrap().parse(&sess).unwrap();
+        assert_eq!(parsed.span, body.span);
+        assert_eq!(parsed.stmts.len(), 2);
+    })
+}
+
+#[test]
+fn parse_lists_from_macro_input() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let fields = with_error_checking_parse("a: u8, pub b: Vec<T>".to_string(), &sess, |p| {
+            p.parse_field_def_list()
+        });
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1].ident.unwrap().name, Name::intern("b"));
+
+        let source = "A, B(u8), C { x: u8 } = 3,".to_string();
+        let variants = with_error_checking_parse(source, &sess, |p| p.parse_variant_list());
+        assert_eq!(variants.len(), 3);
+        assert!(variants[2].disr_expr.is_some());
+
+        let generics = with_error_checking_parse("<'a, T: Clone>".to_string(), &sess, |p| {
+            p.parse_generics_only()
+        });
+        assert_eq!(generics.params.len(), 2);
+
+        let source = "where T: Clone, 'a: 'b".to_string();
+        let where_clause = with_error_checking_parse(source, &sess, |p| p.parse_where_clause_only());
+        assert_eq!(where_clause.predicates.len(), 2);
+
+        let arms = with_error_checking_parse("0 => a, _ => { b }".to_string(), &sess, |p| {
+            p.parse_arm_list()
+        });
+        assert_eq!(arms.len(), 2);
+
+        let mut p = new_parser_from_source_str(&sess, PathBuf::from("bogofile").into(),
+                                               "<T> x".to_string());
+        p.parse_generics_only().unwrap_err().cancel();
+    })
+}
+
+#[test]
+fn token_cursor_walks_nested_delimiters() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "a (b [c] {}) d".to_string();
+        let mut p = new_parser_from_source_str(&sess, PathBuf::from("bogofile").into(), source);
+        assert_eq!(p.look_ahead(1, |t| t.kind.clone()), token::OpenDelim(token::Paren));
+        assert_eq!(p.look_ahead(2, |t| t.kind.clone()), token::Ident(Name::intern("b"), false));
+
+        let mut kinds = Vec::new();
+        while p.token != token::Eof {
+            kinds.push(p.token.kind.clone());
+            p.bump();
+        }
+        let ident = |name| token::Ident(Name::intern(name), false);
+        assert_eq!(kinds, vec![
+            ident("a"),
+            token::OpenDelim(token::Paren),
+            ident("b"),
+            token::OpenDelim(token::Bracket),
+            ident("c"),
+            token::CloseDelim(token::Bracket),
+            token::OpenDelim(token::Brace),
+            token::CloseDelim(token::Brace),
+            token::CloseDelim(token::Paren),
+            ident("d"),
+        ]);
+    })
+}
+
+#[test]
+fn collected_tokens_include_delimited_groups() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let source = "mod m { fn f(x: u8) { g([x], {}) } }".to_string();
+        let mut p = new_parser_from_source_str(&sess, PathBuf::from("bogofile").into(), source);
+        let item = p.parse_item().unwrap().unwrap();
+        let expected = string_to_stream("mod m { fn f(x: u8) { g([x], {}) } }".to_string());
+        assert!(item.tokens.as_ref().unwrap().eq_unspanned(&expected));
+
+        let inner = match &item.node {
+            ast::ItemKind::Mod(module) => &module.items[0],
+            _ => panic!("expected a module"),
+        };
+        let expected = string_to_stream("fn f(x: u8) { g([x], {}) }".to_string());
+        assert!(inner.tokens.as_ref().unwrap().eq_unspanned(&expected));
+    })
+}
@@ -5,11 +5,13 @@ pub use LitKind::*;
 pub use TokenKind::*;
 
 use crate::ast::{self};
+use crate::mut_visit::{self, MutVisitor};
 use crate::parse::{parse_stream_from_source_str, ParseSess};
 use crate::print::pprust;
 use crate::ptr::P;
 use crate::symbol::kw;
-use crate::tokenstream::{self, DelimSpan, TokenStream, TokenTree};
+use crate::tokenstream::{self, DelimSpan, IsJoint, TokenStream, TokenTree, TreeAndJoint};
+use crate::visit::Visitor;
 
 use syntax_pos::symbol::Symbol;
 use syntax_pos::{self, Span, FileName, DUMMY_SP};
@@ -739,6 +741,26 @@ impl fmt::Debug for Nonterminal {
 }
 
 impl Nonterminal {
+    /// The span of the code the nonterminal was parsed from.
+    pub fn span(&self) -> Span {
+        match *self {
+            NtItem(ref item) => item.span,
+            NtBlock(ref block) => block.span,
+            NtStmt(ref stmt) => stmt.span,
+            NtPat(ref pat) => pat.span,
+            NtExpr(ref expr) | NtLiteral(ref expr) => expr.span,
+            NtTy(ref ty) => ty.span,
+            NtIdent(ident, _) | NtLifetime(ident) => ident.span,
+            NtMeta(ref meta) => meta.span,
+            NtPath(ref path) => path.span,
+            NtVis(ref vis) => vis.span,
+            NtTT(ref tt) => tt.span(),
+            NtTraitItem(ref item) => item.span,
+            NtImplItem(ref item) => item.span,
+            NtForeignItem(ref item) => item.span,
+        }
+    }
+
     pub fn to_tokenstream(&self, sess: &ParseSess, span: Span) -> TokenStream {
         // A `Nonterminal` is often a parsed AST item. At this point we now
         // need to convert the parsed AST to an actual token stream, e.g.
@@ -775,9 +797,7 @@ impl Nonterminal {
         };
 
         // FIXME(#43081): Avoid this pretty-print + reparse hack
-        let source = pprust::nonterminal_to_string(self);
-        let filename = FileName::macro_expansion_source_code(&source);
-        let tokens_for_real = parse_stream_from_source_str(filename, source, sess, Some(span));
+        let tokens_for_real = reparse_shielded(self, sess, span);
 
         // During early phases of the compiler the AST could get modified
         // directly (e.g., attributes added or removed) and the internal cache
@@ -808,11 +828,180 @@ impl Nonterminal {
             }
             info!("cached tokens found, but they're not \"probably equal\", \
                    going with stringified version");
+            // The comments kept with `ParseSess::preserve_comments` are found by the spans of
+            // the tokens, which the stringified version doesn't have.
+            if sess.preserve_comments {
+                if let Some(span) = first_span_with_comments(sess, &tokens) {
+                    sess.span_diagnostic.span_warn(
+                        span,
+                        "comments can't be preserved in tokens of an item changed by a macro",
+                    );
+                }
+            }
         }
         return tokens_for_real
     }
 }
 
+/// The span of the first token of `tokens` preceded by comments kept with
+/// `ParseSess::preserve_comments`.
+fn first_span_with_comments(sess: &ParseSess, tokens: &TokenStream) -> Option<Span> {
+    let has_comments = |span: &Span| sess.comment_trivia.borrow().contains_key(&span.lo());
+    tokens.trees().find_map(|tree| match tree {
+        TokenTree::Token(token) => Some(token.span).filter(has_comments),
+        TokenTree::Delimited(span, _, tts) => Some(span.open).filter(has_comments)
+            .or_else(|| first_span_with_comments(sess, &tts))
+            .or_else(|| Some(span.close).filter(has_comments)),
+    })
+}
+
+/// The prefix of the names of the placeholder macros standing in for the macro invocations of a
+/// nonterminal while it's pretty-printed, followed by the index of the invocation.
+const SHIELDED_INVOCATION_PREFIX: &str = "__rustc_shielded_invocation_";
+
+/// Pretty-prints `nt` and reparses the result, except for the macro invocations in it, whose
+/// tokens are kept verbatim, with their spans, instead of being printed and reparsed too.
+///
+/// The invocations are shielded from the pretty-printer by replacing them with placeholder
+/// invocations without arguments, which are swapped back for the original tokens after
+/// reparsing. The placeholders never make it into the result: if one doesn't survive the round
+/// trip in its expected shape, the whole nonterminal is pretty-printed instead. Nonterminals
+/// without invocations are pretty-printed directly, without being copied.
+fn reparse_shielded(nt: &Nonterminal, sess: &ParseSess, span: Span) -> TokenStream {
+    let reparse = |nt: &Nonterminal| {
+        let source = pprust::nonterminal_to_string(nt);
+        let filename = FileName::macro_expansion_source_code(&source);
+        parse_stream_from_source_str(filename, source, sess, Some(span))
+    };
+    if !contains_invocations(nt) {
+        return reparse(nt);
+    }
+    let mut shielded = nt.clone();
+    let mut shield = InvocationShield { invocations: Vec::new() };
+    mut_visit::noop_visit_interpolated(&mut shielded, &mut shield);
+    let mut invocations: Vec<_> = shield.invocations.into_iter().map(Some).collect();
+    let tokens = unshield(reparse(&shielded), &mut invocations);
+    if invocations.iter().any(Option::is_some) {
+        return reparse(nt);
+    }
+    tokens
+}
+
+/// Whether `nt` contains a macro invocation that `InvocationShield` would replace.
+fn contains_invocations(nt: &Nonterminal) -> bool {
+    let mut finder = InvocationFinder { found: false };
+    match nt {
+        NtItem(item) => finder.visit_item(item),
+        NtBlock(block) => finder.visit_block(block),
+        NtStmt(stmt) => finder.visit_stmt(stmt),
+        NtPat(pat) => finder.visit_pat(pat),
+        NtExpr(expr) | NtLiteral(expr) => finder.visit_expr(expr),
+        NtTy(ty) => finder.visit_ty(ty),
+        NtImplItem(item) => finder.visit_impl_item(item),
+        NtTraitItem(item) => finder.visit_trait_item(item),
+        NtForeignItem(item) => finder.visit_foreign_item(item),
+        NtPath(path) => finder.visit_path(path, ast::DUMMY_NODE_ID),
+        NtVis(vis) => finder.visit_vis(vis),
+        NtIdent(..) | NtLifetime(..) | NtMeta(..) | NtTT(..) => {}
+    }
+    finder.found
+}
+
+struct InvocationFinder {
+    found: bool,
+}
+
+impl<'ast> Visitor<'ast> for InvocationFinder {
+    fn visit_mac(&mut self, _mac: &'ast ast::Mac) {
+        self.found = true;
+    }
+}
+
+/// Replaces the macro invocations it visits with placeholders, see `reparse_shielded`.
+struct InvocationShield {
+    invocations: Vec<ast::Mac>,
+}
+
+impl MutVisitor for InvocationShield {
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        let name = format!("{}{}", SHIELDED_INVOCATION_PREFIX, self.invocations.len());
+        let placeholder = ast::Mac {
+            path: ast::Path::from_ident(ast::Ident::from_str_and_span(&name, mac.path.span)),
+            delim: mac.delim,
+            tts: TokenStream::empty(),
+            span: mac.span,
+            prior_type_ascription: mac.prior_type_ascription,
+        };
+        self.invocations.push(mem::replace(mac, placeholder));
+    }
+}
+
+/// Replaces the placeholder invocations in `tokens` with the tokens of the invocations they
+/// stand in for, taking those out of `invocations`.
+fn unshield(tokens: TokenStream, invocations: &mut [Option<ast::Mac>]) -> TokenStream {
+    let mut cursor = tokens.into_trees();
+    let mut trees = Vec::new();
+    while let Some(tree) = cursor.next_with_joint() {
+        trees.push(tree);
+    }
+    let mut result = Vec::with_capacity(trees.len());
+    let mut i = 0;
+    while i < trees.len() {
+        let index = trees.get(i..i + 3).and_then(shielded_invocation_index);
+        if let Some(mac) = index.and_then(|index| invocations.get_mut(index)?.take()) {
+            result.extend(invocation_tokens(mac).into_iter().map(|tree| (tree, IsJoint::NonJoint)));
+            i += 3;
+            continue;
+        }
+        let (tree, joint) = trees[i].clone();
+        let tree = match tree {
+            TokenTree::Delimited(span, delim, tts) => {
+                TokenTree::Delimited(span, delim, unshield(tts, invocations))
+            }
+            tree => tree,
+        };
+        result.push((tree, joint));
+        i += 1;
+    }
+    TokenStream::new(result)
+}
+
+/// Returns the index of the invocation if `trees` are the path, `!` and (empty) arguments of a
+/// placeholder invocation.
+fn shielded_invocation_index(trees: &[TreeAndJoint]) -> Option<usize> {
+    match trees {
+        [(TokenTree::Token(Token { kind: Ident(name, false), .. }), _),
+         (TokenTree::Token(Token { kind: Not, .. }), _),
+         (TokenTree::Delimited(..), _)] => {
+            let name = name.as_str();
+            if name.starts_with(SHIELDED_INVOCATION_PREFIX) {
+                name[SHIELDED_INVOCATION_PREFIX.len()..].parse().ok()
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The tokens of the invocation `mac`: its path, `!` and its arguments.
+fn invocation_tokens(mac: ast::Mac) -> Vec<TokenTree> {
+    let mut trees = Vec::new();
+    for (i, segment) in mac.path.segments.iter().enumerate() {
+        let ident = segment.ident;
+        if i > 0 {
+            trees.push(TokenTree::token(ModSep, ident.span.shrink_to_lo()));
+        }
+        if ident.name != kw::PathRoot {
+            trees.push(TokenTree::token(Ident(ident.name, ident.is_raw_guess()), ident.span));
+        }
+    }
+    trees.push(TokenTree::token(Not, mac.path.span.shrink_to_hi()));
+    let delim_span = DelimSpan::from_single(mac.span);
+    trees.push(TokenTree::Delimited(delim_span, mac.delim.to_token(), mac.tts));
+    trees
+}
+
 fn prepend_attrs(sess: &ParseSess,
                  attrs: &[ast::Attribute],
                  tokens: Option<&tokenstream::TokenStream>,
@@ -852,7 +1041,7 @@ fn prepend_attrs(sess: &ParseSess,
             brackets.push(stream);
         }
 
-        brackets.push(attr.tokens.clone());
+        brackets.push(attr.args.outer_tokens());
 
         // The span we list here for `#` and for `[ ... ]` are both wrong in
         // that it encompasses more than each token, but it hopefully is "good
@@ -1,8 +1,9 @@
 use crate::ast::{self, BlockCheckMode, PatKind, RangeEnd, RangeSyntax};
 use crate::ast::{SelfKind, GenericBound, TraitBoundModifier};
-use crate::ast::{Attribute, MacDelimiter, GenericArg};
+use crate::ast::{Attribute, MacPosition, MacSemicolon, GenericArg};
 use crate::util::parser::{self, AssocOp, Fixity};
 use crate::attr;
+use crate::ext::expand::AstFragment;
 use crate::source_map::{self, SourceMap, Spanned};
 use crate::parse::token::{self, BinOpToken, DelimToken, Nonterminal, Token, TokenKind};
 use crate::parse::lexer::comments;
@@ -40,6 +41,16 @@ pub enum AnnNode<'a> {
 pub trait PpAnn {
     fn pre(&self, _state: &mut State<'_>, _node: AnnNode<'_>) { }
     fn post(&self, _state: &mut State<'_>, _node: AnnNode<'_>) { }
+
+    /// Comments to print on their own lines before the item, trait item, impl item or foreign
+    /// item `id`, in addition to any comments found in the source. Each line is printed as is,
+    /// so it should include its `//` or `///` marker.
+    ///
+    /// This lets code without source text, such as macro output, point back at its origin,
+    /// e.g. `// generated by #[derive(Clone)] at src/lib.rs:42`.
+    fn synthesized_comments(&self, _id: ast::NodeId) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -360,6 +371,152 @@ pub fn tts_to_string(tokens: TokenStream) -> String {
     to_string(|s| s.print_tts(tokens, false))
 }
 
+/// Like `tts_to_string`, but if `tokens` holds more than `max_tokens` tokens, the middle of the
+/// stream is elided with `...`. Delimited groups that are kept are always printed with both of
+/// their delimiters, so the output stays balanced.
+pub fn tts_to_string_truncated(tokens: TokenStream, max_tokens: Option<usize>) -> String {
+    match max_tokens {
+        Some(max_tokens) => tts_to_string(truncate_tts(tokens, max_tokens)),
+        None => tts_to_string(tokens),
+    }
+}
+
+/// Renders `tokens` as the source text they were written as, which is what `stringify_source!`
+/// expands to, as opposed to the canonical rendering of `tts_to_string`.
+///
+/// Runs of token trees written one after another in the same syntax context, with nothing but
+/// whitespace and comments between them, are rendered as the text they span. Tokens are never
+/// joined with text they weren't written next to, e.g., fragments substituted into a
+/// `macro_rules` body are only joined with each other if they were passed to the macro that
+/// way. Tokens without source text, like those made up by procedural macros, are rendered
+/// canonically. Separate runs are joined with a single space.
+pub fn tts_to_source_string(sm: &SourceMap, tokens: TokenStream) -> String {
+    fn push(out: &mut String, s: &str) {
+        if !out.is_empty() && !s.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(s);
+    }
+
+    let mut out = String::new();
+    let mut run: Option<Span> = None;
+    for tt in tokens.into_trees() {
+        // Interpolated tokens are spanned by the `$var` they were substituted for.
+        let span = match tt {
+            TokenTree::Token(Token { kind: token::Interpolated(ref nt), .. }) => nt.span(),
+            _ => tt.span(),
+        };
+        if let Some(run_span) = run {
+            let gap = run_span.between(span);
+            if run_span.ctxt() == span.ctxt() && run_span.hi() <= span.lo() &&
+               sm.token_text(gap).map_or(false, |gap| is_trivia(&gap)) {
+                run = Some(run_span.with_hi(span.hi()));
+                continue;
+            }
+            push(&mut out, &sm.token_text(run_span).unwrap());
+            run = None;
+        }
+        if !span.is_dummy() && sm.token_text(span).is_some() {
+            run = Some(span);
+        } else {
+            push(&mut out, &tt_to_string(tt));
+        }
+    }
+    if let Some(run_span) = run {
+        push(&mut out, &sm.token_text(run_span).unwrap());
+    }
+    out
+}
+
+/// Whether `text` consists of whitespace and comments only.
+fn is_trivia(mut text: &str) -> bool {
+    loop {
+        text = text.trim_start();
+        if text.starts_with("//") {
+            match text.find('\n') {
+                Some(end) => text = &text[end..],
+                None => return true,
+            }
+        } else if text.starts_with("/*") {
+            let bytes = text.as_bytes();
+            let (mut depth, mut i) = (0, 0);
+            loop {
+                if i + 1 >= bytes.len() {
+                    return false;
+                }
+                if bytes[i..i + 2] == *b"/*" {
+                    depth += 1;
+                    i += 2;
+                } else if bytes[i..i + 2] == *b"*/" {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+            text = &text[i..];
+        } else {
+            return text.is_empty();
+        }
+    }
+}
+
+/// The number of tokens in `tree`, counting the delimiters of delimited groups.
+fn tt_token_count(tree: &TokenTree) -> usize {
+    match tree {
+        TokenTree::Token(..) => 1,
+        TokenTree::Delimited(_, _, tts) => {
+            2 + tts.trees().map(|tt| tt_token_count(&tt)).sum::<usize>()
+        }
+    }
+}
+
+/// Elides the middle of `tokens` with a single `...` so that at most `max_tokens` tokens are
+/// left. Whole trees are kept from both ends. If a single tree is left out between them, and
+/// it's a delimited group whose delimiters fit, the middle of its contents is elided instead.
+fn truncate_tts(tokens: TokenStream, max_tokens: usize) -> TokenStream {
+    let trees: Vec<TokenTree> = tokens.into_trees().collect();
+    let counts: Vec<usize> = trees.iter().map(tt_token_count).collect();
+    if counts.iter().sum::<usize>() <= max_tokens {
+        return trees.into_iter().collect();
+    }
+
+    // Reserve one token for the ellipsis and split the rest between the head and the tail.
+    let budget = max_tokens.saturating_sub(1);
+    let mut head_budget = budget - budget / 2;
+    let mut tail_budget = budget / 2;
+
+    let mut head_end = 0;
+    while head_end < trees.len() && counts[head_end] <= head_budget {
+        head_budget -= counts[head_end];
+        head_end += 1;
+    }
+    let mut tail_start = trees.len();
+    while tail_start > head_end && counts[tail_start - 1] <= tail_budget {
+        tail_budget -= counts[tail_start - 1];
+        tail_start -= 1;
+    }
+
+    let mut trees = trees.into_iter();
+    let mut result: Vec<TokenTree> = trees.by_ref().take(head_end).collect();
+    let mut elided = trees.by_ref().take(tail_start - head_end).collect::<Vec<_>>().into_iter();
+    let left = head_budget + tail_budget + max_tokens - budget;
+    match (elided.next(), elided.len()) {
+        (Some(TokenTree::Delimited(dspan, delim, tts)), 0) if left >= 3 => {
+            result.push(TokenTree::Delimited(dspan, delim, truncate_tts(tts, left - 2)));
+        }
+        (first, _) => {
+            let span = first.map_or(syntax_pos::DUMMY_SP, |tt| tt.span());
+            result.push(TokenTree::token(token::DotDotDot, span));
+        }
+    }
+    result.extend(trees);
+    result.into_iter().collect()
+}
+
 pub fn stmt_to_string(stmt: &ast::Stmt) -> String {
     to_string(|s| s.print_stmt(stmt))
 }
@@ -418,10 +575,61 @@ pub fn param_to_string(arg: &ast::Param) -> String {
     to_string(|s| s.print_param(arg, false))
 }
 
+pub fn variant_to_string(variant: &ast::Variant) -> String {
+    to_string(|s| {
+        s.print_outer_attributes(&variant.attrs);
+        s.print_variant(variant)
+    })
+}
+
 fn foreign_item_to_string(arg: &ast::ForeignItem) -> String {
     to_string(|s| s.print_foreign_item(arg))
 }
 
+pub fn ast_fragment_to_string(fragment: &AstFragment) -> String {
+    match *fragment {
+        AstFragment::OptExpr(Some(ref expr)) | AstFragment::Expr(ref expr) => {
+            expr_to_string(expr)
+        }
+        AstFragment::OptExpr(None) => String::new(),
+        AstFragment::Pat(ref pat) => pat_to_string(pat),
+        AstFragment::Ty(ref ty) => ty_to_string(ty),
+        AstFragment::Stmts(ref stmts) => to_string(|s| {
+            for stmt in stmts {
+                s.print_stmt(stmt);
+            }
+        }),
+        AstFragment::Items(ref items) => to_string(|s| {
+            for item in items {
+                s.print_item(item);
+            }
+        }),
+        AstFragment::TraitItems(ref items) => to_string(|s| {
+            for item in items {
+                s.print_trait_item(item);
+            }
+        }),
+        AstFragment::ImplItems(ref items) => to_string(|s| {
+            for item in items {
+                s.print_impl_item(item);
+            }
+        }),
+        AstFragment::ForeignItems(ref items) => to_string(|s| {
+            for item in items {
+                s.print_foreign_item(item);
+            }
+        }),
+        AstFragment::Arms(ref arms) => to_string(|s| {
+            for arm in arms {
+                s.print_arm(arm);
+            }
+        }),
+        AstFragment::Params(ref params) => to_string(|s| {
+            s.commasep(Inconsistent, params, |s, param| s.print_param(param, true));
+        }),
+    }
+}
+
 fn visibility_qualified(vis: &ast::Visibility, s: &str) -> String {
     format!("{}{}", to_string(|s| s.print_visibility(vis)), s)
 }
@@ -541,7 +749,7 @@ pub trait PrintState<'a>: std::ops::Deref<Target=pp::Printer> + std::ops::DerefM
 
     fn print_literal(&mut self, lit: &ast::Lit) {
         self.maybe_print_comment(lit.span.lo());
-        self.word(lit.token.to_string())
+        self.word(lit.token().to_string())
     }
 
     fn print_string(&mut self, st: &str,
@@ -624,18 +832,24 @@ pub trait PrintState<'a>: std::ops::Deref<Target=pp::Printer> + std::ops::DerefM
                 ast::AttrStyle::Outer => self.word("#["),
             }
             self.ibox(0);
-            match attr.tokens.trees().next() {
-                Some(TokenTree::Delimited(_, delim, tts)) => {
+            match &attr.args {
+                ast::AttrArgs::Delimited(_, delim, tts) => {
                     self.print_mac_common(
-                        Some(MacHeader::Path(&attr.path)), false, None, delim, tts, true, attr.span
+                        Some(MacHeader::Path(&attr.path)),
+                        false,
+                        None,
+                        delim.to_token(),
+                        tts.clone(),
+                        true,
+                        attr.span,
                     );
                 }
-                tree => {
+                ast::AttrArgs::Empty => self.print_path(&attr.path, false, 0),
+                ast::AttrArgs::Eq(_, expr) => {
                     self.print_path(&attr.path, false, 0);
-                    if tree.is_some() {
-                        self.space();
-                        self.print_tts(attr.tokens.clone(), true);
-                    }
+                    self.space();
+                    self.word_space("=");
+                    self.print_expr(expr);
                 }
             }
             self.end();
@@ -1055,6 +1269,7 @@ impl<'a> State<'a> {
                               item: &ast::ForeignItem) {
         self.hardbreak_if_not_bol();
         self.maybe_print_comment(item.span.lo());
+        self.print_synthesized_comments(item.id);
         self.print_outer_attributes(&item.attrs);
         match item.node {
             ast::ForeignItemKind::Fn(ref decl, ref generics) => {
@@ -1086,11 +1301,7 @@ impl<'a> State<'a> {
                 self.end(); // end the outer cbox
             }
             ast::ForeignItemKind::Macro(ref m) => {
-                self.print_mac(m);
-                match m.delim {
-                    MacDelimiter::Brace => {},
-                    _ => self.s.word(";")
-                }
+                self.print_item_mac(m);
             }
         }
     }
@@ -1132,10 +1343,19 @@ impl<'a> State<'a> {
         self.s.word(";")
     }
 
+    fn print_synthesized_comments(&mut self, id: ast::NodeId) {
+        for line in self.ann.synthesized_comments(id) {
+            self.hardbreak_if_not_bol();
+            self.word(line);
+            self.hardbreak();
+        }
+    }
+
     /// Pretty-print an item
     crate fn print_item(&mut self, item: &ast::Item) {
         self.hardbreak_if_not_bol();
         self.maybe_print_comment(item.span.lo());
+        self.print_synthesized_comments(item.id);
         self.print_outer_attributes(&item.attrs);
         self.ann.pre(self, AnnNode::Item(item));
         match item.node {
@@ -1360,11 +1580,7 @@ impl<'a> State<'a> {
                 self.s.word(";");
             }
             ast::ItemKind::Mac(ref mac) => {
-                self.print_mac(mac);
-                match mac.delim {
-                    MacDelimiter::Brace => {}
-                    _ => self.s.word(";"),
-                }
+                self.print_item_mac(mac);
             }
             ast::ItemKind::MacroDef(ref macro_def) => {
                 let (kw, has_bang) =
@@ -1473,9 +1689,7 @@ impl<'a> State<'a> {
                         Inconsistent, struct_def.fields(),
                         |s, field| {
                             s.maybe_print_comment(field.span.lo());
-                            s.print_outer_attributes(&field.attrs);
-                            s.print_visibility(&field.vis);
-                            s.print_type(&field.ty)
+                            s.print_struct_field(field)
                         }
                     );
                     self.pclose();
@@ -1496,11 +1710,7 @@ impl<'a> State<'a> {
                 for field in struct_def.fields() {
                     self.hardbreak_if_not_bol();
                     self.maybe_print_comment(field.span.lo());
-                    self.print_outer_attributes(&field.attrs);
-                    self.print_visibility(&field.vis);
-                    self.print_ident(field.ident.unwrap());
-                    self.word_nbsp(":");
-                    self.print_type(&field.ty);
+                    self.print_struct_field(field);
                     self.s.word(",");
                 }
 
@@ -1509,6 +1719,17 @@ impl<'a> State<'a> {
         }
     }
 
+    /// Prints a field of a struct, a union or an enum variant, named or positional.
+    crate fn print_struct_field(&mut self, field: &ast::StructField) {
+        self.print_outer_attributes(&field.attrs);
+        self.print_visibility(&field.vis);
+        if let Some(ident) = field.ident {
+            self.print_ident(ident);
+            self.word_nbsp(":");
+        }
+        self.print_type(&field.ty);
+    }
+
     crate fn print_variant(&mut self, v: &ast::Variant) {
         self.head("");
         let generics = ast::Generics::default();
@@ -1541,6 +1762,7 @@ impl<'a> State<'a> {
         self.ann.pre(self, AnnNode::SubItem(ti.id));
         self.hardbreak_if_not_bol();
         self.maybe_print_comment(ti.span.lo());
+        self.print_synthesized_comments(ti.id);
         self.print_outer_attributes(&ti.attrs);
         match ti.node {
             ast::TraitItemKind::Const(ref ty, ref default) => {
@@ -1573,11 +1795,7 @@ impl<'a> State<'a> {
                                            default.as_ref().map(|ty| &**ty));
             }
             ast::TraitItemKind::Macro(ref mac) => {
-                self.print_mac(mac);
-                match mac.delim {
-                    MacDelimiter::Brace => {}
-                    _ => self.s.word(";"),
-                }
+                self.print_item_mac(mac);
             }
         }
         self.ann.post(self, AnnNode::SubItem(ti.id))
@@ -1587,6 +1805,7 @@ impl<'a> State<'a> {
         self.ann.pre(self, AnnNode::SubItem(ii.id));
         self.hardbreak_if_not_bol();
         self.maybe_print_comment(ii.span.lo());
+        self.print_synthesized_comments(ii.id);
         self.print_outer_attributes(&ii.attrs);
         self.print_defaultness(ii.defaultness);
         match ii.node {
@@ -1610,11 +1829,7 @@ impl<'a> State<'a> {
                 self.s.word(";");
             }
             ast::ImplItemKind::Macro(ref mac) => {
-                self.print_mac(mac);
-                match mac.delim {
-                    MacDelimiter::Brace => {}
-                    _ => self.s.word(";"),
-                }
+                self.print_item_mac(mac);
             }
         }
         self.ann.post(self, AnnNode::SubItem(ii.id))
@@ -1767,6 +1982,15 @@ impl<'a> State<'a> {
         self.print_else(elseopt)
     }
 
+    /// Prints a macro invocation in item position, followed by a semicolon if its delimiters
+    /// require it.
+    fn print_item_mac(&mut self, m: &ast::Mac) {
+        self.print_mac(m);
+        if MacPosition::Item.semicolon(m.delim) == MacSemicolon::Required {
+            self.s.word(";");
+        }
+    }
+
     crate fn print_mac(&mut self, m: &ast::Mac) {
         self.print_mac_common(
             Some(MacHeader::Path(&m.path)),
@@ -2463,8 +2687,15 @@ impl<'a> State<'a> {
         }
         self.cbox(INDENT_UNIT);
         self.ibox(0);
-        self.maybe_print_comment(arm.pats[0].span.lo());
+        self.maybe_print_comment(arm.pats.first().map_or(arm.span, |pat| pat.span).lo());
         self.print_outer_attributes(&arm.attrs);
+        if let Some(mac) = arm.mac() {
+            self.print_mac(mac);
+            self.s.word(",");
+            self.end(); // close the ibox
+            self.end(); // close enclosing cbox
+            return;
+        }
         self.print_pats(&arm.pats);
         self.s.space();
         if let Some(ref e) = arm.guard {
@@ -2761,7 +2992,9 @@ impl<'a> State<'a> {
         self.print_outer_attributes_inline(&input.attrs);
 
         match input.ty.node {
-            ast::TyKind::Infer if is_closure => self.print_pat(&input.pat),
+            ast::TyKind::Infer if is_closure || input.mac().is_some() => {
+                self.print_pat(&input.pat)
+            }
             _ => {
                 if let Some(eself) = input.to_self() {
                     self.print_explicit_self(&eself);
@@ -68,3 +68,48 @@ fn test_variant_to_string() {
         assert_eq!(varstr, "principal_skinner");
     })
 }
+
+#[test]
+fn test_tts_to_string_truncated() {
+    with_default_globals(|| {
+        let tts = crate::tests::string_to_stream("a b c d e f g h".to_string());
+        assert_eq!(tts_to_string_truncated(tts.clone(), None), "a b c d e f g h");
+        assert_eq!(tts_to_string_truncated(tts.clone(), Some(8)), "a b c d e f g h");
+        assert_eq!(tts_to_string_truncated(tts, Some(5)), "a b ... g h");
+
+        let tts = crate::tests::string_to_stream("[a, b, c, d, e] x".to_string());
+        assert_eq!(tts_to_string_truncated(tts.clone(), Some(6)), "[a ... e] x");
+        assert_eq!(tts_to_string_truncated(tts, Some(3)), "... x");
+    })
+}
+
+#[test]
+fn test_synthesized_comments() {
+    struct Banner;
+
+    impl PpAnn for Banner {
+        fn synthesized_comments(&self, id: ast::NodeId) -> Vec<String> {
+            if id == ast::NodeId::from_u32(1) {
+                vec!["// generated by `m!`".to_string()]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    with_default_globals(|| {
+        let sess = ParseSess::new(source_map::FilePathMapping::empty());
+        let source = "fn f() {}\nfn g() {}\n".to_string();
+        let name = FileName::Custom("test".to_string());
+        let mut krate =
+            crate::parse::parse_crate_from_source_str(name.clone(), source.clone(), &sess)
+                .unwrap();
+        krate.module.items[1].id = ast::NodeId::from_u32(1);
+
+        let out = print_crate(sess.source_map(), &sess, &krate, name, source, &Banner, false);
+        let lines: Vec<_> = out.lines().collect();
+        let banner = lines.iter().position(|line| *line == "// generated by `m!`").unwrap();
+        assert!(lines[banner - 1].starts_with("fn f()"), "{}", out);
+        assert!(lines[banner + 1].starts_with("fn g()"), "{}", out);
+    })
+}
@@ -0,0 +1,205 @@
+//! Checking the pretty-printer against a corpus of Rust sources.
+//!
+//! Every source is parsed, printed with `pprust::print_crate`, and parsed again; the source
+//! round-trips if both crates are equal according to `SpanlessEq`. Running a directory of
+//! sources through `round_trip_dir` covers far more syntax than the `pp-exact` tests, which
+//! compare the printed text of a few files.
+//!
+//! The functions here use the globals of the caller, see `with_default_globals`.
+
+use crate::ast;
+use crate::parse::{self, ParseSess};
+use crate::print::pprust;
+use crate::source_map::{FilePathMapping, SourceMap};
+use crate::util::spanless_eq::SpanlessEq;
+
+use errors::{DiagnosticBuilder, Handler};
+use errors::emitter::Emitter;
+use rustc_data_structures::sync::Lrc;
+use syntax_pos::{FileName, Span};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+mod tests;
+
+/// Why a source doesn't round-trip.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoundTripError {
+    /// The source itself has syntax errors, with their messages.
+    Parse(Vec<String>),
+    /// The printed source has syntax errors.
+    Reparse { printed: String, errors: Vec<String> },
+    /// The printed source parses to a different crate. `line` is the first line at which the
+    /// dumps of the crates differ, see `SpanlessEq::spanless_dump`.
+    Divergence { printed: String, line: usize, expected: String, found: String },
+}
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundTripError::Parse(errors) => {
+                write!(f, "the source doesn't parse: {}", errors.join("; "))
+            }
+            RoundTripError::Reparse { errors, .. } => {
+                write!(f, "the printed source doesn't parse: {}", errors.join("; "))
+            }
+            RoundTripError::Divergence { line, expected, found, .. } => write!(
+                f,
+                "the printed source parses differently, at line {} of the AST dump: \
+                 expected `{}`, found `{}`",
+                line,
+                expected.trim(),
+                found.trim(),
+            ),
+        }
+    }
+}
+
+/// The outcome of `round_trip_dir`.
+#[derive(Debug, Default)]
+pub struct RoundTripReport {
+    /// The number of sources that round-trip.
+    pub passed: usize,
+    /// The sources marked with `// ignore-pretty`, which are left out.
+    pub skipped: Vec<PathBuf>,
+    /// The sources that don't round-trip.
+    pub failures: Vec<(PathBuf, RoundTripError)>,
+}
+
+impl RoundTripReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for RoundTripReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.passed,
+            self.failures.len(),
+            self.skipped.len(),
+        )?;
+        for (path, error) in &self.failures {
+            writeln!(f, "{}: {}", path.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `source` parses to the same crate after being pretty-printed.
+pub fn round_trip_source(name: FileName, source: String) -> Result<(), RoundTripError> {
+    let (sess, krate) =
+        parse_crate(name.clone(), source.clone()).map_err(RoundTripError::Parse)?;
+    let printed = pprust::print_crate(
+        sess.source_map(),
+        &sess,
+        &krate,
+        name.clone(),
+        source,
+        &pprust::NoAnn,
+        false,
+    );
+
+    let printed_name = FileName::Custom(format!("{} (pretty-printed)", name));
+    let reprinted = match parse_crate(printed_name, printed.clone()) {
+        Ok((_, reprinted)) => reprinted,
+        Err(errors) => return Err(RoundTripError::Reparse { printed, errors }),
+    };
+    if krate.spanless_eq(&reprinted) {
+        return Ok(());
+    }
+
+    let (expected, found) = (krate.spanless_dump(), reprinted.spanless_dump());
+    let mut lines = expected.lines().map(Some).chain(Some(None))
+        .zip(found.lines().map(Some).chain(Some(None)))
+        .enumerate();
+    let (line, (expected, found)) = lines.find(|(_, (expected, found))| expected != found)
+        .expect("the dumps of unequal crates are equal");
+    Err(RoundTripError::Divergence {
+        printed,
+        line: line + 1,
+        expected: expected.unwrap_or("<end of crate>").to_string(),
+        found: found.unwrap_or("<end of crate>").to_string(),
+    })
+}
+
+/// Round-trips every `.rs` file under `dir`, in the order of their paths.
+pub fn round_trip_dir(dir: &Path) -> io::Result<RoundTripReport> {
+    let mut paths = Vec::new();
+    collect_sources(dir, &mut paths)?;
+    paths.sort();
+
+    let mut report = RoundTripReport::default();
+    for path in paths {
+        let source = fs::read_to_string(&path)?;
+        if source.lines().any(|line| line.trim() == "// ignore-pretty") {
+            report.skipped.push(path);
+            continue;
+        }
+        match round_trip_source(FileName::Real(path.clone()), source) {
+            Ok(()) => report.passed += 1,
+            Err(error) => report.failures.push((path, error)),
+        }
+    }
+    Ok(report)
+}
+
+fn collect_sources(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, paths)?;
+        } else if path.extension().map_or(false, |extension| extension == "rs") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a crate in a session of its own, returning the messages of the errors if there are
+/// any, including the ones the parser recovered from.
+fn parse_crate(name: FileName, source: String) -> Result<(ParseSess, ast::Crate), Vec<String>> {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let emitter = CollectingEmitter { errors: errors.clone() };
+    let source_map = Lrc::new(SourceMap::new(FilePathMapping::empty()));
+    let handler = Handler::with_emitter(false, None, Box::new(emitter));
+    let sess = ParseSess::with_span_handler(handler, source_map);
+
+    let krate = match parse::parse_crate_from_source_str(name, source, &sess) {
+        Ok(krate) => Some(krate),
+        Err(mut err) => {
+            err.emit();
+            None
+        }
+    };
+    let errors: Vec<_> = errors.lock().unwrap().drain(..).map(|(span, message)| {
+        match span {
+            Some(span) => format!("{}: {}", sess.source_map().span_to_string(span), message),
+            None => message,
+        }
+    }).collect();
+    match krate {
+        Some(krate) if errors.is_empty() => Ok((sess, krate)),
+        _ => Err(errors),
+    }
+}
+
+/// Records the errors of a session, with their primary spans.
+struct CollectingEmitter {
+    errors: Arc<Mutex<Vec<(Option<Span>, String)>>>,
+}
+
+impl Emitter for CollectingEmitter {
+    fn emit_diagnostic(&mut self, db: &DiagnosticBuilder<'_>) {
+        if db.is_error() {
+            self.errors.lock().unwrap().push((db.span.primary_span(), db.message()));
+        }
+    }
+}
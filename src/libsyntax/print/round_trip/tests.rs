@@ -0,0 +1,49 @@
+use super::*;
+
+use crate::with_default_globals;
+
+use std::env;
+use std::process;
+
+fn crate_dump(source: &str) -> String {
+    let name = FileName::Custom("source".to_string());
+    parse_crate(name, source.to_string()).unwrap().1.spanless_dump()
+}
+
+#[test]
+fn spanless_eq_ignores_layout() {
+    with_default_globals(|| {
+        let dump = crate_dump("fn f(x: u8) -> u8 { m!(x+1) }");
+        assert_eq!(crate_dump("\n// A comment.\nfn f( x : u8 )->u8{\n    m!( x + 1 )\n}"), dump);
+        assert_ne!(crate_dump("fn f(x: u8) -> u8 { m!(x + 2) }"), dump);
+    })
+}
+
+#[test]
+fn round_trip_reports_divergences() {
+    with_default_globals(|| {
+        let dir = env::temp_dir().join(format!("round-trip-{}", process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let sources = [
+            ("items.rs", "/// Docs.\n#[derive(Debug)]\nstruct S<'a, T: ?Sized> { x: &'a T }\n"),
+            ("nested/exprs.rs", "fn f() { let v = vec![1, 2]; for x in &v { g(x as u8 + 1); } }"),
+            ("broken.rs", "fn f( {}"),
+            ("ignored.rs", "// ignore-pretty\nfn f() {}"),
+            ("README.md", "fn f( {}"),
+        ];
+        for (path, source) in &sources {
+            fs::write(dir.join(path), source).unwrap();
+        }
+
+        let report = round_trip_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(report.passed, 2);
+        assert_eq!(report.skipped, [dir.join("ignored.rs")]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, dir.join("broken.rs"));
+        match report.failures[0].1 {
+            RoundTripError::Parse(ref errors) => assert!(!errors.is_empty()),
+            ref error => panic!("unexpected error: {}", error),
+        }
+    })
+}
@@ -35,6 +35,9 @@ use rustc_serialize::{Encodable, Decodable, Encoder, Decoder};
 
 use rustc_data_structures::stable_hasher::{StableHasher, StableHasherResult,
                                            HashStable};
+
+#[cfg(test)]
+mod tests;
 /// An owned smart pointer.
 #[derive(Hash, PartialEq, Eq)]
 pub struct P<T: ?Sized> {
@@ -80,6 +83,23 @@ impl<T: 'static> P<T> {
         *self.ptr = f(*self.ptr)?;
         Some(self)
     }
+
+    /// Mutate the pointee in place. Unlike `map`, this doesn't move the pointee out and
+    /// back, which is a large copy for nodes like `Item` and `Expr`.
+    pub fn map_in_place<F>(mut self, f: F) -> P<T> where
+        F: FnOnce(&mut T),
+    {
+        f(&mut self.ptr);
+        self
+    }
+
+    /// Mutate the pointee in place, keeping it only if `f` returns `true`. Like
+    /// `map_in_place`, the pointee is never moved.
+    pub fn filter_map_preserving<F>(mut self, f: F) -> Option<P<T>> where
+        F: FnOnce(&mut T) -> bool,
+    {
+        if f(&mut self.ptr) { Some(self) } else { None }
+    }
 }
 
 impl<T: ?Sized> Deref for P<T> {
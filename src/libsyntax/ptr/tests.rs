@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn map_in_place_keeps_allocation() {
+    let p = P(vec![1, 2]);
+    let addr: *const Vec<i32> = &*p;
+    let p = p.map_in_place(|v| v.push(3));
+    assert_eq!(*p, vec![1, 2, 3]);
+    assert_eq!(&*p as *const Vec<i32>, addr);
+}
+
+#[test]
+fn filter_map_preserving() {
+    let p = P(String::from("kept"));
+    let addr: *const String = &*p;
+    let p = p.filter_map_preserving(|s| { s.push('!'); true }).unwrap();
+    assert_eq!(*p, "kept!");
+    assert_eq!(&*p as *const String, addr);
+
+    let mut visited = false;
+    let p = p.filter_map_preserving(|_| { visited = true; false });
+    assert!(visited);
+    assert!(p.is_none());
+}
@@ -0,0 +1,249 @@
+//! Turning replacements of AST nodes into edits of the source text.
+//!
+//! Tools that produce fixes work on the AST, but their output has to be applied to the source
+//! the AST was parsed from. A `Rewrite` collects replacements of nodes by `NodeId` and computes
+//! the smallest text edits that put the pretty-printed replacements in place of the original
+//! nodes, leaving the rest of the source, including its formatting and comments, untouched.
+
+use crate::ast::{self, Attribute, NodeId};
+use crate::attr::HasAttrs;
+use crate::ext::expand::AstFragment;
+use crate::print::pprust;
+use crate::source_map::SourceMap;
+use crate::visit::{self, Visitor};
+
+use rustc_data_structures::fx::FxHashMap;
+use syntax_pos::{BytePos, Span};
+
+#[cfg(test)]
+mod tests;
+
+/// A replacement of the source text in `span` by `text`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RewriteError {
+    /// There is no node with this id in the AST.
+    NodeNotFound(NodeId),
+    /// The node was produced by a macro expansion, so it has no source text of its own.
+    FromExpansion(NodeId, Span),
+    /// The source text of the node isn't available from the source map.
+    SourceUnavailable(NodeId, Span),
+    /// The source text of the two nodes overlaps without one containing the other.
+    Overlap(NodeId, NodeId),
+}
+
+/// A set of replacements of AST nodes.
+#[derive(Default)]
+pub struct Rewrite {
+    replacements: FxHashMap<NodeId, AstFragment>,
+}
+
+/// A node of the original AST to be replaced.
+struct Target {
+    id: NodeId,
+    /// The span of the node, including its outer attributes.
+    span: Span,
+    /// The precedence of the original expression, if the node is one.
+    precedence: Option<i8>,
+}
+
+impl Rewrite {
+    pub fn new() -> Rewrite {
+        Rewrite::default()
+    }
+
+    /// Replaces the node `id` with `replacement`, which should be a fragment of the kind the
+    /// node belongs to. A later replacement of the same node overrides an earlier one.
+    pub fn replace(&mut self, id: NodeId, replacement: AstFragment) {
+        self.replacements.insert(id, replacement);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty()
+    }
+
+    /// Computes the edits of the source text of `krate` that perform the replacements,
+    /// ordered by position. The nodes of `krate` must have been assigned their `NodeId`s, as
+    /// is done during expansion.
+    ///
+    /// Replacements of nodes inside other replaced nodes are subsumed by the outer replacement.
+    /// Replaced expressions are parenthesized where their precedence requires it, and the lines
+    /// of multi-line replacements are indented like the line the original node starts on.
+    pub fn edits(&self, krate: &ast::Crate, source_map: &SourceMap)
+                 -> Result<Vec<TextEdit>, RewriteError> {
+        let mut collector = TargetCollector {
+            rewrite: self,
+            targets: Vec::new(),
+            subsumed: Vec::new(),
+            in_target: false,
+        };
+        visit::walk_crate(&mut collector, krate);
+        let TargetCollector { mut targets, subsumed, .. } = collector;
+
+        if targets.len() + subsumed.len() < self.replacements.len() {
+            let mut missing: Vec<_> = self.replacements.keys()
+                .filter(|id| !targets.iter().any(|target| target.id == **id))
+                .filter(|id| !subsumed.contains(id))
+                .collect();
+            missing.sort();
+            return Err(RewriteError::NodeNotFound(*missing[0]));
+        }
+
+        if let Some(target) = targets.iter().find(|target| target.span.from_expansion()) {
+            return Err(RewriteError::FromExpansion(target.id, target.span));
+        }
+        targets.sort_by_key(|target| (target.span.lo(), target.span.hi()));
+        for pair in targets.windows(2) {
+            if pair[0].span.hi() > pair[1].span.lo() {
+                return Err(RewriteError::Overlap(pair[0].id, pair[1].id));
+            }
+        }
+
+        let mut edits = Vec::new();
+        for target in &targets {
+            let original = source_map.span_to_snippet(target.span)
+                .map_err(|_| RewriteError::SourceUnavailable(target.id, target.span))?;
+            let text = self.replacement_text(target, source_map);
+            edits.extend(minimal_edit(target.span, &original, &text));
+        }
+        Ok(edits)
+    }
+
+    fn replacement_text(&self, target: &Target, source_map: &SourceMap) -> String {
+        let replacement = &self.replacements[&target.id];
+        let mut text = pprust::ast_fragment_to_string(replacement);
+        if let (AstFragment::Expr(expr), Some(precedence)) = (replacement, target.precedence) {
+            if expr.precedence().order() < precedence {
+                text = format!("({})", text);
+            }
+        }
+        if !text.contains('\n') {
+            return text;
+        }
+
+        let loc = source_map.lookup_char_pos(target.span.lo());
+        let line = loc.file.get_line(loc.line - 1).unwrap_or_default();
+        let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+        let mut lines = text.lines();
+        let mut reindented = lines.next().unwrap_or_default().to_string();
+        for line in lines {
+            reindented.push('\n');
+            if !line.is_empty() {
+                reindented.push_str(&indent);
+                reindented.push_str(line);
+            }
+        }
+        reindented
+    }
+}
+
+/// The edit replacing `original`, the text in `span`, with `text`, reduced to the part of the
+/// text that actually changes. `None` if the text doesn't change.
+fn minimal_edit(span: Span, original: &str, text: &str) -> Option<TextEdit> {
+    if original == text {
+        return None;
+    }
+    let prefix = original.char_indices()
+        .zip(text.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or_else(|| original.len().min(text.len()), |((i, _), _)| i);
+    let suffix = original[prefix..].chars().rev()
+        .zip(text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    let lo = span.lo() + BytePos(prefix as u32);
+    let hi = span.hi() - BytePos(suffix as u32);
+    Some(TextEdit {
+        span: span.with_lo(lo).with_hi(hi),
+        text: text[prefix..text.len() - suffix].to_string(),
+    })
+}
+
+/// Extends `span` to the start of the outer attributes of its node.
+fn span_with_attrs(span: Span, attrs: &[Attribute]) -> Span {
+    attrs.iter()
+        .filter(|attr| attr.style == ast::AttrStyle::Outer && !attr.span.from_expansion())
+        .fold(span, |span, attr| {
+            if attr.span.lo() < span.lo() { attr.span.to(span) } else { span }
+        })
+}
+
+/// Finds the outermost nodes of the AST that have a replacement, and the replaced nodes
+/// inside them.
+struct TargetCollector<'r> {
+    rewrite: &'r Rewrite,
+    targets: Vec<Target>,
+    /// The replaced nodes inside other replaced nodes, which are subsumed by them.
+    subsumed: Vec<NodeId>,
+    /// Whether the walk is inside a replaced node.
+    in_target: bool,
+}
+
+impl TargetCollector<'_> {
+    /// Records the node `id` if it's replaced, and walks it with `walk`.
+    fn visit_node(&mut self, id: NodeId, span: Span, precedence: Option<i8>,
+                  walk: impl FnOnce(&mut Self)) {
+        if !self.rewrite.replacements.contains_key(&id) {
+            walk(self);
+        } else if self.in_target {
+            self.subsumed.push(id);
+            walk(self);
+        } else {
+            self.targets.push(Target { id, span, precedence });
+            self.in_target = true;
+            walk(self);
+            self.in_target = false;
+        }
+    }
+}
+
+impl<'ast> Visitor<'ast> for TargetCollector<'_> {
+    fn visit_item(&mut self, item: &'ast ast::Item) {
+        let span = span_with_attrs(item.span, &item.attrs);
+        self.visit_node(item.id, span, None, |this| visit::walk_item(this, item));
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast ast::TraitItem) {
+        let span = span_with_attrs(item.span, &item.attrs);
+        self.visit_node(item.id, span, None, |this| visit::walk_trait_item(this, item));
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast ast::ImplItem) {
+        let span = span_with_attrs(item.span, &item.attrs);
+        self.visit_node(item.id, span, None, |this| visit::walk_impl_item(this, item));
+    }
+
+    fn visit_foreign_item(&mut self, item: &'ast ast::ForeignItem) {
+        let span = span_with_attrs(item.span, &item.attrs);
+        self.visit_node(item.id, span, None, |this| visit::walk_foreign_item(this, item));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast ast::Stmt) {
+        let span = span_with_attrs(stmt.span, stmt.attrs());
+        self.visit_node(stmt.id, span, None, |this| visit::walk_stmt(this, stmt));
+    }
+
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        let span = span_with_attrs(expr.span, &expr.attrs);
+        let precedence = Some(expr.precedence().order());
+        self.visit_node(expr.id, span, precedence, |this| visit::walk_expr(this, expr));
+    }
+
+    fn visit_pat(&mut self, pat: &'ast ast::Pat) {
+        self.visit_node(pat.id, pat.span, None, |this| visit::walk_pat(this, pat));
+    }
+
+    fn visit_ty(&mut self, ty: &'ast ast::Ty) {
+        self.visit_node(ty.id, ty.span, None, |this| visit::walk_ty(this, ty));
+    }
+
+    fn visit_mac(&mut self, mac: &'ast ast::Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
@@ -0,0 +1,95 @@
+use super::*;
+
+use crate::mut_visit::{self, MutVisitor};
+use crate::parse::ParseSess;
+use crate::source_map::FilePathMapping;
+use crate::tests::with_error_checking_parse;
+use crate::with_default_globals;
+
+struct IdAssigner(u32);
+
+impl MutVisitor for IdAssigner {
+    fn visit_id(&mut self, id: &mut NodeId) {
+        self.0 += 1;
+        *id = NodeId::from_u32(self.0);
+    }
+
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        mut_visit::noop_visit_mac(mac, self);
+    }
+}
+
+struct ExprFinder<'a>(&'a str, Option<NodeId>);
+
+impl<'ast> Visitor<'ast> for ExprFinder<'_> {
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        if pprust::expr_to_string(expr) == self.0 {
+            self.1 = Some(expr.id);
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast ast::Mac) {
+        visit::walk_mac(self, mac);
+    }
+}
+
+fn edited_source(source: &str, replacements: &[(&str, &str)]) -> Result<String, RewriteError> {
+    let ps = ParseSess::new(FilePathMapping::empty());
+    let mut krate = with_error_checking_parse(source.to_string(), &ps, |p| p.parse_crate_mod());
+    IdAssigner(0).visit_crate(&mut krate);
+
+    let mut rewrite = Rewrite::new();
+    for &(original, replacement) in replacements {
+        let mut finder = ExprFinder(original, None);
+        visit::walk_crate(&mut finder, &krate);
+        let expr = with_error_checking_parse(replacement.to_string(), &ps, |p| p.parse_expr());
+        rewrite.replace(finder.1.unwrap(), AstFragment::Expr(expr));
+    }
+
+    let mut result = source.to_string();
+    for edit in rewrite.edits(&krate, ps.source_map())?.iter().rev() {
+        let lo = ps.source_map().lookup_byte_offset(edit.span.lo()).pos.0 as usize;
+        let hi = ps.source_map().lookup_byte_offset(edit.span.hi()).pos.0 as usize;
+        result.replace_range(lo..hi, &edit.text);
+    }
+    Ok(result)
+}
+
+#[test]
+fn rewrite_keeps_surrounding_text() {
+    with_default_globals(|| {
+        let source = "fn main() {\n    // keep me\n    f(a,   b);\n    let x = 1 + 2 * 3;\n}\n";
+        let edited = edited_source(source, &[("f(a, b)", "f(a, c)"), ("2 * 3", "4 - 5")]);
+        assert_eq!(
+            edited.unwrap(),
+            "fn main() {\n    // keep me\n    f(a, c);\n    let x = 1 + (4 - 5);\n}\n",
+        );
+    })
+}
+
+#[test]
+fn rewrite_subsumes_nested_replacements() {
+    with_default_globals(|| {
+        let source = "fn main() { let x = (1 + 2) * 3; }";
+        let edited = edited_source(source, &[("(1 + 2) * 3", "0"), ("1 + 2", "3")]);
+        assert_eq!(edited.unwrap(), "fn main() { let x = 0; }");
+    })
+}
+
+#[test]
+fn rewrite_reports_missing_nodes() {
+    with_default_globals(|| {
+        let ps = ParseSess::new(FilePathMapping::empty());
+        let source = "fn main() { 1 + 2; }".to_string();
+        let mut krate = with_error_checking_parse(source, &ps, |p| p.parse_crate_mod());
+        IdAssigner(0).visit_crate(&mut krate);
+
+        let mut rewrite = Rewrite::new();
+        let expr = with_error_checking_parse("3".to_string(), &ps, |p| p.parse_expr());
+        let missing = NodeId::from_u32(1000);
+        rewrite.replace(missing, AstFragment::Expr(expr));
+        let edits = rewrite.edits(&krate, ps.source_map());
+        assert_eq!(edits, Err(RewriteError::NodeNotFound(missing)));
+    })
+}
@@ -12,9 +12,11 @@ pub use syntax_pos::hygiene::{ExpnKind, ExpnData};
 
 use rustc_data_structures::fx::FxHashMap;
 use rustc_data_structures::stable_hasher::StableHasher;
-use rustc_data_structures::sync::{Lrc, Lock, LockGuard, MappedLockGuard};
+use rustc_data_structures::sync::{Lrc, Lock, LockGuard, MappedLockGuard, par_for_each_in};
 use std::cmp;
+use std::fmt;
 use std::hash::Hash;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
 use std::env;
@@ -27,6 +29,23 @@ use errors::SourceMapper;
 #[cfg(test)]
 mod tests;
 
+/// A snippet of source code along with the lines surrounding it, as returned by
+/// `SourceMap::extract_snippet_with_context`. Concatenating `before`, `snippet` and `after`
+/// gives back the complete lines of source starting at `first_line`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnippetWithContext {
+    /// Identifies the file and lines the snippet was taken from, e.g., `src/lib.rs:4-8`.
+    pub header: String,
+    /// The source before the span, starting at the beginning of `first_line`.
+    pub before: String,
+    /// The source covered by the span.
+    pub snippet: String,
+    /// The source after the span, up to the end of the last line of context.
+    pub after: String,
+    /// The 1-based line number of the first line of context.
+    pub first_line: usize,
+}
+
 /// Returns the span itself if it doesn't come from a macro expansion,
 /// otherwise return the call site span up to the `enclosing_sp` by
 /// following the `expn_data` chain.
@@ -69,6 +88,11 @@ pub trait FileLoader {
 
     /// Read the contents of an UTF-8 file into memory.
     fn read_file(&self, path: &Path) -> io::Result<String>;
+
+    /// Read the contents of a file into memory as is.
+    fn read_binary_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
 }
 
 /// A FileLoader that uses std::fs to load real files.
@@ -120,6 +144,27 @@ impl StableSourceFileId {
     }
 }
 
+/// The text of a span, as returned by `SourceMap::token_text`.
+pub struct TokenText {
+    source_file: Lrc<SourceFile>,
+    start: usize,
+    end: usize,
+}
+
+impl Deref for TokenText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.source_file.src.as_ref().unwrap()[self.start..self.end]
+    }
+}
+
+impl fmt::Display for TokenText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
 // _____________________________________________________________________________
 // SourceMap
 //
@@ -130,8 +175,22 @@ pub(super) struct SourceMapFiles {
     stable_id_to_source_file: FxHashMap<StableSourceFileId, Lrc<SourceFile>>
 }
 
+/// The contents of the files loaded with `SourceMap::load_byte_blob`, shared by all loads of
+/// the same path and by files with identical contents.
+#[derive(Default)]
+struct ByteBlobs {
+    by_path: FxHashMap<PathBuf, Lrc<Vec<u8>>>,
+    by_hash: FxHashMap<u128, Lrc<Vec<u8>>>,
+}
+
 pub struct SourceMap {
     files: Lock<SourceMapFiles>,
+    byte_blobs: Lock<ByteBlobs>,
+    /// The files loaded as bytes rather than as source files, see `binary_files`.
+    binary_files: Lock<Vec<PathBuf>>,
+    /// The spans diagnostics are displayed at instead of the spans of the code produced by the
+    /// given expansions, see `redirect_expansion`.
+    span_redirects: Lock<FxHashMap<ExpnId, Span>>,
     file_loader: Box<dyn FileLoader + Sync + Send>,
     // This is used to apply the file path remapping as specified via
     // --remap-path-prefix to all SourceFiles allocated within this SourceMap.
@@ -142,6 +201,9 @@ impl SourceMap {
     pub fn new(path_mapping: FilePathMapping) -> SourceMap {
         SourceMap {
             files: Default::default(),
+            byte_blobs: Default::default(),
+            binary_files: Default::default(),
+            span_redirects: Default::default(),
             file_loader: Box::new(RealFileLoader),
             path_mapping,
         }
@@ -152,6 +214,9 @@ impl SourceMap {
                             -> SourceMap {
         SourceMap {
             files: Default::default(),
+            byte_blobs: Default::default(),
+            binary_files: Default::default(),
+            span_redirects: Default::default(),
             file_loader,
             path_mapping,
         }
@@ -176,21 +241,54 @@ impl SourceMap {
     /// Unlike `load_file`, guarantees that no normalization like BOM-removal
     /// takes place.
     pub fn load_binary_file(&self, path: &Path) -> io::Result<Vec<u8>> {
-        // Ideally, this should use `self.file_loader`, but it can't
-        // deal with binary files yet.
-        let bytes = fs::read(path)?;
-
-        // We need to add file to the `SourceMap`, so that it is present
-        // in dep-info. There's also an edge case that file might be both
-        // loaded as a binary via `include_bytes!` and as proper `SourceFile`
-        // via `mod`, so we try to use real file contents and not just an
-        // empty string.
-        let text = std::str::from_utf8(&bytes).unwrap_or("")
-            .to_string();
-        self.new_source_file(path.to_owned().into(), text);
+        let bytes = self.file_loader.read_binary_file(path)?;
+        self.register_binary_file(path);
         Ok(bytes)
     }
 
+    /// Like `load_binary_file`, but the file is only read the first time its contents are
+    /// requested, and the contents are shared with all other requests for the same path and
+    /// for files with identical contents, so that large files included more than once are
+    /// kept in memory once.
+    pub fn load_byte_blob(&self, path: &Path) -> io::Result<Lrc<Vec<u8>>> {
+        if let Some(bytes) = self.byte_blobs.borrow().by_path.get(path) {
+            return Ok(bytes.clone());
+        }
+        let bytes = self.file_loader.read_binary_file(path)?;
+        self.register_binary_file(path);
+
+        let mut hasher = StableHasher::new();
+        bytes.hash(&mut hasher);
+        let hash: u128 = hasher.finish();
+
+        let mut blobs = self.byte_blobs.borrow_mut();
+        let blob = match blobs.by_hash.get(&hash) {
+            Some(blob) if **blob == bytes => blob.clone(),
+            _ => {
+                let blob = Lrc::new(bytes);
+                blobs.by_hash.insert(hash, blob.clone());
+                blob
+            }
+        };
+        blobs.by_path.insert(path.to_owned(), blob.clone());
+        Ok(blob)
+    }
+
+    fn register_binary_file(&self, path: &Path) {
+        let mut binary_files = self.binary_files.borrow_mut();
+        if !binary_files.iter().any(|file| file == path) {
+            binary_files.push(path.to_owned());
+        }
+    }
+
+    /// The files loaded with `load_binary_file` and `load_byte_blob`, in the order they were
+    /// first loaded. The crate depends on them like on its source files, e.g. in dep-info, but
+    /// unlike them, they are not in `files`, so their contents are neither copied nor split into
+    /// lines. A file loaded both as bytes and as a source file, e.g. via `mod`, is in both.
+    pub fn binary_files(&self) -> Vec<PathBuf> {
+        self.binary_files.borrow().clone()
+    }
+
     pub fn files(&self) -> MappedLockGuard<'_, Vec<Lrc<SourceFile>>> {
         LockGuard::map(self.files.borrow(), |files| &mut files.source_files)
     }
@@ -311,9 +409,11 @@ impl SourceMap {
             external_src: Lock::new(ExternalSource::AbsentOk),
             start_pos,
             end_pos,
-            lines: file_local_lines,
-            multibyte_chars: file_local_multibyte_chars,
-            non_narrow_chars: file_local_non_narrow_chars,
+            line_index: LineIndex {
+                lines: file_local_lines,
+                multibyte_chars: file_local_multibyte_chars,
+                non_narrow_chars: file_local_non_narrow_chars,
+            }.ready(),
             name_hash,
         });
 
@@ -354,22 +454,22 @@ impl SourceMap {
         match self.lookup_line(pos) {
             Ok(SourceFileAndLine { sf: f, line: a }) => {
                 let line = a + 1; // Line numbers start at 1
-                let linebpos = f.lines[a];
+                let linebpos = f.lines()[a];
                 let linechpos = self.bytepos_to_file_charpos(linebpos);
                 let col = chpos - linechpos;
 
                 let col_display = {
                     let start_width_idx = f
-                        .non_narrow_chars
+                        .non_narrow_chars()
                         .binary_search_by_key(&linebpos, |x| x.pos())
                         .unwrap_or_else(|x| x);
                     let end_width_idx = f
-                        .non_narrow_chars
+                        .non_narrow_chars()
                         .binary_search_by_key(&pos, |x| x.pos())
                         .unwrap_or_else(|x| x);
                     let special_chars = end_width_idx - start_width_idx;
                     let non_narrow: usize = f
-                        .non_narrow_chars[start_width_idx..end_width_idx]
+                        .non_narrow_chars()[start_width_idx..end_width_idx]
                         .into_iter()
                         .map(|x| x.width())
                         .sum();
@@ -391,11 +491,11 @@ impl SourceMap {
             Err(f) => {
                 let col_display = {
                     let end_width_idx = f
-                        .non_narrow_chars
+                        .non_narrow_chars()
                         .binary_search_by_key(&pos, |x| x.pos())
                         .unwrap_or_else(|x| x);
                     let non_narrow: usize = f
-                        .non_narrow_chars[0..end_width_idx]
+                        .non_narrow_chars()[0..end_width_idx]
                         .into_iter()
                         .map(|x| x.width())
                         .sum();
@@ -458,6 +558,45 @@ impl SourceMap {
         }
     }
 
+    /// Like `Span::try_join`, but also fails if `sp_lhs` and `sp_rhs` are in different files.
+    pub fn try_join(&self, sp_lhs: Span, sp_rhs: Span) -> Result<Span, JoinError> {
+        let span = sp_lhs.try_join(sp_rhs)?;
+        if self.files.borrow().source_files.is_empty() {
+            return Ok(span);
+        }
+        let begin = self.lookup_byte_offset(sp_lhs.lo()).sf;
+        let end = self.lookup_byte_offset(sp_rhs.hi()).sf;
+        if begin.start_pos != end.start_pos {
+            return Err(JoinError::DistinctSources(DistinctSources {
+                begin: (begin.name.clone(), begin.start_pos),
+                end: (end.name.clone(), end.start_pos),
+            }));
+        }
+        Ok(span)
+    }
+
+    /// Makes diagnostics pointing at code produced by the expansion `expn_id` point at `target`
+    /// instead, e.g., for code a macro generated from a string literal in its input.
+    pub fn redirect_expansion(&self, expn_id: ExpnId, target: Span) {
+        self.span_redirects.borrow_mut().insert(expn_id, target);
+    }
+
+    /// The span diagnostics pointing at `sp` are displayed at. It's the target of the innermost
+    /// redirected expansion that `sp` was produced by, if any, see `redirect_expansion`.
+    pub fn redirected_span(&self, sp: Span) -> Span {
+        let redirects = self.span_redirects.borrow();
+        if redirects.is_empty() {
+            return sp;
+        }
+        let mut ctxt = sp.ctxt();
+        while ctxt != SyntaxContext::root() {
+            if let Some(&target) = redirects.get(&ctxt.remove_mark()) {
+                return target;
+            }
+        }
+        sp
+    }
+
     pub fn span_to_string(&self, sp: Span) -> String {
         if self.files.borrow().source_files.is_empty() && sp.is_dummy() {
             return "no-location".to_string();
@@ -539,8 +678,8 @@ impl SourceMap {
     /// Extracts the source surrounding the given `Span` using the `extract_source` function. The
     /// extract function takes three arguments: a string slice containing the source, an index in
     /// the slice for the beginning of the span and an index in the slice for the end of the span.
-    fn span_to_source<F>(&self, sp: Span, extract_source: F) -> Result<String, SpanSnippetError>
-        where F: Fn(&str, usize, usize) -> Result<String, SpanSnippetError>
+    fn span_to_source<F, T>(&self, sp: Span, extract_source: F) -> Result<T, SpanSnippetError>
+        where F: Fn(&str, usize, usize) -> Result<T, SpanSnippetError>
     {
         if sp.lo() > sp.hi() {
             return Err(SpanSnippetError::IllFormedSpan(sp));
@@ -593,6 +732,91 @@ impl SourceMap {
             .ok_or_else(|| SpanSnippetError::IllFormedSpan(sp)))
     }
 
+    /// Returns the text of a token-sized span without copying it out of its source file.
+    ///
+    /// Unlike `span_to_snippet`, this never loads external sources and reports no details on
+    /// failure: `None` is returned if the span is ill-formed, crosses file boundaries, doesn't
+    /// lie on character boundaries, or points into a file whose source isn't in memory.
+    pub fn token_text(&self, sp: Span) -> Option<TokenText> {
+        if sp.lo() > sp.hi() {
+            return None;
+        }
+        let idx = self.lookup_source_file_idx(sp.lo());
+        let source_file = (*self.files.borrow().source_files)[idx].clone();
+        if sp.hi() > source_file.end_pos {
+            return None;
+        }
+        let start = (sp.lo() - source_file.start_pos).to_usize();
+        let end = (sp.hi() - source_file.start_pos).to_usize();
+        match source_file.src {
+            Some(ref src) if src.is_char_boundary(start) && src.is_char_boundary(end) => {}
+            _ => return None,
+        }
+        Some(TokenText { source_file, start, end })
+    }
+
+    /// Returns the source snippet corresponding to the given `Span` together with up to
+    /// `context_lines` lines of source before and after it, e.g., to embed the failing region
+    /// of a test into a panic message.
+    pub fn extract_snippet_with_context(
+        &self,
+        sp: Span,
+        context_lines: usize,
+    ) -> Result<SnippetWithContext, SpanSnippetError> {
+        let extract = |src: &str, start_index: usize, end_index: usize| {
+            let ill_formed = || SpanSnippetError::IllFormedSpan(sp);
+            let snippet = src.get(start_index..end_index).ok_or_else(ill_formed)?;
+            let prefix = src.get(..start_index).ok_or_else(ill_formed)?;
+            let suffix = src.get(end_index..).ok_or_else(ill_formed)?;
+
+            // Go back to the start of the span's first line, then `context_lines` lines more.
+            let mut context_start = prefix.rfind('\n').map_or(0, |i| i + 1);
+            for _ in 0..context_lines {
+                if context_start == 0 {
+                    break;
+                }
+                context_start = prefix[..context_start - 1].rfind('\n').map_or(0, |i| i + 1);
+            }
+
+            // Go forward to the end of the span's last line, then `context_lines` lines more.
+            let mut context_end = suffix.find('\n').unwrap_or(suffix.len());
+            for _ in 0..context_lines {
+                if context_end == suffix.len() {
+                    break;
+                }
+                context_end = suffix[context_end + 1..]
+                    .find('\n')
+                    .map_or(suffix.len(), |i| context_end + 1 + i);
+            }
+            // Like the other lines of context, the last line of the file ends without its newline.
+            if context_end == suffix.len() && suffix.ends_with('\n') {
+                context_end -= 1;
+            }
+
+            Ok((
+                prefix[context_start..].to_string(),
+                snippet.to_string(),
+                suffix[..context_end].to_string(),
+                src.lines().count().max(1),
+            ))
+        };
+        let (before, snippet, after, lines) = self.span_to_source(sp, extract)?;
+
+        let lo = self.lookup_char_pos(sp.lo());
+        let hi = self.lookup_char_pos(sp.hi());
+        let first_line = lo.line - before.matches('\n').count();
+        // A span ending with a newline ends at the start of the next line, which may be past the
+        // last line of the file.
+        let last_line = (hi.line + after.matches('\n').count()).min(lines);
+        let header = if first_line == last_line {
+            format!("{}:{}", lo.file.name, first_line)
+        } else {
+            format!("{}:{}-{}", lo.file.name, first_line, last_line)
+        };
+
+        Ok(SnippetWithContext { header, before, snippet, after, first_line })
+    }
+
     pub fn span_to_margin(&self, sp: Span) -> Option<usize> {
         match self.span_to_prev_source(sp) {
             Err(_) => None,
@@ -856,7 +1080,7 @@ impl SourceMap {
         // The number of extra bytes due to multibyte chars in the SourceFile
         let mut total_extra_bytes = 0;
 
-        for mbc in map.multibyte_chars.iter() {
+        for mbc in map.multibyte_chars().iter() {
             debug!("{}-byte char at {:?}", mbc.bytes, mbc.pos);
             if mbc.pos < bpos {
                 // every character is at least one byte, so we only
@@ -901,6 +1125,15 @@ impl SourceMap {
         self.files().iter().fold(0, |a, f| a + f.count_lines())
     }
 
+    /// Builds the line indices of all the files not imported from other crates, in parallel if
+    /// the compiler is. The indices are otherwise built one at a time, on the first lookup in
+    /// each file, which is best when only some of the files are going to be looked into.
+    pub fn precompute_line_indices(&self) {
+        let files = self.files().clone();
+        par_for_each_in(files, |file| {
+            file.line_index();
+        });
+    }
 
     pub fn generate_fn_name_span(&self, span: Span) -> Option<Span> {
         let prev_span = self.span_extend_to_prev_str(span, "fn", true);
@@ -1011,6 +1244,9 @@ impl SourceMapper for SourceMap {
     fn doctest_offset_line(&self, file: &FileName, line: usize) -> usize {
         self.doctest_offset_line(file, line)
     }
+    fn redirected_span(&self, sp: Span) -> Span {
+        self.redirected_span(sp)
+    }
 }
 
 #[derive(Clone)]
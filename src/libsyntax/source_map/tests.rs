@@ -167,6 +167,49 @@ fn span_merging_fail() {
     assert!(sm.merge_spans(span1, span2).is_none());
 }
 
+#[test]
+fn span_joining() {
+    let sm = init_source_map();
+    let first = Span::with_root_ctxt(BytePos(0), BytePos(5));
+    let second = Span::with_root_ctxt(BytePos(12), BytePos(18));
+    let other_file = Span::with_root_ctxt(BytePos(25), BytePos(30));
+
+    assert_eq!(sm.try_join(first, second), Ok(Span::with_root_ctxt(BytePos(0), BytePos(18))));
+    assert_eq!(sm.try_join(second, first), Err(JoinError::Reversed));
+    match sm.try_join(first, other_file) {
+        Err(JoinError::DistinctSources(sources)) => {
+            assert_eq!(sources.begin.0, FileName::from(PathBuf::from("blork.rs")));
+            assert_eq!(sources.end.0, FileName::from(PathBuf::from("blork2.rs")));
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn span_redirection() {
+    use crate::edition::Edition;
+    use crate::ext::hygiene::{ExpnData, ExpnId, ExpnKind, MacroKind, Transparency};
+    use crate::symbol::sym;
+
+    crate::with_default_globals(|| {
+        let sm = init_source_map();
+        let fresh_expn = || ExpnId::fresh(Some(ExpnData::default(
+            ExpnKind::Macro(MacroKind::Bang, sym::include), DUMMY_SP, Edition::Edition2018,
+        )));
+        let (outer, inner) = (fresh_expn(), fresh_expn());
+        let target = Span::with_root_ctxt(BytePos(12), BytePos(18));
+        let generated = Span::with_root_ctxt(BytePos(0), BytePos(5))
+            .apply_mark(outer, Transparency::Transparent);
+
+        assert_eq!(sm.redirected_span(generated), generated);
+        sm.redirect_expansion(outer, target);
+        assert_eq!(sm.redirected_span(generated), target);
+        let nested = generated.apply_mark(inner, Transparency::Opaque);
+        assert_eq!(sm.redirected_span(nested), target);
+        assert_eq!(sm.redirected_span(target), target);
+    })
+}
+
 /// Returns the span corresponding to the `n`th occurrence of
 /// `substring` in `source_text`.
 trait SourceMapExtension {
@@ -210,3 +253,119 @@ impl SourceMapExtension for SourceMap {
         }
     }
 }
+
+#[test]
+fn t_extract_snippet_with_context() {
+    let sm = SourceMap::new(FilePathMapping::empty());
+    sm.new_source_file(
+        PathBuf::from("context.rs").into(),
+        "fn f() {\n    let x = 1;\n    assert!(x == 2);\n    drop(x);\n}\n".to_string(),
+    );
+    // The span of `x == 2`.
+    let span = Span::with_root_ctxt(BytePos(36), BytePos(42));
+
+    let snippet = sm.extract_snippet_with_context(span, 0).unwrap();
+    assert_eq!(snippet.header, "context.rs:3");
+    assert_eq!(snippet.before, "    assert!(");
+    assert_eq!(snippet.snippet, "x == 2");
+    assert_eq!(snippet.after, ");");
+
+    let snippet = sm.extract_snippet_with_context(span, 1).unwrap();
+    assert_eq!(snippet.header, "context.rs:2-4");
+    assert_eq!(snippet.first_line, 2);
+    assert_eq!(snippet.before, "    let x = 1;\n    assert!(");
+    assert_eq!(snippet.after, ");\n    drop(x);");
+
+    let snippet = sm.extract_snippet_with_context(span, 10).unwrap();
+    assert_eq!(snippet.header, "context.rs:1-5");
+    assert_eq!(snippet.first_line, 1);
+    assert_eq!(snippet.after, ");\n    drop(x);\n}");
+
+    // The span of the last line, including its newline.
+    let span = Span::with_root_ctxt(BytePos(58), BytePos(60));
+    let snippet = sm.extract_snippet_with_context(span, 1).unwrap();
+    assert_eq!(snippet.header, "context.rs:4-5");
+    assert_eq!(snippet.snippet, "}\n");
+    assert_eq!(snippet.after, "");
+}
+
+#[test]
+fn byte_blobs_are_shared() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingLoader(Arc<AtomicUsize>);
+
+    impl FileLoader for CountingLoader {
+        fn file_exists(&self, _path: &Path) -> bool {
+            true
+        }
+
+        fn abs_path(&self, path: &Path) -> Option<PathBuf> {
+            Some(path.to_owned())
+        }
+
+        fn read_file(&self, _path: &Path) -> io::Result<String> {
+            unreachable!()
+        }
+
+        fn read_binary_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match path.to_str() {
+                Some("b.bin") => Ok(vec![0xff, 0xfe]),
+                _ => Ok(vec![0, 1, 2, 3]),
+            }
+        }
+    }
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let sm = SourceMap::with_file_loader(Box::new(CountingLoader(reads.clone())),
+                                         FilePathMapping::empty());
+    let a = sm.load_byte_blob(Path::new("a.bin")).unwrap();
+    let a_again = sm.load_byte_blob(Path::new("a.bin")).unwrap();
+    let a_copy = sm.load_byte_blob(Path::new("copy-of-a.bin")).unwrap();
+    let b = sm.load_byte_blob(Path::new("b.bin")).unwrap();
+
+    assert_eq!(*a, [0, 1, 2, 3]);
+    assert!(Lrc::ptr_eq(&a, &a_again));
+    assert!(Lrc::ptr_eq(&a, &a_copy));
+    assert_eq!(*b, [0xff, 0xfe]);
+    assert_eq!(reads.load(Ordering::SeqCst), 3);
+    assert_eq!(sm.binary_files(), [Path::new("a.bin"), Path::new("copy-of-a.bin"),
+                                   Path::new("b.bin")]);
+    assert!(sm.files().is_empty());
+}
+
+#[test]
+fn line_indices_are_built_on_demand() {
+    let sm = init_source_map_mbc();
+    assert!(sm.files().iter().all(|file| file.line_index.try_get().is_none()));
+
+    let first = sm.files()[0].clone();
+    assert_eq!(first.lines(), [BytePos(0), BytePos(28)]);
+    assert_eq!(first.multibyte_chars().len(), 5);
+    assert!(sm.files()[1].line_index.try_get().is_none());
+
+    sm.precompute_line_indices();
+    assert!(sm.files().iter().all(|file| file.line_index.try_get().is_some()));
+    let loc = sm.lookup_char_pos(BytePos(56));
+    assert_eq!((loc.line, loc.col), (1, CharPos(12)));
+}
+
+#[test]
+fn token_text_slices_the_source() {
+    let sm = init_source_map_mbc();
+    let text = |lo, hi| {
+        sm.token_text(Span::with_root_ctxt(BytePos(lo), BytePos(hi))).map(|text| text.to_string())
+    };
+    assert_eq!(text(0, 3), Some("fir".to_string()));
+    assert_eq!(text(3, 6), Some("€".to_string()));
+    assert_eq!(text(40, 45), Some("first".to_string()));
+    // Not on character boundaries.
+    assert_eq!(text(3, 5), None);
+    // Across files.
+    assert_eq!(text(20, 45), None);
+    assert_eq!(text(6, 3), None);
+    // Unlike `span_to_snippet`, no line index is needed.
+    assert!(sm.files().iter().all(|file| file.line_index.try_get().is_none()));
+}
@@ -29,9 +29,14 @@ use smallvec::{SmallVec, smallvec};
 use std::borrow::Cow;
 use std::{fmt, iter, mem};
 
+mod diff;
+mod interner;
 #[cfg(test)]
 mod tests;
 
+pub use diff::{diff, render_diff, TokenEdit};
+pub use interner::{TokenInterner, TokenInternerStats};
+
 /// When the main rust parser encounters a syntax-extension invocation, it
 /// parses the arguments to the invocation as a token-tree. This is a very
 /// loose structure, such that all sorts of different AST-fragments can
@@ -119,7 +124,7 @@ impl TokenTree {
     }
 
     pub fn joint(self) -> TokenStream {
-        TokenStream::new(vec![(self, Joint)])
+        TokenStream::single((self, Joint))
     }
 
     pub fn token(kind: TokenKind, span: Span) -> TokenTree {
@@ -157,12 +162,21 @@ impl TokenTree {
 /// The use of `Option` is an optimization that avoids the need for an
 /// allocation when the stream is empty. However, it is not guaranteed that an
 /// empty stream is represented with `None`; it may be represented as a `Some`
-/// around an empty `Vec`.
+/// around an empty vector.
+///
+/// Streams of up to `INLINE_TREES` trees (the common case for attribute
+/// arguments and derive inputs) store them inline in the shared allocation,
+/// so creating one costs a single allocation rather than two.
 #[derive(Clone, Debug)]
-pub struct TokenStream(pub Option<Lrc<Vec<TreeAndJoint>>>);
+pub struct TokenStream(Option<Lrc<TreeVec>>);
 
 pub type TreeAndJoint = (TokenTree, IsJoint);
 
+/// The number of trees a `TokenStream` stores without a separate buffer.
+const INLINE_TREES: usize = 2;
+
+type TreeVec = SmallVec<[TreeAndJoint; INLINE_TREES]>;
+
 // `TokenStream` is used a lot. Make sure it doesn't unintentionally get bigger.
 #[cfg(target_arch = "x86_64")]
 static_assert_size!(TokenStream, 8);
@@ -216,7 +230,7 @@ impl TokenStream {
 
 impl From<TokenTree> for TokenStream {
     fn from(tree: TokenTree) -> TokenStream {
-        TokenStream::new(vec![(tree, NonJoint)])
+        TokenStream::single((tree, NonJoint))
     }
 }
 
@@ -249,10 +263,16 @@ impl TokenStream {
         }
     }
 
-    pub fn empty() -> TokenStream {
+    /// The empty stream, which never allocates.
+    pub const fn empty() -> TokenStream {
         TokenStream(None)
     }
 
+    /// A stream consisting of a single tree, stored inline.
+    fn single(tree: TreeAndJoint) -> TokenStream {
+        TokenStream(Some(Lrc::new(smallvec![tree])))
+    }
+
     pub fn is_empty(&self) -> bool {
         match self.0 {
             None => true,
@@ -286,7 +306,11 @@ impl TokenStream {
     pub fn new(streams: Vec<TreeAndJoint>) -> TokenStream {
         match streams.len() {
             0 => TokenStream(None),
-            _ => TokenStream(Some(Lrc::new(streams))),
+            // Move short streams inline instead of keeping the `Vec`'s buffer alive.
+            len if len <= INLINE_TREES => {
+                TokenStream(Some(Lrc::new(streams.into_iter().collect())))
+            }
+            _ => TokenStream(Some(Lrc::new(TreeVec::from_vec(streams)))),
         }
     }
 
@@ -437,7 +461,7 @@ impl TokenStreamBuilder {
             let len = streams.len();
             match len {
                 1 => {}
-                _ => self.0.push(TokenStream(Some(Lrc::new(streams[0 .. len - 1].to_vec())))),
+                _ => self.0.push(TokenStream::new(streams[0 .. len - 1].to_vec())),
             }
         }
     }
@@ -447,7 +471,7 @@ impl TokenStreamBuilder {
             let len = streams.len();
             match len {
                 1 => {}
-                _ => self.0.push(TokenStream(Some(Lrc::new(streams[1 .. len].to_vec())))),
+                _ => self.0.push(TokenStream::new(streams[1 .. len].to_vec())),
             }
         }
     }
@@ -496,10 +520,10 @@ impl Cursor {
         self.index = index;
     }
 
-    pub fn look_ahead(&self, n: usize) -> Option<TokenTree> {
+    pub fn look_ahead(&self, n: usize) -> Option<&TokenTree> {
         match self.stream.0 {
             None => None,
-            Some(ref stream) => stream[self.index ..].get(n).map(|(tree, _)| tree.clone()),
+            Some(ref stream) => stream[self.index ..].get(n).map(|(tree, _)| tree),
         }
     }
 }
@@ -0,0 +1,203 @@
+//! Structural differences between token streams, e.g., between the input and the output of a
+//! macro.
+
+use super::{DelimSpan, TokenStream, TokenTree};
+use crate::parse::token::{self, DelimToken};
+use crate::print::pprust;
+
+/// A step of the edit script computed by `diff`.
+#[derive(Clone, Debug)]
+pub enum TokenEdit {
+    /// A token tree found in both streams, with its spans from the new stream.
+    Same(TokenTree),
+    /// A token tree of the old stream that's missing from the new one.
+    Removed(TokenTree),
+    /// A token tree of the new stream that's missing from the old one.
+    Inserted(TokenTree),
+    /// A delimited token tree found in both streams with different contents.
+    Changed {
+        delim: DelimToken,
+        old_span: DelimSpan,
+        new_span: DelimSpan,
+        edits: Vec<TokenEdit>,
+    },
+}
+
+impl TokenEdit {
+    fn is_same(&self) -> bool {
+        match self {
+            TokenEdit::Same(..) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Computes the edits that turn `old` into `new`, comparing token trees regardless of their
+/// spans. A delimited tree replaced by one with the same delimiter is reported as `Changed`,
+/// with the edits of its contents, rather than as removed and inserted.
+///
+/// The trees between the common prefix and suffix of the streams are matched with a longest
+/// common subsequence search, which is quadratic in the number of trees that differ. Past
+/// `MAX_LCS_CELLS`, all of the old trees are reported as removed and all of the new ones as
+/// inserted instead.
+pub fn diff(old: &TokenStream, new: &TokenStream) -> Vec<TokenEdit> {
+    let old: Vec<_> = old.trees().collect();
+    let new: Vec<_> = new.trees().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(old, new)| old.eq_unspanned(new)).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old.eq_unspanned(new))
+        .count();
+    let (old_middle, new_middle) =
+        (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut edits: Vec<_> = new[..prefix].iter().cloned().map(TokenEdit::Same).collect();
+    edits.extend(pair_changed_trees(diff_trees(old_middle, new_middle)));
+    edits.extend(new[new.len() - suffix..].iter().cloned().map(TokenEdit::Same));
+    edits
+}
+
+/// The largest table of subsequence lengths `diff_trees` builds, in number of entries.
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// The longest common subsequence of `old` and `new`, as `Same`, `Removed` and `Inserted`
+/// edits, or the removal of `old` followed by the insertion of `new` if the search would need
+/// more than `MAX_LCS_CELLS` entries.
+fn diff_trees(old: &[TokenTree], new: &[TokenTree]) -> Vec<TokenEdit> {
+    let cells = (old.len() + 1).checked_mul(new.len() + 1);
+    if cells.map_or(true, |cells| cells > MAX_LCS_CELLS) {
+        let removed = old.iter().cloned().map(TokenEdit::Removed);
+        return removed.chain(new.iter().cloned().map(TokenEdit::Inserted)).collect();
+    }
+
+    // `lengths[i][j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`.
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i].eq_unspanned(&new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].eq_unspanned(&new[j]) {
+            edits.push(TokenEdit::Same(new[j].clone()));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push(TokenEdit::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            edits.push(TokenEdit::Inserted(new[j].clone()));
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Turns removed and inserted delimited trees with the same delimiter into `Changed` edits.
+/// Within each run of edits between two `Same` ones, the n-th removed tree is paired with the
+/// n-th inserted one.
+fn pair_changed_trees(edits: Vec<TokenEdit>) -> Vec<TokenEdit> {
+    let mut paired = Vec::with_capacity(edits.len());
+    let mut edits = edits.into_iter().peekable();
+    while let Some(edit) = edits.next() {
+        if edit.is_same() {
+            paired.push(edit);
+            continue;
+        }
+        let (mut removed, mut inserted) = (Vec::new(), Vec::new());
+        let mut next = Some(edit);
+        while let Some(edit) = next {
+            match edit {
+                TokenEdit::Removed(tree) => removed.push(tree),
+                TokenEdit::Inserted(tree) => inserted.push(tree),
+                _ => unreachable!(),
+            }
+            next = match edits.peek() {
+                Some(edit) if !edit.is_same() => edits.next(),
+                _ => None,
+            };
+        }
+
+        let mut inserted = inserted.into_iter();
+        for old in removed {
+            match (old, inserted.next()) {
+                (
+                    TokenTree::Delimited(old_span, delim, old_stream),
+                    Some(TokenTree::Delimited(new_span, new_delim, new_stream)),
+                ) if delim == new_delim => paired.push(TokenEdit::Changed {
+                    delim,
+                    old_span,
+                    new_span,
+                    edits: diff(&old_stream, &new_stream),
+                }),
+                (old, new) => {
+                    paired.push(TokenEdit::Removed(old));
+                    paired.extend(new.map(TokenEdit::Inserted));
+                }
+            }
+        }
+        paired.extend(inserted.map(TokenEdit::Inserted));
+    }
+    paired
+}
+
+/// Renders the changes among `edits`, one line per run of removed or inserted trees, e.g.
+///
+/// ```text
+/// - `a b`
+/// + `c`
+/// in `{ ... }`:
+///   + `d`
+/// ```
+///
+/// Trees that are the same in both streams are left out, and each run is printed with at most
+/// `max_tokens` tokens, see `pprust::tts_to_string_truncated`.
+pub fn render_diff(edits: &[TokenEdit], max_tokens: Option<usize>) -> String {
+    let mut lines = Vec::new();
+    render_edits(edits, "", max_tokens, &mut lines);
+    lines.join("\n")
+}
+
+fn render_edits(edits: &[TokenEdit], indent: &str, max_tokens: Option<usize>,
+                lines: &mut Vec<String>) {
+    let mut run: Option<(char, Vec<TokenTree>)> = None;
+    let flush = |run: &mut Option<(char, Vec<TokenTree>)>, lines: &mut Vec<String>| {
+        if let Some((sign, trees)) = run.take() {
+            let stream: TokenStream = trees.into_iter().collect();
+            let stream = pprust::tts_to_string_truncated(stream, max_tokens);
+            lines.push(format!("{}{} `{}`", indent, sign, stream));
+        }
+    };
+    for edit in edits {
+        let (sign, tree) = match edit {
+            TokenEdit::Same(..) => {
+                flush(&mut run, lines);
+                continue;
+            }
+            TokenEdit::Removed(tree) => ('-', tree),
+            TokenEdit::Inserted(tree) => ('+', tree),
+            TokenEdit::Changed { delim, edits, .. } => {
+                flush(&mut run, lines);
+                let open = pprust::token_kind_to_string(&token::OpenDelim(*delim));
+                let close = pprust::token_kind_to_string(&token::CloseDelim(*delim));
+                lines.push(format!("{}in `{} ... {}`:", indent, open, close));
+                render_edits(edits, &format!("{}  ", indent), max_tokens, lines);
+                continue;
+            }
+        };
+        if run.as_ref().map_or(true, |(run_sign, _)| *run_sign != sign) {
+            flush(&mut run, lines);
+            run = Some((sign, Vec::new()));
+        }
+        run.as_mut().unwrap().1.push(tree.clone());
+    }
+    flush(&mut run, lines);
+}
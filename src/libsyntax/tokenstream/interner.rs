@@ -0,0 +1,178 @@
+//! Sharing of identical token streams.
+//!
+//! Macros that expand to the same tokens at the same spans, e.g., the helpers of a derive
+//! applied to many similar items, would otherwise keep a copy of the same trees each. The
+//! interner hands out the stream it has already seen instead of the new one, so the duplicate
+//! can be freed.
+
+use super::{IsJoint, TokenStream, TokenTree, TreeVec};
+use crate::parse::token::{self, Token};
+
+use rustc_data_structures::fx::{FxHashMap, FxHasher};
+use rustc_data_structures::sync::{Lrc, Weak};
+use smallvec::SmallVec;
+
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+/// What the interner has saved so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenInternerStats {
+    /// The number of non-empty streams passed to `TokenInterner::intern`.
+    pub streams: usize,
+    /// The number of streams that were replaced by an identical one interned before.
+    pub shared: usize,
+    /// The number of trees in the replaced streams, not counting the trees nested in them.
+    pub shared_trees: usize,
+}
+
+impl TokenInternerStats {
+    /// An estimate of the memory freed by sharing streams, in bytes.
+    pub fn bytes_saved(&self) -> usize {
+        self.shared_trees * mem::size_of::<(TokenTree, IsJoint)>()
+    }
+}
+
+/// Deduplicates token streams that are equal, including their spans and jointness.
+///
+/// Streams are hashed without their spans, and each stream only once: the hash of an interned
+/// stream is remembered, so that interning a stream that contains it, as the transcription of
+/// nested delimiters does, doesn't walk it again. Since streams are only shared if their spans
+/// are equal too, which macros that give their output fresh hygiene rarely produce, interning
+/// is off by default, see `ExpansionConfig::intern_token_streams`.
+///
+/// The interner only holds weak references, so it keeps no stream alive on its own. Streams
+/// containing interpolated AST fragments are never shared, as those can't be compared.
+#[derive(Default)]
+pub struct TokenInterner {
+    /// The interned streams and their addresses, keyed by a hash of their contents.
+    streams: FxHashMap<u64, SmallVec<[(Weak<TreeVec>, usize); 1]>>,
+    /// The hashes of the interned streams, keyed by their address. The weak references in
+    /// `streams` keep the allocations, and so the addresses, from being reused.
+    hashes: FxHashMap<usize, u64>,
+    /// The number of entries of `streams` the last time dead references were removed.
+    live_after_prune: usize,
+    stats: TokenInternerStats,
+}
+
+impl TokenInterner {
+    /// Returns a stream equal to `stream`, sharing its trees with an earlier stream if possible.
+    pub fn intern(&mut self, stream: TokenStream) -> TokenStream {
+        let trees = match stream.0 {
+            Some(ref trees) if !trees.is_empty() => trees,
+            _ => return stream,
+        };
+        self.stats.streams += 1;
+        let hash = match self.hash(trees) {
+            Some(hash) => hash,
+            None => return stream,
+        };
+
+        let hashes = &mut self.hashes;
+        let bucket = self.streams.entry(hash).or_default();
+        retain_live(bucket, hashes);
+        let existing = bucket.iter()
+            .filter_map(|(interned, _)| interned.upgrade())
+            .find(|interned| Lrc::ptr_eq(interned, trees) || trees_eq(interned, trees));
+        if let Some(interned) = existing {
+            if !Lrc::ptr_eq(&interned, trees) {
+                self.stats.shared += 1;
+                self.stats.shared_trees += trees.len();
+            }
+            return TokenStream(Some(interned));
+        }
+        bucket.push((Lrc::downgrade(trees), address(trees)));
+        hashes.insert(address(trees), hash);
+
+        if self.streams.len() >= 2 * self.live_after_prune.max(1024) {
+            self.streams.retain(|_, bucket| {
+                retain_live(bucket, hashes);
+                !bucket.is_empty()
+            });
+            self.live_after_prune = self.streams.len();
+        }
+        stream
+    }
+
+    pub fn stats(&self) -> TokenInternerStats {
+        self.stats
+    }
+
+    /// Hashes the trees without their spans, reusing the hashes of the interned streams nested
+    /// in them. Returns `None` if they contain an interpolated AST fragment.
+    fn hash(&self, trees: &TreeVec) -> Option<u64> {
+        if let Some(&hash) = self.hashes.get(&address(trees)) {
+            return Some(hash);
+        }
+        let mut hasher = FxHasher::default();
+        trees.len().hash(&mut hasher);
+        for (tree, is_joint) in trees.iter() {
+            (*is_joint == IsJoint::Joint).hash(&mut hasher);
+            match tree {
+                TokenTree::Token(token) => hash_token(token, &mut hasher)?,
+                TokenTree::Delimited(_, delim, stream) => {
+                    delim.hash(&mut hasher);
+                    match stream.0 {
+                        Some(ref nested) => self.hash(nested)?.hash(&mut hasher),
+                        None => 0usize.hash(&mut hasher),
+                    }
+                }
+            }
+        }
+        Some(hasher.finish())
+    }
+}
+
+fn address(trees: &TreeVec) -> usize {
+    trees as *const TreeVec as usize
+}
+
+/// Removes the streams of `bucket` that were freed, and forgets their hashes.
+fn retain_live(bucket: &mut SmallVec<[(Weak<TreeVec>, usize); 1]>,
+               hashes: &mut FxHashMap<usize, u64>) {
+    bucket.retain(|(interned, address)| {
+        let live = interned.upgrade().is_some();
+        if !live {
+            hashes.remove(address);
+        }
+        live
+    });
+}
+
+fn hash_token(token: &Token, hasher: &mut FxHasher) -> Option<()> {
+    mem::discriminant(&token.kind).hash(hasher);
+    match token.kind {
+        token::Interpolated(..) => return None,
+        token::Ident(name, is_raw) => (name, is_raw).hash(hasher),
+        token::Lifetime(name) | token::DocComment(name) | token::Shebang(name) => {
+            name.hash(hasher)
+        }
+        token::Literal(lit) => (lit.symbol, lit.suffix).hash(hasher),
+        _ => {}
+    }
+    Some(())
+}
+/// Compares the trees with their spans and jointness, unlike the `PartialEq` implementation of
+/// `TokenStream`, which ignores the jointness of nested trees.
+fn trees_eq(lhs: &[(TokenTree, IsJoint)], rhs: &[(TokenTree, IsJoint)]) -> bool {
+    lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|((lhs, lhs_joint), (rhs, rhs_joint))| {
+        lhs_joint == rhs_joint && match (lhs, rhs) {
+            (TokenTree::Token(lhs), TokenTree::Token(rhs)) => lhs == rhs,
+            (
+                TokenTree::Delimited(lhs_span, lhs_delim, lhs_stream),
+                TokenTree::Delimited(rhs_span, rhs_delim, rhs_stream),
+            ) => {
+                lhs_span == rhs_span && lhs_delim == rhs_delim &&
+                    trees_eq(stream_trees(lhs_stream), stream_trees(rhs_stream))
+            }
+            _ => false,
+        }
+    })
+}
+
+fn stream_trees(stream: &TokenStream) -> &[(TokenTree, IsJoint)] {
+    match stream.0 {
+        Some(ref trees) => trees,
+        None => &[],
+    }
+}
@@ -106,3 +106,87 @@ fn test_dotdotdot() {
         assert_eq!(stream.trees().count(), 1);
     })
 }
+
+#[test]
+fn test_short_streams() {
+    with_default_globals(|| {
+        let a = TokenTree::token(token::Ident(Name::intern("a"), false), sp(0, 1));
+        let b = TokenTree::token(token::Comma, sp(1, 2));
+        let c = TokenTree::token(token::Ident(Name::intern("c"), false), sp(2, 3));
+
+        let short: TokenStream = vec![a.clone(), b.clone()].into_iter().collect();
+        let long: TokenStream = vec![a, b, c].into_iter().collect();
+        assert_eq!(short.len(), 2);
+        assert_eq!(long.len(), 3);
+        assert!(short.eq_unspanned(&string_to_ts("a,")));
+        assert!(long.eq_unspanned(&string_to_ts("a,c")));
+        assert!(TokenStream::empty().is_empty());
+    })
+}
+
+#[test]
+fn test_diff() {
+    with_default_globals(|| {
+        let old = string_to_ts("fn f(a: u8) { a + 1 }");
+        let new = string_to_ts("fn g(a: u8) { a + 2 * b }");
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 5);
+        match &edits[4] {
+            TokenEdit::Changed { delim: DelimToken::Brace, edits, .. } => {
+                assert_eq!(edits.len(), 6)
+            }
+            edit => panic!("expected a changed block, found {:?}", edit),
+        }
+        assert_eq!(render_diff(&edits, None), "- `f`\n+ `g`\nin `{ ... }`:\n  - `1`\n  + `2 * b`");
+        assert!(diff(&old, &old).iter().all(|edit| match edit {
+            TokenEdit::Same(..) => true,
+            _ => false,
+        }));
+    })
+}
+
+#[test]
+fn test_diff_long_streams() {
+    with_default_globals(|| {
+        let old = string_to_ts(&format!("[{}]", "a, ".repeat(2000)));
+        let new = string_to_ts(&format!("[{}]", "b, ".repeat(2000)));
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 1);
+        match &edits[0] {
+            TokenEdit::Changed { delim: DelimToken::Bracket, edits, .. } => {
+                // Only the trailing comma is common to the contents, the rest of the trees
+                // are removed and inserted without a subsequence search.
+                let count = |kind: fn(&TokenEdit) -> bool| edits.iter().filter(|e| kind(e)).count();
+                assert_eq!(count(|edit| match edit { TokenEdit::Removed(..) => true, _ => false }),
+                           3999);
+                assert_eq!(count(|edit| match edit { TokenEdit::Inserted(..) => true, _ => false }),
+                           3999);
+                match edits.last() {
+                    Some(TokenEdit::Same(..)) => {}
+                    edit => panic!("expected the trailing comma, found {:?}", edit),
+                }
+            }
+            edit => panic!("expected a changed list, found {:?}", edit),
+        }
+    })
+}
+
+#[test]
+fn test_interner() {
+    with_default_globals(|| {
+        let mut interner = TokenInterner::default();
+        let first = interner.intern(string_to_ts("f(a, b)"));
+        let same = interner.intern(string_to_ts("f(a, b)"));
+        let other_span = interner.intern(string_to_ts("f(a,  b)"));
+        let other_token = interner.intern(string_to_ts("f(a, c)"));
+        let shared = |lhs: &TokenStream, rhs: &TokenStream| match (&lhs.0, &rhs.0) {
+            (Some(lhs), Some(rhs)) => Lrc::ptr_eq(lhs, rhs),
+            _ => false,
+        };
+        assert!(shared(&first, &same));
+        assert!(!shared(&first, &other_span));
+        assert!(!shared(&first, &other_token));
+        assert_eq!(interner.stats().shared, 1);
+        assert_eq!(interner.stats().shared_trees, 2);
+    })
+}
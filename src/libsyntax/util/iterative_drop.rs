@@ -0,0 +1,79 @@
+//! Dropping of ASTs without unbounded recursion.
+//!
+//! The derived destructors of the AST recurse once per nesting level, so a crate containing
+//! very deep expression chains (typically produced by code generators) can overflow the stack
+//! after it has been compiled successfully. The functions in this module dismantle the tree
+//! with an explicit worklist instead: every expression is detached from its parent before the
+//! parent is dropped, so no destructor ever recurses through more than one expression.
+//!
+//! Only expressions are detached, nested types and patterns are still dropped recursively.
+
+use crate::ast::{Crate, Expr, ExprKind, Mac, DUMMY_NODE_ID};
+use crate::mut_visit::{self, MutVisitor};
+use crate::ptr::P;
+use crate::tokenstream::TokenStream;
+use crate::ThinVec;
+
+use syntax_pos::DUMMY_SP;
+
+use std::mem;
+
+#[cfg(test)]
+mod tests;
+
+/// Drops `krate` without recursing through nested expressions.
+pub fn drop_crate_iteratively(mut krate: Crate) {
+    let mut detacher = ExprDetacher { detached: Vec::new() };
+    detacher.visit_crate(&mut krate);
+    drop(krate);
+    detacher.drain();
+}
+
+/// Drops `expr` without recursing through nested expressions.
+pub fn drop_expr_iteratively(expr: P<Expr>) {
+    let mut detacher = ExprDetacher { detached: vec![expr.into_inner().node] };
+    detacher.drain();
+}
+
+/// Whether expressions of kind `node` have no subexpressions to detach.
+fn is_leaf(node: &ExprKind) -> bool {
+    match node {
+        ExprKind::Lit(..) | ExprKind::Path(..) | ExprKind::Continue(..) | ExprKind::Mac(..) |
+        ExprKind::Err => true,
+        _ => false,
+    }
+}
+
+/// Moves the kind of every expression it visits onto a worklist, leaving a childless
+/// `ExprKind::Err` behind.
+struct ExprDetacher {
+    detached: Vec<ExprKind>,
+}
+
+impl ExprDetacher {
+    fn drain(&mut self) {
+        while let Some(node) = self.detached.pop() {
+            let mut expr = Expr { id: DUMMY_NODE_ID, node, span: DUMMY_SP, attrs: ThinVec::new() };
+            // Detaches the direct subexpressions (including those nested in blocks, closures
+            // and the like), so dropping `expr` afterwards is shallow.
+            mut_visit::noop_visit_expr(&mut expr, self);
+        }
+    }
+}
+
+impl MutVisitor for ExprDetacher {
+    fn visit_expr(&mut self, expr: &mut P<Expr>) {
+        let node = mem::replace(&mut expr.node, ExprKind::Err);
+        if !is_leaf(&node) {
+            self.detached.push(node);
+        }
+    }
+
+    fn visit_mac(&mut self, _mac: &mut Mac) {
+        // Macro invocations only contain tokens, which are dropped without deep recursion.
+    }
+
+    fn visit_tts(&mut self, _tts: &mut TokenStream) {
+        // Nothing to detach, and visiting interpolated tokens would clone their contents.
+    }
+}
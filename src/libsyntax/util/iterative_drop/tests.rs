@@ -0,0 +1,34 @@
+use super::*;
+
+use crate::ast::{Block, BlockCheckMode, Stmt, StmtKind};
+
+use std::thread;
+
+fn expr(node: ExprKind) -> P<Expr> {
+    P(Expr { id: DUMMY_NODE_ID, node, span: DUMMY_SP, attrs: ThinVec::new() })
+}
+
+#[test]
+fn deeply_nested_expressions_are_dropped_without_overflowing() {
+    // Far deeper than a recursive destructor could go on the small stack of the thread below.
+    let depth = 100_000;
+    let nested = thread::Builder::new().stack_size(256 * 1024).spawn(move || {
+        let mut nested = expr(ExprKind::Tup(Vec::new()));
+        for i in 0..depth {
+            nested = if i % 2 == 0 {
+                expr(ExprKind::Paren(nested))
+            } else {
+                let stmt = Stmt { id: DUMMY_NODE_ID, node: StmtKind::Expr(nested), span: DUMMY_SP };
+                let block = P(Block {
+                    stmts: vec![stmt],
+                    id: DUMMY_NODE_ID,
+                    rules: BlockCheckMode::Default,
+                    span: DUMMY_SP,
+                });
+                expr(ExprKind::Block(block, None))
+            };
+        }
+        drop_expr_iteratively(nested);
+    }).unwrap();
+    assert!(nested.join().is_ok());
+}
@@ -0,0 +1,89 @@
+//! Equality of AST nodes regardless of where they come from.
+//!
+//! Two nodes are spanlessly equal if they only differ in their spans, `NodeId`s, attribute IDs,
+//! the tokens cached for items and the jointness of the tokens in macro calls, i.e., in what
+//! changes when the same code is parsed from a different place or with different whitespace.
+
+use crate::ast;
+use crate::mut_visit::{self, MutVisitor};
+use crate::ptr::P;
+use crate::tokenstream::TokenStream;
+
+use smallvec::SmallVec;
+use syntax_pos::{Span, DUMMY_SP};
+
+pub trait SpanlessEq {
+    /// A dump of the node with everything `spanless_eq` ignores erased, as printed by `{:#?}`.
+    fn spanless_dump(&self) -> String;
+
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.spanless_dump() == other.spanless_dump()
+    }
+}
+
+macro_rules! impl_spanless_eq {
+    ($($ty:ty => |$vis:ident, $node:ident| $normalize:expr;)*) => {$(
+        impl SpanlessEq for $ty {
+            fn spanless_dump(&self) -> String {
+                let mut $node = self.clone();
+                let $vis = &mut Normalizer;
+                $normalize;
+                format!("{:#?}", $node)
+            }
+        }
+    )*}
+}
+
+impl_spanless_eq! {
+    ast::Crate => |vis, krate| vis.visit_crate(&mut krate);
+    P<ast::Item> => |vis, item| item = vis.flat_map_item(item).pop().unwrap();
+    P<ast::Expr> => |vis, expr| vis.visit_expr(&mut expr);
+    P<ast::Ty> => |vis, ty| vis.visit_ty(&mut ty);
+    P<ast::Pat> => |vis, pat| vis.visit_pat(&mut pat);
+    TokenStream => |vis, tts| vis.visit_tts(&mut tts);
+}
+
+/// Erases what `SpanlessEq` ignores.
+struct Normalizer;
+
+impl MutVisitor for Normalizer {
+    fn visit_id(&mut self, id: &mut ast::NodeId) {
+        *id = ast::DUMMY_NODE_ID;
+    }
+
+    fn visit_span(&mut self, span: &mut Span) {
+        *span = DUMMY_SP;
+    }
+
+    fn visit_attribute(&mut self, attr: &mut ast::Attribute) {
+        attr.id = ast::AttrId(0);
+        mut_visit::noop_visit_attribute(attr, self);
+    }
+
+    fn visit_mac(&mut self, mac: &mut ast::Mac) {
+        // Only the position of the type ascription is recorded, which doesn't survive printing.
+        mac.prior_type_ascription = None;
+        mut_visit::noop_visit_mac(mac, self);
+    }
+
+    fn visit_tts(&mut self, tts: &mut TokenStream) {
+        mut_visit::noop_visit_tts(tts, self);
+        // Collecting the trees makes all of them non-joint.
+        *tts = tts.trees().collect();
+    }
+
+    fn flat_map_item(&mut self, mut item: P<ast::Item>) -> SmallVec<[P<ast::Item>; 1]> {
+        item.tokens = None;
+        mut_visit::noop_flat_map_item(item, self)
+    }
+
+    fn flat_map_trait_item(&mut self, mut item: ast::TraitItem) -> SmallVec<[ast::TraitItem; 1]> {
+        item.tokens = None;
+        mut_visit::noop_flat_map_trait_item(item, self)
+    }
+
+    fn flat_map_impl_item(&mut self, mut item: ast::ImplItem) -> SmallVec<[ast::ImplItem; 1]> {
+        item.tokens = None;
+        mut_visit::noop_flat_map_impl_item(item, self)
+    }
+}
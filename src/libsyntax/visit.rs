@@ -14,7 +14,7 @@
 //! those that are created by the expansion of a macro.
 
 use crate::ast::*;
-use crate::parse::token::Token;
+use crate::parse::token::{self, Token};
 use crate::tokenstream::{TokenTree, TokenStream};
 
 use syntax_pos::Span;
@@ -846,7 +846,17 @@ pub fn walk_vis<'a, V: Visitor<'a>>(visitor: &mut V, vis: &'a Visibility) {
 }
 
 pub fn walk_attribute<'a, V: Visitor<'a>>(visitor: &mut V, attr: &'a Attribute) {
-    visitor.visit_tts(attr.tokens.clone());
+    match &attr.args {
+        // The value is walked in place, turning it into tokens would clone it.
+        AttrArgs::Eq(eq_span, expr) => {
+            visitor.visit_token(Token::new(token::Eq, *eq_span));
+            match &expr.node {
+                ExprKind::Lit(lit) => visitor.visit_tts(lit.tokens()),
+                _ => visitor.visit_expr(expr),
+            }
+        }
+        args => visitor.visit_tts(args.outer_tokens()),
+    }
 }
 
 pub fn walk_tt<'a, V: Visitor<'a>>(visitor: &mut V, tt: TokenTree) {
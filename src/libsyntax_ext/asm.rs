@@ -8,6 +8,7 @@ use errors::DiagnosticBuilder;
 
 use syntax::ast;
 use syntax::ext::base::{self, *};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
 use syntax::parse::token::{self, Token};
 use syntax::ptr::P;
 use syntax::symbol::{kw, sym, Symbol};
@@ -213,7 +214,8 @@ fn parse_inline_asm<'a>(
                     let (s, _) = p.parse_str()?;
 
                     if OPTIONS.iter().any(|&opt| s == opt) {
-                        cx.span_warn(p.prev_span, "expected a clobber, found an option");
+                        let msg = "expected a clobber, found an option";
+                        ExpansionDiagnostics::span_warn(cx, p.prev_span.into(), msg);
                     } else if s.as_str().starts_with("{") || s.as_str().ends_with("}") {
                         span_err!(cx, p.prev_span, E0664,
                                                 "clobber should not be surrounded by braces");
@@ -234,7 +236,7 @@ fn parse_inline_asm<'a>(
                 } else if option == sym::intel {
                     dialect = AsmDialect::Intel;
                 } else {
-                    cx.span_warn(p.prev_span, "unrecognized option");
+                    ExpansionDiagnostics::span_warn(cx, p.prev_span.into(), "unrecognized option");
                 }
 
                 if p.token == token::Comma {
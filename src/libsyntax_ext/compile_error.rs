@@ -1,6 +1,7 @@
 // The compiler code necessary to support the compile_error! extension.
 
 use syntax::ext::base::{self, *};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
 use syntax_pos::Span;
 use syntax::tokenstream::TokenStream;
 
@@ -8,12 +9,12 @@ pub fn expand_compile_error<'cx>(cx: &'cx mut ExtCtxt<'_>,
                               sp: Span,
                               tts: TokenStream)
                               -> Box<dyn base::MacResult + 'cx> {
-    let var = match get_single_str_from_tts(cx, sp, tts, "compile_error!") {
-        None => return DummyResult::any(sp),
-        Some(v) => v,
+    let var = match try_get_single_str_from_tts(cx, sp, tts, "compile_error!") {
+        Ok(v) => v,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
 
-    cx.span_err(sp, &var);
+    ExpansionDiagnostics::span_err(cx, sp.into(), &var);
 
     DummyResult::any(sp)
 }
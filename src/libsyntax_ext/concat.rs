@@ -1,23 +1,42 @@
 use syntax::ast;
 use syntax::ext::base::{self, DummyResult};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
+use syntax::parse::token;
+use syntax::ptr::P;
 use syntax::symbol::Symbol;
 use syntax::tokenstream::TokenStream;
 
 use std::string::String;
 
+#[cfg(test)]
+mod tests;
+
 pub fn expand_concat(
     cx: &mut base::ExtCtxt<'_>,
     sp: syntax_pos::Span,
     tts: TokenStream,
 ) -> Box<dyn base::MacResult + 'static> {
-    let es = match base::get_exprs_from_tts(cx, sp, tts) {
-        Some(e) => e,
+    let es = match base::try_get_exprs_from_tts(cx, sp, tts) {
+        Ok(e) => e,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
+    };
+    let accumulator = match concat_exprs(cx, &es) {
+        Some(accumulator) => accumulator,
         None => return DummyResult::any(sp),
     };
+    let sp = cx.with_legacy_ctxt(sp);
+    if cx.parse_sess.literal_too_long(sp, accumulator.len(), token::Str) {
+        return DummyResult::any(sp);
+    }
+    base::MacEager::expr(cx.expr_str(sp, Symbol::intern(&accumulator)))
+}
+
+/// Concatenates the literals in `exprs`, or reports why they can't be and returns `None`.
+fn concat_exprs(diag: &mut dyn ExpansionDiagnostics, exprs: &[P<ast::Expr>]) -> Option<String> {
     let mut accumulator = String::new();
     let mut missing_literal = vec![];
     let mut has_errors = false;
-    for e in es {
+    for e in exprs {
         match e.node {
             ast::ExprKind::Lit(ref lit) => match lit.node {
                 ast::LitKind::Str(ref s, _)
@@ -37,7 +56,7 @@ pub fn expand_concat(
                     accumulator.push_str(&b.to_string());
                 }
                 ast::LitKind::Byte(..) | ast::LitKind::ByteStr(..) => {
-                    cx.span_err(e.span, "cannot concatenate a byte string literal");
+                    diag.span_err(e.span.into(), "cannot concatenate a byte string literal");
                 }
                 ast::LitKind::Err(_) => {
                     has_errors = true;
@@ -52,13 +71,12 @@ pub fn expand_concat(
         }
     }
     if missing_literal.len() > 0 {
-        let mut err = cx.struct_span_err(missing_literal, "expected a literal");
-        err.note("only literals (like `\"foo\"`, `42` and `3.14`) can be passed to `concat!()`");
-        err.emit();
-        return DummyResult::any(sp);
+        diag.span_err_with_notes(missing_literal.into(), "expected a literal", &[
+            "only literals (like `\"foo\"`, `42` and `3.14`) can be passed to `concat!()`",
+        ]);
+        return None;
     } else if has_errors {
-        return DummyResult::any(sp);
+        return None;
     }
-    let sp = cx.with_legacy_ctxt(sp);
-    base::MacEager::expr(cx.expr_str(sp, Symbol::intern(&accumulator)))
+    Some(accumulator)
 }
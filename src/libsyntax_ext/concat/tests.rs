@@ -0,0 +1,61 @@
+use super::concat_exprs;
+
+use syntax::ast::{self, LitKind, StrStyle};
+use syntax::ext::diagnostics::RecordedDiagnostics;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax::with_default_globals;
+use syntax::ThinVec;
+use syntax_pos::DUMMY_SP;
+
+fn expr(node: ast::ExprKind) -> P<ast::Expr> {
+    P(ast::Expr { id: ast::DUMMY_NODE_ID, node, span: DUMMY_SP, attrs: ThinVec::new() })
+}
+
+fn lit(kind: LitKind) -> P<ast::Expr> {
+    expr(ast::ExprKind::Lit(ast::Lit::from_lit_kind(kind, DUMMY_SP)))
+}
+
+#[test]
+fn concatenates_literals() {
+    with_default_globals(|| {
+        let exprs = vec![
+            lit(LitKind::Str(Symbol::intern("a"), StrStyle::Cooked)),
+            lit(LitKind::Char('b')),
+            lit(LitKind::Int(1, ast::LitIntType::Unsuffixed)),
+            lit(LitKind::Bool(true)),
+        ];
+        let mut diag = RecordedDiagnostics::default();
+        assert_eq!(concat_exprs(&mut diag, &exprs), Some("ab1true".to_string()));
+        assert!(diag.diagnostics.is_empty());
+    })
+}
+
+#[test]
+fn reports_non_literals() {
+    with_default_globals(|| {
+        let exprs = vec![
+            lit(LitKind::Str(Symbol::intern("a"), StrStyle::Cooked)),
+            expr(ast::ExprKind::Tup(Vec::new())),
+            lit(LitKind::Byte(b'b')),
+        ];
+        let mut diag = RecordedDiagnostics::default();
+        assert_eq!(concat_exprs(&mut diag, &exprs), None);
+        assert!(diag.has_errors());
+        assert_eq!(
+            diag.messages(),
+            ["cannot concatenate a byte string literal", "expected a literal"],
+        );
+        assert_eq!(diag.diagnostics[1].notes.len(), 1);
+    })
+}
+
+#[test]
+fn error_literals_are_not_reported_again() {
+    with_default_globals(|| {
+        let exprs = vec![lit(LitKind::Err(Symbol::intern("'")))];
+        let mut diag = RecordedDiagnostics::default();
+        assert_eq!(concat_exprs(&mut diag, &exprs), None);
+        assert!(diag.diagnostics.is_empty());
+    })
+}
@@ -2,42 +2,24 @@ use rustc_data_structures::thin_vec::ThinVec;
 
 use syntax::ast;
 use syntax::ext::base::{self, *};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
 use syntax::parse::token::{self, Token};
 use syntax::ptr::P;
 use syntax_pos::Span;
 use syntax_pos::symbol::Symbol;
 use syntax::tokenstream::{TokenTree, TokenStream};
 
+#[cfg(test)]
+mod tests;
+
 pub fn expand_concat_idents<'cx>(cx: &'cx mut ExtCtxt<'_>,
                                  sp: Span,
                                  tts: TokenStream)
                                  -> Box<dyn base::MacResult + 'cx> {
-    if tts.is_empty() {
-        cx.span_err(sp, "concat_idents! takes 1 or more arguments.");
-        return DummyResult::any(sp);
-    }
-
-    let mut res_str = String::new();
-    for (i, e) in tts.into_trees().enumerate() {
-        if i & 1 == 1 {
-            match e {
-                TokenTree::Token(Token { kind: token::Comma, .. }) => {}
-                _ => {
-                    cx.span_err(sp, "concat_idents! expecting comma.");
-                    return DummyResult::any(sp);
-                }
-            }
-        } else {
-            match e {
-                TokenTree::Token(Token { kind: token::Ident(name, _), .. }) =>
-                    res_str.push_str(&name.as_str()),
-                _ => {
-                    cx.span_err(sp, "concat_idents! requires ident args.");
-                    return DummyResult::any(sp);
-                }
-            }
-        }
-    }
+    let res_str = match concat_ident_args(cx, sp, tts) {
+        Some(res_str) => res_str,
+        None => return DummyResult::any(sp),
+    };
 
     let ident = ast::Ident::new(Symbol::intern(&res_str), cx.with_legacy_ctxt(sp));
 
@@ -64,3 +46,38 @@ pub fn expand_concat_idents<'cx>(cx: &'cx mut ExtCtxt<'_>,
 
     Box::new(ConcatIdentsResult { ident })
 }
+
+/// Concatenates the comma-separated identifiers in `tts`, or reports why they can't be and
+/// returns `None`.
+fn concat_ident_args(diag: &mut dyn ExpansionDiagnostics,
+                     sp: Span,
+                     tts: TokenStream)
+                     -> Option<String> {
+    if tts.is_empty() {
+        diag.span_err(sp.into(), "concat_idents! takes 1 or more arguments.");
+        return None;
+    }
+
+    let mut res_str = String::new();
+    for (i, e) in tts.into_trees().enumerate() {
+        if i & 1 == 1 {
+            match e {
+                TokenTree::Token(Token { kind: token::Comma, .. }) => {}
+                _ => {
+                    diag.span_err(sp.into(), "concat_idents! expecting comma.");
+                    return None;
+                }
+            }
+        } else {
+            match e {
+                TokenTree::Token(Token { kind: token::Ident(name, _), .. }) =>
+                    res_str.push_str(&name.as_str()),
+                _ => {
+                    diag.span_err(sp.into(), "concat_idents! requires ident args.");
+                    return None;
+                }
+            }
+        }
+    }
+    Some(res_str)
+}
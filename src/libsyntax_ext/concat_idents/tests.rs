@@ -0,0 +1,42 @@
+use super::concat_ident_args;
+
+use syntax::ext::diagnostics::RecordedDiagnostics;
+use syntax::parse::token::{self, TokenKind};
+use syntax::symbol::Symbol;
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax::with_default_globals;
+use syntax_pos::DUMMY_SP;
+
+fn ident(name: &str) -> TokenKind {
+    token::Ident(Symbol::intern(name), false)
+}
+
+fn stream(kinds: Vec<TokenKind>) -> TokenStream {
+    kinds.into_iter().map(|kind| TokenTree::token(kind, DUMMY_SP)).collect()
+}
+
+#[test]
+fn concatenates_identifiers() {
+    with_default_globals(|| {
+        let tts = stream(vec![ident("foo"), token::Comma, ident("bar"), token::Comma]);
+        let mut diag = RecordedDiagnostics::default();
+        assert_eq!(concat_ident_args(&mut diag, DUMMY_SP, tts), Some("foobar".to_string()));
+        assert!(diag.diagnostics.is_empty());
+    })
+}
+
+#[test]
+fn reports_malformed_arguments() {
+    with_default_globals(|| {
+        let cases = vec![
+            (vec![], "concat_idents! takes 1 or more arguments."),
+            (vec![ident("foo"), ident("bar")], "concat_idents! expecting comma."),
+            (vec![ident("foo"), token::Comma, token::Comma], "concat_idents! requires ident args."),
+        ];
+        for (kinds, message) in cases {
+            let mut diag = RecordedDiagnostics::default();
+            assert_eq!(concat_ident_args(&mut diag, DUMMY_SP, stream(kinds)), None);
+            assert_eq!(diag.messages(), [message]);
+        }
+    })
+}
@@ -5,23 +5,22 @@
 
 use syntax::ast::{self, Ident, GenericArg};
 use syntax::ext::base::{self, *};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
 use syntax::symbol::{kw, sym, Symbol};
 use syntax_pos::Span;
 use syntax::tokenstream::TokenStream;
 
-use std::env;
-
 pub fn expand_option_env<'cx>(cx: &'cx mut ExtCtxt<'_>,
                               sp: Span,
                               tts: TokenStream)
                               -> Box<dyn base::MacResult + 'cx> {
-    let var = match get_single_str_from_tts(cx, sp, tts, "option_env!") {
-        None => return DummyResult::any(sp),
-        Some(v) => v,
+    let var = match try_get_single_str_from_tts(cx, sp, tts, "option_env!") {
+        Ok(v) => v,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
 
     let sp = cx.with_legacy_ctxt(sp);
-    let e = match env::var(&*var.as_str()) {
+    let e = match cx.tracked_env_var(&var.as_str(), sp) {
         Err(..) => {
             let lt = cx.lifetime(sp, Ident::with_dummy_span(kw::StaticLifetime));
             cx.expr_path(cx.path_all(sp,
@@ -47,13 +46,13 @@ pub fn expand_env<'cx>(cx: &'cx mut ExtCtxt<'_>,
                        sp: Span,
                        tts: TokenStream)
                        -> Box<dyn base::MacResult + 'cx> {
-    let mut exprs = match get_exprs_from_tts(cx, sp, tts) {
-        Some(ref exprs) if exprs.is_empty() => {
-            cx.span_err(sp, "env! takes 1 or 2 arguments");
+    let mut exprs = match try_get_exprs_from_tts(cx, sp, tts) {
+        Ok(ref exprs) if exprs.is_empty() => {
+            ExpansionDiagnostics::span_err(cx, sp.into(), "env! takes 1 or 2 arguments");
             return DummyResult::any(sp);
         }
-        None => return DummyResult::any(sp),
-        Some(exprs) => exprs.into_iter(),
+        Ok(exprs) => exprs.into_iter(),
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
 
     let var = match expr_to_string(cx, exprs.next().unwrap(), "expected string literal") {
@@ -71,13 +70,13 @@ pub fn expand_env<'cx>(cx: &'cx mut ExtCtxt<'_>,
     };
 
     if exprs.next().is_some() {
-        cx.span_err(sp, "env! takes 1 or 2 arguments");
+        ExpansionDiagnostics::span_err(cx, sp.into(), "env! takes 1 or 2 arguments");
         return DummyResult::any(sp);
     }
 
-    let e = match env::var(&*var.as_str()) {
+    let e = match cx.tracked_env_var(&var.as_str(), sp) {
         Err(_) => {
-            cx.span_err(sp, &msg.as_str());
+            ExpansionDiagnostics::span_err(cx, sp.into(), &msg.as_str());
             return DummyResult::any(sp);
         }
         Ok(s) => cx.expr_str(sp, Symbol::intern(&s)),
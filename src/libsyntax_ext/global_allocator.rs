@@ -1,10 +1,10 @@
-use syntax::ast::{ItemKind, Mutability, Stmt, Ty, TyKind, Unsafety};
+use syntax::ast::{ItemKind, Mutability, Ty, TyKind, Unsafety};
 use syntax::ast::{self, Param, Attribute, Expr, FnHeader, Generics, Ident};
 use syntax::attr::check_builtin_macro_attribute;
 use syntax::ext::allocator::{AllocatorKind, AllocatorMethod, AllocatorTy, ALLOCATOR_METHODS};
 use syntax::ext::base::{Annotatable, ExtCtxt};
 use syntax::ptr::P;
-use syntax::symbol::{kw, sym, Symbol};
+use syntax::symbol::{sym, Symbol};
 use syntax_pos::Span;
 
 pub fn expand(
@@ -36,14 +36,9 @@ pub fn expand(
         cx: ecx,
     };
 
-    // Generate item statements for the allocator methods.
-    let stmts = ALLOCATOR_METHODS.iter().map(|method| f.allocator_fn(method)).collect();
-
-    // Generate anonymous constant serving as container for the allocator methods.
-    let const_ty = ecx.ty(span, TyKind::Tup(Vec::new()));
-    let const_body = ecx.expr_block(ecx.block(span, stmts));
-    let const_item =
-        ecx.item_const(span, Ident::with_dummy_span(kw::Underscore), const_ty, const_body);
+    // Generate the allocator methods, in an anonymous constant serving as their container.
+    let items = ALLOCATOR_METHODS.iter().map(|method| f.allocator_fn(method)).collect();
+    let const_item = ecx.anon_const_scope(span, items);
 
     // Return the original item and the new methods.
     vec![Annotatable::Item(item), Annotatable::Item(const_item)]
@@ -57,7 +52,7 @@ struct AllocFnFactory<'a, 'b> {
 }
 
 impl AllocFnFactory<'_, '_> {
-    fn allocator_fn(&self, method: &AllocatorMethod) -> Stmt {
+    fn allocator_fn(&self, method: &AllocatorMethod) -> P<ast::Item> {
         let mut abi_args = Vec::new();
         let mut i = 0;
         let ref mut mk = || {
@@ -81,13 +76,12 @@ impl AllocFnFactory<'_, '_> {
             Generics::default(),
             self.cx.block_expr(output_expr),
         );
-        let item = self.cx.item(
+        self.cx.item(
             self.span,
             Ident::from_str(&self.kind.fn_name(method.name)),
             self.attrs(),
             kind,
-        );
-        self.cx.stmt_item(self.span, item)
+        )
     }
 
     fn call_allocator(&self, method: &str, mut args: Vec<P<Expr>>) -> P<Expr> {
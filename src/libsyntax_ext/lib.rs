@@ -17,7 +17,7 @@ use crate::deriving::*;
 
 use syntax::ast::Ident;
 use syntax::edition::Edition;
-use syntax::ext::base::{SyntaxExtension, SyntaxExtensionKind, MacroExpanderFn};
+use syntax::ext::base::{MacroExpanderFn, MacroRegistry, SyntaxExtension, SyntaxExtensionKind};
 use syntax::ext::proc_macro::BangProcMacro;
 use syntax::symbol::sym;
 
@@ -82,6 +82,8 @@ pub fn register_builtin_macros(resolver: &mut dyn syntax::ext::base::Resolver, e
         module_path: source_util::expand_mod,
         option_env: env::expand_option_env,
         stringify: source_util::expand_stringify,
+        stringify_source: source_util::expand_stringify_source,
+        stringify_tokens: source_util::expand_stringify,
         trace_macros: trace_macros::expand_trace_macros,
     }
 
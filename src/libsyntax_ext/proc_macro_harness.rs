@@ -389,11 +389,10 @@ fn mk_decls(
             None, ast::Mutability::Immutable),
         ast::Mutability::Immutable,
         cx.expr_vec_slice(span, decls),
-    ).map(|mut i| {
+    ).map_in_place(|i| {
         let attr = cx.meta_word(span, sym::rustc_proc_macro_decls);
         i.attrs.push(cx.attribute(attr));
         i.vis = respan(span, ast::VisibilityKind::Public);
-        i
     });
 
     let module = cx.item_mod(
@@ -402,10 +401,7 @@ fn mk_decls(
         ast::Ident::from_str("decls").gensym(),
         vec![doc_hidden],
         vec![krate, decls_static],
-    ).map(|mut i| {
-        i.vis = respan(span, ast::VisibilityKind::Public);
-        i
-    });
+    ).map_in_place(|i| i.vis = respan(span, ast::VisibilityKind::Public));
 
     // Integrate the new module into existing module structures.
     let module = AstFragment::Items(smallvec![module]);
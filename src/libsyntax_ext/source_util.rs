@@ -1,5 +1,6 @@
 use syntax::{ast, panictry};
 use syntax::ext::base::{self, *};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
 use syntax::parse::{self, token, DirectoryOwnership};
 use syntax::print::pprust;
 use syntax::ptr::P;
@@ -9,8 +10,6 @@ use syntax::tokenstream::TokenStream;
 use smallvec::SmallVec;
 use syntax_pos::{self, Pos, Span};
 
-use rustc_data_structures::sync::Lrc;
-
 // These macros all relate to the file system; they either return
 // the column/row/filename of the expression, or they include
 // a given file into the current one.
@@ -55,6 +54,13 @@ pub fn expand_stringify(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
     base::MacEager::expr(cx.expr_str(sp, Symbol::intern(&s)))
 }
 
+/// stringify_source!(): the source text of the tokens, see `pprust::tts_to_source_string`
+pub fn expand_stringify_source(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
+                               -> Box<dyn base::MacResult+'static> {
+    let s = pprust::tts_to_source_string(cx.source_map(), tts);
+    base::MacEager::expr(cx.expr_str(sp, Symbol::intern(&s)))
+}
+
 pub fn expand_mod(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
                   -> Box<dyn base::MacResult+'static> {
     base::check_zero_tts(cx, sp, tts, "module_path!");
@@ -69,12 +75,13 @@ pub fn expand_mod(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
 /// unhygienically.
 pub fn expand_include<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
                            -> Box<dyn base::MacResult+'cx> {
-    let file = match get_single_str_from_tts(cx, sp, tts, "include!") {
-        Some(f) => f,
-        None => return DummyResult::any(sp),
+    let file = match try_get_single_str_from_tts(cx, sp, tts, "include!") {
+        Ok(f) => f,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
     // The file will be added to the code map by the parser
     let file = cx.resolve_path(file, sp);
+    cx.track_file(&file, sp);
     let directory_ownership = DirectoryOwnership::Owned { relative: None };
     let p = parse::new_sub_parser_from_file(cx.parse_sess(), &file, directory_ownership, None, sp);
 
@@ -107,11 +114,12 @@ pub fn expand_include<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
 // include_str! : read the given file, insert it as a literal string expr
 pub fn expand_include_str(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
                           -> Box<dyn base::MacResult+'static> {
-    let file = match get_single_str_from_tts(cx, sp, tts, "include_str!") {
-        Some(f) => f,
-        None => return DummyResult::any(sp)
+    let file = match try_get_single_str_from_tts(cx, sp, tts, "include_str!") {
+        Ok(f) => f,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
     let file = cx.resolve_path(file, sp);
+    cx.track_file(&file, sp);
     match cx.source_map().load_binary_file(&file) {
         Ok(bytes) => match std::str::from_utf8(&bytes) {
             Ok(src) => {
@@ -119,12 +127,14 @@ pub fn expand_include_str(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
                 base::MacEager::expr(cx.expr_str(sp, interned_src))
             }
             Err(_) => {
-                cx.span_err(sp, &format!("{} wasn't a utf-8 file", file.display()));
+                let msg = format!("{} wasn't a utf-8 file", file.display());
+                ExpansionDiagnostics::span_err(cx, sp.into(), &msg);
                 DummyResult::any(sp)
             }
         },
         Err(e) => {
-            cx.span_err(sp, &format!("couldn't read {}: {}", file.display(), e));
+            let msg = format!("couldn't read {}: {}", file.display(), e);
+            ExpansionDiagnostics::span_err(cx, sp.into(), &msg);
             DummyResult::any(sp)
         }
     }
@@ -132,17 +142,20 @@ pub fn expand_include_str(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
 
 pub fn expand_include_bytes(cx: &mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
                             -> Box<dyn base::MacResult+'static> {
-    let file = match get_single_str_from_tts(cx, sp, tts, "include_bytes!") {
-        Some(f) => f,
-        None => return DummyResult::any(sp)
+    let file = match try_get_single_str_from_tts(cx, sp, tts, "include_bytes!") {
+        Ok(f) => f,
+        Err(err) => return DummyResult::from_arg_error(err, sp),
     };
     let file = cx.resolve_path(file, sp);
-    match cx.source_map().load_binary_file(&file) {
+    cx.track_file(&file, sp);
+    match cx.source_map().load_byte_blob(&file) {
         Ok(bytes) => {
-            base::MacEager::expr(cx.expr_lit(sp, ast::LitKind::ByteStr(Lrc::new(bytes))))
+            let lit = ast::Lit::from_byte_blob(bytes, sp);
+            base::MacEager::expr(cx.expr(sp, ast::ExprKind::Lit(lit)))
         },
         Err(e) => {
-            cx.span_err(sp, &format!("couldn't read {}: {}", file.display(), e));
+            let msg = format!("couldn't read {}: {}", file.display(), e);
+            ExpansionDiagnostics::span_err(cx, sp.into(), &msg);
             DummyResult::any(sp)
         }
     }
@@ -8,30 +8,78 @@ use syntax_pos::DUMMY_SP;
 
 use std::iter;
 
+#[cfg(test)]
+mod tests;
+
+/// Configures the crates and imports injected into the crate root by `inject_with_config`.
+pub struct InjectionConfig {
+    pub edition: Edition,
+    /// The crates to inject `extern crate` items for, the first one is the one with the prelude.
+    /// If unset, `std` is injected, or `core` and `compiler_builtins` for `#![no_std]` crates.
+    /// Nothing is injected into `#![no_core]` crates either way.
+    pub crates: Option<Vec<Symbol>>,
+    /// The name of the crate loaded in place of each injected crate, see `-Z alt-std-name`.
+    pub alt_std_name: Option<Symbol>,
+    /// The path of the module glob imported as the prelude, relative to the crate with the
+    /// prelude. If unset, it's `prelude::v1`.
+    pub prelude_path: Option<Vec<Symbol>>,
+    /// Paths imported into the crate root next to the prelude, e.g., `[alloc, vec, Vec]` for
+    /// `use ::alloc::vec::Vec;`.
+    pub extra_imports: Vec<Vec<Symbol>>,
+}
+
+impl InjectionConfig {
+    /// The configuration injecting what rustc does by default.
+    pub fn new(edition: Edition) -> InjectionConfig {
+        InjectionConfig {
+            edition,
+            crates: None,
+            alt_std_name: None,
+            prelude_path: None,
+            extra_imports: Vec::new(),
+        }
+    }
+}
+
 pub fn inject(
-    mut krate: ast::Crate, alt_std_name: Option<&str>, edition: Edition
+    krate: ast::Crate, alt_std_name: Option<&str>, edition: Edition
+) -> (ast::Crate, Option<Symbol>) {
+    let config = InjectionConfig {
+        alt_std_name: alt_std_name.map(Symbol::intern),
+        ..InjectionConfig::new(edition)
+    };
+    inject_with_config(krate, &config)
+}
+
+/// Injects the `extern crate` items and the prelude import described by `config` into `krate`,
+/// returning the name of the crate with the prelude, if any.
+pub fn inject_with_config(
+    mut krate: ast::Crate, config: &InjectionConfig
 ) -> (ast::Crate, Option<Symbol>) {
-    let rust_2018 = edition >= Edition::Edition2018;
+    let rust_2018 = config.edition >= Edition::Edition2018;
 
     // the first name in this list is the crate name of the crate with the prelude
-    let names: &[&str] = if attr::contains_name(&krate.attrs, sym::no_core) {
+    let names: Vec<Symbol> = if attr::contains_name(&krate.attrs, sym::no_core) {
         return (krate, None);
+    } else if let Some(ref crates) = config.crates {
+        if crates.is_empty() {
+            return (krate, None);
+        }
+        crates.clone()
     } else if attr::contains_name(&krate.attrs, sym::no_std) {
         if attr::contains_name(&krate.attrs, sym::compiler_builtins) {
-            &["core"]
+            vec![sym::core]
         } else {
-            &["core", "compiler_builtins"]
+            vec![sym::core, sym::compiler_builtins]
         }
     } else {
-        &["std"]
+        vec![sym::std]
     };
 
     // .rev() to preserve ordering above in combination with insert(0, ...)
-    let alt_std_name = alt_std_name.map(Symbol::intern);
-    for orig_name_str in names.iter().rev() {
+    for &orig_name_sym in names.iter().rev() {
         // HACK(eddyb) gensym the injected crates on the Rust 2018 edition,
         // so they don't accidentally interfere with the new import paths.
-        let orig_name_sym = Symbol::intern(orig_name_str);
         let orig_name_ident = Ident::with_dummy_span(orig_name_sym);
         let (rename, orig_name) = if rust_2018 {
             (orig_name_ident.gensym(), Some(orig_name_sym))
@@ -43,7 +91,7 @@ pub fn inject(
                 attr::mk_word_item(ast::Ident::with_dummy_span(sym::macro_use))
             )],
             vis: dummy_spanned(ast::VisibilityKind::Inherited),
-            node: ast::ItemKind::ExternCrate(alt_std_name.or(orig_name)),
+            node: ast::ItemKind::ExternCrate(config.alt_std_name.or(orig_name)),
             ident: rename,
             id: ast::DUMMY_NODE_ID,
             span: DUMMY_SP,
@@ -56,31 +104,45 @@ pub fn inject(
     let name = names[0];
 
     let span = DUMMY_SP.fresh_expansion(ExpnData::allow_unstable(
-        ExpnKind::Macro(MacroKind::Attr, sym::std_inject), DUMMY_SP, edition,
+        ExpnKind::Macro(MacroKind::Attr, sym::std_inject), DUMMY_SP, config.edition,
         [sym::prelude_import][..].into(),
     ));
 
-    krate.module.items.insert(0, P(ast::Item {
-        attrs: vec![attr::mk_attr_outer(
+    let default_prelude_path = [sym::prelude, sym::v1];
+    let prelude_path = config.prelude_path.as_ref()
+        .map_or(&default_prelude_path[..], |path| &path[..]);
+    let mut imports = vec![(
+        iter::once(name).chain(prelude_path.iter().cloned()).collect::<Vec<_>>(),
+        ast::UseTreeKind::Glob,
+        vec![attr::mk_attr_outer(
             attr::mk_word_item(ast::Ident::new(sym::prelude_import, span)))],
-        vis: respan(span.shrink_to_lo(), ast::VisibilityKind::Inherited),
-        node: ast::ItemKind::Use(P(ast::UseTree {
-            prefix: ast::Path {
-                segments: iter::once(ast::Ident::with_dummy_span(kw::PathRoot))
-                    .chain(
-                        [name, "prelude", "v1"].iter().cloned()
-                            .map(ast::Ident::from_str)
-                    ).map(ast::PathSegment::from_ident).collect(),
+    )];
+    imports.extend(config.extra_imports.iter().map(|path| {
+        (path.clone(), ast::UseTreeKind::Simple(None, ast::DUMMY_NODE_ID, ast::DUMMY_NODE_ID),
+         Vec::new())
+    }));
+
+    // .rev() to keep the prelude import first in combination with insert(0, ...)
+    for (path, kind, attrs) in imports.into_iter().rev() {
+        krate.module.items.insert(0, P(ast::Item {
+            attrs,
+            vis: respan(span.shrink_to_lo(), ast::VisibilityKind::Inherited),
+            node: ast::ItemKind::Use(P(ast::UseTree {
+                prefix: ast::Path {
+                    segments: iter::once(ast::Ident::with_dummy_span(kw::PathRoot))
+                        .chain(path.into_iter().map(ast::Ident::with_dummy_span))
+                        .map(ast::PathSegment::from_ident).collect(),
+                    span,
+                },
+                kind,
                 span,
-            },
-            kind: ast::UseTreeKind::Glob,
+            })),
+            id: ast::DUMMY_NODE_ID,
+            ident: ast::Ident::invalid(),
             span,
-        })),
-        id: ast::DUMMY_NODE_ID,
-        ident: ast::Ident::invalid(),
-        span,
-        tokens: None,
-    }));
+            tokens: None,
+        }));
+    }
 
-    (krate, Some(Symbol::intern(name)))
+    (krate, Some(name))
 }
@@ -0,0 +1,70 @@
+use super::*;
+
+use syntax::parse::{self, ParseSess};
+use syntax::print::pprust;
+use syntax::source_map::FilePathMapping;
+use syntax::with_default_globals;
+
+use std::path::PathBuf;
+
+/// The items of `source` after injecting what `config` describes, without their attributes.
+fn inject_into(source: &str, config: &InjectionConfig) -> (Vec<String>, Option<Symbol>) {
+    let sess = ParseSess::new(FilePathMapping::empty());
+    let name = PathBuf::from("test").into();
+    let krate = parse::parse_crate_from_source_str(name, source.to_string(), &sess).unwrap();
+    let (krate, name) = inject_with_config(krate, config);
+    let items = krate.module.items.iter().map(|item| {
+        let item = pprust::item_to_string(item);
+        item.lines().last().unwrap().to_string()
+    }).collect();
+    (items, name)
+}
+
+#[test]
+fn default_injection() {
+    with_default_globals(|| {
+        let config = InjectionConfig::new(Edition::Edition2015);
+        let (items, name) = inject_into("fn f() {}", &config);
+        assert_eq!(items, ["use ::std::prelude::v1::*;", "extern crate std;", "fn f() { }"]);
+        assert_eq!(name, Some(sym::std));
+
+        let (items, name) = inject_into("#![no_std] fn f() {}", &config);
+        assert_eq!(items, [
+            "use ::core::prelude::v1::*;",
+            "extern crate core;",
+            "extern crate compiler_builtins;",
+            "fn f() { }",
+        ]);
+        assert_eq!(name, Some(sym::core));
+    })
+}
+
+#[test]
+fn configured_injection() {
+    with_default_globals(|| {
+        let config = InjectionConfig {
+            crates: Some(vec![Symbol::intern("my_core"), sym::alloc]),
+            prelude_path: Some(vec![sym::prelude]),
+            extra_imports: vec![vec![sym::alloc, sym::vec, sym::Vec]],
+            ..InjectionConfig::new(Edition::Edition2015)
+        };
+        let (items, name) = inject_into("fn f() {}", &config);
+        assert_eq!(items, [
+            "use ::my_core::prelude::*;",
+            "use ::alloc::vec::Vec;",
+            "extern crate my_core;",
+            "extern crate alloc;",
+            "fn f() { }",
+        ]);
+        assert_eq!(name, Some(Symbol::intern("my_core")));
+
+        let config = InjectionConfig {
+            crates: Some(Vec::new()),
+            ..InjectionConfig::new(Edition::Edition2015)
+        };
+        assert_eq!(inject_into("fn f() {}", &config), (vec!["fn f() { }".to_string()], None));
+        let config = InjectionConfig::new(Edition::Edition2015);
+        let (items, name) = inject_into("#![no_core] fn f() {}", &config);
+        assert_eq!((items.len(), name), (1, None));
+    })
+}
@@ -30,13 +30,12 @@ pub fn expand_test_case(
 
     let sp = ecx.with_legacy_ctxt(attr_sp);
     let mut item = anno_item.expect_item();
-    item = item.map(|mut item| {
+    item = item.map_in_place(|item| {
         item.vis = respan(item.vis.span, ast::VisibilityKind::Public);
         item.ident = item.ident.gensym();
         item.attrs.push(
             ecx.attribute(ecx.meta_word(sp, sym::rustc_test_marker))
         );
-        item
     });
 
     return vec![Annotatable::Item(item)]
@@ -71,12 +70,10 @@ pub fn expand_test_or_bench(
     // If we're not in test configuration, remove the annotated item
     if !cx.ecfg.should_test { return vec![]; }
 
-    let item =
-        if let Annotatable::Item(i) = item { i }
-        else {
-            cx.parse_sess.span_diagnostic.span_fatal(item.span(),
-                "`#[test]` attribute is only allowed on non associated functions").raise();
-        };
+    let item = item.try_into_item().unwrap_or_else(|item| {
+        cx.parse_sess.span_diagnostic.span_fatal(item.span(),
+            "`#[test]` attribute is only allowed on non associated functions").raise();
+    });
 
     if let ast::ItemKind::Mac(_) = item.node {
         cx.parse_sess.span_diagnostic.span_warn(item.span,
@@ -190,7 +187,7 @@ pub fn expand_test_or_bench(
             ])
         // }
         ));
-    test_const = test_const.map(|mut tc| { tc.vis.node = ast::VisibilityKind::Public; tc});
+    test_const = test_const.map_in_place(|tc| tc.vis.node = ast::VisibilityKind::Public);
 
     // extern crate test as test_gensym
     let test_extern = cx.item(sp,
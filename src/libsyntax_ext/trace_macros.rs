@@ -1,28 +1,69 @@
-use syntax::ext::base::{self, ExtCtxt};
+use syntax::ext::base::{self, ExtCtxt, TraceFilter};
+use syntax::ext::diagnostics::ExpansionDiagnostics;
+use syntax::parse::token::{self, Token};
 use syntax::symbol::kw;
 use syntax_pos::Span;
 use syntax::tokenstream::{TokenTree, TokenStream};
 
+#[cfg(test)]
+mod tests;
+
 pub fn expand_trace_macros(cx: &mut ExtCtxt<'_>,
                            sp: Span,
                            tt: TokenStream)
                            -> Box<dyn base::MacResult + 'static> {
-    let mut cursor = tt.into_trees();
-    let mut err = false;
+    if let Some(TokenTree::Token(Token { kind: token::Literal(lit), .. })) = tt.trees().next() {
+        if lit.kind == token::Str {
+            return expand_trace_filters(cx, sp, tt);
+        }
+    }
+    match trace_macros_flag(cx, sp, &tt) {
+        Some(value) => {
+            cx.set_trace_macros(value);
+            base::DummyResult::any_valid(sp)
+        }
+        None => base::DummyResult::any(sp),
+    }
+}
+
+/// The flag of `trace_macros!(true)` or `trace_macros!(false)`, or `None` if `tt` is neither,
+/// which is reported.
+fn trace_macros_flag(diag: &mut dyn ExpansionDiagnostics,
+                     sp: Span,
+                     tt: &TokenStream)
+                     -> Option<bool> {
+    let mut cursor = tt.trees();
     let value = match &cursor.next() {
-        Some(TokenTree::Token(token)) if token.is_keyword(kw::True) => true,
-        Some(TokenTree::Token(token)) if token.is_keyword(kw::False) => false,
+        Some(TokenTree::Token(token)) if token.is_keyword(kw::True) => Some(true),
+        Some(TokenTree::Token(token)) if token.is_keyword(kw::False) => Some(false),
+        _ => None,
+    };
+    match value {
+        Some(value) if cursor.next().is_none() => Some(value),
         _ => {
-            err = true;
-            false
-        },
+            diag.span_err(sp.into(), "trace_macros! accepts only `true` or `false`");
+            None
+        }
+    }
+}
+
+/// `trace_macros!("serde::*", "my_macro")`: enables tracing for the matching macros only.
+fn expand_trace_filters(cx: &mut ExtCtxt<'_>,
+                        sp: Span,
+                        tt: TokenStream)
+                        -> Box<dyn base::MacResult + 'static> {
+    let exprs = match base::try_get_exprs_from_tts(cx, sp, tt) {
+        Ok(exprs) => exprs,
+        Err(err) => return base::DummyResult::from_arg_error(err, sp),
     };
-    err |= cursor.next().is_some();
-    if err {
-        cx.span_err(sp, "trace_macros! accepts only `true` or `false`")
-    } else {
-        cx.set_trace_macros(value);
+    let mut filters = Vec::new();
+    for expr in exprs {
+        match base::expr_to_string(cx, expr, "trace_macros! filters must be string literals") {
+            Some((filter, _)) => filters.push(TraceFilter::parse(&filter.as_str())),
+            None => return base::DummyResult::any(sp),
+        }
     }
 
+    cx.set_trace_filters(filters);
     base::DummyResult::any_valid(sp)
 }
@@ -0,0 +1,63 @@
+use super::{expand_trace_macros, trace_macros_flag};
+
+use syntax::ext::base::{ExtCtxt, MacResult};
+use syntax::ext::basic_resolver::BasicResolver;
+use syntax::ext::diagnostics::RecordedDiagnostics;
+use syntax::ext::expand::{AstFragment, ExpansionConfig};
+use syntax::feature_gate::Features;
+use syntax::parse::ParseSess;
+use syntax::parse::token::{self, TokenKind};
+use syntax::source_map::FilePathMapping;
+use syntax::symbol::{kw, Symbol};
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax::with_default_globals;
+use syntax_pos::DUMMY_SP;
+
+fn stream(kinds: Vec<TokenKind>) -> TokenStream {
+    kinds.into_iter().map(|kind| TokenTree::token(kind, DUMMY_SP)).collect()
+}
+
+#[test]
+fn accepts_true_and_false() {
+    with_default_globals(|| {
+        let mut diag = RecordedDiagnostics::default();
+        let tts = stream(vec![token::Ident(kw::True, false)]);
+        assert_eq!(trace_macros_flag(&mut diag, DUMMY_SP, &tts), Some(true));
+        let tts = stream(vec![token::Ident(kw::False, false)]);
+        assert_eq!(trace_macros_flag(&mut diag, DUMMY_SP, &tts), Some(false));
+        assert!(diag.diagnostics.is_empty());
+    })
+}
+
+#[test]
+fn reports_other_arguments() {
+    with_default_globals(|| {
+        let cases = vec![
+            vec![],
+            vec![token::Ident(Symbol::intern("maybe"), false)],
+            vec![token::Ident(kw::True, false), token::Comma],
+        ];
+        for kinds in cases {
+            let mut diag = RecordedDiagnostics::default();
+            assert_eq!(trace_macros_flag(&mut diag, DUMMY_SP, &stream(kinds)), None);
+            assert_eq!(diag.messages(), ["trace_macros! accepts only `true` or `false`"]);
+        }
+    })
+}
+
+#[test]
+fn invalid_arguments_expand_to_error_nodes() {
+    with_default_globals(|| {
+        let sess = ParseSess::new(FilePathMapping::empty());
+        let features = Features::new();
+        let mut resolver = BasicResolver::new(&sess, &features, sess.edition);
+        let mut cx = ExtCtxt::new(&sess, ExpansionConfig::default("test".into()), &mut resolver);
+
+        let tts = stream(vec![token::Ident(Symbol::intern("maybe"), false)]);
+        let expr = expand_trace_macros(&mut cx, DUMMY_SP, tts).make_expr().unwrap();
+        assert!(AstFragment::Expr(expr).has_errors());
+        let tts = stream(vec![token::Ident(kw::False, false)]);
+        let expr = expand_trace_macros(&mut cx, DUMMY_SP, tts).make_expr().unwrap();
+        assert!(!AstFragment::Expr(expr).has_errors());
+    })
+}
@@ -75,11 +75,26 @@ pub enum Transparency {
     Opaque,
 }
 
+/// IDs allocated by `ExpnId::fresh_stable` lie in `STABLE_EXPN_IDS`, away from the densely
+/// allocated ones. The range is within the range of `NodeId`s, so that placeholder IDs can still
+/// be derived from expansion IDs.
+const STABLE_EXPN_IDS: std::ops::Range<u32> = (1 << 31)..(3 << 30);
+
 impl ExpnId {
     pub fn fresh(expn_data: Option<ExpnData>) -> Self {
         HygieneData::with(|data| data.fresh_expn(expn_data))
     }
 
+    /// Like `fresh`, but the ID is derived from `hash` rather than from the number of expansions
+    /// created so far, so that an invocation identified by the same `hash` gets the same ID no
+    /// matter which other invocations were created before it. If that ID is already taken, the
+    /// next free one is used instead, so when the hashes of two invocations collide, which one
+    /// gets the derived ID and which one the next free ID depends on the order they are created
+    /// in. With 2^30 IDs, that only becomes likely with tens of thousands of invocations.
+    pub fn fresh_stable(hash: u64, expn_data: Option<ExpnData>) -> Self {
+        HygieneData::with(|data| data.fresh_stable_expn(hash, expn_data))
+    }
+
     /// The ID of the theoretical expansion that generates freshly parsed, unexpanded AST.
     #[inline]
     pub fn root() -> Self {
@@ -104,7 +119,7 @@ impl ExpnId {
     #[inline]
     pub fn set_expn_data(self, expn_data: ExpnData) {
         HygieneData::with(|data| {
-            let old_expn_data = &mut data.expn_data[self.0 as usize];
+            let old_expn_data = data.expn_data_slot(self);
             assert!(old_expn_data.is_none(), "expansion data is reset for an expansion ID");
             *old_expn_data = Some(expn_data);
         })
@@ -127,6 +142,8 @@ crate struct HygieneData {
     /// between creation of an expansion ID and obtaining its data (e.g. macros are collected
     /// first and then resolved later), so we use an `Option` here.
     expn_data: Vec<Option<ExpnData>>,
+    /// Expansion data for the IDs allocated by `ExpnId::fresh_stable`.
+    stable_expn_data: FxHashMap<ExpnId, Option<ExpnData>>,
     syntax_context_data: Vec<SyntaxContextData>,
     syntax_context_map: FxHashMap<(SyntaxContext, ExpnId, Transparency), SyntaxContext>,
 }
@@ -135,6 +152,7 @@ impl HygieneData {
     crate fn new(edition: Edition) -> Self {
         HygieneData {
             expn_data: vec![Some(ExpnData::default(ExpnKind::Root, DUMMY_SP, edition))],
+            stable_expn_data: FxHashMap::default(),
             syntax_context_data: vec![SyntaxContextData {
                 outer_expn: ExpnId::root(),
                 outer_transparency: Transparency::Opaque,
@@ -156,9 +174,34 @@ impl HygieneData {
         ExpnId(self.expn_data.len() as u32 - 1)
     }
 
+    /// Probes linearly from the ID derived from `hash`, see `ExpnId::fresh_stable` for what
+    /// that means for colliding hashes.
+    fn fresh_stable_expn(&mut self, hash: u64, expn_data: Option<ExpnData>) -> ExpnId {
+        let len = (STABLE_EXPN_IDS.end - STABLE_EXPN_IDS.start) as u64;
+        let mut offset = hash % len;
+        while self.stable_expn_data.contains_key(&ExpnId(STABLE_EXPN_IDS.start + offset as u32)) {
+            offset = (offset + 1) % len;
+        }
+        let expn_id = ExpnId(STABLE_EXPN_IDS.start + offset as u32);
+        self.stable_expn_data.insert(expn_id, expn_data);
+        expn_id
+    }
+
+    fn expn_data_slot(&mut self, expn_id: ExpnId) -> &mut Option<ExpnData> {
+        if STABLE_EXPN_IDS.contains(&expn_id.0) {
+            self.stable_expn_data.get_mut(&expn_id).expect("unknown stable expansion ID")
+        } else {
+            &mut self.expn_data[expn_id.0 as usize]
+        }
+    }
+
     fn expn_data(&self, expn_id: ExpnId) -> &ExpnData {
-        self.expn_data[expn_id.0 as usize].as_ref()
-            .expect("no expansion data for an expansion ID")
+        let expn_data = if STABLE_EXPN_IDS.contains(&expn_id.0) {
+            &self.stable_expn_data[&expn_id]
+        } else {
+            &self.expn_data[expn_id.0 as usize]
+        };
+        expn_data.as_ref().expect("no expansion data for an expansion ID")
     }
 
     fn is_descendant_of(&self, mut expn_id: ExpnId, ancestor: ExpnId) -> bool {
@@ -326,6 +369,34 @@ pub fn walk_chain(span: Span, to: SyntaxContext) -> Span {
     HygieneData::with(|data| data.walk_chain(span, to))
 }
 
+/// Renders the syntax context of `span` for debugging hygiene, with one line per mark from the
+/// outermost to the innermost one. Spans are rendered with their `Debug` implementation, see
+/// `debug_hygiene_with` to render them differently.
+pub fn debug_hygiene(span: Span) -> String {
+    debug_hygiene_with(span, |span| format!("{:?}", span))
+}
+
+/// Like `debug_hygiene`, but renders the call and definition sites with `render_span`.
+pub fn debug_hygiene_with(span: Span, render_span: impl Fn(Span) -> String) -> String {
+    let mut rendered = format!("{:?}", span.ctxt());
+    for mark in span.expansion_chain() {
+        let expn = match mark.expn_data.kind {
+            ExpnKind::Root => "root".to_string(),
+            ExpnKind::Macro(kind, name) => format!("{} `{}`", kind.descr(), name),
+            ExpnKind::Desugaring(kind) => format!("desugaring of {}", kind.descr()),
+        };
+        rendered.push_str(&format!(
+            "\n  {:?}: {}, {:?}, called at {}, defined at {}",
+            mark.expn_id,
+            expn,
+            mark.transparency,
+            render_span(mark.expn_data.call_site),
+            render_span(mark.expn_data.def_site),
+        ));
+    }
+    rendered
+}
+
 pub fn update_dollar_crate_names(mut get_name: impl FnMut(SyntaxContext) -> Symbol) {
     // The new contexts that need updating are at the end of the list and have `$crate` as a name.
     let (len, to_update) = HygieneData::with(|data| (
@@ -532,6 +603,18 @@ impl SyntaxContext {
         })
     }
 
+    /// The marks of this context with the data of their expansions, from the outermost (most
+    /// recently applied) mark to the innermost one.
+    pub fn expansion_chain(self) -> Vec<ExpnMark> {
+        HygieneData::with(|data| {
+            data.marks(self).into_iter().rev().map(|(expn_id, transparency)| ExpnMark {
+                expn_id,
+                transparency,
+                expn_data: data.expn_data(expn_id).clone(),
+            }).collect()
+        })
+    }
+
     pub fn dollar_crate_name(self) -> Symbol {
         HygieneData::with(|data| data.syntax_context_data[self.0 as usize].dollar_crate_name)
     }
@@ -553,6 +636,11 @@ impl Span {
         self.fresh_expansion_with_transparency(expn_data, Transparency::SemiTransparent)
     }
 
+    /// The marks of the syntax context of this span, see `SyntaxContext::expansion_chain`.
+    pub fn expansion_chain(self) -> Vec<ExpnMark> {
+        self.ctxt().expansion_chain()
+    }
+
     pub fn fresh_expansion_with_transparency(
         self, expn_data: ExpnData, transparency: Transparency
     ) -> Span {
@@ -563,6 +651,14 @@ impl Span {
     }
 }
 
+/// A mark of a syntax context, as returned by `SyntaxContext::expansion_chain`.
+#[derive(Clone, Debug)]
+pub struct ExpnMark {
+    pub expn_id: ExpnId,
+    pub transparency: Transparency,
+    pub expn_data: ExpnData,
+}
+
 /// A subset of properties from both macro definition and macro call available through global data.
 /// Avoid using this if you have access to the original definition or call structures.
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
@@ -602,6 +698,9 @@ pub struct ExpnData {
     pub local_inner_macros: bool,
     /// Edition of the crate in which the macro is defined.
     pub edition: Edition,
+    /// The transparency of the marks that the macro applies to the tokens it produces, unless
+    /// it gives them a context itself, e.g. for `macro_rules!` macros.
+    pub default_transparency: Transparency,
 }
 
 impl ExpnData {
@@ -616,6 +715,7 @@ impl ExpnData {
             allow_internal_unsafe: false,
             local_inner_macros: false,
             edition,
+            default_transparency: Transparency::SemiTransparent,
         }
     }
 
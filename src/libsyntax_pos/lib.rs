@@ -25,7 +25,8 @@ pub use hygiene::{ExpnId, SyntaxContext, ExpnData, ExpnKind, MacroKind, Desugari
 use hygiene::Transparency;
 
 mod span_encoding;
-pub use span_encoding::{Span, DUMMY_SP};
+pub use span_encoding::{Span, DUMMY_SP, SpanInternerStats};
+pub use span_encoding::{span_interner_stats, shrink_span_interner};
 
 pub mod symbol;
 pub use symbol::{Symbol, sym};
@@ -33,7 +34,7 @@ pub use symbol::{Symbol, sym};
 mod analyze_source_file;
 
 use rustc_data_structures::stable_hasher::StableHasher;
-use rustc_data_structures::sync::{Lrc, Lock};
+use rustc_data_structures::sync::{Lrc, Lock, Once};
 
 use std::borrow::Cow;
 use std::cell::Cell;
@@ -484,6 +485,25 @@ impl Span {
         )
     }
 
+    /// Returns a `Span` enclosing both `self` and `end`, like `to`, or the reason why the two
+    /// can't be joined into a meaningful span: they come from different expansion contexts
+    /// (where `to` picks either of the contexts or returns one of the spans on its own), or
+    /// `end` starts before `self` does.
+    ///
+    /// Spans from different files can't be told apart without the source map, use
+    /// `SourceMap::try_join` to check for those as well.
+    pub fn try_join(self, end: Span) -> Result<Span, JoinError> {
+        let span_data = self.data();
+        let end_data = end.data();
+        if span_data.ctxt != end_data.ctxt {
+            return Err(JoinError::DifferentContexts(span_data.ctxt, end_data.ctxt));
+        }
+        if end_data.lo < span_data.lo {
+            return Err(JoinError::Reversed);
+        }
+        Ok(Span::new(span_data.lo, cmp::max(span_data.hi, end_data.hi), span_data.ctxt))
+    }
+
     /// Returns a `Span` between the end of `self` to the beginning of `end`.
     pub fn between(self, end: Span) -> Span {
         let span = self.data();
@@ -893,18 +913,46 @@ pub struct SourceFile {
     pub start_pos: BytePos,
     /// The end position of this source in the `SourceMap`.
     pub end_pos: BytePos,
+    /// The positions needed to map byte positions to lines and columns. Built from `src` the
+    /// first time it's needed, see `line_index`, and set up front for imported files.
+    pub line_index: Once<LineIndex>,
+    /// A hash of the filename, used for speeding up hashing in incremental compilation.
+    pub name_hash: u128,
+}
+
+/// The positions in a `SourceFile` needed to map byte positions to lines and columns, all found
+/// in a single pass over the source.
+#[derive(Clone, Debug)]
+pub struct LineIndex {
     /// Locations of lines beginnings in the source code.
     pub lines: Vec<BytePos>,
     /// Locations of multi-byte characters in the source code.
     pub multibyte_chars: Vec<MultiByteChar>,
     /// Width of characters that are not narrow in the source code.
     pub non_narrow_chars: Vec<NonNarrowChar>,
-    /// A hash of the filename, used for speeding up hashing in incremental compilation.
-    pub name_hash: u128,
+}
+
+impl LineIndex {
+    /// Analyzes `src`, the source of a file starting at `start_pos` in the `SourceMap`.
+    pub fn new(src: &str, start_pos: BytePos) -> LineIndex {
+        let (lines, multibyte_chars, non_narrow_chars) =
+            analyze_source_file::analyze_source_file(src, start_pos);
+        LineIndex { lines, multibyte_chars, non_narrow_chars }
+    }
+
+    /// Wraps an index that's already known, e.g., for an imported `SourceFile`.
+    pub fn ready(self) -> Once<LineIndex> {
+        let once = Once::new();
+        once.set(self);
+        once
+    }
 }
 
 impl Encodable for SourceFile {
+    /// Encodes the line index along with the file, building it if it isn't yet. Encoders of many
+    /// files can build their indices up front with `SourceMap::precompute_line_indices`.
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        let line_index = self.line_index();
         s.emit_struct("SourceFile", 8, |s| {
             s.emit_struct_field("name", 0, |s| self.name.encode(s))?;
             s.emit_struct_field("name_was_remapped", 1, |s| self.name_was_remapped.encode(s))?;
@@ -912,7 +960,7 @@ impl Encodable for SourceFile {
             s.emit_struct_field("start_pos", 4, |s| self.start_pos.encode(s))?;
             s.emit_struct_field("end_pos", 5, |s| self.end_pos.encode(s))?;
             s.emit_struct_field("lines", 6, |s| {
-                let lines = &self.lines[..];
+                let lines = &line_index.lines[..];
                 // Store the length.
                 s.emit_u32(lines.len() as u32)?;
 
@@ -958,10 +1006,10 @@ impl Encodable for SourceFile {
                 Ok(())
             })?;
             s.emit_struct_field("multibyte_chars", 7, |s| {
-                self.multibyte_chars.encode(s)
+                line_index.multibyte_chars.encode(s)
             })?;
             s.emit_struct_field("non_narrow_chars", 8, |s| {
-                self.non_narrow_chars.encode(s)
+                line_index.non_narrow_chars.encode(s)
             })?;
             s.emit_struct_field("name_hash", 9, |s| {
                 self.name_hash.encode(s)
@@ -1029,9 +1077,7 @@ impl Decodable for SourceFile {
                 src: None,
                 src_hash,
                 external_src: Lock::new(ExternalSource::AbsentOk),
-                lines,
-                multibyte_chars,
-                non_narrow_chars,
+                line_index: LineIndex { lines, multibyte_chars, non_narrow_chars }.ready(),
                 name_hash,
             })
         })
@@ -1068,9 +1114,6 @@ impl SourceFile {
             return Err(OffsetOverflowError);
         }
 
-        let (lines, multibyte_chars, non_narrow_chars) =
-            analyze_source_file::analyze_source_file(&src[..], start_pos);
-
         Ok(SourceFile {
             name,
             name_was_remapped,
@@ -1081,17 +1124,44 @@ impl SourceFile {
             external_src: Lock::new(ExternalSource::Unneeded),
             start_pos,
             end_pos: Pos::from_usize(end_pos),
-            lines,
-            multibyte_chars,
-            non_narrow_chars,
+            line_index: Once::new(),
             name_hash,
         })
     }
 
+    /// Returns the line index of this file, building it first if this is the first time it's
+    /// needed. Concurrent callers wait for the index to be built once rather than racing.
+    pub fn line_index(&self) -> &LineIndex {
+        if let Some(index) = self.line_index.try_get() {
+            return index;
+        }
+        self.line_index.init_locking(|| {
+            let src = self.src.as_ref()
+                .expect("a `SourceFile` without source must have its line index set");
+            LineIndex::new(src, self.start_pos)
+        });
+        self.line_index.get()
+    }
+
+    /// Locations of lines beginnings in the source code.
+    pub fn lines(&self) -> &[BytePos] {
+        &self.line_index().lines
+    }
+
+    /// Locations of multi-byte characters in the source code.
+    pub fn multibyte_chars(&self) -> &[MultiByteChar] {
+        &self.line_index().multibyte_chars
+    }
+
+    /// Width of characters that are not narrow in the source code.
+    pub fn non_narrow_chars(&self) -> &[NonNarrowChar] {
+        &self.line_index().non_narrow_chars
+    }
+
     /// Returns the `BytePos` of the beginning of the current line.
     pub fn line_begin_pos(&self, pos: BytePos) -> BytePos {
         let line_index = self.lookup_line(pos).unwrap();
-        self.lines[line_index]
+        self.lines()[line_index]
     }
 
     /// Add externally loaded source.
@@ -1142,7 +1212,7 @@ impl SourceFile {
         }
 
         let begin = {
-            let line = if let Some(line) = self.lines.get(line_number) {
+            let line = if let Some(line) = self.lines().get(line_number) {
                 line
             } else {
                 return None;
@@ -1172,7 +1242,7 @@ impl SourceFile {
         self.end_pos.0 - self.start_pos.0
     }
     pub fn count_lines(&self) -> usize {
-        self.lines.len()
+        self.lines().len()
     }
 
     /// Finds the line containing the given position. The return value is the
@@ -1180,12 +1250,13 @@ impl SourceFile {
     /// number. If the source_file is empty or the position is located before the
     /// first line, `None` is returned.
     pub fn lookup_line(&self, pos: BytePos) -> Option<usize> {
-        if self.lines.len() == 0 {
+        let lines = self.lines();
+        if lines.len() == 0 {
             return None;
         }
 
-        let line_index = lookup_line(&self.lines[..], pos);
-        assert!(line_index < self.lines.len() as isize);
+        let line_index = lookup_line(lines, pos);
+        assert!(line_index < lines.len() as isize);
         if line_index >= 0 {
             Some(line_index as usize)
         } else {
@@ -1198,11 +1269,12 @@ impl SourceFile {
             return (self.start_pos, self.end_pos);
         }
 
-        assert!(line_index < self.lines.len());
-        if line_index == (self.lines.len() - 1) {
-            (self.lines[line_index], self.end_pos)
+        let lines = self.lines();
+        assert!(line_index < lines.len());
+        if line_index == (lines.len() - 1) {
+            (lines[line_index], self.end_pos)
         } else {
-            (self.lines[line_index], self.lines[line_index + 1])
+            (lines[line_index], lines[line_index + 1])
         }
     }
 
@@ -1431,9 +1503,21 @@ pub struct MacroBacktrace {
 }
 
 // _____________________________________________________________________________
-// SpanLinesError, SpanSnippetError, DistinctSources, MalformedSourceMapPositions
+// JoinError, SpanLinesError, SpanSnippetError, DistinctSources, MalformedSourceMapPositions
 //
 
+/// The reason why two spans can't be joined, see `Span::try_join`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JoinError {
+    /// The spans come from different expansions, e.g., one is an argument of a macro call
+    /// and the other is produced by the macro.
+    DifferentContexts(SyntaxContext, SyntaxContext),
+    /// The second span starts before the first one.
+    Reversed,
+    /// The spans are in different files.
+    DistinctSources(DistinctSources),
+}
+
 pub type FileLinesResult = Result<FileLines, SpanLinesError>;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -10,6 +10,8 @@ use crate::hygiene::SyntaxContext;
 
 use rustc_data_structures::fx::FxHashMap;
 
+use std::mem;
+
 /// A compressed span.
 ///
 /// `SpanData` is 12 bytes, which is a bit too big to stick everywhere. `Span`
@@ -113,10 +115,13 @@ impl Span {
 pub struct SpanInterner {
     spans: FxHashMap<SpanData, u32>,
     span_data: Vec<SpanData>,
+    /// The number of spans interned, including those already in the table.
+    requests: usize,
 }
 
 impl SpanInterner {
     fn intern(&mut self, span_data: &SpanData) -> u32 {
+        self.requests += 1;
         if let Some(index) = self.spans.get(span_data) {
             return *index;
         }
@@ -131,6 +136,56 @@ impl SpanInterner {
     fn get(&self, index: u32) -> &SpanData {
         &self.span_data[index as usize]
     }
+
+    fn stats(&self) -> SpanInternerStats {
+        let (mut long, mut large_ctxt) = (0, 0);
+        for span_data in &self.span_data {
+            if span_data.hi.0 - span_data.lo.0 > MAX_LEN {
+                long += 1;
+            }
+            if span_data.ctxt.as_u32() > MAX_CTXT {
+                large_ctxt += 1;
+            }
+        }
+        let entry_size = mem::size_of::<SpanData>();
+        SpanInternerStats {
+            requests: self.requests,
+            distinct: self.span_data.len(),
+            long,
+            large_ctxt,
+            allocated_bytes: self.span_data.capacity() * entry_size +
+                             self.spans.capacity() * (entry_size + mem::size_of::<u32>()),
+        }
+    }
+}
+
+/// Statistics about the spans that didn't fit inline and had to be interned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpanInternerStats {
+    /// The number of times a span was interned, whether it was new or not.
+    pub requests: usize,
+    /// The number of distinct spans stored, every other request was deduplicated.
+    pub distinct: usize,
+    /// Of the distinct spans, those interned because they're too long.
+    pub long: usize,
+    /// Of the distinct spans, those interned because their syntax context is too large.
+    pub large_ctxt: usize,
+    /// The approximate size of the interner's storage, including its unused capacity.
+    pub allocated_bytes: usize,
+}
+
+/// Returns statistics about the span interner of the current `GLOBALS`.
+pub fn span_interner_stats() -> SpanInternerStats {
+    with_span_interner(|interner| interner.stats())
+}
+
+/// Releases the unused capacity of the span interner of the current `GLOBALS`. Meant to be
+/// called once macro expansion, which creates most of the interned spans, is done.
+pub fn shrink_span_interner() {
+    with_span_interner(|interner| {
+        interner.spans.shrink_to_fit();
+        interner.span_data.shrink_to_fit();
+    })
 }
 
 // If an interner exists, return it. Otherwise, prepare a fresh one.
@@ -10,11 +10,14 @@ use rustc_macros::symbols;
 use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use rustc_serialize::{UseSpecializedDecodable, UseSpecializedEncodable};
 
+use std::cell::RefCell;
 use std::cmp::{PartialEq, Ordering, PartialOrd, Ord};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::str;
 
+use crate::edition::Edition;
+use crate::hygiene::ExpnId;
 use crate::{Span, DUMMY_SP, GLOBALS};
 
 #[cfg(test)]
@@ -23,6 +26,7 @@ mod tests;
 symbols! {
     // After modifying this list adjust `is_special`, `is_used_keyword`/`is_unused_keyword`,
     // this should be rarely necessary though if the keywords are kept in alphabetic order.
+    // Edition-specific keywords also need an entry in `EditionKeywords`.
     Keywords {
         // Special reserved identifiers used internally for elided lifetimes,
         // unnamed method parameters, crate root module, error recovery etc.
@@ -134,6 +138,7 @@ symbols! {
         allow_internal_unsafe,
         allow_internal_unstable,
         allow_internal_unstable_backcompat_hack,
+        alternative,
         always,
         and,
         any,
@@ -236,6 +241,8 @@ symbols! {
         default_type_params,
         deny,
         deprecated,
+        deprecated_arms,
+        deprecated_suggestion,
         deref,
         deref_mut,
         derive,
@@ -263,6 +270,7 @@ symbols! {
         drop_types_in_const,
         dylib,
         dyn_trait,
+        edition,
         eh_personality,
         eh_unwind_resume,
         enable,
@@ -295,6 +303,7 @@ symbols! {
         ffi_returns_twice,
         field,
         field_init_shorthand,
+        fields,
         file,
         fmt,
         fmt_internals,
@@ -397,12 +406,15 @@ symbols! {
         log_syntax,
         loop_break_value,
         macro_at_most_once_rep,
+        macro_dry_run,
         macro_escape,
         macro_export,
         macro_lifetime_matcher,
         macro_literal_matcher,
         macro_reexport,
         macro_rules,
+        macros_in_arm_position,
+        macros_in_param_position,
         macros_in_extern,
         macro_use,
         macro_vis_matcher,
@@ -580,12 +592,16 @@ symbols! {
         rustc_dump_user_substs,
         rustc_error,
         rustc_expected_cgu_reuse,
+        rustc_expr_attr,
+        rustc_helper_attr_scope,
         rustc_if_this_changed,
         rustc_inherit_overflow_checks,
+        rustc_keep_macro_attr,
         rustc_layout,
         rustc_layout_scalar_valid_range_end,
         rustc_layout_scalar_valid_range_start,
         rustc_macro_transparency,
+        rustc_min_edition,
         rustc_mir,
         rustc_nonnull_optimization_guaranteed,
         rustc_object_lifetime_default,
@@ -601,7 +617,9 @@ symbols! {
         rustc_private,
         rustc_proc_macro_decls,
         rustc_promotable,
+        rustc_pure_macro,
         rustc_regions,
+        rustc_sees_unconfigured,
         rustc_stable,
         rustc_std_internal_symbol,
         rustc_symbol_name,
@@ -627,6 +645,7 @@ symbols! {
         slice_patterns,
         slicing_syntax,
         Some,
+        span_source,
         specialization,
         speed,
         spotlight,
@@ -642,6 +661,8 @@ symbols! {
         std_inject,
         str,
         stringify,
+        stringify_source,
+        stringify_tokens,
         stmt,
         stmt_expr_attributes,
         stop_after_dataflow,
@@ -707,6 +728,7 @@ symbols! {
         unsafe_no_drop_flag,
         unsized_locals,
         unsized_tuple_coercion,
+        unused_imports,
         unstable,
         untagged_unions,
         unwind,
@@ -718,6 +740,7 @@ symbols! {
         usize,
         v1,
         val,
+        variants,
         vec,
         Vec,
         vis,
@@ -725,6 +748,7 @@ symbols! {
         volatile,
         warn,
         warn_directory_ownership,
+        warnings,
         wasm_import_module,
         wasm_target_feature,
         while_let,
@@ -801,6 +825,26 @@ impl Ident {
         Ident::new(self.name, self.span.modern_and_legacy())
     }
 
+    /// Compares the names of two identifiers, ignoring their hygiene contexts entirely.
+    /// Use this where two identifiers are expected to match textually, e.g., when looking for
+    /// a helper attribute or a field of a known name, so that the intent is explicit.
+    pub fn eq_unhygienic(self, other: Ident) -> bool {
+        self.name == other.name
+    }
+
+    /// Compares two identifiers as seen from the expansion `at`, i.e., with "item hygiene"
+    /// (see `modern`) and with the marks of the expansions that `at` is nested within removed
+    /// from both contexts, the way names are compared when resolving within that expansion.
+    pub fn eq_hygienic(self, other: Ident, at: ExpnId) -> bool {
+        if self.name != other.name {
+            return false;
+        }
+        let (mut ctxt, mut other_ctxt) = (self.span.ctxt(), other.span.ctxt());
+        ctxt.modernize_and_adjust(at);
+        other_ctxt.modernize_and_adjust(at);
+        ctxt == other_ctxt
+    }
+
     /// Transforms an identifier into one with the same name, but gensymed.
     pub fn gensym(self) -> Ident {
         let name = with_interner(|interner| interner.gensymed(self.name));
@@ -833,10 +877,64 @@ impl Ident {
 
 impl PartialEq for Ident {
     fn eq(&self, rhs: &Self) -> bool {
-        self.name == rhs.name && self.span.ctxt() == rhs.span.ctxt()
+        if self.name != rhs.name {
+            return false;
+        }
+        let same_ctxt = self.span.ctxt() == rhs.span.ctxt();
+        if cfg!(debug_assertions) && !same_ctxt {
+            record_raw_ident_eq(*self, *rhs);
+        }
+        same_ctxt
     }
 }
 
+/// The comparisons recorded by `with_strict_ident_eq`, if it's enabled.
+thread_local!(static RAW_IDENT_EQS: RefCell<Option<Vec<(Ident, Ident)>>> = RefCell::new(None));
+
+/// The number of comparisons recorded at most by `with_strict_ident_eq`.
+const MAX_RAW_IDENT_EQS: usize = 100;
+
+fn record_raw_ident_eq(ident: Ident, other: Ident) {
+    RAW_IDENT_EQS.with(|eqs| {
+        if let Some(eqs) = &mut *eqs.borrow_mut() {
+            let ctxts = (ident.span.ctxt(), other.span.ctxt());
+            let known = eqs.iter().any(|(a, b)| {
+                a.name == ident.name && (a.span.ctxt(), b.span.ctxt()) == ctxts
+            });
+            if !known && eqs.len() < MAX_RAW_IDENT_EQS {
+                eqs.push((ident, other));
+            }
+        }
+    })
+}
+
+/// Runs `f`, recording the raw `==` comparisons of identifiers with the same name but different
+/// hygiene contexts it makes if `strict` is set. Such comparisons are ambiguous in code handling
+/// macro expansions, where it's rarely obvious whether the hygiene contexts are meant to matter;
+/// `Ident::eq_hygienic` and `Ident::eq_unhygienic` state it explicitly.
+///
+/// The comparisons are returned with the result of `f`, once for each pair of contexts and up to
+/// a limit. Comparisons made within `without_strict_ident_eq` are left out. They are only
+/// recorded by compilers built with debug assertions.
+pub fn with_strict_ident_eq<R>(strict: bool, f: impl FnOnce() -> R) -> (R, Vec<(Ident, Ident)>) {
+    let prev = RAW_IDENT_EQS.with(|eqs| eqs.replace(if strict { Some(Vec::new()) } else { None }));
+    let result = f();
+    let eqs = RAW_IDENT_EQS.with(|eqs| eqs.replace(prev));
+    (result, eqs.unwrap_or_default())
+}
+
+/// Runs `f` without recording the comparisons of identifiers it makes, for code that compares
+/// identifiers with `==` deliberately, like name resolution does.
+pub fn without_strict_ident_eq<R>(f: impl FnOnce() -> R) -> R {
+    let prev = RAW_IDENT_EQS.with(|eqs| eqs.replace(None));
+    let result = f();
+    RAW_IDENT_EQS.with(|eqs| {
+        let suspended = eqs.replace(prev);
+        debug_assert!(suspended.is_none());
+    });
+    result
+}
+
 impl Hash for Ident {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.name.hash(state);
@@ -1087,15 +1185,54 @@ pub mod sym {
     }
 }
 
-impl Symbol {
-    fn is_used_keyword_2018(self) -> bool {
-        self == kw::Dyn
+/// A keyword that is only reserved starting with some edition, and an ordinary identifier in
+/// the editions before it.
+#[derive(Clone, Copy, Debug)]
+pub struct EditionKeyword {
+    pub name: Symbol,
+    /// The first edition in which `name` is a keyword.
+    pub edition: Edition,
+    /// Whether the keyword has a meaning in the language, as opposed to being reserved for
+    /// future use.
+    pub used: bool,
+}
+
+/// The table of edition-specific keywords.
+///
+/// This is the single place deciding whether such a word is a keyword or an identifier, so that
+/// the parser's regular paths and its recovery paths can't disagree about it.
+pub struct EditionKeywords;
+
+impl EditionKeywords {
+    const TABLE: &'static [EditionKeyword] = &[
+        EditionKeyword { name: kw::Dyn, edition: Edition::Edition2018, used: true },
+        EditionKeyword { name: kw::Async, edition: Edition::Edition2018, used: false },
+        EditionKeyword { name: kw::Await, edition: Edition::Edition2018, used: false },
+        EditionKeyword { name: kw::Try, edition: Edition::Edition2018, used: false },
+    ];
+
+    pub fn all() -> &'static [EditionKeyword] {
+        Self::TABLE
+    }
+
+    /// Returns the entry for `name` if it's an edition-specific keyword.
+    pub fn get(name: Symbol) -> Option<&'static EditionKeyword> {
+        Self::TABLE.iter().find(|keyword| keyword.name == name)
     }
 
-    fn is_unused_keyword_2018(self) -> bool {
-        self >= kw::Async && self <= kw::Try
+    /// Returns `true` if `name` is an edition-specific keyword in `edition`.
+    pub fn is_keyword_in(name: Symbol, edition: Edition) -> bool {
+        Self::get(name).map_or(false, |keyword| keyword.edition <= edition)
     }
 
+    /// Returns `true` if `name` is an edition-specific keyword in the edition of `span`.
+    pub fn is_keyword_at(name: Symbol, span: Span) -> bool {
+        // Note: `span.edition()` is relatively expensive, don't call it unless necessary.
+        Self::get(name).map_or(false, |keyword| keyword.edition <= span.edition())
+    }
+}
+
+impl Symbol {
     /// Used for sanity checking rustdoc keyword sections.
     pub fn is_doc_keyword(self) -> bool {
         self <= kw::Union
@@ -1133,14 +1270,20 @@ impl Ident {
     pub fn is_used_keyword(self) -> bool {
         // Note: `span.edition()` is relatively expensive, don't call it unless necessary.
         self.name >= kw::As && self.name <= kw::While ||
-        self.name.is_used_keyword_2018() && self.span.rust_2018()
+        self.is_edition_keyword(true)
     }
 
     /// Returns `true` if the token is a keyword reserved for possible future use.
     pub fn is_unused_keyword(self) -> bool {
         // Note: `span.edition()` is relatively expensive, don't call it unless necessary.
         self.name >= kw::Abstract && self.name <= kw::Yield ||
-        self.name.is_unused_keyword_2018() && self.span.rust_2018()
+        self.is_edition_keyword(false)
+    }
+
+    fn is_edition_keyword(self, used: bool) -> bool {
+        EditionKeywords::get(self.name).map_or(false, |keyword| {
+            keyword.used == used && keyword.edition <= self.span.edition()
+        })
     }
 
     /// Returns `true` if the token is either a special identifier or a keyword.
@@ -30,3 +30,32 @@ fn without_first_quote_test() {
         assert_eq!(i.without_first_quote().name, kw::Break);
     });
 }
+
+#[test]
+fn explicit_ident_comparisons() {
+    use crate::hygiene::{ExpnData, ExpnId, ExpnKind, MacroKind, Transparency};
+
+    GLOBALS.set(&Globals::new(edition::DEFAULT_EDITION), || {
+        let kind = ExpnKind::Macro(MacroKind::Bang, sym::format_args);
+        let expn_data = ExpnData::default(kind, DUMMY_SP, edition::DEFAULT_EDITION);
+        let expn_id = ExpnId::fresh(Some(expn_data));
+        let outside = Ident::from_str("x");
+        let inside = outside.with_span_pos(DUMMY_SP.apply_mark(expn_id, Transparency::Opaque));
+
+        assert!(outside != inside);
+        assert!(outside.eq_unhygienic(inside));
+        // Within the expansion its own mark is significant ...
+        assert!(!outside.eq_hygienic(inside, expn_id));
+        // ... but it's removed when resolving outside of it.
+        assert!(outside.eq_hygienic(inside, ExpnId::root()));
+        assert!(!outside.eq_hygienic(Ident::from_str("y"), ExpnId::root()));
+
+        let (equal, eqs) = with_strict_ident_eq(true, || {
+            let _ = without_strict_ident_eq(|| outside == inside);
+            outside == inside && outside == Ident::from_str("x")
+        });
+        assert!(!equal);
+        assert_eq!(eqs.len(), if cfg!(debug_assertions) { 1 } else { 0 });
+        assert!(with_strict_ident_eq(false, || outside == inside).1.is_empty());
+    });
+}
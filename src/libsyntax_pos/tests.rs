@@ -36,3 +36,54 @@ fn test_normalize_newlines() {
     check("\r\r\n", "\r\n");
     check("hello\rworld", "hello\rworld");
 }
+
+#[test]
+fn test_span_interner_dedup() {
+    GLOBALS.set(&Globals::new(edition::DEFAULT_EDITION), || {
+        let long = Span::new(BytePos(0), BytePos(100_000), SyntaxContext::root());
+        let long_again = Span::new(BytePos(0), BytePos(100_000), SyntaxContext::root());
+        let short = Span::new(BytePos(0), BytePos(10), SyntaxContext::root());
+        assert_eq!(long, long_again);
+        assert_eq!(long.hi(), BytePos(100_000));
+        assert_eq!(short.hi(), BytePos(10));
+
+        let stats = span_interner_stats();
+        assert_eq!((stats.requests, stats.distinct), (2, 1));
+        assert_eq!((stats.long, stats.large_ctxt), (1, 0));
+
+        shrink_span_interner();
+        assert_eq!(Span::new(BytePos(0), BytePos(100_000), SyntaxContext::root()), long);
+        assert_eq!(span_interner_stats().distinct, 1);
+    })
+}
+
+#[test]
+fn test_expansion_chain() {
+    GLOBALS.set(&Globals::new(edition::DEFAULT_EDITION), || {
+        let call_site = Span::new(BytePos(0), BytePos(4), SyntaxContext::root());
+        let kind = ExpnKind::Macro(MacroKind::Bang, Symbol::intern("outer"));
+        let expn_data = ExpnData::default(kind, call_site, edition::DEFAULT_EDITION);
+        let outer = call_site.fresh_expansion(expn_data);
+        let kind = ExpnKind::Macro(MacroKind::Attr, Symbol::intern("inner"));
+        let inner = ExpnId::fresh(Some(ExpnData::default(kind, outer, edition::DEFAULT_EDITION)));
+        let span = outer.with_ctxt(outer.ctxt().apply_mark(inner, Transparency::Opaque));
+
+        let chain = span.expansion_chain();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].expn_data.kind.descr(), Symbol::intern("inner"));
+        assert_eq!(chain[0].transparency, Transparency::Opaque);
+        assert_eq!(chain[1].expn_data.kind.descr(), Symbol::intern("outer"));
+        assert_eq!(chain[1].transparency, Transparency::SemiTransparent);
+
+        let rendered = hygiene::debug_hygiene_with(span, |span| {
+            format!("{}..{}", span.lo().0, span.hi().0)
+        });
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], format!("{:?}", span.ctxt()));
+        assert!(lines[1].ends_with(": attribute macro `inner`, Opaque, called at 0..4, \
+                                    defined at 0..0"));
+        assert!(lines[2].ends_with(": macro `outer`, SemiTransparent, called at 0..4, \
+                                    defined at 0..0"));
+    })
+}
@@ -0,0 +1,13 @@
+#![crate_name = "foo"]
+
+// @!has foo/macro.hidden.html
+#[macro_export(hidden)]
+macro_rules! hidden {
+    () => {};
+}
+
+// @has foo/macro.shown.html
+#[macro_export]
+macro_rules! shown {
+    () => {};
+}
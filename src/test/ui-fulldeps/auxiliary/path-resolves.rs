@@ -0,0 +1,56 @@
+// force-host
+
+#![feature(plugin_registrar)]
+#![feature(rustc_private)]
+
+extern crate syntax;
+extern crate syntax_pos;
+extern crate rustc;
+extern crate rustc_driver;
+
+use syntax::ext::base::{DummyResult, ExtCtxt, MacEager, MacResult, PathNamespace};
+use syntax::ext::base::{SyntaxExtension, SyntaxExtensionKind};
+use syntax::parse::parser::PathStyle;
+use syntax::symbol::Symbol;
+use syntax::tokenstream::TokenStream;
+use syntax_pos::Span;
+use rustc_driver::plugin::Registry;
+
+// Expands to whether the path given to it resolves in the namespace `ns`.
+fn expand_resolves<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, tts: TokenStream,
+                        ns: PathNamespace) -> Box<dyn MacResult + 'cx> {
+    let path = match cx.new_parser_from_tts(tts).parse_path(PathStyle::Mod) {
+        Ok(path) => path,
+        Err(mut err) => {
+            err.emit();
+            return DummyResult::any(sp);
+        }
+    };
+    match cx.path_resolves(&path, ns) {
+        Ok(found) => MacEager::expr(cx.expr_bool(sp, found)),
+        Err(_) => {
+            cx.span_err(sp, "can't tell yet whether the path resolves");
+            DummyResult::any(sp)
+        }
+    }
+}
+
+fn expand_type_resolves<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
+                             -> Box<dyn MacResult + 'cx> {
+    expand_resolves(cx, sp, tts, PathNamespace::Type)
+}
+
+fn expand_value_resolves<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, tts: TokenStream)
+                              -> Box<dyn MacResult + 'cx> {
+    expand_resolves(cx, sp, tts, PathNamespace::Value)
+}
+
+#[plugin_registrar]
+pub fn plugin_registrar(reg: &mut Registry) {
+    reg.register_syntax_extension(Symbol::intern("type_resolves"), SyntaxExtension::default(
+        SyntaxExtensionKind::LegacyBang(Box::new(expand_type_resolves)), reg.sess.edition()
+    ));
+    reg.register_syntax_extension(Symbol::intern("value_resolves"), SyntaxExtension::default(
+        SyntaxExtensionKind::LegacyBang(Box::new(expand_value_resolves)), reg.sess.edition()
+    ));
+}
@@ -0,0 +1,36 @@
+// force-host
+
+#![feature(plugin_registrar)]
+#![feature(rustc_private)]
+
+extern crate syntax;
+extern crate syntax_pos;
+extern crate rustc;
+extern crate rustc_driver;
+
+use syntax::ext::base::{ExtCtxt, MacEager, MacResult, SuppressedWarning};
+use syntax::ext::base::{SyntaxExtension, SyntaxExtensionKind};
+use syntax::symbol::Symbol;
+use syntax::tokenstream::TokenStream;
+use syntax_pos::Span;
+use rustc_driver::plugin::Registry;
+
+// Calls `old` twice, with the deprecation warning of the first call suppressed.
+fn expand_call_old_twice<'cx>(cx: &'cx mut ExtCtxt<'_>, sp: Span, _: TokenStream)
+                              -> Box<dyn MacResult + 'cx> {
+    let old = cx.ident_of("old");
+    let quiet = cx.with_suppressed_warnings(&[SuppressedWarning::Deprecated], |cx| {
+        let sp = cx.with_call_site_ctxt(sp);
+        cx.expr_call_ident(sp, old, vec![])
+    });
+    let sp = cx.with_call_site_ctxt(sp);
+    let loud = cx.expr_call_ident(sp, old, vec![]);
+    MacEager::expr(cx.expr_tuple(sp, vec![quiet, loud]))
+}
+
+#[plugin_registrar]
+pub fn plugin_registrar(reg: &mut Registry) {
+    reg.register_syntax_extension(Symbol::intern("call_old_twice"), SyntaxExtension::default(
+        SyntaxExtensionKind::LegacyBang(Box::new(expand_call_old_twice)), reg.sess.edition()
+    ));
+}
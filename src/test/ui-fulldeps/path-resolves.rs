@@ -0,0 +1,22 @@
+// Macros can ask the resolver whether a path resolves with `ExtCtxt::path_resolves`.
+
+// run-pass
+// aux-build:path-resolves.rs
+// ignore-stage1
+
+#![feature(plugin)]
+#![plugin(path_resolves)]
+
+mod inner {
+    pub struct S;
+    pub fn f() {}
+}
+
+fn main() {
+    assert!(type_resolves!(inner));
+    assert!(type_resolves!(inner::S));
+    assert!(type_resolves!(std::vec::Vec));
+    assert!(!type_resolves!(inner::f));
+    assert!(value_resolves!(inner::f));
+    assert!(!value_resolves!(inner::missing));
+}
@@ -0,0 +1,16 @@
+// Warnings suppressed with `ExtCtxt::with_suppressed_warnings` stay suppressed when warnings
+// are denied, while the others become errors.
+
+// aux-build:suppressed-warnings.rs
+// ignore-stage1
+// compile-flags: -D warnings
+
+#![feature(plugin)]
+#![plugin(suppressed_warnings)]
+
+#[deprecated]
+fn old() {}
+
+fn main() {
+    call_old_twice!(); //~ ERROR use of deprecated item 'old'
+}
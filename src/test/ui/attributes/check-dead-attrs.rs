@@ -0,0 +1,18 @@
+// compile-flags: -Z check-dead-attrs
+// check-pass
+
+#![feature(custom_attribute)]
+#![allow(dead_code, unused_attributes)]
+
+#[my_attr] //~ WARN attribute `my_attr` is not consumed by any macro
+fn f() {}
+
+struct S {
+    #[field_attr] //~ WARN attribute `field_attr` is not consumed by any macro
+    x: u8,
+}
+
+#[inline]
+fn g() {}
+
+fn main() {}
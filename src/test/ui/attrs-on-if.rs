@@ -0,0 +1,5 @@
+#![feature(stmt_expr_attributes)]
+
+fn main() {
+    let _ = #[allow(unused)] if true { 1 } else { 2 }; //~ ERROR not yet allowed on `if`
+}
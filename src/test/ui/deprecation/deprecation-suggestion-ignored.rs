@@ -0,0 +1,16 @@
+// check-pass
+
+#![feature(deprecated_suggestion)]
+#![allow(dead_code)]
+
+#[deprecated(suggestion = "new()")] //~ WARN `suggestion` is ignored on this item
+fn old() {}
+
+struct S;
+
+impl S {
+    #[deprecated(note = "use `S::new`", suggestion = "S::new()")] //~ WARN `suggestion` is ignored
+    fn old() {}
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+#![feature(rustc_attrs)]
+
+#[rustc_min_edition(edition = "2018", alternative = "old_mac")]
+macro_rules! new_mac {
+    () => {}
+}
+
+macro_rules! old_mac {
+    () => {}
+}
+
+fn main() {
+    new_mac!(); //~ ERROR `new_mac!` can only be used in Rust 2018 or later
+    old_mac!();
+}
@@ -0,0 +1,6 @@
+#![allow(unused_macros)]
+
+#[deprecated(suggestion = "new!($args)")] //~ ERROR suggestions on deprecated items are experimental
+macro_rules! old { () => () }
+
+fn main() {}
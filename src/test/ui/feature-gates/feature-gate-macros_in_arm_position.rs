@@ -0,0 +1,9 @@
+macro_rules! arms {
+    () => { _ => () }
+}
+
+fn main() {
+    match 0 {
+        arms!(), //~ ERROR macro invocations in `match` arm position are unstable
+    }
+}
@@ -0,0 +1,14 @@
+macro_rules! params {
+    ($a:ident, $b:ident) => { $a: u8, $b: u8 }
+}
+
+fn add(params!(a, b)) -> u8 { //~ ERROR macro invocations in parameter position are unstable
+    a + b
+}
+
+fn main() {
+    let add_closure = |params!(a, b)| a + b;
+    //~^ ERROR macro invocations in parameter position are unstable
+    add(1, 2);
+    add_closure(1, 2);
+}
@@ -0,0 +1,4 @@
+#[span_source("fn generated() {}")] //~ ERROR the `#[span_source]` attribute is an experimental
+fn generated() {}
+
+fn main() {}
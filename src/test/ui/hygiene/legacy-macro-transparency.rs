@@ -0,0 +1,25 @@
+// `#[rustc_macro_transparency]` applies to `macro_rules!` macros as well.
+
+#![feature(rustc_attrs)]
+
+#[rustc_macro_transparency = "transparent"]
+macro_rules! transparent { () => {
+    struct Transparent;
+    let transparent = 0;
+} }
+#[rustc_macro_transparency = "opaque"]
+macro_rules! opaque { () => {
+    struct Opaque;
+    let opaque = 0;
+} }
+
+fn main() {
+    transparent!();
+    opaque!();
+
+    Transparent; // OK
+    Opaque; //~ ERROR cannot find value `Opaque` in this scope
+
+    transparent; // OK
+    opaque; //~ ERROR expected value, found macro `opaque`
+}
@@ -0,0 +1,11 @@
+// edition:2018
+
+#[macro_export(edition = "2018")]
+macro_rules! new_only {
+    () => {};
+}
+
+#[macro_export]
+macro_rules! everywhere {
+    () => {};
+}
@@ -0,0 +1,9 @@
+// Parse errors in the arguments of builtin macros are reported without aborting, so the
+// arguments of the following macros are checked as well.
+
+fn main() {
+    env!(struct); //~ ERROR expected expression
+    option_env!("A" +); //~ ERROR expected expression
+    concat!("a", struct); //~ ERROR expected expression
+    include_str!(struct); //~ ERROR expected expression
+}
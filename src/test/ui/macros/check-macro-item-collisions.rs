@@ -0,0 +1,14 @@
+// compile-flags: -Z check-macro-item-collisions
+
+macro_rules! make_config {
+    () => { struct Config; }
+}
+
+macro_rules! make_settings {
+    () => { struct Config; }
+}
+
+make_config!();
+make_settings!(); //~ ERROR the name `Config` is defined multiple times
+
+fn main() {}
@@ -0,0 +1,16 @@
+// Diagnostics emitted by macro expansions are reported in the order the invocations appear in
+// the source, including invocations in nested modules and function bodies.
+
+compile_error!("first"); //~ ERROR first
+
+mod m {
+    compile_error!("second"); //~ ERROR second
+
+    fn f() {
+        compile_error!("third"); //~ ERROR third
+    }
+}
+
+fn main() {
+    compile_error!("fourth"); //~ ERROR fourth
+}
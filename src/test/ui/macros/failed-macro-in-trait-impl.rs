@@ -0,0 +1,22 @@
+// A macro that fails to expand among the items of a trait impl doesn't cause the impl to be
+// reported for missing the items the macro would have defined, but still for the other ones.
+
+macro_rules! method {
+    (fn $name:ident) => { fn $name(&self) {} }
+}
+
+trait Tr {
+    fn f(&self);
+    fn g(&self);
+    fn h(&self);
+}
+
+struct S;
+
+impl Tr for S { //~ ERROR not all trait items implemented, missing: `h`
+    method!(f); //~ ERROR no rules expected the token `f`
+    method!(f); //~ ERROR no rules expected the token `f`
+    method!(fn g);
+}
+
+fn main() {}
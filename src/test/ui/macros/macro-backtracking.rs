@@ -0,0 +1,12 @@
+// Nested repetitions continued by the same token are reported when `macro_backtracking` is on.
+
+#![deny(macro_backtracking)]
+
+macro_rules! foo {
+    ($($(x),+),+ ;) => {}; //~ ERROR nested repetitions can match the same input
+    ($($t:tt)*) => {};
+}
+
+fn main() {
+    foo!(x, x, x, x);
+}
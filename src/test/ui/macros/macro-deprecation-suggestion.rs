@@ -0,0 +1,14 @@
+// run-rustfix
+// check-pass
+
+#![feature(deprecated_suggestion)]
+#![allow(unused_macros)]
+
+macro_rules! new_add { ($a:expr, $b:expr) => ($a + $b) }
+
+#[deprecated(since = "1.0.0", note = "use `new_add!` instead", suggestion = "new_add!($args)")]
+macro_rules! old_add { ($a:expr, $b:expr) => ($a + $b) }
+
+fn main() {
+    let _ = old_add![1, 2]; //~ WARN use of deprecated item 'old_add'
+}
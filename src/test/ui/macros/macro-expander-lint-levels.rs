@@ -0,0 +1,22 @@
+// Lints raised by macro expanders obey the lint levels of the item enclosing the call.
+
+#[macro_export(deprecated_arms(1))]
+macro_rules! m {
+    (old) => {};
+    (new) => {};
+}
+
+#[allow(deprecated)]
+fn allowed() {
+    m!(old);
+}
+
+#[deny(deprecated)]
+fn denied() {
+    m!(old); //~ ERROR use of deprecated arm 1 of macro `m`
+}
+
+fn main() {
+    allowed();
+    denied();
+}
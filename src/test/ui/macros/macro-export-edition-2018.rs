@@ -0,0 +1,10 @@
+// check-pass
+// aux-build:macro-export-edition.rs
+// edition:2018
+
+use macro_export_edition::{everywhere, new_only};
+
+fn main() {
+    everywhere!();
+    new_only!();
+}
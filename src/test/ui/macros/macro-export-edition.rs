@@ -0,0 +1,9 @@
+// aux-build:macro-export-edition.rs
+
+#[macro_use]
+extern crate macro_export_edition;
+
+fn main() {
+    everywhere!();
+    new_only!(); //~ ERROR `new_only!` can only be used in Rust 2018 or later
+}
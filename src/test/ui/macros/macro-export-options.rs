@@ -0,0 +1,12 @@
+// check-pass
+
+#[macro_export(deprecated_arms(1))]
+macro_rules! m {
+    (old) => {};
+    (new) => {};
+}
+
+fn main() {
+    m!(old); //~ WARN use of deprecated arm 1 of macro `m`
+    m!(new);
+}
@@ -0,0 +1,8 @@
+#[macro_export(unknown)] //~ ERROR unknown `macro_export` option
+macro_rules! m {
+    () => {};
+}
+
+fn main() {
+    m!();
+}
@@ -0,0 +1,28 @@
+// run-pass
+
+#![feature(macros_in_arm_position)]
+
+macro_rules! small_arms {
+    ($zero:expr) => {
+        0 => $zero,
+        1 | 2 => "small"
+    }
+}
+
+macro_rules! no_arms {
+    () => {}
+}
+
+fn describe(n: u32) -> &'static str {
+    match n {
+        no_arms!(),
+        small_arms!("zero"),
+        _ => "large",
+    }
+}
+
+fn main() {
+    assert_eq!(describe(0), "zero");
+    assert_eq!(describe(2), "small");
+    assert_eq!(describe(7), "large");
+}
@@ -0,0 +1,35 @@
+// run-pass
+
+#![feature(macros_in_param_position)]
+
+macro_rules! point_params {
+    ($x:ident, $y:ident) => { $x: i32, $y: i32 }
+}
+
+macro_rules! typed_params {
+    ($($name:ident),*) => { $($name: u32),* }
+}
+
+macro_rules! pattern {
+    ($name:ident) => { $name }
+}
+
+fn manhattan(point_params!(x, y)) -> i32 {
+    x.abs() + y.abs()
+}
+
+fn sum(first: u32, typed_params!(second, third)) -> u32 {
+    first + second + third
+}
+
+fn main() {
+    assert_eq!(manhattan(3, -4), 7);
+    assert_eq!(sum(1, 2, 3), 6);
+
+    let add = |typed_params!(a, b)| a + b;
+    assert_eq!(add(2, 3), 5);
+
+    // A single pattern is a parameter with an inferred type, as it always was.
+    let double = |pattern!(n)| n * 2;
+    assert_eq!(double(4), 8);
+}
@@ -0,0 +1,17 @@
+// Diagnostics in code annotated with `#[span_source]` point at the given part of the literal.
+
+#![feature(allow_internal_unstable)]
+
+#[allow_internal_unstable(span_source)]
+macro_rules! checked {
+    ($s:literal) => {
+        #[span_source($s, 1, 1)]
+        fn generated() { missing(); }
+    }
+}
+
+checked!("abc"); //~ ERROR cannot find function `missing` in this scope
+
+fn main() {
+    generated();
+}
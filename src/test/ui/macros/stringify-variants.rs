@@ -0,0 +1,20 @@
+// run-pass
+
+#![feature(stringify_variants)]
+
+macro_rules! source {
+    ($e:expr, $($t:tt)*) => { stringify_source!($e $($t)* - 1) }
+}
+
+macro_rules! tokens {
+    ($e:expr, $($t:tt)*) => { stringify_tokens!($e $($t)* - 1) }
+}
+
+fn main() {
+    assert_eq!(stringify_source!(a  +b /* c */ * [d,e]), "a  +b /* c */ * [d,e]");
+    assert_eq!(stringify_tokens!(a  +b /* c */ * [d,e]), "a + b * [d, e]");
+
+    // Fragments are only joined with the text they were written next to.
+    assert_eq!(source!(1  * 2, +  x  %y), "1  * 2 +  x  %y - 1");
+    assert_eq!(tokens!(1  * 2, +  x  %y), "1 * 2 + x % y - 1");
+}
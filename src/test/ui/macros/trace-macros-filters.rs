@@ -0,0 +1,17 @@
+// check-pass
+
+#![feature(trace_macros)]
+
+macro_rules! traced {
+    () => { 1 }
+}
+
+macro_rules! untraced {
+    () => { 2 }
+}
+
+fn main() {
+    trace_macros!("traced", "other::*");
+    let _ = traced!() + untraced!();
+    trace_macros!(false);
+}
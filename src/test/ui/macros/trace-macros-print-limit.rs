@@ -0,0 +1,10 @@
+// compile-flags: -Z trace-macros -Z macro-print-limit=5
+// check-pass
+
+macro_rules! id {
+    ($($t:tt)*) => { $($t)* }
+}
+
+fn main() {
+    id!(let _x = [1, 2, 3, 4, 5];);
+}
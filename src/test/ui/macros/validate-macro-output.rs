@@ -0,0 +1,21 @@
+// compile-flags: -Z validate-macro-output
+
+macro_rules! define_twice {
+    ($name:ident) => {
+        struct $name;
+        struct $name;
+    }
+}
+
+macro_rules! public_helper {
+    () => {
+        pub fn helper() {}
+    }
+}
+
+define_twice!(S); //~ ERROR the name `S` is defined multiple times
+
+fn main() {
+    public_helper!();
+    helper();
+}
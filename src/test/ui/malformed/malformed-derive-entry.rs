@@ -1,10 +1,13 @@
-#[derive(Copy(Bad))] //~ ERROR expected one of `)`, `,`, or `::`, found `(`
+#[derive(Copy(Bad))] //~ ERROR traits in `#[derive(...)]` don't accept arguments
 struct Test1;
 
-#[derive(Copy="bad")] //~ ERROR expected one of `)`, `,`, or `::`, found `=`
+#[derive(Copy="bad")] //~ ERROR traits in `#[derive(...)]` don't accept arguments
 struct Test2;
 
 #[derive] //~ ERROR malformed `derive` attribute input
 struct Test4;
 
+#[derive("Copy")] //~ ERROR expected path to a trait, found literal
+struct Test5;
+
 fn main() {}
@@ -0,0 +1,8 @@
+// compile-flags: -Z literal-size-limit=4
+
+fn main() {
+    let _ = "abcd";
+    let _ = "abcde"; //~ ERROR string literal is too long
+    let _ = b"abcde"; //~ ERROR byte string literal is too long
+    let _ = concat!("abc", "de"); //~ ERROR string literal is too long
+}
@@ -0,0 +1,15 @@
+// Checks recovery from macro invocations in item position that use the wrong delimiters.
+
+macro_rules! foo {
+    () => {};
+}
+
+foo! {}; //~ ERROR expected item, found `;`
+
+struct S;
+
+impl S {
+    foo!() //~ ERROR macros that expand to items must be delimited with braces
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+// Attributes and visibilities out of place on items are recovered from, with the attributes
+// still applying to the items.
+
+pub #[cfg(FALSE)] fn f() {} //~ ERROR an attribute must come before `pub`
+
+pub pub fn g() {} //~ ERROR duplicate visibility qualifier
+
+struct #[cfg(FALSE)] S; //~ ERROR an attribute must come before `struct`
+
+fn main() {
+    f(); //~ ERROR cannot find function `f` in this scope
+    g();
+    S; //~ ERROR cannot find value `S` in this scope
+}
@@ -0,0 +1,15 @@
+// force-host
+// no-prefer-dynamic
+
+#![crate_type = "proc-macro"]
+#![feature(rustc_attrs)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+#[proc_macro_attribute]
+#[rustc_expr_attr]
+pub fn double(_: TokenStream, item: TokenStream) -> TokenStream {
+    format!("({}) * 2", item).parse().unwrap()
+}
@@ -0,0 +1,15 @@
+// force-host
+// no-prefer-dynamic
+
+#![crate_type = "proc-macro"]
+#![feature(rustc_attrs)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+#[proc_macro_derive(Scoped, attributes(field_helper, anywhere))]
+#[rustc_helper_attr_scope(field_helper(fields))]
+pub fn derive_scoped(_: TokenStream) -> TokenStream {
+    TokenStream::new()
+}
@@ -0,0 +1,50 @@
+// force-host
+// no-prefer-dynamic
+
+#![crate_type = "proc-macro"]
+#![feature(proc_macro_tracked, rustc_attrs)]
+
+extern crate proc_macro;
+
+use proc_macro::{tracked, TokenStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static PURE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static IMPURE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn count(counter: &AtomicUsize) -> TokenStream {
+    counter.fetch_add(1, Ordering::SeqCst).to_string().parse().unwrap()
+}
+
+// Not actually pure, which shows whether its output is cached.
+#[proc_macro]
+#[rustc_pure_macro]
+pub fn pure_count(_: TokenStream) -> TokenStream {
+    count(&PURE_COUNT)
+}
+
+#[proc_macro]
+pub fn impure_count(_: TokenStream) -> TokenStream {
+    count(&IMPURE_COUNT)
+}
+
+#[proc_macro]
+#[rustc_pure_macro]
+pub fn read_env(_: TokenStream) -> TokenStream {
+    let _ = tracked::env_var("PURE_MACRO_VAR");
+    TokenStream::new()
+}
+
+#[proc_macro]
+#[rustc_pure_macro]
+pub fn read_file(_: TokenStream) -> TokenStream {
+    tracked::path("data.txt").unwrap();
+    TokenStream::new()
+}
+
+// Not actually pure either, which shows when it's expanded.
+#[proc_macro]
+#[rustc_pure_macro]
+pub fn impure_count_so_far(_: TokenStream) -> TokenStream {
+    IMPURE_COUNT.load(Ordering::SeqCst).to_string().parse().unwrap()
+}
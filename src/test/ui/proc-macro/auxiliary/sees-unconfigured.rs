@@ -0,0 +1,35 @@
+// force-host
+// no-prefer-dynamic
+
+#![crate_type = "proc-macro"]
+#![feature(rustc_attrs)]
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+fn add_stub(input: TokenStream) -> TokenStream {
+    let mut output = input;
+    output.extend("fn stub() -> u8 { 0 }".parse::<TokenStream>().unwrap());
+    output
+}
+
+#[proc_macro_attribute]
+#[rustc_sees_unconfigured]
+pub fn stub_unconfigured(_: TokenStream, input: TokenStream) -> TokenStream {
+    add_stub(input)
+}
+
+#[proc_macro_attribute]
+pub fn stub_configured(_: TokenStream, input: TokenStream) -> TokenStream {
+    add_stub(input)
+}
+
+#[proc_macro_attribute]
+#[rustc_sees_unconfigured]
+pub fn abs_unconfigured(_: TokenStream, input: TokenStream) -> TokenStream {
+    let mut output = input;
+    let stub = r#"#[link_name = "abs"] fn stub_abs(x: i32) -> i32;"#;
+    output.extend(stub.parse::<TokenStream>().unwrap());
+    output
+}
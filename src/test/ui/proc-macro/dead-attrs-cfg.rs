@@ -0,0 +1,19 @@
+// An attribute macro applied to an item disabled by a following `#[cfg]` never runs.
+
+// aux-build:test-macros.rs
+// compile-flags: -Z check-dead-attrs
+// check-pass
+
+#[macro_use]
+extern crate test_macros;
+
+#[identity_attr] //~ WARN attribute `identity_attr` is not consumed by any macro
+#[cfg(FALSE)]
+fn f() {}
+
+// The `#[cfg]` comes first, so the macro isn't expected to run.
+#[cfg(FALSE)]
+#[identity_attr]
+fn g() {}
+
+fn main() {}
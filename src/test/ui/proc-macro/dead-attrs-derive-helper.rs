@@ -0,0 +1,20 @@
+// A misspelled derive helper isn't consumed by the derive.
+
+// aux-build:helper-attr-scope.rs
+// compile-flags: -Z check-dead-attrs
+// check-pass
+
+#![feature(custom_attribute)]
+#![allow(unused_attributes)]
+
+extern crate helper_attr_scope;
+use helper_attr_scope::Scoped;
+
+#[derive(Scoped)]
+struct S {
+    #[anywehre] //~ WARN attribute `anywehre` is not consumed by any macro
+    #[anywhere]
+    field: u8,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+// run-pass
+// aux-build:expr-attr.rs
+
+#![feature(stmt_expr_attributes)]
+
+extern crate expr_attr;
+use expr_attr::double;
+
+fn main() {
+    let x = #[double] (1 + 1);
+    assert_eq!(x, 4);
+
+    // `if` expressions take attributes that a macro consumes.
+    let y = #[double] if x > 3 { 1 } else { 2 };
+    assert_eq!(y, 2);
+
+    let z = {
+        #[double] if x > 3 { 3 } else { 4 }
+    };
+    assert_eq!(z, 6);
+}
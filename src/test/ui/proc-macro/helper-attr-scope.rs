@@ -0,0 +1,23 @@
+// aux-build:helper-attr-scope.rs
+
+extern crate helper_attr_scope;
+use helper_attr_scope::Scoped;
+
+#[derive(Scoped)]
+#[anywhere]
+#[field_helper] //~ WARN derive helper attribute used outside of the scope of its derive
+                //~| ERROR cannot find attribute macro `field_helper` in this scope
+struct S {
+    #[field_helper]
+    #[anywhere]
+    field: u8,
+}
+
+#[derive(Scoped)]
+enum E {
+    #[field_helper] //~ WARN derive helper attribute used outside of the scope of its derive
+                    //~| ERROR the attribute `field_helper` is currently unknown to the compiler
+    A(#[field_helper] u8),
+}
+
+fn main() {}
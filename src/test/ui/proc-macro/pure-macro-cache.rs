@@ -0,0 +1,12 @@
+// run-pass
+// aux-build:pure-macro.rs
+
+extern crate pure_macro;
+use pure_macro::{impure_count, pure_count};
+
+fn main() {
+    // Invoking a pure macro with the same tokens again reuses the output.
+    assert_eq!(pure_count!(a), pure_count!(a));
+    assert_ne!(pure_count!(a), pure_count!(b));
+    assert_ne!(impure_count!(a), impure_count!(a));
+}
@@ -0,0 +1,14 @@
+// run-pass
+// aux-build:pure-macro.rs
+
+extern crate pure_macro;
+use pure_macro::{impure_count, impure_count_so_far};
+
+// The pure invocation is expanded after the impure invocations that follow it.
+const SO_FAR: usize = impure_count_so_far!();
+const FIRST: usize = impure_count!();
+const SECOND: usize = impure_count!();
+
+fn main() {
+    assert_eq!((FIRST, SECOND, SO_FAR), (0, 1, 2));
+}
@@ -0,0 +1,9 @@
+// aux-build:pure-macro.rs
+
+extern crate pure_macro;
+use pure_macro::{read_env, read_file};
+
+read_env!(); //~ ERROR pure macro `read_env` reads the environment variable `PURE_MACRO_VAR`
+read_file!(); //~ ERROR pure macro `read_file` reads the file `data.txt`
+
+fn main() {}
@@ -0,0 +1,63 @@
+// run-pass
+// aux-build:sees-unconfigured.rs
+
+#![feature(proc_macro_hygiene)]
+
+extern crate sees_unconfigured;
+use sees_unconfigured::{abs_unconfigured, stub_configured, stub_unconfigured};
+
+// The macro sees the disabled function, which is removed from its output.
+#[stub_unconfigured]
+#[cfg(FALSE)]
+fn f() -> u8 { 1 }
+
+mod m {
+    use super::stub_configured;
+
+    // The function is removed before the macro would see it.
+    #[stub_configured]
+    #[cfg(FALSE)]
+    fn f() -> u8 { 1 }
+
+    pub fn stub() -> u8 { 2 }
+}
+
+mod n {
+    use super::stub_unconfigured;
+
+    // A `#[cfg]` preceding the macro attribute is applied first.
+    #[cfg(FALSE)]
+    #[stub_unconfigured]
+    fn f() -> u8 { 1 }
+
+    pub fn stub() -> u8 { 3 }
+}
+
+// The attribute isn't resolved, since the item is removed anyway.
+#[not_a_macro]
+#[cfg(FALSE)]
+fn g() {}
+
+fn in_block() -> u8 {
+    // Statements are passed to the macro too.
+    #[stub_unconfigured]
+    #[cfg(FALSE)]
+    fn f() -> u8 { 1 }
+
+    stub()
+}
+
+extern "C" {
+    // And so are foreign items.
+    #[abs_unconfigured]
+    #[cfg(FALSE)]
+    fn f();
+}
+
+fn main() {
+    assert_eq!(stub(), 0);
+    assert_eq!(m::stub(), 2);
+    assert_eq!(n::stub(), 3);
+    assert_eq!(in_block(), 0);
+    assert_eq!(unsafe { stub_abs(-3) }, 3);
+}
@@ -129,6 +129,7 @@ const WHITELIST: &[Crate<'_>] = &[
     Crate("polonius-engine"),
     Crate("ppv-lite86"),
     Crate("proc-macro2"),
+    Crate("psm"),
     Crate("quick-error"),
     Crate("quote"),
     Crate("rand"),
@@ -156,6 +157,7 @@ const WHITELIST: &[Crate<'_>] = &[
     Crate("serde_derive"),
     Crate("smallvec"),
     Crate("stable_deref_trait"),
+    Crate("stacker"),
     Crate("syn"),
     Crate("synstructure"),
     Crate("tempfile"),